@@ -19,7 +19,7 @@
 use std::path::PathBuf;
 
 use flume::{unbounded, Receiver, Sender};
-use hearth_schema::protocol::CapOperation;
+use hearth_schema::protocol::{CapOperation, MAX_OP_LEN};
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::UnixStream,
@@ -80,8 +80,9 @@ impl Connection {
             while let Ok(op) = outgoing_rx.recv_async().await {
                 let payload = bincode::serialize(&op).unwrap();
                 let len = payload.len() as u32;
-                tx.write_u32_le(len).await.unwrap();
-                tx.write_all(&payload).await.unwrap();
+                if tx.write_u32_le(len).await.is_err() || tx.write_all(&payload).await.is_err() {
+                    break;
+                }
             }
         });
 
@@ -89,10 +90,28 @@ impl Connection {
         tokio::spawn(async move {
             let mut buf = Vec::new();
             loop {
-                let len = rx.read_u32_le().await.unwrap();
+                let Ok(len) = rx.read_u32_le().await else {
+                    break;
+                };
+
+                if len > MAX_OP_LEN {
+                    tracing::warn!("Peer sent an oversized operation ({} bytes)", len);
+                    break;
+                }
+
                 buf.resize(len as usize, 0);
-                rx.read_exact(&mut buf).await.unwrap();
-                let op = bincode::deserialize(&buf).unwrap();
+                if rx.read_exact(&mut buf).await.is_err() {
+                    break;
+                }
+
+                let op = match bincode::deserialize(&buf) {
+                    Ok(op) => op,
+                    Err(err) => {
+                        tracing::warn!("Failed to decode operation from peer: {:?}", err);
+                        break;
+                    }
+                };
+
                 if incoming_tx.send(op).is_err() {
                     break;
                 }
@@ -23,7 +23,7 @@ use std::sync::Arc;
 use crate::lump::LumpStoreImpl;
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use hearth_schema::LumpId;
+use hearth_schema::{codec, LumpId};
 use serde::Deserialize;
 use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, error};
@@ -36,7 +36,10 @@ pub trait AssetLoader: Send + Sync + 'static {
 }
 
 /// Helper trait to implement [AssetLoader] for asset loaders that load from
-/// JSON-encoded data.
+/// serialized data.
+///
+/// Despite the name, data encoded with any [codec] is accepted, so that
+/// lumps written by older guests as plain JSON still load.
 #[async_trait]
 pub trait JsonAssetLoader: Send + Sync + 'static {
     type Asset: Send + Sync + 'static;
@@ -50,7 +53,7 @@ impl<T: JsonAssetLoader> AssetLoader for T {
     type Asset = T::Asset;
 
     async fn load_asset(&self, store: &AssetStore, data: &[u8]) -> Result<T::Asset> {
-        let data: T::Data = serde_json::from_slice(data)
+        let data: T::Data = codec::decode(data)
             .with_context(|| format!("Deserializing asset from {}", type_name::<T::Data>()))?;
 
         self.load_asset(store, data).await
@@ -92,6 +95,28 @@ impl<T: AssetLoader> AssetPool<T> {
             Ok(asset)
         }
     }
+
+    /// Reloads every cached asset from its lump.
+    async fn reload(&self, store: &AssetStore) {
+        let lumps: Vec<LumpId> = self
+            .assets
+            .write()
+            .await
+            .drain()
+            .map(|(id, _)| id)
+            .collect();
+
+        for lump in lumps {
+            let Some(data) = store.lump_store.get_lump(&lump).await else {
+                continue;
+            };
+
+            if let Err(err) = self.load_asset(store, &lump, &data).await {
+                let name = type_name::<T::Asset>();
+                error!("Failed to reload {} from lump {}: {:?}", name, lump, err);
+            }
+        }
+    }
 }
 
 pub struct AssetStore {
@@ -126,13 +151,7 @@ impl AssetStore {
     }
 
     pub async fn load_asset<T: AssetLoader>(&self, lump: &LumpId) -> Result<Arc<T::Asset>> {
-        let type_name = std::any::type_name::<T>();
-        let type_id = TypeId::of::<T>();
-        let pool = self
-            .pools
-            .get(&type_id)
-            .ok_or_else(|| anyhow!("Could not find asset loader '{:?}", type_name))?;
-        let pool: &AssetPool<T> = pool.downcast_ref().unwrap();
+        let pool = self.get_pool::<T>()?;
         let data = self
             .lump_store
             .get_lump(lump)
@@ -140,4 +159,24 @@ impl AssetStore {
             .ok_or_else(|| anyhow!("Failed to get lump {}", lump))?;
         pool.load_asset(self, lump, &data).await
     }
+
+    /// Reloads every cached asset of a loader from its lump.
+    ///
+    /// This is for when cached assets become invalid, such as GPU resources
+    /// after the device they were created on is lost. Assets that fail to
+    /// reload are logged and dropped from the cache.
+    pub async fn reload_assets<T: AssetLoader>(&self) -> Result<()> {
+        self.get_pool::<T>()?.reload(self).await;
+        Ok(())
+    }
+
+    fn get_pool<T: AssetLoader>(&self) -> Result<&AssetPool<T>> {
+        let type_name = std::any::type_name::<T>();
+        let type_id = TypeId::of::<T>();
+        let pool = self
+            .pools
+            .get(&type_id)
+            .ok_or_else(|| anyhow!("Could not find asset loader '{:?}", type_name))?;
+        Ok(pool.downcast_ref().unwrap())
+    }
 }
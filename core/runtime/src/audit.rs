@@ -0,0 +1,434 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use flue::{CapabilityHandle, OwnedCapability, Permissions, PostOffice, Table};
+use hearth_schema::audit::*;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::process::{ProcessInfo, ProcessMetadata};
+use crate::snapshot::{unix_millis, write_snapshots};
+use crate::utils::{
+    GetProcessMetadata, RequestInfo, RequestResponseProcess, ResponseInfo, ServiceRunner,
+};
+
+/// The name of the audit snapshot file within the data directory.
+pub const CAP_AUDIT_FILE: &str = "cap-audit.json";
+
+/// The maximum number of sent capabilities that have not been received yet.
+const MAX_PENDING_GRANTS: usize = 256;
+
+/// A host-side log of the capability operations performed by processes.
+///
+/// Processes record their sends, kills, and the capabilities they receive in
+/// messages. Only the most recent records are retained, so that the log's
+/// memory use is bounded.
+///
+/// Sent capabilities are held in the log's own table, where every
+/// capability to the same route shares a handle. A received grant is
+/// attributed to the process that sent the first of its capabilities, found
+/// by that handle.
+pub struct CapAudit {
+    capacity: usize,
+
+    /// A table holding the sent capabilities, demoted to no permissions.
+    table: Table,
+
+    log: Mutex<AuditLog>,
+}
+
+#[derive(Default)]
+struct AuditLog {
+    next_seq: u64,
+    records: VecDeque<CapAuditRecord>,
+
+    /// Sent capabilities that haven't been received yet, oldest first, keyed
+    /// by their handles in [CapAudit::table], and their senders.
+    pending: VecDeque<(CapabilityHandle, hearth_schema::ProcessId)>,
+}
+
+impl CapAudit {
+    /// Creates a new audit log that retains at most `capacity` records.
+    ///
+    /// A capacity of zero disables auditing.
+    pub fn new(post: Arc<PostOffice>, capacity: usize) -> Self {
+        Self {
+            capacity,
+            table: Table::new(post),
+            log: Default::default(),
+        }
+    }
+
+    /// Returns true if this audit log records anything.
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Records that a process sent a message through a capability with the
+    /// `target` permissions, attaching the capabilities in `caps`.
+    pub fn record_send(&self, info: &ProcessInfo, target: Permissions, caps: Vec<OwnedCapability>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let pid = convert_pid(info);
+        let caps: Vec<_> = caps.into_iter().filter_map(|cap| self.key(cap)).collect();
+        let perms = caps.iter().map(|(_, perms)| *perms).collect();
+        let mut log = self.log.lock();
+
+        for (key, _) in caps {
+            if log.pending.len() >= MAX_PENDING_GRANTS {
+                if let Some((old, _)) = log.pending.pop_front() {
+                    let _ = self.table.dec_ref(old);
+                }
+            }
+
+            log.pending.push_back((key, pid));
+        }
+
+        let target = convert_perm(target);
+        let event = CapAuditEvent::Send {
+            target,
+            caps: perms,
+        };
+
+        self.push(&mut log, info, event);
+    }
+
+    /// Records that a process received a message with the capabilities in
+    /// `caps`. Does nothing if the message carries no capabilities.
+    pub fn record_receive(&self, info: &ProcessInfo, caps: Vec<OwnedCapability>) {
+        if !self.is_enabled() || caps.is_empty() {
+            return;
+        }
+
+        let caps: Vec<_> = caps.into_iter().filter_map(|cap| self.key(cap)).collect();
+        let mut log = self.log.lock();
+
+        let mut from = None;
+        for (key, _) in caps.iter() {
+            let sender = log
+                .pending
+                .iter()
+                .position(|(pending, _)| pending == key)
+                .and_then(|idx| log.pending.remove(idx));
+
+            if let Some((pending, pid)) = sender {
+                let _ = self.table.dec_ref(pending);
+                from = from.or(Some(pid));
+            }
+
+            let _ = self.table.dec_ref(*key);
+        }
+
+        let caps = caps.into_iter().map(|(_, perms)| perms).collect();
+        self.push(&mut log, info, CapAuditEvent::Grant { from, caps });
+    }
+
+    /// Records that a process killed a capability with the `target`
+    /// permissions.
+    pub fn record_kill(&self, info: &ProcessInfo, target: Permissions) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let target = convert_perm(target);
+        let mut log = self.log.lock();
+        self.push(&mut log, info, CapAuditEvent::Kill { target });
+    }
+
+    /// Gets at most `limit` of the most recent records, oldest first.
+    ///
+    /// If `pid` is set, only records involving that process are returned.
+    pub fn records(
+        &self,
+        pid: Option<hearth_schema::ProcessId>,
+        limit: usize,
+    ) -> Vec<CapAuditRecord> {
+        let log = self.log.lock();
+        let mut records: Vec<_> = log
+            .records
+            .iter()
+            .rev()
+            .filter(|record| pid.map(|pid| record.involves(pid)).unwrap_or(true))
+            .take(limit)
+            .cloned()
+            .collect();
+
+        records.reverse();
+        records
+    }
+
+    /// Totals the retained records of every process.
+    pub fn summary(&self) -> Vec<ProcessCapSummary> {
+        summarize(self.log.lock().records.iter())
+    }
+
+    /// Takes a snapshot of every retained record.
+    pub fn snapshot(&self) -> AuditSnapshot {
+        let records = self.log.lock().records.iter().cloned().collect();
+        AuditSnapshot {
+            updated: unix_millis() / 1000,
+            records,
+        }
+    }
+
+    /// Returns the sequence number of the next record.
+    ///
+    /// This changes whenever something is recorded.
+    pub fn next_seq(&self) -> u64 {
+        self.log.lock().next_seq
+    }
+
+    /// Periodically writes a snapshot of this log to the [CAP_AUDIT_FILE]
+    /// whenever it has changed, for hearth-ctl to read.
+    pub async fn write_snapshots(&self, period: Duration) {
+        let mut written = None;
+        write_snapshots(CAP_AUDIT_FILE, period, || {
+            let seq = self.next_seq();
+            let changed = written.replace(seq) != Some(seq);
+            std::future::ready(changed.then(|| self.snapshot()))
+        })
+        .await;
+    }
+
+    /// Imports a capability into this log's table. Returns its handle with no
+    /// permissions, which is shared by every capability to the same route,
+    /// and its original permissions.
+    fn key(&self, cap: OwnedCapability) -> Option<(CapabilityHandle, hearth_schema::Permissions)> {
+        let handle = self.table.import_owned(cap).ok()?;
+        let cap = self.table.wrap_handle(handle).ok()?;
+        let perms = convert_perm(cap.get_permissions());
+        let key = cap.demote(Permissions::empty()).ok()?.into_handle();
+        Some((key, perms))
+    }
+
+    /// Helper function to append a record, dropping the oldest one if this log
+    /// is full.
+    fn push(&self, log: &mut AuditLog, info: &ProcessInfo, event: CapAuditEvent) {
+        if log.records.len() >= self.capacity {
+            log.records.pop_front();
+        }
+
+        let seq = log.next_seq;
+        log.next_seq += 1;
+
+        log.records.push_back(CapAuditRecord {
+            seq,
+            timestamp: unix_millis(),
+            pid: convert_pid(info),
+            label: info.meta.name.clone(),
+            event,
+        });
+    }
+}
+
+/// Totals the records of each process, sorted by process ID.
+pub fn summarize<'a>(
+    records: impl IntoIterator<Item = &'a CapAuditRecord>,
+) -> Vec<ProcessCapSummary> {
+    let mut processes = BTreeMap::new();
+
+    for record in records {
+        let summary = summary_entry(&mut processes, record.pid);
+        if record.label.is_some() {
+            summary.label = record.label.clone();
+        }
+
+        match &record.event {
+            CapAuditEvent::Send { .. } => summary.sends += 1,
+            CapAuditEvent::Kill { .. } => summary.kills += 1,
+            CapAuditEvent::Grant { from, caps } => {
+                let num = caps.len() as u64;
+                summary.received += num;
+
+                if let Some(from) = from {
+                    summary_entry(&mut processes, *from).given += num;
+                }
+            }
+        }
+    }
+
+    processes.into_values().collect()
+}
+
+/// Helper function to get a process's summary, creating it if it doesn't
+/// exist yet.
+fn summary_entry(
+    processes: &mut BTreeMap<hearth_schema::ProcessId, ProcessCapSummary>,
+    pid: hearth_schema::ProcessId,
+) -> &mut ProcessCapSummary {
+    processes.entry(pid).or_insert_with(|| ProcessCapSummary {
+        pid,
+        label: None,
+        sends: 0,
+        given: 0,
+        received: 0,
+        kills: 0,
+    })
+}
+
+/// The contents of the [CAP_AUDIT_FILE].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AuditSnapshot {
+    /// When this file was written, in seconds since the Unix epoch.
+    pub updated: u64,
+
+    /// Every retained record, oldest first.
+    pub records: Vec<CapAuditRecord>,
+}
+
+/// The native capability audit service. Accepts [CapAuditRequest].
+///
+/// Reads from the runtime's [CapAudit] log.
+pub struct CapAuditService;
+
+#[async_trait]
+impl RequestResponseProcess for CapAuditService {
+    type Request = CapAuditRequest;
+    type Response = CapAuditResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Request>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        let audit = &request.runtime.audit;
+
+        let data = if !audit.is_enabled() {
+            Err(CapAuditError::Disabled)
+        } else {
+            match &request.data {
+                CapAuditRequest::Records { pid, limit } => Ok(CapAuditSuccess::Records(
+                    audit.records(*pid, *limit as usize),
+                )),
+                CapAuditRequest::Summary => Ok(CapAuditSuccess::Summary(audit.summary())),
+            }
+        };
+
+        ResponseInfo { data, caps: vec![] }
+    }
+}
+
+impl GetProcessMetadata for CapAuditService {
+    fn get_process_metadata() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("CapAuditService".to_string()),
+            description: Some("The native capability audit service.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        }
+    }
+}
+
+impl ServiceRunner for CapAuditService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+fn convert_pid(info: &ProcessInfo) -> hearth_schema::ProcessId {
+    hearth_schema::ProcessId(info.pid as u32)
+}
+
+fn convert_perm(perms: Permissions) -> hearth_schema::Permissions {
+    hearth_schema::Permissions::from_bits_retain(perms.bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use flue::{Mailbox, MailboxGroup};
+
+    use super::*;
+
+    fn info(pid: usize, name: &str) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            process_span: tracing::Span::none(),
+            meta: ProcessMetadata {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            store: Default::default(),
+        }
+    }
+
+    fn cap(mailbox: &Mailbox, perms: Permissions) -> Vec<OwnedCapability> {
+        vec![mailbox.export(perms).unwrap().to_owned()]
+    }
+
+    #[test]
+    fn grants_are_matched_to_sends() {
+        let post = PostOffice::new();
+        let audit = CapAudit::new(post.clone(), 16);
+        let table = Table::new(post);
+        let group = MailboxGroup::new(&table);
+        let granted = group.create_mailbox().unwrap();
+        let other = group.create_mailbox().unwrap();
+        let unknown = group.create_mailbox().unwrap();
+        let perms = Permissions::SEND | Permissions::MONITOR;
+
+        let alice = info(1, "alice");
+        let bob = info(2, "bob");
+
+        audit.record_send(&alice, Permissions::SEND, cap(&other, perms));
+        audit.record_send(&alice, Permissions::SEND, cap(&granted, perms));
+        audit.record_receive(&bob, cap(&granted, Permissions::SEND));
+        audit.record_receive(&bob, cap(&unknown, perms));
+
+        let grants: Vec<_> = audit
+            .records(Some(hearth_schema::ProcessId(2)), 16)
+            .into_iter()
+            .filter_map(|record| match record.event {
+                CapAuditEvent::Grant { from, .. } => Some(from),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(grants, vec![Some(hearth_schema::ProcessId(1)), None]);
+
+        let summary = audit.summary();
+        assert_eq!(summary.len(), 2);
+        assert_eq!((summary[0].sends, summary[0].given), (2, 1));
+        assert_eq!(
+            (summary[1].received, summary[1].label.as_deref()),
+            (2, Some("bob"))
+        );
+    }
+
+    #[test]
+    fn oldest_records_are_dropped() {
+        let audit = CapAudit::new(PostOffice::new(), 2);
+        let process = info(0, "killer");
+
+        for _ in 0..3 {
+            audit.record_kill(&process, Permissions::KILL);
+        }
+
+        let seqs: Vec<_> = audit.records(None, 16).iter().map(|r| r.seq).collect();
+        assert_eq!(seqs, vec![1, 2]);
+    }
+
+    #[test]
+    fn disabled_audit_records_nothing() {
+        let audit = CapAudit::new(PostOffice::new(), 0);
+        audit.record_kill(&info(0, "killer"), Permissions::KILL);
+        assert!(audit.records(None, 16).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::{ArgMatches, Args, Command, FromArgMatches, Subcommand};
+
+/// Collects the command-line interface of a host program and the plugins that
+/// it runs.
+///
+/// Plugins declare their own options as [clap::Args] argument groups and
+/// [clap::Subcommand] enums. Host programs add them to a builder before the
+/// runtime is built, parse the command line once, and then extract each
+/// plugin's arguments from the resulting [CliMatches].
+pub struct CliBuilder {
+    command: Command<'static>,
+}
+
+impl CliBuilder {
+    /// Creates a new builder from the host program's own command.
+    ///
+    /// This is usually retrieved with [clap::CommandFactory::command].
+    pub fn new(command: Command<'static>) -> Self {
+        Self { command }
+    }
+
+    /// Adds a plugin's argument group to the command.
+    pub fn add_args<T: Args>(&mut self) -> &mut Self {
+        self.map_command(T::augment_args)
+    }
+
+    /// Adds a plugin's subcommands to the command.
+    pub fn add_subcommands<T: Subcommand>(&mut self) -> &mut Self {
+        self.map_command(T::augment_subcommands)
+    }
+
+    /// Parses the process's command-line arguments.
+    ///
+    /// Prints usage and exits the process if the arguments are invalid.
+    pub fn parse(&self) -> CliMatches {
+        CliMatches(self.command.clone().get_matches())
+    }
+
+    /// Parses command-line arguments from an iterator.
+    pub fn try_parse_from<I, T>(&self, args: I) -> Result<CliMatches, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        self.command
+            .clone()
+            .try_get_matches_from(args)
+            .map(CliMatches)
+    }
+
+    /// Helper function to replace the inner command.
+    fn map_command(&mut self, f: impl FnOnce(Command<'static>) -> Command<'static>) -> &mut Self {
+        let command = std::mem::replace(&mut self.command, Command::new(""));
+        self.command = f(command);
+        self
+    }
+}
+
+/// Parsed command-line arguments from a [CliBuilder].
+pub struct CliMatches(ArgMatches);
+
+impl CliMatches {
+    /// Extracts an argument group.
+    ///
+    /// Prints usage and exits the process if the group does not match.
+    pub fn get<T: FromArgMatches>(&self) -> T {
+        T::from_arg_matches(&self.0).unwrap_or_else(|err| err.exit())
+    }
+
+    /// Extracts a plugin's subcommand, if one of its subcommands was given.
+    ///
+    /// Prints usage and exits the process if the subcommand does not match.
+    pub fn subcommand<T: Subcommand>(&self) -> Option<T> {
+        let (name, _) = self.0.subcommand()?;
+
+        if !T::has_subcommand(name) {
+            return None;
+        }
+
+        Some(self.get())
+    }
+}
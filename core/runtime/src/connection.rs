@@ -15,56 +15,72 @@
 //
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
-
 //! Implements peer-to-peer capability exchange code for remote processes.
 //!
-//! Currently unimplemented.
-
-// TODO get rid of this when connections are implemented
-#![allow(unused)]
+//! Each side of a [Connection] exports its local capabilities to the other
+//! side by declaring them with IDs. Capabilities that are declared by the
+//! other side are imported as proxy mailboxes whose messages are forwarded
+//! over the connection.
+//!
+//! The two sides don't share a lump store, so messages whose payloads have
+//! been spilled over into lumps are resolved before they're sent, and large
+//! incoming payloads are spilled over again on the receiving side.
 
 use std::{collections::HashMap, sync::Arc};
 
-use flue::{CapabilityRef, Mailbox, MailboxGroup, OwnedCapability, PostOffice, Table};
+use flue::{
+    CapabilityHandle, CapabilityRef, MailboxGroup, OwnedCapability, OwnedTableSignal, Permissions,
+    PostOffice, Table,
+};
 use flume::{Receiver, Sender};
-use hearth_schema::protocol::{CapOperation, LocalCapOperation, RemoteCapOperation};
-use ouroboros::self_referencing;
+use hearth_schema::protocol::{
+    CapOperation, LocalCapOperation, RemoteCapOperation, TransferredCap,
+};
+use hearth_schema::{decode_spillover, encode_spillover, SPILLOVER_THRESHOLD};
 use parking_lot::Mutex;
 use tokio::sync::oneshot;
+use tracing::debug;
 
-pub type RootCapSender = oneshot::Sender<OwnedCapability>;
+use crate::lump::LumpStoreImpl;
 
-#[self_referencing]
-struct Import<'a> {
-    conn: &'a Connection,
+pub type RootCapSender = oneshot::Sender<OwnedCapability>;
 
-    group: MailboxGroup<'a>,
+/// A local capability exported to the other side of a connection.
+struct Export {
+    /// The exported capability's handle in the connection's table.
+    handle: CapabilityHandle,
 
-    #[borrows(group)]
-    #[covariant]
-    mb: Mailbox<'this>,
+    /// Whether this capability has been revoked and is waiting for the
+    /// revocation to be acknowledged.
+    revoked: bool,
 }
 
-struct Export<'a> {
-    cap: CapabilityRef<'a>,
-    revoked: bool,
+/// A remote capability imported from the other side of a connection.
+struct Import {
+    /// A capability to the proxy mailbox with the remote capability's
+    /// permissions.
+    cap: OwnedCapability,
+
+    /// Stops the proxy when sent or dropped.
+    _stop: oneshot::Sender<()>,
 }
 
-struct Exports<'a> {
-    table: &'a Table,
-    inner: Mutex<HashMap<u32, Export<'a>>>,
+#[derive(Default)]
+struct Imports {
+    /// Imports by the IDs that the other side declared them with.
+    by_id: HashMap<u32, Import>,
+
+    /// Remote IDs by the permissionless handles of their proxy mailboxes.
+    by_key: HashMap<CapabilityHandle, u32>,
 }
 
 /// A data structure implementing the capability exchange protocol.
-///
-/// Currently unimplemented.
-#[self_referencing]
 pub struct Connection {
     table: Table,
-
-    #[borrows(table)]
-    #[not_covariant]
-    exports: Exports<'this>,
+    lumps: Option<Arc<LumpStoreImpl>>,
+    op_tx: Sender<CapOperation>,
+    exports: Mutex<HashMap<u32, Export>>,
+    imports: Mutex<Imports>,
 }
 
 impl Connection {
@@ -72,48 +88,68 @@ impl Connection {
     ///
     /// `op_rx` is the channel receiver used to receive incoming [CapOperation]
     /// messages on this connection. `op_tx` is the channel sender used to send
-    /// outgoing [CapOperation]s.
+    /// outgoing [CapOperation]s. The first root cap that the other side sets
+    /// is sent to `on_root_cap`.
+    ///
+    /// Spilled-over messages are resolved from and spilled into `lumps`. Without
+    /// a lump store, messages are forwarded as they are.
+    ///
+    /// The connection lasts until `op_rx` is closed, after which all imported
+    /// capabilities become unreachable.
     pub fn begin(
         post: Arc<PostOffice>,
+        lumps: Option<Arc<LumpStoreImpl>>,
         op_rx: Receiver<CapOperation>,
         op_tx: Sender<CapOperation>,
-        on_root_cap: Option<RootCapSender>,
+        mut on_root_cap: Option<RootCapSender>,
     ) -> Arc<Self> {
-        let conn = Connection::new(Table::new(post), |table| Exports {
-            table,
-            inner: Default::default(),
+        let conn = Arc::new(Self {
+            table: Table::new(post),
+            lumps,
+            op_tx,
+            exports: Default::default(),
+            imports: Default::default(),
         });
 
-        let conn = Arc::new(conn);
+        tokio::spawn({
+            let conn = conn.clone();
+            async move {
+                while let Ok(op) = op_rx.recv_async().await {
+                    conn.on_op(op, &mut on_root_cap).await;
+                }
+
+                debug!("connection closed");
+                conn.close();
+            }
+        });
 
         conn
     }
 
     /// Exports a capability through this connection.
     pub fn export(&self, cap: OwnedCapability) -> u32 {
-        self.with_exports(|exports| {
-            let table = exports.table;
-            let mut exports = exports.inner.lock();
-            let handle = table.import_owned(cap).unwrap();
-            let id: u32 = handle.0.try_into().unwrap();
-
-            if exports.contains_key(&id) {
-                // cap is already exported, so drop this reference
-                table.dec_ref(handle).unwrap();
-            } else {
-                // cap needs to be exported
-                let cap = table.wrap_handle(handle).unwrap();
-                let perms = cap.get_permissions().bits();
-                let perms = hearth_schema::Permissions::from_bits_retain(perms);
-                let op = LocalCapOperation::DeclareCap { id, perms };
-                let revoked = false;
-                let export = Export { cap, revoked };
-                exports.insert(id, export);
-                self.send_local_op(op);
-            }
+        let table = &self.table;
+        let mut exports = self.exports.lock();
+        let handle = table.import_owned(cap).unwrap();
+        let id: u32 = handle.0.try_into().unwrap();
 
-            id
-        })
+        if exports.contains_key(&id) {
+            // cap is already exported, so drop this reference
+            table.dec_ref(handle).unwrap();
+        } else {
+            // cap needs to be exported
+            let cap = table.wrap_handle(handle).unwrap();
+            let perms = cap.get_permissions().bits();
+            let perms = hearth_schema::Permissions::from_bits_retain(perms);
+            let handle = cap.into_handle();
+            let op = LocalCapOperation::DeclareCap { id, perms };
+            let revoked = false;
+            let export = Export { handle, revoked };
+            exports.insert(id, export);
+            self.send_local_op(op);
+        }
+
+        id
     }
 
     /// Exports a capability as this side of the connection's root cap.
@@ -122,55 +158,337 @@ impl Connection {
         self.send_local_op(LocalCapOperation::SetRootCap { id });
     }
 
-    pub fn on_op(self: &Arc<Self>, op: CapOperation) {
+    async fn on_op(self: &Arc<Self>, op: CapOperation, on_root_cap: &mut Option<RootCapSender>) {
         match op {
-            CapOperation::Local(op) => self.on_local_op(op),
-            CapOperation::Remote(op) => self.on_remote_op(op),
+            CapOperation::Local(op) => self.on_local_op(op, on_root_cap).await,
+            CapOperation::Remote(op) => self.on_remote_op(op).await,
+            CapOperation::Lump(_) => {
+                debug!("ignoring lump operation; lumps are exchanged by the transport");
+            }
         }
     }
 
-    fn on_local_op(self: &Arc<Self>, op: LocalCapOperation) {
+    async fn on_local_op(
+        self: &Arc<Self>,
+        op: LocalCapOperation,
+        on_root_cap: &mut Option<RootCapSender>,
+    ) {
         use LocalCapOperation::*;
         match op {
-            DeclareCap { id, perms } => todo!(),
-            RevokeCap { id, reason } => todo!(),
-            SetRootCap { id } => todo!(),
+            DeclareCap { id, perms } => {
+                let perms = Permissions::from_bits_truncate(perms.bits());
+                let (ready_tx, ready_rx) = oneshot::channel();
+                tokio::spawn(self.clone().proxy(id, perms, ready_tx));
+
+                // wait for the proxy so that following operations can use it
+                let _ = ready_rx.await;
+            }
+            RevokeCap { id, reason } => {
+                debug!("remote capability {} revoked: {:?}", id, reason);
+                self.imports.lock().by_id.remove(&id);
+                self.send_remote_op(RemoteCapOperation::AcknowledgeRevocation { id });
+            }
+            SetRootCap { id } => {
+                let Some(cap) = self.get_import(id) else {
+                    debug!("remote root capability {} was never declared", id);
+                    return;
+                };
+
+                if let Some(tx) = on_root_cap.take() {
+                    let _ = tx.send(cap);
+                }
+            }
         }
     }
 
-    fn on_remote_op(&self, op: RemoteCapOperation) {
+    async fn on_remote_op(&self, op: RemoteCapOperation) {
         use RemoteCapOperation::*;
         match op {
             AcknowledgeRevocation { id } => {
-                self.with_exports(|exports| {
-                    let mut exports = exports.inner.lock();
-                    if let Some(export) = exports.get(&id) {
-                        if export.revoked {
-                            exports.remove(&id);
-                        }
+                let mut exports = self.exports.lock();
+                if let Some(export) = exports.get(&id) {
+                    if export.revoked {
+                        let _ = self.table.dec_ref(export.handle);
+                        exports.remove(&id);
                     }
-                });
+                }
+            }
+            FreeCap { id } => {
+                if let Some(export) = self.exports.lock().remove(&id) {
+                    let _ = self.table.dec_ref(export.handle);
+                }
+            }
+            Send { id, data, caps } => {
+                let Some(target) = self.get_export(id) else {
+                    return;
+                };
+
+                let caps: Vec<_> = caps
+                    .into_iter()
+                    .filter_map(|cap| self.receive_cap(cap))
+                    .collect();
+
+                let caps: Vec<_> = caps.iter().collect();
+                let data = self.spill(data).await;
+                if let Err(err) = target.send(&data, &caps).await {
+                    debug!("failed to send to exported capability {}: {:?}", id, err);
+                }
+            }
+            Kill { id } => {
+                if let Some(target) = self.get_export(id) {
+                    let _ = target.kill();
+                }
             }
-            FreeCap { id } => todo!(),
-            Send { id, data, caps } => self.with_export(id, |cap| {}),
-            Kill { id } => self.with_export(id, |cap| {
-                let _ = cap.kill();
-            }),
         }
     }
 
-    fn with_export(&self, id: u32, mut cb: impl FnMut(&CapabilityRef<'_>)) {
-        self.with_exports(|exports| {
-            let inner = exports.inner.lock();
-            if let Some(cap) = inner.get(&id) {
-                if !cap.revoked {
-                    cb(&cap.cap);
-                }
+    /// Forwards the messages sent to a remote capability's proxy mailbox
+    /// until the capability is revoked.
+    async fn proxy(self: Arc<Self>, id: u32, perms: Permissions, ready: oneshot::Sender<()>) {
+        let table = &self.table;
+        let group = MailboxGroup::new(table);
+        let mailbox = group.create_mailbox().unwrap();
+        let cap = mailbox.export(perms).unwrap();
+        let key = cap.demote(Permissions::empty()).unwrap().into_handle();
+        let (stop_tx, mut stop_rx) = oneshot::channel();
+
+        {
+            let mut imports = self.imports.lock();
+            let cap = cap.to_owned();
+            let import = Import {
+                cap,
+                _stop: stop_tx,
+            };
+            imports.by_id.insert(id, import);
+            imports.by_key.insert(key, id);
+        }
+
+        drop(cap);
+        let _ = ready.send(());
+
+        loop {
+            tokio::select! {
+                _ = &mut stop_rx => break,
+                signal = mailbox.recv_owned() => match signal {
+                    Some(OwnedTableSignal::Message { data, caps }) => {
+                        let caps = caps.iter().map(|cap| self.transfer_cap(cap)).collect();
+                        let data = self.unspill(data).await;
+                        self.send_remote_op(RemoteCapOperation::Send { id, data, caps });
+                    }
+                    Some(OwnedTableSignal::Down { .. }) => {}
+                    None => {
+                        // the proxy has been killed, so kill the original
+                        self.imports.lock().by_id.remove(&id);
+                        self.send_remote_op(RemoteCapOperation::Kill { id });
+                        break;
+                    }
+                },
             }
-        });
+        }
+
+        self.imports.lock().by_key.remove(&key);
+        let _ = table.dec_ref(key);
     }
 
-    fn send_local_op(&self, op: LocalCapOperation) {}
+    /// Replaces the data of a spilled-over message with its payload, so that
+    /// the other side doesn't need this side's lump.
+    async fn unspill(&self, data: Vec<u8>) -> Vec<u8> {
+        let (Some(lumps), Some(id)) = (self.lumps.as_ref(), decode_spillover(&data)) else {
+            return data;
+        };
+
+        match lumps.take_spill(&id).await {
+            Some(payload) => payload.to_vec(),
+            None => {
+                debug!("spilled-over payload lump {} is missing", id);
+                data
+            }
+        }
+    }
 
-    fn send_remote_op(&self, op: RemoteCapOperation) {}
+    /// Spills a large payload from the other side of the connection over into
+    /// a lump, like local senders do.
+    async fn spill(&self, data: Vec<u8>) -> Vec<u8> {
+        match self.lumps.as_ref() {
+            Some(lumps) if data.len() > SPILLOVER_THRESHOLD => {
+                encode_spillover(&lumps.add_spill(data.into()).await)
+            }
+            _ => data,
+        }
+    }
+
+    /// Prepares a capability to be sent to the other side of the connection.
+    ///
+    /// Capabilities to proxies are sent back to the other side as its own
+    /// capabilities so that they don't make a round trip through this side.
+    fn transfer_cap(&self, cap: &CapabilityRef) -> TransferredCap {
+        let key = cap.demote(Permissions::empty()).unwrap().into_handle();
+        let import = self.imports.lock().by_key.get(&key).copied();
+        let _ = self.table.dec_ref(key);
+
+        match import {
+            Some(id) => {
+                let perms = cap.get_permissions().bits();
+                let perms = hearth_schema::Permissions::from_bits_retain(perms);
+                TransferredCap::Remote { id, perms }
+            }
+            None => TransferredCap::Local(self.export(cap.to_owned())),
+        }
+    }
+
+    /// Looks up a capability that was sent from the other side of the
+    /// connection.
+    fn receive_cap(&self, cap: TransferredCap) -> Option<CapabilityRef> {
+        match cap {
+            TransferredCap::Local(id) => {
+                let cap = self.get_import(id)?;
+                let handle = self.table.import_owned(cap).ok()?;
+                self.table.wrap_handle(handle).ok()
+            }
+            TransferredCap::Remote { id, perms } => {
+                let perms = Permissions::from_bits_truncate(perms.bits());
+                self.get_export(id)?.demote(perms).ok()
+            }
+        }
+    }
+
+    /// Gets a capability to an import's proxy.
+    fn get_import(&self, id: u32) -> Option<OwnedCapability> {
+        let imports = self.imports.lock();
+        imports.by_id.get(&id).map(|import| import.cap.clone())
+    }
+
+    /// Gets a non-revoked exported capability.
+    fn get_export(&self, id: u32) -> Option<CapabilityRef> {
+        let exports = self.exports.lock();
+        let export = exports.get(&id).filter(|export| !export.revoked)?;
+        self.table.inc_ref(export.handle).ok()?;
+        self.table.wrap_handle(export.handle).ok()
+    }
+
+    /// Frees every import and export once the connection has closed.
+    fn close(&self) {
+        self.imports.lock().by_id.clear();
+
+        for (_, export) in self.exports.lock().drain() {
+            let _ = self.table.dec_ref(export.handle);
+        }
+    }
+
+    fn send_local_op(&self, op: LocalCapOperation) {
+        let _ = self.op_tx.send(CapOperation::Local(op));
+    }
+
+    fn send_remote_op(&self, op: RemoteCapOperation) {
+        let _ = self.op_tx.send(CapOperation::Remote(op));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn root_cap_forwards_messages() {
+        let post = PostOffice::new();
+        let (a_tx, b_rx) = flume::unbounded();
+        let (b_tx, a_rx) = flume::unbounded();
+        let a = Connection::begin(post.clone(), None, a_rx, a_tx, None);
+        let (root_tx, root_rx) = oneshot::channel();
+        let _b = Connection::begin(post.clone(), None, b_rx, b_tx, Some(root_tx));
+
+        let table = Table::new(post);
+        let group = MailboxGroup::new(&table);
+        let mailbox = group.create_mailbox().unwrap();
+        a.export_root(mailbox.export(Permissions::SEND).unwrap().to_owned());
+
+        let root = root_rx.await.unwrap();
+        let root = table.import_owned(root).unwrap();
+        let root = table.wrap_handle(root).unwrap();
+        root.send(b"Hello, world!", &[]).await.unwrap();
+
+        let Some(OwnedTableSignal::Message { data, .. }) = mailbox.recv_owned().await else {
+            panic!("expected a message");
+        };
+
+        assert_eq!(data, b"Hello, world!");
+    }
+
+    #[tokio::test]
+    async fn root_cap_replies_with_caps() {
+        let post = PostOffice::new();
+        let (a_tx, b_rx) = flume::unbounded();
+        let (b_tx, a_rx) = flume::unbounded();
+        let a = Connection::begin(post.clone(), None, a_rx, a_tx, None);
+        let (root_tx, root_rx) = oneshot::channel();
+        let _b = Connection::begin(post.clone(), None, b_rx, b_tx, Some(root_tx));
+
+        let table = Table::new(post);
+        let group = MailboxGroup::new(&table);
+        let registry = group.create_mailbox().unwrap();
+        let service = group.create_mailbox().unwrap();
+        let reply = group.create_mailbox().unwrap();
+        a.export_root(registry.export(Permissions::SEND).unwrap().to_owned());
+
+        // look up a service through the peer's root like the peer registry does
+        let root = root_rx.await.unwrap();
+        let root = table.import_owned(root).unwrap();
+        let root = table.wrap_handle(root).unwrap();
+        let reply_cap = reply.export(Permissions::SEND).unwrap();
+        root.send(b"get", &[&reply_cap]).await.unwrap();
+
+        let Some(OwnedTableSignal::Message { caps, .. }) = registry.recv_owned().await else {
+            panic!("expected a request");
+        };
+
+        let service_cap = service.export(Permissions::SEND).unwrap();
+        caps[0].send(b"found", &[&service_cap]).await.unwrap();
+
+        let Some(OwnedTableSignal::Message { data, caps }) = reply.recv_owned().await else {
+            panic!("expected a response");
+        };
+
+        assert_eq!(data, b"found");
+        caps[0].send(b"Hello, world!", &[]).await.unwrap();
+
+        let Some(OwnedTableSignal::Message { data, .. }) = service.recv_owned().await else {
+            panic!("expected a message");
+        };
+
+        assert_eq!(data, b"Hello, world!");
+    }
+
+    #[tokio::test]
+    async fn spilled_messages_cross_lump_stores() {
+        let post = PostOffice::new();
+        let a_lumps = Arc::new(LumpStoreImpl::new());
+        let b_lumps = Arc::new(LumpStoreImpl::new());
+        let (a_tx, b_rx) = flume::unbounded();
+        let (b_tx, a_rx) = flume::unbounded();
+        let a = Connection::begin(post.clone(), Some(a_lumps.clone()), a_rx, a_tx, None);
+        let (root_tx, root_rx) = oneshot::channel();
+        let b_lumps_conn = Some(b_lumps.clone());
+        let _b = Connection::begin(post.clone(), b_lumps_conn, b_rx, b_tx, Some(root_tx));
+
+        let table = Table::new(post);
+        let group = MailboxGroup::new(&table);
+        let mailbox = group.create_mailbox().unwrap();
+        a.export_root(mailbox.export(Permissions::SEND).unwrap().to_owned());
+
+        let root = root_rx.await.unwrap();
+        let root = table.import_owned(root).unwrap();
+        let root = table.wrap_handle(root).unwrap();
+        let payload = vec![7u8; SPILLOVER_THRESHOLD + 1];
+        let id = b_lumps.add_spill(payload.clone().into()).await;
+        root.send(&encode_spillover(&id), &[]).await.unwrap();
+
+        let Some(OwnedTableSignal::Message { data, .. }) = mailbox.recv_owned().await else {
+            panic!("expected a message");
+        };
+
+        assert!(b_lumps.get_local_lump(&id).await.is_none());
+        assert_eq!(decode_spillover(&data), Some(id));
+        assert_eq!(a_lumps.take_spill(&id).await.unwrap(), payload);
+        assert!(a_lumps.get_local_lump(&id).await.is_none());
+    }
 }
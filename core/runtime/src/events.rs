@@ -0,0 +1,82 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::broadcast;
+
+/// The number of events of each type that are buffered for slow subscribers
+/// before they begin to miss events.
+pub const EVENT_CAPACITY: usize = 64;
+
+/// A typed publish/subscribe bus for events between host plugins.
+///
+/// Each Rust type is its own topic. Plugins can announce things like window
+/// resizes or shutdown without knowing which other plugins, if any, are
+/// listening, so cross-cutting features don't need plugins to look each other
+/// up and hand off channels during building.
+///
+/// Clones of an event bus share the same topics.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    topics: Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>,
+}
+
+impl EventBus {
+    /// Creates a new event bus with no topics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes an event to all current subscribers of its type.
+    ///
+    /// Returns the number of subscribers that the event was sent to.
+    pub fn publish<T: Clone + Send + 'static>(&self, event: T) -> usize {
+        self.with_topic(|tx: &broadcast::Sender<T>| tx.send(event).unwrap_or(0))
+    }
+
+    /// Subscribes to all events of a type that are published from now on.
+    ///
+    /// A subscriber that falls more than [EVENT_CAPACITY] events behind skips
+    /// the oldest ones and receives [broadcast::error::RecvError::Lagged].
+    pub fn subscribe<T: Clone + Send + 'static>(&self) -> broadcast::Receiver<T> {
+        self.with_topic(|tx: &broadcast::Sender<T>| tx.subscribe())
+    }
+
+    /// Helper function to access the sender for a topic, creating it if it
+    /// doesn't exist yet.
+    fn with_topic<T: Clone + Send + 'static, R>(
+        &self,
+        f: impl FnOnce(&broadcast::Sender<T>) -> R,
+    ) -> R {
+        let mut topics = self.topics.lock();
+
+        let topic = topics
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(broadcast::channel::<T>(EVENT_CAPACITY).0));
+
+        f(topic.downcast_ref().unwrap())
+    }
+}
+
+/// Published when the host program is about to exit.
+#[derive(Clone, Copy, Debug)]
+pub struct Shutdown;
@@ -0,0 +1,309 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use flue::{CapabilityHandle, CapabilityRef, OwnedTableSignal, Permissions, Table};
+use hearth_schema::{codec::Codec, group::*};
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::process::{Process, ProcessMetadata};
+use crate::runtime::Runtime;
+use crate::utils::{
+    GetProcessMetadata, RequestInfo, RequestResponseProcess, ResponseInfo, ServiceRunner,
+};
+
+/// The native process group service. Accepts [GroupRequest].
+///
+/// Each group is its own process, so that messages sent to a group are
+/// broadcast without passing through this service.
+#[derive(Default)]
+pub struct ProcessGroupService {
+    groups: BTreeMap<String, Arc<Group>>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for ProcessGroupService {
+    type Request = GroupRequest;
+    type Response = GroupResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Request>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        let table = request.process.borrow_table();
+
+        match &request.data {
+            GroupRequest::Join { group } => {
+                let Some(member) = request.cap_args.first() else {
+                    return GroupError::MissingMember.into();
+                };
+
+                let group = self.get_or_create(group, request.runtime);
+                let id = group
+                    .join(member, request.cap_args.get(1), request.codec)
+                    .await;
+
+                ResponseInfo {
+                    data: Ok(GroupSuccess::Join(id)),
+                    caps: vec![group.export(table)],
+                }
+            }
+            GroupRequest::Leave { group, member } => {
+                let Some(group) = self.groups.get(group) else {
+                    return GroupError::NoSuchGroup.into();
+                };
+
+                if group.leave(*member).await {
+                    Ok(GroupSuccess::Leave).into()
+                } else {
+                    GroupError::NoSuchMember.into()
+                }
+            }
+            GroupRequest::Get { group } => {
+                let group = self.get_or_create(group, request.runtime);
+
+                ResponseInfo {
+                    data: Ok(GroupSuccess::Get),
+                    caps: vec![group.export(table)],
+                }
+            }
+            GroupRequest::List => {
+                let list = self
+                    .groups
+                    .iter()
+                    .map(|(name, group)| GroupInfo {
+                        name: name.clone(),
+                        members: group.members.lock().members.len(),
+                    })
+                    .collect();
+
+                Ok(GroupSuccess::List(list)).into()
+            }
+        }
+    }
+}
+
+impl GetProcessMetadata for ProcessGroupService {
+    fn get_process_metadata() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("ProcessGroupService".to_string()),
+            description: Some("The native process group service.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        }
+    }
+}
+
+impl ServiceRunner for ProcessGroupService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl ProcessGroupService {
+    /// Gets a group by name, spawning it if it doesn't exist yet.
+    fn get_or_create(&mut self, name: &str, runtime: &Arc<Runtime>) -> Arc<Group> {
+        if let Some(group) = self.groups.get(name) {
+            return group.clone();
+        }
+
+        let meta = ProcessMetadata {
+            name: Some(format!("Group {:?}", name)),
+            description: Some("A native process group.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        };
+
+        let group = Arc::new(Group {
+            process: runtime.process_factory.spawn(meta),
+            members: Default::default(),
+        });
+
+        tokio::spawn(group.clone().run());
+        self.groups.insert(name.to_string(), group.clone());
+        group
+    }
+}
+
+/// A member of a [Group], by its handles in the group's table.
+struct Member {
+    /// The capability that receives broadcast messages.
+    cap: CapabilityHandle,
+
+    /// The capability that receives [GroupSignal]s, if any.
+    signals: Option<CapabilityHandle>,
+
+    /// The codec of the member's join request, which its signals use.
+    codec: Codec,
+}
+
+#[derive(Default)]
+struct Members {
+    next_id: MemberId,
+    members: HashMap<MemberId, Member>,
+}
+
+/// A single process group.
+struct Group {
+    /// The group's process. Messages to its parent mailbox are broadcast.
+    process: Process,
+
+    members: Mutex<Members>,
+}
+
+impl Group {
+    /// Broadcasts messages and removes members that go down until the group's
+    /// process is killed.
+    async fn run(self: Arc<Self>) {
+        loop {
+            match self.process.borrow_parent().recv_owned().await {
+                Some(OwnedTableSignal::Message { data, caps }) => {
+                    let caps: Vec<_> = caps.iter().collect();
+                    for (member, ()) in self.wrap_members(|member| Some((member.cap, ()))) {
+                        if let Err(err) = member.send(&data, &caps).await {
+                            debug!("failed to broadcast to group member: {:?}", err);
+                        }
+                    }
+                }
+                Some(OwnedTableSignal::Down { handle }) => {
+                    let id = self
+                        .members
+                        .lock()
+                        .members
+                        .iter()
+                        .find(|(_, member)| member.cap == handle)
+                        .map(|(id, _)| *id);
+
+                    if let Some(id) = id {
+                        self.leave(id).await;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Exports a capability to send to this group into another table.
+    fn export<'a>(&self, table: &'a Table) -> CapabilityRef<'a> {
+        self.process
+            .borrow_parent()
+            .export_to(Permissions::SEND, table)
+            .unwrap()
+    }
+
+    /// Adds a member to this group and notifies the other members.
+    async fn join(
+        &self,
+        cap: &CapabilityRef<'_>,
+        signals: Option<&CapabilityRef<'_>>,
+        codec: Codec,
+    ) -> MemberId {
+        let table = self.process.borrow_table();
+        let cap = table.import_owned(cap.to_owned()).unwrap();
+        let signals = signals.map(|cap| table.import_owned(cap.to_owned()).unwrap());
+
+        // members without the monitor permission stay until they leave
+        let _ = table.monitor(cap, self.process.borrow_parent());
+
+        let (id, count) = {
+            let mut members = self.members.lock();
+            let id = members.next_id;
+            members.next_id += 1;
+            members.members.insert(
+                id,
+                Member {
+                    cap,
+                    signals,
+                    codec,
+                },
+            );
+            (id, members.members.len())
+        };
+
+        self.notify(GroupSignal::Joined {
+            member: id,
+            members: count,
+        })
+        .await;
+
+        id
+    }
+
+    /// Removes a member from this group and notifies the remaining members.
+    ///
+    /// Returns false if there is no member with the given ID.
+    async fn leave(&self, id: MemberId) -> bool {
+        let (member, count) = {
+            let mut members = self.members.lock();
+            let Some(member) = members.members.remove(&id) else {
+                return false;
+            };
+
+            (member, members.members.len())
+        };
+
+        let table = self.process.borrow_table();
+        let _ = table.dec_ref(member.cap);
+        if let Some(signals) = member.signals {
+            let _ = table.dec_ref(signals);
+        }
+
+        self.notify(GroupSignal::Left {
+            member: id,
+            members: count,
+        })
+        .await;
+
+        true
+    }
+
+    /// Sends a signal to every member that asked for signals.
+    async fn notify(&self, signal: GroupSignal) {
+        let mut encoded = HashMap::new();
+        for (cap, codec) in self.wrap_members(|member| Some((member.signals?, member.codec))) {
+            let data = encoded
+                .entry(codec)
+                .or_insert_with(|| codec.encode(&signal));
+
+            if let Err(err) = cap.send(data, &[]).await {
+                debug!("failed to send group signal: {:?}", err);
+            }
+        }
+    }
+
+    /// Creates new references to a capability of each member.
+    ///
+    /// Done while the members are locked so that a concurrent leave can't
+    /// free the capabilities first.
+    fn wrap_members<T>(
+        &self,
+        select: impl Fn(&Member) -> Option<(CapabilityHandle, T)>,
+    ) -> Vec<(CapabilityRef<'_>, T)> {
+        let table = self.process.borrow_table();
+        let members = self.members.lock();
+        members
+            .members
+            .values()
+            .filter_map(select)
+            .filter_map(|(handle, extra)| {
+                table.inc_ref(handle).ok()?;
+                Some((table.wrap_handle(handle).ok()?, extra))
+            })
+            .collect()
+    }
+}
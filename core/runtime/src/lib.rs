@@ -23,6 +23,7 @@ use tracing::{debug, error, info, Level};
 use tracing_subscriber::prelude::*;
 
 pub use anyhow;
+pub use clap;
 pub use flue;
 pub use hearth_macros;
 pub use hearth_schema;
@@ -32,9 +33,21 @@ pub use tracing;
 /// Asset loading and storage.
 pub mod asset;
 
+/// Auditing of the capability operations performed by processes.
+pub mod audit;
+
+/// Command-line interface composition.
+pub mod cli;
+
 /// Network connection.
 pub mod connection;
 
+/// Typed event publishing between host plugins.
+pub mod events;
+
+/// Named process groups with broadcast sends.
+pub mod group;
+
 /// Lump loading and storage.
 pub mod lump;
 
@@ -47,9 +60,18 @@ pub mod registry;
 /// Peer runtime building and execution.
 pub mod runtime;
 
+/// Snapshot files of runtime state for hearth-ctl to read.
+pub mod snapshot;
+
+/// Ring buffers for streaming bulk data from guests to host plugins.
+pub mod stream;
+
 /// Utilities for host-side runtime management.
 pub mod utils;
 
+/// Diagnostics for processes blocked waiting for replies.
+pub mod waits;
+
 /// Helper function to set up console logging with reasonable defaults.
 pub fn init_logging() {
     let filter = tracing_subscriber::filter::Targets::new()
@@ -86,6 +108,16 @@ pub fn get_config_dir() -> PathBuf {
         .to_owned()
 }
 
+/// Gets the system directory for persistent Hearth data files.
+///
+/// Panics if something fails for whatever reason.
+pub fn get_data_dir() -> PathBuf {
+    directories::ProjectDirs::from("rs", "hearth", "hearth")
+        .expect("Failed to get Hearth project directories")
+        .data_dir()
+        .to_owned()
+}
+
 /// Gets the default path of the main Hearth configuration file.
 ///
 /// Panics if something fails for whatever reason.
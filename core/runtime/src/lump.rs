@@ -16,55 +16,321 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
+use async_trait::async_trait;
 use bytes::{Buf, Bytes};
+use hearth_schema::lump::{LumpRequest, SERVICE_NAME};
 use hearth_schema::*;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
-use tracing::debug;
+use tracing::{debug, warn};
+
+use crate::process::ProcessMetadata;
+use crate::snapshot::{unix_millis, write_snapshots};
+use crate::utils::{
+    GetProcessMetadata, RequestInfo, RequestResponseProcess, ResponseInfo, ServiceRunner,
+};
 
 pub use bytes;
 
+/// The name of the lump store usage file in the data directory.
+pub const LUMP_USAGE_FILE: &str = "lumps.json";
+
+/// The name of the directory in the data directory that persisted lumps are
+/// stored in. See [LumpStoreImpl::persist_to].
+pub const LUMP_DIR: &str = "lumps";
+
 #[derive(Debug)]
 struct Lump {
     data: Bytes,
+
+    /// Whether this lump has been added with [LumpStoreImpl::add_lump] and
+    /// is kept for good.
+    pinned: bool,
+
+    /// The number of spilled-over messages with this lump as their payload
+    /// that haven't been resolved yet.
+    spills: usize,
 }
 
-#[derive(Debug, Default)]
+/// A remote source of the lumps that are missing from a [LumpStoreImpl].
+#[async_trait]
+pub trait LumpFetcher: Send + Sync + 'static {
+    /// Fetches the contents of a lump. Returns `None` if it can't be found.
+    async fn fetch_lump(&self, id: &LumpId) -> Option<Bytes>;
+}
+
+#[derive(Default)]
 pub struct LumpStoreImpl {
     store: RwLock<HashMap<LumpId, Lump>>,
+    fetcher: Mutex<Option<Arc<dyn LumpFetcher>>>,
+
+    /// The directory that pinned lumps are persisted in, if any.
+    dir: Mutex<Option<PathBuf>>,
+}
+
+impl std::fmt::Debug for LumpStoreImpl {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("LumpStoreImpl")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
 }
 
 impl LumpStoreImpl {
     pub fn new() -> Self {
-        Self {
-            store: Default::default(),
-        }
+        Self::default()
     }
 
-    pub async fn add_lump(&self, data: Bytes) -> LumpId {
-        let id = LumpId(
-            blake3::Hasher::new()
-                .update(data.chunk())
-                .finalize()
-                .as_bytes()
-                .to_owned(),
-        );
+    /// Sets the fetcher that [Self::get_lump] falls back to for lumps that
+    /// aren't in this store, replacing any previous one.
+    pub fn set_fetcher(&self, fetcher: Arc<dyn LumpFetcher>) {
+        *self.fetcher.lock() = Some(fetcher);
+    }
+
+    /// Persists every lump added with [Self::add_lump] from now on as a file
+    /// in `dir`, and loads lumps that aren't in memory from it.
+    ///
+    /// Servers persist their lumps in the [LUMP_DIR] of the data directory,
+    /// so that they're kept across restarts and included in backups.
+    pub fn persist_to(&self, dir: PathBuf) {
+        *self.dir.lock() = Some(dir);
+    }
 
+    pub async fn add_lump(&self, data: Bytes) -> LumpId {
+        let id = hash_lump(&data);
         let mut store = self.store.write().await;
-        store.entry(id).or_insert_with(|| {
-            debug!("Storing lump {}", id);
-            Lump { data }
-        });
+        let lump = store.entry(id).or_insert_with(|| new_lump(id, data));
+        let newly_pinned = !lump.pinned;
+        lump.pinned = true;
+
+        if newly_pinned {
+            let data = lump.data.clone();
+            drop(store);
+            self.persist(&id, &data).await;
+        }
+
+        id
+    }
 
+    /// Stores the payload of a spilled-over message in a temporary lump.
+    ///
+    /// The lump is freed once every message spilled over into it has been
+    /// taken with [Self::take_spill], unless it's also added with
+    /// [Self::add_lump].
+    pub async fn add_spill(&self, data: Bytes) -> LumpId {
+        let id = hash_lump(&data);
+        let mut store = self.store.write().await;
+        store.entry(id).or_insert_with(|| new_lump(id, data)).spills += 1;
         id
     }
 
+    /// Takes the payload of a spilled-over message, freeing its lump if no
+    /// other messages are spilled over into it.
+    ///
+    /// Falls back to [Self::get_lump] for payloads spilled by other runtimes.
+    pub async fn take_spill(&self, id: &LumpId) -> Option<Bytes> {
+        {
+            let mut store = self.store.write().await;
+            if let Some(lump) = store.get_mut(id) {
+                let data = lump.data.clone();
+                lump.spills = lump.spills.saturating_sub(1);
+
+                if lump.spills == 0 && !lump.pinned {
+                    debug!("Freeing spilled lump {}", id);
+                    store.remove(id);
+                }
+
+                return Some(data);
+            }
+        }
+
+        self.get_lump(id).await
+    }
+
+    /// Gets the contents of a lump, fetching it into this store if it's
+    /// missing and a fetcher has been set.
     pub async fn get_lump(&self, id: &LumpId) -> Option<Bytes> {
-        self.store
-            .read()
-            .await
-            .get(id)
-            .map(|lump| lump.data.clone())
+        if let Some(data) = self.get_local_lump(id).await {
+            return Some(data);
+        }
+
+        let fetcher = self.fetcher.lock().clone()?;
+        let data = fetcher.fetch_lump(id).await?;
+
+        if self.add_lump(data.clone()).await != *id {
+            warn!("Fetched lump {} has the wrong contents", id);
+            return None;
+        }
+
+        Some(data)
+    }
+
+    /// Gets the contents of a lump only if it's already in this store or
+    /// persisted by it.
+    pub async fn get_local_lump(&self, id: &LumpId) -> Option<Bytes> {
+        if let Some(lump) = self.store.read().await.get(id) {
+            return Some(lump.data.clone());
+        }
+
+        let path = self.dir.lock().as_ref()?.join(id.to_string());
+        let data: Bytes = tokio::fs::read(&path).await.ok()?.into();
+
+        if hash_lump(&data) != *id {
+            warn!("Persisted lump {:?} has the wrong contents", path);
+            return None;
+        }
+
+        let mut store = self.store.write().await;
+        store
+            .entry(*id)
+            .or_insert_with(|| new_lump(*id, data.clone()))
+            .pinned = true;
+        Some(data)
     }
+
+    /// Writes a pinned lump to the persisted lump directory, if there is
+    /// one, logging any errors.
+    async fn persist(&self, id: &LumpId, data: &Bytes) {
+        let Some(dir) = self.dir.lock().clone() else {
+            return;
+        };
+
+        let path = dir.join(id.to_string());
+        if path.exists() {
+            return;
+        }
+
+        // write to a separate file first so that partial lumps are never
+        // loaded
+        let partial = path.with_extension("partial");
+        let result = async {
+            tokio::fs::create_dir_all(&dir).await?;
+            tokio::fs::write(&partial, data).await?;
+            tokio::fs::rename(&partial, &path).await
+        };
+
+        if let Err(err) = result.await {
+            warn!("Failed to persist lump {}: {:?}", id, err);
+        }
+    }
+
+    /// Lists the IDs of every lump in this store.
+    pub async fn lump_ids(&self) -> Vec<LumpId> {
+        self.store.read().await.keys().copied().collect()
+    }
+
+    /// Measures the number of lumps in this store and their total size.
+    pub async fn usage(&self) -> LumpUsage {
+        let store = self.store.read().await;
+        LumpUsage {
+            updated: unix_millis() / 1000,
+            count: store.len(),
+            bytes: store.values().map(|lump| lump.data.len() as u64).sum(),
+        }
+    }
+
+    /// Periodically writes this store's [LumpUsage] to the [LUMP_USAGE_FILE]
+    /// for hearth-ctl to read.
+    pub async fn write_usage(&self, period: Duration) {
+        write_snapshots(LUMP_USAGE_FILE, period, move || async move {
+            Some(self.usage().await)
+        })
+        .await;
+    }
+
+    /// Resolves the data of a message, taking its payload from this store
+    /// with [Self::take_spill] if it has been spilled over into a lump.
+    ///
+    /// Returns `None` if the payload lump can't be found.
+    pub async fn resolve_spillover<'a>(&self, data: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        match decode_spillover(data) {
+            Some(id) => self.take_spill(&id).await.map(|data| data.to_vec().into()),
+            None => Some(data.into()),
+        }
+    }
+}
+
+/// Computes the [LumpId] of some lump data.
+pub fn hash_lump(data: &Bytes) -> LumpId {
+    LumpId(
+        blake3::Hasher::new()
+            .update(data.chunk())
+            .finalize()
+            .as_bytes()
+            .to_owned(),
+    )
+}
+
+fn new_lump(id: LumpId, data: Bytes) -> Lump {
+    debug!("Storing lump {}", id);
+    Lump {
+        data,
+        pinned: false,
+        spills: 0,
+    }
+}
+
+/// The contents of the lump store usage file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LumpUsage {
+    /// When this file was written, in seconds since the Unix epoch.
+    pub updated: u64,
+
+    /// The number of lumps in the store.
+    pub count: usize,
+
+    /// The total size of every lump in the store, in bytes.
+    pub bytes: u64,
+}
+
+/// A native service that adds lumps to a runtime's [LumpStoreImpl].
+///
+/// Gives processes without access to the guest lump ABI, such as IPC clients,
+/// a way to upload lumps.
+pub struct LumpStoreService {
+    lumps: Arc<LumpStoreImpl>,
+}
+
+impl LumpStoreService {
+    /// Creates a service for the given lump store.
+    pub fn new(lumps: Arc<LumpStoreImpl>) -> Self {
+        Self { lumps }
+    }
+}
+
+#[async_trait]
+impl RequestResponseProcess for LumpStoreService {
+    type Request = LumpRequest;
+    type Response = LumpId;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, LumpRequest>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        match &mut request.data {
+            LumpRequest::Upload { data } => {
+                let data = std::mem::take(data);
+                self.lumps.add_lump(data.into()).await.into()
+            }
+        }
+    }
+}
+
+impl GetProcessMetadata for LumpStoreService {
+    fn get_process_metadata() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("LumpStoreService".to_string()),
+            description: Some("Adds lumps to the native lump store.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        }
+    }
+}
+
+impl ServiceRunner for LumpStoreService {
+    const NAME: &'static str = SERVICE_NAME;
 }
@@ -18,13 +18,33 @@
 
 #![warn(missing_docs)]
 
-use std::sync::{atomic::AtomicUsize, Arc};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
 
-use flue::{Mailbox, MailboxGroup, PostOffice, Table};
-use hearth_schema::ProcessLogLevel;
+use async_trait::async_trait;
+use flue::{Mailbox, MailboxGroup, OwnedCapability, Permissions, PostOffice, Table};
+use hearth_schema::process::{
+    ProcessEntry, ProcessStoreError, ProcessStoreRequest, ProcessStoreResponse,
+    ProcessStoreSuccess, SERVICE_NAME,
+};
+use hearth_schema::{LumpId, ProcessLogLevel};
 use ouroboros::self_referencing;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use tracing::{debug, Span};
 
+use crate::runtime::Runtime;
+use crate::snapshot::unix_millis;
+use crate::utils::{
+    GetProcessMetadata, ProcessRunner, RequestInfo, RequestResponseProcess, ResponseInfo,
+    ServiceRunner,
+};
+
+/// How many log records a lagging log subscriber can fall behind by.
+const LOG_CAPACITY: usize = 1024;
+
 /// A local Hearth process. The main entrypoint for Hearth programming.
 #[self_referencing]
 pub struct Process {
@@ -68,17 +88,68 @@ pub struct ProcessInfo {
 
     /// This process's [ProcessMetdata].
     pub meta: ProcessMetadata,
+
+    /// The store that lists this process while it's alive.
+    pub(crate) store: Weak<ProcessStore>,
+}
+
+impl ProcessInfo {
+    /// Logs an event from this process.
+    ///
+    /// The event is emitted within [Self::process_span] and sent to the
+    /// subscribers of this process's [ProcessStore].
+    pub fn log(&self, event: ProcessLogEvent) {
+        let module = &event.module;
+        let content = &event.content;
+        self.process_span.in_scope(|| match event.level {
+            ProcessLogLevel::Trace => tracing::trace!(module, "{content}"),
+            ProcessLogLevel::Debug => tracing::debug!(module, "{content}"),
+            ProcessLogLevel::Info => tracing::info!(module, "{content}"),
+            ProcessLogLevel::Warning => tracing::warn!(module, "{content}"),
+            ProcessLogLevel::Error => tracing::error!(module, "{content}"),
+        });
+
+        if let Some(store) = self.store.upgrade() {
+            store.log(ProcessLogRecord {
+                pid: self.pid,
+                name: self.meta.name.clone(),
+                time: unix_millis(),
+                event,
+            });
+        }
+    }
 }
 
 impl Drop for ProcessInfo {
     fn drop(&mut self) {
         debug!("despawning PID {}", self.pid);
+
+        if let Some(store) = self.store.upgrade() {
+            store.remove(self.pid);
+        }
+    }
+}
+
+impl ProcessInfo {
+    /// Checks that a message of `len` bytes sent by this process is within
+    /// its store's [ProcessStore::max_message_size].
+    pub fn check_message_size(&self, len: usize) -> Result<(), usize> {
+        let Some(store) = self.store.upgrade() else {
+            return Ok(());
+        };
+
+        let limit = store.max_message_size();
+        if len > limit {
+            Err(limit)
+        } else {
+            Ok(())
+        }
     }
 }
 
 /// Static metadata about a process.
 #[non_exhaustive]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ProcessMetadata {
     /// A short, human-readable identifier for this process's function.
     pub name: Option<String>,
@@ -97,12 +168,16 @@ pub struct ProcessMetadata {
 
     /// An SPDX license identifier of this process's software license.
     pub license: Option<String>,
+
+    /// The lump that this process's code was loaded from, if any.
+    pub lump: Option<LumpId>,
 }
 
 /// A factory for making local instances of [Process].
 pub struct ProcessFactory {
     post: Arc<PostOffice>,
     pid_gen: AtomicUsize,
+    store: Arc<ProcessStore>,
 }
 
 impl ProcessFactory {
@@ -111,17 +186,21 @@ impl ProcessFactory {
         Self {
             post,
             pid_gen: AtomicUsize::new(0),
+            store: Default::default(),
         }
     }
 
+    /// Gets the store of the live processes spawned by this factory.
+    pub fn store(&self) -> &Arc<ProcessStore> {
+        &self.store
+    }
+
     /// Spawns a process with an existing [Table].
     pub fn spawn_with_table(&self, meta: ProcessMetadata, table: Table) -> Process {
         // this results in guessable PIDs, but access to PIDs and operations
         // consuming PIDs is limited to the debugging infrastructure, which
         // should not be given to untrusted processes.
-        let pid = self
-            .pid_gen
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let pid = self.pid_gen.fetch_add(1, Ordering::Relaxed);
 
         debug!(%pid, ?meta, "spawning process");
 
@@ -134,18 +213,27 @@ impl ProcessFactory {
         let process_span =
             tracing::debug_span!(parent: None, "process", label = name, process_id = pid);
 
+        self.store.insert(pid, meta.clone());
+
         let id = ProcessInfo {
             pid,
             process_span,
             meta,
+            store: Arc::downgrade(&self.store),
         };
 
-        Process::new(
+        let process = Process::new(
             table,
             id,
             |table| MailboxGroup::new(table),
             |store| store.create_mailbox().unwrap(),
-        )
+        );
+
+        let parent = process.borrow_parent();
+        let cap = parent.export(Permissions::all()).unwrap().to_owned();
+        self.store.set_cap(pid, cap);
+
+        process
     }
 
     /// Spawns a process with a new table in this factory's [PostOffice].
@@ -154,8 +242,184 @@ impl ProcessFactory {
     }
 }
 
+/// The live processes spawned by a [ProcessFactory].
+pub struct ProcessStore {
+    processes: Mutex<BTreeMap<ProcessId, StoredProcess>>,
+
+    /// Broadcasts the log events of every live process.
+    logs: broadcast::Sender<ProcessLogRecord>,
+
+    /// The largest message in bytes that untrusted processes may send.
+    max_message_size: AtomicUsize,
+}
+
+impl Default for ProcessStore {
+    fn default() -> Self {
+        Self {
+            processes: Default::default(),
+            logs: broadcast::channel(LOG_CAPACITY).0,
+            max_message_size: AtomicUsize::new(usize::MAX),
+        }
+    }
+}
+
+impl ProcessStore {
+    /// Gets the largest message in bytes that untrusted processes may send.
+    ///
+    /// Unlimited until [Self::set_max_message_size] is called.
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size.load(Ordering::Relaxed)
+    }
+
+    /// Sets the largest message in bytes that untrusted processes may send.
+    ///
+    /// Process runtimes that run untrusted code check the messages sent by
+    /// their processes with [ProcessInfo::check_message_size].
+    pub fn set_max_message_size(&self, limit: usize) {
+        self.max_message_size.store(limit, Ordering::Relaxed);
+    }
+
+    /// Lists every live process, sorted by PID.
+    pub fn list(&self) -> Vec<ProcessEntry> {
+        self.processes
+            .lock()
+            .iter()
+            .map(|(pid, process)| ProcessEntry {
+                pid: hearth_schema::ProcessId(*pid as u32),
+                name: process.meta.name.clone(),
+                description: process.meta.description.clone(),
+                authors: process.meta.authors.clone().unwrap_or_default(),
+                lump: process.meta.lump,
+            })
+            .collect()
+    }
+
+    /// Gets a capability to a live process's parent mailbox with every
+    /// permission.
+    ///
+    /// Like PIDs themselves, this should only be exposed to debugging
+    /// infrastructure.
+    pub fn get_cap(&self, pid: ProcessId) -> Option<OwnedCapability> {
+        self.processes.lock().get(&pid)?.cap.clone()
+    }
+
+    /// Subscribes to the log events of every live process.
+    pub fn subscribe_logs(&self) -> broadcast::Receiver<ProcessLogRecord> {
+        self.logs.subscribe()
+    }
+
+    fn log(&self, record: ProcessLogRecord) {
+        // no subscribers is not an error
+        let _ = self.logs.send(record);
+    }
+
+    fn insert(&self, pid: ProcessId, meta: ProcessMetadata) {
+        let process = StoredProcess { meta, cap: None };
+        self.processes.lock().insert(pid, process);
+    }
+
+    fn set_cap(&self, pid: ProcessId, cap: OwnedCapability) {
+        if let Some(process) = self.processes.lock().get_mut(&pid) {
+            process.cap = Some(cap);
+        }
+    }
+
+    fn remove(&self, pid: ProcessId) {
+        self.processes.lock().remove(&pid);
+    }
+}
+
+/// A native service that gives access to the processes in a [ProcessStore]
+/// by their IDs.
+///
+/// It isn't registered in the runtime's registry. The daemon spawns it with
+/// [Self::spawn_owned] and registers it for IPC clients only.
+pub struct ProcessStoreService {
+    store: Arc<ProcessStore>,
+}
+
+impl ProcessStoreService {
+    /// Creates a service for the given store.
+    pub fn new(store: Arc<ProcessStore>) -> Self {
+        Self { store }
+    }
+
+    /// Spawns this service in a new process and returns a capability to it.
+    pub fn spawn_owned(self, runtime: Arc<Runtime>) -> OwnedCapability {
+        let child = runtime.process_factory.spawn(Self::get_process_metadata());
+        let perms = Permissions::SEND | Permissions::MONITOR;
+        let cap = child.borrow_parent().export(perms).unwrap().to_owned();
+        self.spawn("ProcessStoreService".to_string(), runtime, child);
+        cap
+    }
+}
+
+#[async_trait]
+impl RequestResponseProcess for ProcessStoreService {
+    type Request = ProcessStoreRequest;
+    type Response = ProcessStoreResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, ProcessStoreRequest>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        match &request.data {
+            ProcessStoreRequest::Get { pid } => {
+                let Some(cap) = self.store.get_cap(pid.0 as ProcessId) else {
+                    return ProcessStoreError::NoSuchProcess.into();
+                };
+
+                // sending to a process's parent would impersonate its parent
+                let table = request.process.borrow_table();
+                let cap = table.import_owned(cap).unwrap();
+                let cap = table.wrap_handle(cap).unwrap();
+                let perms = Permissions::KILL | Permissions::MONITOR;
+                ResponseInfo {
+                    data: Ok(ProcessStoreSuccess::Get),
+                    caps: vec![cap.demote(perms).unwrap()],
+                }
+            }
+            ProcessStoreRequest::List { filter } => {
+                let processes = self
+                    .store
+                    .list()
+                    .into_iter()
+                    .filter(|process| filter.matches(process))
+                    .collect();
+
+                ResponseInfo {
+                    data: Ok(ProcessStoreSuccess::List(processes)),
+                    caps: vec![],
+                }
+            }
+        }
+    }
+}
+
+impl GetProcessMetadata for ProcessStoreService {
+    fn get_process_metadata() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("ProcessStoreService".to_string()),
+            description: Some("Gives access to local processes by their IDs.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        }
+    }
+}
+
+impl ServiceRunner for ProcessStoreService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// A live process in a [ProcessStore].
+struct StoredProcess {
+    meta: ProcessMetadata,
+
+    /// A capability to the process's parent mailbox, set once it's created.
+    cap: Option<OwnedCapability>,
+}
+
 /// Log event emitted by a process.
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, Deserialize, Serialize)]
 pub struct ProcessLogEvent {
     /// The level of this log event.
     pub level: ProcessLogLevel,
@@ -165,6 +429,78 @@ pub struct ProcessLogEvent {
 
     /// The main message body of the log event.
     pub content: String,
+
+    /// Structured key-value fields attached to the event.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
     // TODO optional source code location?
-    // TODO serializeable timestamp?
+}
+
+/// A [ProcessLogEvent] sent to the log subscribers of a [ProcessStore].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProcessLogRecord {
+    /// The ID of the process that logged this event.
+    pub pid: ProcessId,
+
+    /// The name of the process that logged this event, if it has one.
+    pub name: Option<String>,
+
+    /// When this event was logged, in milliseconds since the Unix epoch.
+    pub time: u64,
+
+    /// The logged event itself.
+    #[serde(flatten)]
+    pub event: ProcessLogEvent,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("Terminal".to_string()),
+            description: Some("A terminal emulator.".to_string()),
+            authors: Some(vec!["Alice <alice@example.com>".to_string()]),
+            lump: Some(LumpId([0xab; 32])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn store_forgets_despawned_processes() {
+        let factory = ProcessFactory::new(PostOffice::new());
+        let process = factory.spawn(meta());
+        let pid = process.borrow_info().pid;
+
+        let pids: Vec<_> = factory
+            .store()
+            .list()
+            .iter()
+            .map(|p| p.pid.0 as usize)
+            .collect();
+        assert_eq!(pids, vec![pid]);
+
+        drop(process);
+        assert!(factory.store().list().is_empty());
+    }
+
+    #[test]
+    fn store_broadcasts_logs() {
+        let factory = ProcessFactory::new(PostOffice::new());
+        let mut logs = factory.store().subscribe_logs();
+        let process = factory.spawn(meta());
+
+        process.borrow_info().log(ProcessLogEvent {
+            level: ProcessLogLevel::Info,
+            module: "test".to_string(),
+            content: "hello".to_string(),
+            fields: BTreeMap::new(),
+        });
+
+        let record = logs.try_recv().unwrap();
+        assert_eq!(record.pid, process.borrow_info().pid);
+        assert_eq!(record.name.as_deref(), Some("Terminal"));
+        assert_eq!(record.event.content, "hello");
+    }
 }
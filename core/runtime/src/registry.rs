@@ -17,16 +17,30 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
-    collections::{hash_map, HashMap},
+    collections::{hash_map, BTreeMap, BTreeSet, HashMap},
     sync::Arc,
+    time::Duration,
 };
 
 use async_trait::async_trait;
-use flue::{CapabilityHandle, Mailbox, Permissions, PostOffice, Table};
+use flue::{
+    CapabilityHandle, Mailbox, OwnedCapability, Permissions, PostOffice, Table, TableSignal,
+};
+use hearth_schema::codec::{self, Codec};
 use hearth_schema::registry::*;
-use tracing::warn;
+use parking_lot::Mutex;
+use tracing::{debug, warn};
+
+use crate::process::{Process, ProcessMetadata};
+use crate::runtime::Runtime;
+use crate::utils::{
+    GetProcessMetadata, ProcessRunner, RequestInfo, RequestResponseProcess, ResponseInfo,
+    ServiceRunner,
+};
 
-use crate::utils::{RequestInfo, RequestResponseProcess, ResponseInfo};
+/// How long the [PeerRegistry] and [FilteredRegistry] wait for the registries
+/// that they forward requests to.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// A builder to initialize the service entries in a [Registry], since they
 /// can't be modified once the registry has started.
@@ -90,28 +104,20 @@ impl RequestResponseProcess for Registry {
     ) -> ResponseInfo<'a, Self::Response> {
         use RegistryRequest::*;
         match &request.data {
-            Get { name } => {
-                if let Some(handle) = self.services.get(name) {
-                    let cap = request.process.with_table(|table| {
-                        table.inc_ref(*handle).unwrap();
-                        table.wrap_handle(*handle).unwrap()
-                    });
-
-                    ResponseInfo {
-                        data: RegistryResponse::Get(true),
-                        caps: vec![cap],
-                    }
-                } else {
-                    ResponseInfo {
-                        data: RegistryResponse::Get(false),
-                        caps: vec![],
-                    }
-                }
-            }
+            Get { name } => self.get(request.process, name),
             Register { .. } => ResponseInfo {
                 data: RegistryResponse::Register(None),
                 caps: vec![],
             },
+            Watch { name } => watch_once(self.get(request.process, name)),
+            Remove { .. } => ResponseInfo {
+                data: RegistryResponse::Remove(None),
+                caps: vec![],
+            },
+            Scope { .. } => ResponseInfo {
+                data: RegistryResponse::Scope(false),
+                caps: vec![],
+            },
             List => ResponseInfo {
                 data: RegistryResponse::List(
                     self.services.keys().map(ToString::to_string).collect(),
@@ -121,3 +127,441 @@ impl RequestResponseProcess for Registry {
         }
     }
 }
+
+impl Registry {
+    /// Looks up a service and gives it to the requesting process.
+    fn get<'a>(&self, process: &'a Process, name: &str) -> ResponseInfo<'a, RegistryResponse> {
+        let Some(handle) = self.services.get(name) else {
+            return RegistryResponse::Get(false).into();
+        };
+
+        let cap = process.with_table(|table| {
+            table.inc_ref(*handle).unwrap();
+            table.wrap_handle(*handle).unwrap()
+        });
+
+        ResponseInfo {
+            data: RegistryResponse::Get(true),
+            caps: vec![cap],
+        }
+    }
+}
+
+/// Answers a [RegistryRequest::Watch] to a registry that doesn't send
+/// notifications with its answer to a [RegistryRequest::Get] of the same
+/// service.
+///
+/// These registries reply to watches once, with the service if they have it
+/// and with `None` if they don't.
+fn watch_once(get: ResponseInfo<'_, RegistryResponse>) -> ResponseInfo<'_, RegistryResponse> {
+    let present = matches!(get.data, RegistryResponse::Get(true));
+    ResponseInfo {
+        data: RegistryResponse::Watch(present.then_some(true)),
+        caps: get.caps,
+    }
+}
+
+/// A native service that creates read-only [Registry] processes from the
+/// capabilities sent to it.
+///
+/// Lets processes hand out subsets of the services they have access to
+/// without running a registry of their own.
+#[derive(Default)]
+pub struct RegistryFactory;
+
+#[async_trait]
+impl RequestResponseProcess for RegistryFactory {
+    type Request = RegistryFactoryRequest;
+    type Response = RegistryFactoryResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, RegistryFactoryRequest>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        let names = &request.data.names;
+        if names.len() > request.cap_args.len() {
+            return RegistryFactoryError::MissingServices.into();
+        }
+
+        let meta = ProcessMetadata {
+            name: Some("Registry".to_string()),
+            description: Some("A read-only service registry.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        };
+
+        let child = request.runtime.process_factory.spawn(meta);
+        let table = child.borrow_table();
+        let mut registry = Registry::default();
+        for (name, cap) in names.iter().zip(request.cap_args.iter()) {
+            let handle = table.import_owned(cap.to_owned()).unwrap();
+            if let Some(old) = registry.services.insert(name.clone(), handle) {
+                table.dec_ref(old).unwrap();
+            }
+        }
+
+        let perms = Permissions::SEND | Permissions::MONITOR;
+        let cap = child
+            .borrow_parent()
+            .export_to(perms, request.process.borrow_table())
+            .unwrap();
+
+        registry.spawn("Registry".to_string(), request.runtime.clone(), child);
+
+        ResponseInfo {
+            data: Ok(()),
+            caps: vec![cap],
+        }
+    }
+}
+
+impl GetProcessMetadata for RegistryFactory {
+    fn get_process_metadata() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("RegistryFactory".to_string()),
+            description: Some("Creates read-only service registries.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        }
+    }
+}
+
+impl ServiceRunner for RegistryFactory {
+    const NAME: &'static str = REGISTRY_FACTORY_SERVICE_NAME;
+}
+
+/// A host-side implementation of the federated peer registry.
+///
+/// Peers' network root capabilities are added with [Self::add_peer] as their
+/// connections begin. Lookups are forwarded to each peer's root in order of
+/// connection until one has the service, so processes can discover services
+/// on other peers without knowing which peer provides them. Peers whose roots
+/// can no longer be sent to are forgotten.
+///
+/// Clones of this struct share the same set of peers.
+#[derive(Clone, Default)]
+pub struct PeerRegistry {
+    peers: Arc<Mutex<Peers>>,
+}
+
+#[derive(Default)]
+struct Peers {
+    next_id: u64,
+    roots: BTreeMap<u64, OwnedCapability>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for PeerRegistry {
+    type Request = RegistryRequest;
+    type Response = RegistryResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, RegistryRequest>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        match &request.data {
+            RegistryRequest::Get { name } => self.get(request.process, name).await,
+            RegistryRequest::Register { .. } => RegistryResponse::Register(None).into(),
+            RegistryRequest::Watch { name } => watch_once(self.get(request.process, name).await),
+            RegistryRequest::Remove { .. } => RegistryResponse::Remove(None).into(),
+            RegistryRequest::Scope { .. } => RegistryResponse::Scope(false).into(),
+            RegistryRequest::List => {
+                let mut names = BTreeSet::new();
+                for (id, root) in self.roots() {
+                    if let Some((RegistryResponse::List(list), _)) =
+                        self.query(request.process, id, root, &request.data).await
+                    {
+                        names.extend(list);
+                    }
+                }
+
+                RegistryResponse::List(names.into_iter().collect()).into()
+            }
+        }
+    }
+}
+
+impl GetProcessMetadata for PeerRegistry {
+    fn get_process_metadata() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("PeerRegistry".to_string()),
+            description: Some(
+                "A registry of the services exported by connected peers.".to_string(),
+            ),
+            ..crate::utils::cargo_process_metadata!()
+        }
+    }
+}
+
+impl ServiceRunner for PeerRegistry {
+    const NAME: &'static str = PEER_REGISTRY_SERVICE_NAME;
+}
+
+impl PeerRegistry {
+    /// Adds a connected peer's network root, which must implement the
+    /// registry protocol.
+    pub fn add_peer(&self, root: OwnedCapability) {
+        let mut peers = self.peers.lock();
+        let id = peers.next_id;
+        peers.next_id += 1;
+        peers.roots.insert(id, root);
+    }
+
+    /// Gets the roots of all peers in order of connection.
+    fn roots(&self) -> Vec<(u64, OwnedCapability)> {
+        let peers = self.peers.lock();
+        peers
+            .roots
+            .iter()
+            .map(|(id, root)| (*id, root.clone()))
+            .collect()
+    }
+
+    /// Looks up a service from the first peer that has it and gives it to
+    /// the requesting process.
+    async fn get<'a>(
+        &self,
+        process: &'a Process,
+        name: &str,
+    ) -> ResponseInfo<'a, RegistryResponse> {
+        let table = process.borrow_table();
+        let request = RegistryRequest::Get {
+            name: name.to_string(),
+        };
+
+        for (id, root) in self.roots() {
+            let Some((response, caps)) = self.query(process, id, root, &request).await else {
+                continue;
+            };
+
+            let (RegistryResponse::Get(true), Some(cap)) = (response, caps.into_iter().next())
+            else {
+                continue;
+            };
+
+            if let Ok(cap) = table.import_owned(cap) {
+                return ResponseInfo {
+                    data: RegistryResponse::Get(true),
+                    caps: vec![table.wrap_handle(cap).unwrap()],
+                };
+            }
+        }
+
+        RegistryResponse::Get(false).into()
+    }
+
+    /// Sends a request to a peer's root and waits for its response.
+    ///
+    /// Returns `None` if the peer doesn't respond in time. Forgets the peer if
+    /// its root can't be sent to.
+    async fn query(
+        &self,
+        ctx: &Process,
+        id: u64,
+        root: OwnedCapability,
+        request: &RegistryRequest,
+    ) -> Option<(RegistryResponse, Vec<OwnedCapability>)> {
+        match query_registry(ctx, root, request, vec![]).await {
+            Ok(response) => response,
+            Err(()) => {
+                debug!("forgetting unreachable peer registry");
+                self.peers.lock().roots.remove(&id);
+                None
+            }
+        }
+    }
+}
+
+/// A host-side registry that forwards lookups to another registry, but hides
+/// the services that a filter rejects.
+///
+/// Lets a process hand out a restricted view of a registry that it doesn't
+/// know the contents of, like a peer's network root. The view is read-only.
+pub struct FilteredRegistry {
+    inner: OwnedCapability,
+    filter: Box<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for FilteredRegistry {
+    type Request = RegistryRequest;
+    type Response = RegistryResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, RegistryRequest>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        match &request.data {
+            RegistryRequest::Get { name } => self.get(request.process, name).await,
+            RegistryRequest::Register { .. } => RegistryResponse::Register(None).into(),
+            RegistryRequest::Watch { name } => watch_once(self.get(request.process, name).await),
+            RegistryRequest::Remove { .. } => RegistryResponse::Remove(None).into(),
+            RegistryRequest::Scope { .. } => RegistryResponse::Scope(false).into(),
+            RegistryRequest::List => {
+                let inner = self.inner.clone();
+                let response = query_registry(request.process, inner, &request.data, vec![]).await;
+                let Ok(Some((RegistryResponse::List(names), _))) = response else {
+                    return RegistryResponse::List(vec![]).into();
+                };
+
+                let names = names.into_iter().filter(|name| (self.filter)(name));
+                RegistryResponse::List(names.collect()).into()
+            }
+        }
+    }
+}
+
+impl FilteredRegistry {
+    /// Creates a filtered view of a registry. Only the services whose names
+    /// pass the filter can be looked up.
+    pub fn new(
+        inner: OwnedCapability,
+        filter: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            inner,
+            filter: Box::new(filter),
+        }
+    }
+
+    /// Looks up a service that passes the filter from the inner registry
+    /// and gives it to the requesting process.
+    async fn get<'a>(
+        &self,
+        process: &'a Process,
+        name: &str,
+    ) -> ResponseInfo<'a, RegistryResponse> {
+        if !(self.filter)(name) {
+            return RegistryResponse::Get(false).into();
+        }
+
+        let request = RegistryRequest::Get {
+            name: name.to_string(),
+        };
+
+        let response = query_registry(process, self.inner.clone(), &request, vec![]).await;
+        let Ok(Some((RegistryResponse::Get(true), caps))) = response else {
+            return RegistryResponse::Get(false).into();
+        };
+
+        let Some(cap) = caps.into_iter().next() else {
+            return RegistryResponse::Get(false).into();
+        };
+
+        let table = process.borrow_table();
+        match table.import_owned(cap) {
+            Ok(cap) => ResponseInfo {
+                data: RegistryResponse::Get(true),
+                caps: vec![table.wrap_handle(cap).unwrap()],
+            },
+            Err(_) => RegistryResponse::Get(false).into(),
+        }
+    }
+
+    /// Spawns this registry in a new process and returns a capability to it.
+    pub fn spawn_owned(self, runtime: Arc<Runtime>) -> OwnedCapability {
+        let child = runtime.process_factory.spawn(Self::get_process_metadata());
+        let perms = Permissions::SEND | Permissions::MONITOR;
+        let cap = child.borrow_parent().export(perms).unwrap().to_owned();
+        self.spawn("FilteredRegistry".to_string(), runtime, child);
+        cap
+    }
+}
+
+impl GetProcessMetadata for FilteredRegistry {
+    fn get_process_metadata() -> ProcessMetadata {
+        ProcessMetadata {
+            name: Some("FilteredRegistry".to_string()),
+            description: Some("A filtered, read-only view of another registry.".to_string()),
+            ..crate::utils::cargo_process_metadata!()
+        }
+    }
+}
+
+/// Registers a service in another registry on behalf of `ctx`.
+///
+/// Returns true if the registry accepted the service.
+pub async fn register_service(
+    ctx: &Process,
+    registry: OwnedCapability,
+    name: &str,
+    service: OwnedCapability,
+) -> bool {
+    let request = RegistryRequest::Register {
+        name: name.to_string(),
+    };
+
+    let response = query_registry(ctx, registry, &request, vec![service]).await;
+    matches!(response, Ok(Some((RegistryResponse::Register(Some(_)), _))))
+}
+
+/// Sends a request to another registry and waits for its response. `caps`
+/// are attached after the reply capability.
+///
+/// Returns `Ok(None)` if the registry doesn't respond in time and `Err` if it
+/// can't be sent to.
+async fn query_registry(
+    ctx: &Process,
+    registry: OwnedCapability,
+    request: &RegistryRequest,
+    caps: Vec<OwnedCapability>,
+) -> Result<Option<(RegistryResponse, Vec<OwnedCapability>)>, ()> {
+    let table = ctx.borrow_table();
+    let Some(registry) = table
+        .import_owned(registry)
+        .ok()
+        .and_then(|handle| table.wrap_handle(handle).ok())
+    else {
+        return Ok(None);
+    };
+
+    let Ok(reply) = ctx.borrow_group().create_mailbox() else {
+        return Ok(None);
+    };
+
+    let Ok(reply_cap) = reply.export(Permissions::SEND) else {
+        return Ok(None);
+    };
+
+    let caps: Vec<_> = caps
+        .into_iter()
+        .filter_map(|cap| table.import_owned(cap).ok())
+        .filter_map(|handle| table.wrap_handle(handle).ok())
+        .collect();
+
+    let mut attached = vec![&reply_cap];
+    attached.extend(caps.iter());
+
+    // registries may belong to older peers that only parse JSON
+    let data = Codec::Json.encode(request);
+    if let Err(err) = registry.send(&data, &attached).await {
+        debug!("registry is unreachable: {:?}", err);
+        return Err(());
+    }
+
+    let recv = reply.recv(|signal| {
+        let TableSignal::Message { data, caps } = signal else {
+            return None;
+        };
+
+        // take ownership of the caps so that they're freed on failure
+        let caps = caps
+            .iter()
+            .filter_map(|handle| {
+                let cap = table.get_owned(*handle).ok();
+                let _ = table.dec_ref(*handle);
+                cap
+            })
+            .collect();
+
+        let response = codec::decode(data).ok()?;
+        Some((response, caps))
+    });
+
+    match tokio::time::timeout(QUERY_TIMEOUT, recv).await {
+        Ok(Some(response)) => Ok(response),
+        Ok(None) => Ok(None),
+        Err(_) => {
+            debug!("registry timed out");
+            Ok(None)
+        }
+    }
+}
@@ -25,17 +25,23 @@
 use std::any::{Any, TypeId};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use flue::PostOffice;
+use serde::Deserialize;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tracing::{debug, error, warn};
 
 use crate::asset::{AssetLoader, AssetStore};
-use crate::lump::LumpStoreImpl;
-use crate::process::{Process, ProcessFactory, ProcessMetadata};
-use crate::registry::RegistryBuilder;
+use crate::audit::CapAudit;
+use crate::events::EventBus;
+use crate::lump::{LumpStoreImpl, LumpStoreService};
+use crate::process::{Process, ProcessFactory, ProcessMetadata, ProcessStore};
+use crate::registry::{PeerRegistry, RegistryBuilder, RegistryFactory};
+use crate::stream::StreamStore;
 use crate::utils::ProcessRunner;
+use crate::waits::WaitGraph;
 
 /// Interface trait for plugins to the Hearth runtime.
 ///
@@ -71,8 +77,10 @@ pub struct RuntimeBuilder {
     services: HashSet<String>,
     lump_store: Arc<LumpStoreImpl>,
     post: Arc<PostOffice>,
+    event_bus: EventBus,
     process_factory: ProcessFactory,
     registry_builder: RegistryBuilder,
+    peers: PeerRegistry,
     asset_store: AssetStore,
     service_num: usize,
     service_start_tx: UnboundedSender<String>,
@@ -88,21 +96,29 @@ impl RuntimeBuilder {
         let post = PostOffice::new();
         let process_factory = ProcessFactory::new(post.clone());
         let registry_builder = RegistryBuilder::new(post.clone());
+        let peers = PeerRegistry::default();
 
-        Self {
+        let mut builder = Self {
             plugins: Default::default(),
             plugin_order: Default::default(),
             runners: Default::default(),
             services: Default::default(),
-            lump_store,
+            lump_store: lump_store.clone(),
             post,
+            event_bus: EventBus::new(),
             process_factory,
             registry_builder,
+            peers: peers.clone(),
             asset_store,
             service_num: 0,
             service_start_tx,
             service_start_rx,
-        }
+        };
+
+        builder.add_plugin(peers);
+        builder.add_plugin(LumpStoreService::new(lump_store));
+        builder.add_plugin(RegistryFactory);
+        builder
     }
 
     /// Gets a handle to the post office that this runtime will be using.
@@ -110,6 +126,22 @@ impl RuntimeBuilder {
         self.post.clone()
     }
 
+    /// Gets a handle to the lump store that this runtime will be using.
+    pub fn get_lump_store(&self) -> Arc<LumpStoreImpl> {
+        self.lump_store.clone()
+    }
+
+    /// Gets a handle to the event bus that this runtime will be using.
+    pub fn get_event_bus(&self) -> EventBus {
+        self.event_bus.clone()
+    }
+
+    /// Gets a handle to the store of the processes that this runtime will be
+    /// spawning.
+    pub fn get_process_store(&self) -> Arc<ProcessStore> {
+        self.process_factory.store().clone()
+    }
+
     /// Adds a plugin to the runtime.
     ///
     /// Plugins may use their [Plugin::build] method to add other plugins,
@@ -252,15 +284,31 @@ impl RuntimeBuilder {
         let ctx = self.process_factory.spawn_with_table(meta, registry_table);
         let registry = Arc::new(ctx);
 
+        let store = self.process_factory.store();
+        store.set_max_message_size(config.message_size_limit());
+
+        let audit = Arc::new(CapAudit::new(self.post.clone(), config.audit_capacity));
+        let stall_detection = config.stall_threshold > 0.0;
+        let waits = Arc::new(WaitGraph::new(self.post.clone(), stall_detection));
         let runtime = Arc::new(Runtime {
             asset_store: Arc::new(self.asset_store),
             lump_store: self.lump_store,
             config,
             post: self.post,
+            event_bus: self.event_bus,
             process_factory: self.process_factory,
             registry: registry.clone(),
+            peers: self.peers,
+            waits,
+            audit,
+            streams: Default::default(),
         });
 
+        if runtime.waits.is_enabled() {
+            let threshold = Duration::from_secs_f32(runtime.config.stall_threshold);
+            tokio::spawn(runtime.waits.clone().watch(threshold));
+        }
+
         registry_inner.spawn("Registry".to_string(), runtime.clone(), registry);
 
         debug!("Running runners");
@@ -286,8 +334,68 @@ impl RuntimeBuilder {
     }
 }
 
-/// Configuration info for a runtime.
-pub struct RuntimeConfig {}
+/// Configuration info for a runtime, read from the `runtime` table of the
+/// config file.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// The maximum size in bytes of the data in a single message sent by a
+    /// guest process.
+    ///
+    /// Process runtimes that run untrusted code check this limit through
+    /// [crate::process::ProcessStore::max_message_size]. Native processes are
+    /// trusted and aren't limited.
+    ///
+    /// Guest send helpers spill payloads larger than
+    /// [hearth_schema::SPILLOVER_THRESHOLD] over into lumps, so limits below
+    /// that are raised to it.
+    pub max_message_size: usize,
+
+    /// The number of seconds a process can wait for a reply before a request
+    /// cycle that it's part of is logged. Set to zero to disable stall
+    /// detection.
+    pub stall_threshold: f32,
+
+    /// The number of capability operations retained by the runtime's
+    /// [CapAudit] log. Set to zero to disable auditing.
+    pub audit_capacity: usize,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            max_message_size: 1024 * 1024,
+            stall_threshold: 10.0,
+            audit_capacity: 4096,
+        }
+    }
+}
+
+impl RuntimeConfig {
+    /// Loads a runtime config from the `runtime` table of a config file.
+    ///
+    /// Logs an error and uses the default configuration if the table is
+    /// malformed.
+    pub fn from_config_file(config: &toml::Table) -> Self {
+        let Some(table) = config.get("runtime") else {
+            return Self::default();
+        };
+
+        match table.clone().try_into() {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Failed to parse runtime config: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Gets the effective message size limit. See [Self::max_message_size].
+    pub fn message_size_limit(&self) -> usize {
+        self.max_message_size
+            .max(hearth_schema::SPILLOVER_THRESHOLD)
+    }
+}
 
 /// An instance of a single Hearth runtime.
 ///
@@ -310,6 +418,9 @@ pub struct Runtime {
     /// This runtime's post office.
     pub post: Arc<PostOffice>,
 
+    /// The bus for events between this runtime's plugins.
+    pub event_bus: EventBus,
+
     /// This runtime's local process factory.
     pub process_factory: ProcessFactory,
 
@@ -317,4 +428,19 @@ pub struct Runtime {
     ///
     /// Access the `parent` field on it to gain a capability to it.
     pub registry: Arc<Process>,
+
+    /// The registry of the services exported by connected peers.
+    ///
+    /// Connections add their peers' network roots to it with
+    /// [PeerRegistry::add_peer].
+    pub peers: PeerRegistry,
+
+    /// The processes in this runtime that are blocked waiting for replies.
+    pub waits: Arc<WaitGraph>,
+
+    /// The log of capability operations performed by processes.
+    pub audit: Arc<CapAudit>,
+
+    /// The ring buffers that guests stream bulk data to host plugins with.
+    pub streams: Arc<StreamStore>,
 }
@@ -0,0 +1,77 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::warn;
+
+/// Reads a snapshot file written by [write] or [write_snapshots].
+pub fn read<T: DeserializeOwned>(path: &Path) -> std::io::Result<T> {
+    let data = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Writes a snapshot file as JSON, replacing the old one atomically.
+pub fn write<T: Serialize>(path: &Path, snapshot: &T) -> std::io::Result<()> {
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, serde_json::to_vec(snapshot)?)?;
+    std::fs::rename(partial, path)
+}
+
+/// Periodically writes snapshots to the file `name` within the data
+/// directory.
+///
+/// `snapshot` is called every `period` and returns `None` to skip writing
+/// when nothing has changed. Gives up if a snapshot fails to write.
+pub async fn write_snapshots<T, F, Fut>(name: &str, period: Duration, mut snapshot: F)
+where
+    T: Serialize,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let dir = crate::get_data_dir();
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        warn!("Failed to create data directory: {:?}", err);
+        return;
+    }
+
+    let path = dir.join(name);
+    loop {
+        tokio::time::sleep(period).await;
+
+        let Some(snapshot) = snapshot().await else {
+            continue;
+        };
+
+        if let Err(err) = write(&path, &snapshot) {
+            warn!("Failed to write {:?}: {:?}", path, err);
+            return;
+        }
+    }
+}
+
+/// Gets the current time in milliseconds since the Unix epoch.
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
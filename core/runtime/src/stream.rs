@@ -0,0 +1,229 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+use std::sync::{Arc, Weak};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+/// The largest capacity of a [RingBuffer] in bytes.
+pub const MAX_STREAM_CAPACITY: usize = 64 * 1024 * 1024;
+
+/// A bounded queue of byte frames streamed from a guest to a host plugin.
+///
+/// Streams bypass message serialization and mailboxes for high-rate bulk
+/// data like canvas pixels. Producers copy frames straight into the buffer,
+/// and writes fail instead of blocking when the buffer is full, so producers
+/// should drop or coalesce frames until the consumer catches up.
+pub struct RingBuffer {
+    state: Mutex<RingState>,
+    notify: Notify,
+}
+
+struct RingState {
+    frames: VecDeque<Bytes>,
+    capacity: usize,
+    used: usize,
+    closed: bool,
+}
+
+impl RingBuffer {
+    /// Creates an empty ring buffer holding at most `capacity` bytes of
+    /// frames, clamped to [MAX_STREAM_CAPACITY].
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(RingState {
+                frames: VecDeque::new(),
+                capacity: capacity.min(MAX_STREAM_CAPACITY),
+                used: 0,
+                closed: false,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Appends a frame to the buffer.
+    ///
+    /// Returns false if the frame doesn't fit in the buffer's free space or if
+    /// the buffer has been closed.
+    pub fn push(&self, frame: &[u8]) -> bool {
+        let mut state = self.state.lock();
+        if state.closed || state.used + frame.len() > state.capacity {
+            return false;
+        }
+
+        state.used += frame.len();
+        state.frames.push_back(Bytes::copy_from_slice(frame));
+        drop(state);
+
+        self.notify.notify_one();
+        true
+    }
+
+    /// Takes the oldest frame from the buffer without waiting.
+    pub fn try_pop(&self) -> Option<Bytes> {
+        let mut state = self.state.lock();
+        let frame = state.frames.pop_front()?;
+        state.used -= frame.len();
+        Some(frame)
+    }
+
+    /// Waits for the next frame.
+    ///
+    /// Returns `None` once the buffer has been closed and drained.
+    pub async fn recv(&self) -> Option<Bytes> {
+        loop {
+            let notified = self.notify.notified();
+
+            if let Some(frame) = self.try_pop() {
+                return Some(frame);
+            }
+
+            if self.state.lock().closed {
+                return None;
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Closes the buffer. Later pushes fail, and [Self::recv] returns `None`
+    /// once the remaining frames are drained.
+    pub fn close(&self) {
+        self.state.lock().closed = true;
+        self.notify.notify_waiters();
+    }
+
+    /// Returns true if this buffer has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().closed
+    }
+}
+
+/// Hands out the ring buffers of a runtime to guests by token.
+///
+/// Host plugins create streams and give their tokens to a guest in a reply,
+/// and the guest opens them through the Wasm ABI. Tokens are unguessable, so
+/// only processes that were sent a token can write to its stream. The store
+/// only keeps weak references, so streams are freed once both ends drop them.
+pub struct StreamStore {
+    streams: Mutex<HashMap<u64, Weak<RingBuffer>>>,
+    hasher: RandomState,
+    next: Mutex<u64>,
+}
+
+impl Default for StreamStore {
+    fn default() -> Self {
+        Self {
+            streams: Default::default(),
+            hasher: RandomState::new(),
+            next: Mutex::new(0),
+        }
+    }
+}
+
+impl StreamStore {
+    /// Creates a new ring buffer with the given capacity in bytes and returns
+    /// its token.
+    pub fn create(&self, capacity: usize) -> (u64, Arc<RingBuffer>) {
+        let ring = Arc::new(RingBuffer::new(capacity));
+        let mut streams = self.streams.lock();
+        streams.retain(|_, stream| stream.strong_count() > 0);
+
+        let token = loop {
+            let mut next = self.next.lock();
+            let token = self.hasher.hash_one(*next);
+            *next += 1;
+
+            if !streams.contains_key(&token) {
+                break token;
+            }
+        };
+
+        streams.insert(token, Arc::downgrade(&ring));
+        (token, ring)
+    }
+
+    /// Opens a stream by its token.
+    ///
+    /// Returns `None` if the token is unknown or its stream has been freed.
+    pub fn open(&self, token: u64) -> Option<Arc<RingBuffer>> {
+        self.streams.lock().get(&token)?.upgrade()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_until_full() {
+        let ring = RingBuffer::new(8);
+        assert!(ring.push(b"abcd"));
+        assert!(ring.push(b"efg"));
+        assert!(!ring.push(b"hi"));
+        assert!(!ring.push(&[0; 16]));
+
+        assert_eq!(ring.try_pop().as_deref(), Some(&b"abcd"[..]));
+        assert!(ring.push(b"hi"));
+        assert_eq!(ring.try_pop().as_deref(), Some(&b"efg"[..]));
+        assert_eq!(ring.try_pop().as_deref(), Some(&b"hi"[..]));
+        assert_eq!(ring.try_pop(), None);
+    }
+
+    #[tokio::test]
+    async fn close_drains_remaining_frames() {
+        let ring = Arc::new(RingBuffer::new(64));
+        ring.push(b"last");
+        ring.close();
+
+        assert!(!ring.push(b"late"));
+        assert_eq!(ring.recv().await.as_deref(), Some(&b"last"[..]));
+        assert_eq!(ring.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn recv_waits_for_push() {
+        let ring = Arc::new(RingBuffer::new(64));
+        let consumer = tokio::spawn({
+            let ring = ring.clone();
+            async move { ring.recv().await }
+        });
+
+        tokio::task::yield_now().await;
+        ring.push(b"frame");
+        assert_eq!(consumer.await.unwrap().as_deref(), Some(&b"frame"[..]));
+    }
+
+    #[test]
+    fn store_forgets_dropped_streams() {
+        let store = StreamStore::default();
+        let (token, ring) = store.create(64);
+        let (other, _other_ring) = store.create(64);
+        assert_ne!(token, other);
+
+        assert!(Arc::ptr_eq(&store.open(token).unwrap(), &ring));
+        drop(ring);
+        assert!(store.open(token).is_none());
+        assert!(store.open(token ^ other ^ 1).is_none());
+    }
+}
@@ -18,14 +18,15 @@
 
 use std::{
     any::type_name, borrow::Borrow, collections::HashMap, fmt::Debug, marker::PhantomData,
-    sync::Arc,
+    sync::Arc, time::Duration,
 };
 
 use async_trait::async_trait;
 use flue::{CapabilityHandle, CapabilityRef, OwnedTableSignal, Permissions, PostOffice, Table};
+use hearth_schema::codec::{Codec, DecodeError, TraceContext};
 use parking_lot::Mutex;
-use serde::{Deserialize, Serialize};
-use tracing::{debug, error, trace, Instrument};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, debug_span, error, trace, warn, Instrument, Span};
 
 use crate::{
     process::{Process, ProcessMetadata},
@@ -113,6 +114,17 @@ pub struct MessageInfo<'a, T> {
     /// The deserialized data of the message's contents.
     pub data: T,
 
+    /// The codec that the message's contents were encoded with.
+    ///
+    /// Replies should be encoded with the same codec so that guests which
+    /// predate binary codecs can read them.
+    pub codec: Codec,
+
+    /// The trace context that the message was sent with, if any.
+    ///
+    /// Messages sent on behalf of this one should carry a child of it.
+    pub trace: Option<TraceContext>,
+
     /// The capabilities from this message.
     pub caps: &'a [CapabilityRef<'a>],
 }
@@ -146,6 +158,17 @@ pub struct RequestInfo<'a, T> {
 
     /// The deserialized data of the message's contents.
     pub data: T,
+
+    /// The codec that the request was encoded with.
+    ///
+    /// The response is encoded with it automatically. Anything else sent to
+    /// the requester, like events for a subscription, should use it too.
+    pub codec: Codec,
+
+    /// The trace context that the request was sent with, if any.
+    ///
+    /// The response carries a child of it automatically.
+    pub trace: Option<TraceContext>,
 }
 
 impl<'a, T> RunnerContext<'a> for RequestInfo<'a, T> {
@@ -242,7 +265,31 @@ pub trait ProcessRunner: Send {
     }
 }
 
-/// A trait for process runners that continuously receive JSON-formatted messages of a single type.
+/// Decodes a message's contents and detects the codec they were encoded with
+/// and the trace context they were sent with.
+fn decode_message<T: DeserializeOwned>(
+    data: &[u8],
+) -> Result<(Codec, Option<TraceContext>, T), DecodeError> {
+    let (trace, payload) = TraceContext::split(data)?;
+    let codec = Codec::detect(payload)?;
+    Ok((codec, trace, codec.decode(payload)?))
+}
+
+/// Creates a tracing span for the handling of a message sent with a trace
+/// context, so that host-side subscribers can follow the trace.
+fn message_span(label: &str, trace: Option<TraceContext>) -> Span {
+    match trace {
+        Some(trace) => debug_span!(
+            "message",
+            label,
+            trace_id = %format!("{:032x}", trace.trace_id),
+            span_id = %format!("{:016x}", trace.span_id),
+        ),
+        None => Span::none(),
+    }
+}
+
+/// A trait for process runners that continuously receive serialized messages of a single type.
 ///
 /// This trait has a blanket implementation for [ProcessRunner] that loops and
 /// receives new messages of the given data type, and calls [Self::on_message]
@@ -280,7 +327,17 @@ where
             use OwnedTableSignal::*;
             match recv {
                 Some(Message { data, caps }) => {
-                    let data: T::Message = match serde_json::from_slice(&data) {
+                    if runtime.audit.is_enabled() {
+                        let caps = caps.iter().map(|cap| cap.to_owned()).collect();
+                        runtime.audit.record_receive(ctx.borrow_info(), caps);
+                    }
+
+                    let Some(data) = runtime.lump_store.resolve_spillover(&data).await else {
+                        debug!("{:?} received a message with a missing payload lump", label);
+                        continue;
+                    };
+
+                    let (codec, trace, data) = match decode_message::<T::Message>(&data) {
                         Ok(request) => request,
                         Err(err) => {
                             // TODO make this a process log
@@ -296,8 +353,11 @@ where
                         process: ctx,
                         runtime: &runtime,
                         data,
+                        codec,
+                        trace,
                         caps: &caps,
                     })
+                    .instrument(message_span(&label, trace))
                     .await;
 
                     trace!("{:?} finished processing message", label);
@@ -321,6 +381,16 @@ pub trait RequestResponseProcess: Send {
         request: &mut RequestInfo<'a, Self::Request>,
     ) -> ResponseInfo<'a, Self::Response>;
 
+    /// How long [Self::on_request] may take before the request is abandoned.
+    ///
+    /// Requests are handled one at a time, so a request that waits on a
+    /// stalled process would otherwise stall every request after it. An
+    /// abandoned request is logged and never replied to, which leaves the
+    /// requester to time out on its own. Defaults to no deadline.
+    fn deadline(&self) -> Option<Duration> {
+        None
+    }
+
     /// A callback to call when a down signal is received by this process.
     ///
     /// The capability passed is the capability in the down signal; a version
@@ -348,15 +418,47 @@ where
             cap_args: &message.caps[1..],
             runtime: message.runtime,
             data: message.data,
+            codec: message.codec,
+            trace: message.trace,
         };
 
-        let response = self.on_request(&mut request).await;
-        let data = serde_json::to_vec(&response.data).unwrap();
+        let deadline = self.deadline();
+        let response = self.on_request(&mut request);
+        let response = match deadline {
+            None => response.await,
+            Some(deadline) => match tokio::time::timeout(deadline, response).await {
+                Ok(response) => response,
+                Err(_) => {
+                    warn!(
+                        "{:?} abandoned a request after {:?}",
+                        message.label, deadline
+                    );
+                    return;
+                }
+            },
+        };
+
+        let mut data = message.codec.encode(&response.data);
+
+        // a request gets one reply, so its span has just the one child
+        if let Some(trace) = message.trace {
+            data = trace.child(0).wrap(&data);
+        }
+
         let caps: Vec<_> = response.caps.iter().collect();
         let result = reply.send(&data, &caps).await;
 
-        if let Err(err) = result {
-            debug!("{:?} reply error: {:?}", message.label, err);
+        match result {
+            Ok(_) if message.runtime.audit.is_enabled() => {
+                let caps = caps.iter().map(|cap| cap.to_owned()).collect();
+                message.runtime.audit.record_send(
+                    message.process.borrow_info(),
+                    reply.get_permissions(),
+                    caps,
+                );
+            }
+            Ok(_) => {}
+            Err(err) => debug!("{:?} reply error: {:?}", message.label, err),
         }
     }
 
@@ -366,6 +468,50 @@ where
     }
 }
 
+/// A native process that spawns configured child processes on request.
+///
+/// Each request carries a [Self::Config] describing the child to create. If
+/// the child is created successfully, it is spawned as a child of the
+/// factory and its capability is returned as the first capability of an
+/// `Ok(())` response. Otherwise, the error is returned with no capabilities.
+///
+/// This has a blanket implementation of [RequestResponseProcess], so
+/// factories can be run as services with [ServiceRunner].
+#[async_trait]
+pub trait FactoryProcess: Send {
+    type Config: for<'a> Deserialize<'a> + Send + Debug;
+    type Error: Serialize + Send + Debug;
+    type Child: ProcessRunner + GetProcessMetadata + 'static;
+
+    /// Creates a new child process from a spawn request's config.
+    async fn create_child<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Config>,
+    ) -> Result<Self::Child, Self::Error>;
+}
+
+#[async_trait]
+impl<T> RequestResponseProcess for T
+where
+    T: FactoryProcess,
+{
+    type Request = T::Config;
+    type Response = Result<(), T::Error>;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Request>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        match self.create_child(request).await {
+            Ok(child) => ResponseInfo {
+                data: Ok(()),
+                caps: vec![request.spawn(child)],
+            },
+            Err(err) => err.into(),
+        }
+    }
+}
+
 pub trait ServiceRunner: ProcessRunner + GetProcessMetadata {
     const NAME: &'static str;
 }
@@ -387,8 +533,9 @@ pub struct PubSub<T> {
     table: Table,
 
     /// A mutex-locked set of subscribers. Each entry maps a zero permission
-    /// capability as the index to a send-only capability for notifying.
-    subscribers: Mutex<HashMap<CapabilityHandle, CapabilityHandle>>,
+    /// capability as the index to a send-only capability for notifying and
+    /// the codec that the subscriber reads.
+    subscribers: Mutex<HashMap<CapabilityHandle, (CapabilityHandle, Codec)>>,
 
     /// We don't actually carry `T` in this struct, so we need to use it in a
     /// `PhantomData` so that the Rust compiler won't yell at us.
@@ -405,13 +552,15 @@ impl<T: Serialize> PubSub<T> {
         }
     }
 
-    /// Adds a subscriber. Does nothing if the capability is already subscribed.
+    /// Adds a subscriber that reads events encoded with `codec`, which should
+    /// be the codec of its subscription request. Replaces the codec if the
+    /// capability is already subscribed.
     ///
     /// The given capability can be from any table.
     ///
     /// Logs an error and doesn't subscribe if the cap doesn't have the send
     /// perm.
-    pub fn subscribe(&self, cap: CapabilityRef) {
+    pub fn subscribe(&self, cap: CapabilityRef, codec: Codec) {
         // ensure that we can store a send cap
         if !cap.get_permissions().contains(Permissions::SEND) {
             let name = std::any::type_name::<T>();
@@ -427,7 +576,7 @@ impl<T: Serialize> PubSub<T> {
         let mut subs = self.subscribers.lock();
 
         // insert subscriber into map and catch existing entries
-        if let Some(old_val) = subs.insert(key, val) {
+        if let Some((old_val, _)) = subs.insert(key, (val, codec)) {
             // manually decrement reference count for a duplicated subscriber
             self.table.dec_ref(key).unwrap();
             self.table.dec_ref(old_val).unwrap();
@@ -445,7 +594,7 @@ impl<T: Serialize> PubSub<T> {
         let mut subs = self.subscribers.lock();
 
         // remove subscriber from map and catch the old lifetime
-        if let Some(old_val) = subs.remove(&key) {
+        if let Some((old_val, _)) = subs.remove(&key) {
             // manually decrement reference count for removed subscriber
             self.table.dec_ref(key).unwrap();
             self.table.dec_ref(old_val).unwrap();
@@ -455,34 +604,30 @@ impl<T: Serialize> PubSub<T> {
         self.table.dec_ref(key).unwrap();
     }
 
-    /// Broadcasts an event to all current subscribers.
+    /// Broadcasts an event to all current subscribers, encoded with each
+    /// subscriber's codec.
     pub async fn notify(&self, event: &T) {
-        // attempt to serialize the event or gracefully fail
-        let data = match serde_json::to_vec(event) {
-            Ok(data) => data,
-            Err(err) => {
-                let name = std::any::type_name::<T>();
-                error!("Failed to serialize {}: {:?}", name, err);
-                return;
-            }
-        };
-
         // clone subscribers so that we can release the mutex during async
         let subscribers: Vec<_> = self
             .subscribers
             .lock()
             .values()
-            .map(|handle| {
+            .map(|(handle, codec)| {
                 // own handle while sending
                 self.table.inc_ref(*handle).unwrap();
-                *handle
+                (*handle, *codec)
             })
             .collect();
 
+        // encode the event once per codec
+        let mut encoded = HashMap::new();
+
         // send the event to all subscribers
-        for cap in subscribers {
+        for (cap, codec) in subscribers {
             // send event
-            self.table.send(cap, &data, &[]).await.unwrap();
+            let data = encoded.entry(codec).or_insert_with(|| codec.encode(event));
+
+            self.table.send(cap, data, &[]).await.unwrap();
 
             // free handle
             self.table.dec_ref(cap).unwrap();
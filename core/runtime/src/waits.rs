@@ -0,0 +1,340 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use flue::{CapabilityHandle, OwnedCapability, Permissions, PostOffice, Table};
+use parking_lot::Mutex;
+use tracing::warn;
+
+use crate::process::ProcessId;
+
+/// A mailbox of a process, identified by its process and the process's
+/// handle to it.
+type MailboxKey = (ProcessId, u32);
+
+/// A host-side graph of which processes are blocked waiting for replies from
+/// which other processes.
+///
+/// Guests that request each other synchronously (A requests B, which then
+/// requests A) wait on each other forever. Process runtimes tell the graph
+/// which process each mailbox belongs to and which mailboxes are sent along
+/// with requests as reply addresses. When a process waits on a reply
+/// mailbox, that gives the graph an edge from the waiting process to the
+/// process that the request was sent to, and [Self::check] reports the
+/// cycles of stalled processes that it finds by following those edges.
+pub struct WaitGraph {
+    /// A table holding the capabilities used to identify mailboxes.
+    table: Table,
+
+    /// Whether stall detection is enabled.
+    enabled: bool,
+
+    inner: Mutex<WaitGraphInner>,
+}
+
+#[derive(Default)]
+struct WaitGraphInner {
+    /// Maps capabilities in [WaitGraph::table], demoted to no permissions, to
+    /// the mailboxes that they route to.
+    mailboxes: HashMap<CapabilityHandle, MailboxKey>,
+
+    /// The process that each mailbox is expecting a reply from.
+    replies: HashMap<MailboxKey, ProcessId>,
+
+    /// The wait of each blocked process.
+    waits: HashMap<ProcessId, Wait>,
+}
+
+/// A single process's blocked wait.
+struct Wait {
+    /// The waiting process's label.
+    label: String,
+
+    /// When the wait began.
+    since: Instant,
+
+    /// The processes that this process is waiting for replies from.
+    on: Vec<ProcessId>,
+
+    /// Whether this wait has already been logged as part of a cycle.
+    reported: bool,
+}
+
+impl WaitGraph {
+    /// Creates a new wait graph. If `enabled` is false, nothing is recorded.
+    pub fn new(post: Arc<PostOffice>, enabled: bool) -> Self {
+        Self {
+            table: Table::new(post),
+            enabled,
+            inner: Default::default(),
+        }
+    }
+
+    /// Returns true if this graph records anything.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Records that a capability routes to the mailbox with the handle
+    /// `mailbox` in the process `pid`.
+    pub fn add_mailbox(&self, cap: OwnedCapability, pid: ProcessId, mailbox: u32) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let Some(key) = self.key(cap) else {
+            return;
+        };
+
+        let mut inner = self.inner.lock();
+        if inner.mailboxes.insert(key, (pid, mailbox)).is_some() {
+            // drop the duplicate reference to an already known capability
+            let _ = self.table.dec_ref(key);
+        }
+    }
+
+    /// Forgets a destroyed mailbox of a process.
+    pub fn remove_mailbox(&self, pid: ProcessId, mailbox: u32) {
+        let mut inner = self.inner.lock();
+        self.forget(&mut inner, |key| *key == (pid, mailbox));
+    }
+
+    /// Forgets all of the mailboxes and waits of an exited process.
+    pub fn remove_process(&self, pid: ProcessId) {
+        let mut inner = self.inner.lock();
+        inner.waits.remove(&pid);
+        self.forget(&mut inner, |(owner, _)| *owner == pid);
+    }
+
+    /// Records that the process `pid` sent a request to `target` with
+    /// `caps` attached.
+    ///
+    /// If the target is a known mailbox, the attached capabilities to the
+    /// sender's own mailboxes are expected to receive the target process's
+    /// reply.
+    pub fn record_request(
+        &self,
+        pid: ProcessId,
+        target: OwnedCapability,
+        caps: Vec<OwnedCapability>,
+    ) {
+        if !self.is_enabled() || caps.is_empty() {
+            return;
+        }
+
+        let Some((target, _)) = self.lookup(target) else {
+            return;
+        };
+
+        for cap in caps {
+            if let Some(key @ (owner, _)) = self.lookup(cap) {
+                if owner == pid {
+                    self.inner.lock().replies.insert(key, target);
+                }
+            }
+        }
+    }
+
+    /// Records that a process has begun waiting on some of its mailboxes.
+    ///
+    /// The wait ends when the returned guard is dropped.
+    pub fn begin_wait(&self, pid: ProcessId, label: &str, mailboxes: &[u32]) -> WaitGuard<'_> {
+        let mut inner = self.inner.lock();
+
+        let mut on = Vec::new();
+        for mailbox in mailboxes {
+            if let Some(target) = inner.replies.get(&(pid, *mailbox)) {
+                if !on.contains(target) {
+                    on.push(*target);
+                }
+            }
+        }
+
+        inner.waits.insert(
+            pid,
+            Wait {
+                label: label.to_string(),
+                since: Instant::now(),
+                on,
+                reported: false,
+            },
+        );
+
+        WaitGuard { graph: self, pid }
+    }
+
+    /// Logs the cycles of processes that have all been waiting on each other
+    /// for longer than `threshold` and have not already been reported.
+    pub fn check(&self, threshold: Duration) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+
+        // only follow edges between stalled processes
+        let stalled: HashMap<_, _> = inner
+            .waits
+            .iter()
+            .filter(|(_, wait)| now - wait.since >= threshold)
+            .map(|(pid, wait)| (*pid, wait))
+            .collect();
+
+        let mut starts: Vec<_> = stalled
+            .iter()
+            .filter(|(_, wait)| !wait.reported)
+            .map(|(pid, _)| *pid)
+            .collect();
+
+        starts.sort();
+
+        let mut cycles: Vec<Vec<ProcessId>> = Vec::new();
+        for start in starts {
+            if cycles.iter().any(|cycle| cycle.contains(&start)) {
+                continue;
+            }
+
+            let mut path = vec![start];
+            let mut visited = HashSet::from([start]);
+            if find_cycle(&stalled, start, &mut path, &mut visited) {
+                cycles.push(path);
+            }
+        }
+
+        for cycle in cycles {
+            let processes: Vec<_> = cycle
+                .iter()
+                .map(|pid| {
+                    let wait = inner.waits.get_mut(pid).unwrap();
+                    wait.reported = true;
+                    let elapsed = (now - wait.since).as_secs_f32();
+                    format!("{:?} (PID {}, {:.1}s)", wait.label, pid, elapsed)
+                })
+                .collect();
+
+            warn!(
+                "Request cycle between {} stalled processes: {}",
+                processes.len(),
+                processes.join(" -> ")
+            );
+        }
+    }
+
+    /// Periodically checks for stalled waits until the graph is dropped.
+    pub async fn watch(self: Arc<Self>, threshold: Duration) {
+        let weak = Arc::downgrade(&self);
+        drop(self);
+
+        let mut interval = tokio::time::interval(threshold / 2);
+        loop {
+            interval.tick().await;
+
+            let Some(graph) = weak.upgrade() else {
+                break;
+            };
+
+            graph.check(threshold);
+        }
+    }
+
+    /// Imports a capability into this graph's table as a key with no
+    /// permissions, which is shared by all capabilities to the same mailbox.
+    fn key(&self, cap: OwnedCapability) -> Option<CapabilityHandle> {
+        let handle = self.table.import_owned(cap).ok()?;
+        let cap = self.table.wrap_handle(handle).ok()?;
+        let key = cap.demote(Permissions::empty()).ok()?.into_handle();
+        Some(key)
+    }
+
+    /// Looks up the mailbox that a capability routes to.
+    fn lookup(&self, cap: OwnedCapability) -> Option<MailboxKey> {
+        let key = self.key(cap)?;
+        let mailbox = self.inner.lock().mailboxes.get(&key).copied();
+        let _ = self.table.dec_ref(key);
+        mailbox
+    }
+
+    /// Forgets the mailboxes matching a predicate and their expected
+    /// replies.
+    fn forget(&self, inner: &mut WaitGraphInner, mut predicate: impl FnMut(&MailboxKey) -> bool) {
+        inner.mailboxes.retain(|key, mailbox| {
+            let forget = predicate(mailbox);
+            if forget {
+                let _ = self.table.dec_ref(*key);
+            }
+
+            !forget
+        });
+
+        inner.replies.retain(|mailbox, _| !predicate(mailbox));
+    }
+}
+
+/// Follows the edges from the last process in `path` through the `stalled`
+/// processes, looking for a way back to `start`.
+///
+/// Returns true and leaves the cycle in `path` if one is found.
+fn find_cycle(
+    stalled: &HashMap<ProcessId, &Wait>,
+    start: ProcessId,
+    path: &mut Vec<ProcessId>,
+    visited: &mut HashSet<ProcessId>,
+) -> bool {
+    let current = *path.last().unwrap();
+    let Some(wait) = stalled.get(&current) else {
+        return false;
+    };
+
+    for next in wait.on.iter() {
+        if *next == start {
+            return true;
+        }
+
+        if stalled.contains_key(next) && visited.insert(*next) {
+            path.push(*next);
+
+            if find_cycle(stalled, start, path, visited) {
+                return true;
+            }
+
+            path.pop();
+        }
+    }
+
+    false
+}
+
+/// A guard that ends a process's wait in a [WaitGraph] when dropped.
+pub struct WaitGuard<'a> {
+    graph: &'a WaitGraph,
+    pid: ProcessId,
+}
+
+impl<'a> WaitGuard<'a> {
+    /// Ends the wait because one of its mailboxes received a signal, which
+    /// also fulfills that mailbox's expected reply.
+    pub fn received(self, mailbox: u32) {
+        self.graph.inner.lock().replies.remove(&(self.pid, mailbox));
+    }
+}
+
+impl<'a> Drop for WaitGuard<'a> {
+    fn drop(&mut self) {
+        self.graph.inner.lock().waits.remove(&self.pid);
+    }
+}
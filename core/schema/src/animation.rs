@@ -0,0 +1,123 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+use crate::LumpId;
+
+/// The name of the animation factory service.
+pub const SERVICE_NAME: &str = "hearth.animation.AnimationFactory";
+
+/// A request to the animation factory.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FactoryRequest {
+    /// Create a new animator.
+    ///
+    /// The first capability argument must be a renderer object capability
+    /// (created with a skeleton) to stream the resulting joint matrices to
+    /// with `ObjectUpdate::JointMatrices`.
+    ///
+    /// Returns a capability via [FactorySuccess::Animator] to an animator
+    /// instance, which receives [AnimatorUpdate] messages. The animator stops
+    /// when either the animator or the target object is killed.
+    CreateAnimator {
+        /// A lump containing a binary glTF (GLB) file with the animation
+        /// clips and skin to play.
+        model: LumpId,
+
+        /// The index of the skin within the glTF file to animate.
+        skin: usize,
+    },
+}
+
+/// Information about an animation clip available to an animator.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipInfo {
+    /// The name of this clip, if it has one.
+    pub name: Option<String>,
+
+    /// The duration of this clip in seconds.
+    pub duration: f32,
+}
+
+/// A success response from a [FactoryRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FactorySuccess {
+    /// An animator was successfully created.
+    ///
+    /// Contains the list of clips in the model, indexed by [AnimatorUpdate]
+    /// messages.
+    Animator { clips: Vec<ClipInfo> },
+}
+
+/// An error response from a [FactoryRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FactoryError {
+    /// The request has failed to parse.
+    ParseError,
+
+    /// The request is missing the target object capability.
+    MissingTarget,
+
+    /// The model lump failed to load or is not a valid binary glTF file.
+    LumpError,
+
+    /// The given skin index does not exist in the model.
+    InvalidSkin,
+}
+
+/// A type shorthand for [FactorySuccess] and [FactoryError].
+pub type FactoryResponse = Result<FactorySuccess, FactoryError>;
+
+/// A message to update an animator instance.
+///
+/// Clips are referred to by their index in the model. Any number of clips may
+/// play at once, in which case their poses are blended by their weights.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum AnimatorUpdate {
+    /// Start playing a clip from the beginning, replacing the clip's existing
+    /// playback if it is already playing.
+    Play {
+        /// The index of the clip to play.
+        clip: usize,
+
+        /// The blending weight of this clip.
+        weight: f32,
+
+        /// The playback speed multiplier.
+        speed: f32,
+
+        /// Whether to loop the clip. Non-looping clips hold their last pose.
+        looping: bool,
+    },
+
+    /// Stop playing a clip.
+    Stop { clip: usize },
+
+    /// Stop playing all clips and return to the skin's rest pose.
+    StopAll,
+
+    /// Set the blending weight of a playing clip.
+    SetWeight { clip: usize, weight: f32 },
+
+    /// Set the playback speed multiplier of a playing clip.
+    SetSpeed { clip: usize, speed: f32 },
+
+    /// Move the playhead of a playing clip to the given time in seconds.
+    Seek { clip: usize, time: f32 },
+}
@@ -0,0 +1,141 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Permissions, ProcessId};
+
+/// The name of the capability audit service.
+///
+/// The audit records what every process sends and receives, so the default
+/// policy denies it to guest services that don't opt in.
+pub const SERVICE_NAME: &str = "hearth.CapAudit";
+
+/// A single capability operation performed by a process.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CapAuditEvent {
+    /// The process sent a message through a capability with the `target`
+    /// permissions, attaching capabilities with the `caps` permissions.
+    Send {
+        target: Permissions,
+        caps: Vec<Permissions>,
+    },
+
+    /// The process received a message carrying capabilities with the `caps`
+    /// permissions.
+    ///
+    /// `from` is the sending process, if it could be determined. The host
+    /// can't see which process owns a capability, so grants are matched to
+    /// their sends by the contents of the message, and `from` may be `None`
+    /// for messages sent by processes that aren't audited.
+    Grant {
+        from: Option<ProcessId>,
+        caps: Vec<Permissions>,
+    },
+
+    /// The process killed a capability with the `target` permissions.
+    Kill { target: Permissions },
+}
+
+/// A recorded [CapAuditEvent].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CapAuditRecord {
+    /// The sequence number of this record. Increases by one for each record.
+    pub seq: u64,
+
+    /// When this event happened, in milliseconds since the UNIX epoch.
+    pub timestamp: u64,
+
+    /// The process that performed the operation.
+    pub pid: ProcessId,
+
+    /// The name of the process, if it has one.
+    pub label: Option<String>,
+
+    /// The operation itself.
+    pub event: CapAuditEvent,
+}
+
+impl CapAuditRecord {
+    /// Returns true if the given process performed this record's operation
+    /// or was the sender of its granted capabilities.
+    pub fn involves(&self, pid: ProcessId) -> bool {
+        match &self.event {
+            CapAuditEvent::Grant { from, .. } if *from == Some(pid) => true,
+            _ => self.pid == pid,
+        }
+    }
+}
+
+/// The totals of a single process's retained [CapAuditRecord]s.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ProcessCapSummary {
+    /// The process's ID.
+    pub pid: ProcessId,
+
+    /// The name of the process, if it has one.
+    pub label: Option<String>,
+
+    /// The number of messages this process has sent.
+    pub sends: u64,
+
+    /// The number of capabilities this process has given to other audited
+    /// processes.
+    pub given: u64,
+
+    /// The number of capabilities this process has received.
+    pub received: u64,
+
+    /// The number of capabilities this process has killed.
+    pub kills: u64,
+}
+
+/// A request to the capability audit service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CapAuditRequest {
+    /// Gets the most recent records, oldest first.
+    ///
+    /// If `pid` is set, only records performed by that process or granting
+    /// capabilities from it are returned. At most `limit` records are
+    /// returned.
+    ///
+    /// Returns [CapAuditSuccess::Records].
+    Records { pid: Option<ProcessId>, limit: u32 },
+
+    /// Totals the retained records of every process that has any.
+    ///
+    /// Returns [CapAuditSuccess::Summary], sorted by process ID.
+    Summary,
+}
+
+/// A success response from a [CapAuditRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CapAuditSuccess {
+    Records(Vec<CapAuditRecord>),
+    Summary(Vec<ProcessCapSummary>),
+}
+
+/// An error response from a [CapAuditRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CapAuditError {
+    /// Auditing is disabled in the runtime's configuration.
+    Disabled,
+}
+
+/// A type shorthand for [CapAuditSuccess] and [CapAuditError].
+pub type CapAuditResponse = Result<CapAuditSuccess, CapAuditError>;
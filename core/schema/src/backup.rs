@@ -0,0 +1,70 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the backup service.
+pub const SERVICE_NAME: &str = "hearth.Backup";
+
+/// Information about a backup archive.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackupInfo {
+    /// The file name of this archive.
+    pub name: String,
+
+    /// The size of this archive in bytes.
+    pub size: u64,
+
+    /// The time this archive was created in seconds since the UNIX epoch.
+    pub created: i64,
+}
+
+/// A request to the backup service.
+///
+/// The service replies to the first capability of the message if one is
+/// given, so requests may also be delivered by scheduled tasks that have no
+/// reply address.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum BackupRequest {
+    /// Archives the server's data and prunes old archives.
+    ///
+    /// Returns [BackupSuccess::Created] with the new archive.
+    Create,
+
+    /// Lists all backup archives from oldest to newest.
+    ///
+    /// Returns [BackupSuccess::List].
+    List,
+}
+
+/// A success response from a [BackupRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum BackupSuccess {
+    Created(BackupInfo),
+    List(Vec<BackupInfo>),
+}
+
+/// An error response from a [BackupRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum BackupError {
+    /// Creating or reading backups failed. Contains the error.
+    Failed(String),
+}
+
+/// A type shorthand for [BackupSuccess] and [BackupError].
+pub type BackupResponse = Result<BackupSuccess, BackupError>;
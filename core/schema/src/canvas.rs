@@ -18,13 +18,17 @@
 
 use glam::{Quat, Vec2, Vec3};
 use serde::{Deserialize, Serialize};
-use serde_with::{base64::Base64, serde_as};
+use serde_with::serde_as;
+
+use crate::{codec::CompactBytes, LumpId};
 
 /// A rectangular buffer of pixel data.
 #[serde_as]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Pixels {
     /// The width of the buffer, in pixels.
+    ///
+    /// The width and height must be between 1 and 8192.
     pub width: u32,
 
     /// The height of the buffer, in pixels.
@@ -35,7 +39,7 @@ pub struct Pixels {
     /// `width * height * 4` should match the length of `data`. Missing pixel
     /// data will be initialized with `0xff` for all components. Excess data
     /// is ignored.
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub data: Vec<u8>,
 }
 
@@ -54,6 +58,182 @@ pub struct Blit {
     pub pixels: Pixels,
 }
 
+impl Blit {
+    /// The size in bytes of the header of a blit's stream frame.
+    pub const FRAME_HEADER_LEN: usize = 16;
+
+    /// Encodes this blit as a frame for a canvas stream.
+    ///
+    /// Frames are the blit's X, Y, width, and height as little-endian `u32`s,
+    /// followed by its RGBA pixel data.
+    pub fn to_frame(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(Self::FRAME_HEADER_LEN + self.pixels.data.len());
+        for value in [self.x, self.y, self.pixels.width, self.pixels.height] {
+            frame.extend_from_slice(&value.to_le_bytes());
+        }
+
+        frame.extend_from_slice(&self.pixels.data);
+        frame
+    }
+
+    /// Decodes a blit from a canvas stream frame made by [Self::to_frame].
+    ///
+    /// Returns `None` if the frame is too short to have a header.
+    pub fn from_frame(frame: &[u8]) -> Option<Self> {
+        let header = frame.get(..Self::FRAME_HEADER_LEN)?;
+        let mut values = header
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+
+        let mut next = || values.next().unwrap();
+        let (x, y, width, height) = (next(), next(), next(), next());
+
+        Some(Self {
+            x,
+            y,
+            pixels: Pixels {
+                width,
+                height,
+                data: frame[Self::FRAME_HEADER_LEN..].to_vec(),
+            },
+        })
+    }
+}
+
+/// A rectangular region of a canvas, in pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rect {
+    /// The X coordinate of this region's origin.
+    pub x: u32,
+
+    /// The Y coordinate of this region's origin.
+    pub y: u32,
+
+    /// The width of this region.
+    pub width: u32,
+
+    /// The height of this region.
+    pub height: u32,
+}
+
+/// A run of text to rasterize into a target region of a canvas.
+///
+/// The text's bounding box is filled with `background` and then the text is
+/// drawn over it, so the region is replaced like a [Blit]. Only kerning is
+/// applied between glyphs; complex shaping (ligatures, bidirectional text) is
+/// not supported.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TextDraw {
+    /// The X coordinate of the left edge of the text in pixels.
+    pub x: u32,
+
+    /// The Y coordinate of the top of the text's first line in pixels.
+    pub y: u32,
+
+    /// The ID of a lump containing the TrueType or OpenType font to use.
+    pub font: LumpId,
+
+    /// The height of a line of text in pixels.
+    pub size: f32,
+
+    /// The RGBA color of the text.
+    pub color: [u8; 4],
+
+    /// The RGBA color of the text's background.
+    pub background: [u8; 4],
+
+    /// The text to draw. Each newline starts a new line of text.
+    pub text: String,
+}
+
+/// A segment of a vector path, in canvas pixel coordinates.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum PathSegment {
+    /// Starts a new subpath at a point.
+    MoveTo(Vec2),
+
+    /// Draws a straight line to a point.
+    LineTo(Vec2),
+
+    /// Draws a quadratic Bézier curve with a control point to a point.
+    QuadTo(Vec2, Vec2),
+
+    /// Draws a cubic Bézier curve with two control points to a point.
+    CubicTo(Vec2, Vec2, Vec2),
+
+    /// Closes the current subpath with a line back to its start.
+    Close,
+}
+
+/// How to color the area covered by a vector [DrawCommand].
+///
+/// Colors are RGBA in sRGB, and gradients are interpolated in linear space.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Paint {
+    /// A single solid color.
+    Solid([u8; 4]),
+
+    /// A gradient along the line from `start` to `end`.
+    LinearGradient {
+        start: Vec2,
+        end: Vec2,
+        start_color: [u8; 4],
+        end_color: [u8; 4],
+    },
+
+    /// A gradient from a center point out to a radius.
+    RadialGradient {
+        center: Vec2,
+        radius: f32,
+        inner_color: [u8; 4],
+        outer_color: [u8; 4],
+    },
+}
+
+/// A single command in a [DisplayList].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum DrawCommand {
+    /// Fills the inside of a path using the non-zero fill rule.
+    Fill {
+        path: Vec<PathSegment>,
+        paint: Paint,
+    },
+
+    /// Strokes the outline of a path with lines of the given width.
+    Stroke {
+        path: Vec<PathSegment>,
+        width: f32,
+        paint: Paint,
+    },
+
+    /// Fills the outlines of a run of text. Laid out like a [TextDraw].
+    Text {
+        /// The position of the top-left corner of the text.
+        position: Vec2,
+
+        /// The ID of a lump containing the TrueType or OpenType font to use.
+        font: LumpId,
+
+        /// The height of a line of text in pixels.
+        size: f32,
+
+        paint: Paint,
+        text: String,
+    },
+}
+
+/// A list of vector drawing commands that are rendered on the GPU.
+///
+/// Commands are drawn in order, each over the last, with anti-aliasing.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DisplayList {
+    /// The RGBA color to clear the canvas to before drawing.
+    pub background: [u8; 4],
+
+    /// The commands to draw.
+    pub commands: Vec<DrawCommand>,
+}
+
 /// The positioning of a canvas in 3D space.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Position {
@@ -87,6 +267,56 @@ pub enum CanvasUpdate {
 
     /// Blit a buffer to a part of this canvas.
     Blit(Blit),
+
+    /// Blit many buffers to the damaged parts of this canvas at once.
+    ///
+    /// The blits are applied in order. Prefer this over [CanvasUpdate::Resize]
+    /// when only small parts of a large canvas have changed.
+    Damage(Vec<Blit>),
+
+    /// Copy a region of this canvas to another position within it.
+    ///
+    /// The source and destination may overlap, so this can be used to scroll
+    /// the contents of terminal-like UIs before blitting the newly exposed
+    /// region. Out-of-bounds parts of the copy are discarded.
+    CopyRect {
+        /// The region to copy from.
+        src: Rect,
+
+        /// The X coordinate of the destination's origin.
+        x: u32,
+
+        /// The Y coordinate of the destination's origin.
+        y: u32,
+    },
+
+    /// Rasterize and draw text to a part of this canvas.
+    ///
+    /// Text that fails to load its font or is too large to rasterize is
+    /// ignored.
+    DrawText(TextDraw),
+
+    /// Replace the contents of this canvas with a rendered [DisplayList].
+    ///
+    /// Display lists that fail to load their fonts or contain invalid
+    /// geometry are ignored.
+    Draw(DisplayList),
+
+    /// Open a stream of blits to this canvas.
+    ///
+    /// The first capability of this message receives a [CanvasStream] with
+    /// the token of a new stream holding at most `capacity` bytes of frames,
+    /// each one a [Blit] encoded with [Blit::to_frame]. Streamed blits skip
+    /// message serialization entirely, so prefer them for high-rate updates
+    /// like video. The stream is closed when the canvas is destroyed.
+    OpenStream { capacity: u32 },
+}
+
+/// The reply to [CanvasUpdate::OpenStream].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CanvasStream {
+    /// The token to open the stream with.
+    pub token: u64,
 }
 
 /// Configures the method of texture sampling to use for a canvas.
@@ -130,7 +360,42 @@ pub enum FactorySuccess {
 pub enum FactoryError {
     /// The request has failed to parse.
     ParseError,
+
+    /// The canvas's pixel buffer is empty or too large.
+    InvalidSize,
 }
 
 /// A type shorthand for [FactorySuccess] and [FactoryError].
 pub type FactoryResponse = Result<FactorySuccess, FactoryError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_roundtrip() {
+        let blit = Blit {
+            x: 3,
+            y: 70000,
+            pixels: Pixels {
+                width: 2,
+                height: 1,
+                data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            },
+        };
+
+        let frame = blit.to_frame();
+        assert_eq!(frame.len(), Blit::FRAME_HEADER_LEN + 8);
+
+        let decoded = Blit::from_frame(&frame).unwrap();
+        assert_eq!((decoded.x, decoded.y), (3, 70000));
+        assert_eq!((decoded.pixels.width, decoded.pixels.height), (2, 1));
+        assert_eq!(decoded.pixels.data, blit.pixels.data);
+    }
+
+    #[test]
+    fn short_frames_are_rejected() {
+        assert!(Blit::from_frame(&[0; Blit::FRAME_HEADER_LEN - 1]).is_none());
+        assert!(Blit::from_frame(&[0; Blit::FRAME_HEADER_LEN]).is_some());
+    }
+}
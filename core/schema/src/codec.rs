@@ -0,0 +1,515 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use serde::{
+    de::{DeserializeOwned, DeserializeSeed, Error as _, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_with::{base64::Base64, DeserializeAs, SerializeAs};
+
+use crate::LumpId;
+
+/// The first byte of data encoded with a binary codec.
+///
+/// This byte never appears in UTF-8, so it can't be confused with JSON from
+/// guests that predate binary codecs.
+pub const ENVELOPE_MAGIC: u8 = 0xff;
+
+/// The envelope version of CBOR-encoded data.
+pub const CBOR_VERSION: u8 = 1;
+
+/// The envelope version of data that carries a [TraceContext].
+///
+/// The magic and version are followed by the big-endian trace ID and span ID
+/// of the context, and then by the data itself, encoded with any codec.
+pub const TRACED_VERSION: u8 = 2;
+
+/// The length of a [TRACED_VERSION] envelope's header.
+const TRACED_HEADER_LEN: usize = 2 + 16 + 8;
+
+/// A serialization format for messages and lumps.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum Codec {
+    /// Plain JSON with no envelope. Used by older guests.
+    Json,
+
+    /// CBOR behind a versioned envelope.
+    #[default]
+    Cbor,
+}
+
+impl Codec {
+    /// Detects the codec of some encoded data, looking past its trace
+    /// context if it has one.
+    pub fn detect(data: &[u8]) -> Result<Self, DecodeError> {
+        match TraceContext::split(data)?.1 {
+            [ENVELOPE_MAGIC, CBOR_VERSION, ..] => Ok(Codec::Cbor),
+            [ENVELOPE_MAGIC, version, ..] => Err(DecodeError::UnknownVersion(*version)),
+            [ENVELOPE_MAGIC] => Err(DecodeError::Truncated),
+            _ => Ok(Codec::Json),
+        }
+    }
+
+    /// Encodes a value with this codec.
+    ///
+    /// Panics if the value fails to serialize.
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> Vec<u8> {
+        match self {
+            Codec::Json => serde_json::to_vec(value).unwrap(),
+            Codec::Cbor => {
+                let mut data = vec![ENVELOPE_MAGIC, CBOR_VERSION];
+                ciborium::into_writer(value, &mut data).unwrap();
+                data
+            }
+        }
+    }
+
+    /// Decodes data encoded with this codec, ignoring its trace context if
+    /// it has one.
+    pub fn decode<T: DeserializeOwned>(self, data: &[u8]) -> Result<T, DecodeError> {
+        let data = TraceContext::split(data)?.1;
+        match self {
+            Codec::Json => {
+                serde_json::from_slice(data).map_err(|err| DecodeError::Json(err.to_string()))
+            }
+            Codec::Cbor => {
+                let payload = data.get(2..).ok_or(DecodeError::Truncated)?;
+                ciborium::from_reader(payload).map_err(|err| DecodeError::Cbor(err.to_string()))
+            }
+        }
+    }
+}
+
+/// Encodes a value with the default codec.
+///
+/// Panics if the value fails to serialize.
+pub fn encode<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    Codec::default().encode(value)
+}
+
+/// Decodes data encoded with any codec.
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, DecodeError> {
+    Codec::detect(data)?.decode(data)
+}
+
+/// The distributed tracing context of a message: the trace that it's part
+/// of and the span that sent it.
+///
+/// A context rides in a [TRACED_VERSION] envelope around a message's data,
+/// so receivers that don't trace simply decode past it.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TraceContext {
+    /// The ID of the trace, shared by every span in it.
+    pub trace_id: u128,
+
+    /// The ID of the span within the trace.
+    pub span_id: u64,
+}
+
+impl TraceContext {
+    /// Creates the context of the root span of a new trace with a unique ID.
+    pub fn new(trace_id: u128) -> Self {
+        Self {
+            trace_id,
+            span_id: mix(trace_id as u64 ^ (trace_id >> 64) as u64),
+        }
+    }
+
+    /// Derives the context of a child of this span.
+    ///
+    /// The children of a span must each be given a different `seed`.
+    pub fn child(&self, seed: u64) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: mix(self.span_id ^ mix(seed)),
+        }
+    }
+
+    /// Wraps encoded data in an envelope that carries this context.
+    pub fn wrap(&self, data: &[u8]) -> Vec<u8> {
+        let mut traced = Vec::with_capacity(TRACED_HEADER_LEN + data.len());
+        traced.extend_from_slice(&[ENVELOPE_MAGIC, TRACED_VERSION]);
+        traced.extend_from_slice(&self.trace_id.to_be_bytes());
+        traced.extend_from_slice(&self.span_id.to_be_bytes());
+        traced.extend_from_slice(data);
+        traced
+    }
+
+    /// Splits the trace context off of some data, if it has one, returning
+    /// the context and the data that it wraps.
+    pub fn split(data: &[u8]) -> Result<(Option<Self>, &[u8]), DecodeError> {
+        let [ENVELOPE_MAGIC, TRACED_VERSION, ..] = data else {
+            return Ok((None, data));
+        };
+
+        if data.len() < TRACED_HEADER_LEN {
+            return Err(DecodeError::Truncated);
+        }
+
+        let (trace_id, rest) = data[2..].split_at(16);
+        let (span_id, data) = rest.split_at(8);
+        let context = Self {
+            trace_id: u128::from_be_bytes(trace_id.try_into().unwrap()),
+            span_id: u64::from_be_bytes(span_id.try_into().unwrap()),
+        };
+
+        Ok((Some(context), data))
+    }
+}
+
+/// Scrambles the bits of an ID so that IDs derived from each other look
+/// unrelated. This is the SplitMix64 finalizer.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Finds every [LumpId] that some encoded data refers to.
+///
+/// The data is walked without knowing its schema, so any array or byte
+/// string of 32 bytes is taken to be a lump ID. Byte strings that JSON
+/// carries as Base64 are not found. Returns nothing if the data fails to
+/// decode.
+pub fn find_lump_ids(data: &[u8]) -> Vec<LumpId> {
+    decode::<LumpIds>(data).map(|ids| ids.0).unwrap_or_default()
+}
+
+/// The lump IDs found in a value of any schema.
+struct LumpIds(Vec<LumpId>);
+
+impl<'de> Deserialize<'de> for LumpIds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut ids = Vec::new();
+        LumpIdScanner(&mut ids).deserialize(deserializer)?;
+        Ok(LumpIds(ids))
+    }
+}
+
+/// Walks a value of any schema and collects the lump IDs in it.
+///
+/// Yields the value itself if it's a byte-sized integer, so that sequences
+/// can tell whether they're made of bytes.
+struct LumpIdScanner<'a>(&'a mut Vec<LumpId>);
+
+impl<'de, 'a> DeserializeSeed<'de> for LumpIdScanner<'a> {
+    type Value = Option<u8>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<u8>, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for LumpIdScanner<'a> {
+    type Value = Option<u8>;
+
+    fn expecting(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "any value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<Option<u8>, E> {
+        Ok(None)
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Option<u8>, E> {
+        Ok(value.try_into().ok())
+    }
+
+    fn visit_i128<E>(self, value: i128) -> Result<Option<u8>, E> {
+        Ok(value.try_into().ok())
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Option<u8>, E> {
+        Ok(value.try_into().ok())
+    }
+
+    fn visit_u128<E>(self, value: u128) -> Result<Option<u8>, E> {
+        Ok(value.try_into().ok())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<Option<u8>, E> {
+        Ok(None)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<Option<u8>, E> {
+        Ok(None)
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Option<u8>, E> {
+        if let Ok(id) = bytes.try_into() {
+            self.0.push(LumpId(id));
+        }
+
+        Ok(None)
+    }
+
+    fn visit_none<E>(self) -> Result<Option<u8>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<u8>, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_unit<E>(self) -> Result<Option<u8>, E> {
+        Ok(None)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Option<u8>, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Option<u8>, A::Error> {
+        let ids = self.0;
+        let mut bytes = Some(Vec::new());
+        while let Some(byte) = seq.next_element_seed(LumpIdScanner(&mut *ids))? {
+            match (bytes.as_mut(), byte) {
+                (Some(bytes), Some(byte)) => bytes.push(byte),
+                _ => bytes = None,
+            }
+        }
+
+        if let Some(Ok(id)) = bytes.map(<[u8; 32]>::try_from) {
+            ids.push(LumpId(id));
+        }
+
+        Ok(None)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Option<u8>, A::Error> {
+        let ids = self.0;
+        while map.next_key_seed(LumpIdScanner(&mut *ids))?.is_some() {
+            map.next_value_seed(LumpIdScanner(&mut *ids))?;
+        }
+
+        Ok(None)
+    }
+}
+
+/// An error from decoding data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data's envelope is cut short.
+    Truncated,
+
+    /// The data's envelope has a version that this codec doesn't know.
+    UnknownVersion(u8),
+
+    /// The data is not valid JSON for the expected type.
+    Json(String),
+
+    /// The data is not valid CBOR for the expected type.
+    Cbor(String),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            DecodeError::Truncated => write!(fmt, "truncated envelope"),
+            DecodeError::UnknownVersion(version) => {
+                write!(fmt, "unknown envelope version {version}")
+            }
+            DecodeError::Json(err) => write!(fmt, "invalid JSON: {err}"),
+            DecodeError::Cbor(err) => write!(fmt, "invalid CBOR: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A [serde_with] adapter for byte buffers.
+///
+/// Serializes to Base64 strings in human-readable formats like JSON and to
+/// raw byte strings in binary formats like CBOR.
+pub struct CompactBytes;
+
+impl<T: AsRef<[u8]>> SerializeAs<T> for CompactBytes {
+    fn serialize_as<S: Serializer>(source: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            <Base64 as SerializeAs<T>>::serialize_as(source, serializer)
+        } else {
+            serializer.serialize_bytes(source.as_ref())
+        }
+    }
+}
+
+impl<'de, T: TryFrom<Vec<u8>>> DeserializeAs<'de, T> for CompactBytes {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        if deserializer.is_human_readable() {
+            return <Base64 as DeserializeAs<T>>::deserialize_as(deserializer);
+        }
+
+        let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+        T::try_from(bytes).map_err(|_| D::Error::custom("invalid byte buffer length"))
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "a byte string")
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_with::serde_as;
+
+    use super::*;
+
+    #[serde_as]
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Payload {
+        name: String,
+        #[serde_as(as = "CompactBytes")]
+        data: Vec<u8>,
+    }
+
+    fn payload() -> Payload {
+        Payload {
+            name: "mesh".to_string(),
+            data: (0..=255).collect(),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        for codec in [Codec::Json, Codec::Cbor] {
+            let data = codec.encode(&payload());
+            assert_eq!(Codec::detect(&data), Ok(codec));
+            assert_eq!(decode::<Payload>(&data), Ok(payload()));
+        }
+    }
+
+    #[test]
+    fn decode_legacy_json() {
+        let data = br#"{"name":"mesh","data":"AAEC"}"#;
+        let decoded: Payload = decode(data).unwrap();
+        assert_eq!(decoded.data, [0, 1, 2]);
+    }
+
+    #[test]
+    fn cbor_stores_raw_bytes() {
+        let json = Codec::Json.encode(&payload());
+        let cbor = Codec::Cbor.encode(&payload());
+        assert!(cbor.len() < json.len());
+        assert!(cbor
+            .windows(256)
+            .any(|window| window == payload().data.as_slice()));
+    }
+
+    #[test]
+    fn find_nested_lump_ids() {
+        #[serde_as]
+        #[derive(Serialize)]
+        struct Refs {
+            mesh: LumpId,
+            textures: Vec<Option<LumpId>>,
+            #[serde_as(as = "CompactBytes")]
+            raw: [u8; 32],
+            not_a_lump: Vec<u32>,
+        }
+
+        let refs = Refs {
+            mesh: LumpId([1; 32]),
+            textures: vec![None, Some(LumpId([2; 32]))],
+            raw: [3; 32],
+            not_a_lump: vec![256; 32],
+        };
+
+        let ids = find_lump_ids(&Codec::Json.encode(&refs));
+        assert_eq!(ids, [LumpId([1; 32]), LumpId([2; 32])]);
+
+        // only binary codecs store raw byte strings
+        let ids = find_lump_ids(&Codec::Cbor.encode(&refs));
+        assert_eq!(ids, [LumpId([1; 32]), LumpId([2; 32]), LumpId([3; 32])]);
+
+        assert!(find_lump_ids(b"not json").is_empty());
+    }
+
+    #[test]
+    fn traced_roundtrip() {
+        let trace = TraceContext::new(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        let child = trace.child(1);
+        assert_eq!(child.trace_id, trace.trace_id);
+        assert_ne!(child.span_id, trace.span_id);
+        assert_ne!(child.span_id, trace.child(2).span_id);
+
+        for codec in [Codec::Json, Codec::Cbor] {
+            let data = codec.encode(&payload());
+            let traced = child.wrap(&data);
+            assert_eq!(Codec::detect(&traced), Ok(codec));
+            assert_eq!(decode::<Payload>(&traced), Ok(payload()));
+            assert_eq!(TraceContext::split(&traced), Ok((Some(child), &data[..])));
+            assert_eq!(TraceContext::split(&data), Ok((None, &data[..])));
+        }
+
+        let traced = trace.wrap(&encode(&payload()));
+        assert_eq!(
+            decode::<Payload>(&traced[..TRACED_HEADER_LEN - 1]),
+            Err(DecodeError::Truncated)
+        );
+
+        // contexts don't nest
+        assert_eq!(
+            decode::<Payload>(&trace.wrap(&traced)),
+            Err(DecodeError::UnknownVersion(TRACED_VERSION))
+        );
+    }
+
+    #[test]
+    fn reject_unknown_versions() {
+        let mut data = encode(&payload());
+        data[1] = 0x80;
+        assert_eq!(
+            decode::<Payload>(&data),
+            Err(DecodeError::UnknownVersion(0x80))
+        );
+        assert_eq!(
+            decode::<Payload>(&[ENVELOPE_MAGIC]),
+            Err(DecodeError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::codec::CompactBytes;
+
+/// The name of the cron service.
+pub const SERVICE_NAME: &str = "hearth.Cron";
+
+/// Where a scheduled task delivers its message.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CronTarget {
+    /// Deliver to the first capability argument of the [CronRequest::Schedule]
+    /// request.
+    ///
+    /// Capabilities can't outlive the runtime, so these tasks are forgotten
+    /// when the runtime restarts.
+    Capability,
+
+    /// Deliver to a native service by its name in the runtime's registry.
+    ///
+    /// The first capability argument of the [CronRequest::Schedule] request
+    /// must be to the same service, so that processes can only schedule
+    /// deliveries to the services they've been given.
+    ///
+    /// These tasks are persisted and rescheduled when the runtime restarts.
+    Service(String),
+}
+
+/// A recurring task that delivers a message on a schedule.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CronTask {
+    /// The cron expression of this task's schedule, in UTC.
+    ///
+    /// The fields are, in order: seconds, minutes, hours, day of month, month,
+    /// day of week, and an optional year. For example, `0 30 4 * * Sun` runs
+    /// every Sunday at 04:30:00.
+    pub schedule: String,
+
+    /// The destination of this task's message.
+    pub target: CronTarget,
+
+    /// The raw contents of the message to deliver.
+    #[serde_as(as = "CompactBytes")]
+    pub data: Vec<u8>,
+}
+
+/// Information about a scheduled task.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CronTaskInfo {
+    /// The ID of this task.
+    pub id: u64,
+
+    /// The task itself.
+    pub task: CronTask,
+
+    /// The time of the next delivery of this task in seconds since the UNIX
+    /// epoch, if it will run again.
+    pub next: Option<i64>,
+}
+
+/// A request to the cron service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CronRequest {
+    /// Schedules a new task.
+    ///
+    /// Returns [CronSuccess::Scheduled] with the ID of the new task.
+    Schedule(CronTask),
+
+    /// Cancels a task by its ID.
+    ///
+    /// Returns [CronSuccess::Cancelled].
+    Cancel(u64),
+
+    /// Lists all scheduled tasks.
+    ///
+    /// Returns [CronSuccess::List].
+    List,
+}
+
+/// A success response from a [CronRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CronSuccess {
+    Scheduled(u64),
+    Cancelled,
+    List(Vec<CronTaskInfo>),
+}
+
+/// An error response from a [CronRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CronError {
+    /// The schedule's cron expression failed to parse. Contains the error.
+    InvalidSchedule(String),
+
+    /// A task was scheduled without a capability to its target.
+    MissingTarget,
+
+    /// The [CronTarget::Service] service does not exist.
+    ServiceNotFound(String),
+
+    /// The capability argument of a [CronTarget::Service] task is not to the
+    /// named service.
+    ServiceNotHeld(String),
+
+    /// The given task ID does not exist.
+    TaskNotFound,
+}
+
+/// A type shorthand for [CronSuccess] and [CronError].
+pub type CronResponse = Result<CronSuccess, CronError>;
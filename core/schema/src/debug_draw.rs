@@ -16,7 +16,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use glam::Vec3;
+use glam::{vec2, Mat4, Vec2, Vec3};
 use serde::{Deserialize, Serialize};
 
 use crate::Color;
@@ -28,23 +28,369 @@ pub struct DebugDrawVertex {
 
     /// The color of this vertex. Alpha is ignored and fixed to opaque.
     pub color: Color,
+
+    /// An offset from `position` along the camera's right and up axes.
+    ///
+    /// Vertices with an offset stay facing the camera, which is how text
+    /// billboards are drawn. Zero for ordinary vertices.
+    #[serde(default)]
+    pub offset: Vec2,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+impl DebugDrawVertex {
+    /// Creates a vertex with no billboard offset.
+    pub fn new(position: Vec3, color: Color) -> Self {
+        Self {
+            position,
+            color,
+            offset: Vec2::ZERO,
+        }
+    }
+}
+
+/// A mesh of lines. Each pair of indices is one line segment.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct DebugDrawMesh {
     pub vertices: Vec<DebugDrawVertex>,
     pub indices: Vec<u32>,
 }
 
+impl DebugDrawMesh {
+    /// Adds a line segment between two points.
+    pub fn push_line(&mut self, start: Vec3, end: Vec3, color: Color) {
+        let base = self.vertices.len() as u32;
+        self.vertices.push(DebugDrawVertex::new(start, color));
+        self.vertices.push(DebugDrawVertex::new(end, color));
+        self.indices.extend([base, base + 1]);
+    }
+
+    /// Adds the edges of an axis-aligned bounding box.
+    pub fn push_aabb(&mut self, min: Vec3, max: Vec3, color: Color) {
+        let base = self.vertices.len() as u32;
+
+        for corner in 0..8 {
+            let position = Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+
+            self.vertices.push(DebugDrawVertex::new(position, color));
+        }
+
+        // each edge connects two corners that differ in one axis
+        for corner in 0..8u32 {
+            for axis in [1, 2, 4] {
+                if corner & axis == 0 {
+                    self.indices.extend([base + corner, base + (corner | axis)]);
+                }
+            }
+        }
+    }
+
+    /// Adds a wireframe sphere made of a circle around each axis.
+    pub fn push_sphere(&mut self, center: Vec3, radius: f32, color: Color) {
+        const SEGMENTS: u32 = 32;
+
+        for (u, v) in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)] {
+            let base = self.vertices.len() as u32;
+
+            for i in 0..SEGMENTS {
+                let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+                let (sin, cos) = angle.sin_cos();
+                let position = center + (u * cos + v * sin) * radius;
+                self.vertices.push(DebugDrawVertex::new(position, color));
+                self.indices.extend([base + i, base + (i + 1) % SEGMENTS]);
+            }
+        }
+    }
+
+    /// Adds a gizmo showing the axes of a transform, with X in red, Y in
+    /// green, and Z in blue.
+    pub fn push_gizmo(&mut self, transform: Mat4, size: f32) {
+        let origin = transform.transform_point3(Vec3::ZERO);
+
+        let axes = [
+            (Vec3::X, Color::from_rgb(0xff, 0x30, 0x30)),
+            (Vec3::Y, Color::from_rgb(0x30, 0xff, 0x30)),
+            (Vec3::Z, Color::from_rgb(0x30, 0x60, 0xff)),
+        ];
+
+        for (axis, color) in axes {
+            let end = transform.transform_point3(axis * size);
+            self.push_line(origin, end, color);
+        }
+    }
+
+    /// Adds an arrow pointing from `start` to `end`.
+    pub fn push_arrow(&mut self, start: Vec3, end: Vec3, color: Color) {
+        self.push_line(start, end, color);
+
+        let delta = end - start;
+        let length = delta.length();
+        if length <= f32::EPSILON {
+            return;
+        }
+
+        // pick any axis perpendicular to the arrow to build its head from
+        let direction = delta / length;
+        let side = direction.any_orthonormal_vector();
+        let up = direction.cross(side);
+
+        let head = length.min(1.0) * 0.2;
+        let back = end - direction * head;
+
+        for offset in [side, -side, up, -up] {
+            self.push_line(end, back + offset * head * 0.5, color);
+        }
+    }
+
+    /// Adds text that always faces the camera.
+    ///
+    /// The text is centered on `position` and `size` is the height of each
+    /// character in world units. Letters are drawn in uppercase and lines are
+    /// separated by `\n`. Characters without a glyph are drawn as `?`.
+    pub fn push_text(&mut self, position: Vec3, text: &str, size: f32, color: Color) {
+        const ADVANCE: f32 = 0.9;
+        const LINE_HEIGHT: f32 = 1.5;
+
+        let lines: Vec<&str> = text.lines().collect();
+        let height = (lines.len() as f32 - 1.0) * LINE_HEIGHT + 1.0;
+
+        for (row, line) in lines.iter().enumerate() {
+            let width = line.chars().count() as f32 * ADVANCE - (ADVANCE - GLYPH_WIDTH);
+            let origin = vec2(-width / 2.0, height / 2.0 - 1.0 - row as f32 * LINE_HEIGHT);
+
+            for (column, c) in line.chars().enumerate() {
+                let segments = glyph(c).unwrap_or_else(|| glyph('?').unwrap());
+                let corner = origin + Vec2::X * column as f32 * ADVANCE;
+
+                for (index, (start, end)) in SEGMENTS.iter().enumerate() {
+                    if segments & (1 << index) == 0 {
+                        continue;
+                    }
+
+                    let base = self.vertices.len() as u32;
+
+                    for point in [start, end] {
+                        let offset = corner + vec2(point.0 * GLYPH_WIDTH, point.1);
+                        self.vertices.push(DebugDrawVertex {
+                            position,
+                            color,
+                            offset: offset * size,
+                        });
+                    }
+
+                    self.indices.extend([base, base + 1]);
+                }
+            }
+        }
+    }
+}
+
 /// An update to a debug draw mesh.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum DebugDrawUpdate {
     /// Updates the contents of this debug draw mesh.
     Contents(DebugDrawMesh),
 
+    /// Adds a mesh that is drawn alongside this debug draw's contents until
+    /// the given number of seconds have passed.
+    ///
+    /// Timed meshes accumulate and are not replaced by [Self::Contents], so
+    /// they can be used to draw events like collisions as they happen.
+    Timed {
+        /// The mesh to draw.
+        mesh: DebugDrawMesh,
+
+        /// How long to draw the mesh for in seconds.
+        duration: f32,
+    },
+
     /// Sets whether to hide this mesh.
     Hide(bool),
 
     /// Destroys this debug draw mesh.
     Destroy,
 }
+
+/// The width of a glyph relative to its height.
+const GLYPH_WIDTH: f32 = 0.6;
+
+/// The endpoints of each segment of a glyph in a unit square, with the
+/// origin at the bottom left.
+const SEGMENTS: [((f32, f32), (f32, f32)); 18] = [
+    ((0.0, 1.0), (0.5, 1.0)),  // top left
+    ((0.5, 1.0), (1.0, 1.0)),  // top right
+    ((1.0, 1.0), (1.0, 0.5)),  // upper right
+    ((1.0, 0.5), (1.0, 0.0)),  // lower right
+    ((1.0, 0.0), (0.5, 0.0)),  // bottom right
+    ((0.5, 0.0), (0.0, 0.0)),  // bottom left
+    ((0.0, 0.0), (0.0, 0.5)),  // lower left
+    ((0.0, 0.5), (0.0, 1.0)),  // upper left
+    ((0.0, 0.5), (0.5, 0.5)),  // middle left
+    ((0.5, 0.5), (1.0, 0.5)),  // middle right
+    ((0.0, 1.0), (0.5, 0.5)),  // upper left diagonal
+    ((0.5, 1.0), (0.5, 0.5)),  // upper center
+    ((1.0, 1.0), (0.5, 0.5)),  // upper right diagonal
+    ((0.0, 0.0), (0.5, 0.5)),  // lower left diagonal
+    ((0.5, 0.0), (0.5, 0.5)),  // lower center
+    ((1.0, 0.0), (0.5, 0.5)),  // lower right diagonal
+    ((0.5, 0.0), (0.5, 0.15)), // low dot
+    ((0.5, 0.55), (0.5, 0.7)), // high dot
+];
+
+/// Looks up the segments of the glyph for a character as a bitmask of
+/// indices into [SEGMENTS].
+fn glyph(c: char) -> Option<u32> {
+    const A1: u32 = 1 << 0;
+    const A2: u32 = 1 << 1;
+    const B: u32 = 1 << 2;
+    const C: u32 = 1 << 3;
+    const D2: u32 = 1 << 4;
+    const D1: u32 = 1 << 5;
+    const E: u32 = 1 << 6;
+    const F: u32 = 1 << 7;
+    const G1: u32 = 1 << 8;
+    const G2: u32 = 1 << 9;
+    const H: u32 = 1 << 10;
+    const I: u32 = 1 << 11;
+    const J: u32 = 1 << 12;
+    const K: u32 = 1 << 13;
+    const L: u32 = 1 << 14;
+    const M: u32 = 1 << 15;
+    const DOT: u32 = 1 << 16;
+    const HIGH_DOT: u32 = 1 << 17;
+
+    const A: u32 = A1 | A2;
+    const D: u32 = D1 | D2;
+    const G: u32 = G1 | G2;
+    const O: u32 = A | B | C | D | E | F;
+
+    let segments = match c.to_ascii_uppercase() {
+        ' ' => 0,
+        '0' => O | J | K,
+        '1' => B | C,
+        '2' => A | B | G | E | D,
+        '3' => A | B | C | D | G2,
+        '4' => F | G | B | C,
+        '5' => A | F | G | C | D,
+        '6' => A | F | E | D | C | G,
+        '7' => A | B | C,
+        '8' => O | G,
+        '9' => A | B | C | D | F | G,
+        'A' => A | B | C | E | F | G,
+        'B' => A | B | C | D | I | L | G2,
+        'C' => A | F | E | D,
+        'D' => A | B | C | D | I | L,
+        'E' => A | F | E | D | G1,
+        'F' => A | F | E | G1,
+        'G' => A | F | E | D | C | G2,
+        'H' => F | E | B | C | G,
+        'I' => A | I | L | D,
+        'J' => B | C | D | E,
+        'K' => F | E | G1 | J | M,
+        'L' => F | E | D,
+        'M' => F | E | B | C | H | J,
+        'N' => F | E | B | C | H | M,
+        'O' => O,
+        'P' => A | B | F | E | G,
+        'Q' => O | M,
+        'R' => A | B | F | E | G | M,
+        'S' => A | H | G2 | C | D,
+        'T' => A | I | L,
+        'U' => F | E | D | C | B,
+        'V' => F | E | K | J,
+        'W' => F | E | B | C | K | M,
+        'X' => H | J | K | M,
+        'Y' => H | J | L,
+        'Z' => A | J | K | D,
+        '!' => I | DOT,
+        '"' => F | I,
+        '%' => A1 | F | J | K | D2 | C,
+        '\'' => I,
+        '(' => J | M,
+        ')' => H | K,
+        '*' => G | H | I | J | K | L | M,
+        '+' => G | I | L,
+        ',' => K,
+        '-' => G,
+        '.' => DOT,
+        '/' => J | K,
+        ':' => DOT | HIGH_DOT,
+        ';' => K | HIGH_DOT,
+        '<' => J | M,
+        '=' => G | D,
+        '>' => H | K,
+        '?' => A | B | G2 | DOT,
+        '[' => A2 | I | L | D2,
+        '\\' => H | M,
+        ']' => A1 | I | L | D1,
+        '_' => D,
+        '{' => A2 | I | L | D2 | G1,
+        '|' => I | L,
+        '}' => A1 | I | L | D1 | G2,
+        _ => return None,
+    };
+
+    Some(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_valid(mesh: &DebugDrawMesh) {
+        assert_eq!(mesh.indices.len() % 2, 0);
+
+        for index in mesh.indices.iter() {
+            assert!((*index as usize) < mesh.vertices.len());
+        }
+    }
+
+    #[test]
+    fn aabb_has_twelve_edges() {
+        let mut mesh = DebugDrawMesh::default();
+        mesh.push_aabb(Vec3::ZERO, Vec3::ONE, Color(0));
+        assert_valid(&mesh);
+        assert_eq!(mesh.indices.len(), 24);
+    }
+
+    #[test]
+    fn shapes_are_valid() {
+        let mut mesh = DebugDrawMesh::default();
+        let color = Color::from_rgb(0xff, 0xff, 0xff);
+        mesh.push_line(Vec3::ZERO, Vec3::X, color);
+        mesh.push_sphere(Vec3::Y, 2.0, color);
+        mesh.push_gizmo(Mat4::from_translation(Vec3::Z), 1.0);
+        mesh.push_arrow(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0), color);
+        mesh.push_arrow(Vec3::ONE, Vec3::ONE, color);
+        assert_valid(&mesh);
+    }
+
+    #[test]
+    fn text_is_centered() {
+        let mut mesh = DebugDrawMesh::default();
+        mesh.push_text(Vec3::ONE, "HI\nHEY", 2.0, Color(0));
+        assert_valid(&mesh);
+        assert!(mesh.vertices.iter().all(|v| v.position == Vec3::ONE));
+
+        let (min, max) = mesh.vertices.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), v| (min.min(v.offset), max.max(v.offset)),
+        );
+
+        assert!((min + max).abs().max_element() < 1e-4);
+    }
+
+    #[test]
+    fn all_printable_ascii_have_glyphs() {
+        for c in ' '..='~' {
+            if "#$&@^`~".contains(c) {
+                assert!(glyph(c).is_none());
+            } else {
+                assert!(glyph(c).is_some(), "{c:?} has no glyph");
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+use crate::LumpId;
+
+/// The name of the file picker service.
+pub const SERVICE_NAME: &str = "hearth.FilePicker";
+
+/// A filter on the files shown in a dialog.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FileFilter {
+    /// The name of this filter shown to the user, like "Images".
+    pub name: String,
+
+    /// The file extensions this filter matches, without leading dots.
+    pub extensions: Vec<String>,
+}
+
+/// A request to the file picker service.
+///
+/// Each request shows a native dialog to the user. Only one dialog is open at
+/// a time, so requests made while another is open fail with
+/// [FilePickerError::Busy]. Dialog titles are chosen by the host.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FilePickerRequest {
+    /// Asks the user to choose a file to open.
+    ///
+    /// Returns [FilePickerSuccess::Opened] with the file's contents.
+    Open {
+        /// The filters to choose from. All files are shown if empty.
+        filters: Vec<FileFilter>,
+    },
+
+    /// Asks the user to choose where to save a file.
+    ///
+    /// Returns [FilePickerSuccess::Saved] once the file is written.
+    Save {
+        /// The filters to choose from. All files are shown if empty.
+        filters: Vec<FileFilter>,
+
+        /// The default name of the file.
+        file_name: Option<String>,
+
+        /// The lump containing the file's contents.
+        contents: LumpId,
+    },
+}
+
+/// A success response from a [FilePickerRequest].
+///
+/// Only file names are returned to the guest, never full paths.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FilePickerSuccess {
+    /// The user chose a file to open.
+    Opened {
+        /// The file's name.
+        name: String,
+
+        /// The lump containing the file's contents.
+        contents: LumpId,
+    },
+
+    /// The file was saved.
+    Saved {
+        /// The file's name.
+        name: String,
+    },
+}
+
+/// An error response from a [FilePickerRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FilePickerError {
+    /// The user closed the dialog without choosing a file.
+    Cancelled,
+
+    /// The lump to save was not found.
+    LumpNotFound,
+
+    /// The file could not be read or written.
+    Io(String),
+
+    /// Another dialog is already open.
+    Busy,
+}
+
+/// A type shorthand for [FilePickerSuccess] and [FilePickerError].
+pub type FilePickerResponse = Result<FilePickerSuccess, FilePickerError>;
@@ -29,6 +29,20 @@ pub enum Error {
     DirectoryTraversal,
     InvalidTarget,
     InvalidRequest,
+
+    /// The filesystem service is read-only.
+    ReadOnly,
+
+    /// The write would make the files in the writable service's root larger
+    /// than its quota.
+    QuotaExceeded,
+
+    /// The target of a [RequestKind::Mkdir] already exists.
+    AlreadyExists,
+
+    /// The target of a [RequestKind::Delete] is a directory with files in it.
+    DirectoryNotEmpty,
+
     Other(String),
 }
 
@@ -36,6 +50,29 @@ pub enum Error {
 pub enum RequestKind {
     Get,
     List,
+
+    /// Replaces the target file's contents with a lump's, creating the file
+    /// if it doesn't exist.
+    Write(LumpId),
+
+    /// Appends a lump's contents to the target file, creating the file if it
+    /// doesn't exist.
+    Append(LumpId),
+
+    /// Deletes the target file or empty directory.
+    Delete,
+
+    /// Creates the target directory and any missing parent directories.
+    Mkdir,
+
+    /// Watches the target file or directory, and everything under it, for
+    /// changes.
+    ///
+    /// The first capability after the reply capability receives a
+    /// [WatchEvent] for each change. If it has the monitor permission, the
+    /// watch stops when it goes down. Otherwise, the watch stops once it can
+    /// no longer be sent to.
+    Watch,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -54,6 +91,60 @@ pub struct FileInfo {
 pub enum Success {
     Get(LumpId),
     List(Vec<FileInfo>),
+    Write,
+    Append,
+    Delete,
+    Mkdir,
+    Watch,
 }
 
 pub type Response = Result<Success, Error>;
+
+/// A change to the files under a watched path.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct WatchEvent {
+    pub kind: WatchEventKind,
+
+    /// The paths of the changed files, relative to the filesystem service's
+    /// root.
+    pub paths: Vec<String>,
+}
+
+/// The kind of a [WatchEvent].
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum WatchEventKind {
+    /// A file or directory was created or renamed into place.
+    Created,
+
+    /// A file's contents or metadata were modified.
+    Modified,
+
+    /// A file or directory was removed or renamed away.
+    Removed,
+}
+
+/// A request to a filesystem factory to spawn a new filesystem service scoped
+/// to a subdirectory of the factory's root.
+///
+/// `hearth.fs.Factory` spawns read-only services. `hearth.fs.WritableFactory`
+/// spawns services that accept the requests that modify files, creating the
+/// subdirectory if it doesn't exist. Each writable service has its own quota
+/// on the files in its root, and can't be rooted at directories containing
+/// the host's own files.
+///
+/// On success, the capability to the new filesystem service is the first
+/// capability of the response.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FactoryRequest {
+    /// The path of the new service's root, relative to the factory's root.
+    pub root: String,
+
+    /// The most bytes that the files in the new service's root may take up.
+    ///
+    /// Only writable factories read this. It's capped by the limit set by the
+    /// host, which is used if this is `None`.
+    #[serde(default)]
+    pub quota: Option<u64>,
+}
+
+pub type FactoryResponse = Result<(), Error>;
@@ -0,0 +1,132 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the gamepad service. Accepts [GamepadCommand].
+pub const SERVICE_NAME: &str = "hearth.Gamepad";
+
+/// Identifies a gamepad for as long as it is connected.
+///
+/// IDs may be reused after a gamepad disconnects.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GamepadId(pub usize);
+
+/// A message to the gamepad service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum GamepadCommand {
+    /// Subscribes to all [GamepadEvents][GamepadEvent] using the first
+    /// attached capability.
+    ///
+    /// The subscriber is first sent a [GamepadEvent::Connected] for each
+    /// gamepad that is already connected.
+    ///
+    /// If the capability has the monitor permission, it will be automatically
+    /// unsubscribed when down.
+    Subscribe,
+
+    /// Unsubscribes from gamepad events using the first attached capability.
+    Unsubscribe,
+}
+
+/// An event from a gamepad.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum GamepadEvent {
+    /// A gamepad has been connected.
+    Connected {
+        id: GamepadId,
+
+        /// The name of the gamepad as reported by the operating system.
+        name: String,
+    },
+
+    /// A gamepad has been disconnected.
+    Disconnected { id: GamepadId },
+
+    /// A button has been pressed.
+    ButtonPressed { id: GamepadId, button: Button },
+
+    /// A button has been released.
+    ButtonReleased { id: GamepadId, button: Button },
+
+    /// The analog value of a button, like a trigger, has changed. Ranges from
+    /// 0.0 to 1.0.
+    ButtonChanged {
+        id: GamepadId,
+        button: Button,
+        value: f32,
+    },
+
+    /// The value of an axis has changed. Ranges from -1.0 to 1.0, where
+    /// positive values are up or right.
+    AxisChanged {
+        id: GamepadId,
+        axis: Axis,
+        value: f32,
+    },
+}
+
+/// A gamepad button, named by its position on a standard layout.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Button {
+    /// The bottom face button (A on Xbox, cross on PlayStation).
+    South,
+    /// The right face button (B on Xbox, circle on PlayStation).
+    East,
+    /// The top face button (Y on Xbox, triangle on PlayStation).
+    North,
+    /// The left face button (X on Xbox, square on PlayStation).
+    West,
+    C,
+    Z,
+    /// The left bumper.
+    LeftTrigger,
+    /// The left analog trigger.
+    LeftTrigger2,
+    /// The right bumper.
+    RightTrigger,
+    /// The right analog trigger.
+    RightTrigger2,
+    Select,
+    Start,
+    /// The vendor button in the middle of the gamepad.
+    Mode,
+    /// Pressing in the left stick.
+    LeftThumb,
+    /// Pressing in the right stick.
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Unknown,
+}
+
+/// A gamepad axis.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Axis {
+    LeftStickX,
+    LeftStickY,
+    LeftZ,
+    RightStickX,
+    RightStickY,
+    RightZ,
+    DPadX,
+    DPadY,
+    Unknown,
+}
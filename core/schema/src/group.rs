@@ -0,0 +1,104 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the process group service.
+pub const SERVICE_NAME: &str = "hearth.ProcessGroups";
+
+/// The ID of a member of a process group, unique within its group.
+pub type MemberId = u64;
+
+/// A request to the process group service.
+///
+/// A process group is a named set of member capabilities. Every message sent
+/// to a group's capability, along with its capabilities, is broadcast to all
+/// of the group's members. Groups are created when they are first requested
+/// and last for the lifetime of the runtime.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum GroupRequest {
+    /// Adds the first capability in the request to the named group.
+    ///
+    /// If a second capability is given, it receives a [GroupSignal] whenever
+    /// a member joins or leaves the group. Members that are monitorable
+    /// automatically leave the group when they go down.
+    ///
+    /// Returns [GroupSuccess::Join] with the group's capability.
+    Join { group: String },
+
+    /// Removes a member from the named group.
+    ///
+    /// Returns [GroupSuccess::Leave].
+    Leave { group: String, member: MemberId },
+
+    /// Gets the named group's capability without joining it.
+    ///
+    /// Returns [GroupSuccess::Get] with the group's capability.
+    Get { group: String },
+
+    /// Lists every group.
+    ///
+    /// Returns [GroupSuccess::List], sorted by name.
+    List,
+}
+
+/// A success response from a [GroupRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum GroupSuccess {
+    /// The new member's ID, used to leave the group later.
+    Join(MemberId),
+    Leave,
+    Get,
+    List(Vec<GroupInfo>),
+}
+
+/// An error response from a [GroupRequest].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum GroupError {
+    /// A [GroupRequest::Join] request did not include a member capability.
+    MissingMember,
+
+    /// The group does not exist.
+    NoSuchGroup,
+
+    /// The group has no member with the given ID.
+    NoSuchMember,
+}
+
+/// A type shorthand for [GroupSuccess] and [GroupError].
+pub type GroupResponse = Result<GroupSuccess, GroupError>;
+
+/// Information about a process group.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GroupInfo {
+    /// The name of the group.
+    pub name: String,
+
+    /// The number of members in the group.
+    pub members: usize,
+}
+
+/// A change to a group's membership, sent to members that asked for signals.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum GroupSignal {
+    /// A member has joined the group. Also sent to the member itself.
+    Joined { member: MemberId, members: usize },
+
+    /// A member has left the group, either by request or by going down.
+    Left { member: MemberId, members: usize },
+}
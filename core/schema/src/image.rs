@@ -0,0 +1,92 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use glam::UVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::LumpId;
+
+/// The name of the image decoding service.
+pub const SERVICE_NAME: &str = "hearth.ImageDecoder";
+
+/// A compressed image format supported by the image decoding service.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+
+    /// Only the first frame of animated GIFs is decoded.
+    Gif,
+}
+
+/// A request to the image decoding service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ImageDecoderRequest {
+    /// Decodes a compressed image lump into a lump containing
+    /// [TextureData](crate::renderer::TextureData).
+    ///
+    /// Returns [ImageDecoderSuccess::Decoded] with no capabilities.
+    Decode {
+        /// The lump containing the compressed image.
+        image: LumpId,
+
+        /// The format of the image. If `None`, the format is guessed from
+        /// the image's contents.
+        format: Option<ImageFormat>,
+
+        /// The label of the decoded texture.
+        label: Option<String>,
+    },
+}
+
+/// A success response from an [ImageDecoderRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ImageDecoderSuccess {
+    /// The image was decoded.
+    Decoded {
+        /// The lump containing the decoded
+        /// [TextureData](crate::renderer::TextureData).
+        texture: LumpId,
+
+        /// The width and height of the image in pixels.
+        size: UVec2,
+
+        /// The format that the image was decoded from.
+        format: ImageFormat,
+    },
+}
+
+/// An error response from an [ImageDecoderRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ImageDecoderError {
+    /// The image lump was not found.
+    LumpNotFound,
+
+    /// The image's format could not be guessed or is not supported.
+    UnsupportedFormat,
+
+    /// The image's width or height exceeds the service's limit.
+    TooLarge,
+
+    /// The image is malformed.
+    Decode(String),
+}
+
+/// A type shorthand for [ImageDecoderSuccess] and [ImageDecoderError].
+pub type ImageDecoderResponse = Result<ImageDecoderSuccess, ImageDecoderError>;
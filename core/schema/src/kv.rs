@@ -0,0 +1,119 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::codec::CompactBytes;
+
+/// The name of the key-value store service.
+pub const SERVICE_NAME: &str = "hearth.KvStore";
+
+/// The maximum size of a stored value in bytes.
+pub const MAX_VALUE_SIZE: usize = 1024 * 1024;
+
+/// A request to a key-value store.
+///
+/// Every capability to a store is scoped to a namespace, and can only access
+/// the keys in that namespace. The `hearth.KvStore` service is the root
+/// namespace, and [KvRequest::Namespace] hands out capabilities to nested
+/// namespaces that can't access their parents or siblings.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum KvRequest {
+    /// Gets the value of a key.
+    ///
+    /// Returns [KvSuccess::Value].
+    Get { key: String },
+
+    /// Sets the value of a key, replacing any existing value.
+    ///
+    /// Returns [KvSuccess::Done].
+    Put {
+        key: String,
+        #[serde_as(as = "CompactBytes")]
+        value: Vec<u8>,
+    },
+
+    /// Removes a key.
+    ///
+    /// Returns [KvSuccess::Deleted] with whether the key existed.
+    Delete { key: String },
+
+    /// Lists every key starting with a prefix, in order.
+    ///
+    /// Returns [KvSuccess::Keys].
+    List { prefix: String },
+
+    /// Sends a [KvEvent] to the first capability of the request whenever a
+    /// key starting with the prefix changes.
+    ///
+    /// The subscription ends when the capability can no longer be sent to.
+    ///
+    /// Returns [KvSuccess::Done].
+    Watch { prefix: String },
+
+    /// Gets a capability to a namespace nested in this one.
+    ///
+    /// Returns [KvSuccess::Done] with the namespace's capability.
+    Namespace { name: String },
+}
+
+/// A success response from a [KvRequest].
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum KvSuccess {
+    Done,
+    Value(#[serde_as(as = "Option<CompactBytes>")] Option<Vec<u8>>),
+    Deleted(bool),
+    Keys(Vec<String>),
+}
+
+/// An error response from a [KvRequest].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum KvError {
+    /// Namespace names must be non-empty and can't contain `/`.
+    InvalidNamespace,
+
+    /// The value is larger than [MAX_VALUE_SIZE].
+    ValueTooLarge,
+
+    /// A [KvRequest::Watch] request did not include a subscriber.
+    MissingSubscriber,
+
+    /// The underlying database failed.
+    Storage(String),
+}
+
+/// A type shorthand for [KvSuccess] and [KvError].
+pub type KvResponse = Result<KvSuccess, KvError>;
+
+/// A change to a watched key.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum KvEvent {
+    /// A key was set to a new value.
+    Put {
+        key: String,
+        #[serde_as(as = "CompactBytes")]
+        value: Vec<u8>,
+    },
+
+    /// A key was removed.
+    Delete { key: String },
+}
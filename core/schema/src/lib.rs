@@ -17,6 +17,7 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
+    collections::BTreeMap,
     fmt::{Display, Formatter, Result as FmtResult},
     ops::{Deref, DerefMut},
 };
@@ -24,15 +25,60 @@ use std::{
 use bytemuck::{Pod, Zeroable};
 use serde::{Deserialize, Serialize};
 
+/// Skeletal animation protocol.
+pub mod animation;
+
+/// Capability audit log protocol.
+pub mod audit;
+
+/// Backup service protocol.
+pub mod backup;
+
 /// Canvas protocol.
 pub mod canvas;
 
+/// Message and lump serialization formats.
+pub mod codec;
+
+/// Scheduled task (cron) protocol.
+pub mod cron;
+
 /// Debug draw protocol
 pub mod debug_draw;
 
+/// Native file picker dialog protocol.
+pub mod file_picker;
+
 /// Filesystem native service protocol.
 pub mod fs;
 
+/// Image decoding service protocol.
+pub mod image;
+
+/// Gamepad input protocol.
+pub mod gamepad;
+
+/// Process group protocol.
+pub mod group;
+
+/// Persistent key-value store protocol.
+pub mod kv;
+
+/// Persistent process log protocol.
+pub mod logs;
+
+/// Lump store protocol.
+pub mod lump;
+
+/// Notification protocol.
+pub mod notify;
+
+/// Process store protocol.
+pub mod process;
+
+/// Frame profiling protocol.
+pub mod profiler;
+
 /// Network/IPC protocol definitions.
 pub mod protocol;
 
@@ -45,6 +91,9 @@ pub mod renderer;
 /// Terminal protocol.
 pub mod terminal;
 
+/// Timing protocols.
+pub mod time;
+
 /// WebAssembly process protocols and utilities.
 pub mod wasm;
 
@@ -69,6 +118,29 @@ impl Display for LumpId {
     }
 }
 
+/// The prefix of a message whose payload has been spilled over into a lump.
+///
+/// A spilled message's data is this prefix followed by the bytes of the
+/// payload lump's [LumpId]. Receivers replace it with the lump's contents.
+pub const SPILLOVER_MAGIC: &[u8] = b"\0hearth spillover\0";
+
+/// The payload size in bytes above which guest send helpers spill messages
+/// over into lumps.
+pub const SPILLOVER_THRESHOLD: usize = 64 * 1024;
+
+/// Encodes the message data of a payload spilled over into the given lump.
+pub fn encode_spillover(id: &LumpId) -> Vec<u8> {
+    [SPILLOVER_MAGIC, &id.0].concat()
+}
+
+/// Decodes the ID of a spilled-over payload lump from message data.
+///
+/// Returns `None` if the message was not spilled over.
+pub fn decode_spillover(data: &[u8]) -> Option<LumpId> {
+    let id = data.strip_prefix(SPILLOVER_MAGIC)?;
+    id.try_into().ok().map(LumpId)
+}
+
 bitflags::bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
     pub struct Permissions: u32 {
@@ -141,6 +213,25 @@ impl From<ProcessLogLevel> for tracing::Level {
     }
 }
 
+/// A log event with structured fields.
+///
+/// Guests pass these to the host JSON-encoded, since they don't fit in the
+/// plain log call's fixed arguments.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StructuredLogEvent {
+    /// The level of this event.
+    pub level: ProcessLogLevel,
+
+    /// The module that logged this event.
+    pub module: String,
+
+    /// The message of this event.
+    pub content: String,
+
+    /// Key-value pairs describing this event.
+    pub fields: BTreeMap<String, String>,
+}
+
 /// A kind of guest-side signal.
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
 pub enum SignalKind {
@@ -201,7 +292,7 @@ impl Color {
 ///
 /// Wraps `Vec<T>` and provides `AsRef<[u8]>` and `TryFrom<Vec<u8>>` for types
 /// that implement [Pod] so that vectors of `T` can be used with
-/// [serde_with::base64::Base64].
+/// [codec::CompactBytes].
 #[derive(Clone, Debug, Hash, Deserialize, Serialize)]
 pub struct ByteVec<T>(pub Vec<T>);
 
@@ -229,6 +320,160 @@ impl<T: Pod> TryFrom<Vec<u8>> for ByteVec<T> {
     type Error = bytemuck::PodCastError;
 
     fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
-        bytemuck::try_cast_slice(bytes.as_slice()).map(|slice| Self(slice.to_vec()))
+        let size = std::mem::size_of::<T>();
+
+        if size == 0 {
+            return Err(bytemuck::PodCastError::SizeMismatch);
+        }
+
+        if !bytes.len().is_multiple_of(size) {
+            return Err(bytemuck::PodCastError::OutputSliceWouldHaveSlop);
+        }
+
+        // copy instead of casting in place because the bytes' allocation is
+        // not necessarily aligned for T
+        let mut vec = vec![T::zeroed(); bytes.len() / size];
+        bytemuck::cast_slice_mut(&mut vec).copy_from_slice(&bytes);
+        Ok(Self(vec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use glam::Vec3;
+    use proptest::prelude::*;
+    use serde_json::Value;
+
+    /// Decodes data as every type that native services receive from guests.
+    fn decode_all(data: &[u8]) {
+        fn decode<T: for<'a> Deserialize<'a>>(data: &[u8]) {
+            let _ = codec::decode::<T>(data);
+        }
+
+        decode::<animation::FactoryRequest>(data);
+        decode::<animation::AnimatorUpdate>(data);
+        decode::<backup::BackupRequest>(data);
+        decode::<canvas::FactoryRequest>(data);
+        decode::<canvas::CanvasUpdate>(data);
+        decode::<cron::CronRequest>(data);
+        decode::<debug_draw::DebugDrawUpdate>(data);
+        decode::<file_picker::FilePickerRequest>(data);
+        decode::<fs::Request>(data);
+        decode::<fs::FactoryRequest>(data);
+        decode::<gamepad::GamepadCommand>(data);
+        decode::<group::GroupRequest>(data);
+        decode::<kv::KvRequest>(data);
+        decode::<logs::ProcessLogRequest>(data);
+        decode::<lump::LumpRequest>(data);
+        decode::<notify::Notification>(data);
+        decode::<process::ProcessStoreRequest>(data);
+        decode::<registry::RegistryRequest>(data);
+        decode::<registry::RegistryFactoryRequest>(data);
+        decode::<renderer::RendererRequest>(data);
+        decode::<renderer::DirectionalLightUpdate>(data);
+        decode::<renderer::PointLightUpdate>(data);
+        decode::<renderer::SpotLightUpdate>(data);
+        decode::<renderer::ObjectUpdate>(data);
+        decode::<renderer::RenderTargetUpdate>(data);
+        decode::<renderer::ViewportUpdate>(data);
+        decode::<renderer::MaterialData>(data);
+        decode::<renderer::MeshData>(data);
+        decode::<renderer::TextureData>(data);
+        decode::<terminal::FactoryRequest>(data);
+        decode::<terminal::TerminalUpdate>(data);
+        decode::<time::TickCommand>(data);
+        decode::<time::ClockRequest>(data);
+        decode::<time::ClockAdminRequest>(data);
+        decode::<wasm::CrashReportRequest>(data);
+        decode::<wasm::ReplayRequest>(data);
+        decode::<wasm::SupervisorRequest>(data);
+        decode::<wasm::SupervisorSpec>(data);
+        decode::<wasm::WasmSpawnInfo>(data);
+        decode::<window::WindowCommand>(data);
+        decode::<window::ClipboardCommand>(data);
+    }
+
+    /// Generates JSON values shaped like the schema's enums and structs.
+    fn arb_json() -> impl Strategy<Value = Value> {
+        let key = prop::sample::select(vec![
+            "AddObject",
+            "Blit",
+            "CreateCanvas",
+            "Get",
+            "List",
+            "Resize",
+            "SetCamera",
+            "Schedule",
+            "Service",
+            "data",
+            "height",
+            "kind",
+            "mesh",
+            "pixels",
+            "size",
+            "target",
+            "transform",
+            "width",
+            "x",
+            "y",
+        ]);
+
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            "[A-Za-z0-9+/=]{0,16}".prop_map(Value::from),
+            key.clone().prop_map(Value::from),
+        ];
+
+        leaf.prop_recursive(4, 64, 8, move |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+                prop::collection::btree_map(key.clone(), inner, 0..4).prop_map(
+                    |map| Value::Object(map.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+                ),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn byte_vec_roundtrip(values in prop::collection::vec(any::<[f32; 3]>(), 0..64)) {
+            let values: Vec<Vec3> = values.into_iter().map(Vec3::from).collect();
+            let bytes = ByteVec(values.clone()).as_ref().to_vec();
+            let decoded = ByteVec::<Vec3>::try_from(bytes.clone()).unwrap();
+            prop_assert_eq!(decoded.as_ref(), bytes.as_slice());
+        }
+
+        #[test]
+        fn byte_vec_rejects_partial_elements(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            let is_whole = bytes.len().is_multiple_of(std::mem::size_of::<Vec3>());
+            prop_assert_eq!(ByteVec::<Vec3>::try_from(bytes).is_ok(), is_whole);
+        }
+
+        #[test]
+        fn spillover_roundtrip(id in any::<[u8; 32]>()) {
+            let data = encode_spillover(&LumpId(id));
+            prop_assert_eq!(decode_spillover(&data), Some(LumpId(id)));
+        }
+
+        #[test]
+        fn spillover_ignores_json(json in arb_json()) {
+            let data = serde_json::to_vec(&json).unwrap();
+            prop_assert_eq!(decode_spillover(&data), None);
+        }
+
+        #[test]
+        fn decode_arbitrary_bytes(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            decode_all(&data);
+        }
+
+        #[test]
+        fn decode_arbitrary_json(json in arb_json()) {
+            decode_all(json.to_string().as_bytes());
+        }
     }
 }
@@ -0,0 +1,118 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ProcessId, ProcessLogLevel};
+
+/// The name of the process log service.
+///
+/// The process log service keeps the logs of every local process on disk, so
+/// that they can be read after the process has exited. Logs are kept for the
+/// lifetime of the runtime that wrote them. Like the rest of the debugging
+/// infrastructure, it should not be given to untrusted processes.
+pub const SERVICE_NAME: &str = "hearth.ProcessLogs";
+
+/// A request to the process log service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProcessLogRequest {
+    /// Gets the most recent events logged by a process, live or dead, oldest
+    /// first.
+    ///
+    /// At most `limit` events are returned. Events that have been rotated out
+    /// of storage are lost.
+    ///
+    /// Returns [ProcessLogSuccess::Events].
+    Get { pid: ProcessId, limit: u32 },
+
+    /// Lists the IDs of every process with stored logs.
+    ///
+    /// Returns [ProcessLogSuccess::Processes].
+    List,
+
+    /// Sends a [FollowedLogEntry] to the first capability of the request
+    /// whenever one of the given processes logs an event. Follows every
+    /// process if `pids` is empty.
+    ///
+    /// The subscription ends when the capability can no longer be sent to.
+    /// Events that are logged faster than they can be sent are dropped.
+    ///
+    /// Returns [ProcessLogSuccess::Following].
+    Follow { pids: Vec<ProcessId> },
+}
+
+/// A stored log event of a process.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ProcessLogEntry {
+    /// The name of the process when it logged this event, if it had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// When this event was logged, in milliseconds since the Unix epoch.
+    pub time: u64,
+
+    /// The level of this event.
+    pub level: ProcessLogLevel,
+
+    /// The module that logged this event.
+    pub module: String,
+
+    /// The message of this event.
+    pub content: String,
+
+    /// The structured fields of this event, if it has any.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// A live log event sent to the subscriber of a [ProcessLogRequest::Follow].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FollowedLogEntry {
+    /// The ID of the process that logged this event.
+    pub pid: ProcessId,
+
+    /// The event itself.
+    #[serde(flatten)]
+    pub entry: ProcessLogEntry,
+}
+
+/// A success response from a [ProcessLogRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProcessLogSuccess {
+    Events(Vec<ProcessLogEntry>),
+    Processes(Vec<ProcessId>),
+    Following,
+}
+
+/// An error response from a [ProcessLogRequest].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ProcessLogError {
+    /// No logs are stored for the requested process.
+    NoLogs,
+
+    /// A [ProcessLogRequest::Follow] request did not include a subscriber.
+    MissingSubscriber,
+
+    /// The log files could not be read.
+    Storage(String),
+}
+
+/// A type shorthand for [ProcessLogSuccess] and [ProcessLogError].
+pub type ProcessLogResponse = Result<ProcessLogSuccess, ProcessLogError>;
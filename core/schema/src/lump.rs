@@ -0,0 +1,38 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::codec::CompactBytes;
+
+/// The name of the lump store service.
+pub const SERVICE_NAME: &str = "hearth.LumpStore";
+
+/// A request to the lump store service.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum LumpRequest {
+    /// Adds a lump with the given contents to the store.
+    ///
+    /// Replies with the new lump's [LumpId](crate::LumpId).
+    Upload {
+        #[serde_as(as = "CompactBytes")]
+        data: Vec<u8>,
+    },
+}
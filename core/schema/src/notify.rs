@@ -0,0 +1,69 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the notification service.
+///
+/// This is provided by a guest notifier that shows notifications in-world.
+pub const SERVICE_NAME: &str = "hearth.Notify";
+
+/// The name of the native service that shows notifications through the
+/// operating system.
+pub const OS_SERVICE_NAME: &str = "hearth.OsNotify";
+
+/// How urgent a [Notification] is.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Urgency {
+    /// Informational notifications that can be missed.
+    Low,
+
+    /// Most notifications, like chat mentions.
+    #[default]
+    Normal,
+
+    /// Notifications that need the user's attention, like errors. These are
+    /// shown for longer than other notifications.
+    Critical,
+}
+
+/// A notification for the user.
+///
+/// Notifications are sent as messages to the [SERVICE_NAME] and
+/// [OS_SERVICE_NAME] services. The first capability of the message, if any,
+/// is sent [NotificationAction::Activated] when the user activates the
+/// notification.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Notification {
+    /// The notification's title.
+    pub title: String,
+
+    /// The notification's body text.
+    pub body: String,
+
+    /// How urgent the notification is.
+    #[serde(default)]
+    pub urgency: Urgency,
+}
+
+/// A message sent to a notification's action capability.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum NotificationAction {
+    /// The user activated the notification, for example by clicking it.
+    Activated,
+}
@@ -0,0 +1,195 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LumpId, ProcessId};
+
+/// The name of the process store service.
+///
+/// The process store gives access to every local process by its ID. It's only
+/// registered in the daemon's registry, so that local IPC clients can reach
+/// it but guests can't.
+pub const SERVICE_NAME: &str = "hearth.ProcessStore";
+
+/// A request to the process store.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProcessStoreRequest {
+    /// Gets a capability to kill and monitor a live process.
+    ///
+    /// Replies with [ProcessStoreSuccess::Get] and the capability on success.
+    Get { pid: ProcessId },
+
+    /// Lists the live processes that match a filter, sorted by PID.
+    ///
+    /// Replies with [ProcessStoreSuccess::List].
+    List { filter: ProcessFilter },
+}
+
+/// A success response from the process store.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProcessStoreSuccess {
+    Get,
+    List(Vec<ProcessEntry>),
+}
+
+/// An error that the process store can reply with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProcessStoreError {
+    /// No live process has the requested ID.
+    NoSuchProcess,
+}
+
+/// A response to a [ProcessStoreRequest].
+pub type ProcessStoreResponse = Result<ProcessStoreSuccess, ProcessStoreError>;
+
+/// A live process listed by [ProcessStoreRequest::List].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProcessEntry {
+    /// The process's ID.
+    pub pid: ProcessId,
+
+    /// A short, human-readable identifier for the process's function.
+    pub name: Option<String>,
+
+    /// Longer documentation of the process's function.
+    pub description: Option<String>,
+
+    /// The authors of the process.
+    #[serde(default)]
+    pub authors: Vec<String>,
+
+    /// The lump that the process's code was loaded from, if any.
+    pub lump: Option<LumpId>,
+}
+
+/// A query over the processes listed by [ProcessStoreRequest::List].
+///
+/// Text fields match case-insensitively if they contain the filter's text.
+/// Unset fields match every process.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ProcessFilter {
+    /// Text in the process's name.
+    pub name: Option<String>,
+
+    /// Text in the process's description.
+    pub description: Option<String>,
+
+    /// Text in any of the process's authors.
+    pub author: Option<String>,
+
+    /// A prefix of the hex ID of the lump the process was loaded from.
+    pub lump: Option<String>,
+}
+
+impl ProcessFilter {
+    /// Tests if a process matches this filter.
+    pub fn matches(&self, process: &ProcessEntry) -> bool {
+        fn contains(field: Option<&str>, filter: &Option<String>) -> bool {
+            let Some(filter) = filter else {
+                return true;
+            };
+
+            field
+                .map(|field| field.to_lowercase().contains(&filter.to_lowercase()))
+                .unwrap_or(false)
+        }
+
+        let author = match &self.author {
+            None => true,
+            Some(_) => process
+                .authors
+                .iter()
+                .any(|author| contains(Some(author.as_str()), &self.author)),
+        };
+
+        let lump = match &self.lump {
+            None => true,
+            Some(prefix) => process
+                .lump
+                .map(|lump| lump.to_string().starts_with(&prefix.to_lowercase()))
+                .unwrap_or(false),
+        };
+
+        contains(process.name.as_deref(), &self.name)
+            && contains(process.description.as_deref(), &self.description)
+            && author
+            && lump
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> ProcessEntry {
+        ProcessEntry {
+            pid: ProcessId(1),
+            name: Some("Terminal".to_string()),
+            description: Some("A terminal emulator.".to_string()),
+            authors: vec!["Alice <alice@example.com>".to_string()],
+            lump: Some(LumpId([0xab; 32])),
+        }
+    }
+
+    fn empty() -> ProcessEntry {
+        ProcessEntry {
+            pid: ProcessId(2),
+            name: None,
+            description: None,
+            authors: Vec::new(),
+            lump: None,
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = ProcessFilter::default();
+        assert!(filter.matches(&entry()));
+        assert!(filter.matches(&empty()));
+    }
+
+    #[test]
+    fn filter_text_is_case_insensitive() {
+        let filter = ProcessFilter {
+            name: Some("TERM".to_string()),
+            description: Some("emulator".to_string()),
+            author: Some("alice".to_string()),
+            ..Default::default()
+        };
+
+        assert!(filter.matches(&entry()));
+        assert!(!filter.matches(&empty()));
+    }
+
+    #[test]
+    fn filter_lump_by_prefix() {
+        let matching = ProcessFilter {
+            lump: Some("ABAB".to_string()),
+            ..Default::default()
+        };
+
+        let other = ProcessFilter {
+            lump: Some("00".to_string()),
+            ..Default::default()
+        };
+
+        assert!(matching.matches(&entry()));
+        assert!(!other.matches(&entry()));
+    }
+}
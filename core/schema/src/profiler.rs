@@ -0,0 +1,76 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the frame profiler service. Accepts [ProfilerRequest] and
+/// responds with [ProfilerReport].
+pub const SERVICE_NAME: &str = "hearth.Profiler";
+
+/// A request to the frame profiler service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProfilerRequest {
+    /// Gets a report of up to the given number of the most recent frames.
+    GetReport { frames: u32 },
+}
+
+/// A report of the most recent frames, ordered from oldest to newest.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProfilerReport {
+    /// Whether the GPU supports timing its passes. If false, no frame has
+    /// GPU timings.
+    pub gpu_timing: bool,
+
+    /// The profiles of the frames.
+    pub frames: Vec<FrameProfile>,
+}
+
+/// The profile of a single drawn frame.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FrameProfile {
+    /// The number of frames drawn before this one.
+    pub index: u64,
+
+    /// The time in milliseconds between the start of the previous frame and
+    /// the start of this one.
+    pub frame_time: f32,
+
+    /// The total CPU time in milliseconds spent drawing this frame.
+    pub cpu_time: f32,
+
+    /// The CPU time spent on each step of drawing this frame, including each
+    /// custom render routine.
+    pub cpu: Vec<Timing>,
+
+    /// The GPU time spent on each pass of this frame.
+    ///
+    /// GPU timings are read back from the GPU a few frames after they are
+    /// recorded, so this is `None` for the newest frames and for frames
+    /// whose timings could not be recorded.
+    pub gpu: Option<Vec<Timing>>,
+}
+
+/// The time that a named part of a frame took.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Timing {
+    /// The name of this part of the frame.
+    pub name: String,
+
+    /// The time this part took in milliseconds.
+    pub time: f32,
+}
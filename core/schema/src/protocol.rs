@@ -16,10 +16,20 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
+use std::net::SocketAddr;
+
 use serde::{Deserialize, Serialize};
 
+use crate::LumpId;
+
 pub use crate::Permissions;
 
+/// The maximum length in bytes of an encoded [CapOperation] sent over a
+/// transport.
+///
+/// Peers that send longer operations are disconnected.
+pub const MAX_OP_LEN: u32 = 64 * 1024 * 1024;
+
 /// A reason for the revocation or unlinking of a process.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum UnlinkReason {
@@ -38,6 +48,7 @@ pub enum UnlinkReason {
 pub enum CapOperation {
     Local(LocalCapOperation),
     Remote(RemoteCapOperation),
+    Lump(LumpOperation),
 }
 
 /// Operations on local capabilities.
@@ -65,6 +76,19 @@ pub enum LocalCapOperation {
     SetRootCap { id: u32 },
 }
 
+/// A capability transferred in a [RemoteCapOperation::Send].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TransferredCap {
+    /// A capability declared by the sender.
+    Local(u32),
+
+    /// A capability declared by the receiver, sent back to it.
+    ///
+    /// The receiver demotes the capability to the given permissions so that
+    /// the sender can't gain permissions that it wasn't given.
+    Remote { id: u32, perms: Permissions },
+}
+
 /// Operations on remote capabilities.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum RemoteCapOperation {
@@ -91,8 +115,8 @@ pub enum RemoteCapOperation {
         /// The contents of the message.
         data: Vec<u8>,
 
-        /// The local capabilities transferred in this message.
-        caps: Vec<u32>,
+        /// The capabilities transferred in this message.
+        caps: Vec<TransferredCap>,
     },
 
     /// Kills a remote capability.
@@ -105,3 +129,61 @@ pub enum RemoteCapOperation {
         id: u32,
     },
 }
+
+/// Operations for exchanging lumps between two peers.
+///
+/// A peer only serves the lumps that it has referred to in the messages it
+/// has sent over the same connection.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LumpOperation {
+    /// Advertises that the sender serves these lumps to other peers on the
+    /// given TCP port of its address.
+    ///
+    /// Replaces the sender's previous advertisement. Only sent by clients to
+    /// servers, which withdraw advertisements when the connection closes.
+    Advertise { port: u16, lumps: Vec<LumpId> },
+
+    /// Looks up the peers that a lump can be fetched from directly.
+    ///
+    /// Answered with [LumpOperation::Providers].
+    Locate { request: u32, lump: LumpId },
+
+    /// Answers a [LumpOperation::Locate] with a ticket for each peer that
+    /// the lump can be fetched from.
+    Providers {
+        request: u32,
+        tickets: Vec<LumpTicket>,
+    },
+
+    /// Tells a client to serve a lump once to the peer that presents a
+    /// ticket.
+    ///
+    /// Only sent by servers to clients.
+    Expect {
+        ticket: [u8; 32],
+        secret: [u8; 32],
+        lump: LumpId,
+    },
+
+    /// Requests the contents of a lump.
+    ///
+    /// Answered with [LumpOperation::Lump].
+    Fetch { request: u32, lump: LumpId },
+
+    /// Answers a [LumpOperation::Fetch] with the lump's contents, if the
+    /// sender has it and is willing to serve it.
+    Lump { request: u32, data: Option<Vec<u8>> },
+}
+
+/// A one-time permission to fetch a lump directly from another peer.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LumpTicket {
+    /// The address that the providing peer serves lumps on.
+    pub addr: SocketAddr,
+
+    /// The ticket to present to the providing peer.
+    pub ticket: [u8; 32],
+
+    /// The secret that the transfer is encrypted with.
+    pub secret: [u8; 32],
+}
@@ -18,10 +18,49 @@
 
 use serde::{Deserialize, Serialize};
 
+/// The name of the peer registry service.
+///
+/// The peer registry is a read-only registry of the services that connected
+/// peers export. Each peer exports a registry as its network root, and
+/// requests to the peer registry are answered by the first peer, in order of
+/// connection, that has the requested service.
+pub const PEER_REGISTRY_SERVICE_NAME: &str = "hearth.PeerRegistry";
+
+/// The name of the registry factory service.
+///
+/// The registry factory creates read-only registries from the capabilities
+/// that are sent to it. Accepts [RegistryFactoryRequest].
+pub const REGISTRY_FACTORY_SERVICE_NAME: &str = "hearth.RegistryFactory";
+
+/// A request to the registry factory to create a read-only registry.
+///
+/// The first capability in the message is the reply capability, and each
+/// following capability is the service with the name at the same index in
+/// `names`. The factory replies with a [RegistryFactoryResponse] and, on
+/// success, a capability to the new registry.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RegistryFactoryRequest {
+    pub names: Vec<String>,
+}
+
+/// An error that a registry factory can reply with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum RegistryFactoryError {
+    /// The request named more services than it had capabilities.
+    MissingServices,
+}
+
+/// A response to a [RegistryFactoryRequest].
+pub type RegistryFactoryResponse = Result<(), RegistryFactoryError>;
+
 /// A message schema for messages sent to a registry process. All variants require
 /// that a reply cap is the first capability in the message.
 ///
 /// Compliant registry processes will reply with a [RegistryResponse].
+///
+/// Read-only registries refuse the requests that change the registry.
+/// Mutable registries hand out a register-capable capability that accepts
+/// every request, and a read-only view of the same services.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RegistryRequest {
     /// Gets a service by name. Returns [RegistryResponse::Get].
@@ -34,6 +73,34 @@ pub enum RegistryRequest {
     /// Requests a list of all of the registered services. Returns
     /// [RegistryReponse::List].
     List,
+
+    /// Watches a service by name. Returns [RegistryResponse::Watch].
+    ///
+    /// Mutable registries reply immediately with whether the service is
+    /// registered, then reply again each time the service is registered or
+    /// removed. Each reply includes the service when it's registered. The
+    /// reply capability must have the monitor permission, and the watch ends
+    /// when it goes down.
+    ///
+    /// Registries that don't send notifications, like read-only registries,
+    /// reply once with the service if they have it.
+    Watch { name: String },
+
+    /// Removes a service by name. Returns [RegistryResponse::Remove].
+    Remove { name: String },
+
+    /// Creates a child registry scoped to a namespace prefix. Returns
+    /// [RegistryResponse::Scope].
+    ///
+    /// The child registry shares this registry's services, but can only get,
+    /// list, watch, register, and remove services whose names start with this
+    /// registry's own prefix followed by `prefix`. Hand it to sandboxed
+    /// processes to limit them to a namespace.
+    ///
+    /// The second capability is the child registry's owner, which must have
+    /// the monitor permission. The child registry is freed when its owner
+    /// goes down.
+    Scope { prefix: String },
 }
 
 /// A response to a [RegistryRequest].
@@ -48,10 +115,32 @@ pub enum RegistryResponse {
     ///   was an old service present.
     /// - `Some(false)`: the service has been successfully registered and no
     ///   service has been replaced.
-    /// - `None`: this registry is read-only and the service has not been
-    ///   registered.
+    /// - `None`: this registry is read-only or the name can't be replaced,
+    ///   and the service has not been registered.
     Register(Option<bool>),
 
     /// Returns a list of the names of all services in this registry.
     List(Vec<String>),
+
+    /// Returns one of the following:
+    /// - `Some(true)`: the watched service is registered and is given with
+    ///   the first capability.
+    /// - `Some(false)`: the watched service is not registered or has been
+    ///   removed.
+    /// - `None`: this registry doesn't send notifications and doesn't have
+    ///   the service, or the reply capability can't be monitored. No more
+    ///   replies will be sent.
+    Watch(Option<bool>),
+
+    /// Returns one of the following:
+    /// - `Some(true)`: the service has been removed.
+    /// - `Some(false)`: no service with the name was registered.
+    /// - `None`: this registry is read-only or the service can't be removed,
+    ///   and no service has been removed.
+    Remove(Option<bool>),
+
+    /// If true, returns the scoped registry with the first capability and a
+    /// read-only view of it with the second. If false, this registry is
+    /// read-only or the owner can't be monitored, and no caps are given.
+    Scope(bool),
 }
@@ -18,9 +18,9 @@
 
 use glam::{Mat4, UVec2, Vec2, Vec3, Vec4};
 use serde::{Deserialize, Serialize};
-use serde_with::{base64::Base64, serde_as};
+use serde_with::serde_as;
 
-use crate::{ByteVec, LumpId};
+use crate::{codec::CompactBytes, ByteVec, LumpId};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RendererRequest {
@@ -34,6 +34,25 @@ pub enum RendererRequest {
         initial_state: DirectionalLightState,
     },
 
+    /// Adds a new point light to the scene.
+    ///
+    /// Returns [RendererSuccess::Ok] and a capability to the new light when
+    /// successful. The light accepts [PointLightUpdate] messages.
+    ///
+    /// When the capability is killed, the light is removed from the scene.
+    AddPointLight { initial_state: PointLightState },
+
+    /// Adds a new spot light to the scene.
+    ///
+    /// Returns [RendererSuccess::Ok] and a capability to the new light when
+    /// successful. The light accepts [SpotLightUpdate] messages.
+    ///
+    /// When the capability is killed, the light is removed from the scene.
+    ///
+    /// The renderer backend can't attenuate lights by cone yet, so this
+    /// currently always fails with [RendererError::Unsupported].
+    AddSpotLight { initial_state: SpotLightState },
+
     /// Adds a new object to the scene.
     ///
     /// Returns [RendererSuccess::Ok] and a capability to the new object when
@@ -52,6 +71,15 @@ pub enum RendererRequest {
 
         /// The initial transform of this object.
         transform: Mat4,
+
+        /// Whether this object moves often.
+        ///
+        /// Transform updates to dynamic objects are interpolated over the
+        /// time between updates instead of being applied immediately, so
+        /// objects moved on every simulation tick move smoothly at any frame
+        /// rate. Motion lags behind by one update in exchange.
+        #[serde(default)]
+        dynamic: bool,
     },
 
     /// Updates the scene's skybox.
@@ -62,10 +90,249 @@ pub enum RendererRequest {
         texture: LumpId,
     },
 
+    /// Updates the scene's skybox from a single equirectangular environment
+    /// map, converted into a cube texture by the host.
+    ///
+    /// The map is a Radiance HDR or OpenEXR file, or any 8-bit image format
+    /// that the host can decode. Its radiance is scaled by `exposure` and
+    /// clamped into the skybox's 8-bit sRGB range.
+    ///
+    /// Returns [RendererSuccess::Ok] with no capabilities when successful.
+    /// Returns [RendererError::LumpError] if the map fails to decode, or
+    /// [RendererError::InvalidSize] if `face_size` is zero or too large.
+    SetEnvironmentMap {
+        /// The lump ID of the environment map file.
+        map: LumpId,
+
+        /// The width and height of each face of the cube texture in pixels.
+        face_size: u32,
+
+        /// The factor that the map's radiance is multiplied by.
+        exposure: f32,
+
+        /// Whether to also set the ambient lighting to the map's average
+        /// radiance, so that the scene is lit with the sky's color.
+        ///
+        /// The renderer has no image-based lighting yet, so the ambient
+        /// color is the only lighting taken from the map.
+        set_ambient: bool,
+    },
+
     /// Updates the scene's ambient lighting.
     ///
     /// Returns [RendererSuccess::Ok] with no capabilities when successful.
     SetAmbientLighting { ambient: Vec4 },
+
+    /// Updates the renderer's quality settings.
+    ///
+    /// Returns [RendererSuccess::Ok] with no capabilities when successful.
+    SetRenderSettings(RenderSettings),
+
+    /// Replaces the post-processing effects applied to the main view.
+    ///
+    /// Returns [RendererSuccess::Ok] with no capabilities when successful.
+    /// Returns [RendererError::LumpError] if the color grading lookup table
+    /// fails to load.
+    SetPostProcessing(PostProcessSettings),
+
+    /// Creates a texture that the scene is rendered into from a separate
+    /// camera, for mirrors, security cameras, portals, and the like.
+    ///
+    /// Returns [RendererSuccess::RenderTarget] and a capability to the new
+    /// render target when successful. The render target accepts
+    /// [RenderTargetUpdate] messages.
+    ///
+    /// When the capability is killed, the scene stops being rendered into the
+    /// texture and the texture keeps its last contents.
+    CreateRenderTarget {
+        /// The size of the texture in pixels. Each dimension must be between
+        /// 1 and 4096.
+        size: UVec2,
+
+        /// Vertical field of view in degrees.
+        vfov: f32,
+
+        /// Near plane distance. All projection uses an infinite far plane.
+        near: f32,
+
+        /// The camera's view matrix.
+        view: Mat4,
+    },
+
+    /// Creates a viewport that renders the scene from a separate camera into
+    /// a rectangle of the main window, for splitscreen or picture-in-picture.
+    ///
+    /// Viewports are drawn over the main view in the order they were created.
+    ///
+    /// Returns [RendererSuccess::Ok] and a capability to the new viewport
+    /// when successful. The viewport accepts [ViewportUpdate] messages.
+    ///
+    /// When the capability is killed, the viewport is removed.
+    CreateViewport {
+        /// The rectangle of the window to draw the viewport in.
+        rect: ViewportRect,
+
+        /// Vertical field of view in degrees.
+        vfov: f32,
+
+        /// Near plane distance. All projection uses an infinite far plane.
+        near: f32,
+
+        /// The camera's view matrix.
+        view: Mat4,
+    },
+
+    /// Subscribes a capability to [RendererEvent]s.
+    ///
+    /// The capability to subscribe is the first capability after the reply
+    /// capability. Returns [RendererSuccess::Ok] with no capabilities, or
+    /// [RendererError::MissingSubscriber] if there is no such capability.
+    Subscribe,
+
+    /// Unsubscribes a capability from [RendererEvent]s.
+    ///
+    /// The capability to unsubscribe is the first capability after the reply
+    /// capability. Returns [RendererSuccess::Ok] with no capabilities, or
+    /// [RendererError::MissingSubscriber] if there is no such capability.
+    Unsubscribe,
+}
+
+/// An event sent to the renderer's subscribers.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum RendererEvent {
+    /// The GPU device was lost, and nothing is drawn until it is recovered.
+    DeviceLost,
+
+    /// The renderer has been re-created on a new GPU device.
+    ///
+    /// Every light, object, render target, and viewport created before the
+    /// device was lost is no longer drawn, and the skybox and
+    /// post-processing effects have been reset. Debug draws and canvases are
+    /// cleared and terminals are no longer drawn. Subscribers should
+    /// re-create their renderer state. Assets are re-uploaded from their
+    /// lumps automatically.
+    DeviceRestored,
+}
+
+/// A rectangle of the main window, in fractions of the window's size.
+///
+/// The origin is the window's top-left corner. Parts of the rectangle outside
+/// of the window are not drawn.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ViewportRect {
+    /// The position of the rectangle's top-left corner.
+    pub position: Vec2,
+
+    /// The width and height of the rectangle. Must be positive.
+    pub size: Vec2,
+}
+
+impl ViewportRect {
+    /// Tests if this rectangle is finite and has a positive size.
+    pub fn is_valid(&self) -> bool {
+        self.position.is_finite() && self.size.is_finite() && self.size.cmpgt(Vec2::ZERO).all()
+    }
+}
+
+/// Global renderer quality settings.
+///
+/// These can also be set in the `renderer` table of the client's config file.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// The number of multisample anti-aliasing (MSAA) samples per pixel.
+    ///
+    /// Only 1 (no MSAA) and 4 are supported. Other values are rounded down
+    /// to one of those.
+    pub msaa_samples: u32,
+
+    /// The scale of the 3D scene's rendering resolution relative to the
+    /// window's resolution. Clamped between 0.25 and 2.0.
+    pub resolution_scale: f32,
+
+    /// Whether to synchronize frame presentation with the display's refresh
+    /// rate.
+    pub vsync: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            msaa_samples: 1,
+            resolution_scale: 1.0,
+            vsync: true,
+        }
+    }
+}
+
+/// Post-processing effects applied to the main view.
+///
+/// Render targets and viewports are not post-processed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct PostProcessSettings {
+    /// Makes bright parts of the scene glow. Disabled if `None`.
+    pub bloom: Option<BloomSettings>,
+
+    /// Whether to smooth jagged edges with fast approximate anti-aliasing
+    /// (FXAA).
+    pub fxaa: bool,
+
+    /// A color grading lookup table (LUT) lump. Disabled if `None`.
+    ///
+    /// The lump is [TextureData] containing a horizontal strip of `N` square
+    /// slices of `N` by `N` pixels, where `N` is between 2 and 64. Red
+    /// increases along each slice's X axis, green along its Y axis, and blue
+    /// from slice to slice.
+    pub color_grading: Option<LumpId>,
+
+    /// Darkens the edges of the view. Disabled if `None`.
+    pub vignette: Option<VignetteSettings>,
+}
+
+/// Settings for the bloom post-processing effect.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct BloomSettings {
+    /// The brightness above which parts of the scene begin to glow.
+    pub threshold: f32,
+
+    /// The strength of the glow.
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.5,
+        }
+    }
+}
+
+/// Settings for the vignette post-processing effect.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct VignetteSettings {
+    /// How dark the edges become, from 0 (not at all) to 1 (black).
+    pub intensity: f32,
+
+    /// The distance from the center at which darkening starts, where 1 is
+    /// the distance to the corners.
+    pub radius: f32,
+
+    /// The distance over which darkening fades in.
+    pub softness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.5,
+            radius: 0.75,
+            softness: 0.5,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -74,24 +341,64 @@ pub enum RendererSuccess {
     ///
     /// Capabilities returned by this response are defined by the request kind.
     Ok,
+
+    /// A render target was created.
+    ///
+    /// Returns a capability to the render target.
+    RenderTarget {
+        /// The lump ID of the render target's texture.
+        ///
+        /// This can be used as the albedo of a [MaterialData] but has no
+        /// contents of its own.
+        texture: LumpId,
+    },
+
+    /// A render target's contents were captured.
+    Capture {
+        /// The lump ID of the [TextureData] with the captured pixels.
+        texture: LumpId,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RendererError {
     /// A lump involved in this operation was improperly formatted or not found.
     LumpError,
+
+    /// The requested texture or viewport size is zero or too large.
+    InvalidSize,
+
+    /// A subscription request is missing the capability to subscribe.
+    MissingSubscriber,
+
+    /// The GPU failed to read back a render target, such as when the device
+    /// was lost or the render target was destroyed first.
+    CaptureFailed,
+
+    /// The renderer backend doesn't support this request yet.
+    Unsupported,
 }
 
 pub type RendererResponse = Result<RendererSuccess, RendererError>;
 
+/// The initial state of a directional light.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct DirectionalLightState {
+    /// The linear RGB color of this light.
     pub color: Vec3,
+
+    /// The brightness of this light.
     pub intensity: f32,
+
+    /// The direction this light points in.
     pub direction: Vec3,
+
+    /// The distance from the camera that shadows are rendered to.
     pub distance: f32,
 }
 
+/// An update to a directional light. Each variant replaces the
+/// [DirectionalLightState] field of the same name.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum DirectionalLightUpdate {
     Color(Vec3),
@@ -100,6 +407,91 @@ pub enum DirectionalLightUpdate {
     Distance(f32),
 }
 
+/// The initial state of a point light.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PointLightState {
+    /// The position of this light in world space.
+    pub position: Vec3,
+
+    /// The linear RGB color of this light.
+    pub color: Vec3,
+
+    /// The brightness of this light.
+    pub intensity: f32,
+
+    /// The distance from the light at which its falloff reaches zero.
+    pub radius: f32,
+}
+
+/// An update to a point light. Each variant replaces the
+/// [PointLightState] field of the same name.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum PointLightUpdate {
+    Position(Vec3),
+    Color(Vec3),
+    Intensity(f32),
+    Radius(f32),
+}
+
+/// The initial state of a spot light.
+///
+/// Spot lights are not supported by the renderer backend yet. See
+/// [RendererRequest::AddSpotLight].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpotLightState {
+    /// The position of this light in world space.
+    pub position: Vec3,
+
+    /// The direction this light points in.
+    pub direction: Vec3,
+
+    /// The linear RGB color of this light.
+    pub color: Vec3,
+
+    /// The brightness of this light.
+    pub intensity: f32,
+
+    /// The distance from the light at which its falloff reaches zero.
+    pub radius: f32,
+
+    /// The angle from the direction in radians where the cone's falloff begins.
+    pub inner_angle: f32,
+
+    /// The angle from the direction in radians where the cone's falloff ends.
+    pub outer_angle: f32,
+
+    /// The exponent of the cone's falloff from the inner angle to the outer
+    /// angle. 1 fades linearly, and higher values fade out sooner.
+    #[serde(default = "SpotLightState::default_falloff")]
+    pub falloff: f32,
+}
+
+impl SpotLightState {
+    /// The default [Self::falloff], which fades linearly.
+    pub fn default_falloff() -> f32 {
+        1.0
+    }
+}
+
+/// An update to a spot light. Each variant replaces the [SpotLightState]
+/// field of the same name.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum SpotLightUpdate {
+    Position(Vec3),
+    Direction(Vec3),
+    Color(Vec3),
+    Intensity(f32),
+    Radius(f32),
+
+    /// Replaces both [SpotLightState::inner_angle] and
+    /// [SpotLightState::outer_angle].
+    ConeAngles {
+        inner: f32,
+        outer: f32,
+    },
+    Falloff(f32),
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum ObjectUpdate {
     Transform(Mat4),
@@ -110,10 +502,126 @@ pub enum ObjectUpdate {
     },
 }
 
+/// An update to a render target.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum RenderTargetUpdate {
+    /// Updates the camera that the scene is rendered from.
+    SetCamera {
+        /// Vertical field of view in degrees.
+        vfov: f32,
+
+        /// Near plane distance. All projection uses an infinite far plane.
+        near: f32,
+
+        /// The camera's view matrix.
+        view: Mat4,
+    },
+
+    /// Reads back the render target's contents after it is next rendered,
+    /// for thumbnails, previews, and automated tests.
+    ///
+    /// The first attached capability is sent a [RendererResponse] with
+    /// [RendererSuccess::Capture] when successful, or
+    /// [RendererError::CaptureFailed] if the contents couldn't be read.
+    Capture,
+}
+
+/// An update to a viewport.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ViewportUpdate {
+    /// Updates the camera that the scene is rendered from.
+    SetCamera {
+        /// Vertical field of view in degrees.
+        vfov: f32,
+
+        /// Near plane distance. All projection uses an infinite far plane.
+        near: f32,
+
+        /// The camera's view matrix.
+        view: Mat4,
+    },
+
+    /// Moves the viewport to a new rectangle of the window.
+    ///
+    /// Invalid rectangles are ignored.
+    SetRect(ViewportRect),
+}
+
+/// A command to the compositor service, `hearth.Compositor`, which applies
+/// full-screen effects to the main window.
+///
+/// Each effect moves from its current value to the new one over `duration`
+/// seconds, or changes immediately if `duration` is zero. If the command
+/// carries a capability, it's sent an empty message once the transition is
+/// finished, so that guests can, for example, teleport the player while the
+/// window is faded out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CompositorCommand {
+    /// Fades the window towards a solid color.
+    ///
+    /// The fade covers the whole window, including overlays like terminals.
+    Fade {
+        /// The linear RGB color to fade to.
+        color: Vec3,
+
+        /// How much of the window is covered, from 0 (not at all) to 1
+        /// (completely).
+        amount: f32,
+
+        /// The length of the transition in seconds.
+        duration: f32,
+    },
+
+    /// Darkens the edges of the window. Disabled if `settings` is `None`.
+    ///
+    /// This is drawn over the whole window independently of the vignette in
+    /// [PostProcessSettings].
+    Vignette {
+        settings: Option<VignetteSettings>,
+
+        /// The length of the transition in seconds.
+        duration: f32,
+    },
+
+    /// Transforms the linear colors of the main view by a matrix.
+    ///
+    /// Colors are multiplied as RGB vectors with a W of 1, so the matrix's
+    /// last column is added to every color. The filter is applied after
+    /// color grading but before either vignette, and isn't applied to
+    /// overlays. The identity matrix disables it.
+    ColorFilter {
+        matrix: Mat4,
+
+        /// The length of the transition in seconds.
+        duration: f32,
+    },
+
+    /// Returns every effect to its disabled state.
+    Reset {
+        /// The length of the transition in seconds.
+        duration: f32,
+    },
+}
+
+impl CompositorCommand {
+    /// Gets the length of this command's transition in seconds.
+    pub fn duration(&self) -> f32 {
+        use CompositorCommand::*;
+        match self {
+            Fade { duration, .. }
+            | Vignette { duration, .. }
+            | ColorFilter { duration, .. }
+            | Reset { duration } => *duration,
+        }
+    }
+}
+
 /// A material lump's data format.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MaterialData {
     /// The lump ID of the [TextureData] to use for the material's albedo.
+    ///
+    /// This lump may also be a KTX2 file. See [TextureData] for details.
     pub albedo: LumpId,
 }
 
@@ -123,35 +631,41 @@ pub struct MaterialData {
 #[serde_as]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MeshData {
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub positions: ByteVec<Vec3>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub normals: ByteVec<Vec3>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub tangents: ByteVec<Vec3>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub uv0: ByteVec<Vec2>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub uv1: ByteVec<Vec2>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub colors: ByteVec<[u8; 4]>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub joint_indices: ByteVec<[u16; 4]>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub joint_weights: ByteVec<Vec4>,
 
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub indices: ByteVec<u32>,
 }
 
 /// A texture lump's data format.
+///
+/// 2D texture lumps may alternatively contain a KTX2 file, which is detected
+/// by its file identifier. KTX2 textures may be block-compressed (BC, ETC2,
+/// EAC, or ASTC 4x4) with uploaded mipmaps and Zstandard supercompression,
+/// but fail to load if the device doesn't support their format. Basis
+/// Universal textures must be transcoded to one of those formats first.
 #[serde_as]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TextureData {
@@ -163,6 +677,6 @@ pub struct TextureData {
 
     /// The data of this texture. Currently only supports RGBA sRGB. Must be
     /// a size equivalent to `size.x * size.y * 4`.
-    #[serde_as(as = "Base64")]
+    #[serde_as(as = "CompactBytes")]
     pub data: Vec<u8>,
 }
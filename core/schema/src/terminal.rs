@@ -21,12 +21,29 @@ use std::collections::HashMap;
 use glam::{Quat, Vec2, Vec3};
 use serde::{Deserialize, Serialize};
 
-use crate::Color;
+use crate::{Color, LumpId};
+
+/// The name of the terminal factory service, which creates terminals running
+/// the default shell.
+pub const SERVICE_NAME: &str = "hearth.terminal.TerminalFactory";
+
+/// The name of the terminal factory service that also accepts custom
+/// [TerminalCommands][TerminalCommand].
+///
+/// This is separate from [SERVICE_NAME] so that only the processes that are
+/// given this service can choose which programs run on the host.
+pub const COMMAND_SERVICE_NAME: &str = "hearth.terminal.CommandTerminalFactory";
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum FactoryError {
     /// The request has failed to parse.
     ParseError,
+
+    /// A custom command was requested from a factory that doesn't allow them.
+    CommandNotAllowed,
+
+    /// The terminal's command is malformed or failed to start.
+    SpawnFailed,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -45,11 +62,188 @@ pub enum TerminalUpdate {
     Quit,
     Input(String),
     State(TerminalState),
+
+    /// Scrolls the terminal's view through its scrollback.
+    Scroll(TerminalScroll),
+
+    /// Reads a range of the terminal's lines.
+    ///
+    /// Line 0 is the top line of the screen, and negative lines are in the
+    /// scrollback, which goes back [TerminalContents::history_size] lines.
+    /// Lines outside of the screen and scrollback are left out. At most
+    /// [MAX_READ_LINES] lines are read at once.
+    ///
+    /// The first attached capability is sent the [TerminalContents].
+    Read {
+        /// The first line to read.
+        start: i32,
+
+        /// The number of lines to read.
+        count: u32,
+    },
+
+    /// Replaces the terminal's font and the size of its text.
+    ///
+    /// Fonts that fail to load are logged and ignored.
+    SetFont {
+        /// The new font, or `None` for the built-in font.
+        fonts: Option<TerminalFonts>,
+
+        /// The size of an em in the terminal's units. Overrides
+        /// [TerminalState::units_per_em].
+        units_per_em: f32,
+    },
+}
+
+/// A terminal font, loaded from lumps of TrueType or OpenType font files.
+///
+/// Fonts on the host filesystem can be loaded into lumps with the filesystem
+/// service. Missing styles fall back to the regular style.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TerminalFonts {
+    /// The regular style of the font.
+    pub regular: LumpId,
+
+    /// The italic style of the font.
+    pub italic: Option<LumpId>,
+
+    /// The bold style of the font.
+    pub bold: Option<LumpId>,
+
+    /// The bold italic style of the font.
+    pub bold_italic: Option<LumpId>,
+}
+
+/// The most lines that can be read with a single [TerminalUpdate::Read].
+pub const MAX_READ_LINES: u32 = 1000;
+
+/// A change to the part of a terminal's scrollback that is displayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TerminalScroll {
+    /// Scrolls up into the scrollback by a number of lines, or down towards
+    /// the screen if negative.
+    Lines(i32),
+
+    /// Scrolls up by the height of the screen.
+    PageUp,
+
+    /// Scrolls down by the height of the screen.
+    PageDown,
+
+    /// Scrolls to the oldest line of the scrollback.
+    Top,
+
+    /// Scrolls back down to the screen.
+    Bottom,
+}
+
+/// A range of lines read from a terminal.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TerminalContents {
+    /// The number of columns in the terminal's grid.
+    pub columns: u32,
+
+    /// The number of lines on the terminal's screen.
+    pub screen_lines: u32,
+
+    /// The number of lines in the terminal's scrollback.
+    pub history_size: u32,
+
+    /// The number of lines that the view is scrolled up into the scrollback.
+    pub display_offset: u32,
+
+    /// The line and column of the cursor.
+    pub cursor: (i32, u32),
+
+    /// The line number of the first of [Self::lines].
+    pub start: i32,
+
+    /// The lines that were read.
+    pub lines: Vec<TerminalLine>,
+}
+
+/// A line of a terminal.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TerminalLine {
+    /// Runs of text in this line with the same attributes, from left to right.
+    ///
+    /// Together, the spans cover every column of the line.
+    pub spans: Vec<TerminalSpan>,
+
+    /// True if this line was soft-wrapped onto the next line, so that copied
+    /// text can join them.
+    pub wrapped: bool,
+}
+
+/// A run of text with the same attributes in a [TerminalLine].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TerminalSpan {
+    /// The text of this span.
+    pub text: String,
+
+    /// The foreground color.
+    pub fg: Color,
+
+    /// The background color.
+    pub bg: Color,
+
+    /// Attributes of the text.
+    pub flags: TerminalFlags,
+}
+
+bitflags::bitflags! {
+    /// The attributes of a [TerminalSpan].
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+    pub struct TerminalFlags: u32 {
+        const BOLD = 1 << 0;
+        const ITALIC = 1 << 1;
+        const UNDERLINE = 1 << 2;
+        const DOUBLE_UNDERLINE = 1 << 3;
+        const STRIKEOUT = 1 << 4;
+        const DIM = 1 << 5;
+
+        /// The foreground and background colors are displayed swapped.
+        const INVERSE = 1 << 6;
+
+        /// The text isn't displayed.
+        const HIDDEN = 1 << 7;
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum FactoryRequest {
-    CreateTerminal(TerminalState),
+    /// Creates a new terminal.
+    ///
+    /// Returns [FactoryError::CommandNotAllowed] if `command` is set and the
+    /// factory isn't the [COMMAND_SERVICE_NAME] service.
+    CreateTerminal {
+        /// The initial state of the terminal.
+        state: TerminalState,
+
+        /// The command to run in the terminal instead of the default shell.
+        command: Option<TerminalCommand>,
+    },
+}
+
+/// A program to run in a terminal.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TerminalCommand {
+    /// The name or path of the program.
+    pub program: String,
+
+    /// The program's arguments.
+    pub args: Vec<String>,
+
+    /// The directory to run the program in. Defaults to the host's working
+    /// directory.
+    pub working_directory: Option<String>,
+
+    /// Environment variables to set for the program, in addition to the
+    /// host's.
+    ///
+    /// Names can't be empty, start with `-`, or contain `=`. When any are
+    /// set, the program name can't start with `-` or contain `=` either.
+    pub env: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -0,0 +1,140 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the simulation tick service. Accepts [TickCommand].
+pub const TICK_SERVICE_NAME: &str = "hearth.SimulationTick";
+
+/// A message to the simulation tick service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum TickCommand {
+    /// Subscribes to [Ticks][Tick] using the first attached capability.
+    ///
+    /// If the capability has the monitor permission, it will be automatically
+    /// unsubscribed when down.
+    Subscribe,
+
+    /// Unsubscribes from ticks using the first attached capability.
+    Unsubscribe,
+}
+
+/// A simulation tick.
+///
+/// Ticks are sent at a fixed rate that is independent of the display's frame
+/// rate, so that simulations can step at a stable rate while rendering
+/// interpolates between steps. Ticks that are missed because the host fell
+/// behind are skipped instead of being sent late, which shows up as a gap in
+/// [Tick::index].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Tick {
+    /// The number of tick periods since the tick service started.
+    pub index: u64,
+
+    /// The length of a tick period in seconds.
+    pub dt: f32,
+}
+
+/// The name of the virtual clock service. Accepts [ClockRequest].
+pub const CLOCK_SERVICE_NAME: &str = "hearth.Clock";
+
+/// The name of the virtual clock's admin service. Accepts
+/// [ClockAdminRequest] and responds with [ClockState].
+///
+/// Only processes that are given this service can control the clock.
+pub const CLOCK_ADMIN_SERVICE_NAME: &str = "hearth.ClockAdmin";
+
+/// A message to the virtual clock service.
+///
+/// The virtual clock advances once per simulation tick by the tick period
+/// times its [ClockState::scale], and stops while it's paused. Processes that
+/// are timed by the virtual clock instead of wall-clock timers can be paused,
+/// slowed down, sped up, and stepped through one tick at a time with
+/// [ClockAdminRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ClockRequest {
+    /// Subscribes to [ClockTicks][ClockTick] using the first attached
+    /// capability.
+    ///
+    /// If the capability has the monitor permission, it will be automatically
+    /// unsubscribed when down.
+    Subscribe,
+
+    /// Unsubscribes from clock ticks using the first attached capability.
+    Unsubscribe,
+
+    /// Sends an empty message to the first attached capability once the
+    /// virtual clock has advanced by the given number of seconds.
+    Sleep { secs: f32 },
+
+    /// Sends the current [ClockState] to the first attached capability.
+    GetState,
+}
+
+/// A tick of the virtual clock.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ClockTick {
+    /// The number of ticks that the virtual clock has advanced by.
+    pub index: u64,
+
+    /// The virtual time in seconds that this tick advanced by.
+    pub dt: f32,
+
+    /// The virtual time in seconds after this tick.
+    pub time: f64,
+}
+
+/// A request to the virtual clock's admin service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ClockAdminRequest {
+    /// Gets the clock's state without changing it.
+    GetState,
+
+    /// Stops the clock from advancing on its own.
+    Pause,
+
+    /// Lets the clock advance on its own again.
+    Resume,
+
+    /// Sets the rate that the clock advances at compared to wall-clock time.
+    ///
+    /// Clamped between 0 and 1000.
+    SetScale { scale: f32 },
+
+    /// Advances the clock by the given number of ticks right away, using the
+    /// current scale. Usually used while paused.
+    ///
+    /// At most 10000 ticks are stepped per request.
+    Step { ticks: u32 },
+}
+
+/// The state of the virtual clock.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ClockState {
+    /// The virtual time in seconds since the clock started.
+    pub time: f64,
+
+    /// The number of ticks that the clock has advanced by.
+    pub index: u64,
+
+    /// The rate that the clock advances at compared to wall-clock time.
+    pub scale: f32,
+
+    /// Whether the clock is paused.
+    pub paused: bool,
+}
@@ -16,9 +16,12 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::LumpId;
+use crate::{LumpId, ProcessId};
 use serde::{Deserialize, Serialize};
 
+/// The name of the Wasm process spawner service. Accepts [WasmSpawnInfo].
+pub const SPAWNER_SERVICE_NAME: &str = "hearth.wasm.WasmProcessSpawner";
+
 /// A spawn message sent to the Wasm process spawner service.
 ///
 /// The service replies with a message containing the decimal representation of
@@ -31,4 +34,334 @@ pub struct WasmSpawnInfo {
     /// The identifier of the entrypoint to execute. If not specified, runs
     /// the exported "run" function.
     pub entrypoint: Option<u32>,
+
+    /// If true, every signal the process receives and the results of its
+    /// stream, spill, and trace ID host calls are recorded so that its run
+    /// can be replayed with [ReplayRequest] once it exits.
+    #[serde(default)]
+    pub record: bool,
+}
+
+/// The name of the Wasm process supervisor service. Accepts [SupervisorSpec].
+pub const SUPERVISOR_SERVICE_NAME: &str = "hearth.wasm.Supervisor";
+
+/// Which children a supervisor restarts when one of them exits.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum RestartStrategy {
+    /// Only the child that exited is restarted.
+    #[default]
+    OneForOne,
+
+    /// Every other child is killed, then all of them are restarted in order.
+    ///
+    /// Use this when children depend on each other's state.
+    OneForAll,
+}
+
+/// Whether a supervised child is restarted after it exits.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// The child is always restarted.
+    #[default]
+    Permanent,
+
+    /// The child is only restarted if it crashed or was killed.
+    Transient,
+
+    /// The child is never restarted.
+    Temporary,
+}
+
+/// The delay between a child exiting and its restart.
+///
+/// Each consecutive restart of the same child doubles the delay, starting at
+/// `initial` and up to `max` seconds. A child that runs for longer than `max`
+/// seconds before exiting starts over from `initial`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Backoff {
+    pub initial: f32,
+    pub max: f32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: 0.1,
+            max: 30.0,
+        }
+    }
+}
+
+/// The most restarts that a supervisor makes before giving up.
+///
+/// If a supervisor restarts its children more than `max_restarts` times
+/// within `period` seconds, it kills every child and exits. A supervisor
+/// with a `max_restarts` of `u32::MAX` never gives up.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct RestartIntensity {
+    pub max_restarts: u32,
+    pub period: f32,
+}
+
+impl Default for RestartIntensity {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            period: 10.0,
+        }
+    }
+}
+
+/// A child process of a supervisor.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChildSpec {
+    /// A name for this child, used in logs.
+    pub name: String,
+
+    /// The Wasm module and entrypoint to run.
+    pub spawn: WasmSpawnInfo,
+
+    /// When to restart this child.
+    #[serde(default)]
+    pub restart: RestartPolicy,
+
+    /// The capabilities given to this child every time it starts, as indices
+    /// into the request's capabilities after the reply capability.
+    #[serde(default)]
+    pub caps: Vec<usize>,
 }
+
+/// A request to the supervisor service to start a new supervision tree.
+///
+/// The reply capability is sent a [SupervisorResponse] once every child has
+/// started. On success, its first capability is the supervisor, which stops
+/// every child when killed, followed by a handle to each child in order.
+///
+/// Messages sent to a child's handle are forwarded to its current instance
+/// and are held while it is restarting, so registries and other processes
+/// can keep using the handle across restarts. Handles go down when their
+/// supervisor exits.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SupervisorSpec {
+    #[serde(default)]
+    pub strategy: RestartStrategy,
+
+    #[serde(default)]
+    pub backoff: Backoff,
+
+    #[serde(default)]
+    pub intensity: RestartIntensity,
+
+    pub children: Vec<ChildSpec>,
+}
+
+/// An error in response to a [SupervisorSpec].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SupervisorError {
+    /// A child refers to a capability that was not sent with the request.
+    InvalidCap { child: String, index: usize },
+
+    /// A child failed to start for the first time.
+    SpawnFailed { child: String },
+}
+
+pub type SupervisorResponse = Result<(), SupervisorError>;
+
+/// A request to a running supervisor, sent to the supervisor capability from
+/// a [SupervisorResponse].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum SupervisorRequest {
+    /// Replies to the first capability with a [SupervisorStatus].
+    Status,
+}
+
+/// The status of each of a supervisor's children, in the order of
+/// [SupervisorSpec::children].
+pub type SupervisorStatus = Vec<ChildStatus>;
+
+/// The status of a supervised child.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ChildStatus {
+    /// The name of the child from its [ChildSpec].
+    pub name: String,
+
+    /// Whether an instance of the child is running.
+    pub running: bool,
+
+    /// How many times the child has been restarted.
+    pub restarts: u32,
+
+    /// The number of seconds until the child is restarted, if it is waiting
+    /// to be.
+    pub restart_in: Option<f32>,
+}
+
+/// The name of the Wasm crash report service. Accepts [CrashReportRequest].
+///
+/// Monitors of a crashed process only receive a down signal, so they can look
+/// up why it went down here by its process ID.
+pub const CRASH_REPORTS_SERVICE_NAME: &str = "hearth.wasm.CrashReports";
+
+/// A request to the Wasm crash report service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CrashReportRequest {
+    /// Gets the most recent crash report of a process.
+    ///
+    /// Process IDs are reused between runs of the runtime, so this may return
+    /// a report from an earlier run if the process hasn't crashed in this one.
+    /// Check [CrashReport::time] to tell them apart.
+    ///
+    /// Returns [CrashReportSuccess::Report].
+    Get { pid: ProcessId },
+
+    /// Lists every stored crash report, oldest first, without the received
+    /// messages and backtraces.
+    ///
+    /// Returns [CrashReportSuccess::Reports].
+    List,
+}
+
+/// A success response from a [CrashReportRequest].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CrashReportSuccess {
+    Report(CrashReport),
+    Reports(Vec<CrashReport>),
+}
+
+/// An error response from a [CrashReportRequest].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CrashReportError {
+    /// No crash report is stored for the requested process.
+    NotFound,
+
+    /// The crash reports could not be read.
+    Storage(String),
+}
+
+/// A type shorthand for [CrashReportSuccess] and [CrashReportError].
+pub type CrashReportResponse = Result<CrashReportSuccess, CrashReportError>;
+
+/// A report of a Wasm process that crashed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrashReport {
+    /// The ID of the process that crashed.
+    pub pid: ProcessId,
+
+    /// When the process crashed, in milliseconds since the Unix epoch.
+    pub time: u64,
+
+    /// The name of the process, if it has one.
+    pub name: Option<String>,
+
+    /// The description of the process, if it has one.
+    pub description: Option<String>,
+
+    /// The lump of the process's Wasm module.
+    pub lump: LumpId,
+
+    /// The error that the process crashed with, including its context.
+    pub error: String,
+
+    /// The reason the process trapped, if it crashed because of a trap.
+    pub trap: Option<String>,
+
+    /// The Wasm call stack at the time of the crash, innermost frame first.
+    ///
+    /// Empty if the crash did not happen in Wasm code.
+    pub backtrace: Vec<CrashFrame>,
+
+    /// The last messages the process received, oldest first.
+    pub messages: Vec<CrashMessage>,
+
+    /// The lump of the process's recording, if it was spawned with
+    /// [WasmSpawnInfo::record].
+    #[serde(default)]
+    pub recording: Option<LumpId>,
+}
+
+/// A single frame of a [CrashReport::backtrace].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrashFrame {
+    /// The index of the frame's function in its module.
+    pub func_index: u32,
+
+    /// The name of the frame's function, if the module has a name section.
+    pub func_name: Option<String>,
+
+    /// The offset of the frame's instruction within its module, if known.
+    pub module_offset: Option<usize>,
+}
+
+/// A message received by a crashed process.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrashMessage {
+    /// When the message was received, in milliseconds since the Unix epoch.
+    pub time: u64,
+
+    /// The size of the message's data in bytes.
+    pub len: usize,
+
+    /// The beginning of the message's data, decoded as UTF-8 with invalid
+    /// sequences replaced.
+    pub preview: String,
+
+    /// The number of capabilities sent with the message.
+    pub caps: usize,
+}
+
+/// The name of the Wasm replay service. Accepts [ReplayRequest].
+pub const REPLAYER_SERVICE_NAME: &str = "hearth.wasm.Replayer";
+
+/// A request to replay the recorded run of a Wasm process.
+///
+/// Processes spawned with [WasmSpawnInfo::record] store the signals they
+/// received and the results of their stream, spill, and trace ID host calls
+/// in a lump when they exit, and log its ID. Replaying that lump runs the same module
+/// again, giving it the recorded inputs in the same order, so that bugs that
+/// depend on the timing of messages can be reproduced and debugged.
+///
+/// Replays are sandboxed: every capability the replayed process receives is
+/// replaced by a stand-in that drops the messages sent to it, and stream
+/// writes return their recorded results without reaching the stream, so a
+/// replay can't affect the rest of the runtime.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReplayRequest {
+    /// The lump of the recording to replay.
+    pub recording: LumpId,
+}
+
+/// How a replayed process finished.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    /// The process received every recorded signal or host call result and
+    /// then needed another one.
+    Exhausted,
+
+    /// The process exited before receiving `remaining` recorded signals.
+    Exited { remaining: usize },
+
+    /// The process failed before receiving `remaining` recorded signals.
+    ///
+    /// This includes processes that diverged from their recording by
+    /// receiving signals or making host calls differently than in the
+    /// recorded run.
+    Failed { error: String, remaining: usize },
+}
+
+/// An error in response to a [ReplayRequest].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The recording lump is not in the lump store.
+    MissingRecording,
+
+    /// The lump is not a valid recording.
+    InvalidRecording,
+
+    /// The recorded module could not be started again.
+    SpawnFailed(String),
+}
+
+/// A type shorthand for [ReplayOutcome] and [ReplayError].
+pub type ReplayResponse = Result<ReplayOutcome, ReplayError>;
@@ -30,6 +30,13 @@ use serde::{Deserialize, Serialize};
 /// The name of the service that provides the main client window.
 pub const SERVICE_NAME: &str = "hearth.Window";
 
+/// The name of the service that provides read access to the main client
+/// window's clipboard. Accepts [ClipboardCommand].
+///
+/// This is separate from [SERVICE_NAME] so that only the processes that are
+/// given this service can read the user's clipboard.
+pub const CLIPBOARD_SERVICE_NAME: &str = "hearth.Clipboard";
+
 /// An event on the sender's window.
 ///
 /// Refer to https://docs.rs/winit/latest/winit/event/enum.WindowEvent.html for
@@ -38,7 +45,6 @@ pub const SERVICE_NAME: &str = "hearth.Window";
 ///
 /// If something is missing, wrong, or otherwise broken, please open an issue.
 // TODO file dropping/hovering?
-// TODO IME support?
 // TODO touchpad support?
 // TODO touch support?
 // TODO port DeviceId?
@@ -53,6 +59,13 @@ pub enum WindowEvent {
     /// The window has resized. The new size is in physical display units.
     Resized(UVec2),
     ReceivedCharacter(char),
+
+    /// An event from an input method editor (IME).
+    ///
+    /// Only sent while IME input is allowed with
+    /// [WindowCommand::SetImeAllowed].
+    Ime(Ime),
+
     Focused(bool),
     KeyboardInput {
         input: KeyboardInput,
@@ -82,6 +95,15 @@ pub enum WindowEvent {
 
     /// Raw, unfiltered physical motion from a mouse device in unspecified units.
     MouseMotion(DVec2),
+
+    /// A touch or pen contact on a touchscreen or drawing tablet.
+    Touch(Touch),
+
+    /// The text contents of the clipboard, sent only in response to a
+    /// [ClipboardCommand::Paste].
+    ///
+    /// This is empty if the clipboard is empty or does not contain text.
+    Paste(String),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -99,6 +121,23 @@ pub enum WindowCommand {
     /// Sets the title of the window.
     SetTitle(String),
 
+    /// Sets whether the window covers the whole of its current monitor
+    /// without borders.
+    SetFullscreen(bool),
+
+    /// Sets whether the window has a title bar and borders.
+    SetDecorations(bool),
+
+    /// Requests a new size for the window's drawable area in physical
+    /// display units.
+    ///
+    /// The window may not be resized exactly as requested. Subscribers receive
+    /// a [WindowEvent::Resized] with the actual size.
+    SetInnerSize(UVec2),
+
+    /// Sets the icon of the cursor while it is over the window.
+    SetCursorIcon(CursorIcon),
+
     /// Sets the grabbing mode of the cursor.
     SetCursorGrab(CursorGrabMode),
 
@@ -116,6 +155,32 @@ pub enum WindowCommand {
         /// The camera's view matrix.
         view: Mat4,
     },
+
+    /// Sets the text contents of the clipboard.
+    ///
+    /// Reading the clipboard requires the [CLIPBOARD_SERVICE_NAME] service.
+    SetClipboard(String),
+
+    /// Sets whether the window accepts input method editor (IME) input.
+    ///
+    /// While allowed, composed text is sent as [WindowEvent::Ime] instead of
+    /// [WindowEvent::ReceivedCharacter] on some platforms. Text fields should
+    /// allow IME input while they are focused.
+    SetImeAllowed(bool),
+
+    /// Sets the position of the IME candidate box in physical display units,
+    /// relative to the top left of the window.
+    SetImePosition(DVec2),
+}
+
+/// A request to the clipboard service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ClipboardCommand {
+    /// Reads the text contents of the clipboard.
+    ///
+    /// The first attached capability is sent the contents in a
+    /// [WindowEvent::Paste].
+    Paste,
 }
 
 /// Describes a keyboard input event.
@@ -131,6 +196,29 @@ pub struct KeyboardInput {
     pub virtual_keycode: Option<VirtualKeyCode>,
 }
 
+/// Describes an input method editor (IME) event.
+///
+/// Refer to https://docs.rs/winit/0.27.5/winit/event/enum.Ime.html.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Ime {
+    /// The IME has been enabled.
+    Enabled,
+
+    /// A new composing text should be displayed in place of any previous
+    /// composing text.
+    ///
+    /// The cursor range is the byte-wise range of the cursor within the
+    /// text. If it is `None`, the cursor should be hidden. An empty text
+    /// clears the composition.
+    Preedit(String, Option<(usize, usize)>),
+
+    /// Composition has finished and this text should be inserted.
+    Commit(String),
+
+    /// The IME has been disabled. Any composing text should be cleared.
+    Disabled,
+}
+
 /// Describes touch-screen input state.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
 pub enum TouchPhase {
@@ -140,6 +228,30 @@ pub enum TouchPhase {
     Cancelled,
 }
 
+/// A touch or pen contact event.
+///
+/// Each contact begins with [TouchPhase::Started], moves with
+/// [TouchPhase::Moved], and finishes with either [TouchPhase::Ended] or
+/// [TouchPhase::Cancelled].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Touch {
+    /// The phase of this contact.
+    pub phase: TouchPhase,
+
+    /// The position of the contact in physical display units.
+    pub position: DVec2,
+
+    /// The pressure of the contact, from 0 to 1, if the device reports it.
+    pub pressure: Option<f64>,
+
+    /// The angle in radians between a pen and the surface, if the device
+    /// reports it. A pen perpendicular to the surface has an angle of pi/2.
+    pub altitude_angle: Option<f64>,
+
+    /// An identifier for this contact that is unique among active contacts.
+    pub id: u64,
+}
+
 /// The state of an input element such as a key or mouse button.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum ElementState {
@@ -439,3 +551,64 @@ pub enum CursorGrabMode {
     /// - **iOS / Android:** Always returns an [`ExternalError::NotSupported`].
     Locked,
 }
+
+/// The appearance of the cursor.
+///
+/// Use this enum with [`WindowCommand::SetCursorIcon`] to change the cursor.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum CursorIcon {
+    /// The platform-dependent default cursor.
+    #[default]
+    Default,
+    /// A simple crosshair.
+    Crosshair,
+    /// A hand (often used to indicate links in web browsers).
+    Hand,
+    /// Self explanatory.
+    Arrow,
+    /// Indicates something is to be moved.
+    Move,
+    /// Indicates text that may be selected or edited.
+    Text,
+    /// Program busy indicator.
+    Wait,
+    /// Help indicator (often rendered as a "?")
+    Help,
+    /// Progress indicator. Shows that processing is being done. But in contrast
+    /// with "Wait" the user may still interact with the program. Often rendered
+    /// as a spinning beach ball, or an arrow with a watch or hourglass.
+    Progress,
+
+    /// Cursor showing that something cannot be done.
+    NotAllowed,
+    ContextMenu,
+    Cell,
+    VerticalText,
+    Alias,
+    Copy,
+    NoDrop,
+    /// Indicates something can be grabbed.
+    Grab,
+    /// Indicates something is grabbed.
+    Grabbing,
+    AllScroll,
+    ZoomIn,
+    ZoomOut,
+
+    /// Indicate that some edge is to be moved. For example, the 'SeResize' cursor
+    /// is used when the movement starts from the south-east corner of the box.
+    EResize,
+    NResize,
+    NeResize,
+    NwResize,
+    SResize,
+    SeResize,
+    SwResize,
+    WResize,
+    EwResize,
+    NsResize,
+    NeswResize,
+    NwseResize,
+    ColResize,
+    RowResize,
+}
@@ -0,0 +1,59 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+#![no_main]
+
+use hearth_schema::*;
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+
+fn decode<T: for<'a> Deserialize<'a>>(data: &[u8]) {
+    let _ = serde_json::from_slice::<T>(data);
+}
+
+fuzz_target!(|data: &[u8]| {
+    decode::<animation::FactoryRequest>(data);
+    decode::<animation::AnimatorUpdate>(data);
+    decode::<backup::BackupRequest>(data);
+    decode::<canvas::FactoryRequest>(data);
+    decode::<canvas::CanvasUpdate>(data);
+    decode::<cron::CronRequest>(data);
+    decode::<debug_draw::DebugDrawUpdate>(data);
+    decode::<file_picker::FilePickerRequest>(data);
+    decode::<fs::Request>(data);
+    decode::<fs::FactoryRequest>(data);
+    decode::<gamepad::GamepadCommand>(data);
+    decode::<notify::Notification>(data);
+    decode::<registry::RegistryRequest>(data);
+    decode::<renderer::RendererRequest>(data);
+    decode::<renderer::DirectionalLightUpdate>(data);
+    decode::<renderer::PointLightUpdate>(data);
+    decode::<renderer::SpotLightUpdate>(data);
+    decode::<renderer::ObjectUpdate>(data);
+    decode::<renderer::RenderTargetUpdate>(data);
+    decode::<renderer::ViewportUpdate>(data);
+    decode::<renderer::MaterialData>(data);
+    decode::<renderer::MeshData>(data);
+    decode::<renderer::TextureData>(data);
+    decode::<terminal::FactoryRequest>(data);
+    decode::<terminal::TerminalUpdate>(data);
+    decode::<time::TickCommand>(data);
+    decode::<wasm::WasmSpawnInfo>(data);
+    decode::<window::WindowCommand>(data);
+    decode::<window::ClipboardCommand>(data);
+});
@@ -22,10 +22,16 @@
 
 mod subscriber;
 
+pub mod trace;
+
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use codec::TraceContext;
 pub use hearth_schema::*;
 use subscriber::ProcessSubscriber;
 
@@ -37,6 +43,11 @@ fn abi_string(str: &str) -> (u32, u32) {
     (ptr, len)
 }
 
+/// Internal helper function to turn a duration into saturated nanoseconds.
+fn abi_duration(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
 /// Fetches the lump ID of the module used to spawn the current process.
 pub fn this_lump() -> LumpId {
     // load lump ID from the host
@@ -80,15 +91,37 @@ impl Capability {
         Capability(handle)
     }
 
-    /// Sends a type, serialized as JSON, to this capability.
+    /// Sends a type, serialized with the default [codec], to this capability.
     pub fn send(&self, data: &impl Serialize, caps: &[&Capability]) {
-        let json_msg = serde_json::to_string(data).unwrap();
-        let bytes_msg = json_msg.into_bytes();
-        self.send_raw(&bytes_msg, &caps);
+        let data = codec::encode(data);
+        self.send_raw(&data, caps);
     }
 
     /// Sends a raw message to this capability.
+    ///
+    /// If there's a current [trace] context, the message carries a new child
+    /// span of it. Payloads larger than [SPILLOVER_THRESHOLD] are spilled over
+    /// into a temporary lump that the receiver fetches transparently.
     pub fn send_raw(&self, data: &[u8], caps: &[&Capability]) {
+        let traced;
+        let data = match trace::next_span() {
+            Some(span) => {
+                traced = span.wrap(data);
+                &traced
+            }
+            None => data,
+        };
+
+        if data.len() > SPILLOVER_THRESHOLD {
+            let data = encode_spillover(&Lump::spill(data));
+            self.send_inline(&data, caps);
+        } else {
+            self.send_inline(data, caps);
+        }
+    }
+
+    /// Sends a raw message to this capability without spilling it over.
+    fn send_inline(&self, data: &[u8], caps: &[&Capability]) {
         let caps: Vec<u32> = caps.iter().map(|cap| (*cap).borrow().0).collect();
         unsafe {
             abi::table::send(
@@ -213,15 +246,48 @@ impl Mailbox {
         (index, signal)
     }
 
-    /// Receives a JSON message. Panics if the next signal isn't a message or
-    /// if deserialization fails.
+    /// Waits at most `timeout` for this mailbox to receive a [Signal].
+    ///
+    /// Returns `None` if the timeout elapsed first.
+    pub fn recv_signal_timeout(&self, timeout: Duration) -> Option<Signal> {
+        unsafe {
+            let handle = abi::mailbox::recv_timeout(self.0, abi_duration(timeout));
+
+            if handle == u32::MAX {
+                None
+            } else {
+                Some(Signal::from_handle(handle))
+            }
+        }
+    }
+
+    /// Waits at most `timeout` for one of many mailboxes to receive a signal.
+    ///
+    /// Returns `None` if the timeout elapsed first.
+    pub fn poll_timeout(mailboxes: &[&Self], timeout: Duration) -> Option<(usize, Signal)> {
+        let handles: Vec<_> = mailboxes.iter().map(|mb| mb.0).collect();
+        let ptr = handles.as_ptr() as u32;
+        let len = handles.len() as u32;
+        let result = unsafe { abi::mailbox::poll_timeout(ptr, len, abi_duration(timeout)) };
+
+        if result == u64::MAX {
+            return None;
+        }
+
+        let index = (result >> 32) as usize;
+        let signal = unsafe { Signal::from_handle(result as u32) };
+        Some((index, signal))
+    }
+
+    /// Receives a serialized message. Panics if the next signal isn't a
+    /// message or if deserialization fails.
     pub fn recv<T>(&self) -> (T, Vec<Capability>)
     where
         T: for<'a> Deserialize<'a>,
     {
         let (bytes_data, caps) = self.recv_raw();
-        let json_data = serde_json::from_slice(&bytes_data).unwrap();
-        (json_data, caps)
+        let data = codec::decode(&bytes_data).unwrap();
+        (data, caps)
     }
 
     /// Receives a raw bytes message. Panics if the next signal isn't a message or
@@ -236,6 +302,33 @@ impl Mailbox {
         (msg.data, msg.caps)
     }
 
+    /// Receives a raw bytes message, waiting at most `timeout`. Panics if the
+    /// next signal isn't a message.
+    ///
+    /// Returns `None` if the timeout elapsed first.
+    pub fn recv_raw_timeout(&self, timeout: Duration) -> Option<(Vec<u8>, Vec<Capability>)> {
+        let signal = self.recv_signal_timeout(timeout)?;
+
+        let Signal::Message(msg) = signal else {
+            panic!("expected message, received {:?}", signal);
+        };
+
+        Some((msg.data, msg.caps))
+    }
+
+    /// Receives a serialized message, waiting at most `timeout`. Panics if the
+    /// next signal isn't a message or if deserialization fails.
+    ///
+    /// Returns `None` if the timeout elapsed first.
+    pub fn recv_timeout<T>(&self, timeout: Duration) -> Option<(T, Vec<Capability>)>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        let (bytes_data, caps) = self.recv_raw_timeout(timeout)?;
+        let data = codec::decode(&bytes_data).unwrap();
+        Some((data, caps))
+    }
+
     /// Check if this mailbox has received any signals without waiting.
     pub fn try_recv_signal(&self) -> Option<Signal> {
         unsafe {
@@ -265,14 +358,14 @@ impl Mailbox {
 
     /// Check if this mailbox has received any signals without waiting
     /// and return None if there was either no signal, it was down,
-    /// or there was an error while processing json.
+    /// or there was an error while decoding.
     pub fn try_recv<T>(&self) -> Option<(T, Vec<Capability>)>
     where
         T: for<'a> Deserialize<'a>,
     {
         let msg = self.try_recv_raw()?;
 
-        let data = serde_json::from_slice(&msg.0).unwrap();
+        let data = codec::decode(&msg.0).unwrap();
 
         Some((data, msg.1))
     }
@@ -286,22 +379,43 @@ pub struct Message {
 
     /// The list of capabilities that were transferred in this message.
     pub caps: Vec<Capability>,
+
+    /// The trace context that this message was sent with, if any.
+    pub trace: Option<TraceContext>,
 }
 
 impl Message {
     /// Loads a message signal by its handle.
+    ///
+    /// Fetches the message's payload if it was spilled over into a lump, and
+    /// makes its trace context the current one.
     unsafe fn load_from_handle(handle: u32) -> Self {
         let data_len = abi::mailbox::get_message_data_len(handle) as usize;
         let mut data = Vec::with_capacity(data_len);
         data.set_len(data_len);
         abi::mailbox::get_message_data(handle, data.as_ptr() as u32);
 
+        if let Some(id) = decode_spillover(&data) {
+            data = Lump::load_spill(&id).get_data();
+        }
+
+        // malformed contexts are left for the decoder to reject
+        let trace = match TraceContext::split(&data) {
+            Ok((Some(trace), payload)) => {
+                data = payload.to_vec();
+                Some(trace)
+            }
+            _ => None,
+        };
+
+        trace::set_current(trace);
+
         let caps_num = abi::mailbox::get_message_caps_num(handle) as usize;
         let mut caps = Vec::with_capacity(caps_num);
         caps.set_len(caps_num);
         abi::mailbox::get_message_caps(handle, caps.as_ptr() as u32);
 
-        Self { data, caps }
+        Self { data, caps, trace }
     }
 }
 
@@ -326,9 +440,10 @@ impl Lump {
         }
     }
 
-    /// Loads a JSON-encoded lump from a serializable data type.
+    /// Loads a lump from a serializable data type, encoded with the default
+    /// [codec].
     pub fn load(data: &impl Serialize) -> Self {
-        let bytes = serde_json::to_vec(data).unwrap();
+        let bytes = codec::encode(data);
         Self::load_raw(&bytes)
     }
 
@@ -340,6 +455,25 @@ impl Lump {
         }
     }
 
+    /// Spills the payload of a message over into a temporary lump.
+    fn spill(data: &[u8]) -> LumpId {
+        unsafe {
+            let id = LumpId(Default::default());
+            let id_ptr = &id as *const LumpId as u32;
+            abi::lump::spill(data.as_ptr() as u32, data.len() as u32, id_ptr);
+            id
+        }
+    }
+
+    /// Loads the payload of a spilled-over message, freeing its temporary
+    /// lump once every receiver has loaded it.
+    fn load_spill(id: &LumpId) -> Self {
+        unsafe {
+            let handle = abi::lump::load_spill(id as *const LumpId as u32);
+            Self(handle)
+        }
+    }
+
     /// Gets the ID of this lump.
     pub fn get_id(&self) -> LumpId {
         unsafe {
@@ -361,6 +495,133 @@ impl Lump {
             data
         }
     }
+
+    /// Copies the data of this lump starting at `offset` into `buf`.
+    ///
+    /// Returns the number of bytes copied, which is less than the length of
+    /// `buf` only if the end of the lump is reached.
+    pub fn read_at(&self, offset: u32, buf: &mut [u8]) -> usize {
+        let ptr = buf.as_mut_ptr() as u32;
+        let len = buf.len().try_into().unwrap_or(u32::MAX);
+        unsafe { abi::lump::read(self.0, offset, ptr, len) as usize }
+    }
+
+    /// Creates a [LumpReader] to read this lump in chunks.
+    pub fn reader(&self) -> LumpReader<'_> {
+        LumpReader {
+            lump: self,
+            len: unsafe { abi::lump::get_len(self.0) } as u64,
+            position: 0,
+        }
+    }
+}
+
+/// Reads a lump's data in chunks with random access.
+///
+/// Unlike [Lump::get_data], this only copies the parts of the lump that are
+/// read into guest memory, which avoids copying large lumps all at once.
+#[derive(Debug)]
+pub struct LumpReader<'a> {
+    lump: &'a Lump,
+    len: u64,
+    position: u64,
+}
+
+impl<'a> LumpReader<'a> {
+    /// Gets the total length of the lump in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the lump is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'a> Read for LumpReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.len {
+            return Ok(0);
+        }
+
+        let num = self.lump.read_at(self.position as u32, buf);
+        self.position += num as u64;
+        Ok(num)
+    }
+}
+
+impl<'a> Seek for LumpReader<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        // lumps are addressed with 32-bit offsets
+        match position.filter(|position| *position <= u32::MAX as u64) {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// The write end of a ring buffer stream to a host plugin.
+///
+/// Streams carry high-rate bulk data, like canvas pixels, without the
+/// overhead of encoding messages. Host plugins hand out stream tokens in
+/// their replies, which are opened with [Stream::open].
+#[derive(Debug)]
+pub struct Stream(u32);
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe { abi::stream::free(self.0) }
+    }
+}
+
+impl Stream {
+    /// Opens a stream by the token given by a host plugin.
+    ///
+    /// Returns `None` if the token is unknown or its stream has been freed.
+    pub fn open(token: u64) -> Option<Self> {
+        match unsafe { abi::stream::open(token) } {
+            u32::MAX => None,
+            handle => Some(Self(handle)),
+        }
+    }
+
+    /// Writes a frame of data to this stream.
+    ///
+    /// Writes never block. If the consumer has fallen behind and the stream
+    /// is full, [StreamError::Full] is returned and the frame is dropped.
+    pub fn write(&self, frame: &[u8]) -> Result<(), StreamError> {
+        let ptr = frame.as_ptr() as u32;
+        let len = frame.len() as u32;
+        match unsafe { abi::stream::write(self.0, ptr, len) } {
+            0 => Ok(()),
+            1 => Err(StreamError::Full),
+            2 => Err(StreamError::Closed),
+            result => panic!("unexpected stream write result {}", result),
+        }
+    }
+}
+
+/// An error from writing to a [Stream].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamError {
+    /// The stream doesn't have enough free space for the frame.
+    Full,
+
+    /// The consumer has closed the stream.
+    Closed,
 }
 
 /// Log a message.
@@ -371,6 +632,24 @@ pub fn log(level: ProcessLogLevel, module: &str, content: &str) {
     unsafe { abi::log::log(level, module_ptr, module_len, content_ptr, content_len) }
 }
 
+/// Log a message with structured key-value fields.
+pub fn log_with_fields(
+    level: ProcessLogLevel,
+    module: &str,
+    content: &str,
+    fields: BTreeMap<String, String>,
+) {
+    let event = StructuredLogEvent {
+        level,
+        module: module.to_string(),
+        content: content.to_string(),
+        fields,
+    };
+
+    let event = serde_json::to_vec(&event).unwrap();
+    unsafe { abi::log::log_fields(event.as_ptr() as u32, event.len() as u32) }
+}
+
 #[allow(clashing_extern_declarations)]
 mod abi {
     pub mod log {
@@ -383,6 +662,8 @@ mod abi {
                 content_ptr: u32,
                 content_len: u32,
             );
+            pub fn log_fields(ptr: u32, len: u32);
+            pub fn new_trace_id(ptr: u32);
         }
     }
 
@@ -395,7 +676,10 @@ mod abi {
             pub fn get_id(handle: u32, id_ptr: u32);
             pub fn get_len(handle: u32) -> u32;
             pub fn get_data(handle: u32, ptr: u32);
+            pub fn read(handle: u32, offset: u32, ptr: u32, len: u32) -> u32;
             pub fn free(handle: u32);
+            pub fn spill(ptr: u32, len: u32, id_ptr: u32);
+            pub fn load_spill(id_ptr: u32) -> u32;
         }
     }
 
@@ -421,6 +705,8 @@ mod abi {
             pub fn recv(handle: u32) -> u32;
             pub fn try_recv(handle: u32) -> u32;
             pub fn poll(handles_ptr: u32, handles_len: u32) -> u64;
+            pub fn recv_timeout(handle: u32, timeout_ns: u64) -> u32;
+            pub fn poll_timeout(handles_ptr: u32, handles_len: u32, timeout_ns: u64) -> u64;
             pub fn destroy_signal(handle: u32);
             pub fn get_signal_kind(handle: u32) -> u32;
             pub fn get_down_capability(handle: u32) -> u32;
@@ -430,6 +716,15 @@ mod abi {
             pub fn get_message_caps(handle: u32, dst_ptr: u32);
         }
     }
+
+    pub mod stream {
+        #[link(wasm_import_module = "hearth::stream")]
+        extern "C" {
+            pub fn open(token: u64) -> u32;
+            pub fn write(handle: u32, ptr: u32, len: u32) -> u32;
+            pub fn free(handle: u32);
+        }
+    }
 }
 
 /// Exports this WebAssembly module's process metadata using the calling Cargo
@@ -17,6 +17,7 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
+    collections::BTreeMap,
     fmt::Write,
     sync::atomic::{AtomicUsize, Ordering},
 };
@@ -52,20 +53,24 @@ impl Subscriber for ProcessSubscriber {
     fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
 
     fn event(&self, event: &tracing::Event<'_>) {
-        let mut message = String::new();
-
-        let mut visitor = FmtEvent {
-            message: &mut message,
-            needs_comma: false,
-        };
-
+        let mut visitor = FieldVisitor::default();
         event.record(&mut visitor);
 
+        if let Some(trace) = crate::trace::current() {
+            let trace_id = format!("{:032x}", trace.trace_id);
+            let span_id = format!("{:016x}", trace.span_id);
+            visitor.fields.insert("trace_id".to_string(), trace_id);
+            visitor.fields.insert("span_id".to_string(), span_id);
+        }
+
         let module = event.metadata().target();
         let level = (*event.metadata().level()).into();
 
-        // TODO: Support structured logging
-        crate::log(level, module, &message);
+        if visitor.fields.is_empty() {
+            crate::log(level, module, &visitor.message);
+        } else {
+            crate::log_with_fields(level, module, &visitor.message, visitor.fields);
+        }
     }
 
     fn enter(&self, _span: &span::Id) {}
@@ -73,21 +78,28 @@ impl Subscriber for ProcessSubscriber {
     fn exit(&self, _span: &span::Id) {}
 }
 
-pub struct FmtEvent<'a> {
-    pub message: &'a mut String,
-    pub needs_comma: bool,
+/// Collects an event's message and the rest of its fields.
+#[derive(Default)]
+pub struct FieldVisitor {
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
 }
 
-impl<'a> Visit for FmtEvent<'a> {
-    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-        let comma = if self.needs_comma { ", " } else { "" };
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
         match field.name() {
-            "message" => {
-                write!(self.message, "{comma}{value:?}").unwrap();
-                self.needs_comma = true;
+            "message" => self.message.push_str(value),
+            name => {
+                self.fields.insert(name.to_string(), value.to_string());
             }
+        }
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => write!(self.message, "{value:?}").unwrap(),
             name => {
-                write!(self.message, "{comma}{name}={value:?}").unwrap();
+                self.fields.insert(name.to_string(), format!("{value:?}"));
             }
         }
     }
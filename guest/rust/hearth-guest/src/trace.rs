@@ -0,0 +1,64 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Distributed tracing across messages.
+//!
+//! Receiving a message makes the [TraceContext] it was sent with current,
+//! and every message sent while a context is current carries a new child span
+//! of it, so a trace follows a request from process to process. Log events
+//! are tagged with the current trace and span IDs in their `trace_id` and
+//! `span_id` fields.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::codec::TraceContext;
+
+/// The trace context of the message being handled.
+static CURRENT: Mutex<Option<TraceContext>> = Mutex::new(None);
+
+/// The seed of the next child span of this process.
+static NEXT_SEED: AtomicU64 = AtomicU64::new(0);
+
+/// Gets the current trace context, if there is one.
+pub fn current() -> Option<TraceContext> {
+    *CURRENT.lock().unwrap()
+}
+
+/// Replaces the current trace context.
+///
+/// Receiving a message does this automatically.
+pub fn set_current(trace: Option<TraceContext>) {
+    *CURRENT.lock().unwrap() = trace;
+}
+
+/// Starts a new trace and makes its root span current.
+pub fn begin() -> TraceContext {
+    let mut id = [0u8; 16];
+    unsafe { crate::abi::log::new_trace_id(id.as_mut_ptr() as u32) };
+    let trace = TraceContext::new(u128::from_be_bytes(id));
+    set_current(Some(trace));
+    trace
+}
+
+/// Derives a new child span of the current trace context for an outgoing
+/// message, if there is a current context.
+pub(crate) fn next_span() -> Option<TraceContext> {
+    let seed = NEXT_SEED.fetch_add(1, Ordering::Relaxed);
+    current().map(|trace| trace.child(seed))
+}
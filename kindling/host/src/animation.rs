@@ -0,0 +1,117 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::{animation::*, Lump};
+
+use crate::renderer::Object;
+
+lazy_static::lazy_static! {
+    static ref ANIMATION_FACTORY: RequestResponse<FactoryRequest, FactoryResponse> =
+        RequestResponse::expect_service(SERVICE_NAME);
+}
+
+/// An animator that plays clips from a glTF model on a skinned [Object].
+///
+/// Stops animating when dropped.
+pub struct Animator {
+    cap: Capability,
+    clips: Vec<ClipInfo>,
+}
+
+impl Drop for Animator {
+    fn drop(&mut self) {
+        self.cap.kill();
+    }
+}
+
+impl Animator {
+    /// Creates a new animator for the given skin in a binary glTF model lump
+    /// that streams its joint matrices to `object`.
+    ///
+    /// The object must have been created with a skeleton.
+    pub fn new(model: &Lump, skin: usize, object: &Object) -> Result<Self, FactoryError> {
+        let (result, caps) = ANIMATION_FACTORY.request(
+            FactoryRequest::CreateAnimator {
+                model: model.get_id(),
+                skin,
+            },
+            &[object.as_ref()],
+        );
+
+        let FactorySuccess::Animator { clips } = result?;
+
+        Ok(Self {
+            cap: caps.first().unwrap().clone(),
+            clips,
+        })
+    }
+
+    /// Lists the clips available to this animator, in index order.
+    pub fn get_clips(&self) -> &[ClipInfo] {
+        &self.clips
+    }
+
+    /// Looks up the index of a clip by its name.
+    pub fn find_clip(&self, name: &str) -> Option<usize> {
+        self.clips
+            .iter()
+            .position(|clip| clip.name.as_deref() == Some(name))
+    }
+
+    /// Internal helper function to update this animator.
+    fn update(&self, update: AnimatorUpdate) {
+        self.cap.send(&update, &[]);
+    }
+
+    /// Starts playing a clip from the beginning.
+    pub fn play(&self, clip: usize, weight: f32, speed: f32, looping: bool) {
+        self.update(AnimatorUpdate::Play {
+            clip,
+            weight,
+            speed,
+            looping,
+        });
+    }
+
+    /// Stops playing a clip.
+    pub fn stop(&self, clip: usize) {
+        self.update(AnimatorUpdate::Stop { clip });
+    }
+
+    /// Stops all clips and returns to the rest pose.
+    pub fn stop_all(&self) {
+        self.update(AnimatorUpdate::StopAll);
+    }
+
+    /// Sets the blending weight of a playing clip.
+    pub fn set_weight(&self, clip: usize, weight: f32) {
+        self.update(AnimatorUpdate::SetWeight { clip, weight });
+    }
+
+    /// Sets the playback speed of a playing clip.
+    pub fn set_speed(&self, clip: usize, speed: f32) {
+        self.update(AnimatorUpdate::SetSpeed { clip, speed });
+    }
+
+    /// Moves the playhead of a playing clip to a time in seconds.
+    pub fn seek(&self, clip: usize, time: f32) {
+        self.update(AnimatorUpdate::Seek { clip, time });
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::audit::*;
+use hearth_guest::ProcessId;
+
+lazy_static::lazy_static! {
+    static ref AUDIT: RequestResponse<CapAuditRequest, CapAuditResponse> =
+        RequestResponse::expect_service(SERVICE_NAME);
+}
+
+/// Gets at most `limit` of the most recent capability operations, oldest
+/// first.
+///
+/// If `pid` is set, only operations involving that process are returned.
+pub fn records(pid: Option<ProcessId>, limit: u32) -> Result<Vec<CapAuditRecord>, CapAuditError> {
+    let success = AUDIT
+        .request(CapAuditRequest::Records { pid, limit }, &[])
+        .0?;
+    match success {
+        CapAuditSuccess::Records(records) => Ok(records),
+        _ => panic!("expected CapAuditSuccess::Records, got {:?}", success),
+    }
+}
+
+/// Gets the totals of each process's recent capability operations.
+pub fn summary() -> Result<Vec<ProcessCapSummary>, CapAuditError> {
+    let success = AUDIT.request(CapAuditRequest::Summary, &[]).0?;
+    match success {
+        CapAuditSuccess::Summary(summary) => Ok(summary),
+        _ => panic!("expected CapAuditSuccess::Summary, got {:?}", success),
+    }
+}
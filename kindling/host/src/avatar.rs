@@ -0,0 +1,134 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers for joining a shared space's avatar service and following the
+//! avatars in it.
+
+use super::*;
+
+use hearth_guest::LumpId;
+use kindling_schema::avatar::*;
+
+use crate::registry::PEER_REGISTRY;
+
+/// A capability to an avatar service.
+pub struct AvatarHub {
+    cap: Capability,
+    remote: bool,
+}
+
+impl AvatarHub {
+    /// Finds the avatar service of a connected peer, like the server of a
+    /// client, or falls back to this peer's own avatar service.
+    ///
+    /// Returns `None` if neither is available.
+    pub fn find() -> Option<Self> {
+        if let Some(cap) = Self::find_remote() {
+            return Some(cap);
+        }
+
+        registry::REGISTRY
+            .get_service(SERVICE_NAME)
+            .map(|cap| Self { cap, remote: false })
+    }
+
+    /// Finds the avatar service of a connected peer.
+    pub fn find_remote() -> Option<Self> {
+        PEER_REGISTRY
+            .get_service(SERVICE_NAME)
+            .map(|cap| Self { cap, remote: true })
+    }
+
+    /// Returns true if this avatar service belongs to a connected peer.
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Adds a new avatar to the space. The avatar leaves when the returned
+    /// [Avatar] is dropped.
+    pub fn join(&self, name: &str, vrm: Option<LumpId>) -> Result<Avatar, JoinError> {
+        let presence = Mailbox::new();
+        let presence_cap = presence.make_capability(Permissions::MONITOR);
+
+        let request = AvatarRequest::Join {
+            name: name.to_string(),
+            vrm,
+        };
+
+        let hub = RequestResponse::<AvatarRequest, JoinResponse>::new(self.cap.clone());
+        let (response, mut caps) = hub.request(request, &[&presence_cap]);
+        let id = response?;
+
+        Ok(Avatar {
+            id,
+            cap: caps.remove(0),
+            _presence: presence,
+        })
+    }
+
+    /// Subscribes to the space's [AvatarEvents][AvatarEvent].
+    ///
+    /// Returns a mailbox that first receives an [AvatarEvent::Joined] for
+    /// every avatar already in the space, followed by every change to the
+    /// space's avatars. The mailbox receives a down signal if the avatar
+    /// service becomes unavailable.
+    pub fn subscribe(&self) -> Mailbox {
+        let mailbox = Mailbox::new();
+        let reply_cap = mailbox.make_capability(Permissions::SEND | Permissions::MONITOR);
+        mailbox.monitor(&self.cap);
+        self.cap.send(&AvatarRequest::Subscribe, &[&reply_cap]);
+        mailbox
+    }
+}
+
+/// An avatar owned by this process.
+pub struct Avatar {
+    id: AvatarId,
+    cap: Capability,
+
+    /// Monitored by the avatar service, which removes the avatar once this
+    /// mailbox is dropped.
+    _presence: Mailbox,
+}
+
+impl Avatar {
+    /// Gets this avatar's ID.
+    pub fn id(&self) -> AvatarId {
+        self.id
+    }
+
+    /// Sends an update to this avatar.
+    pub fn update(&self, update: &AvatarUpdate) {
+        self.cap.send(update, &[]);
+    }
+
+    /// Moves this avatar.
+    pub fn set_pose(&self, pose: AvatarPose) {
+        self.update(&AvatarUpdate::Pose(Box::new(pose)));
+    }
+
+    /// Changes this avatar's display name.
+    pub fn set_name(&self, name: &str) {
+        self.update(&AvatarUpdate::SetName(name.to_string()));
+    }
+
+    /// Changes this avatar's VRM model.
+    pub fn set_vrm(&self, vrm: Option<LumpId>) {
+        self.update(&AvatarUpdate::SetVrm(vrm));
+    }
+}
@@ -18,7 +18,7 @@
 
 use super::*;
 
-use hearth_guest::canvas::*;
+use hearth_guest::{canvas::*, Stream, StreamError};
 
 lazy_static::lazy_static! {
     /// A lazily-initialized handle to the canvas factory service.
@@ -64,4 +64,56 @@ impl Canvas {
     pub fn blit(&self, blit: Blit) {
         self.cap.send(&CanvasUpdate::Blit(blit), &[])
     }
+
+    /// Blit many buffers to the damaged parts of this canvas at once.
+    pub fn damage(&self, blits: Vec<Blit>) {
+        self.cap.send(&CanvasUpdate::Damage(blits), &[])
+    }
+
+    /// Copy a region of this canvas to another position within it.
+    pub fn copy_rect(&self, src: Rect, x: u32, y: u32) {
+        self.cap.send(&CanvasUpdate::CopyRect { src, x, y }, &[])
+    }
+
+    /// Replace the contents of this canvas with a GPU-rendered display list.
+    pub fn draw(&self, list: DisplayList) {
+        self.cap.send(&CanvasUpdate::Draw(list), &[])
+    }
+
+    /// Draw a run of text to a part of this canvas.
+    pub fn draw_text(&self, text: TextDraw) {
+        self.cap.send(&CanvasUpdate::DrawText(text), &[])
+    }
+    /// Open a stream of blits to this canvas that bypasses message encoding.
+    ///
+    /// `capacity` is the number of bytes of blits that may be queued before
+    /// further blits are dropped. The stream closes when this canvas is
+    /// destroyed.
+    ///
+    /// Panics if the canvas is unavailable or doesn't support streams.
+    pub fn open_stream(&self, capacity: u32) -> BlitStream {
+        let reply = Mailbox::new();
+        let reply_cap = reply.make_capability(Permissions::SEND);
+        reply.monitor(&self.cap);
+        self.cap
+            .send(&CanvasUpdate::OpenStream { capacity }, &[&reply_cap]);
+        let (CanvasStream { token }, _) = reply.recv();
+        let stream = Stream::open(token).expect("canvas stream was freed before opening");
+        BlitStream { stream }
+    }
+}
+
+/// A stream of blits to a [Canvas] for high-rate pixel updates, like video.
+pub struct BlitStream {
+    stream: Stream,
+}
+
+impl BlitStream {
+    /// Blit a rectangular buffer to a part of the canvas.
+    ///
+    /// Fails with [StreamError::Full] if the canvas has fallen behind, in
+    /// which case the blit is dropped and may be retried with newer pixels.
+    pub fn blit(&self, blit: &Blit) -> Result<(), StreamError> {
+        self.stream.write(&blit.to_frame())
+    }
 }
@@ -0,0 +1,128 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers for talking through a shared space's chat service.
+
+use super::*;
+
+use kindling_schema::chat::*;
+
+use crate::registry::PEER_REGISTRY;
+
+/// A capability to a chat service.
+pub struct ChatHub {
+    cap: Capability,
+    remote: bool,
+}
+
+impl ChatHub {
+    /// Finds the chat service of a connected peer, like the server of a
+    /// client, or falls back to this peer's own chat service.
+    ///
+    /// Returns `None` if neither is available.
+    pub fn find() -> Option<Self> {
+        if let Some(hub) = Self::find_remote() {
+            return Some(hub);
+        }
+
+        registry::REGISTRY
+            .get_service(SERVICE_NAME)
+            .map(|cap| Self { cap, remote: false })
+    }
+
+    /// Finds the chat service of a connected peer.
+    pub fn find_remote() -> Option<Self> {
+        PEER_REGISTRY
+            .get_service(SERVICE_NAME)
+            .map(|cap| Self { cap, remote: true })
+    }
+
+    /// Returns true if this chat service belongs to a connected peer.
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Joins the chat under a display name. The returned [ChatSender] stops
+    /// working once it is dropped.
+    pub fn join(&self, name: &str) -> Result<ChatSender, ChatError> {
+        let presence = Mailbox::new();
+        let presence_cap = presence.make_capability(Permissions::MONITOR);
+
+        let request = ChatRequest::Join {
+            name: name.to_string(),
+        };
+
+        let hub = RequestResponse::<ChatRequest, JoinResponse>::new(self.cap.clone());
+        let (response, mut caps) = hub.request(request, &[&presence_cap]);
+        response?;
+
+        Ok(ChatSender {
+            cap: caps.remove(0),
+            _presence: presence,
+        })
+    }
+
+    /// Subscribes to a channel's new [ChatMessages][ChatMessage].
+    ///
+    /// The returned mailbox receives a down signal if the chat service
+    /// becomes unavailable.
+    pub fn subscribe(&self, channel: &str) -> Mailbox {
+        let mailbox = Mailbox::new();
+        let reply_cap = mailbox.make_capability(Permissions::SEND | Permissions::MONITOR);
+        mailbox.monitor(&self.cap);
+
+        let request = ChatRequest::Subscribe {
+            channel: channel.to_string(),
+        };
+
+        self.cap.send(&request, &[&reply_cap]);
+        mailbox
+    }
+
+    /// Gets up to `limit` of a channel's most recent messages, oldest first.
+    pub fn history(&self, channel: &str, limit: usize) -> HistoryResponse {
+        let request = ChatRequest::History {
+            channel: channel.to_string(),
+            limit,
+        };
+
+        let hub = RequestResponse::<ChatRequest, HistoryResponse>::new(self.cap.clone());
+        hub.request(request, &[]).0
+    }
+}
+
+/// A joined participant of a chat service.
+pub struct ChatSender {
+    cap: Capability,
+
+    /// Monitored by the chat service, which forgets this sender once this
+    /// mailbox is dropped.
+    _presence: Mailbox,
+}
+
+impl ChatSender {
+    /// Posts a message to a channel.
+    pub fn send(&self, channel: &str, text: &str) {
+        let send = ChatSend {
+            channel: channel.to_string(),
+            text: text.to_string(),
+        };
+
+        self.cap.send(&send, &[]);
+    }
+}
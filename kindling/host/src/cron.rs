@@ -0,0 +1,92 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::cron::*;
+
+use crate::registry::REGISTRY;
+
+lazy_static::lazy_static! {
+    static ref CRON: RequestResponse<CronRequest, CronResponse> =
+        RequestResponse::expect_service(SERVICE_NAME);
+}
+
+/// Schedules raw message data to be sent to a capability on a cron schedule.
+///
+/// Returns the ID of the new task. The task is forgotten when the runtime
+/// restarts. See [CronTask::schedule] for the schedule format.
+pub fn schedule_message(
+    schedule: &str,
+    target: &Capability,
+    data: &[u8],
+) -> Result<u64, CronError> {
+    let task = CronTask {
+        schedule: schedule.to_string(),
+        target: CronTarget::Capability,
+        data: data.to_vec(),
+    };
+
+    schedule_task(task, &[target])
+}
+
+/// Schedules raw message data to be sent to a native service on a cron
+/// schedule.
+///
+/// The service must be in this process's registry, since the cron service
+/// only accepts service tasks with a capability to the service. Returns the ID
+/// of the new task. The task persists across runtime restarts.
+pub fn schedule_service(schedule: &str, service: &str, data: &[u8]) -> Result<u64, CronError> {
+    let Some(cap) = REGISTRY.get_service(service) else {
+        return Err(CronError::ServiceNotFound(service.to_string()));
+    };
+
+    let task = CronTask {
+        schedule: schedule.to_string(),
+        target: CronTarget::Service(service.to_string()),
+        data: data.to_vec(),
+    };
+
+    schedule_task(task, &[&cap])
+}
+
+fn schedule_task(task: CronTask, args: &[&Capability]) -> Result<u64, CronError> {
+    let success = CRON.request(CronRequest::Schedule(task), args).0?;
+    match success {
+        CronSuccess::Scheduled(id) => Ok(id),
+        _ => panic!("expected CronSuccess::Scheduled, got {:?}", success),
+    }
+}
+
+/// Cancels a scheduled task by its ID.
+pub fn cancel(id: u64) -> Result<(), CronError> {
+    let success = CRON.request(CronRequest::Cancel(id), &[]).0?;
+    match success {
+        CronSuccess::Cancelled => Ok(()),
+        _ => panic!("expected CronSuccess::Cancelled, got {:?}", success),
+    }
+}
+
+/// Lists all scheduled tasks.
+pub fn list_tasks() -> Vec<CronTaskInfo> {
+    let response = CRON.request(CronRequest::List, &[]).0;
+    match response {
+        Ok(CronSuccess::List(tasks)) => tasks,
+        _ => panic!("expected CronSuccess::List, got {:?}", response),
+    }
+}
@@ -71,4 +71,11 @@ impl DebugDraw {
     pub fn update(&self, mesh: DebugDrawMesh) {
         self.cap.send(&DebugDrawUpdate::Contents(mesh), &[]);
     }
+
+    /// Draw a mesh alongside this debug draw's contents for the given number
+    /// of seconds.
+    pub fn update_timed(&self, mesh: DebugDrawMesh, duration: f32) {
+        self.cap
+            .send(&DebugDrawUpdate::Timed { mesh, duration }, &[]);
+    }
 }
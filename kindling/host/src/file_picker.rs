@@ -0,0 +1,60 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::{file_picker::*, Lump};
+
+lazy_static::lazy_static! {
+    static ref FILE_PICKER: RequestResponse<FilePickerRequest, FilePickerResponse> =
+        RequestResponse::expect_service(SERVICE_NAME);
+}
+
+/// Asks the user to choose a file to open. Returns its name and contents.
+///
+/// Kindling's policy denies the file picker to services unless they're
+/// allowed it.
+pub fn open_file(filters: Vec<FileFilter>) -> Result<(String, Lump), FilePickerError> {
+    let (response, _) = FILE_PICKER.request(FilePickerRequest::Open { filters }, &[]);
+
+    match response? {
+        FilePickerSuccess::Opened { name, contents } => Ok((name, Lump::load_by_id(&contents))),
+        other => panic!("expected FilePickerSuccess::Opened, got {:?}", other),
+    }
+}
+
+/// Asks the user where to save a file and writes a lump's contents to it.
+/// Returns the file's name.
+pub fn save_file(
+    filters: Vec<FileFilter>,
+    file_name: Option<String>,
+    contents: &Lump,
+) -> Result<String, FilePickerError> {
+    let request = FilePickerRequest::Save {
+        filters,
+        file_name,
+        contents: contents.get_id(),
+    };
+
+    let (response, _) = FILE_PICKER.request(request, &[]);
+
+    match response? {
+        FilePickerSuccess::Saved { name } => Ok(name),
+        other => panic!("expected FilePickerSuccess::Saved, got {:?}", other),
+    }
+}
@@ -22,47 +22,176 @@ use core::panic;
 use hearth_guest::{fs::*, Lump, LumpId};
 
 lazy_static::lazy_static! {
-    static ref FILESYSTEM: RequestResponse<Request, Response> =
-        RequestResponse::expect_service("hearth.fs.Filesystem");
+    static ref FILESYSTEM: Filesystem = Filesystem(
+        RequestResponse::expect_service("hearth.fs.Filesystem")
+    );
+
+    static ref FACTORY: RequestResponse<FactoryRequest, FactoryResponse> =
+        RequestResponse::expect_service("hearth.fs.Factory");
+
+    static ref WRITABLE_FACTORY: RequestResponse<FactoryRequest, FactoryResponse> =
+        RequestResponse::expect_service("hearth.fs.WritableFactory");
 }
 
 /// Get a LumpId of a file from a path.
 pub fn get_file(path: &str) -> Result<LumpId, Error> {
-    let success = FILESYSTEM
-        .request(
-            Request {
-                target: path.to_string(),
-                kind: RequestKind::Get,
-            },
-            &[],
-        )
-        .0?;
-    match success {
-        Success::Get(lump) => Ok(lump),
-        _ => panic!("expected Success::Get, got {:?}", success),
-    }
+    FILESYSTEM.get_file(path)
 }
 
 /// Read the bytes of a file into a `Vec<u8>`.
 pub fn read_file(path: &str) -> Result<Vec<u8>, Error> {
-    let lump = get_file(path)?;
-    let lump = Lump::load_by_id(&lump);
-    Ok(lump.get_data())
+    FILESYSTEM.read_file(path)
 }
 
 /// List all files and directories inside of a path.
 pub fn list_files(path: &str) -> Result<Vec<FileInfo>, Error> {
-    let success = FILESYSTEM
-        .request(
-            Request {
-                target: path.to_string(),
-                kind: RequestKind::List,
-            },
-            &[],
-        )
-        .0?;
-    match success {
-        Success::List(files) => Ok(files),
-        _ => panic!("expected Success::List, got {:?}", success),
+    FILESYSTEM.list_files(path)
+}
+
+/// Spawn a new filesystem service whose root is a subdirectory of the main
+/// filesystem root.
+///
+/// The returned [Filesystem] can only access files within that directory,
+/// so its capability can be safely handed to less-trusted processes.
+pub fn scoped(root: &str) -> Result<Filesystem, Error> {
+    let request = FactoryRequest {
+        root: root.to_string(),
+        quota: None,
+    };
+
+    let (response, mut caps) = FACTORY.request(request, &[]);
+    response?;
+
+    Ok(Filesystem::new(caps.remove(0)))
+}
+
+/// Spawn a new filesystem service that can modify the files in a
+/// subdirectory of the main filesystem root, creating the subdirectory if it
+/// doesn't exist.
+///
+/// Writes fail once the files in the subdirectory would take up more than
+/// `quota` bytes, or the host's quota if that's smaller or `quota` is `None`.
+/// Only services allowed `hearth.fs.WritableFactory` by the policy can call
+/// this.
+pub fn scoped_writable(root: &str, quota: Option<u64>) -> Result<Filesystem, Error> {
+    let request = FactoryRequest {
+        root: root.to_string(),
+        quota,
+    };
+
+    let (response, mut caps) = WRITABLE_FACTORY.request(request, &[]);
+    response?;
+
+    Ok(Filesystem::new(caps.remove(0)))
+}
+
+/// A capability to a filesystem service.
+pub struct Filesystem(RequestResponse<Request, Response>);
+
+impl AsRef<Capability> for Filesystem {
+    fn as_ref(&self) -> &Capability {
+        self.0.as_ref()
+    }
+}
+
+impl Filesystem {
+    /// Wrap a raw filesystem service capability.
+    pub const fn new(cap: Capability) -> Self {
+        Self(RequestResponse::new(cap))
+    }
+
+    /// Get a LumpId of a file from a path.
+    pub fn get_file(&self, path: &str) -> Result<LumpId, Error> {
+        let success = self
+            .0
+            .request(
+                Request {
+                    target: path.to_string(),
+                    kind: RequestKind::Get,
+                },
+                &[],
+            )
+            .0?;
+        match success {
+            Success::Get(lump) => Ok(lump),
+            _ => panic!("expected Success::Get, got {:?}", success),
+        }
+    }
+
+    /// Read the bytes of a file into a `Vec<u8>`.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let lump = self.get_file(path)?;
+        let lump = Lump::load_by_id(&lump);
+        Ok(lump.get_data())
+    }
+
+    /// List all files and directories inside of a path.
+    pub fn list_files(&self, path: &str) -> Result<Vec<FileInfo>, Error> {
+        let success = self
+            .0
+            .request(
+                Request {
+                    target: path.to_string(),
+                    kind: RequestKind::List,
+                },
+                &[],
+            )
+            .0?;
+        match success {
+            Success::List(files) => Ok(files),
+            _ => panic!("expected Success::List, got {:?}", success),
+        }
+    }
+
+    /// Replace the contents of a file, creating it if it doesn't exist.
+    pub fn write_file(&self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let lump = Lump::load_raw(data);
+        self.modify(path, RequestKind::Write(lump.get_id()))
+    }
+
+    /// Append bytes to a file, creating it if it doesn't exist.
+    pub fn append_file(&self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let lump = Lump::load_raw(data);
+        self.modify(path, RequestKind::Append(lump.get_id()))
+    }
+
+    /// Delete a file or an empty directory.
+    pub fn delete(&self, path: &str) -> Result<(), Error> {
+        self.modify(path, RequestKind::Delete)
+    }
+
+    /// Create a directory and any missing parent directories.
+    pub fn mkdir(&self, path: &str) -> Result<(), Error> {
+        self.modify(path, RequestKind::Mkdir)
+    }
+
+    /// Watch a file or directory, and everything under it, for changes.
+    ///
+    /// Returns a mailbox that receives a [WatchEvent] for each change. The
+    /// watch stops when the mailbox is dropped.
+    pub fn watch(&self, path: &str) -> Result<Mailbox, Error> {
+        let mailbox = Mailbox::new();
+        let cap = mailbox.make_capability(Permissions::SEND | Permissions::MONITOR);
+        let request = Request {
+            target: path.to_string(),
+            kind: RequestKind::Watch,
+        };
+
+        self.0.request(request, &[&cap]).0?;
+        Ok(mailbox)
+    }
+
+    /// Performs a request that modifies the filesystem.
+    fn modify(&self, path: &str, kind: RequestKind) -> Result<(), Error> {
+        self.0
+            .request(
+                Request {
+                    target: path.to_string(),
+                    kind,
+                },
+                &[],
+            )
+            .0
+            .map(|_| ())
     }
 }
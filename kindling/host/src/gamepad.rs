@@ -0,0 +1,51 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::gamepad::*;
+
+lazy_static::lazy_static! {
+    /// The gamepads connected to the local client.
+    pub static ref GAMEPADS: Gamepads = {
+        Gamepads {
+            cap: registry::REGISTRY
+                .get_service(SERVICE_NAME)
+                .unwrap_or_else(|| panic!("requested service {SERVICE_NAME:?} is unavailable"))
+        }
+    };
+}
+
+/// The gamepad input service.
+pub struct Gamepads {
+    cap: Capability,
+}
+
+impl Gamepads {
+    /// Subscribe to the events of all gamepads.
+    ///
+    /// Returns a Mailbox that receives all [GamepadEvents][GamepadEvent],
+    /// starting with a [GamepadEvent::Connected] for each gamepad that is
+    /// already connected.
+    pub fn subscribe(&self) -> Mailbox {
+        let mailbox = Mailbox::new();
+        let reply_cap = mailbox.make_capability(Permissions::SEND | Permissions::MONITOR);
+        self.cap.send(&GamepadCommand::Subscribe, &[&reply_cap]);
+        mailbox
+    }
+}
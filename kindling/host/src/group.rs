@@ -0,0 +1,105 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::group::*;
+use tracing::warn;
+
+lazy_static::lazy_static! {
+    static ref GROUPS: RequestResponse<GroupRequest, GroupResponse> =
+        RequestResponse::expect_service(SERVICE_NAME);
+}
+
+/// A membership in a process group.
+pub struct Group {
+    /// The name of the group.
+    pub name: String,
+
+    /// This member's ID within the group.
+    pub member: MemberId,
+
+    /// A capability to broadcast messages to every member of the group.
+    pub broadcast: Capability,
+}
+
+impl Group {
+    /// Joins the named group, creating it if needed.
+    ///
+    /// Every message broadcast to the group is sent to `member`. If `signals`
+    /// is given, it receives a [GroupSignal] whenever the group's membership
+    /// changes.
+    pub fn join(name: &str, member: &Capability, signals: Option<&Capability>) -> Self {
+        let request = GroupRequest::Join {
+            group: name.to_string(),
+        };
+
+        let mut caps = vec![member];
+        caps.extend(signals);
+
+        let (response, mut caps) = GROUPS.request(request, &caps);
+        let member = match response {
+            Ok(GroupSuccess::Join(member)) => member,
+            other => panic!("failed to join group {:?}: {:?}", name, other),
+        };
+
+        Self {
+            name: name.to_string(),
+            member,
+            broadcast: caps.remove(0),
+        }
+    }
+
+    /// Leaves this group.
+    pub fn leave(self) {
+        let request = GroupRequest::Leave {
+            group: self.name,
+            member: self.member,
+        };
+
+        if let Err(err) = GROUPS.request(request, &[]).0 {
+            warn!("failed to leave group: {:?}", err);
+        }
+    }
+
+    /// Broadcasts a message to every member of this group.
+    pub fn send<T: Serialize>(&self, data: &T, caps: &[&Capability]) {
+        self.broadcast.send(data, caps);
+    }
+}
+
+/// Gets a capability to broadcast to the named group without joining it.
+pub fn get(name: &str) -> Capability {
+    let request = GroupRequest::Get {
+        group: name.to_string(),
+    };
+
+    let (response, mut caps) = GROUPS.request(request, &[]);
+    match response {
+        Ok(GroupSuccess::Get) => caps.remove(0),
+        other => panic!("failed to get group {:?}: {:?}", name, other),
+    }
+}
+
+/// Lists every process group.
+pub fn list() -> Vec<GroupInfo> {
+    match GROUPS.request(GroupRequest::List, &[]).0 {
+        Ok(GroupSuccess::List(list)) => list,
+        other => panic!("failed to list groups: {:?}", other),
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use glam::UVec2;
+use hearth_guest::{image::*, Lump};
+
+lazy_static::lazy_static! {
+    static ref IMAGE_DECODER: RequestResponse<ImageDecoderRequest, ImageDecoderResponse> =
+        RequestResponse::expect_service(SERVICE_NAME);
+}
+
+/// Decodes a PNG, JPEG, WebP, or GIF image.
+///
+/// If `format` is `None`, it is guessed from the image's contents. Returns a
+/// lump containing [TextureData](hearth_guest::renderer::TextureData) that
+/// can be used as a texture, along with the image's size.
+pub fn decode_image(
+    image: &Lump,
+    format: Option<ImageFormat>,
+    label: Option<String>,
+) -> Result<(Lump, UVec2), ImageDecoderError> {
+    let request = ImageDecoderRequest::Decode {
+        image: image.get_id(),
+        format,
+        label,
+    };
+
+    let (response, _) = IMAGE_DECODER.request(request, &[]);
+
+    match response? {
+        ImageDecoderSuccess::Decoded { texture, size, .. } => {
+            Ok((Lump::load_by_id(&texture), size))
+        }
+    }
+}
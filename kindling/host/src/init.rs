@@ -0,0 +1,36 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers for querying init about the guest services it manages.
+
+use super::*;
+
+use kindling_schema::init::*;
+
+lazy_static::lazy_static! {
+    static ref STATUS: RequestResponse<StatusRequest, StatusResponse> =
+        RequestResponse::expect_service(STATUS_SERVICE_NAME);
+}
+
+/// Lists the status of every guest service that init found, sorted by name.
+///
+/// Services need `rs.hearth.kindling.InitStatus` in their dependencies to use
+/// this.
+pub fn list_services() -> Vec<ServiceStatus> {
+    STATUS.request(StatusRequest::List, &[]).0
+}
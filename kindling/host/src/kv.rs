@@ -0,0 +1,116 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::kv::*;
+
+/// A wrapper for capabilities implementing the key-value store protocol.
+///
+/// Values are stored as raw bytes; use [Self::get_json] and [Self::put_json]
+/// to store serializable types.
+pub type KvStore = RequestResponse<KvRequest, KvResponse>;
+
+impl KvStore {
+    /// Retrieves the root key-value store from [registry::REGISTRY].
+    ///
+    /// Panics if the service is unavailable.
+    pub fn root() -> Self {
+        Self::expect_service(SERVICE_NAME)
+    }
+
+    /// Gets the value of a key, if it exists.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, KvError> {
+        let request = KvRequest::Get {
+            key: key.to_string(),
+        };
+
+        match self.request(request, &[]).0? {
+            KvSuccess::Value(value) => Ok(value),
+            other => panic!("expected KvSuccess::Value, got {:?}", other),
+        }
+    }
+
+    /// Sets the value of a key.
+    pub fn put(&self, key: &str, value: Vec<u8>) -> Result<(), KvError> {
+        let request = KvRequest::Put {
+            key: key.to_string(),
+            value,
+        };
+
+        self.request(request, &[]).0.map(|_| ())
+    }
+
+    /// Gets a key's value deserialized from JSON.
+    ///
+    /// Returns `Ok(None)` if the key doesn't exist or fails to deserialize.
+    pub fn get_json<T: for<'a> Deserialize<'a>>(&self, key: &str) -> Result<Option<T>, KvError> {
+        Ok(self
+            .get(key)?
+            .and_then(|value| serde_json::from_slice(&value).ok()))
+    }
+
+    /// Sets a key's value to the JSON serialization of `value`.
+    pub fn put_json<T: Serialize>(&self, key: &str, value: &T) -> Result<(), KvError> {
+        self.put(key, serde_json::to_vec(value).unwrap())
+    }
+
+    /// Removes a key. Returns true if the key existed.
+    pub fn delete(&self, key: &str) -> Result<bool, KvError> {
+        let request = KvRequest::Delete {
+            key: key.to_string(),
+        };
+
+        match self.request(request, &[]).0? {
+            KvSuccess::Deleted(existed) => Ok(existed),
+            other => panic!("expected KvSuccess::Deleted, got {:?}", other),
+        }
+    }
+
+    /// Lists every key starting with a prefix, in order.
+    pub fn list(&self, prefix: &str) -> Result<Vec<String>, KvError> {
+        let request = KvRequest::List {
+            prefix: prefix.to_string(),
+        };
+
+        match self.request(request, &[]).0? {
+            KvSuccess::Keys(keys) => Ok(keys),
+            other => panic!("expected KvSuccess::Keys, got {:?}", other),
+        }
+    }
+
+    /// Subscribes a capability to [KvEvent]s for keys starting with a prefix.
+    pub fn watch(&self, prefix: &str, subscriber: &Capability) -> Result<(), KvError> {
+        let request = KvRequest::Watch {
+            prefix: prefix.to_string(),
+        };
+
+        self.request(request, &[subscriber]).0.map(|_| ())
+    }
+
+    /// Gets a store for a namespace nested in this one.
+    pub fn namespace(&self, name: &str) -> Result<Self, KvError> {
+        let request = KvRequest::Namespace {
+            name: name.to_string(),
+        };
+
+        let (response, mut caps) = self.request(request, &[]);
+        response?;
+        Ok(Self::new(caps.remove(0)))
+    }
+}
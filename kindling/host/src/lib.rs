@@ -17,21 +17,37 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use std::marker::PhantomData;
+use std::time::Duration;
 
-use hearth_guest::{Capability, Mailbox, Permissions};
+use hearth_guest::{codec, Capability, Mailbox, Permissions, Signal};
 use serde::{Deserialize, Serialize};
 
 pub use glam;
 
+pub mod animation;
+pub mod audit;
+pub mod avatar;
 pub mod canvas;
+pub mod chat;
+pub mod cron;
 pub mod debug_draw;
+pub mod file_picker;
 pub mod fs;
+pub mod gamepad;
+pub mod group;
+pub mod image;
+pub mod init;
+pub mod kv;
+pub mod notify;
+pub mod profiler;
 pub mod registry;
 pub mod renderer;
+pub mod store;
 pub mod terminal;
 pub mod time;
 pub mod wasm;
 pub mod window;
+pub mod world;
 
 /// A convenience module to import all of the most important host-side structures.
 ///
@@ -42,23 +58,41 @@ pub mod window;
 /// ```
 pub mod prelude {
     pub use crate::{
+        animation::Animator,
         canvas::Canvas,
         debug_draw::DebugDraw,
         fs::{get_file, list_files, read_file},
+        gamepad::GAMEPADS,
         glam,
         registry::REGISTRY,
+        store::{bind_view, AnyBinding, Binding, Store},
         terminal::Terminal,
         time::{sleep, Stopwatch, Timer},
-        wasm::{spawn_fn, spawn_mod},
+        wasm::{spawn_fn, spawn_mod, supervise},
         window::MAIN_WINDOW,
-        RequestResponse,
+        RequestError, RequestResponse,
     };
     pub use tracing::{debug, error, info, trace, warn};
 }
 
+/// An error from [RequestResponse::try_request] or
+/// [RequestResponse::request_timeout].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RequestError {
+    /// The deadline passed before a response was received.
+    TimedOut,
+
+    /// The service became unavailable before responding.
+    Unavailable,
+
+    /// The service's response couldn't be decoded.
+    Malformed,
+}
+
 /// A helper struct for request-response capabilities.
 pub struct RequestResponse<Request, Response> {
     cap: Capability,
+    timeout: Option<f32>,
     _request: PhantomData<Request>,
     _response: PhantomData<Response>,
 }
@@ -78,15 +112,80 @@ where
     pub const fn new(cap: Capability) -> Self {
         Self {
             cap,
+            timeout: None,
             _request: PhantomData,
             _response: PhantomData,
         }
     }
 
+    /// Sets a deadline in seconds for every request made with [Self::request]
+    /// and [Self::try_request].
+    ///
+    /// By default, requests wait forever for a response.
+    pub const fn with_timeout(mut self, timeout: f32) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
     /// Perform a request on this capability.
     ///
-    /// Fails if the capability is unavailable.
+    /// Panics if the capability is unavailable, if the request times out, or
+    /// if the response is malformed.
     pub fn request(&self, request: Request, args: &[&Capability]) -> (Response, Vec<Capability>) {
+        self.try_request(request, args)
+            .unwrap_or_else(|err| panic!("request failed: {err:?}"))
+    }
+
+    /// Perform a request on this capability, returning an error instead of
+    /// panicking if it fails.
+    ///
+    /// Waits for at most the timeout set by [Self::with_timeout], if any.
+    pub fn try_request(
+        &self,
+        request: Request,
+        args: &[&Capability],
+    ) -> Result<(Response, Vec<Capability>), RequestError> {
+        match self.timeout {
+            Some(timeout) => self.request_timeout(request, args, timeout),
+            None => {
+                let reply = self.send_request(request, args);
+                Self::parse_response(Some(reply.recv_signal()))
+            }
+        }
+    }
+
+    /// Perform a request on this capability with a deadline in seconds.
+    ///
+    /// Unlike [Self::request], this returns an error instead of waiting
+    /// forever if the service never responds, such as when two processes
+    /// are synchronously waiting on requests to each other.
+    pub fn request_timeout(
+        &self,
+        request: Request,
+        args: &[&Capability],
+        timeout: f32,
+    ) -> Result<(Response, Vec<Capability>), RequestError> {
+        let reply = self.send_request(request, args);
+        let signal = reply.recv_signal_timeout(Duration::from_secs_f32(timeout.max(0.0)));
+        Self::parse_response(signal)
+    }
+
+    /// Interprets the signal received by a request's reply mailbox, or `None`
+    /// if it timed out.
+    fn parse_response(signal: Option<Signal>) -> Result<(Response, Vec<Capability>), RequestError> {
+        match signal {
+            Some(Signal::Message(msg)) => {
+                let data = codec::decode(&msg.data).map_err(|_| RequestError::Malformed)?;
+                Ok((data, msg.caps))
+            }
+            Some(Signal::Down { .. }) => Err(RequestError::Unavailable),
+            None => Err(RequestError::TimedOut),
+        }
+    }
+
+    /// Sends a request on this capability and returns the mailbox that the
+    /// response will be sent to.
+    fn send_request(&self, request: Request, args: &[&Capability]) -> Mailbox {
         let reply = Mailbox::new();
         let reply_cap = reply.make_capability(Permissions::SEND);
         reply.monitor(&self.cap);
@@ -97,7 +196,7 @@ where
 
         self.cap.send(&request, caps.as_slice());
 
-        reply.recv()
+        reply
     }
 
     /// Retrieves a [RequestResponse] service from [registry::REGISTRY] by name.
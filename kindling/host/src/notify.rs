@@ -0,0 +1,35 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+
+use hearth_guest::notify::*;
+
+lazy_static::lazy_static! {
+    static ref NOTIFY: Capability =
+        registry::REGISTRY.get_service(SERVICE_NAME)
+            .unwrap_or_else(|| panic!("requested service {SERVICE_NAME:?} is unavailable"));
+}
+
+/// Posts a notification for the user.
+///
+/// If `action` is provided, it is sent [NotificationAction::Activated] when
+/// the user activates the notification.
+pub fn notify(notification: Notification, action: Option<&Capability>) {
+    NOTIFY.send(&notification, action.as_slice());
+}
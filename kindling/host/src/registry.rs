@@ -46,6 +46,100 @@ impl Registry {
         }
     }
 
+    /// Waits until a service is registered, then gets it.
+    ///
+    /// Returns `Ok(None)` if this registry doesn't send notifications and
+    /// doesn't have the service. Returns [RequestError::Unavailable] if this
+    /// registry goes down while waiting and [RequestError::Malformed] if it
+    /// sends a reply that can't be decoded.
+    pub fn wait_for_service(&self, name: &str) -> Result<Option<Capability>, RequestError> {
+        let reply = Mailbox::new();
+        let reply_cap = reply.make_capability(Permissions::SEND | Permissions::MONITOR);
+        reply.monitor(self.as_ref());
+
+        let request = RegistryRequest::Watch {
+            name: name.to_string(),
+        };
+
+        self.as_ref().send(&request, &[&reply_cap]);
+
+        loop {
+            let Signal::Message(msg) = reply.recv_signal() else {
+                return Err(RequestError::Unavailable);
+            };
+
+            let Ok(RegistryResponse::Watch(present)) = codec::decode(&msg.data) else {
+                return Err(RequestError::Malformed);
+            };
+
+            match present {
+                Some(true) => {
+                    let service = msg.caps.into_iter().next();
+                    return service.map(Some).ok_or(RequestError::Malformed);
+                }
+                Some(false) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Registers a service under a name.
+    ///
+    /// Returns `Some(true)` if a service was replaced, `Some(false)` if not,
+    /// and `None` if this registry is read-only.
+    pub fn register(&self, name: &str, service: &Capability) -> Option<bool> {
+        let request = RegistryRequest::Register {
+            name: name.to_string(),
+        };
+
+        let (data, _) = self.request(request, &[service]);
+        let RegistryResponse::Register(replaced) = data else {
+            panic!("failed to register service {:?}", name);
+        };
+
+        replaced
+    }
+
+    /// Removes a service by its name.
+    ///
+    /// Returns `Some(true)` if the service was removed, `Some(false)` if it
+    /// wasn't registered, and `None` if this registry is read-only.
+    pub fn remove(&self, name: &str) -> Option<bool> {
+        let request = RegistryRequest::Remove {
+            name: name.to_string(),
+        };
+
+        let (data, _) = self.request(request, &[]);
+        let RegistryResponse::Remove(removed) = data else {
+            panic!("failed to remove service {:?}", name);
+        };
+
+        removed
+    }
+
+    /// Creates a child registry that can only access services whose names
+    /// start with this registry's prefix followed by `prefix`.
+    ///
+    /// The child is freed when `owner` goes down, so `owner` needs the
+    /// monitor permission.
+    ///
+    /// Returns the child and a read-only view of it, or `None` if this
+    /// registry is read-only or `owner` can't be monitored.
+    pub fn scope(&self, prefix: &str, owner: &Capability) -> Option<(Registry, Registry)> {
+        let request = RegistryRequest::Scope {
+            prefix: prefix.to_string(),
+        };
+
+        let (data, mut caps) = self.request(request, &[owner]);
+        let RegistryResponse::Scope(true) = data else {
+            return None;
+        };
+
+        let view = caps.pop()?;
+        let child = caps.pop()?;
+        Some((RequestResponse::new(child), RequestResponse::new(view)))
+    }
+
     /// Lists all services in this registry.
     pub fn list_services(&self) -> Vec<String> {
         let (data, _) = self.request(RegistryRequest::List, &[]);
@@ -58,3 +152,13 @@ impl Registry {
 
 /// A capability to the registry that this process has base access to.
 pub static REGISTRY: Registry = RequestResponse::new(unsafe { Capability::new_raw(0) });
+
+lazy_static::lazy_static! {
+    /// The registry of the services exported by this runtime's connected
+    /// peers.
+    ///
+    /// Services opt into being exported with `export = true` in their
+    /// `service.toml`.
+    pub static ref PEER_REGISTRY: Registry =
+        Registry::expect_service(registry::PEER_REGISTRY_SERVICE_NAME);
+}
@@ -18,12 +18,16 @@
 
 use super::*;
 
-use glam::{Mat4, Vec3};
+use glam::{Mat4, UVec2, Vec3};
 use hearth_guest::{renderer::*, Lump};
 
 lazy_static::lazy_static! {
     static ref RENDERER: RequestResponse<RendererRequest, RendererResponse> =
         RequestResponse::expect_service("hearth.Renderer");
+
+    static ref COMPOSITOR: Capability =
+        registry::REGISTRY.get_service("hearth.Compositor")
+            .expect("requested service \"hearth.Compositor\" is unavailable");
 }
 
 /// Set the global ambient lighting levels.
@@ -50,6 +54,71 @@ pub fn set_skybox(texture: &Lump) {
     let _ = result.unwrap();
 }
 
+/// Update the skybox with an equirectangular HDR or EXR environment map lump.
+///
+/// `face_size` is the edge length of the generated cube texture's faces and
+/// `exposure` scales the map's radiance. If `set_ambient` is true, the scene's
+/// ambient lighting is also set to the map's average radiance.
+pub fn set_environment_map(map: &Lump, face_size: u32, exposure: f32, set_ambient: bool) {
+    let (result, _) = RENDERER.request(
+        RendererRequest::SetEnvironmentMap {
+            map: map.get_id(),
+            face_size,
+            exposure,
+            set_ambient,
+        },
+        &[],
+    );
+
+    let _ = result.unwrap();
+}
+
+/// Update the renderer's quality settings.
+pub fn set_render_settings(settings: RenderSettings) {
+    let (result, _) = RENDERER.request(RendererRequest::SetRenderSettings(settings), &[]);
+    let _ = result.unwrap();
+}
+
+/// Update the main view's post-processing effects.
+///
+/// The color grading LUT, if any, must be a lump containing [TextureData].
+pub fn set_post_processing(settings: PostProcessSettings) {
+    let (result, _) = RENDERER.request(RendererRequest::SetPostProcessing(settings), &[]);
+    let _ = result.unwrap();
+}
+
+/// Subscribe to the renderer's [RendererEvents][RendererEvent].
+///
+/// Returns a Mailbox that receives a [RendererEvent::DeviceLost] when the GPU
+/// device is lost and a [RendererEvent::DeviceRestored] once everything that
+/// was lost can be created again.
+pub fn subscribe_events() -> Mailbox {
+    let mailbox = Mailbox::new();
+    let reply_cap = mailbox.make_capability(Permissions::SEND | Permissions::MONITOR);
+    let (result, _) = RENDERER.request(RendererRequest::Subscribe, &[&reply_cap]);
+    let _ = result.unwrap();
+    mailbox
+}
+
+/// Send a command to the window compositor without waiting for its
+/// transition to finish.
+pub fn composite(command: CompositorCommand) {
+    COMPOSITOR.send(&command, &[]);
+}
+
+/// Send a command to the window compositor and block until its transition is
+/// finished.
+///
+/// For example, a teleport can be hidden by waiting for a fade to black,
+/// moving the camera, and then fading back in.
+pub fn composite_and_wait(command: CompositorCommand) {
+    let reply = Mailbox::new();
+    let reply_cap = reply.make_capability(Permissions::SEND);
+    reply.monitor(&COMPOSITOR);
+    COMPOSITOR.send(&command, &[&reply_cap]);
+    let _ = reply.recv_raw();
+}
+
 /// A directional light.
 pub struct DirectionalLight(Capability);
 
@@ -100,6 +169,124 @@ impl DirectionalLight {
     }
 }
 
+/// A point light.
+pub struct PointLight(Capability);
+
+impl Drop for PointLight {
+    fn drop(&mut self) {
+        self.0.kill();
+    }
+}
+
+impl PointLight {
+    /// Create a new point light.
+    pub fn new(state: PointLightState) -> Self {
+        let (result, caps) = RENDERER.request(
+            RendererRequest::AddPointLight {
+                initial_state: state,
+            },
+            &[],
+        );
+
+        let _ = result.expect("failed to create point light");
+
+        Self(caps.first().unwrap().clone())
+    }
+
+    /// Internal helper function to update this light.
+    fn update(&self, update: PointLightUpdate) {
+        self.0.send(&update, &[]);
+    }
+
+    /// Set this point light's position.
+    pub fn set_position(&self, position: Vec3) {
+        self.update(PointLightUpdate::Position(position));
+    }
+
+    /// Set this point light's color.
+    pub fn set_color(&self, color: Vec3) {
+        self.update(PointLightUpdate::Color(color));
+    }
+
+    /// Set this point light's intensity.
+    pub fn set_intensity(&self, intensity: f32) {
+        self.update(PointLightUpdate::Intensity(intensity));
+    }
+
+    /// Set this point light's radius.
+    pub fn set_radius(&self, radius: f32) {
+        self.update(PointLightUpdate::Radius(radius));
+    }
+}
+
+/// A spot light.
+pub struct SpotLight(Capability);
+
+impl Drop for SpotLight {
+    fn drop(&mut self) {
+        self.0.kill();
+    }
+}
+
+impl SpotLight {
+    /// Create a new spot light.
+    ///
+    /// The renderer doesn't support spot lights yet, so this currently fails
+    /// with [RendererError::Unsupported].
+    pub fn new(state: SpotLightState) -> Result<Self, RendererError> {
+        let (result, caps) = RENDERER.request(
+            RendererRequest::AddSpotLight {
+                initial_state: state,
+            },
+            &[],
+        );
+
+        result?;
+
+        Ok(Self(caps.first().unwrap().clone()))
+    }
+
+    /// Internal helper function to update this light.
+    fn update(&self, update: SpotLightUpdate) {
+        self.0.send(&update, &[]);
+    }
+
+    /// Set this spot light's position.
+    pub fn set_position(&self, position: Vec3) {
+        self.update(SpotLightUpdate::Position(position));
+    }
+
+    /// Set this spot light's direction.
+    pub fn set_direction(&self, direction: Vec3) {
+        self.update(SpotLightUpdate::Direction(direction));
+    }
+
+    /// Set this spot light's color.
+    pub fn set_color(&self, color: Vec3) {
+        self.update(SpotLightUpdate::Color(color));
+    }
+
+    /// Set this spot light's intensity.
+    pub fn set_intensity(&self, intensity: f32) {
+        self.update(SpotLightUpdate::Intensity(intensity));
+    }
+
+    /// Set this spot light's radius.
+    pub fn set_radius(&self, radius: f32) {
+        self.update(SpotLightUpdate::Radius(radius));
+    }
+
+    /// Set this spot light's inner and outer cone angles, in radians.
+    pub fn set_cone_angles(&self, inner: f32, outer: f32) {
+        self.update(SpotLightUpdate::ConeAngles { inner, outer });
+    }
+
+    /// Set the exponent of this spot light's cone falloff.
+    pub fn set_falloff(&self, falloff: f32) {
+        self.update(SpotLightUpdate::Falloff(falloff));
+    }
+}
+
 /// Configuration for the creation of an [Object].
 #[derive(Clone, Debug)]
 pub struct ObjectConfig<'a> {
@@ -114,11 +301,21 @@ pub struct ObjectConfig<'a> {
 
     /// The initial transform of this object.
     pub transform: Mat4,
+
+    /// Whether to interpolate this object's transform updates. Set this for
+    /// objects that are moved on every simulation tick.
+    pub dynamic: bool,
 }
 
 /// An object.
 pub struct Object(Capability);
 
+impl AsRef<Capability> for Object {
+    fn as_ref(&self) -> &Capability {
+        &self.0
+    }
+}
+
 impl Drop for Object {
     fn drop(&mut self) {
         self.0.kill();
@@ -134,6 +331,7 @@ impl Object {
                 skeleton: config.skeleton,
                 material: config.material.get_id(),
                 transform: config.transform,
+                dynamic: config.dynamic,
             },
             &[],
         );
@@ -164,3 +362,129 @@ impl Object {
         );
     }
 }
+
+/// A texture that the scene is rendered into from its own camera.
+///
+/// Use [RenderTarget::texture] as the albedo of a [MaterialData] to show the
+/// render target on objects, like mirrors, screens, or portals.
+pub struct RenderTarget {
+    cap: Capability,
+    texture: Lump,
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        self.cap.kill();
+    }
+}
+
+impl RenderTarget {
+    /// Create a new render target with the given size in pixels.
+    ///
+    /// `vfov` - The vertical field of view, in degrees.
+    /// `near` - Near plane distance. All projection uses an infinite far plan.
+    /// `view` - The camera's view matrix.
+    pub fn new(size: UVec2, vfov: f32, near: f32, view: Mat4) -> Self {
+        let (result, caps) = RENDERER.request(
+            RendererRequest::CreateRenderTarget {
+                size,
+                vfov,
+                near,
+                view,
+            },
+            &[],
+        );
+
+        let texture = match result.expect("failed to create render target") {
+            RendererSuccess::RenderTarget { texture } => texture,
+            other => panic!("expected RendererSuccess::RenderTarget, got {:?}", other),
+        };
+
+        Self {
+            cap: caps.first().unwrap().clone(),
+            texture: Lump::load_by_id(&texture),
+        }
+    }
+
+    /// Gets the lump of this render target's texture.
+    pub fn texture(&self) -> &Lump {
+        &self.texture
+    }
+
+    /// Updates the camera that the scene is rendered from.
+    ///
+    /// `vfov` - The vertical field of view, in degrees.
+    /// `near` - Near plane distance. All projection uses an infinite far plan.
+    /// `view` - The camera's view matrix.
+    pub fn set_camera(&self, vfov: f32, near: f32, view: Mat4) {
+        self.cap
+            .send(&RenderTargetUpdate::SetCamera { vfov, near, view }, &[]);
+    }
+
+    /// Captures this render target's contents after it's next rendered.
+    ///
+    /// Returns a lump of [TextureData] with the captured pixels.
+    ///
+    /// Panics if the render target is unavailable.
+    pub fn capture(&self) -> Result<Lump, RendererError> {
+        let target = RequestResponse::<RenderTargetUpdate, RendererResponse>::new(self.cap.clone());
+        let (result, _) = target.request(RenderTargetUpdate::Capture, &[]);
+        match result? {
+            RendererSuccess::Capture { texture } => Ok(Lump::load_by_id(&texture)),
+            other => panic!("expected RendererSuccess::Capture, got {:?}", other),
+        }
+    }
+}
+
+/// A view of the scene drawn into a rectangle of the main window from its own
+/// camera, for splitscreen or picture-in-picture.
+pub struct Viewport {
+    cap: Capability,
+}
+
+impl Drop for Viewport {
+    fn drop(&mut self) {
+        self.cap.kill();
+    }
+}
+
+impl Viewport {
+    /// Create a new viewport in the given rectangle of the main window.
+    ///
+    /// `rect` - The rectangle, in fractions of the window's size.
+    /// `vfov` - The vertical field of view, in degrees.
+    /// `near` - Near plane distance. All projection uses an infinite far plan.
+    /// `view` - The camera's view matrix.
+    pub fn new(rect: ViewportRect, vfov: f32, near: f32, view: Mat4) -> Self {
+        let (result, caps) = RENDERER.request(
+            RendererRequest::CreateViewport {
+                rect,
+                vfov,
+                near,
+                view,
+            },
+            &[],
+        );
+
+        let _ = result.expect("failed to create viewport");
+
+        Self {
+            cap: caps.first().unwrap().clone(),
+        }
+    }
+
+    /// Moves this viewport to a new rectangle of the main window.
+    pub fn set_rect(&self, rect: ViewportRect) {
+        self.cap.send(&ViewportUpdate::SetRect(rect), &[]);
+    }
+
+    /// Updates the camera that the scene is rendered from.
+    ///
+    /// `vfov` - The vertical field of view, in degrees.
+    /// `near` - Near plane distance. All projection uses an infinite far plan.
+    /// `view` - The camera's view matrix.
+    pub fn set_camera(&self, vfov: f32, near: f32, view: Mat4) {
+        self.cap
+            .send(&ViewportUpdate::SetCamera { vfov, near, view }, &[]);
+    }
+}
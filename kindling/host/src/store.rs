@@ -0,0 +1,272 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Observable state containers that services publish and views bind to.
+//!
+//! A service owns a [Store] and hands out capabilities to it. Other
+//! processes subscribe to those capabilities with [Binding::subscribe] and
+//! receive every change to the store's value. [bind_view] rebuilds a view
+//! whenever any of its bindings change, so UI apps don't need to write their
+//! own subscribe-and-diff loops.
+//!
+//! ```rs
+//! let count = Binding::<u32>::subscribe(&counter_cap);
+//! bind_view(&[&count], || {
+//!     info!("count is now {}", *count.get());
+//! });
+//! ```
+
+use super::*;
+
+use std::cell::{Cell, Ref, RefCell};
+
+use hearth_guest::{codec, Signal};
+use kindling_schema::store::StoreRequest;
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+/// A subscriber to a [Store].
+struct Subscriber {
+    /// The subscriber's reply capability.
+    cap: Capability,
+
+    /// A mailbox monitoring the subscriber for when it goes down.
+    monitor: Mailbox,
+}
+
+/// A typed value that is published to every process subscribed to it.
+pub struct Store<T> {
+    value: T,
+    mailbox: Mailbox,
+    subscribers: Vec<Subscriber>,
+}
+
+impl<T: Serialize + PartialEq> Store<T> {
+    /// Creates a new store with an initial value and no subscribers.
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            mailbox: Mailbox::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Makes a capability that other processes can subscribe to.
+    pub fn make_capability(&self) -> Capability {
+        self.mailbox.make_capability(Permissions::SEND)
+    }
+
+    /// Gets the mailbox that this store receives subscriptions on.
+    ///
+    /// Services that wait on many mailboxes at once can include this one in
+    /// [Mailbox::poll] and pass its signals to [Store::handle_signal].
+    pub fn mailbox(&self) -> &Mailbox {
+        &self.mailbox
+    }
+
+    /// Gets the current value of this store.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Replaces the value of this store, publishing it if it has changed.
+    pub fn set(&mut self, value: T) {
+        if self.value != value {
+            self.value = value;
+            self.publish();
+        }
+    }
+
+    /// Modifies the value of this store in place, publishing it if it has
+    /// changed.
+    pub fn update(&mut self, f: impl FnOnce(&mut T))
+    where
+        T: Clone,
+    {
+        let mut value = self.value.clone();
+        f(&mut value);
+        self.set(value);
+    }
+
+    /// Handles any pending subscriptions and forgets any subscribers that
+    /// have gone down.
+    pub fn flush(&mut self) {
+        while let Some(signal) = self.mailbox.try_recv_signal() {
+            self.handle_signal(signal);
+        }
+
+        self.subscribers
+            .retain(|subscriber| subscriber.monitor.try_recv_signal().is_none());
+    }
+
+    /// Handles a signal received on this store's [Store::mailbox].
+    pub fn handle_signal(&mut self, signal: Signal) {
+        let Signal::Message(msg) = signal else {
+            return;
+        };
+
+        let request = match codec::decode(&msg.data) {
+            Ok(request) => request,
+            Err(err) => {
+                debug!("invalid store request: {err:?}");
+                return;
+            }
+        };
+
+        match request {
+            StoreRequest::Subscribe => {
+                let Some(cap) = msg.caps.into_iter().next() else {
+                    debug!("store subscription did not contain a capability");
+                    return;
+                };
+
+                let monitor = Mailbox::new();
+                monitor.monitor(&cap);
+                cap.send(&self.value, &[]);
+                self.subscribers.push(Subscriber { cap, monitor });
+            }
+        }
+    }
+
+    /// Sends the current value to every subscriber.
+    fn publish(&mut self) {
+        self.flush();
+
+        for subscriber in self.subscribers.iter() {
+            subscriber.cap.send(&self.value, &[]);
+        }
+    }
+}
+
+/// A subscription to a [Store] that tracks its latest value.
+pub struct Binding<T> {
+    value: RefCell<T>,
+    mailbox: Mailbox,
+    alive: Cell<bool>,
+}
+
+impl<T: DeserializeOwned> Binding<T> {
+    /// Subscribes to a store and waits for its current value.
+    ///
+    /// Panics if the store is unavailable.
+    pub fn subscribe(store: &Capability) -> Self {
+        let mailbox = Mailbox::new();
+        let reply = mailbox.make_capability(Permissions::SEND);
+        mailbox.monitor(store);
+        store.send(&StoreRequest::Subscribe, &[&reply]);
+        let (value, _) = mailbox.recv();
+
+        Self {
+            value: RefCell::new(value),
+            mailbox,
+            alive: Cell::new(true),
+        }
+    }
+
+    /// Gets the latest received value of the store.
+    ///
+    /// Does not receive new values; use [AnyBinding::refresh] for that.
+    pub fn get(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+}
+
+/// An object-safe interface to a [Binding] of any type.
+pub trait AnyBinding {
+    /// Gets the mailbox that this binding receives updates on.
+    fn mailbox(&self) -> &Mailbox;
+
+    /// Tests if this binding's store is still available.
+    fn is_alive(&self) -> bool;
+
+    /// Handles a signal received on this binding's mailbox. Returns true if
+    /// the value changed.
+    fn handle_signal(&self, signal: Signal) -> bool;
+
+    /// Receives all pending updates without waiting. Returns true if the
+    /// value changed.
+    fn refresh(&self) -> bool {
+        let mut changed = false;
+        while let Some(signal) = self.mailbox().try_recv_signal() {
+            changed |= self.handle_signal(signal);
+        }
+
+        changed
+    }
+}
+
+impl<T: DeserializeOwned> AnyBinding for Binding<T> {
+    fn mailbox(&self) -> &Mailbox {
+        &self.mailbox
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.get()
+    }
+
+    fn handle_signal(&self, signal: Signal) -> bool {
+        match signal {
+            Signal::Down { .. } => {
+                self.alive.set(false);
+                false
+            }
+            Signal::Message(msg) => match codec::decode(&msg.data) {
+                Ok(value) => {
+                    *self.value.borrow_mut() = value;
+                    true
+                }
+                Err(err) => {
+                    debug!("invalid store update: {err:?}");
+                    false
+                }
+            },
+        }
+    }
+}
+
+/// Builds a view from a set of bindings and rebuilds it whenever any of them
+/// change.
+///
+/// `view` is called once immediately, then again after each batch of updates
+/// is received. Returns once all of the bindings' stores are unavailable.
+pub fn bind_view(bindings: &[&dyn AnyBinding], mut view: impl FnMut()) {
+    view();
+
+    loop {
+        let alive: Vec<_> = bindings
+            .iter()
+            .filter(|binding| binding.is_alive())
+            .collect();
+
+        if alive.is_empty() {
+            return;
+        }
+
+        let mailboxes: Vec<_> = alive.iter().map(|binding| binding.mailbox()).collect();
+        let (index, signal) = Mailbox::poll(&mailboxes);
+        let mut changed = alive[index].handle_signal(signal);
+
+        for binding in bindings.iter() {
+            changed |= binding.refresh();
+        }
+
+        if changed {
+            view();
+        }
+    }
+}
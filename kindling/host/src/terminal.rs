@@ -22,7 +22,10 @@ use hearth_guest::terminal::*;
 
 lazy_static::lazy_static! {
     static ref TERMINAL_FACTORY: RequestResponse<FactoryRequest, FactoryResponse> =
-        RequestResponse::expect_service("hearth.terminal.TerminalFactory");
+        RequestResponse::expect_service(SERVICE_NAME);
+
+    static ref COMMAND_TERMINAL_FACTORY: RequestResponse<FactoryRequest, FactoryResponse> =
+        RequestResponse::expect_service(COMMAND_SERVICE_NAME);
 }
 
 /// A wrapper around the Terminal Capability.
@@ -41,13 +44,40 @@ impl Terminal {
     ///
     /// Panics if the factory responds with an error.
     pub fn new(state: TerminalState) -> Self {
-        let resp = TERMINAL_FACTORY.request(FactoryRequest::CreateTerminal(state), &[]);
+        let resp = TERMINAL_FACTORY.request(
+            FactoryRequest::CreateTerminal {
+                state,
+                command: None,
+            },
+            &[],
+        );
+
         let _ = resp.0.unwrap();
         Terminal {
             cap: resp.1.get(0).unwrap().clone(),
         }
     }
 
+    /// Creates a new terminal running a custom command instead of the
+    /// default shell.
+    ///
+    /// Panics if this process wasn't given the command terminal factory
+    /// service or if the factory responds with an error.
+    pub fn with_command(state: TerminalState, command: TerminalCommand) -> Self {
+        let resp = COMMAND_TERMINAL_FACTORY.request(
+            FactoryRequest::CreateTerminal {
+                state,
+                command: Some(command),
+            },
+            &[],
+        );
+
+        let _ = resp.0.unwrap();
+        Terminal {
+            cap: resp.1.first().unwrap().clone(),
+        }
+    }
+
     /// Send input to this terminal.
     pub fn input(&self, input: String) {
         self.cap.send(&TerminalUpdate::Input(input), &[])
@@ -57,4 +87,37 @@ impl Terminal {
     pub fn update(&self, state: TerminalState) {
         self.cap.send(&TerminalUpdate::State(state), &[])
     }
+
+    /// Replace this terminal's font and the size of its text.
+    ///
+    /// `fonts` are lumps of TrueType or OpenType font files, or `None` for
+    /// the built-in font.
+    pub fn set_font(&self, fonts: Option<TerminalFonts>, units_per_em: f32) {
+        self.cap.send(
+            &TerminalUpdate::SetFont {
+                fonts,
+                units_per_em,
+            },
+            &[],
+        )
+    }
+
+    /// Scroll this terminal's view through its scrollback.
+    pub fn scroll(&self, scroll: TerminalScroll) {
+        self.cap.send(&TerminalUpdate::Scroll(scroll), &[])
+    }
+
+    /// Read up to `count` lines of this terminal starting at `start`, where
+    /// line 0 is the top of the screen and negative lines are in the
+    /// scrollback.
+    ///
+    /// Panics if the terminal is unavailable.
+    pub fn read(&self, start: i32, count: u32) -> TerminalContents {
+        let reply = Mailbox::new();
+        let reply_cap = reply.make_capability(Permissions::SEND);
+        reply.monitor(&self.cap);
+        self.cap
+            .send(&TerminalUpdate::Read { start, count }, &[&reply_cap]);
+        reply.recv().0
+    }
 }
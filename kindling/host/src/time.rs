@@ -18,6 +18,8 @@
 
 use super::*;
 
+use hearth_guest::time::*;
+
 lazy_static::lazy_static! {
     static ref SLEEP_SERVICE: Capability =
         registry::REGISTRY.get_service("hearth.Sleep")
@@ -31,19 +33,74 @@ lazy_static::lazy_static! {
 
     static ref UNIX_TIME: RequestResponse<(), u128> =
         RequestResponse::expect_service("hearth.UnixTime");
+
+    static ref TICK_SERVICE: Capability =
+        registry::REGISTRY.get_service(TICK_SERVICE_NAME)
+            .unwrap_or_else(|| panic!("requested service {TICK_SERVICE_NAME:?} is unavailable"));
+
+    static ref CLOCK_SERVICE: Capability =
+        registry::REGISTRY.get_service(CLOCK_SERVICE_NAME)
+            .unwrap_or_else(|| panic!("requested service {CLOCK_SERVICE_NAME:?} is unavailable"));
 }
 
 /// Sleeps for the given time in seconds.
 pub fn sleep(duration: f32) {
+    let _ = deadline(duration).recv_raw();
+}
+
+/// Creates a mailbox that receives a signal after the given time in seconds.
+pub(crate) fn deadline(duration: f32) -> Mailbox {
     let reply = Mailbox::new();
     let reply_cap = reply.make_capability(Permissions::SEND);
     reply.monitor(&SLEEP_SERVICE);
 
     SLEEP_SERVICE.send(&duration, &[&reply_cap]);
 
+    reply
+}
+
+/// Subscribes to the fixed-rate simulation tick.
+///
+/// Returns a Mailbox that receives a [Tick] at a rate independent of the
+/// display's frame rate. Step simulations on ticks and create moving objects
+/// as dynamic so that the renderer smooths their motion between steps.
+pub fn subscribe_ticks() -> Mailbox {
+    let mailbox = Mailbox::new();
+    let reply_cap = mailbox.make_capability(Permissions::SEND | Permissions::MONITOR);
+    TICK_SERVICE.send(&TickCommand::Subscribe, &[&reply_cap]);
+    mailbox
+}
+
+/// Subscribes to the virtual clock.
+///
+/// Returns a Mailbox that receives a [ClockTick] each time the virtual clock
+/// advances. Unlike [subscribe_ticks], the virtual clock can be paused,
+/// scaled, and stepped through `hearth.ClockAdmin` to debug simulations.
+pub fn subscribe_clock() -> Mailbox {
+    let mailbox = Mailbox::new();
+    let reply_cap = mailbox.make_capability(Permissions::SEND | Permissions::MONITOR);
+    CLOCK_SERVICE.send(&ClockRequest::Subscribe, &[&reply_cap]);
+    mailbox
+}
+
+/// Sleeps until the virtual clock has advanced by the given time in seconds.
+pub fn clock_sleep(duration: f32) {
+    let reply = Mailbox::new();
+    let reply_cap = reply.make_capability(Permissions::SEND);
+    reply.monitor(&CLOCK_SERVICE);
+    CLOCK_SERVICE.send(&ClockRequest::Sleep { secs: duration }, &[&reply_cap]);
     let _ = reply.recv_raw();
 }
 
+/// Gets the current state of the virtual clock.
+pub fn get_clock_state() -> ClockState {
+    let reply = Mailbox::new();
+    let reply_cap = reply.make_capability(Permissions::SEND);
+    reply.monitor(&CLOCK_SERVICE);
+    CLOCK_SERVICE.send(&ClockRequest::GetState, &[&reply_cap]);
+    reply.recv::<ClockState>().0
+}
+
 /// Gets the time since the UNIX epoch in nanoseconds as a unsigned 128-bit
 /// integer.
 pub fn get_unix_time() -> u128 {
@@ -23,6 +23,8 @@ use hearth_guest::{wasm::*, LumpId};
 lazy_static::lazy_static! {
     static ref WASM_SPAWNER: RequestResponse<wasm::WasmSpawnInfo, ()> =
         RequestResponse::expect_service("hearth.wasm.WasmProcessSpawner");
+    static ref SUPERVISOR: RequestResponse<wasm::SupervisorSpec, wasm::SupervisorResponse> =
+        RequestResponse::expect_service(wasm::SUPERVISOR_SERVICE_NAME);
 }
 
 /// Spawns a child process for the given function.
@@ -38,6 +40,7 @@ pub fn spawn_fn(cb: fn(), registry: Option<Capability>) -> Capability {
         wasm::WasmSpawnInfo {
             lump: hearth_guest::this_lump(),
             entrypoint: Some(entrypoint),
+            record: false,
         },
         &[registry.as_ref().unwrap_or(registry::REGISTRY.as_ref())],
     );
@@ -55,8 +58,33 @@ pub fn spawn_mod(lump: LumpId, registry: Option<Capability>) -> Capability {
         wasm::WasmSpawnInfo {
             lump,
             entrypoint: None,
+            record: false,
         },
         &[registry.as_ref().unwrap_or(registry::REGISTRY.as_ref())],
     );
     caps.get(0).cloned().unwrap()
 }
+
+/// Starts a supervisor that restarts the given children when they exit.
+///
+/// Each child's `caps` indexes into `caps`. Returns a capability to the
+/// supervisor, which stops every child when killed, and a handle to each
+/// child that stays valid across restarts.
+pub fn supervise(
+    spec: wasm::SupervisorSpec,
+    caps: &[&Capability],
+) -> Result<(Capability, Vec<Capability>), wasm::SupervisorError> {
+    let (result, mut caps) = SUPERVISOR.request(spec, caps);
+    result?;
+    let children = caps.split_off(1);
+    Ok((caps.remove(0), children))
+}
+
+/// Gets the status of each child of a supervisor started by [supervise].
+///
+/// Returns [RequestError::Unavailable] if the supervisor has exited.
+pub fn supervisor_status(supervisor: &Capability) -> Result<wasm::SupervisorStatus, RequestError> {
+    RequestResponse::<_, wasm::SupervisorStatus>::new(supervisor.to_owned())
+        .try_request(wasm::SupervisorRequest::Status, &[])
+        .map(|(status, _)| status)
+}
@@ -16,7 +16,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use super::{glam::Mat4, *};
+use super::{
+    glam::{DVec2, Mat4, UVec2},
+    *,
+};
 
 use hearth_guest::window::*;
 
@@ -29,6 +32,22 @@ lazy_static::lazy_static! {
                 .unwrap_or_else(|| panic!("requested service {SERVICE_NAME:?} is unavailable"))
         }
     };
+
+    static ref CLIPBOARD: RequestResponse<ClipboardCommand, WindowEvent> =
+        RequestResponse::expect_service(CLIPBOARD_SERVICE_NAME);
+}
+
+/// Reads the text contents of the clipboard.
+///
+/// Requires the `hearth.Clipboard` service, and panics if it is unavailable.
+/// Kindling's policy denies it to services unless they're allowed it.
+pub fn paste() -> String {
+    let (response, _) = CLIPBOARD.request(ClipboardCommand::Paste, &[]);
+
+    match response {
+        WindowEvent::Paste(text) => text,
+        other => panic!("expected WindowEvent::Paste, got {:?}", other),
+    }
 }
 
 /// Instance of a desktop window.
@@ -52,6 +71,47 @@ impl Window {
         self.cap.send(&WindowCommand::SetTitle(title), &[]);
     }
 
+    /// Sets whether this window covers its whole monitor without borders.
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        self.cap
+            .send(&WindowCommand::SetFullscreen(fullscreen), &[]);
+    }
+
+    /// Sets whether this window has a title bar and borders.
+    pub fn set_decorations(&self, decorations: bool) {
+        self.cap
+            .send(&WindowCommand::SetDecorations(decorations), &[]);
+    }
+
+    /// Requests a new size for this window's drawable area in physical
+    /// display units.
+    pub fn set_inner_size(&self, size: UVec2) {
+        self.cap.send(&WindowCommand::SetInnerSize(size), &[]);
+    }
+
+    /// Sets the icon of the cursor while it is over this window.
+    pub fn set_cursor_icon(&self, icon: CursorIcon) {
+        self.cap.send(&WindowCommand::SetCursorIcon(icon), &[]);
+    }
+
+    /// Sets the text contents of the clipboard.
+    pub fn set_clipboard(&self, text: String) {
+        self.cap.send(&WindowCommand::SetClipboard(text), &[]);
+    }
+
+    /// Sets whether this window accepts input method editor (IME) input.
+    ///
+    /// Text fields should enable this while they are focused to receive
+    /// [WindowEvent::Ime] events.
+    pub fn set_ime_allowed(&self, allowed: bool) {
+        self.cap.send(&WindowCommand::SetImeAllowed(allowed), &[]);
+    }
+
+    /// Sets the position of the IME candidate box in physical display units.
+    pub fn set_ime_position(&self, position: DVec2) {
+        self.cap.send(&WindowCommand::SetImePosition(position), &[]);
+    }
+
     /// Set the cursor's grab mode.
     pub fn cursor_grab_mode(&self, mode: CursorGrabMode) {
         self.cap.send(&WindowCommand::SetCursorGrab(mode), &[]);
@@ -0,0 +1,84 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers for spawning the persistent contents of a space through the world
+//! service.
+
+use super::*;
+
+use hearth_guest::canvas::{CanvasSamplingMode, Pixels, Position};
+use kindling_schema::world::*;
+
+/// A wrapper for capabilities to the world service.
+pub type World = RequestResponse<WorldRequest, WorldResponse>;
+
+impl World {
+    /// Retrieves the world service from [registry::REGISTRY].
+    ///
+    /// Panics if the service is unavailable.
+    pub fn root() -> Self {
+        Self::expect_service(SERVICE_NAME)
+    }
+
+    /// Spawns an entity that isn't a panel.
+    pub fn spawn(&self, entity: WorldEntity) -> Result<EntityId, WorldError> {
+        match self.request(WorldRequest::Spawn(entity), &[]).0? {
+            WorldSuccess::Spawned(id) => Ok(id),
+            other => panic!("expected WorldSuccess::Spawned, got {:?}", other),
+        }
+    }
+
+    /// Spawns a panel showing the given pixels.
+    pub fn spawn_panel(
+        &self,
+        position: Position,
+        sampling: CanvasSamplingMode,
+        pixels: Pixels,
+    ) -> Result<EntityId, WorldError> {
+        let request = WorldRequest::SpawnPanel {
+            position,
+            sampling,
+            pixels,
+        };
+
+        match self.request(request, &[]).0? {
+            WorldSuccess::Spawned(id) => Ok(id),
+            other => panic!("expected WorldSuccess::Spawned, got {:?}", other),
+        }
+    }
+
+    /// Removes an entity from the world.
+    pub fn despawn(&self, id: EntityId) -> Result<(), WorldError> {
+        self.request(WorldRequest::Despawn { id }, &[])
+            .0
+            .map(|_| ())
+    }
+
+    /// Lists every entity in the world in the order they were spawned.
+    pub fn list(&self) -> Result<Vec<SpawnedEntity>, WorldError> {
+        match self.request(WorldRequest::List, &[]).0? {
+            WorldSuccess::Entities(entities) => Ok(entities),
+            other => panic!("expected WorldSuccess::Entities, got {:?}", other),
+        }
+    }
+
+    /// Removes every entity from the world.
+    pub fn clear(&self) -> Result<(), WorldError> {
+        self.request(WorldRequest::Clear, &[]).0.map(|_| ())
+    }
+}
@@ -18,8 +18,9 @@
 
 use std::collections::HashMap;
 
-use hearth_guest::Capability;
-use kindling_host::{prelude::*, registry::Registry};
+use hearth_guest::{codec, wasm::*, Capability, Mailbox, Permissions, Signal};
+use kindling_host::{kv::KvStore, prelude::*, registry::Registry, wasm::supervisor_status};
+use kindling_schema::init::*;
 use kindling_utils::registry::*;
 use petgraph::{algo::toposort, prelude::DiGraph};
 use serde::Deserialize;
@@ -29,9 +30,28 @@ hearth_guest::export_metadata!();
 /// The subpath within the filesystem root where services are scanned.
 const SEARCH_DIR: &str = "init";
 
+/// The native service that gives init the capability policy.
+const POLICY_SERVICE: &str = "hearth.init.Policy";
+
+/// The native init hooks that receive this peer's network root.
+const NETWORK_HOOKS: &[&str] = &["hearth.init.Client", "hearth.init.Server"];
+
+/// The native init hook that receives the root given to IPC clients.
+const DAEMON_HOOK: &str = "hearth.init.Daemon";
+
+/// The delay before a crashed service is restarted.
+///
+/// Init's services are supervised with no restart intensity, so their
+/// supervisors keep restarting them with this backoff instead of giving up.
+const RESTART_BACKOFF: Backoff = Backoff {
+    initial: 1.0,
+    max: 60.0,
+};
+
 /// A persistent service container object.
 pub struct Service {
-    /// A capability to this process, stays as `None` until this process is started.
+    /// A capability to this service's supervisor, stays as `None` until this
+    /// service is started.
     pub process: Option<Capability>,
 
     name: String,
@@ -47,12 +67,93 @@ impl Service {
         }
     }
 
-    pub fn spawn(&mut self, registry: Option<Registry>) -> Capability {
-        let lump = get_file(&format!("{}/{}/service.wasm", SEARCH_DIR, self.name))
-            .expect("WASM module not found");
-        let cap = spawn_mod(lump, registry.map(|x| x.as_ref().to_owned()));
-        self.process = Some(cap.to_owned());
-        cap
+    /// Starts this service under a supervisor that restarts it whenever it
+    /// crashes.
+    ///
+    /// Returns the supervisor's handle to the service, which stays valid
+    /// across restarts and holds messages while the service is restarting,
+    /// or `None` if the service failed to start.
+    pub fn spawn(&mut self, registry: Registry) -> Option<Capability> {
+        let lump = match get_file(&format!("{}/{}/service.wasm", SEARCH_DIR, self.name)) {
+            Ok(lump) => lump,
+            Err(err) => {
+                error!(
+                    "WASM module of service \'{}\' not found: {:?}",
+                    self.name, err
+                );
+                return None;
+            }
+        };
+
+        let spec = SupervisorSpec {
+            strategy: RestartStrategy::OneForOne,
+            backoff: RESTART_BACKOFF,
+            intensity: RestartIntensity {
+                max_restarts: u32::MAX,
+                period: RESTART_BACKOFF.max,
+            },
+            children: vec![ChildSpec {
+                name: self.name.clone(),
+                spawn: WasmSpawnInfo {
+                    lump,
+                    entrypoint: None,
+                    record: false,
+                },
+                restart: RestartPolicy::Permanent,
+                caps: vec![0],
+            }],
+        };
+
+        let (supervisor, handle) = match supervise(spec, &[registry.as_ref()]) {
+            Ok((supervisor, mut children)) => (supervisor, children.pop()),
+            Err(err) => {
+                error!("Failed to start service \'{}\': {:?}", self.name, err);
+                return None;
+            }
+        };
+
+        let Some(handle) = handle else {
+            error!("Supervisor of service \'{}\' returned no handle", self.name);
+            supervisor.kill();
+            return None;
+        };
+
+        self.process = Some(supervisor);
+        Some(handle)
+    }
+
+    /// Gets the current status of this service from its supervisor.
+    fn status(&self) -> ServiceStatus {
+        let child =
+            self.process
+                .as_ref()
+                .and_then(|supervisor| match supervisor_status(supervisor) {
+                    Ok(mut children) => children.pop(),
+                    Err(err) => {
+                        debug!(
+                            "Supervisor of service \'{}\' is unavailable: {:?}",
+                            self.name, err
+                        );
+                        None
+                    }
+                });
+
+        let (state, restarts) = match child {
+            Some(child) if child.running => (ServiceState::Up, child.restarts),
+            Some(ChildStatus {
+                restart_in: Some(delay),
+                restarts,
+                ..
+            }) => (ServiceState::Restarting { delay }, restarts),
+            Some(child) => (ServiceState::Stopped, child.restarts),
+            None => (ServiceState::Stopped, 0),
+        };
+
+        ServiceStatus {
+            name: self.name.clone(),
+            state,
+            restarts,
+        }
     }
 }
 
@@ -63,6 +164,9 @@ pub extern "C" fn run() {
     // first of all, enumerate available native services
     let native_services = REGISTRY.list_services();
 
+    // load the policy restricting which services each service may receive
+    let policy = get_policy();
+
     // add all guest services into a dependency graph structure
     let mut graph = DiGraph::<Service, ()>::new();
 
@@ -88,6 +192,10 @@ pub extern "C" fn run() {
         names_to_idxs.insert(name, idx);
     }
 
+    // the names of services that were removed because of missing or denied
+    // deps
+    let mut missing = Vec::new();
+
     // add dependency edges to graph
     for idx in graph.node_indices() {
         let node = graph.node_weight(idx).unwrap();
@@ -99,6 +207,13 @@ pub extern "C" fn run() {
 
         // iterate all needed deps
         for dep in node.config.dependencies.need.clone() {
+            // withhold services that this service's policy denies
+            if !policy.allows(&name, &dep) {
+                remove = true;
+                error!("Dependency \'{dep}\' is denied by policy");
+                continue;
+            }
+
             match names_to_idxs.get(&dep.clone()) {
                 // is this service an existing guest process?
                 Some(dep_idx) => {
@@ -109,7 +224,7 @@ pub extern "C" fn run() {
                     // check if the service is native
                     // if the service is native, we skip adding this edge, and
                     // its capability will be retrieved during service startup
-                    if !native_services.contains(&dep) {
+                    if !native_services.contains(&dep) && dep != STATUS_SERVICE_NAME {
                         // if it isn't, this dep is missing
                         remove = true;
                         error!("Dependency \'{dep}\' not found");
@@ -123,6 +238,7 @@ pub extern "C" fn run() {
             info!("Service \'{name}\' will not be spawned");
             graph.remove_node(idx);
             names_to_idxs.remove(&name);
+            missing.push(name);
         }
     }
 
@@ -139,6 +255,23 @@ pub extern "C" fn run() {
         names_to_caps.insert(service, cap);
     }
 
+    // the mailbox of init's own status service
+    let status = Mailbox::new();
+    names_to_caps.insert(
+        STATUS_SERVICE_NAME.to_string(),
+        status.make_capability(Permissions::SEND),
+    );
+
+    // the services that are exported to peers, starting with the native
+    // services that the policy exports
+    let mut exports = Vec::new();
+    for name in policy.export.iter() {
+        match names_to_caps.get(name) {
+            Some(cap) => exports.push((name.clone(), cap.to_owned())),
+            None => warn!("Policy exports missing native service \'{name}\'"),
+        }
+    }
+
     // start up all guest services in dependency order
     for idx in sorted_services {
         // get service data
@@ -148,19 +281,111 @@ pub extern "C" fn run() {
         let mut deps = Vec::new();
         for dep in service.config.dependencies.need.clone() {
             // look up service cap (either guest or host)
-            let cap = names_to_caps.get(&dep).unwrap().to_owned();
+            let Some(cap) = names_to_caps.get(&dep) else {
+                break;
+            };
+
+            // give each service its own key-value namespace
+            let cap = if dep == hearth_guest::kv::SERVICE_NAME {
+                match KvStore::new(cap.to_owned()).namespace(&service.name) {
+                    Ok(store) => store.as_ref().to_owned(),
+                    Err(err) => {
+                        error!("Failed to create key-value namespace: {:?}", err);
+                        break;
+                    }
+                }
+            } else {
+                cap.to_owned()
+            };
+
             deps.push((dep, cap));
         }
 
+        // skip services whose dependencies failed to start
+        if deps.len() < service.config.dependencies.need.len() {
+            error!("Service \'{}\' will not be spawned", service.name);
+            continue;
+        }
+
         // create a new registry with this service's deps
-        let registry = Some(RegistryServer::spawn(deps));
+        let registry = RegistryServer::spawn(deps);
 
         // spawn the service
-        let cap = service.spawn(registry);
+        let Some(cap) = service.spawn(registry) else {
+            continue;
+        };
+
+        // export this service to peers if it opts in
+        if service.config.export {
+            exports.push((service.name.clone(), cap.to_owned()));
+        }
 
         // provide this service to its dependents
         names_to_caps.insert(service.name.clone(), cap);
     }
+
+    // give the network hooks a registry of the exported services, which
+    // connected peers will see in their peer registries
+    let exports = RegistryServer::spawn(exports);
+    for name in NETWORK_HOOKS {
+        if let Some(hook) = names_to_caps.get(*name) {
+            info!("Exporting services to {name}");
+            hook.send(&(), &[exports.as_ref()]);
+        }
+    }
+
+    // give local IPC clients, like hearth-ctl, a registry of every service
+    if let Some(hook) = names_to_caps.get(DAEMON_HOOK) {
+        info!("Exporting all services to {DAEMON_HOOK}");
+        let services = names_to_caps
+            .iter()
+            .filter(|(name, _)| !name.starts_with("hearth.init."))
+            .map(|(name, cap)| (name.clone(), cap.to_owned()))
+            .collect();
+
+        // a mutable registry lets IPC clients register the processes they
+        // spawn. the init services are pinned in it, so clients can add
+        // services alongside them but can't replace or remove them.
+        let registry = MutableRegistryServer::spawn(services);
+        hook.send(&(), &[registry.admin.as_ref()]);
+    }
+
+    let services: Vec<Service> = graph
+        .into_nodes_edges()
+        .0
+        .into_iter()
+        .map(|node| node.weight)
+        .collect();
+
+    // answer status requests
+    loop {
+        let Signal::Message(msg) = status.recv_signal() else {
+            continue;
+        };
+
+        let Some(reply) = msg.caps.first() else {
+            debug!("Status request did not contain a capability");
+            continue;
+        };
+
+        match codec::decode(&msg.data) {
+            Ok(StatusRequest::List) => {
+                let mut list: StatusResponse = services
+                    .iter()
+                    .map(Service::status)
+                    .chain(missing.iter().map(|name| ServiceStatus {
+                        name: name.clone(),
+                        state: ServiceState::Stopped,
+                        restarts: 0,
+                    }))
+                    .collect();
+
+                list.sort_by(|a, b| a.name.cmp(&b.name));
+                reply.send(&list, &[]);
+            }
+            Err(err) => debug!("Invalid status request: {:?}", err),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -188,6 +413,10 @@ pub struct ServiceConfig {
 
     pub description: Option<String>,
 
+    /// Whether to export this service to connected peers.
+    #[serde(default)]
+    pub export: bool,
+
     #[serde(default)]
     pub license: Vec<License>,
 
@@ -195,6 +424,110 @@ pub struct ServiceConfig {
     pub targets: Vec<String>,
 }
 
+/// Restrictions on which services each guest service may receive.
+///
+/// A service only receives the services in its `dependencies.need`, and a
+/// service that needs a denied service is not started.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Policy {
+    /// The rules applied to every service.
+    #[serde(default)]
+    pub default: PolicyRules,
+
+    /// The rules applied to each service by name. These take precedence over
+    /// the default rules.
+    #[serde(default)]
+    pub services: HashMap<String, PolicyRules>,
+
+    /// The native services to export to connected peers, alongside the guest
+    /// services that opt in with `export`. Peers are less trusted than local
+    /// services, so none are exported by default.
+    #[serde(default)]
+    pub export: Vec<String>,
+}
+
+impl Policy {
+    /// A policy that denies every service, used when the policy is invalid.
+    pub fn deny_all() -> Self {
+        Self {
+            default: PolicyRules {
+                deny: vec!["*".to_string()],
+                allow: Vec::new(),
+            },
+            services: HashMap::new(),
+            export: Vec::new(),
+        }
+    }
+
+    /// Returns true if `service` may receive `dep`.
+    pub fn allows(&self, service: &str, dep: &str) -> bool {
+        self.services
+            .get(service)
+            .and_then(|rules| rules.verdict(dep))
+            .or_else(|| self.default.verdict(dep))
+            .unwrap_or(true)
+    }
+}
+
+/// A set of service names to deny or allow.
+///
+/// Names ending in `*` match every service name with that prefix, so
+/// `hearth.terminal.*` matches every terminal service and `*` matches all of
+/// them.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PolicyRules {
+    /// The services to withhold.
+    #[serde(default)]
+    pub deny: Vec<String>,
+
+    /// Exceptions to `deny` and to any less specific rules.
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl PolicyRules {
+    /// Returns whether these rules allow or deny `dep`, or `None` if they
+    /// don't mention it.
+    fn verdict(&self, dep: &str) -> Option<bool> {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => dep.starts_with(prefix),
+            None => pattern == dep,
+        };
+
+        if self.allow.iter().any(matches) {
+            Some(true)
+        } else if self.deny.iter().any(matches) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+fn get_policy() -> Policy {
+    let Some(service) = REGISTRY.get_service(POLICY_SERVICE) else {
+        info!("No policy found; services receive all of their dependencies");
+        return Policy::default();
+    };
+
+    let service = RequestResponse::<(), Option<String>>::new(service);
+    let Ok((Some(data), _)) = service.try_request((), &[]) else {
+        error!("Failed to load policy, denying all dependencies");
+        return Policy::deny_all();
+    };
+
+    match toml::from_str(&data) {
+        Ok(policy) => {
+            info!("policy: {:?}", policy);
+            policy
+        }
+        Err(err) => {
+            error!("Invalid policy, denying all dependencies: {err}");
+            Policy::deny_all()
+        }
+    }
+}
+
 fn get_config(name: &str) -> Option<ServiceConfig> {
     let config_path = format!("{}/{}/service.toml", SEARCH_DIR, name);
     let config_data = read_file(&config_path).ok()?;
@@ -0,0 +1,145 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The protocol of the avatar service, which tracks the people present in a
+//! shared space and broadcasts their poses to each other.
+
+use glam::Mat4;
+use hearth_guest::LumpId;
+use serde::{Deserialize, Serialize};
+
+/// The name of the avatar service. Accepts [AvatarRequest].
+pub const SERVICE_NAME: &str = "rs.hearth.kindling.Avatars";
+
+/// The longest display name an avatar can have, in characters.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// A request to the avatar service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum AvatarRequest {
+    /// Adds a new avatar to the space.
+    ///
+    /// The first capability is the reply capability, which receives a
+    /// [JoinResponse] and, when successful, a capability to the new avatar.
+    /// The avatar accepts [AvatarUpdate] messages.
+    ///
+    /// The second capability is the avatar's presence and must have the
+    /// monitor permission. The avatar leaves the space when its presence goes
+    /// down.
+    Join {
+        /// The avatar's display name.
+        name: String,
+
+        /// The lump of the avatar's VRM model, if it has one.
+        vrm: Option<LumpId>,
+    },
+
+    /// Subscribes to [AvatarEvents][AvatarEvent] using the first capability.
+    ///
+    /// The subscriber is first sent an [AvatarEvent::Joined] for every avatar
+    /// already in the space. If the capability has the monitor permission, it
+    /// will be automatically unsubscribed when down.
+    Subscribe,
+}
+
+/// The response to [AvatarRequest::Join].
+pub type JoinResponse = Result<AvatarId, JoinError>;
+
+/// An error joining the space.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum JoinError {
+    /// The request didn't include a presence capability with the monitor
+    /// permission.
+    MissingPresence,
+
+    /// The display name is empty or longer than [MAX_NAME_LEN].
+    InvalidName,
+}
+
+/// Identifies an avatar within a single avatar service.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AvatarId(pub u32);
+
+/// Everything known about an avatar.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AvatarInfo {
+    pub id: AvatarId,
+
+    /// The avatar's display name.
+    pub name: String,
+
+    /// The lump of the avatar's VRM model, if it has one.
+    pub vrm: Option<LumpId>,
+
+    /// The avatar's latest pose.
+    pub pose: AvatarPose,
+}
+
+/// The pose of an avatar. All transforms are in world space.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AvatarPose {
+    /// The transform of the avatar's head.
+    pub head: Mat4,
+
+    /// The transform of the avatar's left hand, if it's tracked.
+    pub left_hand: Option<Mat4>,
+
+    /// The transform of the avatar's right hand, if it's tracked.
+    pub right_hand: Option<Mat4>,
+
+    /// The transform of every joint in the avatar's VRM skeleton, in the
+    /// skeleton's order, if the full body is tracked.
+    pub joints: Option<Vec<Mat4>>,
+}
+
+/// An update sent to an avatar's capability by its owner.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum AvatarUpdate {
+    /// Moves the avatar.
+    Pose(Box<AvatarPose>),
+
+    /// Changes the avatar's display name. Invalid names are ignored.
+    SetName(String),
+
+    /// Changes the avatar's VRM model.
+    SetVrm(Option<LumpId>),
+}
+
+/// An event sent to the avatar service's subscribers.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum AvatarEvent {
+    /// An avatar joined the space.
+    Joined(AvatarInfo),
+
+    /// An avatar left the space.
+    Left(AvatarId),
+
+    /// An avatar moved.
+    Posed { id: AvatarId, pose: AvatarPose },
+
+    /// An avatar changed its display name.
+    Renamed { id: AvatarId, name: String },
+
+    /// An avatar changed its VRM model.
+    ChangedVrm { id: AvatarId, vrm: Option<LumpId> },
+}
+
+/// Checks that a display name is non-empty and not too long.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.trim().is_empty() && name.chars().count() <= MAX_NAME_LEN
+}
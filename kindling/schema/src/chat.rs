@@ -0,0 +1,145 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The protocol of the chat service, which relays text messages between the
+//! people in a shared space and keeps each channel's recent history.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the chat service. Accepts [ChatRequest].
+pub const SERVICE_NAME: &str = "rs.hearth.kindling.Chat";
+
+/// The longest sender name, in characters.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// The longest channel name, in characters.
+pub const MAX_CHANNEL_LEN: usize = 64;
+
+/// The longest message text, in characters.
+pub const MAX_TEXT_LEN: usize = 500;
+
+/// The channel that chat front-ends use by default.
+pub const DEFAULT_CHANNEL: &str = "general";
+
+/// A request to the chat service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ChatRequest {
+    /// Joins the chat under a display name.
+    ///
+    /// The first capability is the reply capability, which receives a
+    /// [JoinResponse] and, when successful, a capability to a sender. The
+    /// sender accepts [ChatSend] messages and signs every message sent
+    /// through it with this name, so other participants can't be
+    /// impersonated by the sender's holder.
+    ///
+    /// The second capability is the sender's presence and must have the
+    /// monitor permission. The sender stops working when its presence goes
+    /// down.
+    Join {
+        /// The sender's display name.
+        name: String,
+    },
+
+    /// Subscribes to a channel's new [ChatMessages][ChatMessage] using the
+    /// first capability.
+    ///
+    /// If the capability has the monitor permission, it will be
+    /// automatically unsubscribed when down.
+    Subscribe {
+        /// The name of the channel.
+        channel: String,
+    },
+
+    /// Replies to the first capability with a [HistoryResponse] containing
+    /// a channel's most recent messages, oldest first.
+    History {
+        /// The name of the channel.
+        channel: String,
+
+        /// The most messages to return.
+        limit: usize,
+    },
+}
+
+/// The response to [ChatRequest::Join].
+pub type JoinResponse = Result<(), ChatError>;
+
+/// The response to [ChatRequest::History].
+pub type HistoryResponse = Result<Vec<ChatMessage>, ChatError>;
+
+/// An error from the chat service.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ChatError {
+    /// The join request didn't include a presence capability with the
+    /// monitor permission.
+    MissingPresence,
+
+    /// The display name is empty or longer than [MAX_NAME_LEN].
+    InvalidName,
+
+    /// The channel name is empty or longer than [MAX_CHANNEL_LEN].
+    InvalidChannel,
+}
+
+/// A message sent to a sender capability to post to a channel.
+///
+/// Messages to invalid channels and messages with empty or too-long text are
+/// dropped.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatSend {
+    /// The name of the channel to post to.
+    pub channel: String,
+
+    /// The message's text.
+    pub text: String,
+}
+
+/// A message posted to a channel.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// The name of the channel that the message was posted to.
+    pub channel: String,
+
+    /// The display name that the sender joined with.
+    pub sender: String,
+
+    /// The message's text.
+    pub text: String,
+
+    /// When the chat service received the message, in milliseconds since
+    /// the UNIX epoch.
+    pub time: u64,
+}
+
+/// Checks that a display name is non-empty and not too long.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.trim().is_empty() && name.chars().count() <= MAX_NAME_LEN
+}
+
+/// Checks that a channel name is non-empty, not too long, and contains no
+/// whitespace.
+pub fn is_valid_channel(channel: &str) -> bool {
+    !channel.is_empty()
+        && channel.chars().count() <= MAX_CHANNEL_LEN
+        && !channel.chars().any(char::is_whitespace)
+}
+
+/// Checks that a message's text is non-empty and not too long.
+pub fn is_valid_text(text: &str) -> bool {
+    !text.trim().is_empty() && text.chars().count() <= MAX_TEXT_LEN
+}
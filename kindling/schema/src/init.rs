@@ -0,0 +1,64 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The protocol of init's status service, which reports whether each of the
+//! guest services that init manages is up.
+
+use serde::{Deserialize, Serialize};
+
+/// The name of init's status service. Accepts [StatusRequest].
+pub const STATUS_SERVICE_NAME: &str = "rs.hearth.kindling.InitStatus";
+
+/// A request to init's status service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum StatusRequest {
+    /// Replies to the first capability with a [StatusResponse] listing every
+    /// guest service that init found, sorted by name.
+    List,
+}
+
+/// The response to [StatusRequest::List].
+pub type StatusResponse = Vec<ServiceStatus>;
+
+/// The status of a single guest service.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ServiceStatus {
+    /// The name of the service's directory.
+    pub name: String,
+
+    /// Whether the service is currently running.
+    pub state: ServiceState,
+
+    /// How many times this service's supervisor has restarted it.
+    pub restarts: u32,
+}
+
+/// Whether a guest service is running.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum ServiceState {
+    /// The service is running under its supervisor.
+    Up,
+
+    /// The service went down and will be restarted in the given number of
+    /// seconds. Messages sent to the service are held until it restarts.
+    Restarting { delay: f32 },
+
+    /// The service was never started, either because it failed to start or
+    /// because one of its dependencies is missing.
+    Stopped,
+}
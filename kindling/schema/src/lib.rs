@@ -15,3 +15,11 @@
 //
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+pub mod avatar;
+pub mod chat;
+pub mod init;
+pub mod model;
+pub mod scene;
+pub mod store;
+pub mod world;
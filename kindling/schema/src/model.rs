@@ -0,0 +1,112 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The protocol of the model loader, which imports model files into renderer
+//! mesh and material lumps.
+
+use std::collections::HashMap;
+
+use hearth_guest::LumpId;
+use serde::{Deserialize, Serialize};
+
+/// The name of the model loader service. Accepts [ModelRequest] and replies
+/// with [ModelResponse].
+pub const SERVICE_NAME: &str = "rs.hearth.kindling.ModelLoader";
+
+/// A model file format that the model loader can import.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ModelFormat {
+    /// Wavefront OBJ, with materials from MTL libraries.
+    Obj,
+
+    /// Binary or ASCII STL.
+    Stl,
+
+    /// ASCII or binary Stanford PLY.
+    Ply,
+}
+
+impl ModelFormat {
+    /// Guesses the format of a file from its extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "obj" => Some(ModelFormat::Obj),
+            "stl" => Some(ModelFormat::Stl),
+            "ply" => Some(ModelFormat::Ply),
+            _ => None,
+        }
+    }
+}
+
+/// A request to the model loader.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ModelRequest {
+    /// Imports a model file.
+    Load {
+        format: ModelFormat,
+
+        /// The lump containing the model file.
+        model: LumpId,
+
+        /// Other files that the model refers to, by the name it uses for
+        /// them, like an OBJ's MTL libraries and their textures.
+        #[serde(default)]
+        resources: HashMap<String, LumpId>,
+    },
+}
+
+/// An imported model.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Model {
+    /// The model's meshes, one for each of its materials.
+    pub meshes: Vec<ModelMesh>,
+}
+
+/// A mesh of an imported model and its material.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModelMesh {
+    /// The name of the mesh's material, if it has one.
+    pub name: Option<String>,
+
+    /// The lump ID of the mesh's mesh data.
+    pub mesh: LumpId,
+
+    /// The lump ID of the mesh's material data.
+    pub material: LumpId,
+}
+
+/// An error importing a model.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ModelError {
+    /// The model file is malformed.
+    Parse(String),
+
+    /// The model refers to a file that wasn't passed in
+    /// [ModelRequest::Load::resources].
+    MissingResource(String),
+
+    /// A texture failed to decode.
+    Texture { name: String, message: String },
+
+    /// The model has no triangles.
+    Empty,
+}
+
+/// A type shorthand for [Model] and [ModelError].
+pub type ModelResponse = Result<Model, ModelError>;
@@ -0,0 +1,231 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The scene description format and the protocol of the scene loader, which
+//! instantiates scenes authored as data files.
+//!
+//! Scene files are JSON-encoded [Scenes][Scene]. Paths in a scene file are
+//! relative to the directory containing it unless they start with `/`, in
+//! which case they're relative to the root of the filesystem.
+
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// The name of the scene loader service. Accepts [SceneRequest] and replies
+/// with [SceneResponse].
+pub const SERVICE_NAME: &str = "rs.hearth.kindling.SceneLoader";
+
+/// A world authored as data.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Scene {
+    /// The scene's ambient lighting, if it sets it.
+    #[serde(default)]
+    pub ambient: Option<Vec3>,
+
+    /// The things in the scene.
+    #[serde(default)]
+    pub entities: Vec<Entity>,
+}
+
+/// A thing in a scene, made of optional components.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Entity {
+    /// An optional name for this entity, used in errors and given to its
+    /// script.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Where this entity is.
+    #[serde(default)]
+    pub transform: Transform,
+
+    /// The model drawn at this entity's transform.
+    #[serde(default)]
+    pub model: Option<Model>,
+
+    /// The light at this entity's transform.
+    #[serde(default)]
+    pub light: Option<Light>,
+
+    /// The Wasm module spawned for this entity.
+    #[serde(default)]
+    pub script: Option<Script>,
+}
+
+/// The position, rotation, and scale of an entity.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    /// Converts this transform into a matrix.
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Gets the direction that this transform points in, which is its
+    /// rotation applied to negative Z.
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * -Vec3::Z
+    }
+}
+
+/// A model component.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Model {
+    /// The path to a file containing the model's JSON-encoded mesh data.
+    pub mesh: String,
+
+    /// The path to a file containing the model's albedo texture data, or a
+    /// KTX2 texture. The model is white if this is unset.
+    #[serde(default)]
+    pub albedo: Option<String>,
+}
+
+/// A light component. Lights are positioned and pointed by their entity's
+/// transform.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Light {
+    Directional {
+        color: Vec3,
+        intensity: f32,
+
+        /// The distance from the camera that shadows are rendered to.
+        distance: f32,
+    },
+    Point {
+        color: Vec3,
+        intensity: f32,
+        radius: f32,
+    },
+    /// Not supported by the renderer yet, so spot lights are skipped with a
+    /// warning.
+    Spot {
+        color: Vec3,
+        intensity: f32,
+        radius: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+
+        #[serde(default = "hearth_guest::renderer::SpotLightState::default_falloff")]
+        falloff: f32,
+    },
+}
+
+/// A script component.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Script {
+    /// The path to the script's Wasm module.
+    pub module: String,
+}
+
+/// The first message sent to an entity's script after it is spawned.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScriptStart {
+    /// The entity's name, if it has one.
+    pub name: Option<String>,
+
+    /// The entity's transform.
+    pub transform: Transform,
+}
+
+/// A request to the scene loader.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum SceneRequest {
+    /// Reads a scene file and instantiates everything in it.
+    ///
+    /// Nothing from the scene is kept if any of it fails to load. Replies
+    /// with [SceneSuccess::Loaded].
+    Load {
+        /// The path to the scene file.
+        path: String,
+    },
+
+    /// Removes everything in a loaded scene and kills its scripts.
+    ///
+    /// Replies with [SceneSuccess::Done].
+    Unload { id: SceneId },
+}
+
+/// Identifies a scene loaded by a single scene loader.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SceneId(pub u32);
+
+/// A successful response from the scene loader.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum SceneSuccess {
+    /// The request succeeded.
+    Done,
+
+    /// The scene was loaded with this ID.
+    Loaded(SceneId),
+}
+
+/// An error from the scene loader.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SceneError {
+    /// A file couldn't be read.
+    Read { path: String, message: String },
+
+    /// The scene file isn't a valid scene.
+    Parse(String),
+
+    /// An entity failed to be instantiated.
+    Entity {
+        /// The entity's index in the scene.
+        index: usize,
+
+        /// The entity's name, if it has one.
+        name: Option<String>,
+
+        message: String,
+    },
+
+    /// No loaded scene has the given ID.
+    NotFound,
+}
+
+/// A type shorthand for [SceneSuccess] and [SceneError].
+pub type SceneResponse = Result<SceneSuccess, SceneError>;
+
+/// Resolves a path in a scene file relative to the directory of the scene
+/// file at `scene_path`.
+pub fn resolve_path(scene_path: &str, path: &str) -> String {
+    if let Some(absolute) = path.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    match scene_path.rsplit_once('/') {
+        Some((dir, _)) => format!("{dir}/{path}"),
+        None => path.to_string(),
+    }
+}
@@ -0,0 +1,32 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The protocol between kindling stores and their subscribers.
+
+use serde::{Deserialize, Serialize};
+
+/// A request sent to a store's capability.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum StoreRequest {
+    /// Subscribes to changes in the store's value.
+    ///
+    /// The first capability is the subscriber's reply capability. The store
+    /// immediately replies with its current value, then sends its new value
+    /// whenever it changes, until the subscriber becomes unavailable.
+    Subscribe,
+}
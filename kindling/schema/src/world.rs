@@ -0,0 +1,149 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The protocol of the world service, which spawns the persistent contents
+//! of a space and restores them when the space starts again.
+
+use glam::Mat4;
+use hearth_guest::{
+    canvas::{CanvasSamplingMode, Pixels, Position},
+    renderer::{DirectionalLightState, PointLightState, SpotLightState},
+    LumpId,
+};
+use serde::{Deserialize, Serialize};
+
+/// The name of the world service. Accepts [WorldRequest] and replies with
+/// [WorldResponse].
+pub const SERVICE_NAME: &str = "rs.hearth.kindling.World";
+
+/// A request to the world service.
+///
+/// Every change is saved before the service replies, so the space's
+/// contents survive restarts of the peer running the service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WorldRequest {
+    /// Spawns an entity that isn't a [WorldEntity::Panel].
+    ///
+    /// Replies with [WorldSuccess::Spawned].
+    Spawn(WorldEntity),
+
+    /// Spawns a panel.
+    ///
+    /// The world service stores the pixels in a lump of its own. Replies with
+    /// [WorldSuccess::Spawned].
+    SpawnPanel {
+        position: Position,
+        sampling: CanvasSamplingMode,
+        pixels: Pixels,
+    },
+
+    /// Removes an entity from the world.
+    ///
+    /// Replies with [WorldSuccess::Done].
+    Despawn { id: EntityId },
+
+    /// Lists every entity in the world in the order they were spawned.
+    ///
+    /// Replies with [WorldSuccess::Entities].
+    List,
+
+    /// Removes every entity from the world.
+    ///
+    /// Replies with [WorldSuccess::Done].
+    Clear,
+}
+
+/// Identifies an entity within a single world service. IDs are kept across
+/// restarts.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct EntityId(pub u32);
+
+/// Something in the world, as it is saved.
+///
+/// All lumps are saved along with the entity, including the albedo texture
+/// of a model's material.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WorldEntity {
+    /// A renderer object.
+    Model {
+        /// The lump ID of the object's mesh data.
+        mesh: LumpId,
+
+        /// The lump ID of the object's material data.
+        material: LumpId,
+
+        /// The object's transform.
+        transform: Mat4,
+    },
+
+    DirectionalLight(DirectionalLightState),
+    PointLight(PointLightState),
+    SpotLight(SpotLightState),
+
+    /// A canvas.
+    Panel {
+        position: Position,
+        sampling: CanvasSamplingMode,
+
+        /// The lump ID of the JSON-encoded [Pixels] of the canvas.
+        pixels: LumpId,
+    },
+}
+
+/// An entity that has been spawned in the world.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpawnedEntity {
+    pub id: EntityId,
+    pub entity: WorldEntity,
+}
+
+/// A successful response from the world service.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum WorldSuccess {
+    /// The request succeeded.
+    Done,
+
+    /// The entity was spawned with this ID.
+    Spawned(EntityId),
+
+    /// The world's entities.
+    Entities(Vec<SpawnedEntity>),
+}
+
+/// An error from the world service.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum WorldError {
+    /// A [WorldEntity::Panel] was passed to [WorldRequest::Spawn] instead of
+    /// [WorldRequest::SpawnPanel].
+    PanelWithoutPixels,
+
+    /// No entity has the given ID.
+    NotFound,
+
+    /// A lump is too large to be saved.
+    LumpTooLarge(LumpId),
+
+    /// The renderer or canvas factory failed to create the entity.
+    SpawnFailed(String),
+
+    /// The world couldn't be saved or loaded.
+    Storage(String),
+}
+
+/// A type shorthand for [WorldSuccess] and [WorldError].
+pub type WorldResponse = Result<WorldSuccess, WorldError>;
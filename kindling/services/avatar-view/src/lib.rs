@@ -0,0 +1,391 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `rs.hearth.kindling.AvatarView` service: joins the shared space as
+//! this peer's avatar and draws every other avatar in it.
+//!
+//! VRM models can't be loaded yet, so avatars are drawn as wireframe heads
+//! and hands with a nametag above them, with a marker at each joint of
+//! full-body poses.
+//!
+//! Messages sent to this service are [AvatarUpdates][AvatarUpdate] for this
+//! peer's own avatar, so that whatever moves the camera can move the avatar
+//! too. The display name is read from the `name` key of this service's
+//! key-value namespace.
+
+use std::collections::HashMap;
+
+use hearth_guest::{
+    canvas::*, codec, debug_draw::*, window::WindowEvent, Color, Lump, LumpId, Mailbox, Signal,
+    PARENT,
+};
+use kindling_host::{
+    avatar::{Avatar, AvatarHub},
+    kv::KvStore,
+    prelude::{
+        glam::{vec2, vec3, Mat4, Quat, Vec3},
+        *,
+    },
+};
+use kindling_schema::avatar::*;
+
+hearth_guest::export_metadata!();
+
+/// The display name used if none is configured.
+const DEFAULT_NAME: &str = "Guest";
+
+/// How often to look for a connected peer's avatar service while using this
+/// peer's own, in seconds.
+const RETRY_INTERVAL: f32 = 5.0;
+
+/// The world-space sizes of the wireframe cubes drawn for each body part.
+const HEAD_SIZE: f32 = 0.25;
+const HAND_SIZE: f32 = 0.08;
+const JOINT_SIZE: f32 = 0.03;
+
+/// The pixel size of each nametag's canvas.
+const NAMETAG_WIDTH: u32 = 256;
+const NAMETAG_HEIGHT: u32 = 40;
+
+/// The world-space half-width of each nametag.
+const NAMETAG_HALF_WIDTH: f32 = 0.4;
+
+/// The height of nametags above their avatar's head.
+const NAMETAG_OFFSET: f32 = 0.35;
+
+const NAMETAG_BACKGROUND: [u8; 4] = [0x19, 0x17, 0x24, 0xff];
+const NAMETAG_COLOR: [u8; 4] = [0xe0, 0xde, 0xf4, 0xff];
+
+/// Adds the edges of a wireframe cube to a debug draw mesh.
+fn push_cube(mesh: &mut DebugDrawMesh, transform: Mat4, size: f32) {
+    let base = mesh.vertices.len() as u32;
+    let color = Color::from_rgb(0xf6, 0xc1, 0x77);
+
+    for corner in 0..8 {
+        let offset = vec3(
+            if corner & 1 == 0 { -0.5 } else { 0.5 },
+            if corner & 2 == 0 { -0.5 } else { 0.5 },
+            if corner & 4 == 0 { -0.5 } else { 0.5 },
+        );
+
+        let position = transform.transform_point3(offset * size);
+        mesh.vertices.push(DebugDrawVertex::new(position, color));
+    }
+
+    // each edge connects two corners that differ in one axis
+    for corner in 0..8u32 {
+        for axis in [1, 2, 4] {
+            if corner & axis == 0 {
+                mesh.indices.push(base + corner);
+                mesh.indices.push(base + (corner | axis));
+            }
+        }
+    }
+}
+
+/// Another avatar in the space, drawn by this peer.
+struct Body {
+    info: AvatarInfo,
+    wireframe: DebugDraw,
+    nametag: Canvas,
+}
+
+impl Body {
+    fn new(info: AvatarInfo, font: &Lump) -> Self {
+        let pixels = Pixels {
+            width: NAMETAG_WIDTH,
+            height: NAMETAG_HEIGHT,
+            data: NAMETAG_BACKGROUND
+                .into_iter()
+                .cycle()
+                .take((NAMETAG_WIDTH * NAMETAG_HEIGHT * 4) as usize)
+                .collect(),
+        };
+
+        let nametag = Canvas::new(
+            Self::nametag_position(&info.pose),
+            pixels,
+            CanvasSamplingMode::Linear,
+        );
+
+        let body = Self {
+            info,
+            wireframe: DebugDraw::new(),
+            nametag,
+        };
+
+        body.draw_name(font);
+        body.draw_pose();
+        body
+    }
+
+    /// Draws this avatar's name onto its nametag.
+    fn draw_name(&self, font: &Lump) {
+        self.nametag.draw_text(TextDraw {
+            x: 8,
+            y: 4,
+            font: font.get_id(),
+            size: 28.0,
+            color: NAMETAG_COLOR,
+            background: NAMETAG_BACKGROUND,
+            text: self.info.name.clone(),
+        });
+    }
+
+    /// Redraws this avatar's wireframe and moves its nametag to its pose.
+    fn draw_pose(&self) {
+        let pose = &self.info.pose;
+
+        let mut mesh = DebugDrawMesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        };
+
+        push_cube(&mut mesh, pose.head, HEAD_SIZE);
+
+        for hand in [pose.left_hand, pose.right_hand].into_iter().flatten() {
+            push_cube(&mut mesh, hand, HAND_SIZE);
+        }
+
+        for joint in pose.joints.iter().flatten() {
+            push_cube(&mut mesh, *joint, JOINT_SIZE);
+        }
+
+        self.wireframe.update(mesh);
+        self.nametag.relocate(Self::nametag_position(pose));
+    }
+
+    /// Gets the position of a nametag above a pose's head, turned the same
+    /// way as the head.
+    fn nametag_position(pose: &AvatarPose) -> Position {
+        let (_scale, rotation, translation) = pose.head.to_scale_rotation_translation();
+        let forward = rotation * Vec3::Z;
+        let aspect = NAMETAG_HEIGHT as f32 / NAMETAG_WIDTH as f32;
+
+        Position {
+            origin: translation + Vec3::Y * NAMETAG_OFFSET,
+            orientation: Quat::from_rotation_y(forward.x.atan2(forward.z)),
+            half_size: vec2(NAMETAG_HALF_WIDTH, NAMETAG_HALF_WIDTH * aspect),
+        }
+    }
+}
+
+/// This peer's presence in an avatar service.
+struct Connection {
+    hub: AvatarHub,
+    avatar: Avatar,
+    events: Mailbox,
+    bodies: HashMap<AvatarId, Body>,
+}
+
+struct View {
+    font: Lump,
+    name: String,
+    pose: AvatarPose,
+    vrm: Option<LumpId>,
+    connection: Option<Connection>,
+
+    /// The time in seconds until looking for a connected peer's avatar
+    /// service again.
+    retry: f32,
+}
+
+impl View {
+    /// Joins an avatar service, leaving the current one, if any.
+    fn connect(&mut self, hub: AvatarHub) {
+        self.connection = None;
+
+        let avatar = match hub.join(&self.name, self.vrm) {
+            Ok(avatar) => avatar,
+            Err(err) => {
+                error!("Failed to join avatar service: {err:?}");
+                return;
+            }
+        };
+
+        avatar.set_pose(self.pose.clone());
+        let events = hub.subscribe();
+
+        info!(
+            "Joined {} avatar service as {:?}",
+            if hub.is_remote() { "remote" } else { "local" },
+            self.name
+        );
+
+        self.connection = Some(Connection {
+            hub,
+            avatar,
+            events,
+            bodies: HashMap::new(),
+        });
+    }
+
+    /// Looks for a connected peer's avatar service if not using one already.
+    fn update(&mut self, dt: f32) {
+        if let Some(connection) = self.connection.as_ref() {
+            if connection.hub.is_remote() {
+                return;
+            }
+        }
+
+        self.retry -= dt;
+        if self.retry > 0.0 {
+            return;
+        }
+
+        self.retry = RETRY_INTERVAL;
+
+        if let Some(hub) = AvatarHub::find_remote() {
+            self.connect(hub);
+        } else if self.connection.is_none() {
+            if let Some(hub) = AvatarHub::find() {
+                self.connect(hub);
+            }
+        }
+    }
+
+    /// Handles a signal received from the current avatar service.
+    fn on_event(&mut self, signal: Signal) {
+        let Some(connection) = self.connection.as_mut() else {
+            return;
+        };
+
+        let msg = match signal {
+            Signal::Message(msg) => msg,
+            Signal::Down { .. } => {
+                warn!("Avatar service became unavailable");
+                self.connection = None;
+                self.retry = 0.0;
+                return;
+            }
+        };
+
+        let event = match codec::decode(&msg.data) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("invalid avatar event: {err:?}");
+                return;
+            }
+        };
+
+        let own_id = connection.avatar.id();
+        let bodies = &mut connection.bodies;
+        match event {
+            AvatarEvent::Joined(info) if info.id != own_id => {
+                bodies.insert(info.id, Body::new(info, &self.font));
+            }
+            AvatarEvent::Left(id) => {
+                bodies.remove(&id);
+            }
+            AvatarEvent::Posed { id, pose } => {
+                if let Some(body) = bodies.get_mut(&id) {
+                    body.info.pose = pose;
+                    body.draw_pose();
+                }
+            }
+            AvatarEvent::Renamed { id, name } => {
+                if let Some(body) = bodies.get_mut(&id) {
+                    body.info.name = name;
+                    body.draw_name(&self.font);
+                }
+            }
+            AvatarEvent::ChangedVrm { id, vrm } => {
+                if let Some(body) = bodies.get_mut(&id) {
+                    body.info.vrm = vrm;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Updates this peer's own avatar.
+    fn on_update(&mut self, update: AvatarUpdate) {
+        match &update {
+            AvatarUpdate::Pose(pose) => self.pose = (**pose).clone(),
+            AvatarUpdate::SetName(name) => self.name = name.clone(),
+            AvatarUpdate::SetVrm(vrm) => self.vrm = *vrm,
+        }
+
+        if let Some(connection) = self.connection.as_ref() {
+            connection.avatar.update(&update);
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    let font = include_bytes!("../../../../resources/mononoki/mononoki-Regular.ttf");
+
+    let name = match KvStore::root().get_json::<String>("name") {
+        Ok(Some(name)) if is_valid_name(&name) => name,
+        Ok(_) => DEFAULT_NAME.to_string(),
+        Err(err) => {
+            warn!("Failed to read display name: {err:?}");
+            DEFAULT_NAME.to_string()
+        }
+    };
+
+    let mut view = View {
+        font: Lump::load_raw(font),
+        name,
+        pose: AvatarPose {
+            head: Mat4::from_translation(vec3(0.0, 1.6, 0.0)),
+            ..Default::default()
+        },
+        vrm: None,
+        connection: None,
+        retry: 0.0,
+    };
+
+    view.update(0.0);
+
+    let window_events = MAIN_WINDOW.subscribe();
+
+    loop {
+        let mut mailboxes = vec![&PARENT, &window_events];
+        if let Some(connection) = view.connection.as_ref() {
+            mailboxes.push(&connection.events);
+        }
+
+        let (index, signal) = Mailbox::poll(&mailboxes);
+        drop(mailboxes);
+
+        match index {
+            0 => {
+                let Signal::Message(msg) = signal else {
+                    continue;
+                };
+
+                match codec::decode(&msg.data) {
+                    Ok(update) => view.on_update(update),
+                    Err(err) => warn!("invalid avatar update: {err:?}"),
+                }
+            }
+            1 => {
+                let Signal::Message(msg) = signal else {
+                    continue;
+                };
+
+                if let Ok(WindowEvent::Redraw { dt }) = codec::decode(&msg.data) {
+                    view.update(dt);
+                }
+            }
+            _ => view.on_event(signal),
+        }
+    }
+}
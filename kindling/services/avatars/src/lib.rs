@@ -0,0 +1,212 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `rs.hearth.kindling.Avatars` service: tracks the avatars present in a
+//! shared space and broadcasts their changes to every subscriber.
+//!
+//! The service is exported to connected peers, so clients join the avatar
+//! service of the server that they're connected to.
+
+use hearth_guest::{codec, Capability, LumpId, Mailbox, Message, Permissions, Signal, PARENT};
+use kindling_host::prelude::*;
+use kindling_schema::avatar::*;
+
+hearth_guest::export_metadata!();
+
+/// An avatar in the space.
+struct Avatar {
+    info: AvatarInfo,
+
+    /// Receives the avatar's updates and the down signal of its presence.
+    mailbox: Mailbox,
+
+    /// Kept so that the presence stays monitored.
+    _presence: Capability,
+}
+
+/// A process subscribed to the space's avatar events.
+struct Subscriber {
+    cap: Capability,
+
+    /// A mailbox monitoring the subscriber for when it goes down.
+    monitor: Mailbox,
+}
+
+#[derive(Default)]
+struct Space {
+    next_id: u32,
+    avatars: Vec<Avatar>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl Space {
+    /// Handles a message sent to the service.
+    fn on_request(&mut self, msg: Message) {
+        let request = match codec::decode(&msg.data) {
+            Ok(request) => request,
+            Err(err) => {
+                warn!("invalid avatar request: {err:?}");
+                return;
+            }
+        };
+
+        let mut caps = msg.caps.into_iter();
+        let Some(reply) = caps.next() else {
+            warn!("avatar request is missing a capability");
+            return;
+        };
+
+        match request {
+            AvatarRequest::Join { name, vrm } => match self.join(name, vrm, caps.next()) {
+                Ok((id, avatar)) => reply.send(&JoinResponse::Ok(id), &[&avatar]),
+                Err(err) => reply.send(&JoinResponse::Err(err), &[]),
+            },
+            AvatarRequest::Subscribe => self.subscribe(reply),
+        }
+    }
+
+    /// Adds a new avatar to the space. Returns its ID and a capability to it.
+    fn join(
+        &mut self,
+        name: String,
+        vrm: Option<LumpId>,
+        presence: Option<Capability>,
+    ) -> Result<(AvatarId, Capability), JoinError> {
+        let presence = presence
+            .filter(|cap| cap.get_flags().contains(Permissions::MONITOR))
+            .ok_or(JoinError::MissingPresence)?;
+
+        if !is_valid_name(&name) {
+            return Err(JoinError::InvalidName);
+        }
+
+        let id = AvatarId(self.next_id);
+        self.next_id += 1;
+
+        let mailbox = Mailbox::new();
+        mailbox.monitor(&presence);
+
+        let info = AvatarInfo {
+            id,
+            name,
+            vrm,
+            pose: AvatarPose::default(),
+        };
+
+        info!("{:?} joined as {:?}", id, info.name);
+        self.broadcast(&AvatarEvent::Joined(info.clone()));
+        let cap = mailbox.make_capability(Permissions::SEND);
+        self.avatars.push(Avatar {
+            info,
+            mailbox,
+            _presence: presence,
+        });
+        Ok((id, cap))
+    }
+
+    /// Adds a subscriber and tells it about every avatar in the space.
+    fn subscribe(&mut self, cap: Capability) {
+        let monitor = Mailbox::new();
+        if cap.get_flags().contains(Permissions::MONITOR) {
+            monitor.monitor(&cap);
+        }
+
+        for avatar in self.avatars.iter() {
+            cap.send(&AvatarEvent::Joined(avatar.info.clone()), &[]);
+        }
+
+        self.subscribers.push(Subscriber { cap, monitor });
+    }
+
+    /// Handles a signal received by the avatar at the given index.
+    fn on_avatar_signal(&mut self, index: usize, signal: Signal) {
+        let msg = match signal {
+            Signal::Message(msg) => msg,
+            Signal::Down { .. } => {
+                let avatar = self.avatars.remove(index);
+                info!("{:?} left", avatar.info.id);
+                self.broadcast(&AvatarEvent::Left(avatar.info.id));
+                return;
+            }
+        };
+
+        let update = match codec::decode(&msg.data) {
+            Ok(update) => update,
+            Err(err) => {
+                debug!("invalid avatar update: {err:?}");
+                return;
+            }
+        };
+
+        let info = &mut self.avatars[index].info;
+        let id = info.id;
+        let event = match update {
+            AvatarUpdate::Pose(pose) => {
+                info.pose = (*pose).clone();
+                AvatarEvent::Posed { id, pose: *pose }
+            }
+            AvatarUpdate::SetName(name) => {
+                if !is_valid_name(&name) {
+                    debug!("{:?} tried to rename to an invalid name", id);
+                    return;
+                }
+
+                info.name = name.clone();
+                AvatarEvent::Renamed { id, name }
+            }
+            AvatarUpdate::SetVrm(vrm) => {
+                info.vrm = vrm;
+                AvatarEvent::ChangedVrm { id, vrm }
+            }
+        };
+
+        self.broadcast(&event);
+    }
+
+    /// Sends an event to every subscriber, forgetting any that have gone
+    /// down.
+    fn broadcast(&mut self, event: &AvatarEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.monitor.try_recv_signal().is_none());
+
+        for subscriber in self.subscribers.iter() {
+            subscriber.cap.send(event, &[]);
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    let mut space = Space::default();
+
+    loop {
+        let mut mailboxes = vec![&PARENT];
+        mailboxes.extend(space.avatars.iter().map(|avatar| &avatar.mailbox));
+        let (index, signal) = Mailbox::poll(&mailboxes);
+        drop(mailboxes);
+
+        if index > 0 {
+            space.on_avatar_signal(index - 1, signal);
+            continue;
+        }
+
+        if let Signal::Message(msg) = signal {
+            space.on_request(msg);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `rs.hearth.kindling.Chat` service: relays text messages between the
+//! people in a shared space and keeps the recent history of each channel.
+//!
+//! The service is exported to connected peers, so clients chat through the
+//! chat service of the server that they're connected to. Channels are
+//! created when first used and history is kept in memory only.
+
+use std::collections::{HashMap, VecDeque};
+
+use hearth_guest::{codec, Capability, Mailbox, Message, Permissions, Signal, PARENT};
+use kindling_host::{prelude::*, time::get_unix_time};
+use kindling_schema::chat::*;
+
+hearth_guest::export_metadata!();
+
+/// The number of messages kept in each channel's history.
+const HISTORY_LEN: usize = 200;
+
+/// A joined participant's capability for posting messages.
+struct Sender {
+    /// The display name that every message from this sender is signed with.
+    name: String,
+
+    /// Receives the sender's [ChatSend] messages and the down signal of its
+    /// presence.
+    mailbox: Mailbox,
+
+    /// Kept so that the presence stays monitored.
+    _presence: Capability,
+}
+
+/// A process subscribed to a channel.
+struct Subscriber {
+    cap: Capability,
+
+    /// A mailbox monitoring the subscriber for when it goes down.
+    monitor: Mailbox,
+}
+
+#[derive(Default)]
+struct Channel {
+    history: VecDeque<ChatMessage>,
+    subscribers: Vec<Subscriber>,
+}
+
+#[derive(Default)]
+struct Chat {
+    channels: HashMap<String, Channel>,
+    senders: Vec<Sender>,
+}
+
+impl Chat {
+    /// Handles a message sent to the service.
+    fn on_request(&mut self, msg: Message) {
+        let request = match codec::decode(&msg.data) {
+            Ok(request) => request,
+            Err(err) => {
+                warn!("invalid chat request: {err:?}");
+                return;
+            }
+        };
+
+        let mut caps = msg.caps.into_iter();
+        let Some(reply) = caps.next() else {
+            warn!("chat request is missing a capability");
+            return;
+        };
+
+        match request {
+            ChatRequest::Join { name } => match self.join(name, caps.next()) {
+                Ok(sender) => reply.send(&JoinResponse::Ok(()), &[&sender]),
+                Err(err) => reply.send(&JoinResponse::Err(err), &[]),
+            },
+            ChatRequest::Subscribe { channel } => self.subscribe(channel, reply),
+            ChatRequest::History { channel, limit } => {
+                reply.send(&self.history(&channel, limit), &[]);
+            }
+        }
+    }
+
+    /// Adds a new sender. Returns a capability to it.
+    fn join(
+        &mut self,
+        name: String,
+        presence: Option<Capability>,
+    ) -> Result<Capability, ChatError> {
+        let presence = presence
+            .filter(|cap| cap.get_flags().contains(Permissions::MONITOR))
+            .ok_or(ChatError::MissingPresence)?;
+
+        if !is_valid_name(&name) {
+            return Err(ChatError::InvalidName);
+        }
+
+        let mailbox = Mailbox::new();
+        mailbox.monitor(&presence);
+        let cap = mailbox.make_capability(Permissions::SEND);
+
+        info!("{name:?} joined the chat");
+        self.senders.push(Sender {
+            name,
+            mailbox,
+            _presence: presence,
+        });
+
+        Ok(cap)
+    }
+
+    /// Subscribes a capability to a channel's new messages.
+    fn subscribe(&mut self, channel: String, cap: Capability) {
+        if !is_valid_channel(&channel) {
+            debug!("tried to subscribe to invalid channel {channel:?}");
+            return;
+        }
+
+        let monitor = Mailbox::new();
+        if cap.get_flags().contains(Permissions::MONITOR) {
+            monitor.monitor(&cap);
+        }
+
+        let channel = self.channels.entry(channel).or_default();
+        channel.subscribers.push(Subscriber { cap, monitor });
+    }
+
+    /// Gets up to `limit` of a channel's most recent messages, oldest first.
+    fn history(&self, channel: &str, limit: usize) -> HistoryResponse {
+        if !is_valid_channel(channel) {
+            return Err(ChatError::InvalidChannel);
+        }
+
+        let Some(channel) = self.channels.get(channel) else {
+            return Ok(Vec::new());
+        };
+
+        let skip = channel.history.len().saturating_sub(limit);
+        Ok(channel.history.iter().skip(skip).cloned().collect())
+    }
+
+    /// Handles a signal received by the sender at the given index.
+    fn on_sender_signal(&mut self, index: usize, signal: Signal) {
+        let msg = match signal {
+            Signal::Message(msg) => msg,
+            Signal::Down { .. } => {
+                let sender = self.senders.remove(index);
+                info!("{:?} left the chat", sender.name);
+                return;
+            }
+        };
+
+        let send: ChatSend = match codec::decode(&msg.data) {
+            Ok(send) => send,
+            Err(err) => {
+                debug!("invalid chat message: {err:?}");
+                return;
+            }
+        };
+
+        if !is_valid_channel(&send.channel) || !is_valid_text(&send.text) {
+            debug!("dropping invalid message to {:?}", send.channel);
+            return;
+        }
+
+        let message = ChatMessage {
+            channel: send.channel,
+            sender: self.senders[index].name.clone(),
+            text: send.text,
+            time: (get_unix_time() / 1_000_000) as u64,
+        };
+
+        self.post(message);
+    }
+
+    /// Adds a message to its channel's history and sends it to the channel's
+    /// subscribers, forgetting any that have gone down.
+    fn post(&mut self, message: ChatMessage) {
+        let channel = self.channels.entry(message.channel.clone()).or_default();
+
+        channel
+            .subscribers
+            .retain(|subscriber| subscriber.monitor.try_recv_signal().is_none());
+
+        for subscriber in channel.subscribers.iter() {
+            subscriber.cap.send(&message, &[]);
+        }
+
+        if channel.history.len() >= HISTORY_LEN {
+            channel.history.pop_front();
+        }
+
+        channel.history.push_back(message);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    let mut chat = Chat::default();
+
+    loop {
+        let mut mailboxes = vec![&PARENT];
+        mailboxes.extend(chat.senders.iter().map(|sender| &sender.mailbox));
+        let (index, signal) = Mailbox::poll(&mailboxes);
+        drop(mailboxes);
+
+        if index > 0 {
+            chat.on_sender_signal(index - 1, signal);
+            continue;
+        }
+
+        if let Signal::Message(msg) = signal {
+            chat.on_request(msg);
+        }
+    }
+}
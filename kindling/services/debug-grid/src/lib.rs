@@ -24,12 +24,7 @@ pub extern "C" fn run() {
     let size = 15;
     let color = Color::from_rgb(0x6a, 0xf5, 0xfc);
     let grid_to_pos = |x: i32, y: i32| vec3(x as f32 * 5.0, -8.0, y as f32 * 5.0);
-    let vertex = |x, y, color| -> DebugDrawVertex {
-        DebugDrawVertex {
-            position: grid_to_pos(x, y),
-            color,
-        }
-    };
+    let vertex = |x, y, color| DebugDrawVertex::new(grid_to_pos(x, y), color);
 
     let mut vertices = Vec::new();
 
@@ -0,0 +1,253 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A panel page for talking through the shared space's chat service.
+
+use std::collections::VecDeque;
+
+use hearth_guest::{codec, window::VirtualKeyCode, Mailbox, Signal};
+use kindling_host::{
+    chat::{ChatHub, ChatSender},
+    kv::KvStore,
+    prelude::*,
+};
+use kindling_schema::chat::*;
+
+use crate::panel::{Line, Page};
+
+/// The display name used if none is configured.
+const DEFAULT_NAME: &str = "Guest";
+
+/// The number of lines of chat history shown above the input line.
+const HISTORY_LINES: usize = 9;
+
+/// The most characters that fit on a line of a panel.
+const LINE_WIDTH: usize = 42;
+
+/// How often to look for a connected peer's chat service while using this
+/// peer's own, in seconds.
+const RETRY_INTERVAL: f32 = 5.0;
+
+/// A joined chat service and its default channel.
+struct Connection {
+    hub: ChatHub,
+    sender: ChatSender,
+    messages: Mailbox,
+}
+
+/// Shows the recent messages of the default chat channel and sends typed
+/// messages to it.
+///
+/// The display name is read from the `name` key of the home space's
+/// key-value namespace.
+pub struct Chat {
+    name: String,
+    input: String,
+    history: VecDeque<String>,
+    status: String,
+    connection: Option<Connection>,
+
+    /// The time in seconds until looking for a connected peer's chat service
+    /// again.
+    retry: f32,
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        let name = match KvStore::root().get_json::<String>("name") {
+            Ok(Some(name)) if is_valid_name(&name) => name,
+            Ok(_) => DEFAULT_NAME.to_string(),
+            Err(err) => {
+                warn!("Failed to read display name: {err:?}");
+                DEFAULT_NAME.to_string()
+            }
+        };
+
+        let mut chat = Self {
+            name,
+            input: String::new(),
+            history: VecDeque::new(),
+            status: String::new(),
+            connection: None,
+            retry: 0.0,
+        };
+
+        chat.update(0.0);
+        chat
+    }
+
+    /// Joins a chat service, leaving the current one, if any, and shows the
+    /// default channel's recent history.
+    fn connect(&mut self, hub: ChatHub) {
+        self.connection = None;
+        self.history.clear();
+
+        let sender = match hub.join(&self.name) {
+            Ok(sender) => sender,
+            Err(err) => {
+                error!("Failed to join chat service: {err:?}");
+                self.status = format!("Failed to join chat: {err:?}");
+                return;
+            }
+        };
+
+        let messages = hub.subscribe(DEFAULT_CHANNEL);
+
+        match hub.history(DEFAULT_CHANNEL, HISTORY_LINES) {
+            Ok(history) => history.iter().for_each(|msg| self.push(msg)),
+            Err(err) => warn!("Failed to get chat history: {err:?}"),
+        }
+
+        let location = if hub.is_remote() { "server" } else { "local" };
+        info!("Joined {location} chat service as {:?}", self.name);
+        self.status = format!(
+            "In #{DEFAULT_CHANNEL} on the {location} chat as {}",
+            self.name
+        );
+
+        self.connection = Some(Connection {
+            hub,
+            sender,
+            messages,
+        });
+    }
+
+    /// Adds a message to the bottom of the shown history.
+    fn push(&mut self, msg: &ChatMessage) {
+        let text = format!("{}: {}", msg.sender, msg.text);
+        let chars: Vec<char> = text.chars().collect();
+
+        for line in chars.chunks(LINE_WIDTH) {
+            self.history.push_back(line.iter().collect());
+        }
+
+        while self.history.len() > HISTORY_LINES {
+            self.history.pop_front();
+        }
+    }
+}
+
+impl Page for Chat {
+    fn title(&self) -> &str {
+        "Chat"
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let padding = HISTORY_LINES - self.history.len();
+        let mut lines: Vec<Line> = (0..padding).map(|_| Line::new("")).collect();
+        lines.extend(self.history.iter().map(|line| Line::new(line.as_str())));
+
+        // show the end of the input if it's too long to fit
+        let input: String = {
+            let chars: Vec<char> = self.input.chars().collect();
+            let skip = chars.len().saturating_sub(LINE_WIDTH - 3);
+            chars[skip..].iter().collect()
+        };
+
+        lines.push(Line::selected(format!("> {input}_"), true));
+        lines.push(Line::new(self.status.as_str()));
+        lines
+    }
+
+    fn on_key(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            VirtualKeyCode::Back => {
+                self.input.pop();
+            }
+            VirtualKeyCode::Return if !is_valid_text(&self.input) => {}
+            VirtualKeyCode::Return => match self.connection.as_ref() {
+                Some(connection) => {
+                    connection.sender.send(DEFAULT_CHANNEL, self.input.trim());
+                    self.input.clear();
+                }
+                None => self.status = "Not connected to a chat service".into(),
+            },
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn on_char(&mut self, c: char) -> bool {
+        if c.is_control() || self.input.chars().count() >= MAX_TEXT_LEN {
+            return false;
+        }
+
+        self.input.push(c);
+        true
+    }
+
+    fn mailbox(&self) -> Option<&Mailbox> {
+        self.connection
+            .as_ref()
+            .map(|connection| &connection.messages)
+    }
+
+    fn on_signal(&mut self, signal: Signal) -> bool {
+        let msg = match signal {
+            Signal::Message(msg) => msg,
+            Signal::Down { .. } => {
+                warn!("Chat service became unavailable");
+                self.connection = None;
+                self.status = "Lost the chat service".into();
+                self.retry = 0.0;
+                return true;
+            }
+        };
+
+        match codec::decode(&msg.data) {
+            Ok(msg) => {
+                self.push(&msg);
+                true
+            }
+            Err(err) => {
+                warn!("invalid chat message: {err:?}");
+                false
+            }
+        }
+    }
+
+    fn update(&mut self, dt: f32) -> bool {
+        if let Some(connection) = self.connection.as_ref() {
+            if connection.hub.is_remote() {
+                return false;
+            }
+        }
+
+        self.retry -= dt;
+        if self.retry > 0.0 {
+            return false;
+        }
+
+        self.retry = RETRY_INTERVAL;
+
+        if let Some(hub) = ChatHub::find_remote() {
+            self.connect(hub);
+            return true;
+        }
+
+        if self.connection.is_none() {
+            if let Some(hub) = ChatHub::find() {
+                self.connect(hub);
+                return true;
+            }
+        }
+
+        false
+    }
+}
@@ -0,0 +1,355 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The default home space: a welcome panel, a settings panel, a panel for
+//! joining servers, a chat panel, a frame profiler panel, and a pair of
+//! terminals, all driven by the keyboard.
+
+use std::collections::HashMap;
+
+use hearth_guest::{
+    codec,
+    renderer::RendererEvent,
+    terminal::TerminalState,
+    window::{ElementState, VirtualKeyCode, WindowEvent},
+    Color, Lump, LumpId, Mailbox, Signal,
+};
+use kindling_host::{
+    prelude::{
+        glam::{vec2, vec3, Mat4, Quat, Vec3},
+        *,
+    },
+    renderer,
+};
+
+use chat::Chat;
+use pages::{Connect, Settings, Welcome};
+use panel::{Page, Panel};
+use profiler::Profiler;
+
+mod chat;
+mod pages;
+mod panel;
+mod profiler;
+
+hearth_guest::export_metadata!();
+
+/// The height of the panels' centers above the ground.
+const PANEL_HEIGHT: f32 = 2.6;
+
+/// The height of the terminals' centers above the ground.
+const TERMINAL_HEIGHT: f32 = 1.0;
+
+/// Something in the home space that can take keyboard input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Panel(usize),
+    Terminal(usize),
+}
+
+impl Focus {
+    /// Gets the focus target for a function key.
+    fn from_key(key: VirtualKeyCode) -> Option<Self> {
+        match key {
+            VirtualKeyCode::F1 => Some(Focus::Panel(0)),
+            VirtualKeyCode::F2 => Some(Focus::Panel(1)),
+            VirtualKeyCode::F3 => Some(Focus::Panel(2)),
+            VirtualKeyCode::F4 => Some(Focus::Terminal(0)),
+            VirtualKeyCode::F5 => Some(Focus::Terminal(1)),
+            VirtualKeyCode::F6 => Some(Focus::Panel(3)),
+            VirtualKeyCode::F7 => Some(Focus::Panel(4)),
+            _ => None,
+        }
+    }
+}
+
+struct Home {
+    font: LumpId,
+    pages: Vec<Box<dyn Page>>,
+    panels: Vec<Panel>,
+    terminals: Vec<Terminal>,
+    focus: Focus,
+}
+
+impl Home {
+    fn new(font: LumpId) -> Self {
+        let mut home = Self {
+            font,
+            pages: vec![
+                Box::new(Welcome),
+                Box::new(Settings::default()),
+                Box::new(Connect::default()),
+                Box::new(Chat::new()),
+                Box::new(Profiler::new()),
+            ],
+            panels: Vec::new(),
+            terminals: Vec::new(),
+            focus: Focus::Panel(0),
+        };
+
+        home.build();
+        home
+    }
+
+    /// Creates every panel and terminal and points the camera at them.
+    ///
+    /// This is also used to re-create them after the renderer has lost them.
+    fn build(&mut self) {
+        // welcome in the middle, with the others turned towards the camera
+        // and chat and the profiler beside the terminals
+        let placements = [
+            (0.0, PANEL_HEIGHT, 0.0, 0.0),
+            (-2.2, PANEL_HEIGHT, 0.4, 0.35),
+            (2.2, PANEL_HEIGHT, 0.4, -0.35),
+            (3.3, TERMINAL_HEIGHT, 0.9, -0.6),
+            (-3.3, TERMINAL_HEIGHT, 0.9, 0.6),
+        ];
+
+        self.panels = placements
+            .into_iter()
+            .map(|(x, y, z, yaw)| Panel::new(self.font, vec3(x, y, z), yaw))
+            .collect();
+
+        let palettes = [Palette::rose_pine(), Palette::gruvbox_material()];
+        self.terminals = palettes
+            .into_iter()
+            .enumerate()
+            .map(|(index, palette)| {
+                Terminal::new(TerminalState {
+                    position: vec3(index as f32 * 2.2 - 1.1, TERMINAL_HEIGHT, 0.0),
+                    orientation: Quat::IDENTITY,
+                    half_size: vec2(1.0, 0.75),
+                    opacity: 1.0,
+                    padding: Default::default(),
+                    units_per_em: 0.05,
+                    colors: palette.to_ansi(),
+                })
+            })
+            .collect();
+
+        for index in 0..self.panels.len() {
+            self.redraw(index);
+        }
+
+        MAIN_WINDOW.set_camera(
+            90.0,
+            0.01,
+            Mat4::look_at_rh(vec3(0.0, 1.8, 4.0), vec3(0.0, 1.8, 0.0), Vec3::Y),
+        );
+    }
+
+    fn redraw(&self, index: usize) {
+        let focused = self.focus == Focus::Panel(index);
+        self.panels[index].draw(self.pages[index].as_ref(), focused);
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        let last = std::mem::replace(&mut self.focus, focus);
+
+        for focus in [last, focus] {
+            if let Focus::Panel(index) = focus {
+                self.redraw(index);
+            }
+        }
+    }
+
+    /// Updates every page once per frame.
+    fn update(&mut self, dt: f32) {
+        for index in 0..self.pages.len() {
+            if self.pages[index].update(dt) {
+                self.redraw(index);
+            }
+        }
+    }
+
+    fn on_page_signal(&mut self, index: usize, signal: Signal) {
+        if self.pages[index].on_signal(signal) {
+            self.redraw(index);
+        }
+    }
+
+    fn on_key(&mut self, key: VirtualKeyCode) {
+        if let Some(focus) = Focus::from_key(key) {
+            self.set_focus(focus);
+            return;
+        }
+
+        match self.focus {
+            Focus::Panel(index) => {
+                if self.pages[index].on_key(key) {
+                    self.redraw(index);
+                }
+            }
+            Focus::Terminal(index) => {
+                // typed text arrives as characters, but arrow keys don't
+                let escape = match key {
+                    VirtualKeyCode::Up => "\x1b[A",
+                    VirtualKeyCode::Down => "\x1b[B",
+                    VirtualKeyCode::Right => "\x1b[C",
+                    VirtualKeyCode::Left => "\x1b[D",
+                    _ => return,
+                };
+
+                self.terminals[index].input(escape.into());
+            }
+        }
+    }
+
+    fn on_char(&mut self, c: char) {
+        match self.focus {
+            Focus::Panel(index) => {
+                if self.pages[index].on_char(c) {
+                    self.redraw(index);
+                }
+            }
+            Focus::Terminal(index) => {
+                // terminals erase on DEL rather than backspace
+                let c = if c == '\u{8}' { '\x7f' } else { c };
+                self.terminals[index].input(c.into());
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    let font = include_bytes!("../../../../resources/mononoki/mononoki-Regular.ttf");
+    let font = Lump::load_raw(font);
+    let mut home = Home::new(font.get_id());
+
+    let window = MAIN_WINDOW.subscribe();
+    let renderer = renderer::subscribe_events();
+
+    loop {
+        let mut mailboxes = vec![&window, &renderer];
+        let mut owners = Vec::new();
+        for (index, page) in home.pages.iter().enumerate() {
+            if let Some(mailbox) = page.mailbox() {
+                mailboxes.push(mailbox);
+                owners.push(index);
+            }
+        }
+
+        let (index, signal) = Mailbox::poll(&mailboxes);
+        drop(mailboxes);
+
+        if index > 1 {
+            home.on_page_signal(owners[index - 2], signal);
+            continue;
+        }
+
+        let Signal::Message(msg) = signal else {
+            continue;
+        };
+
+        if index == 1 {
+            if let Ok(RendererEvent::DeviceRestored) = codec::decode(&msg.data) {
+                info!("Re-creating the home space on the restored renderer");
+                home.build();
+            }
+
+            continue;
+        }
+
+        match codec::decode(&msg.data) {
+            Ok(WindowEvent::KeyboardInput { input, .. }) => {
+                if let (ElementState::Pressed, Some(key)) = (input.state, input.virtual_keycode) {
+                    home.on_key(key);
+                }
+            }
+            Ok(WindowEvent::ReceivedCharacter(c)) => home.on_char(c),
+            Ok(WindowEvent::Redraw { dt }) => home.update(dt),
+            _ => {}
+        }
+    }
+}
+
+/// Helper struct for containing and identifying terminal colors.
+struct Palette {
+    pub bg: Color,
+    pub fg: Color,
+    pub black: Color,
+    pub red: Color,
+    pub green: Color,
+    pub yellow: Color,
+    pub blue: Color,
+    pub magenta: Color,
+    pub cyan: Color,
+    pub white: Color,
+}
+
+/// Shorthand color initialization. Fixes alpha to 0xff.
+fn c(rgb: u32) -> Color {
+    Color(0xff000000 | rgb)
+}
+
+impl Palette {
+    /// Convert a palette into a standard terminal color map.
+    pub fn to_ansi(&self) -> HashMap<usize, Color> {
+        FromIterator::from_iter([
+            (0x0, self.black),   // black
+            (0x1, self.red),     // red
+            (0x2, self.green),   // green
+            (0x3, self.yellow),  // yellow
+            (0x4, self.blue),    // blue
+            (0x5, self.magenta), // magenta
+            (0x6, self.cyan),    // cyan
+            (0x7, self.white),   // white
+            (0x8, self.black),   // bright black
+            (0x9, self.red),     // bright red
+            (0xA, self.green),   // bright green
+            (0xB, self.yellow),  // bright yellow
+            (0xC, self.blue),    // bright blue
+            (0xD, self.magenta), // bright magenta
+            (0xE, self.cyan),    // bright cyan
+            (0xF, self.white),   // bright white
+            (0x100, self.fg),    // foreground
+            (0x101, self.bg),    // background
+        ])
+    }
+
+    pub fn rose_pine() -> Self {
+        Self {
+            bg: c(0x191724),
+            fg: c(0xe0def4),
+            black: c(0x26233a),
+            red: c(0xeb6f92),
+            green: c(0x31748f),
+            yellow: c(0xf6c177),
+            blue: c(0x9ccfd8),
+            magenta: c(0xc4a7e7),
+            cyan: c(0xebbcba),
+            white: c(0xe0def4),
+        }
+    }
+
+    pub fn gruvbox_material() -> Self {
+        Self {
+            bg: c(0x1d2021),
+            fg: c(0xd4be98),
+            black: c(0x504945),
+            red: c(0xea6962),
+            green: c(0xa9b665),
+            yellow: c(0xd8a657),
+            blue: c(0x7daea3),
+            magenta: c(0xd3869b),
+            cyan: c(0x89b482),
+            white: c(0xddc7a1),
+        }
+    }
+}
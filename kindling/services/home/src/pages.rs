@@ -0,0 +1,237 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The pages shown on the home space's panels.
+
+use hearth_guest::{
+    renderer::{BloomSettings, PostProcessSettings, RenderSettings},
+    window::VirtualKeyCode,
+};
+use kindling_host::{prelude::*, renderer};
+
+use crate::panel::{Line, Page};
+
+/// Introduces Hearth and lists the home space's controls.
+pub struct Welcome;
+
+impl Page for Welcome {
+    fn title(&self) -> &str {
+        "Welcome to Hearth"
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        [
+            "This is your home space. Everything here",
+            "is a Kindling service that you can change.",
+            "",
+            "F1-F3      focus this, settings, or connect",
+            "F4, F5     focus a terminal",
+            "F6         focus chat",
+            "F7         focus the frame profiler",
+            "Up, Down   select a setting",
+            "Left/Right change the selected setting",
+            "Enter      confirm",
+        ]
+        .into_iter()
+        .map(Line::new)
+        .collect()
+    }
+}
+
+/// A setting that can be changed from the [Settings] page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Setting {
+    Msaa,
+    ResolutionScale,
+    Vsync,
+    Fxaa,
+    Bloom,
+}
+
+impl Setting {
+    const ALL: [Setting; 5] = [
+        Setting::Msaa,
+        Setting::ResolutionScale,
+        Setting::Vsync,
+        Setting::Fxaa,
+        Setting::Bloom,
+    ];
+}
+
+/// The resolution scales that can be chosen, in order.
+const RESOLUTION_SCALES: [f32; 5] = [0.5, 0.75, 1.0, 1.5, 2.0];
+
+/// Changes graphics settings and lists keybindings.
+///
+/// The renderer doesn't report its current settings, so this page starts
+/// from the defaults and only applies settings once they are changed here.
+#[derive(Default)]
+pub struct Settings {
+    render: RenderSettings,
+    post: PostProcessSettings,
+    selected: usize,
+}
+
+impl Settings {
+    /// Changes the selected setting by one step and applies it.
+    fn step(&mut self, forward: bool) {
+        match Setting::ALL[self.selected] {
+            Setting::Msaa => {
+                self.render.msaa_samples = if self.render.msaa_samples > 1 { 1 } else { 4 };
+            }
+            Setting::ResolutionScale => {
+                let current = RESOLUTION_SCALES
+                    .iter()
+                    .position(|scale| *scale >= self.render.resolution_scale)
+                    .unwrap_or(RESOLUTION_SCALES.len() - 1);
+
+                let next = if forward {
+                    (current + 1).min(RESOLUTION_SCALES.len() - 1)
+                } else {
+                    current.saturating_sub(1)
+                };
+
+                self.render.resolution_scale = RESOLUTION_SCALES[next];
+            }
+            Setting::Vsync => self.render.vsync = !self.render.vsync,
+            Setting::Fxaa => self.post.fxaa = !self.post.fxaa,
+            Setting::Bloom => {
+                self.post.bloom = match self.post.bloom {
+                    Some(_) => None,
+                    None => Some(BloomSettings::default()),
+                };
+            }
+        }
+
+        match Setting::ALL[self.selected] {
+            Setting::Msaa | Setting::ResolutionScale | Setting::Vsync => {
+                renderer::set_render_settings(self.render.clone());
+            }
+            Setting::Fxaa | Setting::Bloom => {
+                renderer::set_post_processing(self.post.clone());
+            }
+        }
+    }
+
+    fn describe(&self, setting: Setting) -> String {
+        let on_off = |on| if on { "on" } else { "off" };
+
+        match setting {
+            Setting::Msaa => format!("MSAA            {}x", self.render.msaa_samples.max(1)),
+            Setting::ResolutionScale => {
+                format!(
+                    "Resolution      {:.0}%",
+                    self.render.resolution_scale * 100.0
+                )
+            }
+            Setting::Vsync => format!("Vsync           {}", on_off(self.render.vsync)),
+            Setting::Fxaa => format!("FXAA            {}", on_off(self.post.fxaa)),
+            Setting::Bloom => format!("Bloom           {}", on_off(self.post.bloom.is_some())),
+        }
+    }
+}
+
+impl Page for Settings {
+    fn title(&self) -> &str {
+        "Settings"
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let mut lines = vec![Line::new("Graphics")];
+
+        for (index, setting) in Setting::ALL.into_iter().enumerate() {
+            let text = format!("  {}", self.describe(setting));
+            lines.push(Line::selected(text, index == self.selected));
+        }
+
+        lines.push(Line::new("Audio"));
+        lines.push(Line::new("  No audio service is available"));
+        lines.push(Line::new("Keybindings"));
+        lines.push(Line::new("  See the welcome panel (F1)"));
+        lines
+    }
+
+    fn on_key(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            VirtualKeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            VirtualKeyCode::Down => self.selected = (self.selected + 1).min(Setting::ALL.len() - 1),
+            VirtualKeyCode::Left => self.step(false),
+            VirtualKeyCode::Right | VirtualKeyCode::Return => self.step(true),
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+/// The longest server address that can be typed.
+const MAX_ADDRESS_LEN: usize = 64;
+
+/// Prepares a command to join a server.
+///
+/// Clients choose their server when they start, so joining from here copies
+/// the command to restart the client connected to the typed address.
+#[derive(Default)]
+pub struct Connect {
+    address: String,
+    status: String,
+}
+
+impl Page for Connect {
+    fn title(&self) -> &str {
+        "Connect to a server"
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        vec![
+            Line::new("Type a server address and press Enter:"),
+            Line::selected(format!("> {}_", self.address), true),
+            Line::new(""),
+            Line::new(self.status.as_str()),
+        ]
+    }
+
+    fn on_key(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            VirtualKeyCode::Back => {
+                self.address.pop();
+            }
+            VirtualKeyCode::Return if self.address.is_empty() => {
+                self.status = "Enter an address first".into();
+            }
+            VirtualKeyCode::Return => {
+                let command = format!("hearth-client --server {}", self.address);
+                info!("Copying connect command: {command}");
+                MAIN_WINDOW.set_clipboard(command);
+                self.status = "Copied. Restart the client with it to join.".into();
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn on_char(&mut self, c: char) -> bool {
+        if c.is_control() || c.is_whitespace() || self.address.len() >= MAX_ADDRESS_LEN {
+            return false;
+        }
+
+        self.address.push(c);
+        true
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! In-world text panels.
+
+use hearth_guest::{canvas::*, window::VirtualKeyCode, LumpId, Mailbox, Signal};
+use kindling_host::prelude::{
+    glam::{vec2, Quat, Vec3},
+    *,
+};
+
+/// The size of each panel's canvas in pixels.
+const PANEL_WIDTH: u32 = 512;
+const PANEL_HEIGHT: u32 = 384;
+
+/// The world-space half-width of each panel.
+const PANEL_HALF_WIDTH: f32 = 1.0;
+
+/// The height of the title bar in pixels.
+const HEADER_HEIGHT: u32 = 48;
+
+/// The distance between lines of text in pixels.
+const LINE_SPACING: u32 = 28;
+
+/// The distance between the edges of the panel and its text in pixels.
+const MARGIN: u32 = 16;
+
+const BACKGROUND: [u8; 4] = [0x19, 0x17, 0x24, 0xff];
+const HEADER: [u8; 4] = [0x26, 0x23, 0x3a, 0xff];
+const FOCUSED_HEADER: [u8; 4] = [0x31, 0x74, 0x8f, 0xff];
+const SELECTED: [u8; 4] = [0x40, 0x3d, 0x52, 0xff];
+const TEXT: [u8; 4] = [0xe0, 0xde, 0xf4, 0xff];
+
+/// A line of text on a panel.
+pub struct Line {
+    pub text: String,
+
+    /// Whether this line is drawn as the panel's current selection.
+    pub selected: bool,
+}
+
+impl Line {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            selected: false,
+        }
+    }
+
+    pub fn selected(text: impl Into<String>, selected: bool) -> Self {
+        Self {
+            text: text.into(),
+            selected,
+        }
+    }
+}
+
+/// The contents of a panel.
+pub trait Page {
+    /// Gets the title shown in this page's title bar.
+    fn title(&self) -> &str;
+
+    /// Gets the lines of text on this page.
+    fn lines(&self) -> Vec<Line>;
+
+    /// Handles a key press while this page's panel is focused. Returns true
+    /// if the page needs to be redrawn.
+    fn on_key(&mut self, _key: VirtualKeyCode) -> bool {
+        false
+    }
+
+    /// Handles typed text while this page's panel is focused. Returns true
+    /// if the page needs to be redrawn.
+    fn on_char(&mut self, _c: char) -> bool {
+        false
+    }
+
+    /// Gets a mailbox whose signals this page handles, if any.
+    fn mailbox(&self) -> Option<&Mailbox> {
+        None
+    }
+
+    /// Handles a signal received by [Self::mailbox]. Returns true if the
+    /// page needs to be redrawn.
+    fn on_signal(&mut self, _signal: Signal) -> bool {
+        false
+    }
+
+    /// Updates this page once per frame, with the time since the last frame
+    /// in seconds. Returns true if the page needs to be redrawn.
+    fn update(&mut self, _dt: f32) -> bool {
+        false
+    }
+}
+
+/// A canvas in the world that shows a [Page].
+pub struct Panel {
+    canvas: Canvas,
+    font: LumpId,
+}
+
+impl Panel {
+    /// Creates an empty panel centered on a point and turned by `yaw`
+    /// radians.
+    pub fn new(font: LumpId, origin: Vec3, yaw: f32) -> Self {
+        let aspect = PANEL_HEIGHT as f32 / PANEL_WIDTH as f32;
+
+        let position = Position {
+            origin,
+            orientation: Quat::from_rotation_y(yaw),
+            half_size: vec2(PANEL_HALF_WIDTH, PANEL_HALF_WIDTH * aspect),
+        };
+
+        Self {
+            canvas: Canvas::new(position, Self::fill(false), CanvasSamplingMode::Linear),
+            font,
+        }
+    }
+
+    /// Redraws this panel with the contents of a page.
+    pub fn draw(&self, page: &dyn Page, focused: bool) {
+        self.canvas.update(Self::fill(focused));
+
+        let header = if focused { FOCUSED_HEADER } else { HEADER };
+        self.draw_text(MARGIN, 8, 32.0, header, page.title().to_string());
+
+        let top = HEADER_HEIGHT + MARGIN / 2;
+        for (index, line) in page.lines().into_iter().enumerate() {
+            let y = top + index as u32 * LINE_SPACING;
+            if y + LINE_SPACING > PANEL_HEIGHT {
+                warn!("{:?} panel has too many lines to draw", page.title());
+                break;
+            }
+
+            let background = if line.selected { SELECTED } else { BACKGROUND };
+            self.draw_text(MARGIN, y, 22.0, background, line.text);
+        }
+    }
+
+    fn draw_text(&self, x: u32, y: u32, size: f32, background: [u8; 4], text: String) {
+        self.canvas.draw_text(TextDraw {
+            x,
+            y,
+            font: self.font,
+            size,
+            color: TEXT,
+            background,
+            text,
+        });
+    }
+
+    /// Creates the blank pixels of a panel with its title bar.
+    fn fill(focused: bool) -> Pixels {
+        let header = if focused { FOCUSED_HEADER } else { HEADER };
+        let row = |color: [u8; 4]| color.into_iter().cycle().take(PANEL_WIDTH as usize * 4);
+
+        let data = (0..PANEL_HEIGHT)
+            .flat_map(|y| {
+                row(if y < HEADER_HEIGHT {
+                    header
+                } else {
+                    BACKGROUND
+                })
+            })
+            .collect();
+
+        Pixels {
+            width: PANEL_WIDTH,
+            height: PANEL_HEIGHT,
+            data,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A panel page that graphs the renderer's recent frame timings.
+
+use std::collections::HashMap;
+
+use hearth_guest::profiler::{FrameProfile, ProfilerReport, Timing};
+use kindling_host::profiler::get_report;
+
+use crate::panel::{Line, Page};
+
+/// How often to fetch a new report, in seconds.
+const REFRESH_INTERVAL: f32 = 0.5;
+
+/// The number of frames that each report covers.
+const REPORT_FRAMES: u32 = 120;
+
+/// The frame time that fills a bar or the top of the graph, in milliseconds.
+const BUDGET: f32 = 1000.0 / 60.0;
+
+/// The number of characters in the frame time graph.
+const GRAPH_WIDTH: usize = 42;
+
+/// The characters of the frame time graph, from shortest to tallest.
+const GRAPH_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// The number of characters in a full timing bar.
+const BAR_WIDTH: usize = 16;
+
+/// The number of GPU passes and CPU steps listed.
+const TOP_TIMINGS: usize = 3;
+
+/// Shows the average and worst frame times, a graph of recent frame times,
+/// and the most expensive GPU passes and CPU steps.
+pub struct Profiler {
+    report: Option<ProfilerReport>,
+    since_refresh: f32,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            report: None,
+            since_refresh: REFRESH_INTERVAL,
+        }
+    }
+
+    /// Draws a graph of frame times, one character per group of frames.
+    fn graph(frames: &[FrameProfile]) -> String {
+        let group = frames.len().div_ceil(GRAPH_WIDTH).max(1);
+        let top = GRAPH_RAMP.len() - 1;
+
+        frames
+            .chunks(group)
+            .map(|frames| {
+                let worst = frames.iter().map(|f| f.frame_time).fold(0.0, f32::max);
+                let level = (worst / (BUDGET * 2.0) * top as f32).ceil() as usize;
+                GRAPH_RAMP[level.min(top)] as char
+            })
+            .collect()
+    }
+
+    /// Lists the most expensive timings averaged over every frame they're in.
+    fn top(timings: Vec<&Timing>, frames: usize) -> Vec<Line> {
+        let mut totals: HashMap<&str, f32> = HashMap::new();
+        for timing in timings {
+            *totals.entry(timing.name.as_str()).or_default() += timing.time;
+        }
+
+        let mut averages: Vec<_> = totals
+            .into_iter()
+            .map(|(name, total)| (name, total / frames.max(1) as f32))
+            .collect();
+
+        averages.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        averages
+            .into_iter()
+            .take(TOP_TIMINGS)
+            .map(|(name, time)| {
+                let bar = ((time / BUDGET * BAR_WIDTH as f32).ceil() as usize).min(BAR_WIDTH);
+                let name: String = name.chars().take(16).collect();
+                Line::new(format!("{name:<16}{time:>7.2} {}", "#".repeat(bar)))
+            })
+            .collect()
+    }
+}
+
+impl Page for Profiler {
+    fn title(&self) -> &str {
+        "Frame Profiler"
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let Some(report) = self.report.as_ref() else {
+            return vec![Line::new("Waiting for frames...")];
+        };
+
+        let frames = &report.frames;
+        if frames.is_empty() {
+            return vec![Line::new("No frames have been drawn yet.")];
+        }
+
+        let count = frames.len() as f32;
+        let average = frames.iter().map(|f| f.frame_time).sum::<f32>() / count;
+        let worst = frames.iter().map(|f| f.frame_time).fold(0.0, f32::max);
+        let cpu = frames.iter().map(|f| f.cpu_time).sum::<f32>() / count;
+        let fps = if average > 0.0 { 1000.0 / average } else { 0.0 };
+
+        let mut lines = vec![
+            Line::new(format!("frame {average:>6.2} ms avg   {fps:>5.1} fps")),
+            Line::new(format!("worst {worst:>6.2} ms      cpu {cpu:>5.2} ms")),
+            Line::new(Self::graph(frames)),
+        ];
+
+        let gpu: Vec<_> = frames.iter().filter_map(|f| f.gpu.as_ref()).collect();
+        if !report.gpu_timing {
+            lines.push(Line::new("gpu timing off (--gpu-timing)"));
+        } else {
+            lines.push(Line::new("gpu passes (avg ms)"));
+            let timings = gpu.iter().flat_map(|timings| timings.iter()).collect();
+            lines.extend(Self::top(timings, gpu.len()));
+        }
+
+        lines.push(Line::new("cpu steps (avg ms)"));
+        let timings = frames.iter().flat_map(|f| f.cpu.iter()).collect();
+        lines.extend(Self::top(timings, frames.len()));
+
+        lines
+    }
+
+    fn update(&mut self, dt: f32) -> bool {
+        self.since_refresh += dt;
+        if self.since_refresh < REFRESH_INTERVAL {
+            return false;
+        }
+
+        self.since_refresh = 0.0;
+        self.report = Some(get_report(REPORT_FRAMES));
+        true
+    }
+}
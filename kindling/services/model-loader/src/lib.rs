@@ -0,0 +1,121 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `rs.hearth.kindling.ModelLoader` service: imports OBJ, STL, and PLY
+//! model files into renderer mesh and material lumps.
+//!
+//! Each part of a model with its own material becomes a separate mesh. The
+//! renderer's materials only have an albedo texture, so material colors are
+//! baked into it. Vertex colors are kept in the meshes.
+
+use std::collections::HashMap;
+
+use hearth_guest::{codec, Lump, LumpId, Signal, PARENT};
+use kindling_host::prelude::*;
+use kindling_schema::model::*;
+
+use mesh::Part;
+
+mod mesh;
+mod obj;
+mod ply;
+mod stl;
+
+hearth_guest::export_metadata!();
+
+/// Imports a model.
+fn load(
+    format: ModelFormat,
+    model: LumpId,
+    resources: HashMap<String, LumpId>,
+) -> Result<Model, ModelError> {
+    let resource = |name: &str| -> Result<Vec<u8>, ModelError> {
+        let id = resources
+            .get(name)
+            .ok_or_else(|| ModelError::MissingResource(name.to_string()))?;
+
+        Ok(Lump::load_by_id(id).get_data())
+    };
+
+    let data = Lump::load_by_id(&model).get_data();
+    let parts = match format {
+        ModelFormat::Obj => obj::parse(&data, resource)?,
+        ModelFormat::Stl => vec![stl::parse(&data)?],
+        ModelFormat::Ply => vec![ply::parse(&data)?],
+    };
+
+    let mut meshes = Vec::new();
+    for Part {
+        name,
+        mesh,
+        material,
+    } in parts
+    {
+        if mesh.is_empty() {
+            continue;
+        }
+
+        let texture = material.texture.as_deref().map(resource).transpose()?;
+        let material = material.load(texture.as_deref())?;
+        let mesh = Lump::load(&mesh.build());
+
+        meshes.push(ModelMesh {
+            name,
+            mesh: mesh.get_id(),
+            material: material.get_id(),
+        });
+    }
+
+    if meshes.is_empty() {
+        return Err(ModelError::Empty);
+    }
+
+    Ok(Model { meshes })
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    loop {
+        let Signal::Message(msg) = PARENT.recv_signal() else {
+            continue;
+        };
+
+        let Some(reply) = msg.caps.first() else {
+            warn!("model request is missing a reply capability");
+            continue;
+        };
+
+        let response: ModelResponse = match codec::decode(&msg.data) {
+            Ok(ModelRequest::Load {
+                format,
+                model,
+                resources,
+            }) => load(format, model, resources),
+            Err(err) => {
+                warn!("invalid model request: {err:?}");
+                continue;
+            }
+        };
+
+        if let Err(err) = response.as_ref() {
+            debug!("failed to load model: {err:?}");
+        }
+
+        reply.send(&response, &[]);
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Conversion of imported geometry and materials into renderer lumps, shared
+//! by every format.
+
+use hearth_guest::{renderer::*, ByteVec, Lump};
+use kindling_host::prelude::glam::{UVec2, Vec2, Vec3, Vec4};
+use kindling_schema::model::*;
+
+/// Builds a mesh one vertex and triangle at a time.
+#[derive(Default)]
+pub struct MeshBuilder {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    colors: Vec<[u8; 4]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// Adds a vertex and returns its index.
+    ///
+    /// Vertices with a zero normal are given smooth normals from the
+    /// triangles that use them when the mesh is built.
+    pub fn push_vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2, color: [u8; 4]) -> u32 {
+        let index = self.positions.len() as u32;
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.colors.push(color);
+        index
+    }
+
+    /// Adds a triangle. Triangles with out-of-range indices are ignored.
+    pub fn push_triangle(&mut self, triangle: [u32; 3]) {
+        let len = self.positions.len() as u32;
+        if triangle.iter().all(|index| *index < len) {
+            self.indices.extend(triangle);
+        }
+    }
+
+    /// Adds a polygon as a fan of triangles around its first vertex.
+    pub fn push_polygon(&mut self, polygon: &[u32]) {
+        for pair in polygon.windows(2).skip(1) {
+            self.push_triangle([polygon[0], pair[0], pair[1]]);
+        }
+    }
+
+    /// Returns true if this mesh has no triangles.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Finishes this mesh.
+    pub fn build(mut self) -> MeshData {
+        self.fill_normals();
+
+        let len = self.positions.len();
+        MeshData {
+            positions: ByteVec(self.positions),
+            normals: ByteVec(self.normals),
+            tangents: ByteVec(vec![Vec3::X; len]),
+            uv0: ByteVec(self.uvs),
+            uv1: ByteVec(vec![Vec2::ZERO; len]),
+            colors: ByteVec(self.colors),
+            joint_indices: ByteVec(vec![[0; 4]; len]),
+            joint_weights: ByteVec(vec![Vec4::ZERO; len]),
+            indices: ByteVec(self.indices),
+        }
+    }
+
+    /// Gives every vertex without a normal the average normal of the
+    /// triangles that use it.
+    fn fill_normals(&mut self) {
+        let missing: Vec<bool> = self.normals.iter().map(|n| *n == Vec3::ZERO).collect();
+        if !missing.contains(&true) {
+            return;
+        }
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            let [pa, pb, pc] = [a, b, c].map(|i| self.positions[i]);
+
+            // unnormalized, so that larger triangles weigh more
+            let normal = (pb - pa).cross(pc - pa);
+
+            for index in [a, b, c] {
+                if missing[index] {
+                    self.normals[index] += normal;
+                }
+            }
+        }
+
+        for (normal, missing) in self.normals.iter_mut().zip(missing) {
+            if missing {
+                *normal = normal.try_normalize().unwrap_or(Vec3::Y);
+            }
+        }
+    }
+}
+
+/// A part of a model with a single material.
+#[derive(Default)]
+pub struct Part {
+    /// The name of the part's material, if it has one.
+    pub name: Option<String>,
+    pub mesh: MeshBuilder,
+    pub material: MaterialDesc,
+}
+
+/// A material as described by a model file.
+#[derive(Clone, Debug)]
+pub struct MaterialDesc {
+    /// The base color, which tints the texture.
+    pub color: [f32; 4],
+
+    /// The name of the texture resource, if any.
+    pub texture: Option<String>,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 4],
+            texture: None,
+        }
+    }
+}
+
+impl MaterialDesc {
+    /// Creates this material's lump, given the data of its texture.
+    ///
+    /// The renderer's materials only have an albedo texture, so the base
+    /// color is baked into it.
+    pub fn load(&self, texture: Option<&[u8]>) -> Result<Lump, ModelError> {
+        let mut albedo = match (texture, self.texture.as_ref()) {
+            (Some(data), Some(name)) => {
+                let image = image::load_from_memory(data).map_err(|err| ModelError::Texture {
+                    name: name.clone(),
+                    message: err.to_string(),
+                })?;
+
+                let image = image.into_rgba8();
+                TextureData {
+                    label: Some(name.clone()),
+                    size: UVec2::new(image.width(), image.height()),
+                    data: image.into_raw(),
+                }
+            }
+            _ => TextureData {
+                label: None,
+                size: UVec2::ONE,
+                data: vec![0xff; 4],
+            },
+        };
+
+        if self.color != [1.0; 4] {
+            for pixel in albedo.data.chunks_exact_mut(4) {
+                for (channel, factor) in pixel.iter_mut().zip(self.color) {
+                    *channel = (*channel as f32 * factor.clamp(0.0, 1.0)).round() as u8;
+                }
+            }
+        }
+
+        let albedo = Lump::load(&albedo);
+        Ok(Lump::load(&MaterialData {
+            albedo: albedo.get_id(),
+        }))
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Wavefront OBJ and MTL import.
+
+use std::collections::HashMap;
+
+use kindling_host::prelude::glam::{Vec2, Vec3};
+use kindling_schema::model::ModelError;
+
+use crate::mesh::{MaterialDesc, Part};
+
+/// The indices of a face vertex's position, texture coordinate, and normal.
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+/// Parses an OBJ file into one part for each material it uses.
+///
+/// MTL libraries are read with `resource`. Texture coordinates are flipped
+/// vertically, since OBJ puts their origin at the bottom of the texture.
+pub fn parse(
+    data: &[u8],
+    resource: impl Fn(&str) -> Result<Vec<u8>, ModelError>,
+) -> Result<Vec<Part>, ModelError> {
+    let text = std::str::from_utf8(data).map_err(|err| ModelError::Parse(err.to_string()))?;
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut materials = HashMap::new();
+
+    let mut parts = vec![Part::default()];
+    let mut vertices: Vec<HashMap<VertexKey, u32>> = vec![HashMap::new()];
+    let mut current = 0;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let error =
+            |message: &str| ModelError::Parse(format!("line {}: {message}", line_index + 1));
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match keyword {
+            "v" => {
+                let values = parse_floats(rest).ok_or_else(|| error("invalid vertex"))?;
+                let position = to_vec3(&values).ok_or_else(|| error("too few coordinates"))?;
+                positions.push(position);
+
+                // a common extension puts vertex colors after the position
+                let color = match values.get(3..6) {
+                    Some(&[r, g, b]) => [r, g, b, 1.0].map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8),
+                    _ => [0xff; 4],
+                };
+
+                colors.push(color);
+            }
+            "vt" => {
+                let values =
+                    parse_floats(rest).ok_or_else(|| error("invalid texture coordinate"))?;
+                let u = *values.first().ok_or_else(|| error("too few coordinates"))?;
+                let v = values.get(1).copied().unwrap_or(0.0);
+                uvs.push(Vec2::new(u, 1.0 - v));
+            }
+            "vn" => {
+                let values = parse_floats(rest).ok_or_else(|| error("invalid normal"))?;
+                let normal = to_vec3(&values).ok_or_else(|| error("too few coordinates"))?;
+                normals.push(normal.normalize_or_zero());
+            }
+            "f" => {
+                let mut polygon = Vec::new();
+                for word in rest.split_whitespace() {
+                    let mut fields = word.split('/');
+                    let mut index = |len: usize| -> Result<Option<usize>, ModelError> {
+                        match fields.next() {
+                            None | Some("") => Ok(None),
+                            Some(field) => resolve_index(field, len)
+                                .map(Some)
+                                .ok_or_else(|| error("invalid vertex index")),
+                        }
+                    };
+
+                    let position =
+                        index(positions.len())?.ok_or_else(|| error("missing position"))?;
+                    let key = (position, index(uvs.len())?, index(normals.len())?);
+
+                    let part = &mut parts[current];
+                    let vertex = *vertices[current].entry(key).or_insert_with(|| {
+                        part.mesh.push_vertex(
+                            positions[key.0],
+                            key.2.map(|n| normals[n]).unwrap_or(Vec3::ZERO),
+                            key.1.map(|uv| uvs[uv]).unwrap_or(Vec2::ZERO),
+                            colors[key.0],
+                        )
+                    });
+
+                    polygon.push(vertex);
+                }
+
+                if polygon.len() < 3 {
+                    return Err(error("face has fewer than three vertices"));
+                }
+
+                parts[current].mesh.push_polygon(&polygon);
+            }
+            "usemtl" => {
+                let name = Some(rest.to_string());
+                current = match parts.iter().position(|part| part.name == name) {
+                    Some(index) => index,
+                    None => {
+                        parts.push(Part {
+                            name,
+                            ..Default::default()
+                        });
+
+                        vertices.push(HashMap::new());
+                        parts.len() - 1
+                    }
+                };
+            }
+            "mtllib" => {
+                for name in rest.split_whitespace() {
+                    parse_mtl(&resource(name)?, &mut materials)?;
+                }
+            }
+            // objects, groups, smoothing groups, lines, and so on
+            _ => {}
+        }
+    }
+
+    for part in parts.iter_mut() {
+        if let Some(material) = part.name.as_ref().and_then(|name| materials.get(name)) {
+            part.material = material.clone();
+        }
+    }
+
+    Ok(parts)
+}
+
+/// Parses an MTL library, adding its materials to `materials`.
+fn parse_mtl(data: &[u8], materials: &mut HashMap<String, MaterialDesc>) -> Result<(), ModelError> {
+    let text = std::str::from_utf8(data).map_err(|err| ModelError::Parse(err.to_string()))?;
+    let mut current: Option<&mut MaterialDesc> = None;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let error =
+            |message: &str| ModelError::Parse(format!("MTL line {}: {message}", line_index + 1));
+
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        if keyword == "newmtl" {
+            current = Some(materials.entry(rest.to_string()).or_default());
+            continue;
+        }
+
+        let Some(material) = current.as_deref_mut() else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" => {
+                let values = parse_floats(rest).ok_or_else(|| error("invalid color"))?;
+                if let [r, g, b, ..] = values[..] {
+                    material.color[..3].copy_from_slice(&[r, g, b]);
+                }
+            }
+            "d" => {
+                let values = parse_floats(rest).ok_or_else(|| error("invalid opacity"))?;
+                if let Some(opacity) = values.first() {
+                    material.color[3] = *opacity;
+                }
+            }
+            "Tr" => {
+                let values = parse_floats(rest).ok_or_else(|| error("invalid transparency"))?;
+                if let Some(transparency) = values.first() {
+                    material.color[3] = 1.0 - transparency;
+                }
+            }
+            // texture options come before the file name
+            "map_Kd" => material.texture = rest.split_whitespace().last().map(str::to_string),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses whitespace-separated floats.
+fn parse_floats(text: &str) -> Option<Vec<f32>> {
+    text.split_whitespace()
+        .map(|word| word.parse().ok())
+        .collect()
+}
+
+/// Gets a vector from the first three of a list of floats.
+fn to_vec3(values: &[f32]) -> Option<Vec3> {
+    match values {
+        [x, y, z, ..] => Some(Vec3::new(*x, *y, *z)),
+        _ => None,
+    }
+}
+
+/// Resolves a one-based or negative, relative OBJ index into a list of
+/// length `len`.
+fn resolve_index(field: &str, len: usize) -> Option<usize> {
+    let index: isize = field.parse().ok()?;
+    let resolved = match index {
+        0 => return None,
+        index if index > 0 => index as usize - 1,
+        index => len.checked_sub(index.unsigned_abs())?,
+    };
+
+    (resolved < len).then_some(resolved)
+}
@@ -0,0 +1,346 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Stanford PLY import.
+
+use kindling_host::prelude::glam::{Vec2, Vec3};
+use kindling_schema::model::ModelError;
+
+use crate::mesh::Part;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum PropertyKind {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// Finds the index of the first property with one of the given names.
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+/// A vertex's data, indexed by property.
+type Row = Vec<Vec<f64>>;
+
+/// Reads the values in a PLY file's body.
+struct Reader<'a> {
+    encoding: Encoding,
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn scalar(&mut self, ty: ScalarType) -> Result<f64, ModelError> {
+        let eof = || ModelError::Parse("unexpected end of file".into());
+
+        if self.encoding == Encoding::Ascii {
+            let rest = &self.data[self.offset..];
+            let start = rest
+                .iter()
+                .position(|byte| !byte.is_ascii_whitespace())
+                .ok_or_else(eof)?;
+
+            let len = rest[start..]
+                .iter()
+                .position(|byte| byte.is_ascii_whitespace())
+                .unwrap_or(rest.len() - start);
+
+            self.offset += start + len;
+            return std::str::from_utf8(&rest[start..start + len])
+                .ok()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| ModelError::Parse("invalid number".into()));
+        }
+
+        let size = ty.size();
+        let mut bytes = [0u8; 8];
+        let source = self
+            .data
+            .get(self.offset..self.offset + size)
+            .ok_or_else(eof)?;
+        bytes[..size].copy_from_slice(source);
+        self.offset += size;
+
+        if self.encoding == Encoding::BigEndian {
+            bytes[..size].reverse();
+        }
+
+        let [b0, b1, b2, b3, ..] = bytes;
+        Ok(match ty {
+            ScalarType::I8 => b0 as i8 as f64,
+            ScalarType::U8 => b0 as f64,
+            ScalarType::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::F64 => f64::from_le_bytes(bytes),
+        })
+    }
+
+    fn row(&mut self, element: &Element) -> Result<Row, ModelError> {
+        element
+            .properties
+            .iter()
+            .map(|property| match property.kind {
+                PropertyKind::Scalar(ty) => Ok(vec![self.scalar(ty)?]),
+                PropertyKind::List { count, item } => {
+                    let count = self.scalar(count)? as usize;
+                    (0..count).map(|_| self.scalar(item)).collect()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Parses an ASCII or binary PLY file's vertices and faces.
+///
+/// Vertices without normals are smooth-shaded. Vertex colors are imported
+/// into the mesh.
+pub fn parse(data: &[u8]) -> Result<Part, ModelError> {
+    let (encoding, elements, body) = parse_header(data)?;
+    let mut reader = Reader {
+        encoding,
+        data,
+        offset: body,
+    };
+
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+    let mut vertex_element = None;
+
+    for element in elements.iter() {
+        for _ in 0..element.count {
+            let row = reader.row(element)?;
+            match element.name.as_str() {
+                "vertex" => vertices.push(row),
+                "face" => faces.push(row),
+                _ => {}
+            }
+        }
+
+        if element.name == "vertex" {
+            vertex_element = Some(element);
+        }
+    }
+
+    let vertex_element =
+        vertex_element.ok_or_else(|| ModelError::Parse("no vertex element".into()))?;
+    let face_element = elements
+        .iter()
+        .find(|element| element.name == "face")
+        .ok_or_else(|| ModelError::Parse("no face element".into()))?;
+
+    let indices = face_element
+        .find(&["vertex_indices", "vertex_index"])
+        .ok_or_else(|| ModelError::Parse("faces have no vertex indices".into()))?;
+
+    let get = |names: &[&str]| vertex_element.find(names);
+    let position = [get(&["x"]), get(&["y"]), get(&["z"])];
+    let normal = [get(&["nx"]), get(&["ny"]), get(&["nz"])];
+    let uv = [
+        get(&["u", "s", "texture_u", "texture_s"]),
+        get(&["v", "t", "texture_v", "texture_t"]),
+    ];
+    let color = [
+        get(&["red", "diffuse_red"]),
+        get(&["green", "diffuse_green"]),
+        get(&["blue", "diffuse_blue"]),
+        get(&["alpha"]),
+    ];
+
+    // colors are bytes when stored as integers and fractions otherwise
+    let color_scale = match color[0].map(|index| &vertex_element.properties[index].kind) {
+        Some(PropertyKind::Scalar(ScalarType::F32 | ScalarType::F64)) => 255.0,
+        _ => 1.0,
+    };
+
+    let mut part = Part::default();
+    for row in vertices.iter() {
+        let value = |index: Option<usize>, default: f64| {
+            index
+                .and_then(|index| row[index].first().copied())
+                .unwrap_or(default)
+        };
+
+        let [x, y, z] = position.map(|index| value(index, 0.0) as f32);
+        let [nx, ny, nz] = normal.map(|index| value(index, 0.0) as f32);
+        let [u, v] = uv.map(|index| value(index, 0.0) as f32);
+        let color = color.map(|index| {
+            let default = 255.0 / color_scale;
+            (value(index, default) * color_scale).clamp(0.0, 255.0) as u8
+        });
+
+        part.mesh.push_vertex(
+            Vec3::new(x, y, z),
+            Vec3::new(nx, ny, nz).normalize_or_zero(),
+            Vec2::new(u, 1.0 - v),
+            color,
+        );
+    }
+
+    for row in faces.iter() {
+        let polygon: Vec<u32> = row[indices].iter().map(|index| *index as u32).collect();
+        part.mesh.push_polygon(&polygon);
+    }
+
+    Ok(part)
+}
+
+/// Parses a PLY header. Returns the encoding, the elements, and the offset
+/// of the body.
+fn parse_header(data: &[u8]) -> Result<(Encoding, Vec<Element>, usize), ModelError> {
+    let error = |message: &str| ModelError::Parse(message.to_string());
+
+    const END: &[u8] = b"end_header";
+    let end = data
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or_else(|| error("missing end_header"))?;
+
+    // the body starts after the end of the end_header line
+    let body = data[end..]
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map(|newline| end + newline + 1)
+        .ok_or_else(|| error("missing body"))?;
+
+    let header = std::str::from_utf8(&data[..end]).map_err(|_| error("header isn't UTF-8"))?;
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(error("missing PLY magic number"));
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(error("unknown format")),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| error("invalid element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let parse = |ty| ScalarType::parse(ty).ok_or_else(|| error("unknown type"));
+                let kind = PropertyKind::List {
+                    count: parse(count)?,
+                    item: parse(item)?,
+                };
+
+                push_property(&mut elements, name, kind)?;
+            }
+            ["property", ty, name] => {
+                let ty = ScalarType::parse(ty).ok_or_else(|| error("unknown type"))?;
+                push_property(&mut elements, name, PropertyKind::Scalar(ty))?;
+            }
+            // comments, obj_info, and blank lines
+            _ => {}
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| error("missing format"))?;
+    Ok((encoding, elements, body))
+}
+
+fn push_property(
+    elements: &mut [Element],
+    name: &str,
+    kind: PropertyKind,
+) -> Result<(), ModelError> {
+    let element = elements
+        .last_mut()
+        .ok_or_else(|| ModelError::Parse("property before any element".into()))?;
+
+    element.properties.push(Property {
+        name: name.to_string(),
+        kind,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! STL import.
+
+use kindling_host::prelude::glam::{Vec2, Vec3};
+use kindling_schema::model::ModelError;
+
+use crate::mesh::Part;
+
+/// The size of a binary STL's header, including its triangle count.
+const HEADER_LEN: usize = 84;
+
+/// The size of each triangle in a binary STL.
+const TRIANGLE_LEN: usize = 50;
+
+/// Parses a binary or ASCII STL file.
+///
+/// Every triangle gets its own vertices, so that the model is flat-shaded
+/// with the file's facet normals.
+pub fn parse(data: &[u8]) -> Result<Part, ModelError> {
+    let mut part = Part::default();
+
+    if is_binary(data) {
+        for triangle in data[HEADER_LEN..].chunks_exact(TRIANGLE_LEN) {
+            let vectors: Vec<Vec3> = triangle[..48]
+                .chunks_exact(12)
+                .map(|vector| {
+                    let [x, y, z] = [0, 4, 8].map(|offset| {
+                        f32::from_le_bytes(vector[offset..offset + 4].try_into().unwrap())
+                    });
+
+                    Vec3::new(x, y, z)
+                })
+                .collect();
+
+            push_facet(&mut part, vectors[0], &vectors[1..]);
+        }
+    } else {
+        parse_ascii(data, &mut part)?;
+    }
+
+    Ok(part)
+}
+
+/// Checks if an STL file is binary by whether its size matches its triangle
+/// count, since binary files may also start with `solid`.
+fn is_binary(data: &[u8]) -> bool {
+    let Some(count) = data.get(80..HEADER_LEN) else {
+        return false;
+    };
+
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as u64;
+    HEADER_LEN as u64 + count * TRIANGLE_LEN as u64 == data.len() as u64
+}
+
+fn parse_ascii(data: &[u8], part: &mut Part) -> Result<(), ModelError> {
+    let text = std::str::from_utf8(data).map_err(|err| ModelError::Parse(err.to_string()))?;
+    let mut words = text.split_whitespace();
+    let mut normal = Vec3::ZERO;
+    let mut vertices = Vec::new();
+
+    while let Some(word) = words.next() {
+        match word {
+            "normal" => normal = read_vec3(&mut words)?,
+            "vertex" => vertices.push(read_vec3(&mut words)?),
+            "endfacet" => {
+                if vertices.len() < 3 {
+                    return Err(ModelError::Parse(
+                        "facet has fewer than three vertices".into(),
+                    ));
+                }
+
+                push_facet(part, normal, &vertices);
+                normal = Vec3::ZERO;
+                vertices.clear();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads three floats as a vector.
+fn read_vec3<'a>(words: &mut impl Iterator<Item = &'a str>) -> Result<Vec3, ModelError> {
+    let mut next = || {
+        words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| ModelError::Parse("invalid vector".into()))
+    };
+
+    Ok(Vec3::new(next()?, next()?, next()?))
+}
+
+/// Adds a facet with its own vertices. A zero normal is replaced with the
+/// facet's computed normal.
+fn push_facet(part: &mut Part, normal: Vec3, vertices: &[Vec3]) {
+    let normal = normal.normalize_or_zero();
+    let polygon: Vec<u32> = vertices
+        .iter()
+        .map(|position| {
+            part.mesh
+                .push_vertex(*position, normal, Vec2::ZERO, [0xff; 4])
+        })
+        .collect();
+
+    part.mesh.push_polygon(&polygon);
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `hearth.Notify` service: shows notifications as a stack of in-world
+//! toasts and forwards them to the OS while the window is unfocused.
+//!
+//! Toasts can't be activated in-world yet, so notification actions are only
+//! delivered through OS notifications.
+
+use std::collections::VecDeque;
+
+use hearth_guest::{
+    canvas::*,
+    codec,
+    notify::{Notification, Urgency, OS_SERVICE_NAME},
+    window::WindowEvent,
+    Capability, Lump, Mailbox, Signal, PARENT,
+};
+use kindling_host::prelude::{
+    glam::{const_vec3, vec2, Quat, Vec3},
+    *,
+};
+
+hearth_guest::export_metadata!();
+
+/// The size of each toast's canvas in pixels.
+const TOAST_WIDTH: u32 = 512;
+const TOAST_HEIGHT: u32 = 96;
+
+/// The world-space position of the newest toast.
+const TOAST_ORIGIN: Vec3 = const_vec3!([0.0, 2.5, 0.0]);
+
+/// The world-space half-width of each toast.
+const TOAST_HALF_WIDTH: f32 = 1.0;
+
+/// The vertical world-space distance between stacked toasts.
+const TOAST_SPACING: f32 = 0.45;
+
+/// The most toasts to show at once. Older toasts are dismissed first.
+const MAX_TOASTS: usize = 5;
+
+/// A notification being shown in-world.
+struct Toast {
+    canvas: Canvas,
+
+    /// The time in seconds until this toast is dismissed.
+    remaining: f32,
+}
+
+struct Notifier {
+    font: Lump,
+    toasts: VecDeque<Toast>,
+    focused: bool,
+    os_notify: Option<Capability>,
+}
+
+impl Notifier {
+    fn post(&mut self, notification: Notification, action: Option<&Capability>) {
+        if !self.focused {
+            if let Some(os_notify) = self.os_notify.as_ref() {
+                os_notify.send(&notification, action.as_slice());
+            }
+        }
+
+        let (background, duration) = match notification.urgency {
+            Urgency::Low => ([0x26, 0x23, 0x3a, 0xff], 4.0),
+            Urgency::Normal => ([0x1f, 0x3a, 0x5f, 0xff], 8.0),
+            Urgency::Critical => ([0x6e, 0x1e, 0x2a, 0xff], 30.0),
+        };
+
+        let pixels = Pixels {
+            width: TOAST_WIDTH,
+            height: TOAST_HEIGHT,
+            data: background
+                .into_iter()
+                .cycle()
+                .take((TOAST_WIDTH * TOAST_HEIGHT * 4) as usize)
+                .collect(),
+        };
+
+        let canvas = Canvas::new(Self::position(0), pixels, CanvasSamplingMode::Linear);
+
+        let draw_text = |y, size, text| {
+            canvas.draw_text(TextDraw {
+                x: 16,
+                y,
+                font: self.font.get_id(),
+                size,
+                color: [0xe0, 0xde, 0xf4, 0xff],
+                background,
+                text,
+            })
+        };
+
+        draw_text(10, 32.0, notification.title);
+        draw_text(52, 24.0, notification.body);
+
+        self.toasts.push_front(Toast {
+            canvas,
+            remaining: duration,
+        });
+
+        self.toasts.truncate(MAX_TOASTS);
+        self.relayout();
+    }
+
+    /// Ages the toasts and dismisses any that have expired.
+    fn update(&mut self, dt: f32) {
+        let len = self.toasts.len();
+
+        self.toasts.retain_mut(|toast| {
+            toast.remaining -= dt;
+            toast.remaining > 0.0
+        });
+
+        if self.toasts.len() != len {
+            self.relayout();
+        }
+    }
+
+    /// Moves each toast to its place in the stack.
+    fn relayout(&self) {
+        for (index, toast) in self.toasts.iter().enumerate() {
+            toast.canvas.relocate(Self::position(index));
+        }
+    }
+
+    /// Gets the position of the toast at the given index in the stack.
+    fn position(index: usize) -> Position {
+        let aspect = TOAST_HEIGHT as f32 / TOAST_WIDTH as f32;
+
+        Position {
+            origin: TOAST_ORIGIN - Vec3::Y * TOAST_SPACING * index as f32,
+            orientation: Quat::IDENTITY,
+            half_size: vec2(TOAST_HALF_WIDTH, TOAST_HALF_WIDTH * aspect),
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    let font = include_bytes!("../../../../resources/mononoki/mononoki-Regular.ttf");
+
+    let mut notifier = Notifier {
+        font: Lump::load_raw(font),
+        toasts: VecDeque::new(),
+        focused: true,
+        os_notify: REGISTRY.get_service(OS_SERVICE_NAME),
+    };
+
+    if notifier.os_notify.is_none() {
+        info!("OS notifications are unavailable");
+    }
+
+    let events = MAIN_WINDOW.subscribe();
+
+    loop {
+        let (index, signal) = Mailbox::poll(&[&PARENT, &events]);
+
+        let Signal::Message(msg) = signal else {
+            continue;
+        };
+
+        if index == 0 {
+            match codec::decode(&msg.data) {
+                Ok(notification) => notifier.post(notification, msg.caps.first()),
+                Err(err) => warn!("invalid notification: {err:?}"),
+            }
+
+            continue;
+        }
+
+        match codec::decode(&msg.data) {
+            Ok(WindowEvent::Redraw { dt }) => notifier.update(dt),
+            Ok(WindowEvent::Focused(focused)) => notifier.focused = focused,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,289 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `rs.hearth.kindling.SceneLoader` service: instantiates
+//! [Scenes][Scene] read from scene files.
+//!
+//! On startup, every scene file listed in the `autoload` key of this
+//! service's key-value namespace is loaded. Models are added to the renderer,
+//! lights are placed by their entity's transform, and scripts are spawned
+//! with this service's registry and sent a [ScriptStart].
+
+use std::collections::HashMap;
+
+use hearth_guest::{codec, renderer::*, Capability, Lump, Signal, PARENT};
+use kindling_host::{fs, kv::KvStore, prelude::*, renderer::set_ambient_lighting, wasm};
+use kindling_schema::scene::*;
+
+hearth_guest::export_metadata!();
+
+/// A capability to a renderer resource or a script that is killed when
+/// dropped.
+struct Handle(Capability);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.0.kill();
+    }
+}
+
+/// Everything instantiated for a scene.
+#[derive(Default)]
+struct Instance {
+    handles: Vec<Handle>,
+
+    /// The lumps of the scene's models, kept loaded while the scene is.
+    lumps: Vec<Lump>,
+}
+
+struct Loader {
+    renderer: RequestResponse<RendererRequest, RendererResponse>,
+    next_id: u32,
+    scenes: HashMap<SceneId, Instance>,
+}
+
+impl Loader {
+    /// Reads a scene file and instantiates it.
+    fn load(&mut self, path: &str) -> Result<SceneId, SceneError> {
+        let data = read(path)?;
+        let scene: Scene =
+            serde_json::from_slice(&data).map_err(|err| SceneError::Parse(err.to_string()))?;
+
+        let mut instance = Instance::default();
+        for (index, entity) in scene.entities.iter().enumerate() {
+            self.instantiate(path, entity, &mut instance)
+                .map_err(|message| SceneError::Entity {
+                    index,
+                    name: entity.name.clone(),
+                    message,
+                })?;
+        }
+
+        if let Some(ambient) = scene.ambient {
+            set_ambient_lighting(ambient);
+        }
+
+        let id = SceneId(self.next_id);
+        self.next_id += 1;
+
+        info!(
+            "Loaded {:?} with {} entities as {:?}",
+            path,
+            scene.entities.len(),
+            id
+        );
+
+        self.scenes.insert(id, instance);
+        Ok(id)
+    }
+
+    /// Instantiates every component of an entity into a scene instance.
+    fn instantiate(
+        &self,
+        scene_path: &str,
+        entity: &Entity,
+        instance: &mut Instance,
+    ) -> Result<(), String> {
+        let transform = &entity.transform;
+
+        if let Some(model) = entity.model.as_ref() {
+            let mesh = fs::get_file(&resolve_path(scene_path, &model.mesh))
+                .map_err(|err| format!("reading mesh: {err:?}"))?;
+
+            let albedo = match model.albedo.as_ref() {
+                Some(albedo) => {
+                    let albedo = fs::get_file(&resolve_path(scene_path, albedo))
+                        .map_err(|err| format!("reading albedo: {err:?}"))?;
+                    Lump::load_by_id(&albedo)
+                }
+                None => Lump::load(&TextureData {
+                    label: None,
+                    size: (1, 1).into(),
+                    data: vec![0xff; 4],
+                }),
+            };
+
+            let material = Lump::load(&MaterialData {
+                albedo: albedo.get_id(),
+            });
+
+            let request = RendererRequest::AddObject {
+                mesh,
+                skeleton: None,
+                material: material.get_id(),
+                transform: transform.to_matrix(),
+                dynamic: false,
+            };
+
+            instance.handles.push(self.add(request)?);
+            instance
+                .lumps
+                .extend([Lump::load_by_id(&mesh), albedo, material]);
+        }
+
+        if let Some(light) = entity.light.clone() {
+            let position = transform.translation;
+            let direction = transform.forward();
+
+            let request = match light {
+                Light::Directional {
+                    color,
+                    intensity,
+                    distance,
+                } => RendererRequest::AddDirectionalLight {
+                    initial_state: DirectionalLightState {
+                        color,
+                        intensity,
+                        direction,
+                        distance,
+                    },
+                },
+                Light::Point {
+                    color,
+                    intensity,
+                    radius,
+                } => RendererRequest::AddPointLight {
+                    initial_state: PointLightState {
+                        position,
+                        color,
+                        intensity,
+                        radius,
+                    },
+                },
+                Light::Spot {
+                    color,
+                    intensity,
+                    radius,
+                    inner_angle,
+                    outer_angle,
+                    falloff,
+                } => RendererRequest::AddSpotLight {
+                    initial_state: SpotLightState {
+                        position,
+                        direction,
+                        color,
+                        intensity,
+                        radius,
+                        inner_angle,
+                        outer_angle,
+                        falloff,
+                    },
+                },
+            };
+
+            let (response, mut caps) = self.renderer.request(request, &[]);
+            match response {
+                Ok(_) => instance.handles.push(Handle(caps.remove(0))),
+                // leave the rest of the scene intact
+                Err(RendererError::Unsupported) => {
+                    warn!("{:?}: renderer doesn't support its light", entity.name);
+                }
+                Err(err) => return Err(format!("renderer error: {err:?}")),
+            }
+        }
+
+        if let Some(script) = entity.script.as_ref() {
+            let module = fs::get_file(&resolve_path(scene_path, &script.module))
+                .map_err(|err| format!("reading script: {err:?}"))?;
+
+            let process = wasm::spawn_mod(module, None);
+            let start = ScriptStart {
+                name: entity.name.clone(),
+                transform: *transform,
+            };
+
+            process.send(&start, &[]);
+            instance.handles.push(Handle(process));
+        }
+
+        Ok(())
+    }
+
+    /// Adds something to the renderer.
+    fn add(&self, request: RendererRequest) -> Result<Handle, String> {
+        let (response, mut caps) = self.renderer.request(request, &[]);
+        response.map_err(|err| format!("renderer error: {err:?}"))?;
+        Ok(Handle(caps.remove(0)))
+    }
+
+    /// Handles a request to the service.
+    fn on_request(&mut self, request: SceneRequest) -> SceneResponse {
+        match request {
+            SceneRequest::Load { path } => self.load(&path).map(SceneSuccess::Loaded),
+            SceneRequest::Unload { id } => match self.scenes.remove(&id) {
+                Some(_) => {
+                    info!("Unloaded {:?}", id);
+                    Ok(SceneSuccess::Done)
+                }
+                None => Err(SceneError::NotFound),
+            },
+        }
+    }
+}
+
+/// Reads a file from the filesystem.
+fn read(path: &str) -> Result<Vec<u8>, SceneError> {
+    fs::read_file(path).map_err(|err| SceneError::Read {
+        path: path.to_string(),
+        message: format!("{err:?}"),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    let mut loader = Loader {
+        renderer: RequestResponse::expect_service("hearth.Renderer"),
+        next_id: 0,
+        scenes: HashMap::new(),
+    };
+
+    let autoload = match KvStore::root().get_json::<Vec<String>>("autoload") {
+        Ok(paths) => paths.unwrap_or_default(),
+        Err(err) => {
+            warn!("Failed to read scenes to autoload: {err:?}");
+            Vec::new()
+        }
+    };
+
+    for path in autoload {
+        if let Err(err) = loader.load(&path) {
+            error!("Failed to load {:?}: {:?}", path, err);
+        }
+    }
+
+    loop {
+        let Signal::Message(msg) = PARENT.recv_signal() else {
+            continue;
+        };
+
+        let Some(reply) = msg.caps.first() else {
+            warn!("scene request is missing a reply capability");
+            continue;
+        };
+
+        let response = match codec::decode(&msg.data) {
+            Ok(request) => loader.on_request(request),
+            Err(err) => {
+                warn!("invalid scene request: {err:?}");
+                continue;
+            }
+        };
+
+        reply.send(&response, &[]);
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `rs.hearth.kindling.World` service: spawns the persistent contents of
+//! a space and restores them when the space starts again.
+//!
+//! The world is saved to this service's key-value namespace after every
+//! change. The manifest of spawned entities is kept under the `manifest` key
+//! and the data of every lump that they use is kept in the `lumps`
+//! namespace, keyed by lump ID, so that the lumps can be loaded again after a
+//! restart.
+
+use std::collections::HashSet;
+
+use hearth_guest::{
+    canvas::*,
+    codec,
+    kv::{KvError, MAX_VALUE_SIZE},
+    renderer::*,
+    Capability, Lump, LumpId, Signal, PARENT,
+};
+use kindling_host::{kv::KvStore, prelude::*};
+use kindling_schema::world::*;
+use serde::{Deserialize, Serialize};
+
+hearth_guest::export_metadata!();
+
+/// The key of the saved manifest.
+const MANIFEST_KEY: &str = "manifest";
+
+/// The namespace that lump data is saved in.
+const LUMPS_NAMESPACE: &str = "lumps";
+
+/// The saved state of the world.
+#[derive(Default, Deserialize, Serialize)]
+struct Manifest {
+    next_id: u32,
+    entities: Vec<SpawnedEntity>,
+}
+
+/// A capability to a renderer or canvas resource that is killed, removing the
+/// resource, when dropped.
+struct Handle(Capability);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.0.kill();
+    }
+}
+
+/// An entity that exists in the world right now.
+struct Live {
+    spawned: SpawnedEntity,
+    _handle: Handle,
+
+    /// Every lump that the entity uses, kept loaded for the entity's
+    /// lifetime.
+    lumps: Vec<Lump>,
+}
+
+struct World {
+    renderer: RequestResponse<RendererRequest, RendererResponse>,
+    canvas_factory: RequestResponse<FactoryRequest, FactoryResponse>,
+    kv: KvStore,
+    lump_kv: KvStore,
+
+    /// The keys of every lump saved in [Self::lump_kv].
+    saved_lumps: HashSet<String>,
+
+    next_id: u32,
+    entities: Vec<Live>,
+}
+
+impl World {
+    /// Opens the saved world, spawning everything in it.
+    fn open() -> Result<Self, WorldError> {
+        let kv = KvStore::root();
+        let lump_kv = kv.namespace(LUMPS_NAMESPACE).map_err(storage_error)?;
+
+        let saved_lumps = lump_kv
+            .list("")
+            .map_err(storage_error)?
+            .into_iter()
+            .collect();
+
+        let manifest: Manifest = kv
+            .get_json(MANIFEST_KEY)
+            .map_err(storage_error)?
+            .unwrap_or_default();
+
+        let mut world = Self {
+            renderer: RequestResponse::expect_service("hearth.Renderer"),
+            canvas_factory: RequestResponse::expect_service("hearth.canvas.CanvasFactory"),
+            kv,
+            lump_kv,
+            saved_lumps,
+            next_id: manifest.next_id,
+            entities: Vec::new(),
+        };
+
+        for spawned in manifest.entities {
+            if let Err(err) = world.restore(spawned.clone()) {
+                error!("Failed to restore {:?}: {:?}", spawned, err);
+            }
+        }
+
+        info!("Restored {} saved entities", world.entities.len());
+        Ok(world)
+    }
+
+    /// Spawns a saved entity, loading its lumps from storage.
+    fn restore(&mut self, spawned: SpawnedEntity) -> Result<(), WorldError> {
+        let mut lumps = Vec::new();
+        for id in direct_lumps(&spawned.entity) {
+            let lump = self.load_lump(id)?;
+
+            if let Some(albedo) = material_albedo(&spawned.entity, &lump) {
+                lumps.push(self.load_lump(albedo)?);
+            }
+
+            lumps.push(lump);
+        }
+
+        let handle = self.instantiate(&spawned.entity)?;
+        self.entities.push(Live {
+            spawned,
+            _handle: handle,
+            lumps,
+        });
+
+        Ok(())
+    }
+
+    /// Spawns a new entity and saves the world.
+    fn spawn(&mut self, entity: WorldEntity) -> Result<EntityId, WorldError> {
+        // the renderer has checked that every lump exists once this succeeds
+        let handle = self.instantiate(&entity)?;
+
+        let mut lumps = Vec::new();
+        for id in direct_lumps(&entity) {
+            let lump = Lump::load_by_id(&id);
+
+            if let Some(albedo) = material_albedo(&entity, &lump) {
+                lumps.push(Lump::load_by_id(&albedo));
+            }
+
+            lumps.push(lump);
+        }
+
+        for lump in lumps.iter() {
+            self.save_lump(lump)?;
+        }
+
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+
+        self.entities.push(Live {
+            spawned: SpawnedEntity { id, entity },
+            _handle: handle,
+            lumps,
+        });
+
+        self.save()?;
+        Ok(id)
+    }
+
+    /// Creates an entity's renderer or canvas resource.
+    fn instantiate(&self, entity: &WorldEntity) -> Result<Handle, WorldError> {
+        let request = match entity.clone() {
+            WorldEntity::Model {
+                mesh,
+                material,
+                transform,
+            } => RendererRequest::AddObject {
+                mesh,
+                skeleton: None,
+                material,
+                transform,
+                dynamic: false,
+            },
+            WorldEntity::DirectionalLight(initial_state) => {
+                RendererRequest::AddDirectionalLight { initial_state }
+            }
+            WorldEntity::PointLight(initial_state) => {
+                RendererRequest::AddPointLight { initial_state }
+            }
+            WorldEntity::SpotLight(initial_state) => {
+                RendererRequest::AddSpotLight { initial_state }
+            }
+            WorldEntity::Panel {
+                position,
+                sampling,
+                pixels,
+            } => {
+                // panel lumps are always saved or loaded before this
+                let pixels = Lump::load_by_id(&pixels).get_data();
+                let pixels: Pixels = codec::decode(&pixels)
+                    .map_err(|err| WorldError::SpawnFailed(err.to_string()))?;
+
+                let request = FactoryRequest::CreateCanvas {
+                    position,
+                    pixels,
+                    sampling,
+                };
+
+                let (response, mut caps) = self.canvas_factory.request(request, &[]);
+                response.map_err(|err| WorldError::SpawnFailed(format!("{err:?}")))?;
+                return Ok(Handle(caps.remove(0)));
+            }
+        };
+
+        let (response, mut caps) = self.renderer.request(request, &[]);
+        response.map_err(|err| WorldError::SpawnFailed(format!("{err:?}")))?;
+        Ok(Handle(caps.remove(0)))
+    }
+
+    /// Removes an entity and saves the world.
+    fn despawn(&mut self, id: EntityId) -> Result<(), WorldError> {
+        let index = self
+            .entities
+            .iter()
+            .position(|live| live.spawned.id == id)
+            .ok_or(WorldError::NotFound)?;
+
+        self.entities.remove(index);
+        self.save()?;
+        self.prune_lumps()
+    }
+
+    /// Removes every entity and saves the world.
+    fn clear(&mut self) -> Result<(), WorldError> {
+        self.entities.clear();
+        self.save()?;
+        self.prune_lumps()
+    }
+
+    /// Writes the manifest to storage.
+    fn save(&self) -> Result<(), WorldError> {
+        let manifest = Manifest {
+            next_id: self.next_id,
+            entities: self.list(),
+        };
+
+        self.kv
+            .put_json(MANIFEST_KEY, &manifest)
+            .map_err(storage_error)
+    }
+
+    /// Gets every entity in spawn order.
+    fn list(&self) -> Vec<SpawnedEntity> {
+        self.entities
+            .iter()
+            .map(|live| live.spawned.clone())
+            .collect()
+    }
+
+    /// Saves a lump's data if it hasn't been saved already.
+    fn save_lump(&mut self, lump: &Lump) -> Result<(), WorldError> {
+        let id = lump.get_id();
+        let key = id.to_string();
+        if self.saved_lumps.contains(&key) {
+            return Ok(());
+        }
+
+        let data = lump.get_data();
+        if data.len() > MAX_VALUE_SIZE {
+            return Err(WorldError::LumpTooLarge(id));
+        }
+
+        self.lump_kv.put(&key, data).map_err(storage_error)?;
+        self.saved_lumps.insert(key);
+        Ok(())
+    }
+
+    /// Loads a saved lump.
+    fn load_lump(&self, id: LumpId) -> Result<Lump, WorldError> {
+        match self.lump_kv.get(&id.to_string()).map_err(storage_error)? {
+            Some(data) => Ok(Lump::load_raw(&data)),
+            None => Err(WorldError::Storage(format!("lump {id} is not saved"))),
+        }
+    }
+
+    /// Deletes every saved lump that no entity uses anymore.
+    fn prune_lumps(&mut self) -> Result<(), WorldError> {
+        let used: HashSet<String> = self
+            .entities
+            .iter()
+            .flat_map(|live| live.lumps.iter())
+            .map(|lump| lump.get_id().to_string())
+            .collect();
+
+        let unused: Vec<String> = self.saved_lumps.difference(&used).cloned().collect();
+        for key in unused {
+            self.lump_kv.delete(&key).map_err(storage_error)?;
+            self.saved_lumps.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    /// Handles a request to the service.
+    fn on_request(&mut self, request: WorldRequest) -> WorldResponse {
+        match request {
+            WorldRequest::Spawn(WorldEntity::Panel { .. }) => Err(WorldError::PanelWithoutPixels),
+            WorldRequest::Spawn(entity) => self.spawn(entity).map(WorldSuccess::Spawned),
+            WorldRequest::SpawnPanel {
+                position,
+                sampling,
+                pixels,
+            } => {
+                let pixels = Lump::load(&pixels);
+                let entity = WorldEntity::Panel {
+                    position,
+                    sampling,
+                    pixels: pixels.get_id(),
+                };
+
+                self.spawn(entity).map(WorldSuccess::Spawned)
+            }
+            WorldRequest::Despawn { id } => self.despawn(id).map(|_| WorldSuccess::Done),
+            WorldRequest::List => Ok(WorldSuccess::Entities(self.list())),
+            WorldRequest::Clear => self.clear().map(|_| WorldSuccess::Done),
+        }
+    }
+}
+
+/// Gets the lumps that an entity refers to directly.
+fn direct_lumps(entity: &WorldEntity) -> Vec<LumpId> {
+    match entity {
+        WorldEntity::Model { mesh, material, .. } => vec![*mesh, *material],
+        WorldEntity::Panel { pixels, .. } => vec![*pixels],
+        _ => Vec::new(),
+    }
+}
+
+/// Gets the albedo texture of a lump if it's the material of a model.
+fn material_albedo(entity: &WorldEntity, lump: &Lump) -> Option<LumpId> {
+    match entity {
+        WorldEntity::Model { material, .. } if *material == lump.get_id() => {
+            codec::decode::<MaterialData>(&lump.get_data())
+                .ok()
+                .map(|data| data.albedo)
+        }
+        _ => None,
+    }
+}
+
+fn storage_error(err: KvError) -> WorldError {
+    WorldError::Storage(format!("{err:?}"))
+}
+
+#[no_mangle]
+pub extern "C" fn run() {
+    let mut world = match World::open() {
+        Ok(world) => world,
+        Err(err) => {
+            error!("Failed to open the saved world: {err:?}");
+            return;
+        }
+    };
+
+    loop {
+        let Signal::Message(msg) = PARENT.recv_signal() else {
+            continue;
+        };
+
+        let Some(reply) = msg.caps.first() else {
+            warn!("world request is missing a reply capability");
+            continue;
+        };
+
+        let response = match codec::decode(&msg.data) {
+            Ok(request) => world.on_request(request),
+            Err(err) => {
+                warn!("invalid world request: {err:?}");
+                continue;
+            }
+        };
+
+        reply.send(&response, &[]);
+    }
+}
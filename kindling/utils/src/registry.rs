@@ -16,11 +16,12 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use hearth_guest::{
+    codec,
     registry::{RegistryRequest, RegistryResponse},
-    Capability, PARENT,
+    Capability, Mailbox, Permissions, Signal, PARENT,
 };
 use kindling_host::{prelude::*, registry::Registry};
 use serde::{Deserialize, Serialize};
@@ -85,6 +86,374 @@ impl RegistryServer {
                 RegistryResponse::List(self.services.keys().map(|k| k.to_string()).collect()),
                 vec![],
             ),
+            // immutable registries reply to watches once
+            Watch { name } => match self.services.get(&name) {
+                Some(service) => (RegistryResponse::Watch(Some(true)), vec![service]),
+                None => (RegistryResponse::Watch(None), vec![]),
+            },
+            Remove { .. } => (RegistryResponse::Remove(None), vec![]),
+            Scope { .. } => (RegistryResponse::Scope(false), vec![]),
+        }
+    }
+}
+
+/// Capabilities to a mutable registry or to one of its scopes.
+pub struct MutableRegistry {
+    /// Accepts every request, including registering and removing services
+    /// and creating scoped child registries.
+    pub admin: Registry,
+
+    /// A read-only view of the same services.
+    pub view: Registry,
+}
+
+/// A registry that services can be registered in and removed from.
+///
+/// Registered services are removed when they go down. Processes can watch
+/// for services with [RegistryRequest::Watch] instead of polling.
+///
+/// The services that the registry is spawned with are pinned: they can't be
+/// replaced or removed, so holders of the admin capability can only add
+/// services alongside them.
+pub struct MutableRegistryServer {
+    services: HashMap<String, Entry>,
+    pinned: HashSet<String>,
+    watchers: Vec<Watcher>,
+    namespaces: Vec<Namespace>,
+
+    /// Receives the down signals of monitored services, watchers, and scope
+    /// owners.
+    monitors: Mailbox,
+}
+
+/// A registered service.
+struct Entry {
+    cap: Capability,
+
+    /// The service's capability with no permissions, if it's monitored.
+    key: Option<Capability>,
+}
+
+/// A process watching for changes to a service.
+struct Watcher {
+    name: String,
+    cap: Capability,
+
+    /// The watcher's capability with no permissions.
+    key: Capability,
+}
+
+/// A namespace of the registry with its own pair of capabilities.
+struct Namespace {
+    /// The prefix of every service name in this scope.
+    prefix: String,
+
+    /// The scope is freed when this capability, with no permissions, goes
+    /// down. The root namespace has no owner.
+    owner: Option<Capability>,
+
+    /// Receives requests from holders of the register-capable capability.
+    admin: Mailbox,
+
+    /// Receives requests from holders of the read-only view.
+    view: Mailbox,
+}
+
+impl Namespace {
+    fn new(prefix: String, owner: Option<Capability>) -> Self {
+        Self {
+            prefix,
+            owner,
+            admin: Mailbox::new(),
+            view: Mailbox::new(),
+        }
+    }
+
+    fn make_capabilities(&self) -> (Capability, Capability) {
+        let perms = Permissions::SEND | Permissions::MONITOR;
+        (
+            self.admin.make_capability(perms),
+            self.view.make_capability(perms),
+        )
+    }
+}
+
+/// Where a signal to a [MutableRegistryServer] came from.
+enum Source {
+    Request { namespace: usize, admin: bool },
+    Monitor,
+}
+
+impl MutableRegistryServer {
+    /// Spawn a new mutable registry with some initial services.
+    pub fn spawn(services: Vec<(String, Capability)>) -> MutableRegistry {
+        let (service_names, caps): (Vec<String>, Vec<Capability>) = services.into_iter().unzip();
+        let reply = Mailbox::new();
+        let reply_cap = reply.make_capability(Permissions::SEND);
+        let mut caps: Vec<&Capability> = caps.iter().collect();
+        caps.insert(0, &reply_cap);
+
+        let config = RegistryConfig { service_names };
+        let registry = spawn_fn(Self::init, None);
+        registry.send(&config, &caps);
+
+        let ((), mut caps) = reply.recv::<()>();
+        let view = caps.pop().expect("mutable registry did not reply");
+        let admin = caps.pop().expect("mutable registry did not reply");
+
+        MutableRegistry {
+            admin: RequestResponse::new(admin),
+            view: RequestResponse::new(view),
+        }
+    }
+
+    fn init() {
+        let (config, mut caps) = PARENT.recv::<RegistryConfig>();
+        let reply = caps.remove(0);
+
+        let mut registry = MutableRegistryServer {
+            services: HashMap::new(),
+            pinned: HashSet::new(),
+            watchers: Vec::new(),
+            namespaces: vec![Namespace::new(String::new(), None)],
+            monitors: Mailbox::new(),
+        };
+
+        for (cap, name) in caps.into_iter().zip(config.service_names) {
+            info!("now serving {:?}", name);
+            registry.register(name.clone(), cap);
+            registry.pinned.insert(name);
+        }
+
+        let (admin, view) = registry.namespaces[0].make_capabilities();
+        reply.send(&(), &[&admin, &view]);
+
+        loop {
+            let (source, signal) = registry.poll();
+            match (source, signal) {
+                (Source::Request { namespace, admin }, Signal::Message(msg)) => {
+                    let Some(reply) = msg.caps.first() else {
+                        debug!("Request did not contain a capability");
+                        continue;
+                    };
+
+                    let request = match codec::decode(&msg.data) {
+                        Ok(request) => request,
+                        Err(err) => {
+                            debug!("Invalid registry request: {err:?}");
+                            continue;
+                        }
+                    };
+
+                    let (response, caps) =
+                        registry.on_request(namespace, admin, request, &msg.caps);
+                    reply.send(&response, &caps.iter().collect::<Vec<_>>());
+                }
+                (Source::Monitor, Signal::Down { subject }) => registry.on_down(&subject),
+                _ => {}
+            }
+        }
+    }
+
+    /// Waits for a signal on any of this registry's mailboxes.
+    ///
+    /// The poll set only changes when scopes are created or freed: the
+    /// monitor mailbox comes first, followed by the admin and view mailboxes
+    /// of each namespace in order.
+    fn poll(&self) -> (Source, Signal) {
+        let mut mailboxes = Vec::with_capacity(1 + self.namespaces.len() * 2);
+        mailboxes.push(&self.monitors);
+
+        for namespace in self.namespaces.iter() {
+            mailboxes.push(&namespace.admin);
+            mailboxes.push(&namespace.view);
+        }
+
+        let (idx, signal) = Mailbox::poll(&mailboxes);
+        let source = match idx {
+            0 => Source::Monitor,
+            idx => Source::Request {
+                namespace: (idx - 1) / 2,
+                admin: idx % 2 == 1,
+            },
+        };
+
+        (source, signal)
+    }
+
+    /// Monitors a capability with this registry's monitor mailbox and
+    /// returns the capability with no permissions that its down signal will
+    /// carry.
+    fn monitor(&self, cap: &Capability) -> Capability {
+        self.monitors.monitor(cap);
+        cap.demote(Permissions::empty())
+    }
+
+    /// Removes every service, watcher, and scope that went down with a
+    /// capability.
+    fn on_down(&mut self, subject: &Capability) {
+        let down: Vec<String> = self
+            .services
+            .iter()
+            .filter(|(_, entry)| entry.key.as_ref() == Some(subject))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in down {
+            info!("service {:?} went down", name);
+            self.remove(&name);
         }
+
+        self.watchers.retain(|watcher| watcher.key != *subject);
+
+        self.namespaces.retain(|namespace| {
+            let freed = namespace.owner.as_ref() == Some(subject);
+            if freed {
+                info!("freeing scope {:?}", namespace.prefix);
+            }
+
+            !freed
+        });
+    }
+
+    fn on_request(
+        &mut self,
+        namespace: usize,
+        admin: bool,
+        request: RegistryRequest,
+        caps: &[Capability],
+    ) -> (RegistryResponse, Vec<Capability>) {
+        use RegistryRequest::*;
+
+        let prefix = self.namespaces[namespace].prefix.clone();
+        let in_scope = |name: &str| name.starts_with(&prefix);
+
+        match request {
+            Get { name } => match self.services.get(&name) {
+                Some(entry) if in_scope(&name) => {
+                    (RegistryResponse::Get(true), vec![entry.cap.clone()])
+                }
+                _ => {
+                    info!("Requested service \"{name}\" not found");
+                    (RegistryResponse::Get(false), vec![])
+                }
+            },
+            Register { name } => {
+                if !admin || !in_scope(&name) || self.pinned.contains(&name) {
+                    debug!("Attempted to register {name:?} without permission");
+                    return (RegistryResponse::Register(None), vec![]);
+                }
+
+                let Some(cap) = caps.get(1) else {
+                    debug!("Register request did not contain a service capability");
+                    return (RegistryResponse::Register(None), vec![]);
+                };
+
+                let replaced = self.register(name, cap.clone());
+                (RegistryResponse::Register(Some(replaced)), vec![])
+            }
+            List => {
+                let mut names: Vec<String> = self
+                    .services
+                    .keys()
+                    .filter(|name| in_scope(name))
+                    .cloned()
+                    .collect();
+
+                names.sort();
+                (RegistryResponse::List(names), vec![])
+            }
+            Watch { name } => {
+                if !in_scope(&name) {
+                    return (RegistryResponse::Watch(None), vec![]);
+                }
+
+                let Some(cap) = caps.first() else {
+                    return (RegistryResponse::Watch(None), vec![]);
+                };
+
+                // every watch must end when its watcher goes down
+                if !cap.get_flags().contains(Permissions::MONITOR) {
+                    debug!("Attempted to watch {name:?} with an unmonitorable capability");
+                    return (RegistryResponse::Watch(None), vec![]);
+                }
+
+                let key = self.monitor(cap);
+                self.watchers.push(Watcher {
+                    name: name.clone(),
+                    cap: cap.clone(),
+                    key,
+                });
+
+                match self.services.get(&name) {
+                    Some(entry) => (RegistryResponse::Watch(Some(true)), vec![entry.cap.clone()]),
+                    None => (RegistryResponse::Watch(Some(false)), vec![]),
+                }
+            }
+            Remove { name } => {
+                if !admin || !in_scope(&name) || self.pinned.contains(&name) {
+                    debug!("Attempted to remove {name:?} without permission");
+                    return (RegistryResponse::Remove(None), vec![]);
+                }
+
+                (RegistryResponse::Remove(Some(self.remove(&name))), vec![])
+            }
+            Scope { prefix: child } => {
+                if !admin {
+                    debug!("Attempted to create a scope without permission");
+                    return (RegistryResponse::Scope(false), vec![]);
+                }
+
+                // the scope must be freed when its owner goes down
+                let Some(owner) = caps
+                    .get(1)
+                    .filter(|owner| owner.get_flags().contains(Permissions::MONITOR))
+                else {
+                    debug!("Scope request did not contain a monitorable owner");
+                    return (RegistryResponse::Scope(false), vec![]);
+                };
+
+                let owner = self.monitor(owner);
+                let namespace = Namespace::new(prefix + &child, Some(owner));
+                let (admin, view) = namespace.make_capabilities();
+                self.namespaces.push(namespace);
+                (RegistryResponse::Scope(true), vec![admin, view])
+            }
+        }
+    }
+
+    /// Registers a service and notifies its watchers.
+    ///
+    /// Returns true if a service was replaced.
+    fn register(&mut self, name: String, cap: Capability) -> bool {
+        for watcher in self.watchers.iter().filter(|watcher| watcher.name == name) {
+            watcher
+                .cap
+                .send(&RegistryResponse::Watch(Some(true)), &[&cap]);
+        }
+
+        let key = if cap.get_flags().contains(Permissions::MONITOR) {
+            Some(self.monitor(&cap))
+        } else {
+            None
+        };
+
+        let entry = Entry { cap, key };
+        self.services.insert(name, entry).is_some()
+    }
+
+    /// Removes a service and notifies its watchers.
+    ///
+    /// Returns true if the service was registered.
+    fn remove(&mut self, name: &str) -> bool {
+        if self.services.remove(name).is_none() {
+            return false;
+        }
+
+        for watcher in self.watchers.iter().filter(|watcher| watcher.name == name) {
+            watcher.cap.send(&RegistryResponse::Watch(Some(false)), &[]);
+        }
+
+        true
     }
 }
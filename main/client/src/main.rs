@@ -16,28 +16,38 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::{
-    net::{SocketAddr, ToSocketAddrs},
-    path::PathBuf,
-    str::FromStr,
-    sync::Arc,
+use std::{net::Ipv4Addr, path::PathBuf, sync::Arc, time::Duration};
+
+use clap::{CommandFactory, Parser};
+use hearth_fs::FsArgs;
+use hearth_network::{
+    auth::login,
+    connection::{Connection, Side},
+    lumps::{LumpExchange, LumpPeer, LumpSource},
+    transport::TransportKind,
+    NetworkConfig,
 };
-
-use clap::Parser;
-use hearth_network::{auth::login, connection::Connection};
-use hearth_rend3::Rend3Plugin;
+use hearth_rend3::{Rend3Args, Rend3Plugin};
 use hearth_runtime::{
+    async_trait,
+    cli::CliBuilder,
+    events::Shutdown,
     flue::OwnedCapability,
+    hearth_schema::{renderer::RenderSettings, LumpId},
+    lump::{bytes::Bytes, LumpFetcher, LumpStoreImpl},
     runtime::{Plugin, Runtime, RuntimeBuilder, RuntimeConfig},
 };
-use tokio::{net::TcpStream, sync::oneshot};
-use tracing::{debug, error, info};
+use tokio::{net::TcpListener, sync::oneshot};
+use tracing::{debug, error, info, warn};
 use window::WindowPlugin;
 
 use crate::window::WindowCtx;
 
 mod window;
 
+/// How often to re-advertise this client's lumps to the server.
+const ADVERTISE_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Client program to the Hearth virtual space server.
 #[derive(Parser, Debug)]
 pub struct Args {
@@ -45,6 +55,15 @@ pub struct Args {
     #[clap(short, long)]
     pub server: Option<String>,
 
+    /// The transport to connect to the server over.
+    #[clap(long, value_enum, default_value = "tcp")]
+    pub transport: TransportKind,
+
+    /// The user to log in to the server as. Logs in with the server's shared
+    /// password if unset.
+    #[clap(short, long, default_value = "")]
+    pub username: String,
+
     /// Password to use to authenticate to the server. Defaults to empty.
     #[clap(short, long, default_value = "")]
     pub password: String,
@@ -58,14 +77,15 @@ pub struct Args {
     /// [default: <ROOT>/init.wasm]
     #[clap(short, long)]
     pub init: Option<PathBuf>,
-
-    /// A path to the guest-side filesystem root.
-    #[clap(short, long)]
-    pub root: PathBuf,
 }
 
 fn main() {
-    let args = Args::parse();
+    let mut cli = CliBuilder::new(Args::command());
+    cli.add_args::<Rend3Args>().add_args::<FsArgs>();
+    let matches = cli.parse();
+    let args: Args = matches.get();
+    let rend3_args: Rend3Args = matches.get();
+    let fs_args: FsArgs = matches.get();
     hearth_runtime::init_logging();
 
     // winit requires that running its event loop takes over the calling thread,
@@ -76,9 +96,43 @@ fn main() {
         .build()
         .unwrap();
 
-    let (window, mut window_offer) = runtime.block_on(WindowCtx::new());
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(hearth_runtime::get_config_path);
+
+    let config_file = match hearth_runtime::load_config(&config_path) {
+        Ok(config_file) => config_file,
+        Err(err) => {
+            warn!("Failed to load config file: {:?}", err);
+            Default::default()
+        }
+    };
+
+    let render_settings = match config_file.get("renderer") {
+        None => RenderSettings::default(),
+        Some(table) => table.clone().try_into().unwrap_or_else(|err| {
+            error!("Failed to parse renderer config: {:?}", err);
+            RenderSettings::default()
+        }),
+    };
+
+    let config = RuntimeConfig::from_config_file(&config_file);
+    let network_config = NetworkConfig::from_config_file(&config_file);
+    let time_plugin = hearth_time::TimePlugin::from_config_file(&config_file);
+    let logs_plugin =
+        hearth_logs::ProcessLogPlugin::from_config_file(&config_file).with_subdir("client");
+    let backend = rend3_args.backend();
+    let features = rend3_args.features();
+    let (window, mut window_offer) =
+        runtime.block_on(WindowCtx::new(render_settings, backend, features));
     let mut join_main = runtime.spawn(async_main(
         args,
+        fs_args,
+        config,
+        network_config,
+        time_plugin,
+        logs_plugin,
         window_offer.rend3_plugin,
         window_offer.window_plugin,
     ));
@@ -105,39 +159,70 @@ fn main() {
     window.run();
 }
 
-async fn async_main(args: Args, rend3_plugin: Rend3Plugin, window_plugin: WindowPlugin) {
-    let init = args.init.unwrap_or(args.root.join("init.wasm"));
+async fn async_main(
+    args: Args,
+    fs_args: FsArgs,
+    config: RuntimeConfig,
+    network_config: NetworkConfig,
+    time_plugin: hearth_time::TimePlugin,
+    logs_plugin: hearth_logs::ProcessLogPlugin,
+    rend3_plugin: Rend3Plugin,
+    window_plugin: WindowPlugin,
+) {
+    let init = args.init.unwrap_or(fs_args.root.join("init.wasm"));
     let mut builder = RuntimeBuilder::new();
-    builder.add_plugin(hearth_time::TimePlugin);
+    builder.add_plugin(time_plugin);
     builder.add_plugin(hearth_wasm::WasmPlugin::default());
     builder.add_plugin(hearth_init::InitPlugin::new(init));
-    builder.add_plugin(hearth_fs::FsPlugin::new(args.root));
+    builder.add_plugin(hearth_fs::FsPlugin::new(fs_args.root.clone()));
+    builder.add_plugin(hearth_fs::FsFactory::new(fs_args.root.clone()));
+    builder.add_plugin(hearth_fs::WritableFsFactory::new(
+        fs_args.root.clone(),
+        fs_args.quota,
+        vec![fs_args.root.join("init.wasm")],
+    ));
+    builder.add_plugin(hearth_file_picker::FilePickerPlugin::default());
+    builder.add_plugin(hearth_notify::OsNotifyService);
+    builder.add_plugin(hearth_image_decoder::ImageDecoderService);
     builder.add_plugin(rend3_plugin);
     builder.add_plugin(hearth_renderer::RendererPlugin::default());
+    builder.add_plugin(hearth_animation::AnimationPlugin);
     builder.add_plugin(window_plugin);
+    builder.add_plugin(hearth_gamepad::GamepadPlugin);
     builder.add_plugin(hearth_debug_draw::DebugDrawPlugin::default());
     builder.add_plugin(hearth_canvas::CanvasPlugin);
     builder.add_plugin(hearth_terminal::TerminalPlugin::default());
     builder.add_plugin(hearth_daemon::DaemonPlugin::default());
+    builder.add_plugin(logs_plugin);
+    builder.add_plugin(hearth_runtime::audit::CapAuditService);
+    builder.add_plugin(hearth_runtime::group::ProcessGroupService::default());
 
     if let (Some(server), password) = (args.server, args.password) {
-        builder.add_plugin(ClientPlugin { server, password });
+        builder.add_plugin(ClientPlugin {
+            server,
+            username: args.username,
+            password,
+            transport: args.transport,
+            network_config,
+        });
     } else {
         info!("Running in serverless mode");
     }
 
-    let config = RuntimeConfig {};
-
-    let _runtime = builder.run(config).await;
+    let runtime = builder.run(config).await;
 
     hearth_runtime::wait_for_interrupt().await;
     info!("Ctrl+C hit; quitting client");
+    runtime.event_bus.publish(Shutdown);
 }
 
 /// The plugin that implements the client side of a network connection.
 pub struct ClientPlugin {
     pub server: String,
+    pub username: String,
     pub password: String,
+    pub transport: TransportKind,
+    pub network_config: NetworkConfig,
 }
 
 impl Plugin for ClientPlugin {
@@ -164,29 +249,12 @@ impl ClientPlugin {
         info!("Waiting for network root cap hook");
         let network_root = on_network_root.await.unwrap();
 
-        info!("Resolving {}", self.server);
-        let server = match SocketAddr::from_str(&self.server) {
-            Err(_) => {
-                info!(
-                    "Failed to parse \'{}\' to SocketAddr, attempting DNS resolution",
-                    self.server
-                );
-                match self.server.to_socket_addrs() {
-                    Err(err) => {
-                        error!("Failed to resolve IP: {:?}", err);
-                        return;
-                    }
-                    Ok(addrs) => match addrs.last() {
-                        None => return,
-                        Some(addr) => addr,
-                    },
-                }
-            }
-            Ok(addr) => addr,
-        };
-
-        info!("Connecting to server at {:?}", server);
-        let mut socket = match TcpStream::connect(server).await {
+        info!(
+            "Connecting to server at {:?} over {:?}",
+            self.server, self.transport
+        );
+        let transport = self.transport.transport();
+        let mut link = match transport.connect(&self.server).await {
             Ok(s) => s,
             Err(err) => {
                 error!("Failed to connect to server: {:?}", err);
@@ -195,27 +263,30 @@ impl ClientPlugin {
         };
 
         info!("Authenticating");
-        let session_key = match login(&mut socket, self.password.as_bytes()).await {
-            Ok(key) => key,
-            Err(err) => {
-                error!("Failed to authenticate with server: {:?}", err);
-                return;
-            }
-        };
+        let session_key =
+            match login(&mut link.stream, &self.username, self.password.as_bytes()).await {
+                Ok(key) => key,
+                Err(err) => {
+                    error!("Failed to authenticate with server: {:?}", err);
+                    return;
+                }
+            };
 
-        use hearth_network::encryption::{AsyncDecryptor, AsyncEncryptor, Key};
-        let client_key = Key::from_client_session(&session_key);
-        let server_key = Key::from_server_session(&session_key);
+        let conn = Connection::encrypted(link, &session_key, Side::Client, &self.network_config);
 
-        let (server_rx, server_tx) = tokio::io::split(socket);
-        let server_rx = AsyncDecryptor::new(&server_key, server_rx);
-        let server_tx = AsyncEncryptor::new(&client_key, server_tx);
-        let conn = Connection::new(server_rx, server_tx);
+        // fetch missing lumps through the server connection
+        let source = Arc::new(RuntimeLumps(runtime.lump_store.clone()));
+        let exchange = Arc::new(LumpExchange::new(source));
+        let (lumps, conn) = exchange.attach(conn, Side::Client, None);
+        runtime
+            .lump_store
+            .set_fetcher(Arc::new(PeerLumps(lumps.clone())));
 
         info!("Beginning connection");
         let (root_cap_tx, root_cap) = tokio::sync::oneshot::channel();
         let conn = hearth_runtime::connection::Connection::begin(
             runtime.post.clone(),
+            Some(runtime.lump_store.clone()),
             conn.op_rx,
             conn.op_tx,
             Some(root_cap_tx),
@@ -225,7 +296,7 @@ impl ClientPlugin {
         conn.export_root(network_root);
 
         info!("Waiting for server's root cap...");
-        let _root_cap = match root_cap.await {
+        let root_cap = match root_cap.await {
             Ok(cap) => cap,
             Err(err) => {
                 eprintln!("Server's root cap was never received: {:?}", err);
@@ -234,5 +305,80 @@ impl ClientPlugin {
         };
 
         info!("Successfully connected!");
+        runtime.peers.add_peer(root_cap);
+
+        if let Some(port) = self.network_config.lump_port {
+            tokio::spawn(serve_lumps(
+                port,
+                exchange,
+                lumps,
+                runtime.lump_store.clone(),
+            ));
+        }
+    }
+}
+
+/// Serves this client's lumps to the peers that the server gives tickets to
+/// and periodically advertises them to the server.
+async fn serve_lumps(
+    port: u16,
+    exchange: Arc<LumpExchange>,
+    server: Arc<LumpPeer>,
+    lumps: Arc<LumpStoreImpl>,
+) {
+    // peers may reach this client on any of its addresses, but they can only
+    // fetch the lumps that the server has given them tickets for
+    let listener = match TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to bind lump exchange: {:?}", err);
+            return;
+        }
+    };
+
+    let port = match listener.local_addr() {
+        Ok(addr) => addr.port(),
+        Err(err) => {
+            error!("Failed to get lump exchange address: {:?}", err);
+            return;
+        }
+    };
+
+    info!("Serving lumps to peers on port {}", port);
+    tokio::spawn(exchange.serve(listener));
+
+    loop {
+        if let Err(err) = server.advertise(port, lumps.lump_ids().await) {
+            debug!("Stopped advertising lumps: {:?}", err);
+            return;
+        }
+
+        tokio::time::sleep(ADVERTISE_INTERVAL).await;
+    }
+}
+
+/// Fetches the lumps that are missing from the lump store from peers.
+struct PeerLumps(Arc<LumpPeer>);
+
+#[async_trait]
+impl LumpFetcher for PeerLumps {
+    async fn fetch_lump(&self, id: &LumpId) -> Option<Bytes> {
+        match self.0.fetch(id).await {
+            Ok(data) => data.map(Bytes::from),
+            Err(err) => {
+                warn!("Failed to fetch lump {}: {:?}", id, err);
+                None
+            }
+        }
+    }
+}
+
+/// Serves lumps from a runtime's lump store.
+struct RuntimeLumps(Arc<LumpStoreImpl>);
+
+#[async_trait]
+impl LumpSource for RuntimeLumps {
+    async fn get_lump(&self, id: &LumpId) -> Option<Vec<u8>> {
+        self.0.get_local_lump(id).await.map(|data| data.to_vec())
     }
 }
@@ -18,7 +18,7 @@
 
 use std::{sync::Arc, time::Instant};
 
-use glam::{dvec2, uvec2, Mat4};
+use glam::{dvec2, uvec2, DVec2, Mat4, UVec2};
 use hearth_rend3::{
     rend3::{
         self,
@@ -30,12 +30,15 @@ use hearth_runtime::{
     async_trait,
     flue::{CapabilityRef, Permissions},
     hearth_macros::GetProcessMetadata,
-    hearth_schema::window::*,
+    hearth_schema::{renderer::RenderSettings, window::*},
     runtime::{Plugin, RuntimeBuilder},
-    utils::{MessageInfo, PubSub, ServiceRunner, SinkProcess},
+    utils::{
+        MessageInfo, PubSub, RequestInfo, RequestResponseProcess, ResponseInfo, ServiceRunner,
+        SinkProcess,
+    },
 };
-use rend3::InstanceAdapterDevice;
-use tokio::sync::{mpsc, oneshot};
+use rend3::Renderer;
+use tokio::sync::{mpsc, oneshot, watch};
 use tracing::warn;
 use winit::{
     event::{DeviceEvent, Event, WindowEvent as WinitWindowEvent},
@@ -44,11 +47,23 @@ use winit::{
 };
 
 /// A message sent from the rest of the program to a window.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub enum WindowRxMessage {
     /// Update the title.
     SetTitle(String),
 
+    /// Set whether the window is borderless fullscreen.
+    SetFullscreen(bool),
+
+    /// Set whether the window has decorations.
+    SetDecorations(bool),
+
+    /// Request a new inner size in physical display units.
+    SetInnerSize(UVec2),
+
+    /// Set the cursor icon.
+    SetCursorIcon(CursorIcon),
+
     /// Set the cursor grab mode.
     SetCursorGrab(CursorGrabMode),
 
@@ -67,6 +82,18 @@ pub enum WindowRxMessage {
         view: Mat4,
     },
 
+    /// Set whether IME input is allowed.
+    SetImeAllowed(bool),
+
+    /// Set the position of the IME candidate box.
+    SetImePosition(DVec2),
+
+    /// Set the text contents of the clipboard.
+    SetClipboard(String),
+
+    /// Read the text contents of the clipboard.
+    Paste(oneshot::Sender<String>),
+
     /// Broadcast the current state of the window to all event subscribers.
     BroadcastState,
 
@@ -104,8 +131,9 @@ struct Window {
     /// The inner winit window.
     window: WinitWindow,
 
-    /// The wgpu instance, adapter, and device compatible with this window.
-    iad: InstanceAdapterDevice,
+    /// The current rend3 renderer, whose device this window's surface is
+    /// configured for.
+    renderer: watch::Receiver<Arc<Renderer>>,
 
     /// This window's wgpu surface.
     surface: Arc<wgpu::Surface>,
@@ -113,6 +141,9 @@ struct Window {
     /// This window's wgpu surface configuration.
     config: wgpu::SurfaceConfiguration,
 
+    /// Receives render settings that affect this window's surface.
+    settings: watch::Receiver<RenderSettings>,
+
     /// Sender of frame requests to the rend3 renderer.
     frame_request_tx: mpsc::UnboundedSender<FrameRequest>,
 
@@ -124,10 +155,18 @@ struct Window {
 
     /// Tracks the last redraw to this window.
     last_redraw: Instant,
+
+    /// The system clipboard, if it is available.
+    clipboard: Option<arboard::Clipboard>,
 }
 
 impl Window {
-    async fn new(event_loop: &EventLoop<WindowRxMessage>) -> (Self, WindowOffer) {
+    async fn new(
+        event_loop: &EventLoop<WindowRxMessage>,
+        settings: RenderSettings,
+        backend: Option<wgpu::Backend>,
+        features: Option<wgpu::Features>,
+    ) -> (Self, WindowOffer) {
         let window = WindowBuilder::new()
             .with_title("Hearth Client")
             .with_inner_size(winit::dpi::LogicalSize::new(128.0, 128.0))
@@ -136,7 +175,9 @@ impl Window {
 
         let size = window.inner_size();
         let swapchain_format = wgpu::TextureFormat::Bgra8UnormSrgb;
-        let iad = rend3::create_iad(None, None, None, None).await.unwrap();
+        let iad = rend3::create_iad(backend, None, None, features)
+            .await
+            .unwrap();
         let surface = unsafe { iad.instance.create_surface(&window) };
         let surface = Arc::new(surface);
 
@@ -145,25 +186,32 @@ impl Window {
             format: swapchain_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: present_mode(settings.vsync),
         };
 
         surface.configure(&iad.device, &config);
         let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
         let rend3_plugin = Rend3Plugin::new(iad.to_owned(), swapchain_format);
+        rend3_plugin.set_settings(settings);
+        let settings = rend3_plugin.subscribe_settings();
+        let renderer = rend3_plugin.subscribe_renderer();
         let frame_request_tx = rend3_plugin.frame_request_tx.clone();
         let (events_tx, events_rx) = mpsc::unbounded_channel();
 
         let window = Self {
             outgoing_tx,
             window,
-            iad,
+            renderer,
             surface,
             config,
+            settings,
             camera: Camera::default(),
             frame_request_tx,
             events_tx,
             last_redraw: Instant::now(),
+            clipboard: arboard::Clipboard::new()
+                .map_err(|err| warn!("clipboard is unavailable: {err:?}"))
+                .ok(),
         };
 
         let window_plugin = WindowPlugin {
@@ -184,11 +232,31 @@ impl Window {
     pub fn on_resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
         self.config.width = size.width;
         self.config.height = size.height;
-        self.surface.configure(&self.iad.device, &self.config);
+        self.configure_surface();
         self.window.request_redraw();
     }
 
+    /// Configures this window's surface for the current renderer's device.
+    fn configure_surface(&mut self) {
+        let renderer = self.renderer.borrow_and_update();
+        self.surface.configure(&renderer.device, &self.config);
+    }
+
     pub fn on_draw(&mut self) {
+        // apply surface settings before rendering with them
+        if self.settings.has_changed().unwrap_or(false) {
+            let mode = present_mode(self.settings.borrow_and_update().vsync);
+            if mode != self.config.present_mode {
+                self.config.present_mode = mode;
+                self.configure_surface();
+            }
+        }
+
+        // the renderer was re-created on a new device after a device loss
+        if self.renderer.has_changed().unwrap_or(false) {
+            self.configure_surface();
+        }
+
         // notify redraw event
         let now = Instant::now();
         let elapsed = now.duration_since(self.last_redraw);
@@ -229,6 +297,9 @@ impl Window {
             WinitWindowEvent::ReceivedCharacter(c) => {
                 self.notify_event(WindowEvent::ReceivedCharacter(*c));
             }
+            WinitWindowEvent::Ime(ime) => {
+                self.notify_event(WindowEvent::Ime(conv_ime(ime.clone())));
+            }
             WinitWindowEvent::Focused(focus) => {
                 self.notify_event(WindowEvent::Focused(*focus));
             }
@@ -268,6 +339,9 @@ impl Window {
                     phase: conv_touch_phase(*phase),
                 });
             }
+            WinitWindowEvent::Touch(touch) => {
+                self.notify_event(WindowEvent::Touch(conv_touch(touch)));
+            }
             WinitWindowEvent::MouseInput { state, button, .. } => {
                 self.notify_event(WindowEvent::MouseInput {
                     state: conv_element_state(*state),
@@ -299,6 +373,31 @@ impl Window {
         let _ = self.events_tx.send(event);
     }
 
+    pub fn set_clipboard(&mut self, text: String) {
+        let Some(clipboard) = self.clipboard.as_mut() else {
+            return;
+        };
+
+        if let Err(err) = clipboard.set_text(text) {
+            warn!("failed to set clipboard: {err:?}");
+        }
+    }
+
+    pub fn paste(&mut self) -> String {
+        let Some(clipboard) = self.clipboard.as_mut() else {
+            return String::new();
+        };
+
+        match clipboard.get_text() {
+            Ok(text) => text,
+            Err(arboard::Error::ContentNotAvailable) => String::new(),
+            Err(err) => {
+                warn!("failed to read clipboard: {err:?}");
+                String::new()
+            }
+        }
+    }
+
     pub fn broadcast_state(&self) {
         let size = self.window.inner_size();
         let size = uvec2(size.width, size.height);
@@ -318,9 +417,13 @@ pub struct WindowCtx {
 }
 
 impl WindowCtx {
-    pub async fn new() -> (Self, WindowOffer) {
+    pub async fn new(
+        settings: RenderSettings,
+        backend: Option<wgpu::Backend>,
+        features: Option<wgpu::Features>,
+    ) -> (Self, WindowOffer) {
         let event_loop = EventLoopBuilder::with_user_event().build();
-        let (window, offer) = Window::new(&event_loop).await;
+        let (window, offer) = Window::new(&event_loop, settings, backend, features).await;
         (Self { event_loop, window }, offer)
     }
 
@@ -353,6 +456,24 @@ impl WindowCtx {
                 }
                 Event::UserEvent(event) => match event {
                     WindowRxMessage::SetTitle(title) => window.window.set_title(&title),
+                    WindowRxMessage::SetFullscreen(fullscreen) => {
+                        let fullscreen = fullscreen.then(|| {
+                            let monitor = window.window.current_monitor();
+                            winit::window::Fullscreen::Borderless(monitor)
+                        });
+
+                        window.window.set_fullscreen(fullscreen);
+                    }
+                    WindowRxMessage::SetDecorations(decorations) => {
+                        window.window.set_decorations(decorations)
+                    }
+                    WindowRxMessage::SetInnerSize(size) => {
+                        let size = winit::dpi::PhysicalSize::new(size.x, size.y);
+                        window.window.set_inner_size(size);
+                    }
+                    WindowRxMessage::SetCursorIcon(icon) => {
+                        window.window.set_cursor_icon(conv_cursor_icon(icon))
+                    }
                     WindowRxMessage::SetCursorGrab(mode) => {
                         // convert from guest type to native type
                         use winit::window::CursorGrabMode as Winit;
@@ -376,6 +497,17 @@ impl WindowCtx {
                             view,
                         }
                     }
+                    WindowRxMessage::SetImeAllowed(allowed) => {
+                        window.window.set_ime_allowed(allowed)
+                    }
+                    WindowRxMessage::SetImePosition(position) => {
+                        let position = winit::dpi::PhysicalPosition::new(position.x, position.y);
+                        window.window.set_ime_position(position);
+                    }
+                    WindowRxMessage::SetClipboard(text) => window.set_clipboard(text),
+                    WindowRxMessage::Paste(reply) => {
+                        let _ = reply.send(window.paste());
+                    }
                     WindowRxMessage::BroadcastState => window.broadcast_state(),
                     WindowRxMessage::Quit => control_flow.set_exit(),
                 },
@@ -385,6 +517,15 @@ impl WindowCtx {
     }
 }
 
+/// Selects a surface present mode for a vsync setting.
+fn present_mode(vsync: bool) -> wgpu::PresentMode {
+    if vsync {
+        wgpu::PresentMode::Fifo
+    } else {
+        wgpu::PresentMode::Immediate
+    }
+}
+
 /// A plugin that provides native window access to guests.
 pub struct WindowPlugin {
     incoming: EventLoopProxy<WindowRxMessage>,
@@ -394,16 +535,23 @@ pub struct WindowPlugin {
 impl Plugin for WindowPlugin {
     fn finalize(mut self, builder: &mut RuntimeBuilder) {
         let pubsub = Arc::new(PubSub::new(builder.get_post()));
+        let event_bus = builder.get_event_bus();
 
+        // publish window events to both guests and other plugins
         tokio::spawn({
             let pubsub = pubsub.clone();
             async move {
                 while let Some(event) = self.events_rx.recv().await {
+                    event_bus.publish(event.clone());
                     pubsub.notify(&event).await;
                 }
             }
         });
 
+        builder.add_plugin(ClipboardService {
+            incoming: self.incoming.clone(),
+        });
+
         builder.add_plugin(WindowService {
             incoming: self.incoming,
             pubsub,
@@ -439,7 +587,7 @@ impl SinkProcess for WindowService {
                     sub.monitor(message.process.borrow_parent()).unwrap();
                 }
 
-                self.pubsub.subscribe(sub.clone());
+                self.pubsub.subscribe(sub.clone(), message.codec);
 
                 send(WindowRxMessage::BroadcastState);
             }
@@ -452,9 +600,16 @@ impl SinkProcess for WindowService {
                 self.pubsub.unsubscribe(sub.clone());
             }
             SetTitle(title) => send(WindowRxMessage::SetTitle(title)),
+            SetFullscreen(fullscreen) => send(WindowRxMessage::SetFullscreen(fullscreen)),
+            SetDecorations(decorations) => send(WindowRxMessage::SetDecorations(decorations)),
+            SetInnerSize(size) => send(WindowRxMessage::SetInnerSize(size)),
+            SetCursorIcon(icon) => send(WindowRxMessage::SetCursorIcon(icon)),
             SetCursorGrab(grab) => send(WindowRxMessage::SetCursorGrab(grab)),
             SetCursorVisible(visible) => send(WindowRxMessage::SetCursorVisible(visible)),
             SetCamera { vfov, near, view } => send(WindowRxMessage::SetCamera { vfov, near, view }),
+            SetImeAllowed(allowed) => send(WindowRxMessage::SetImeAllowed(allowed)),
+            SetImePosition(position) => send(WindowRxMessage::SetImePosition(position)),
+            SetClipboard(text) => send(WindowRxMessage::SetClipboard(text)),
         }
     }
 
@@ -467,6 +622,47 @@ impl ServiceRunner for WindowService {
     const NAME: &'static str = SERVICE_NAME;
 }
 
+/// The native clipboard service. Accepts ClipboardCommand.
+///
+/// Reading the clipboard can leak anything the user has copied, so Kindling's
+/// policy withholds this service from every guest service except the ones
+/// hosting terminals.
+#[derive(GetProcessMetadata)]
+pub struct ClipboardService {
+    incoming: EventLoopProxy<WindowRxMessage>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for ClipboardService {
+    type Request = ClipboardCommand;
+    type Response = WindowEvent;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, ClipboardCommand>,
+    ) -> ResponseInfo<'a, WindowEvent> {
+        match request.data {
+            ClipboardCommand::Paste => {
+                // the clipboard is owned by the event loop thread
+                let (tx, rx) = oneshot::channel();
+                self.incoming
+                    .send_event(WindowRxMessage::Paste(tx))
+                    .unwrap();
+                let text = rx.await.unwrap_or_default();
+
+                ResponseInfo {
+                    data: WindowEvent::Paste(text),
+                    caps: vec![],
+                }
+            }
+        }
+    }
+}
+
+impl ServiceRunner for ClipboardService {
+    const NAME: &'static str = CLIPBOARD_SERVICE_NAME;
+}
+
 fn conv_element_state(state: winit::event::ElementState) -> ElementState {
     use winit::event::ElementState as Winit;
     use ElementState as Schema;
@@ -476,6 +672,59 @@ fn conv_element_state(state: winit::event::ElementState) -> ElementState {
     }
 }
 
+fn conv_cursor_icon(icon: CursorIcon) -> winit::window::CursorIcon {
+    use winit::window::CursorIcon as Winit;
+    use CursorIcon as Schema;
+    match icon {
+        Schema::Default => Winit::Default,
+        Schema::Crosshair => Winit::Crosshair,
+        Schema::Hand => Winit::Hand,
+        Schema::Arrow => Winit::Arrow,
+        Schema::Move => Winit::Move,
+        Schema::Text => Winit::Text,
+        Schema::Wait => Winit::Wait,
+        Schema::Help => Winit::Help,
+        Schema::Progress => Winit::Progress,
+        Schema::NotAllowed => Winit::NotAllowed,
+        Schema::ContextMenu => Winit::ContextMenu,
+        Schema::Cell => Winit::Cell,
+        Schema::VerticalText => Winit::VerticalText,
+        Schema::Alias => Winit::Alias,
+        Schema::Copy => Winit::Copy,
+        Schema::NoDrop => Winit::NoDrop,
+        Schema::Grab => Winit::Grab,
+        Schema::Grabbing => Winit::Grabbing,
+        Schema::AllScroll => Winit::AllScroll,
+        Schema::ZoomIn => Winit::ZoomIn,
+        Schema::ZoomOut => Winit::ZoomOut,
+        Schema::EResize => Winit::EResize,
+        Schema::NResize => Winit::NResize,
+        Schema::NeResize => Winit::NeResize,
+        Schema::NwResize => Winit::NwResize,
+        Schema::SResize => Winit::SResize,
+        Schema::SeResize => Winit::SeResize,
+        Schema::SwResize => Winit::SwResize,
+        Schema::WResize => Winit::WResize,
+        Schema::EwResize => Winit::EwResize,
+        Schema::NsResize => Winit::NsResize,
+        Schema::NeswResize => Winit::NeswResize,
+        Schema::NwseResize => Winit::NwseResize,
+        Schema::ColResize => Winit::ColResize,
+        Schema::RowResize => Winit::RowResize,
+    }
+}
+
+fn conv_ime(ime: winit::event::Ime) -> Ime {
+    use winit::event::Ime as Winit;
+    use Ime as Schema;
+    match ime {
+        Winit::Enabled => Schema::Enabled,
+        Winit::Preedit(text, cursor) => Schema::Preedit(text, cursor),
+        Winit::Commit(text) => Schema::Commit(text),
+        Winit::Disabled => Schema::Disabled,
+    }
+}
+
 fn conv_scroll_delta(delta: winit::event::MouseScrollDelta) -> MouseScrollDelta {
     use winit::event::MouseScrollDelta as Winit;
     use MouseScrollDelta as Schema;
@@ -496,6 +745,23 @@ fn conv_touch_phase(phase: winit::event::TouchPhase) -> TouchPhase {
     }
 }
 
+fn conv_touch(touch: &winit::event::Touch) -> Touch {
+    use winit::event::Force;
+
+    let altitude_angle = match touch.force {
+        Some(Force::Calibrated { altitude_angle, .. }) => altitude_angle,
+        _ => None,
+    };
+
+    Touch {
+        phase: conv_touch_phase(touch.phase),
+        position: dvec2(touch.location.x, touch.location.y),
+        pressure: touch.force.map(|force| force.normalized()),
+        altitude_angle,
+        id: touch.id,
+    }
+}
+
 fn conv_mouse_button(button: winit::event::MouseButton) -> MouseButton {
     use winit::event::MouseButton as Winit;
     use MouseButton as Schema;
@@ -0,0 +1,147 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::io::ErrorKind;
+
+use clap::Args;
+use hearth_runtime::audit::{summarize, AuditSnapshot, CAP_AUDIT_FILE};
+use hearth_runtime::snapshot;
+use hearth_schema::audit::{CapAuditEvent, CapAuditRecord};
+use hearth_schema::{Permissions, ProcessId};
+
+use super::*;
+
+/// Arguments for querying the capability audit log.
+#[derive(Debug, Args)]
+pub struct AuditArgs {
+    /// Only show operations involving this process ID.
+    #[clap(short, long)]
+    pub pid: Option<u32>,
+
+    /// The maximum number of operations to show.
+    #[clap(short, long, default_value_t = 50)]
+    pub limit: usize,
+
+    /// Show the totals of each process instead of individual operations.
+    #[clap(short, long)]
+    pub summary: bool,
+}
+
+impl AuditArgs {
+    pub async fn run(self) -> CommandResult<()> {
+        let path = hearth_runtime::get_data_dir().join(CAP_AUDIT_FILE);
+        let snapshot = match snapshot::read::<AuditSnapshot>(&path) {
+            Ok(snapshot) => snapshot,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(CommandError {
+                    message: "no capability audit log found; is a server running?".to_string(),
+                    exit_code: EX_NOINPUT,
+                });
+            }
+            Err(err) => return Err(err).to_command_error("reading audit log", EX_IOERR),
+        };
+
+        let pid = self.pid.map(ProcessId);
+        let records = snapshot
+            .records
+            .iter()
+            .filter(|record| pid.map(|pid| record.involves(pid)).unwrap_or(true));
+
+        if self.summary {
+            println!(
+                "{:>6} {:<24} {:>8} {:>8} {:>8} {:>8}",
+                "PID", "NAME", "SENDS", "GIVEN", "RECEIVED", "KILLS"
+            );
+
+            for process in summarize(records) {
+                println!(
+                    "{:>6} {:<24} {:>8} {:>8} {:>8} {:>8}",
+                    process.pid.0,
+                    process.label.as_deref().unwrap_or("-"),
+                    process.sends,
+                    process.given,
+                    process.received,
+                    process.kills
+                );
+            }
+
+            return Ok(());
+        }
+
+        let records: Vec<_> = records.collect();
+        let skip = records.len().saturating_sub(self.limit);
+
+        println!("{:>8} {:>6} {:<24} OPERATION", "SEQ", "PID", "NAME");
+        for record in &records[skip..] {
+            println!(
+                "{:>8} {:>6} {:<24} {}",
+                record.seq,
+                record.pid.0,
+                record.label.as_deref().unwrap_or("-"),
+                format_event(record)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a record's operation for display.
+fn format_event(record: &CapAuditRecord) -> String {
+    match &record.event {
+        CapAuditEvent::Send { target, caps } if caps.is_empty() => {
+            format!("send to {}", format_perms(*target))
+        }
+        CapAuditEvent::Send { target, caps } => {
+            format!(
+                "send to {} with [{}]",
+                format_perms(*target),
+                format_caps(caps)
+            )
+        }
+        CapAuditEvent::Grant { from, caps } => {
+            let from = match from {
+                Some(pid) => format!("PID {}", pid.0),
+                None => "unknown".to_string(),
+            };
+
+            format!("received [{}] from {}", format_caps(caps), from)
+        }
+        CapAuditEvent::Kill { target } => format!("kill {}", format_perms(*target)),
+    }
+}
+
+/// Formats a list of capabilities by their permissions.
+fn format_caps(caps: &[Permissions]) -> String {
+    caps.iter()
+        .map(|perms| format_perms(*perms))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats permissions as a compact flag string, like `sm-`.
+fn format_perms(perms: Permissions) -> String {
+    [
+        (Permissions::SEND, 's'),
+        (Permissions::MONITOR, 'm'),
+        (Permissions::KILL, 'k'),
+    ]
+    .iter()
+    .map(|(flag, c)| if perms.contains(*flag) { *c } else { '-' })
+    .collect()
+}
@@ -0,0 +1,153 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use hearth_backup::BackupConfig;
+
+use super::*;
+
+/// Arguments for locating the backup configuration.
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    /// A configuration file to use if not the default one.
+    #[clap(short, long)]
+    pub config: Option<PathBuf>,
+}
+
+impl ConfigArgs {
+    /// Loads the backup configuration.
+    ///
+    /// Falls back to the default configuration if the default config file is
+    /// missing, but fails if an explicitly given one is.
+    pub fn load(&self) -> CommandResult<BackupConfig> {
+        let path = match self.config.as_ref() {
+            Some(path) => path.to_owned(),
+            None => {
+                let path = hearth_runtime::get_config_path();
+                if !path.exists() {
+                    return Ok(BackupConfig::default());
+                }
+
+                path
+            }
+        };
+
+        let config =
+            hearth_runtime::load_config(&path).to_command_error("loading config", EX_CONFIG)?;
+        Ok(BackupConfig::from_config_file(&config))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupCommands {
+    /// Archives the server's data immediately and prunes old archives.
+    Now(ConfigArgs),
+
+    /// Lists all backup archives from oldest to newest.
+    List(ConfigArgs),
+}
+
+impl BackupCommands {
+    pub async fn run(self) -> CommandResult<()> {
+        match self {
+            BackupCommands::Now(args) => {
+                let config = args.load()?;
+
+                let info = config
+                    .create()
+                    .map_err(|err| format!("{err:#}"))
+                    .to_command_error("creating backup", EX_IOERR)?;
+
+                config
+                    .prune()
+                    .map_err(|err| format!("{err:#}"))
+                    .to_command_error("pruning backups", EX_IOERR)?;
+
+                println!("{}", config.destination.join(info.name).display());
+            }
+            BackupCommands::List(args) => {
+                let config = args.load()?;
+
+                let backups = config
+                    .list()
+                    .map_err(|err| format!("{err:#}"))
+                    .to_command_error("listing backups", EX_IOERR)?;
+
+                println!("{:<40} {:>12}", "NAME", "SIZE");
+                for backup in backups {
+                    println!("{:<40} {:>12}", backup.name, backup.size);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Arguments for the restore command.
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// The path to the archive, or the name of an archive in the backup
+    /// directory.
+    pub archive: PathBuf,
+
+    /// Restore even if a Hearth daemon appears to be running.
+    #[clap(short, long)]
+    pub force: bool,
+
+    #[clap(flatten)]
+    pub config: ConfigArgs,
+}
+
+impl RestoreArgs {
+    pub async fn run(self) -> CommandResult<()> {
+        let config = self.config.load()?;
+
+        let archive = if self.archive.exists() {
+            self.archive
+        } else {
+            let path = config.destination.join(&self.archive);
+            if !path.exists() {
+                return Err(CommandError {
+                    message: format!("archive {:?} not found", self.archive),
+                    exit_code: EX_NOINPUT,
+                });
+            }
+
+            path
+        };
+
+        if !self.force && hearth_ipc::connect().await.is_ok() {
+            return Err(CommandError {
+                message: "a Hearth daemon is running; stop it before restoring".to_string(),
+                exit_code: EX_TEMPFAIL,
+            });
+        }
+
+        config
+            .restore(&archive)
+            .map_err(|err| format!("{err:#}"))
+            .to_command_error("restoring backup", EX_IOERR)?;
+
+        println!("Restored {}", archive.display());
+
+        Ok(())
+    }
+}
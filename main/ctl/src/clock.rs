@@ -0,0 +1,70 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::Subcommand;
+use hearth_schema::time::{ClockAdminRequest, ClockState, CLOCK_ADMIN_SERVICE_NAME};
+
+use super::*;
+use crate::daemon::Daemon;
+
+#[derive(Debug, Subcommand)]
+pub enum ClockCommands {
+    /// Shows the virtual clock's time, tick, scale, and whether it's paused.
+    Status,
+
+    /// Stops the virtual clock from advancing on its own.
+    Pause,
+
+    /// Lets the virtual clock advance on its own again.
+    Resume,
+
+    /// Sets the rate that the virtual clock advances at compared to
+    /// wall-clock time.
+    Scale {
+        /// The new rate. 0.5 is half speed and 2 is double speed.
+        scale: f32,
+    },
+
+    /// Advances the virtual clock by a number of ticks.
+    Step {
+        /// The number of ticks to step by.
+        #[clap(default_value_t = 1)]
+        ticks: u32,
+    },
+}
+
+impl ClockCommands {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let request = match self {
+            ClockCommands::Status => ClockAdminRequest::GetState,
+            ClockCommands::Pause => ClockAdminRequest::Pause,
+            ClockCommands::Resume => ClockAdminRequest::Resume,
+            ClockCommands::Scale { scale } => ClockAdminRequest::SetScale { scale },
+            ClockCommands::Step { ticks } => ClockAdminRequest::Step { ticks },
+        };
+
+        let service = daemon.get_service(CLOCK_ADMIN_SERVICE_NAME).await?;
+        let (state, _): (ClockState, _) = daemon.request(&service, &request, &[]).await?;
+
+        println!("Time:    {:.3}s", state.time);
+        println!("Tick:    {}", state.index);
+        println!("Scale:   {}x", state.scale);
+        println!("Paused:  {}", state.paused);
+        Ok(())
+    }
+}
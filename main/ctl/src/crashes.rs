@@ -0,0 +1,149 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::Args;
+use hearth_schema::wasm::{
+    CrashReport, CrashReportRequest, CrashReportResponse, CrashReportSuccess,
+    CRASH_REPORTS_SERVICE_NAME,
+};
+use hearth_schema::ProcessId;
+
+use super::*;
+use crate::daemon::Daemon;
+use crate::logs::format_time;
+
+/// Arguments for showing crash reports.
+#[derive(Debug, Args)]
+pub struct CrashesArgs {
+    /// The ID of a process to show the most recent crash report of. Lists
+    /// every crash report if not given.
+    pub pid: Option<u32>,
+
+    /// Print the reports as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+impl CrashesArgs {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let service = daemon.get_service(CRASH_REPORTS_SERVICE_NAME).await?;
+        let request = match self.pid {
+            Some(pid) => CrashReportRequest::Get {
+                pid: ProcessId(pid),
+            },
+            None => CrashReportRequest::List,
+        };
+
+        let (response, _): (CrashReportResponse, _) =
+            daemon.request(&service, &request, &[]).await?;
+
+        let response = response
+            .map_err(|err| format!("{:?}", err))
+            .to_command_error("getting crash reports", EX_NOINPUT)?;
+
+        if self.json {
+            let json = match &response {
+                CrashReportSuccess::Report(report) => serde_json::to_string_pretty(report),
+                CrashReportSuccess::Reports(reports) => serde_json::to_string_pretty(reports),
+            };
+
+            println!("{}", json.unwrap());
+            return Ok(());
+        }
+
+        match response {
+            CrashReportSuccess::Report(report) => print_report(&report),
+            CrashReportSuccess::Reports(reports) => {
+                println!("{:<12} {:>6} {:<32} REASON", "TIME", "PID", "NAME");
+                for report in reports {
+                    println!(
+                        "{:<12} {:>6} {:<32} {}",
+                        format_time(report.time),
+                        report.pid.0,
+                        report.name.as_deref().unwrap_or("-"),
+                        reason(&report)
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets a one-line summary of why a process crashed.
+fn reason(report: &CrashReport) -> &str {
+    match &report.trap {
+        Some(trap) => trap,
+        None => report.error.lines().next().unwrap_or_default(),
+    }
+}
+
+/// Prints every detail of a crash report.
+fn print_report(report: &CrashReport) {
+    println!("PID:     {}", report.pid.0);
+    println!("Name:    {}", report.name.as_deref().unwrap_or("-"));
+    println!("Lump:    {}", report.lump);
+    println!("Time:    {}", format_time(report.time));
+    println!("Reason:  {}", reason(report));
+
+    if let Some(recording) = &report.recording {
+        println!("Replay:  hearth-ctl replay {}", recording);
+    }
+
+    println!();
+    println!("Error:");
+    println!("  {}", report.error);
+
+    if !report.backtrace.is_empty() {
+        println!();
+        println!("Backtrace:");
+        for (index, frame) in report.backtrace.iter().enumerate() {
+            let offset = frame
+                .module_offset
+                .map(|offset| format!(" @ {:#x}", offset))
+                .unwrap_or_default();
+
+            match &frame.func_name {
+                Some(name) => println!("  {:>3}: {}{}", index, name, offset),
+                None => println!("  {:>3}: <function {}>{}", index, frame.func_index, offset),
+            }
+        }
+    }
+
+    if !report.messages.is_empty() {
+        println!();
+        println!("Last received messages:");
+        for message in report.messages.iter() {
+            let truncated = if message.preview.len() < message.len {
+                "..."
+            } else {
+                ""
+            };
+
+            println!(
+                "  {} ({} bytes, {} caps) {}{}",
+                format_time(message.time),
+                message.len,
+                message.caps,
+                message.preview.escape_debug(),
+                truncated
+            );
+        }
+    }
+}
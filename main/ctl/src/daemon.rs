@@ -0,0 +1,376 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use hearth_runtime::connection::Connection as CapConnection;
+use hearth_runtime::flue::{
+    CapabilityRef, MailboxGroup, OwnedCapability, OwnedTableSignal, Permissions, PostOffice, Table,
+};
+use hearth_schema::codec;
+use hearth_schema::logs::{
+    FollowedLogEntry, ProcessLogError, ProcessLogRequest, ProcessLogResponse, ProcessLogSuccess,
+};
+use hearth_schema::process::{
+    ProcessEntry, ProcessFilter, ProcessStoreRequest, ProcessStoreResponse, ProcessStoreSuccess,
+    SERVICE_NAME as PROCESS_STORE_SERVICE_NAME,
+};
+use hearth_schema::registry::{RegistryRequest, RegistryResponse, PEER_REGISTRY_SERVICE_NAME};
+use hearth_schema::ProcessId;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{sync::oneshot, time::timeout};
+
+use super::*;
+
+/// How long to wait for the daemon to send its root capability.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a reply to a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The state shared between the commands run in one invocation of hearth-ctl.
+#[derive(Default)]
+pub struct Session {
+    daemon: Option<Daemon>,
+}
+
+impl Session {
+    /// Gets this session's daemon connection, connecting on first use.
+    pub async fn daemon(&mut self) -> CommandResult<&Daemon> {
+        if self.daemon.is_none() {
+            self.daemon = Some(Daemon::connect().await?);
+        }
+
+        Ok(self.daemon.as_ref().unwrap())
+    }
+}
+
+/// A capability-level connection to the Hearth daemon.
+pub struct Daemon {
+    table: Table,
+    root: OwnedCapability,
+    _conn: Arc<CapConnection>,
+}
+
+impl Daemon {
+    /// Connects to the daemon and waits for its root registry.
+    pub async fn connect() -> CommandResult<Self> {
+        let transport = get_daemon().await?;
+        let post = PostOffice::new();
+        let (root_tx, root_rx) = oneshot::channel();
+        let conn = CapConnection::begin(
+            post.clone(),
+            None,
+            transport.op_rx,
+            transport.op_tx,
+            Some(root_tx),
+        );
+
+        let root = timeout(CONNECT_TIMEOUT, root_rx)
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .to_command_error("daemon never sent its root registry", EX_PROTOCOL)?;
+
+        Ok(Self {
+            table: Table::new(post),
+            root,
+            _conn: conn,
+        })
+    }
+
+    /// Gets the daemon's root registry.
+    pub fn root(&self) -> &OwnedCapability {
+        &self.root
+    }
+
+    /// Looks up a service in the daemon's root registry.
+    pub async fn get_service(&self, name: &str) -> CommandResult<OwnedCapability> {
+        self.lookup(&self.root, name)
+            .await?
+            .ok_or_else(|| CommandError {
+                message: format!("daemon has no {:?} service", name),
+                exit_code: EX_PROTOCOL,
+            })
+    }
+
+    /// Looks up a service exported by the daemon's connected peers.
+    pub async fn get_peer_service(&self, name: &str) -> CommandResult<OwnedCapability> {
+        let peers = self.get_service(PEER_REGISTRY_SERVICE_NAME).await?;
+        self.lookup(&peers, name)
+            .await?
+            .ok_or_else(|| CommandError {
+                message: format!("no connected peer exports a {:?} service", name),
+                exit_code: EX_UNAVAILABLE,
+            })
+    }
+
+    /// Looks up a service in a registry.
+    async fn lookup(
+        &self,
+        registry: &OwnedCapability,
+        name: &str,
+    ) -> CommandResult<Option<OwnedCapability>> {
+        let request = RegistryRequest::Get {
+            name: name.to_string(),
+        };
+
+        let (response, caps) = self.request(registry, &request, &[]).await?;
+        match (response, caps.into_iter().next()) {
+            (RegistryResponse::Get(true), Some(cap)) => Ok(Some(cap)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Lists the names of the services in the daemon's root registry.
+    pub async fn list_services(&self) -> CommandResult<Vec<String>> {
+        let (response, _) = self
+            .request(&self.root, &RegistryRequest::List, &[])
+            .await?;
+        match response {
+            RegistryResponse::List(names) => Ok(names),
+            _ => Err(CommandError {
+                message: "unexpected reply from the root registry".to_string(),
+                exit_code: EX_PROTOCOL,
+            }),
+        }
+    }
+
+    /// Gets a capability to kill and monitor a local process by its ID.
+    pub async fn get_process(&self, pid: u32) -> CommandResult<OwnedCapability> {
+        let store = self.get_service(PROCESS_STORE_SERVICE_NAME).await?;
+        let request = ProcessStoreRequest::Get {
+            pid: ProcessId(pid),
+        };
+
+        let (response, caps): (ProcessStoreResponse, _) =
+            self.request(&store, &request, &[]).await?;
+
+        match (response, caps.into_iter().next()) {
+            (Ok(ProcessStoreSuccess::Get), Some(cap)) => Ok(cap),
+            _ => Err(CommandError {
+                message: format!("no process with PID {}", pid),
+                exit_code: EX_NOINPUT,
+            }),
+        }
+    }
+
+    /// Lists the local processes that match a filter, sorted by PID.
+    pub async fn list_processes(&self, filter: ProcessFilter) -> CommandResult<Vec<ProcessEntry>> {
+        let store = self.get_service(PROCESS_STORE_SERVICE_NAME).await?;
+        let request = ProcessStoreRequest::List { filter };
+        let (response, _): (ProcessStoreResponse, _) = self.request(&store, &request, &[]).await?;
+
+        match response {
+            Ok(ProcessStoreSuccess::List(processes)) => Ok(processes),
+            _ => Err(CommandError {
+                message: "unexpected reply from the process store".to_string(),
+                exit_code: EX_PROTOCOL,
+            }),
+        }
+    }
+
+    /// Reads at most `limit` past log events of each of the given processes,
+    /// or of every process with stored logs if none are given, oldest first.
+    pub async fn read_logs(
+        &self,
+        service: &OwnedCapability,
+        pids: &[ProcessId],
+        limit: u32,
+    ) -> CommandResult<Vec<FollowedLogEntry>> {
+        let pids = match pids {
+            [] => {
+                let request = ProcessLogRequest::List;
+                let (response, _): (ProcessLogResponse, _) =
+                    self.request(service, &request, &[]).await?;
+                match response {
+                    Ok(ProcessLogSuccess::Processes(pids)) => pids,
+                    response => return Err(log_error(response)),
+                }
+            }
+            pids => pids.to_vec(),
+        };
+
+        let mut events = Vec::new();
+        for pid in pids {
+            let request = ProcessLogRequest::Get { pid, limit };
+            let (response, _): (ProcessLogResponse, _) =
+                self.request(service, &request, &[]).await?;
+            match response {
+                Ok(ProcessLogSuccess::Events(entries)) => events.extend(
+                    entries
+                        .into_iter()
+                        .map(|entry| FollowedLogEntry { pid, entry }),
+                ),
+                // the process hasn't logged anything yet
+                Err(ProcessLogError::NoLogs) => {}
+                response => return Err(log_error(response)),
+            }
+        }
+
+        // stable, so each process's events stay in order
+        events.sort_by_key(|event| event.entry.time);
+        Ok(events)
+    }
+
+    /// Follows the log events of the given processes, or of every process if
+    /// none are given.
+    ///
+    /// Subscribes to new events, then passes at most `limit` past events of
+    /// each process to `on_history`, then passes each new event to
+    /// `on_event` until it returns false. New events that were already read
+    /// as past events are skipped.
+    pub async fn follow_logs(
+        &self,
+        service: &OwnedCapability,
+        pids: &[ProcessId],
+        limit: u32,
+        on_history: impl FnOnce(&[FollowedLogEntry]),
+        mut on_event: impl FnMut(FollowedLogEntry) -> bool,
+    ) -> CommandResult<()> {
+        let group = MailboxGroup::new(&self.table);
+        let subscriber = group.create_mailbox().unwrap();
+        let subscriber_cap = subscriber.export(Permissions::SEND).unwrap().to_owned();
+
+        // subscribe before reading the history so that no events are missed
+        let request = ProcessLogRequest::Follow {
+            pids: pids.to_vec(),
+        };
+
+        let (response, _): (ProcessLogResponse, _) =
+            self.request(service, &request, &[&subscriber_cap]).await?;
+        match response {
+            Ok(ProcessLogSuccess::Following) => {}
+            response => return Err(log_error(response)),
+        }
+
+        let history = self.read_logs(service, pids, limit).await?;
+        on_history(&history);
+
+        let latest = history.last().map(|event| event.entry.time);
+        loop {
+            let Some(OwnedTableSignal::Message { data, .. }) = subscriber.recv_owned().await else {
+                return Err(CommandError {
+                    message: "daemon closed the connection".to_string(),
+                    exit_code: EX_PROTOCOL,
+                });
+            };
+
+            let event: FollowedLogEntry =
+                codec::decode(&data).to_command_error("decoding log event", EX_PROTOCOL)?;
+
+            // events logged while the history was read are in both
+            if latest.is_some_and(|latest| event.entry.time <= latest) && history.contains(&event) {
+                continue;
+            }
+
+            if !on_event(event) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Kills a capability.
+    pub fn kill(&self, cap: &OwnedCapability) -> CommandResult<()> {
+        self.import(cap)
+            .kill()
+            .map_err(|err| format!("{err:?}"))
+            .to_command_error("killing capability", EX_PROTOCOL)
+    }
+
+    /// Sends a message to a capability without expecting a reply.
+    ///
+    /// Returns once the daemon has answered a request sent after the
+    /// message, so that the message isn't lost if hearth-ctl exits right
+    /// away.
+    pub async fn send(&self, target: &OwnedCapability, data: &[u8]) -> CommandResult<()> {
+        self.import(target)
+            .send(data, &[])
+            .await
+            .map_err(|err| format!("{err:?}"))
+            .to_command_error("sending message", EX_PROTOCOL)?;
+
+        self.list_services().await?;
+        Ok(())
+    }
+
+    /// Sends a JSON request to a capability and waits for its reply.
+    ///
+    /// A reply capability is sent as the first capability of the message,
+    /// followed by `caps`.
+    pub async fn request<T, R>(
+        &self,
+        target: &OwnedCapability,
+        request: &T,
+        caps: &[&OwnedCapability],
+    ) -> CommandResult<(R, Vec<OwnedCapability>)>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        let group = MailboxGroup::new(&self.table);
+        let reply = group.create_mailbox().unwrap();
+        let reply_cap = reply.export(Permissions::SEND).unwrap();
+
+        let target = self.import(target);
+        let caps: Vec<_> = caps.iter().map(|cap| self.import(cap)).collect();
+        let args: Vec<_> = std::iter::once(&reply_cap).chain(caps.iter()).collect();
+        let data = codec::encode(request);
+        target
+            .send(&data, &args)
+            .await
+            .map_err(|err| format!("{err:?}"))
+            .to_command_error("sending request", EX_PROTOCOL)?;
+
+        let signal = timeout(REQUEST_TIMEOUT, reply.recv_owned())
+            .await
+            .to_command_error("waiting for reply", EX_TEMPFAIL)?;
+
+        let Some(OwnedTableSignal::Message { data, caps }) = signal else {
+            return Err(CommandError {
+                message: "daemon closed the connection".to_string(),
+                exit_code: EX_PROTOCOL,
+            });
+        };
+
+        let response = codec::decode(&data).to_command_error("decoding reply", EX_PROTOCOL)?;
+        let caps = caps.iter().map(|cap| cap.to_owned()).collect();
+        Ok((response, caps))
+    }
+
+    /// Imports a capability into this connection's table.
+    fn import(&self, cap: &OwnedCapability) -> CapabilityRef<'_> {
+        let handle = self.table.import_owned(cap.clone()).unwrap();
+        self.table.wrap_handle(handle).unwrap()
+    }
+}
+
+/// Converts an unexpected reply from the process log service to an error.
+fn log_error(response: ProcessLogResponse) -> CommandError {
+    match response {
+        Err(ProcessLogError::Storage(err)) => CommandError {
+            message: format!("daemon failed to read process logs: {}", err),
+            exit_code: EX_IOERR,
+        },
+        _ => CommandError {
+            message: "unexpected reply from the process log service".to_string(),
+            exit_code: EX_PROTOCOL,
+        },
+    }
+}
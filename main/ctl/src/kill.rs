@@ -0,0 +1,38 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::Args;
+
+use super::*;
+use crate::daemon::Daemon;
+
+/// Arguments for killing a process.
+#[derive(Debug, Args)]
+pub struct KillArgs {
+    /// The ID of the process to kill.
+    pub pid: u32,
+}
+
+impl KillArgs {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let process = daemon.get_process(self.pid).await?;
+        daemon.kill(&process)?;
+        println!("Killed PID {}", self.pid);
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+
+use clap::{Args, ValueEnum};
+use hearth_schema::logs::{FollowedLogEntry, SERVICE_NAME as PROCESS_LOGS_SERVICE_NAME};
+use hearth_schema::{ProcessId, ProcessLogLevel};
+
+use super::*;
+use crate::daemon::Daemon;
+
+/// The number of past events of each process that are read to find the
+/// past events that pass the filters.
+const HISTORY_LIMIT: u32 = 1000;
+
+/// Arguments for showing process logs.
+#[derive(Debug, Args)]
+pub struct LogsArgs {
+    /// The IDs of the processes to show logs from. Shows logs from every
+    /// process if none are given.
+    pub pids: Vec<u32>,
+
+    /// Show the logs of the first connected peer that exports its process
+    /// logs instead of the daemon's own.
+    #[clap(long)]
+    pub peer: bool,
+
+    /// The minimum level of events to show.
+    #[clap(short, long, value_enum, default_value = "trace")]
+    pub level: LevelArg,
+
+    /// Only show events whose module contains this text.
+    #[clap(short, long)]
+    pub module: Option<String>,
+
+    /// The number of past events to show before following new ones.
+    #[clap(short = 'n', long, default_value_t = 20)]
+    pub lines: usize,
+
+    /// Exit after showing past events instead of following new ones.
+    #[clap(long)]
+    pub no_follow: bool,
+
+    /// When to color the output.
+    #[clap(long, value_enum, default_value = "auto")]
+    pub color: ColorChoice,
+}
+
+/// A minimum log level to show.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum LevelArg {
+    Trace,
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl From<LevelArg> for ProcessLogLevel {
+    fn from(level: LevelArg) -> Self {
+        match level {
+            LevelArg::Trace => ProcessLogLevel::Trace,
+            LevelArg::Debug => ProcessLogLevel::Debug,
+            LevelArg::Info => ProcessLogLevel::Info,
+            LevelArg::Warning => ProcessLogLevel::Warning,
+            LevelArg::Error => ProcessLogLevel::Error,
+        }
+    }
+}
+
+/// When to color the output.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ColorChoice {
+    /// Color the output if it's a terminal.
+    Auto,
+
+    /// Always color the output.
+    Always,
+
+    /// Never color the output.
+    Never,
+}
+
+impl LogsArgs {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let service = match self.peer {
+            false => daemon.get_service(PROCESS_LOGS_SERVICE_NAME).await?,
+            true => daemon.get_peer_service(PROCESS_LOGS_SERVICE_NAME).await?,
+        };
+
+        let pids: Vec<_> = self.pids.iter().copied().map(ProcessId).collect();
+        let limit = HISTORY_LIMIT.max(self.lines as u32);
+        let color = match self.color {
+            ColorChoice::Auto => std::io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+
+        let show_history = |history: &[FollowedLogEntry]| {
+            let past: Vec<_> = history.iter().filter(|event| self.matches(event)).collect();
+            let skip = past.len().saturating_sub(self.lines);
+            for event in &past[skip..] {
+                println!("{}", format_event(event, color));
+            }
+        };
+
+        if self.no_follow {
+            let history = daemon.read_logs(&service, &pids, limit).await?;
+            show_history(&history);
+            return Ok(());
+        }
+
+        daemon
+            .follow_logs(&service, &pids, limit, show_history, |event| {
+                if self.matches(&event) {
+                    println!("{}", format_event(&event, color));
+                }
+
+                true
+            })
+            .await
+    }
+
+    /// Tests if an event passes this command's filters.
+    fn matches(&self, event: &FollowedLogEntry) -> bool {
+        let min_level = u32::from(ProcessLogLevel::from(self.level));
+
+        (self.pids.is_empty() || self.pids.contains(&event.pid.0))
+            && u32::from(event.entry.level) >= min_level
+            && self
+                .module
+                .as_ref()
+                .map(|module| event.entry.module.contains(module))
+                .unwrap_or(true)
+    }
+}
+
+/// Formats a log event for display, optionally with ANSI colors.
+fn format_event(event: &FollowedLogEntry, color: bool) -> String {
+    let entry = &event.entry;
+    let time = format_time(entry.time);
+    let (level, code) = match entry.level {
+        ProcessLogLevel::Trace => ("TRACE", "35"),
+        ProcessLogLevel::Debug => ("DEBUG", "34"),
+        ProcessLogLevel::Info => ("INFO", "32"),
+        ProcessLogLevel::Warning => ("WARN", "33"),
+        ProcessLogLevel::Error => ("ERROR", "31"),
+    };
+
+    let level = format!("{:>5}", level);
+    let process = match &entry.name {
+        Some(name) => format!("{} {}", event.pid.0, name),
+        None => event.pid.0.to_string(),
+    };
+
+    let fields = format_fields(&entry.fields);
+
+    if color {
+        format!(
+            "\x1b[2m{}\x1b[0m \x1b[{}m{}\x1b[0m [{}] \x1b[2m{}:\x1b[0m {}\x1b[2m{}\x1b[0m",
+            time, code, level, process, entry.module, entry.content, fields
+        )
+    } else {
+        format!(
+            "{} {} [{}] {}: {}{}",
+            time, level, process, entry.module, entry.content, fields
+        )
+    }
+}
+
+/// Formats a log event's structured fields as space-prefixed `key=value`
+/// pairs, or an empty string if there are none.
+pub fn format_fields(fields: &BTreeMap<String, String>) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| format!(" {}={}", key, value))
+        .collect()
+}
+
+/// Formats a log event's timestamp as the UTC time of day.
+pub fn format_time(time: u64) -> String {
+    let millis = time % (24 * 60 * 60 * 1000);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
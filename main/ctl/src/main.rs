@@ -21,7 +21,41 @@ use std::{collections::HashMap, fmt::Display, process::ExitCode};
 use clap::{Parser, Subcommand};
 use hearth_ipc::Connection;
 
+use audit::AuditArgs;
+use backup::{BackupCommands, RestoreArgs};
+use clock::ClockCommands;
+use crashes::CrashesArgs;
+use daemon::Session;
+use kill::KillArgs;
+use logs::LogsArgs;
+use ps::PsArgs;
+use replay::ReplayArgs;
+use send::SendArgs;
+use spawn::SpawnArgs;
+
+mod audit;
+mod backup;
+mod clock;
+mod crashes;
+mod daemon;
+mod kill;
+mod logs;
+mod peers;
+mod ps;
+mod repl;
+mod replay;
+mod send;
+mod spawn;
+mod top;
+
+pub const EX_USAGE: u8 = 64;
+pub const EX_DATAERR: u8 = 65;
+pub const EX_NOINPUT: u8 = 66;
+pub const EX_UNAVAILABLE: u8 = 69;
+pub const EX_IOERR: u8 = 74;
+pub const EX_TEMPFAIL: u8 = 75;
 pub const EX_PROTOCOL: u8 = 76;
+pub const EX_CONFIG: u8 = 78;
 
 pub struct DaemonOffer {}
 
@@ -72,13 +106,103 @@ pub struct Args {
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
-    /// A dummy command.
-    Dummy,
+    /// Shows the server's recent capability operations.
+    ///
+    /// Each operation is a message sent, a set of capabilities received, or a
+    /// capability killed by a process. Permissions are shown as `s` for send,
+    /// `m` for monitor, and `k` for kill.
+    Audit(AuditArgs),
+
+    /// Creates and lists backups of the server's data.
+    #[clap(subcommand)]
+    Backup(BackupCommands),
+
+    /// Restores the server's data from a backup archive.
+    ///
+    /// The server must be stopped first.
+    Restore(RestoreArgs),
+
+    /// Pauses, scales, and steps the virtual clock that simulations can be
+    /// timed by.
+    #[clap(subcommand)]
+    Clock(ClockCommands),
+
+    /// Shows the crash reports of Wasm processes that failed.
+    ///
+    /// Lists every stored report unless a process ID is given, in which case
+    /// that process's most recent report is shown in full, including its Wasm
+    /// backtrace and the last messages it received.
+    Crashes(CrashesArgs),
+
+    /// Kills a process on the daemon by its ID.
+    Kill(KillArgs),
+
+    /// Shows and follows the logs of the daemon's processes.
+    ///
+    /// Follows every process at once unless specific process IDs are given.
+    /// With `--peer`, follows a connected peer's processes instead.
+    Logs(LogsArgs),
+
+    /// Lists the peers connected to the local server and their throughput.
+    Peers,
+
+    /// Lists the server's live processes.
+    ///
+    /// Filters match processes whose metadata contains the given text,
+    /// ignoring case, and can be combined.
+    Ps(PsArgs),
+
+    /// Runs commands interactively over a single daemon connection.
+    ///
+    /// Supports tab completion of commands and options, and keeps a history
+    /// of past commands in the data directory.
+    Repl,
+
+    /// Replays a recorded run of a Wasm process with its recorded signals.
+    ///
+    /// Processes spawned with `--record` store their recording in a lump when
+    /// they exit. The replayed process gets stand-in capabilities in place of
+    /// the recorded ones, so its messages go nowhere.
+    Replay(ReplayArgs),
+
+    /// Sends a JSON message to a service by name.
+    Send(SendArgs),
+
+    /// Uploads a Wasm module to the daemon and spawns it as a new process.
+    ///
+    /// The process is given a registry of the daemon's services as its first
+    /// capability, like the services started by init.
+    Spawn(SpawnArgs),
+
+    /// Shows a live dashboard of the server's processes, logs, services, lump
+    /// store, and network peers.
+    ///
+    /// Use the arrow keys to pick a process to follow the logs of, escape to
+    /// follow every process again, and `q` to quit.
+    Top,
 }
 
 impl Commands {
-    pub async fn run(self) -> CommandResult<()> {
-        Ok(())
+    pub async fn run(self, session: &mut Session) -> CommandResult<()> {
+        match self {
+            Commands::Audit(args) => args.run().await,
+            Commands::Backup(command) => command.run().await,
+            Commands::Restore(args) => args.run().await,
+            Commands::Clock(command) => command.run(session.daemon().await?).await,
+            Commands::Crashes(args) => args.run(session.daemon().await?).await,
+            Commands::Kill(args) => args.run(session.daemon().await?).await,
+            Commands::Logs(args) => args.run(session.daemon().await?).await,
+            Commands::Peers => peers::list_peers().await,
+            Commands::Ps(args) => args.run(session.daemon().await?).await,
+            Commands::Replay(args) => args.run(session.daemon().await?).await,
+            Commands::Repl => Err(CommandError {
+                message: "already running a REPL".to_string(),
+                exit_code: EX_USAGE,
+            }),
+            Commands::Send(args) => args.run(session.daemon().await?).await,
+            Commands::Spawn(args) => args.run(session.daemon().await?).await,
+            Commands::Top => top::run(session).await,
+        }
     }
 }
 
@@ -86,7 +210,12 @@ impl Commands {
 async fn main() -> ExitCode {
     let args = Args::parse();
 
-    match args.command.run().await {
+    let result = match args.command {
+        Commands::Repl => repl::run().await,
+        command => command.run(&mut Session::default()).await,
+    };
+
+    match result {
         Ok(_) => 0,
         Err(e) => {
             eprintln!("ERROR: {}", e.message);
@@ -0,0 +1,69 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use hearth_network::stats::{StatsFile, STATS_FILE};
+use hearth_runtime::snapshot;
+
+use super::*;
+
+/// Stats older than this were likely left behind by a server that has exited.
+const STALE_AFTER: Duration = Duration::from_secs(5);
+
+/// Lists the peers connected to the local server and their throughput.
+pub async fn list_peers() -> CommandResult<()> {
+    let path = hearth_runtime::get_data_dir().join(STATS_FILE);
+    let stats = match snapshot::read::<StatsFile>(&path) {
+        Ok(stats) => stats,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(CommandError {
+                message: "no network stats found; is a server listening?".to_string(),
+                exit_code: EX_NOINPUT,
+            });
+        }
+        Err(err) => return Err(err).to_command_error("reading network stats", EX_IOERR),
+    };
+
+    if stats.age() > STALE_AFTER {
+        eprintln!(
+            "WARNING: network stats were last updated {}s ago; the server may have exited",
+            stats.age().as_secs()
+        );
+    }
+
+    println!(
+        "{:<24} {:>10} {:>12} {:>12} {:>14} {:>14}",
+        "ADDRESS", "CONNECTED", "UP (B/s)", "DOWN (B/s)", "SENT", "RECEIVED"
+    );
+
+    for peer in stats.peers {
+        println!(
+            "{:<24} {:>9}s {:>12.0} {:>12.0} {:>14} {:>14}",
+            peer.address,
+            peer.connected_secs,
+            peer.send_rate,
+            peer.recv_rate,
+            peer.totals.bytes_sent,
+            peer.totals.bytes_received
+        );
+    }
+
+    Ok(())
+}
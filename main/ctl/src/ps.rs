@@ -0,0 +1,108 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::{Args, ValueEnum};
+use hearth_schema::process::ProcessFilter;
+
+use super::*;
+use crate::daemon::Daemon;
+
+/// Arguments for listing processes.
+#[derive(Debug, Args)]
+pub struct PsArgs {
+    /// Only show processes whose name contains this text.
+    #[clap(long)]
+    pub name: Option<String>,
+
+    /// Only show processes whose description contains this text.
+    #[clap(long)]
+    pub description: Option<String>,
+
+    /// Only show processes with an author containing this text.
+    #[clap(long)]
+    pub author: Option<String>,
+
+    /// Only show processes loaded from a lump whose ID starts with this hex
+    /// prefix.
+    #[clap(long)]
+    pub lump: Option<String>,
+
+    /// The field to sort processes by.
+    #[clap(long, value_enum, default_value = "pid")]
+    pub sort: SortKey,
+
+    /// Print the matching processes as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// A field to sort processes by.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SortKey {
+    Pid,
+    Name,
+}
+
+impl PsArgs {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let filter = ProcessFilter {
+            name: self.name,
+            description: self.description,
+            author: self.author,
+            lump: self.lump,
+        };
+
+        let mut processes = daemon.list_processes(filter).await?;
+
+        match self.sort {
+            SortKey::Pid => processes.sort_by_key(|process| process.pid),
+            SortKey::Name => processes.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+
+        if self.json {
+            let json = serde_json::to_string_pretty(&processes)
+                .to_command_error("serializing process list", EX_IOERR)?;
+            println!("{}", json);
+            return Ok(());
+        }
+
+        println!("{:>6} {:<32} {:<8} AUTHORS", "PID", "NAME", "LUMP");
+        for process in processes {
+            let lump = process
+                .lump
+                .map(|lump| lump.to_string()[..8].to_string())
+                .unwrap_or_else(|| "-".to_string());
+
+            let authors = if process.authors.is_empty() {
+                "-".to_string()
+            } else {
+                process.authors.join(", ")
+            };
+
+            println!(
+                "{:>6} {:<32} {:<8} {}",
+                process.pid.0,
+                process.name.as_deref().unwrap_or("-"),
+                lump,
+                authors
+            );
+        }
+
+        Ok(())
+    }
+}
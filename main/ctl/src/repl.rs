@@ -0,0 +1,192 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::{CommandFactory, Parser};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+
+use super::*;
+use crate::daemon::Session;
+
+/// The name of the REPL's history file within the data directory.
+const HISTORY_FILE: &str = "ctl-history";
+
+/// The words that quit the REPL.
+const EXIT_WORDS: &[&str] = &["exit", "quit"];
+
+/// A line of input to the REPL.
+#[derive(Debug, Parser)]
+#[clap(no_binary_name = true)]
+struct ReplLine {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+/// Completes command names and their options.
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct ReplHelper {
+    /// Each command's name and the words that can follow it.
+    commands: Vec<(String, Vec<String>)>,
+}
+
+impl ReplHelper {
+    fn new() -> Self {
+        let commands = ReplLine::command()
+            .get_subcommands()
+            .map(|command| {
+                let subcommands = command
+                    .get_subcommands()
+                    .map(|sub| sub.get_name().to_string());
+                let options = command
+                    .get_arguments()
+                    .filter_map(|arg| arg.get_long())
+                    .map(|long| format!("--{}", long));
+
+                let words = subcommands.chain(options).collect();
+                (command.get_name().to_string(), words)
+            })
+            .collect();
+
+        Self { commands }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let word = &line[start..];
+
+        let candidates: Vec<&str> = match line[..start].split_whitespace().next() {
+            None => self
+                .commands
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .chain(["help"])
+                .chain(EXIT_WORDS.iter().copied())
+                .collect(),
+            Some(command) => self
+                .commands
+                .iter()
+                .find(|(name, _)| name == command)
+                .map(|(_, words)| words.iter().map(String::as_str).collect())
+                .unwrap_or_default(),
+        };
+
+        let pairs = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.to_string(),
+                replacement: format!("{} ", candidate),
+            })
+            .collect();
+
+        Ok((start, pairs))
+    }
+}
+
+/// Runs commands from interactive input until the user quits.
+///
+/// All commands share one daemon connection. Ctrl+C stops the running
+/// command, such as a followed log, without quitting.
+pub async fn run() -> CommandResult<()> {
+    let mut editor: Editor<ReplHelper, DefaultHistory> =
+        Editor::new().to_command_error("starting line editor", EX_IOERR)?;
+    editor.set_helper(Some(ReplHelper::new()));
+
+    let history = hearth_runtime::get_data_dir().join(HISTORY_FILE);
+    let _ = editor.load_history(&history);
+
+    println!("Enter a command, \"help\" for a list of commands, or \"exit\" to quit.");
+
+    let mut session = Session::default();
+    loop {
+        // read on a blocking thread so that the daemon connection keeps going
+        let (returned, line) = tokio::task::spawn_blocking(move || {
+            let line = editor.readline("hearth> ");
+            (editor, line)
+        })
+        .await
+        .unwrap();
+
+        editor = returned;
+
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err).to_command_error("reading input", EX_IOERR),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(line);
+
+        if EXIT_WORDS.contains(&line) {
+            break;
+        }
+
+        let words = match shell_words::split(line) {
+            Ok(words) => words,
+            Err(err) => {
+                eprintln!("ERROR: {}", err);
+                continue;
+            }
+        };
+
+        let command = match ReplLine::try_parse_from(words) {
+            Ok(line) => line.command,
+            Err(err) => {
+                let _ = err.print();
+                continue;
+            }
+        };
+
+        let result = tokio::select! {
+            result = command.run(&mut session) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+        };
+
+        if let Err(err) = result {
+            eprintln!("ERROR: {}", err.message);
+        }
+    }
+
+    if let Some(parent) = history.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    if let Err(err) = editor.save_history(&history) {
+        eprintln!("WARNING: failed to save history: {}", err);
+    }
+
+    Ok(())
+}
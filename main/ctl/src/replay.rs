@@ -0,0 +1,78 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::Args;
+use hearth_schema::wasm::{ReplayOutcome, ReplayRequest, ReplayResponse, REPLAYER_SERVICE_NAME};
+use hearth_schema::LumpId;
+
+use super::*;
+use crate::daemon::Daemon;
+
+/// Arguments for replaying a recorded Wasm process.
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// The lump of the recording, as printed in the server's logs or in the
+    /// process's crash report.
+    #[clap(value_parser = parse_lump_id)]
+    pub recording: LumpId,
+}
+
+impl ReplayArgs {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let service = daemon.get_service(REPLAYER_SERVICE_NAME).await?;
+        let request = ReplayRequest {
+            recording: self.recording,
+        };
+
+        let (response, _): (ReplayResponse, _) = daemon.request(&service, &request, &[]).await?;
+
+        let outcome = response
+            .map_err(|err| format!("{:?}", err))
+            .to_command_error("replaying recording", EX_NOINPUT)?;
+
+        match outcome {
+            ReplayOutcome::Exhausted => {
+                println!("Replayed every recorded signal");
+            }
+            ReplayOutcome::Exited { remaining } => {
+                println!("Process exited with {} recorded signals left", remaining);
+            }
+            ReplayOutcome::Failed { error, remaining } => {
+                println!("Process failed with {} recorded signals left:", remaining);
+                println!("  {}", error);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a lump ID from its hexadecimal form.
+fn parse_lump_id(hex: &str) -> Result<LumpId, String> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err("lump IDs are 64 hexadecimal digits".to_string());
+    }
+
+    let mut id = [0u8; 32];
+    for (byte, digits) in id.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).unwrap();
+        *byte = u8::from_str_radix(digits, 16).map_err(|err| err.to_string())?;
+    }
+
+    Ok(LumpId(id))
+}
@@ -0,0 +1,67 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::Args;
+use serde_json::Value;
+
+use super::*;
+use crate::daemon::Daemon;
+
+/// Arguments for sending a JSON message.
+#[derive(Debug, Args)]
+pub struct SendArgs {
+    /// The name of the service to send to.
+    ///
+    /// Processes can't be sent to by their IDs, because the process store
+    /// only hands out capabilities to kill and monitor them.
+    pub target: String,
+
+    /// The JSON message to send.
+    #[clap(short, long)]
+    pub json: String,
+
+    /// Wait for a reply to the message and print it.
+    ///
+    /// A reply capability is attached to the message as its first capability,
+    /// following the request-response convention of Hearth's services.
+    #[clap(short, long)]
+    pub reply: bool,
+}
+
+impl SendArgs {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let message: Value =
+            serde_json::from_str(&self.json).to_command_error("parsing JSON", EX_DATAERR)?;
+
+        let target = daemon.get_service(&self.target).await?;
+
+        if !self.reply {
+            let data = hearth_schema::codec::encode(&message);
+            return daemon.send(&target, &data).await;
+        }
+
+        let (reply, caps): (Value, _) = daemon.request(&target, &message, &[]).await?;
+        println!("{}", serde_json::to_string_pretty(&reply).unwrap());
+
+        if !caps.is_empty() {
+            eprintln!("(reply carried {} capabilities)", caps.len());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use clap::Args;
+use hearth_schema::lump::{LumpRequest, SERVICE_NAME as LUMP_SERVICE_NAME};
+use hearth_schema::registry::*;
+use hearth_schema::wasm::{WasmSpawnInfo, SPAWNER_SERVICE_NAME};
+use hearth_schema::LumpId;
+
+use super::*;
+use crate::daemon::Daemon;
+
+/// Arguments for spawning a Wasm module.
+#[derive(Debug, Args)]
+pub struct SpawnArgs {
+    /// The path to the Wasm module to spawn.
+    pub path: PathBuf,
+
+    /// Registers the new process with the daemon's registry under this name.
+    #[clap(short, long)]
+    pub service: Option<String>,
+
+    /// A comma-separated list of the services to give the process in its
+    /// registry. Gives the process every service if not set.
+    #[clap(short, long, value_delimiter = ',')]
+    pub registry: Option<Vec<String>>,
+
+    /// The identifier of the entrypoint to execute instead of the module's
+    /// exported "run" function.
+    #[clap(short, long)]
+    pub entrypoint: Option<u32>,
+
+    /// Record the signals the process receives so that its run can be
+    /// replayed with `hearth-ctl replay` after it exits.
+    #[clap(long)]
+    pub record: bool,
+}
+
+impl SpawnArgs {
+    pub async fn run(self, daemon: &Daemon) -> CommandResult<()> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(CommandError {
+                    message: format!("{} not found", self.path.display()),
+                    exit_code: EX_NOINPUT,
+                });
+            }
+            Err(err) => return Err(err).to_command_error("reading Wasm module", EX_IOERR),
+        };
+
+        let lumps = daemon.get_service(LUMP_SERVICE_NAME).await?;
+        let request = LumpRequest::Upload { data };
+        let (lump, _): (LumpId, _) = daemon.request(&lumps, &request, &[]).await?;
+
+        let registry = match self.registry {
+            None => daemon.root().clone(),
+            Some(names) => {
+                let mut services = Vec::with_capacity(names.len());
+                for name in names.iter() {
+                    services.push(daemon.get_service(name).await?);
+                }
+
+                let factory = daemon.get_service(REGISTRY_FACTORY_SERVICE_NAME).await?;
+                let request = RegistryFactoryRequest { names };
+                let services: Vec<_> = services.iter().collect();
+                let (response, caps): (RegistryFactoryResponse, _) =
+                    daemon.request(&factory, &request, &services).await?;
+
+                response
+                    .map_err(|err| format!("{err:?}"))
+                    .to_command_error("creating registry", EX_PROTOCOL)?;
+
+                caps.into_iter()
+                    .next()
+                    .to_command_error("registry factory replied without a registry", EX_PROTOCOL)?
+            }
+        };
+
+        let spawner = daemon.get_service(SPAWNER_SERVICE_NAME).await?;
+        let request = WasmSpawnInfo {
+            lump,
+            entrypoint: self.entrypoint,
+            record: self.record,
+        };
+
+        let ((), caps) = daemon.request(&spawner, &request, &[&registry]).await?;
+        let process = caps.into_iter().next().to_command_error(
+            "failed to spawn process; see the server's logs for details",
+            EX_PROTOCOL,
+        )?;
+
+        println!("Spawned {} from lump {}", self.path.display(), lump);
+
+        let Some(name) = self.service else {
+            return Ok(());
+        };
+
+        let request = RegistryRequest::Register { name: name.clone() };
+        let (response, _) = daemon.request(daemon.root(), &request, &[&process]).await?;
+        match response {
+            RegistryResponse::Register(Some(replaced)) => {
+                if replaced {
+                    println!("Replaced service {:?}", name);
+                } else {
+                    println!("Registered service {:?}", name);
+                }
+
+                Ok(())
+            }
+            _ => Err(CommandError {
+                message: format!("the daemon's registry refused to register {:?}", name),
+                exit_code: EX_PROTOCOL,
+            }),
+        }
+    }
+}
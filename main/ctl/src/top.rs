@@ -0,0 +1,478 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::VecDeque;
+use std::io::Stdout;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use hearth_network::stats::{StatsFile, STATS_FILE};
+use hearth_runtime::lump::{LumpUsage, LUMP_USAGE_FILE};
+use hearth_runtime::process::ProcessId;
+use hearth_runtime::snapshot;
+use hearth_schema::logs::{FollowedLogEntry, SERVICE_NAME as PROCESS_LOGS_SERVICE_NAME};
+use hearth_schema::process::{ProcessEntry, ProcessFilter};
+use hearth_schema::ProcessLogLevel;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal};
+use tokio::sync::mpsc::{self, error::TryRecvError};
+
+use super::*;
+use crate::daemon::{Daemon, Session};
+use crate::logs::{format_fields, format_time};
+
+/// How often the dashboard reloads its data.
+const REFRESH_PERIOD: Duration = Duration::from_secs(1);
+
+/// The number of past log events kept for the log pane.
+const LOG_HISTORY: usize = 1000;
+
+/// Network stats older than this were likely left behind by a server that has
+/// exited.
+const STALE_AFTER: Duration = Duration::from_secs(5);
+
+/// Runs the dashboard until the user quits.
+pub async fn run(session: &mut Session) -> CommandResult<()> {
+    let mut dashboard = Dashboard::default();
+    let mut terminal = TerminalGuard::enter()?;
+
+    loop {
+        dashboard.refresh(session).await;
+
+        terminal
+            .0
+            .draw(|frame| dashboard.draw(frame))
+            .to_command_error("drawing dashboard", EX_IOERR)?;
+
+        // handle input until it's time for the next refresh
+        let deadline = Instant::now() + REFRESH_PERIOD;
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            let event = tokio::task::spawn_blocking(move || -> std::io::Result<_> {
+                match event::poll(timeout)? {
+                    true => event::read().map(Some),
+                    false => Ok(None),
+                }
+            })
+            .await
+            .unwrap()
+            .to_command_error("reading terminal input", EX_IOERR)?;
+
+            let Some(Event::Key(key)) = event else {
+                continue;
+            };
+
+            if !dashboard.on_key(key) {
+                return Ok(());
+            }
+
+            terminal
+                .0
+                .draw(|frame| dashboard.draw(frame))
+                .to_command_error("drawing dashboard", EX_IOERR)?;
+        }
+    }
+}
+
+/// Puts the terminal in raw mode on an alternate screen until dropped.
+struct TerminalGuard(Terminal<CrosstermBackend<Stdout>>);
+
+impl TerminalGuard {
+    fn enter() -> CommandResult<Self> {
+        let backend = CrosstermBackend::new(std::io::stdout());
+        let terminal = Terminal::new(backend).to_command_error("opening terminal", EX_IOERR)?;
+        enable_raw_mode().to_command_error("enabling raw terminal mode", EX_IOERR)?;
+
+        // from here on, dropping the guard restores the terminal
+        let mut guard = Self(terminal);
+        execute!(guard.0.backend_mut(), EnterAlternateScreen)
+            .to_command_error("entering alternate screen", EX_IOERR)?;
+
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(self.0.backend_mut(), LeaveAlternateScreen);
+        let _ = self.0.show_cursor();
+    }
+}
+
+/// The state of the dashboard.
+#[derive(Default)]
+struct Dashboard {
+    /// The live processes, sorted by PID.
+    processes: Vec<ProcessEntry>,
+
+    /// The ID of the selected process. The log pane follows every process
+    /// when none is selected.
+    selected: Option<ProcessId>,
+
+    /// The most recent log events of every process.
+    logs: VecDeque<FollowedLogEntry>,
+
+    /// Receives new log events from the daemon, while it's being followed.
+    log_events: Option<mpsc::UnboundedReceiver<FollowedLogEntry>>,
+
+    /// The names of the daemon's services, if the daemon is reachable.
+    services: Option<Vec<String>>,
+
+    /// The usage of the lump store, if a server is running.
+    lumps: Option<LumpUsage>,
+
+    /// The stats of the server's peers, if it is listening.
+    network: Option<StatsFile>,
+}
+
+impl Dashboard {
+    /// Reloads every pane's data.
+    async fn refresh(&mut self, session: &mut Session) {
+        let dir = hearth_runtime::get_data_dir();
+
+        // the follower starts with the past events, so start over when the
+        // daemon is followed again after a restart
+        let log_events = self.log_events.get_or_insert_with(|| {
+            self.logs.clear();
+            follow_logs()
+        });
+
+        loop {
+            match log_events.try_recv() {
+                Ok(event) => self.logs.push_back(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.log_events = None;
+                    break;
+                }
+            }
+        }
+
+        let excess = self.logs.len().saturating_sub(LOG_HISTORY);
+        self.logs.drain(..excess);
+
+        match session.daemon().await {
+            Ok(daemon) => {
+                self.processes = daemon
+                    .list_processes(ProcessFilter::default())
+                    .await
+                    .unwrap_or_default();
+
+                self.services = daemon.list_services().await.ok();
+            }
+            Err(_) => {
+                self.processes.clear();
+                self.services = None;
+            }
+        }
+
+        self.lumps = snapshot::read::<LumpUsage>(&dir.join(LUMP_USAGE_FILE)).ok();
+
+        self.network = snapshot::read::<StatsFile>(&dir.join(STATS_FILE))
+            .ok()
+            .filter(|stats| stats.age() <= STALE_AFTER);
+    }
+
+    /// Handles a key press. Returns false if the user has quit.
+    fn on_key(&mut self, key: KeyEvent) -> bool {
+        if key.kind != KeyEventKind::Press {
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Esc => self.selected = None,
+            KeyCode::Up => self.step_selection(false),
+            KeyCode::Down => self.step_selection(true),
+            _ => {}
+        }
+
+        true
+    }
+
+    /// The row of the selected process in the process table, if it's alive.
+    fn selected_row(&self) -> Option<usize> {
+        let pid = self.selected?;
+        self.processes
+            .iter()
+            .position(|process| process.pid.0 as ProcessId == pid)
+    }
+
+    /// Selects the process above or below the selected one.
+    fn step_selection(&mut self, down: bool) {
+        let Some(last) = self.processes.len().checked_sub(1) else {
+            return;
+        };
+
+        let row = match (self.selected_row(), down) {
+            (Some(row), true) => (row + 1).min(last),
+            (Some(row), false) => row.saturating_sub(1),
+            (None, true) => 0,
+            (None, false) => last,
+        };
+
+        self.selected = Some(self.processes[row].pid.0 as ProcessId);
+    }
+
+    /// Draws the whole dashboard.
+    fn draw(&self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(8),
+                Constraint::Length(8),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+
+        let top = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .split(rows[0]);
+
+        let bottom = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(35),
+                Constraint::Percentage(20),
+                Constraint::Percentage(45),
+            ])
+            .split(rows[1]);
+
+        self.draw_processes(frame, top[0]);
+        self.draw_logs(frame, top[1]);
+        self.draw_services(frame, bottom[0]);
+        self.draw_lumps(frame, bottom[1]);
+        self.draw_network(frame, bottom[2]);
+
+        let help = Paragraph::new("q: quit  ↑/↓: select process  esc: logs of every process")
+            .style(Style::default().add_modifier(Modifier::DIM));
+        frame.render_widget(help, rows[2]);
+    }
+
+    fn draw_processes(&self, frame: &mut Frame, area: Rect) {
+        let header =
+            Row::new(vec!["PID", "NAME"]).style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self.processes.iter().map(|process| {
+            Row::new(vec![
+                process.pid.0.to_string(),
+                process.name.clone().unwrap_or_else(|| "-".to_string()),
+            ])
+        });
+
+        let table = Table::new(rows)
+            .header(header)
+            .widths(&[Constraint::Length(6), Constraint::Min(8)])
+            .block(pane(format!("Processes ({})", self.processes.len())))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        let mut state = TableState::default();
+        state.select(self.selected_row());
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    fn draw_logs(&self, frame: &mut Frame, area: Rect) {
+        let title = match self.selected {
+            Some(pid) if self.selected_row().is_none() => format!("Logs: PID {} (exited)", pid),
+            Some(pid) => format!("Logs: PID {}", pid),
+            None => "Logs: every process".to_string(),
+        };
+
+        // only the most recent events that fit in the pane are shown
+        let height = area.height.saturating_sub(2) as usize;
+        let mut lines: Vec<Line> = self
+            .logs
+            .iter()
+            .rev()
+            .filter(|event| {
+                self.selected
+                    .map(|pid| pid == event.pid.0 as ProcessId)
+                    .unwrap_or(true)
+            })
+            .take(height)
+            .map(log_line)
+            .collect();
+        lines.reverse();
+
+        frame.render_widget(Paragraph::new(lines).block(pane(title)), area);
+    }
+
+    fn draw_services(&self, frame: &mut Frame, area: Rect) {
+        let (title, items) = match &self.services {
+            Some(services) => {
+                let mut names = services.clone();
+                names.sort();
+                let items = names.into_iter().map(ListItem::new).collect();
+                (format!("Services ({})", services.len()), items)
+            }
+            None => (
+                "Services".to_string(),
+                vec![unavailable("daemon unreachable")],
+            ),
+        };
+
+        frame.render_widget(List::new(items).block(pane(title)), area);
+    }
+
+    fn draw_lumps(&self, frame: &mut Frame, area: Rect) {
+        let lines = match &self.lumps {
+            Some(usage) => vec![
+                Line::from(format!("{} lumps", usage.count)),
+                Line::from(format_bytes(usage.bytes as f64)),
+            ],
+            None => vec![Line::from(Span::styled(
+                "no server running",
+                unavailable_style(),
+            ))],
+        };
+
+        frame.render_widget(
+            Paragraph::new(lines).block(pane("Lump store".to_string())),
+            area,
+        );
+    }
+
+    fn draw_network(&self, frame: &mut Frame, area: Rect) {
+        let Some(stats) = &self.network else {
+            let message = unavailable("server is not listening");
+            frame.render_widget(
+                List::new(vec![message]).block(pane("Network".to_string())),
+                area,
+            );
+            return;
+        };
+
+        let header = Row::new(vec!["ADDRESS", "UP", "DOWN"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+
+        let rows = stats.peers.iter().map(|peer| {
+            Row::new(vec![
+                peer.address.clone(),
+                format!("{}/s", format_bytes(peer.send_rate)),
+                format!("{}/s", format_bytes(peer.recv_rate)),
+            ])
+        });
+
+        let table = Table::new(rows)
+            .header(header)
+            .widths(&[
+                Constraint::Min(16),
+                Constraint::Length(11),
+                Constraint::Length(11),
+            ])
+            .block(pane(format!("Network ({} peers)", stats.peers.len())));
+
+        frame.render_widget(table, area);
+    }
+}
+
+/// Creates the bordered block around a pane.
+fn pane(title: String) -> Block<'static> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+/// The style of a message shown in place of unavailable data.
+fn unavailable_style() -> Style {
+    Style::default().add_modifier(Modifier::DIM)
+}
+
+/// A list item shown in place of unavailable data.
+fn unavailable(message: &str) -> ListItem<'static> {
+    ListItem::new(message.to_string()).style(unavailable_style())
+}
+
+/// Follows the logs of every process over a separate daemon connection, so
+/// that waiting for events doesn't hold up the dashboard's other requests.
+///
+/// The returned receiver is closed once the daemon can't be followed.
+fn follow_logs() -> mpsc::UnboundedReceiver<FollowedLogEntry> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let Ok(daemon) = Daemon::connect().await else {
+            return;
+        };
+
+        let Ok(service) = daemon.get_service(PROCESS_LOGS_SERVICE_NAME).await else {
+            return;
+        };
+
+        let on_history = |history: &[FollowedLogEntry]| {
+            for event in history {
+                let _ = tx.send(event.clone());
+            }
+        };
+
+        let on_event = |event| tx.send(event).is_ok();
+        let limit = LOG_HISTORY as u32;
+        let _ = daemon
+            .follow_logs(&service, &[], limit, on_history, on_event)
+            .await;
+    });
+
+    rx
+}
+
+/// Formats a log event as a line in the log pane.
+fn log_line(event: &FollowedLogEntry) -> Line<'static> {
+    let entry = &event.entry;
+    let (level, color) = match entry.level {
+        ProcessLogLevel::Trace => ("TRACE", Color::Magenta),
+        ProcessLogLevel::Debug => ("DEBUG", Color::Blue),
+        ProcessLogLevel::Info => ("INFO", Color::Green),
+        ProcessLogLevel::Warning => ("WARN", Color::Yellow),
+        ProcessLogLevel::Error => ("ERROR", Color::Red),
+    };
+
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    Line::from(vec![
+        Span::styled(format_time(entry.time), dim),
+        Span::raw(" "),
+        Span::styled(format!("{:>5}", level), Style::default().fg(color)),
+        Span::raw(format!(" [{}] ", event.pid.0)),
+        Span::styled(format!("{}: ", entry.module), dim),
+        Span::raw(entry.content.clone()),
+        Span::styled(format_fields(&entry.fields), dim),
+    ])
+}
+
+/// Formats a number of bytes with a binary unit prefix.
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{:.0} {}", value, UNITS[unit]),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
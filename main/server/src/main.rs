@@ -19,28 +19,66 @@
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-use hearth_network::auth::ServerAuthenticator;
+use clap::{CommandFactory, Parser};
+use hearth_fs::FsArgs;
+use hearth_network::auth::{ServerAuthenticator, UserStore, USERS_FILE};
+use hearth_network::connection::Side;
+use hearth_network::lumps::{LumpDirectory, LumpExchange, LumpSource};
+use hearth_network::profile::Profile;
+use hearth_network::stats::{PeerTracker, STATS_FILE};
+use hearth_network::transport::{Link, Transport};
+use hearth_network::{NetworkArgs, NetworkConfig};
+use hearth_rend3::{Rend3Args, Rend3Plugin};
+use hearth_runtime::async_trait;
+use hearth_runtime::audit::CapAuditService;
+use hearth_runtime::cli::CliBuilder;
 use hearth_runtime::connection::Connection;
-use hearth_runtime::flue::{OwnedCapability, PostOffice};
+use hearth_runtime::events::Shutdown;
+use hearth_runtime::flue::OwnedCapability;
+use hearth_runtime::group::ProcessGroupService;
+use hearth_runtime::hearth_schema::LumpId;
+use hearth_runtime::lump::{LumpStoreImpl, LUMP_DIR};
+use hearth_runtime::registry::FilteredRegistry;
 use hearth_runtime::runtime::Runtime;
 use hearth_runtime::runtime::{RuntimeBuilder, RuntimeConfig};
-use tokio::net::{TcpListener, TcpStream};
+use hearth_runtime::snapshot::write_snapshots;
 use tokio::sync::oneshot;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 /// The Hearth virtual space server program.
 #[derive(Parser, Debug)]
 pub struct Args {
-    /// IP address and port to listen on.
-    #[clap(short, long)]
-    pub bind: Option<SocketAddr>,
-
     /// Password to use to authenticate with clients. Defaults to empty.
+    ///
+    /// Clients that log in without a username use this shared password. With
+    /// --add-user, this is the new user's password instead.
     #[clap(short, long, default_value = "")]
     pub password: String,
 
+    /// The file of registered users.
+    ///
+    /// The default file is in the data directory, so it's included in
+    /// backups. A users.toml left in the config directory by older versions
+    /// is moved there.
+    ///
+    /// [default: <DATA DIR>/users.toml]
+    #[clap(long)]
+    pub users: Option<PathBuf>,
+
+    /// Registers a user with the password given by --password and exits.
+    #[clap(long, value_name = "USERNAME")]
+    pub add_user: Option<String>,
+
+    /// Unregisters a user and exits.
+    #[clap(long, value_name = "USERNAME")]
+    pub remove_user: Option<String>,
+
+    /// The permission profile of the user registered with --add-user.
+    #[clap(long, value_enum, default_value = "visitor")]
+    pub profile: Profile,
+
     /// A configuration file to use if not the default one.
     #[clap(short, long)]
     pub config: Option<PathBuf>,
@@ -51,38 +89,166 @@ pub struct Args {
     #[clap(short, long)]
     pub init: Option<PathBuf>,
 
-    /// A path to the guest-side filesystem root.
-    #[clap(short, long)]
-    pub root: PathBuf,
+    /// Render offscreen views, like render targets, without a window at this
+    /// many frames per second.
+    ///
+    /// Lets server-side processes render thumbnails, previews, and automated
+    /// scene tests. Disabled by default.
+    #[clap(long)]
+    pub render_rate: Option<f32>,
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    let mut cli = CliBuilder::new(Args::command());
+    cli.add_args::<NetworkArgs>()
+        .add_args::<FsArgs>()
+        .add_args::<Rend3Args>();
+    let matches = cli.parse();
+    let args: Args = matches.get();
+    let network_args: NetworkArgs = matches.get();
+    let fs_args: FsArgs = matches.get();
+    let rend3_args: Rend3Args = matches.get();
     hearth_runtime::init_logging();
 
-    let authenticator = ServerAuthenticator::from_password(args.password.as_bytes()).unwrap();
-    let authenticator = Arc::new(authenticator);
+    let users_path = args.users.unwrap_or_else(default_users_path);
+
+    let mut users = match UserStore::load(&users_path) {
+        Ok(users) => users,
+        Err(err) => {
+            error!("Failed to load users from {:?}: {:?}", users_path, err);
+            return;
+        }
+    };
+
+    if args.add_user.is_some() || args.remove_user.is_some() {
+        if let Some(username) = args.add_user {
+            if args.password.is_empty() {
+                error!("Registering a user requires a --password");
+                return;
+            }
+
+            if let Err(err) = users.add_user(&username, args.password.as_bytes(), args.profile) {
+                error!("Failed to register {:?}: {:?}", username, err);
+                return;
+            }
+
+            info!("Registered {:?} as {:?}", username, args.profile);
+        }
+
+        if let Some(username) = args.remove_user {
+            if !users.remove_user(&username) {
+                warn!("No user named {:?} is registered", username);
+            }
+        }
+
+        if let Err(err) = users.save(&users_path) {
+            error!("Failed to save users to {:?}: {:?}", users_path, err);
+        }
+
+        return;
+    }
 
     debug!("Initializing runtime");
-    let config = RuntimeConfig {};
+    let config_path = args.config.unwrap_or_else(hearth_runtime::get_config_path);
+
+    let config_file = match hearth_runtime::load_config(&config_path) {
+        Ok(config_file) => config_file,
+        Err(err) => {
+            warn!("Failed to load config file: {:?}", err);
+            Default::default()
+        }
+    };
+
+    let config = RuntimeConfig::from_config_file(&config_file);
+    let network_config = NetworkConfig::from_config_file(&config_file);
+    let mut authenticator = match ServerAuthenticator::from_users(&users) {
+        Ok(authenticator) => authenticator,
+        Err(err) => {
+            error!("Failed to load users from {:?}: {:?}", users_path, err);
+            return;
+        }
+    };
+
+    let password_profile = network_config
+        .password_profile
+        .or(users.is_empty().then_some(Profile::Admin));
+
+    if let Some(profile) = password_profile {
+        authenticator
+            .allow_password(args.password.as_bytes(), profile)
+            .unwrap();
+    }
 
     let (network_root_tx, network_root_rx) = oneshot::channel();
-    let init = args.init.unwrap_or(args.root.join("init.wasm"));
+    let init = args.init.unwrap_or(fs_args.root.join("init.wasm"));
     let mut init = hearth_init::InitPlugin::new(init);
     init.add_hook("hearth.init.Server".into(), network_root_tx);
 
     let mut builder = RuntimeBuilder::new();
-    builder.add_plugin(hearth_time::TimePlugin);
+    let lump_dir = hearth_runtime::get_data_dir().join(LUMP_DIR);
+    builder.get_lump_store().persist_to(lump_dir);
+    builder.add_plugin(hearth_time::TimePlugin::from_config_file(&config_file));
     builder.add_plugin(hearth_wasm::WasmPlugin::default());
-    builder.add_plugin(hearth_fs::FsPlugin::new(args.root));
+    builder.add_plugin(hearth_fs::FsPlugin::new(fs_args.root.clone()));
+    builder.add_plugin(hearth_fs::FsFactory::new(fs_args.root.clone()));
+    builder.add_plugin(hearth_fs::WritableFsFactory::new(
+        fs_args.root.clone(),
+        fs_args.quota,
+        vec![fs_args.root.join("init.wasm")],
+    ));
     builder.add_plugin(init);
     builder.add_plugin(hearth_daemon::DaemonPlugin::default());
+    builder.add_plugin(hearth_image_decoder::ImageDecoderService);
+    builder.add_plugin(hearth_cron::CronPlugin::from_config_file(&config_file));
+    builder.add_plugin(hearth_kv::KvPlugin::from_config_file(&config_file));
+    builder.add_plugin(hearth_logs::ProcessLogPlugin::from_config_file(
+        &config_file,
+    ));
+    builder.add_plugin(hearth_backup::BackupPlugin::new(
+        hearth_backup::BackupConfig::from_config_file(&config_file),
+    ));
+    builder.add_plugin(CapAuditService);
+    builder.add_plugin(ProcessGroupService::default());
+
+    if let Some(frame_rate) = args.render_rate {
+        match Rend3Plugin::new_headless(&rend3_args, frame_rate).await {
+            Ok(rend3) => {
+                info!("Rendering headlessly at {} frames per second", frame_rate);
+                builder.add_plugin(rend3);
+                builder.add_plugin(hearth_renderer::RendererPlugin::default());
+            }
+            Err(err) => error!("Failed to create headless renderer: {:?}", err),
+        }
+    }
+
     let runtime = builder.run(config).await;
 
-    if let Some(addr) = args.bind {
+    if runtime.audit.is_enabled() {
+        let audit = runtime.audit.clone();
+        tokio::spawn(async move { audit.write_snapshots(SNAPSHOT_PERIOD).await });
+    }
+
+    let lumps = runtime.lump_store.clone();
+    tokio::spawn(async move { lumps.write_usage(SNAPSHOT_PERIOD).await });
+
+    if let Some(addr) = network_args.listen {
+        let runtime = runtime.clone();
+        let transport = network_args.transport.transport();
+        let peers = Arc::new(PeerTracker::default());
+        tokio::spawn(write_stats(peers.clone()));
+
+        let source = Arc::new(RuntimeLumps(runtime.lump_store.clone()));
+        let directory = Arc::new(LumpDirectory::default());
+        let clients = Arc::new(Clients {
+            authenticator,
+            config: network_config,
+            peers,
+            lumps: Arc::new(LumpExchange::with_directory(source, directory)),
+        });
+
         tokio::spawn(async move {
-            bind(network_root_rx, addr, runtime.clone(), authenticator).await;
+            bind(network_root_rx, addr, transport, clients, runtime).await;
         });
     } else {
         info!("Server running in headless mode");
@@ -91,19 +257,29 @@ async fn main() {
     hearth_runtime::wait_for_interrupt().await;
 
     info!("Interrupt received; exiting server");
+    runtime.event_bus.publish(Shutdown);
+}
+
+/// The state shared by the connections to every client.
+struct Clients {
+    authenticator: ServerAuthenticator,
+    config: NetworkConfig,
+    peers: Arc<PeerTracker>,
+    lumps: Arc<LumpExchange>,
 }
 
 async fn bind(
     on_network_root: oneshot::Receiver<OwnedCapability>,
     addr: SocketAddr,
+    transport: Box<dyn Transport>,
+    clients: Arc<Clients>,
     runtime: Arc<Runtime>,
-    authenticator: Arc<ServerAuthenticator>,
 ) {
     info!("Waiting for network root cap hook");
     let network_root = on_network_root.await.unwrap();
 
     info!("Binding to {:?}", addr);
-    let listener = match TcpListener::bind(addr).await {
+    let mut listener = match transport.listen(&addr.to_string()).await {
         Ok(l) => l,
         Err(err) => {
             error!("Failed to listen: {:?}", err);
@@ -113,7 +289,7 @@ async fn bind(
 
     info!("Listening");
     loop {
-        let (socket, addr) = match listener.accept().await {
+        let (link, addr) = match listener.accept().await {
             Ok(v) => v,
             Err(err) => {
                 error!("Listening error: {:?}", err);
@@ -122,51 +298,67 @@ async fn bind(
         };
 
         info!("Connection from {:?}", addr);
-        let post = runtime.post.clone();
-        let authenticator = authenticator.clone();
+        let runtime = runtime.clone();
+        let clients = clients.clone();
         let network_root = network_root.clone();
         tokio::task::spawn(async move {
-            on_accept(post, authenticator, socket, addr, network_root).await;
+            on_accept(runtime, clients, link, addr, network_root).await;
         });
     }
 }
 
 async fn on_accept(
-    post: Arc<PostOffice>,
-    authenticator: Arc<ServerAuthenticator>,
-    mut client: TcpStream,
-    addr: SocketAddr,
+    runtime: Arc<Runtime>,
+    clients: Arc<Clients>,
+    mut link: Link,
+    addr: String,
     network_root: OwnedCapability,
 ) {
     info!("Authenticating with client {:?}", addr);
-    let session_key = match authenticator.login(&mut client).await {
-        Ok(key) => key,
+    let (session_key, identity) = match clients.authenticator.login(&mut link.stream).await {
+        Ok(login) => login,
         Err(err) => {
             error!("Authentication error: {:?}", err);
             return;
         }
     };
 
-    info!("Successfully authenticated");
-    use hearth_network::encryption::{AsyncDecryptor, AsyncEncryptor, Key};
-    let client_key = Key::from_client_session(&session_key);
-    let server_key = Key::from_server_session(&session_key);
+    info!(
+        "Successfully authenticated {:?} as {:?}",
+        identity.username, identity.profile
+    );
+    let conn = hearth_network::connection::Connection::encrypted(
+        link,
+        &session_key,
+        Side::Server,
+        &clients.config,
+    );
 
-    let (client_rx, client_tx) = tokio::io::split(client);
-    let client_rx = AsyncDecryptor::new(&client_key, client_rx);
-    let client_tx = AsyncEncryptor::new(&server_key, client_tx);
-    let conn = hearth_network::connection::Connection::new(client_rx, client_tx);
+    // exchange lumps with the client and let it serve lumps to other peers
+    let (_, conn) = clients.lumps.attach(conn, Side::Server, addr.parse().ok());
+    clients.peers.add(addr, &conn.stats);
 
     let (root_cap_tx, client_root) = tokio::sync::oneshot::channel();
 
     info!("Beginning connection");
-    let conn = Connection::begin(post, conn.op_rx, conn.op_tx, Some(root_cap_tx));
+    let conn = Connection::begin(
+        runtime.post.clone(),
+        Some(runtime.lump_store.clone()),
+        conn.op_rx,
+        conn.op_tx,
+        Some(root_cap_tx),
+    );
+
+    // only let the client look up the services its profile grants
+    let grant = clients.config.grants.get(identity.profile).clone();
+    let network_root = FilteredRegistry::new(network_root, move |name| grant.allows(name));
+    let network_root = network_root.spawn_owned(runtime.clone());
 
     info!("Sending the client our root cap");
     conn.export_root(network_root);
 
     info!("Waiting for client's root cap...");
-    let _client_root = match client_root.await {
+    let client_root = match client_root.await {
         Ok(cap) => cap,
         Err(err) => {
             eprintln!("Client's root cap was never received: {:?}", err);
@@ -175,4 +367,60 @@ async fn on_accept(
     };
 
     info!("Client sent a root cap!");
+    runtime.peers.add_peer(client_root);
+}
+
+/// Gets the path of the user store in the data directory, moving it there
+/// from the config directory where older versions kept it.
+///
+/// Falls back to the old path if it can't be moved.
+fn default_users_path() -> PathBuf {
+    let path = hearth_runtime::get_data_dir().join(USERS_FILE);
+    let old = hearth_runtime::get_config_dir().join(USERS_FILE);
+    if path.exists() || !old.exists() {
+        return path;
+    }
+
+    info!("Moving {:?} to {:?}", old, path);
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::rename(&old, &path));
+
+    match result {
+        Ok(()) => path,
+        Err(err) => {
+            warn!(
+                "Failed to move {:?}, so it won't be backed up: {:?}",
+                old, err
+            );
+            old
+        }
+    }
+}
+
+/// Serves lumps from a runtime's lump store.
+struct RuntimeLumps(Arc<LumpStoreImpl>);
+
+#[async_trait]
+impl LumpSource for RuntimeLumps {
+    async fn get_lump(&self, id: &LumpId) -> Option<Vec<u8>> {
+        self.0.get_local_lump(id).await.map(|data| data.to_vec())
+    }
+}
+
+/// How often the snapshots in the data directory are updated.
+const SNAPSHOT_PERIOD: Duration = Duration::from_secs(1);
+
+/// Periodically writes the statistics of all connected peers to the data
+/// directory for hearth-ctl to read.
+async fn write_stats(peers: Arc<PeerTracker>) {
+    let mut last = Instant::now();
+    write_snapshots(STATS_FILE, SNAPSHOT_PERIOD, || {
+        let now = Instant::now();
+        let stats = peers.sample(now - last);
+        last = now;
+        std::future::ready(Some(stats))
+    })
+    .await;
 }
@@ -0,0 +1,400 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use glam::{Mat4, Quat, Vec3, Vec4};
+use gltf::animation::{util::ReadOutputs, Interpolation};
+use hearth_runtime::anyhow::{anyhow, bail, Context, Result};
+
+/// A decomposed local transform of a node.
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// A node in a model's scene hierarchy.
+#[derive(Clone, Debug)]
+pub struct Node {
+    /// The index of this node's parent, if it has one.
+    pub parent: Option<usize>,
+
+    /// This node's transform when no animation is applied.
+    pub rest: Transform,
+}
+
+/// A set of joints that deform a mesh.
+#[derive(Clone, Debug)]
+pub struct Skin {
+    /// The node indices of each joint.
+    pub joints: Vec<usize>,
+
+    /// The inverse bind matrix of each joint.
+    pub inverse_bind: Vec<Mat4>,
+}
+
+/// The keyframe values of a single channel.
+#[derive(Clone, Debug)]
+pub enum ChannelValues {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+/// A single animated property of a single node.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    /// The index of the animated node.
+    pub node: usize,
+
+    /// How to interpolate between keyframes.
+    pub interpolation: Interpolation,
+
+    /// The timestamp of each keyframe in seconds.
+    pub times: Vec<f32>,
+
+    /// The keyframe values. For cubic spline interpolation, each keyframe has
+    /// three values: in-tangent, value, and out-tangent.
+    pub values: ChannelValues,
+}
+
+/// A named animation.
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub name: Option<String>,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+/// All of the animation data loaded from a glTF model.
+#[derive(Clone, Debug)]
+pub struct AnimatedModel {
+    pub nodes: Vec<Node>,
+    pub skins: Vec<Skin>,
+    pub clips: Vec<Clip>,
+}
+
+impl AnimatedModel {
+    /// Loads the animation data out of a binary glTF (GLB) file.
+    ///
+    /// External buffers are not supported, since lumps are self-contained.
+    pub fn from_glb(data: &[u8]) -> Result<Self> {
+        let gltf = gltf::Gltf::from_slice(data).context("parsing glTF")?;
+
+        let mut buffers = Vec::new();
+        for buffer in gltf.document.buffers() {
+            match buffer.source() {
+                gltf::buffer::Source::Bin => {
+                    let blob = gltf.blob.as_ref().context("glTF has no binary chunk")?;
+                    buffers.push(blob.as_slice());
+                }
+                gltf::buffer::Source::Uri(uri) => {
+                    bail!("external glTF buffers are unsupported (uri: {:?})", uri);
+                }
+            }
+        }
+
+        let get_buffer = |buffer: gltf::Buffer| buffers.get(buffer.index()).copied();
+
+        let mut nodes: Vec<_> = gltf
+            .document
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Node {
+                    parent: None,
+                    rest: Transform {
+                        translation: translation.into(),
+                        rotation: Quat::from_array(rotation),
+                        scale: scale.into(),
+                    },
+                }
+            })
+            .collect();
+
+        for node in gltf.document.nodes() {
+            for child in node.children() {
+                nodes[child.index()].parent = Some(node.index());
+            }
+        }
+
+        let mut skins = Vec::new();
+        for skin in gltf.document.skins() {
+            let joints: Vec<_> = skin.joints().map(|joint| joint.index()).collect();
+
+            let inverse_bind = match skin.reader(get_buffer).read_inverse_bind_matrices() {
+                Some(matrices) => matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect(),
+                None => vec![Mat4::IDENTITY; joints.len()],
+            };
+
+            if inverse_bind.len() != joints.len() {
+                bail!(
+                    "skin #{} has mismatched inverse bind matrices",
+                    skin.index()
+                );
+            }
+
+            skins.push(Skin {
+                joints,
+                inverse_bind,
+            });
+        }
+
+        let mut clips = Vec::new();
+        for animation in gltf.document.animations() {
+            let mut channels = Vec::new();
+            let mut duration = 0.0f32;
+
+            for channel in animation.channels() {
+                let reader = channel.reader(get_buffer);
+
+                let times: Vec<f32> = reader
+                    .read_inputs()
+                    .ok_or_else(|| anyhow!("animation channel is missing inputs"))?
+                    .collect();
+
+                let values = match reader.read_outputs() {
+                    Some(ReadOutputs::Translations(iter)) => {
+                        ChannelValues::Translation(iter.map(Vec3::from).collect())
+                    }
+                    Some(ReadOutputs::Rotations(iter)) => {
+                        ChannelValues::Rotation(iter.into_f32().map(Quat::from_array).collect())
+                    }
+                    Some(ReadOutputs::Scales(iter)) => {
+                        ChannelValues::Scale(iter.map(Vec3::from).collect())
+                    }
+                    // morph targets aren't supported by the renderer
+                    Some(ReadOutputs::MorphTargetWeights(_)) => continue,
+                    None => bail!("animation channel is missing outputs"),
+                };
+
+                let interpolation = channel.sampler().interpolation();
+                let per_key = match interpolation {
+                    Interpolation::CubicSpline => 3,
+                    _ => 1,
+                };
+
+                let value_num = match &values {
+                    ChannelValues::Translation(values) => values.len(),
+                    ChannelValues::Rotation(values) => values.len(),
+                    ChannelValues::Scale(values) => values.len(),
+                };
+
+                if times.is_empty() || value_num != times.len() * per_key {
+                    bail!("animation channel has mismatched keyframe count");
+                }
+
+                duration = duration.max(*times.last().unwrap());
+
+                channels.push(Channel {
+                    node: channel.target().node().index(),
+                    interpolation,
+                    times,
+                    values,
+                });
+            }
+
+            clips.push(Clip {
+                name: animation.name().map(ToString::to_string),
+                duration,
+                channels,
+            });
+        }
+
+        Ok(Self {
+            nodes,
+            skins,
+            clips,
+        })
+    }
+
+    /// Computes the joint matrices of a skin for a pose.
+    pub fn joint_matrices(&self, skin: &Skin, pose: &Pose) -> Vec<Mat4> {
+        let mut globals: Vec<Option<Mat4>> = vec![None; self.nodes.len()];
+
+        skin.joints
+            .iter()
+            .zip(skin.inverse_bind.iter())
+            .map(|(joint, inverse_bind)| {
+                self.global_transform(*joint, pose, &mut globals) * *inverse_bind
+            })
+            .collect()
+    }
+
+    /// Recursively computes the global transform of a node, caching results.
+    fn global_transform(&self, node: usize, pose: &Pose, globals: &mut [Option<Mat4>]) -> Mat4 {
+        if let Some(global) = globals[node] {
+            return global;
+        }
+
+        let local = pose.locals[node].to_matrix();
+        let global = match self.nodes[node].parent {
+            Some(parent) => self.global_transform(parent, pose, globals) * local,
+            None => local,
+        };
+
+        globals[node] = Some(global);
+        global
+    }
+}
+
+/// Accumulates weighted samples of multiple clips into a set of local node
+/// transforms.
+pub struct Pose {
+    locals: Vec<Transform>,
+    translations: Vec<(Vec3, f32)>,
+    rotations: Vec<(Vec4, f32)>,
+    scales: Vec<(Vec3, f32)>,
+}
+
+impl Pose {
+    /// Creates an empty pose with no samples.
+    pub fn new(model: &AnimatedModel) -> Self {
+        let len = model.nodes.len();
+
+        Self {
+            locals: model.nodes.iter().map(|node| node.rest).collect(),
+            translations: vec![(Vec3::ZERO, 0.0); len],
+            rotations: vec![(Vec4::ZERO, 0.0); len],
+            scales: vec![(Vec3::ZERO, 0.0); len],
+        }
+    }
+
+    /// Samples a clip at the given time and adds it to this pose.
+    pub fn add_clip(&mut self, clip: &Clip, time: f32, weight: f32) {
+        if weight <= 0.0 {
+            return;
+        }
+
+        for channel in clip.channels.iter() {
+            if channel.node >= self.locals.len() {
+                continue;
+            }
+
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    let value = sample(channel, values, time, Vec3::lerp);
+                    let (sum, total) = &mut self.translations[channel.node];
+                    *sum += value * weight;
+                    *total += weight;
+                }
+                ChannelValues::Rotation(values) => {
+                    let value = sample(channel, values, time, Quat::slerp).normalize();
+                    let (sum, total) = &mut self.rotations[channel.node];
+
+                    // keep all rotations in the same hemisphere
+                    let mut value = Vec4::from(value);
+                    if sum.dot(value) < 0.0 {
+                        value = -value;
+                    }
+
+                    *sum += value * weight;
+                    *total += weight;
+                }
+                ChannelValues::Scale(values) => {
+                    let value = sample(channel, values, time, Vec3::lerp);
+                    let (sum, total) = &mut self.scales[channel.node];
+                    *sum += value * weight;
+                    *total += weight;
+                }
+            }
+        }
+    }
+
+    /// Resolves all of the added samples into the final local transforms.
+    ///
+    /// Properties with a total weight under one are blended with the rest pose.
+    pub fn resolve(&mut self) {
+        for (idx, local) in self.locals.iter_mut().enumerate() {
+            let (sum, total) = self.translations[idx];
+            if total > 0.0 {
+                local.translation = local.translation.lerp(sum / total, total.min(1.0));
+            }
+
+            let (sum, total) = self.rotations[idx];
+            if total > 0.0 {
+                let rotation = Quat::from_vec4(sum).normalize();
+                local.rotation = local.rotation.slerp(rotation, total.min(1.0));
+            }
+
+            let (sum, total) = self.scales[idx];
+            if total > 0.0 {
+                local.scale = local.scale.lerp(sum / total, total.min(1.0));
+            }
+        }
+    }
+}
+
+/// Samples a channel's keyframes at a time.
+fn sample<T>(channel: &Channel, values: &[T], time: f32, lerp: impl Fn(T, T, f32) -> T) -> T
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    let times = &channel.times;
+    let cubic = channel.interpolation == Interpolation::CubicSpline;
+
+    // fetches the value of a keyframe, skipping cubic spline tangents
+    let value = |key: usize| {
+        if cubic {
+            values[key * 3 + 1]
+        } else {
+            values[key]
+        }
+    };
+
+    // index of the first keyframe after the given time
+    let next = times.partition_point(|t| *t <= time);
+
+    if next == 0 {
+        return value(0);
+    } else if next >= times.len() {
+        return value(times.len() - 1);
+    }
+
+    let prev = next - 1;
+    let delta = times[next] - times[prev];
+    let t = if delta > 0.0 {
+        (time - times[prev]) / delta
+    } else {
+        0.0
+    };
+
+    match channel.interpolation {
+        Interpolation::Step => value(prev),
+        Interpolation::Linear => lerp(value(prev), value(next), t),
+        Interpolation::CubicSpline => {
+            let t2 = t * t;
+            let t3 = t2 * t;
+            let p0 = value(prev);
+            let m0 = values[prev * 3 + 2] * delta;
+            let p1 = value(next);
+            let m1 = values[next * 3] * delta;
+
+            p0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + m0 * (t3 - 2.0 * t2 + t)
+                + p1 * (-2.0 * t3 + 3.0 * t2)
+                + m1 * (t3 - t2)
+        }
+    }
+}
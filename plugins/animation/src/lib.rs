@@ -0,0 +1,321 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, sync::Arc};
+
+use glam::Mat4;
+use hearth_runtime::{
+    anyhow,
+    asset::{AssetLoader, AssetStore},
+    async_trait,
+    flue::{OwnedCapability, OwnedTableSignal},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::{animation::*, codec, renderer::ObjectUpdate},
+    process::Process,
+    runtime::{Plugin, Runtime, RuntimeBuilder},
+    tokio::{
+        self,
+        time::{Duration, Instant, MissedTickBehavior},
+    },
+    tracing::{debug, error, warn},
+    utils::*,
+};
+
+use clip::{AnimatedModel, Pose};
+
+/// Animation data loading and sampling.
+pub mod clip;
+
+/// The rate at which animators stream joint matrices, in updates per second.
+pub const TICK_RATE: f32 = 60.0;
+
+/// Loads an [AnimatedModel] from a binary glTF lump.
+pub struct AnimatedModelLoader;
+
+#[async_trait]
+impl AssetLoader for AnimatedModelLoader {
+    type Asset = AnimatedModel;
+
+    async fn load_asset(&self, _store: &AssetStore, data: &[u8]) -> anyhow::Result<Self::Asset> {
+        AnimatedModel::from_glb(data)
+    }
+}
+
+/// The playback state of a single clip within an [Animator].
+struct Playback {
+    time: f32,
+    weight: f32,
+    speed: f32,
+    looping: bool,
+}
+
+impl Playback {
+    /// Moves this playback's playhead forward by a time step.
+    fn advance(&mut self, duration: f32, dt: f32) {
+        self.time += dt * self.speed;
+
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0.0, duration);
+        }
+    }
+}
+
+/// An animator process. Accepts [AnimatorUpdate].
+///
+/// Plays and blends clips on its own clock and streams the resulting joint
+/// matrices to its target renderer object.
+#[derive(GetProcessMetadata)]
+pub struct Animator {
+    model: Arc<AnimatedModel>,
+    skin: usize,
+    target: OwnedCapability,
+    playbacks: HashMap<usize, Playback>,
+
+    /// Set when the pose has changed without any clips playing, so that the
+    /// next tick still sends an update.
+    dirty: bool,
+}
+
+#[async_trait]
+impl ProcessRunner for Animator {
+    async fn run(
+        mut self,
+        label: String,
+        _runtime: Arc<Runtime>,
+        ctx: &Process,
+        _: ProcessRunToken,
+    ) {
+        let table = ctx.borrow_table();
+
+        let target = match table.import_owned(self.target.clone()) {
+            Ok(target) => target,
+            Err(err) => {
+                error!("{label} failed to import target object: {err:?}");
+                return;
+            }
+        };
+
+        // quit when the target object goes away
+        if let Err(err) = table.monitor(target, ctx.borrow_parent()) {
+            debug!("{label} failed to monitor target object: {err:?}");
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs_f32(1.0 / TICK_RATE));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut last_tick = Instant::now();
+
+        loop {
+            tokio::select! {
+                signal = ctx.borrow_parent().recv_owned() => {
+                    use OwnedTableSignal::*;
+                    match signal {
+                        Some(Message { data, .. }) => match codec::decode(&data) {
+                            Ok(update) => self.on_update(update),
+                            Err(err) => debug!("{label} failed to parse AnimatorUpdate: {err:?}"),
+                        },
+                        Some(Down { .. }) => break,
+                        None => break,
+                    }
+                }
+                now = interval.tick() => {
+                    let dt = now.duration_since(last_tick).as_secs_f32();
+                    last_tick = now;
+
+                    let Some(matrices) = self.advance(dt) else {
+                        continue;
+                    };
+
+                    let update = ObjectUpdate::JointMatrices(matrices);
+                    let data = codec::encode(&update);
+                    if let Err(err) = table.send(target, &data, &[]).await {
+                        debug!("{label} failed to update target object: {err:?}");
+                        break;
+                    }
+                }
+            }
+        }
+
+        let _ = table.dec_ref(target);
+    }
+}
+
+impl Animator {
+    fn on_update(&mut self, update: AnimatorUpdate) {
+        use AnimatorUpdate::*;
+        match update {
+            Play {
+                clip,
+                weight,
+                speed,
+                looping,
+            } => {
+                if clip >= self.model.clips.len() {
+                    warn!("tried to play nonexistent animation clip #{clip}");
+                    return;
+                }
+
+                self.playbacks.insert(
+                    clip,
+                    Playback {
+                        time: 0.0,
+                        weight,
+                        speed,
+                        looping,
+                    },
+                );
+            }
+            Stop { clip } => {
+                self.playbacks.remove(&clip);
+                self.dirty = true;
+            }
+            StopAll => {
+                self.playbacks.clear();
+                self.dirty = true;
+            }
+            SetWeight { clip, weight } => {
+                if let Some(playback) = self.get_playback(clip) {
+                    playback.weight = weight;
+                }
+            }
+            SetSpeed { clip, speed } => {
+                if let Some(playback) = self.get_playback(clip) {
+                    playback.speed = speed;
+                }
+            }
+            Seek { clip, time } => {
+                if let Some(playback) = self.get_playback(clip) {
+                    playback.time = time;
+                }
+            }
+        }
+    }
+
+    /// Helper function to look up a playing clip or log a warning if it's not
+    /// playing.
+    fn get_playback(&mut self, clip: usize) -> Option<&mut Playback> {
+        let playback = self.playbacks.get_mut(&clip);
+
+        if playback.is_none() {
+            warn!("animation clip #{clip} is not playing");
+        }
+
+        playback
+    }
+
+    /// Steps all playing clips forward and returns the new joint matrices, or
+    /// `None` if the pose hasn't changed.
+    fn advance(&mut self, dt: f32) -> Option<Vec<Mat4>> {
+        if self.playbacks.is_empty() && !self.dirty {
+            return None;
+        }
+
+        let mut pose = Pose::new(&self.model);
+
+        for (clip, playback) in self.playbacks.iter_mut() {
+            let clip = &self.model.clips[*clip];
+            playback.advance(clip.duration, dt);
+            pose.add_clip(clip, playback.time, playback.weight);
+        }
+
+        pose.resolve();
+        self.dirty = false;
+
+        let skin = &self.model.skins[self.skin];
+        Some(self.model.joint_matrices(skin, &pose))
+    }
+}
+
+/// The native animation factory service. Accepts [FactoryRequest].
+#[derive(GetProcessMetadata)]
+pub struct AnimationFactory;
+
+#[async_trait]
+impl RequestResponseProcess for AnimationFactory {
+    type Request = FactoryRequest;
+    type Response = FactoryResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Request>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        let FactoryRequest::CreateAnimator { model, skin } = &request.data;
+
+        let Some(target) = request.cap_args.first() else {
+            return FactoryError::MissingTarget.into();
+        };
+
+        let model = match request
+            .runtime
+            .asset_store
+            .load_asset::<AnimatedModelLoader>(model)
+            .await
+        {
+            Ok(model) => model,
+            Err(err) => {
+                error!("failed to load animated model: {err:?}");
+                return FactoryError::LumpError.into();
+            }
+        };
+
+        if *skin >= model.skins.len() {
+            return FactoryError::InvalidSkin.into();
+        }
+
+        let clips = model
+            .clips
+            .iter()
+            .map(|clip| ClipInfo {
+                name: clip.name.clone(),
+                duration: clip.duration,
+            })
+            .collect();
+
+        let child = request.spawn(Animator {
+            model,
+            skin: *skin,
+            target: target.to_owned(),
+            playbacks: HashMap::new(),
+            dirty: true,
+        });
+
+        ResponseInfo {
+            data: Ok(FactorySuccess::Animator { clips }),
+            caps: vec![child],
+        }
+    }
+}
+
+impl ServiceRunner for AnimationFactory {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// A plugin that provides skeletal animation playback to guests.
+///
+/// Adds the [AnimationFactory] service.
+#[derive(Default)]
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&mut self, builder: &mut RuntimeBuilder) {
+        builder
+            .add_asset_loader(AnimatedModelLoader)
+            .add_plugin(AnimationFactory);
+    }
+}
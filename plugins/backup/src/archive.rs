@@ -0,0 +1,341 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{File, OpenOptions},
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{NaiveDateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hearth_runtime::{
+    anyhow::{bail, Context, Result},
+    hearth_schema::backup::BackupInfo,
+    tracing::{error, info},
+};
+use serde::Deserialize;
+
+/// The file name prefix of backup archives.
+const PREFIX: &str = "hearth-backup-";
+
+/// The file name extension of backup archives.
+const EXTENSION: &str = ".tar.gz";
+
+/// The format of the timestamp in backup archive names.
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Configuration for backups, read from the `backup` table of the config file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// The directory to back up. Defaults to the Hearth data directory.
+    pub source: PathBuf,
+
+    /// The directory to store archives in. Defaults to `backups` in the
+    /// Hearth data directory.
+    ///
+    /// If this is inside of the source directory, it's left out of archives
+    /// and left alone by restores.
+    pub destination: PathBuf,
+
+    /// The number of archives to keep. Older archives are deleted after each
+    /// new backup. Zero keeps every archive.
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        let source = hearth_runtime::get_data_dir();
+
+        Self {
+            destination: source.join("backups"),
+            source,
+            keep: 7,
+        }
+    }
+}
+
+impl BackupConfig {
+    /// Reads the backup config from the `backup` table of a config file.
+    ///
+    /// Logs an error and uses the default configuration if the table is
+    /// malformed.
+    pub fn from_config_file(config: &toml::Table) -> Self {
+        let Some(table) = config.get("backup") else {
+            return Self::default();
+        };
+
+        match table.clone().try_into() {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Failed to parse backup config: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Archives the source directory into a new timestamped archive.
+    ///
+    /// Archives created within the same second are numbered after their
+    /// timestamps so that they don't replace each other.
+    pub fn create(&self) -> Result<BackupInfo> {
+        std::fs::create_dir_all(&self.destination).context("creating backup directory")?;
+
+        let now = Utc::now();
+        let timestamp = now.format(TIME_FORMAT).to_string();
+        let (name, path) = (0..)
+            .map(|num| match num {
+                0 => format!("{PREFIX}{timestamp}{EXTENSION}"),
+                num => format!("{PREFIX}{timestamp}-{num}{EXTENSION}"),
+            })
+            .map(|name| {
+                let path = self.destination.join(&name);
+                (name, path)
+            })
+            .find(|(name, path)| {
+                !path.exists() && !self.destination.join(format!("{name}.partial")).exists()
+            })
+            .unwrap();
+
+        // write to a separate file first so that incomplete archives are
+        // never listed or restored
+        let partial = self.destination.join(format!("{name}.partial"));
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        // archives include the user store's secret keys
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let file = options.open(&partial).context("creating archive")?;
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        builder.follow_symlinks(false);
+
+        if self.source.exists() {
+            let source = self.source.canonicalize()?;
+            let exclude = self.destination.canonicalize()?;
+            append_dir(&mut builder, &source, Path::new(""), &exclude)?;
+        }
+
+        builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .and_then(|file| file.sync_all())
+            .context("writing archive")?;
+
+        std::fs::rename(&partial, &path).context("renaming archive")?;
+        info!("Created backup {:?}", path);
+
+        Ok(BackupInfo {
+            name,
+            size: path.metadata()?.len(),
+            created: now.timestamp(),
+        })
+    }
+
+    /// Lists all archives in the destination directory from oldest to newest.
+    pub fn list(&self) -> Result<Vec<BackupInfo>> {
+        if !self.destination.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut backups = Vec::new();
+        for entry in std::fs::read_dir(&self.destination)? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+
+            let Some(stem) = name
+                .strip_prefix(PREFIX)
+                .and_then(|name| name.strip_suffix(EXTENSION))
+            else {
+                continue;
+            };
+
+            // archives made within the same second are numbered
+            let (timestamp, num) = match stem.split_once('-') {
+                Some((timestamp, num)) => match num.parse::<u32>() {
+                    Ok(num) => (timestamp, num),
+                    Err(_) => continue,
+                },
+                None => (stem, 0),
+            };
+
+            let Ok(created) = NaiveDateTime::parse_from_str(timestamp, TIME_FORMAT) else {
+                continue;
+            };
+
+            let info = BackupInfo {
+                name,
+                size: entry.metadata()?.len(),
+                created: created.and_utc().timestamp(),
+            };
+
+            backups.push((info, num));
+        }
+
+        backups.sort_by_key(|(info, num)| (info.created, *num));
+        Ok(backups.into_iter().map(|(info, _)| info).collect())
+    }
+
+    /// Deletes the oldest archives beyond the number to keep.
+    pub fn prune(&self) -> Result<()> {
+        if self.keep == 0 {
+            return Ok(());
+        }
+
+        let backups = self.list()?;
+        let excess = backups.len().saturating_sub(self.keep);
+        for backup in backups.into_iter().take(excess) {
+            let path = self.destination.join(&backup.name);
+            std::fs::remove_file(&path).with_context(|| format!("removing {:?}", path))?;
+            info!("Pruned backup {:?}", path);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the contents of the source directory with an archive's.
+    ///
+    /// The archive is unpacked next to the source directory and swapped into
+    /// its place, so the old contents are kept until the new ones are ready.
+    /// This must not be done while a runtime is using the source directory.
+    pub fn restore(&self, archive: &Path) -> Result<()> {
+        // check the whole archive before touching anything
+        let file = File::open(archive).context("opening archive")?;
+        let mut reader = tar::Archive::new(GzDecoder::new(file));
+        for entry in reader.entries().context("reading archive")? {
+            let entry = entry.context("reading archive")?;
+            let path = entry.path()?;
+            if !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+            {
+                bail!("archive contains invalid path {:?}", path);
+            }
+        }
+
+        std::fs::create_dir_all(&self.source).context("creating source directory")?;
+        let source = self.source.canonicalize()?;
+        let unpacked = sibling(&source, "restoring")?;
+        let old = sibling(&source, "old")?;
+
+        // the backup directory is left out of archives, so move it over
+        let exclude = self
+            .destination
+            .canonicalize()
+            .ok()
+            .and_then(|dst| dst.strip_prefix(&source).ok().map(Path::to_path_buf))
+            .filter(|dst| !dst.as_os_str().is_empty());
+
+        remove_leftover(&unpacked)?;
+        let file = File::open(archive).context("opening archive")?;
+        let mut reader = tar::Archive::new(GzDecoder::new(file));
+        if let Err(err) = reader.unpack(&unpacked) {
+            let _ = std::fs::remove_dir_all(&unpacked);
+            return Err(err).context("unpacking archive");
+        }
+
+        if old.exists() {
+            let _ = std::fs::remove_dir_all(&unpacked);
+            bail!(
+                "{:?} was left by an interrupted restore, move it away first",
+                old
+            );
+        }
+
+        if let Err(err) = std::fs::rename(&source, &old) {
+            let _ = std::fs::remove_dir_all(&unpacked);
+            return Err(err).with_context(|| format!("moving {:?} to {:?}", source, old));
+        }
+
+        if let Err(err) = std::fs::rename(&unpacked, &source) {
+            std::fs::rename(&old, &source)
+                .with_context(|| format!("moving {:?} back to {:?}", old, source))?;
+            let _ = std::fs::remove_dir_all(&unpacked);
+            return Err(err).with_context(|| format!("moving {:?} to {:?}", unpacked, source));
+        }
+
+        if let Some(exclude) = exclude {
+            let from = old.join(&exclude);
+            let to = source.join(&exclude);
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            // keep the old tree around if the backups couldn't be moved out
+            std::fs::rename(&from, &to)
+                .with_context(|| format!("moving backups from {:?} to {:?}", from, to))?;
+        }
+
+        std::fs::remove_dir_all(&old).with_context(|| format!("removing {:?}", old))?;
+        info!("Restored backup {:?} to {:?}", archive, source);
+
+        Ok(())
+    }
+}
+
+/// Gets the path next to a directory with a suffix added to its name.
+fn sibling(dir: &Path, suffix: &str) -> Result<PathBuf> {
+    let Some(name) = dir.file_name() else {
+        bail!("{:?} has no name", dir);
+    };
+
+    let mut name = name.to_os_string();
+    name.push(".");
+    name.push(suffix);
+    Ok(dir.with_file_name(name))
+}
+
+/// Removes a directory left over from an interrupted restore.
+fn remove_leftover(dir: &Path) -> Result<()> {
+    if dir.exists() {
+        std::fs::remove_dir_all(dir).with_context(|| format!("removing {:?}", dir))?;
+    }
+
+    Ok(())
+}
+
+/// Recursively adds a directory's contents to an archive, skipping `exclude`.
+fn append_dir(
+    builder: &mut tar::Builder<GzEncoder<File>>,
+    dir: &Path,
+    prefix: &Path,
+    exclude: &Path,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path == exclude {
+            continue;
+        }
+
+        let name = prefix.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            builder.append_dir(&name, &path)?;
+            append_dir(builder, &path, &name, exclude)?;
+        } else {
+            builder
+                .append_path_with_name(&path, &name)
+                .with_context(|| format!("archiving {:?}", path))?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use hearth_runtime::{
+    async_trait,
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::backup::*,
+    runtime::{Plugin, RuntimeBuilder},
+    tokio::task::spawn_blocking,
+    tracing::{debug, error},
+    utils::*,
+};
+
+pub use archive::BackupConfig;
+
+/// Backup archive creation, pruning, and restoration.
+pub mod archive;
+
+/// Native service that archives the server's data. Accepts [BackupRequest].
+///
+/// Unlike most services, the reply capability is optional, so that backups
+/// can be triggered by the cron service.
+#[derive(GetProcessMetadata)]
+pub struct BackupService {
+    config: Arc<BackupConfig>,
+}
+
+#[async_trait]
+impl SinkProcess for BackupService {
+    type Message = BackupRequest;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let config = self.config.clone();
+        let result = match message.data {
+            BackupRequest::Create => {
+                spawn_blocking(move || {
+                    let info = config.create()?;
+                    config.prune()?;
+                    Ok(BackupSuccess::Created(info))
+                })
+                .await
+            }
+            BackupRequest::List => {
+                spawn_blocking(move || config.list().map(BackupSuccess::List)).await
+            }
+        };
+
+        let response: BackupResponse = match result {
+            Ok(Ok(success)) => Ok(success),
+            Ok(Err(err)) => {
+                error!("{} request failed: {:?}", message.label, err);
+                Err(BackupError::Failed(format!("{:#}", err)))
+            }
+            Err(err) => Err(BackupError::Failed(err.to_string())),
+        };
+
+        let Some(reply) = message.caps.first() else {
+            return;
+        };
+
+        let data = message.codec.encode(&response);
+        if let Err(err) = reply.send(&data, &[]).await {
+            debug!("{:?} reply error: {:?}", message.label, err);
+        }
+    }
+}
+
+impl ServiceRunner for BackupService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// A plugin that provides the [BackupService].
+#[derive(Default)]
+pub struct BackupPlugin {
+    config: BackupConfig,
+}
+
+impl Plugin for BackupPlugin {
+    fn finalize(self, builder: &mut RuntimeBuilder) {
+        builder.add_plugin(BackupService {
+            config: Arc::new(self.config),
+        });
+    }
+}
+
+impl BackupPlugin {
+    /// Creates a new backup plugin with the given configuration.
+    pub fn new(config: BackupConfig) -> Self {
+        Self { config }
+    }
+}
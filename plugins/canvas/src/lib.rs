@@ -25,6 +25,7 @@ use hearth_rend3::{
         graph::{DepthHandle, RenderPassDepthTarget, RenderPassTarget, RenderPassTargets},
         types::glam::{vec2, Mat4, Vec4},
     },
+    utils::DynamicMesh,
     wgpu::{util::DeviceExt, *},
     Node, Rend3Plugin, Routine, RoutineInfo,
 };
@@ -34,9 +35,108 @@ use hearth_runtime::{
     hearth_macros::GetProcessMetadata,
     hearth_schema::canvas::*,
     runtime::{Plugin, RuntimeBuilder},
+    stream::RingBuffer,
+    tokio,
+    tracing::warn,
     utils::*,
 };
 
+pub mod text;
+pub mod vector;
+
+use text::FontLoader;
+use vector::{VectorMesh, VectorVertex};
+
+/// The maximum width and height of a pixel buffer.
+///
+/// This is the maximum texture size guaranteed by wgpu's default limits.
+const MAX_SIZE: u32 = 8192;
+
+/// Returns true if a pixel buffer is not empty and no larger than [MAX_SIZE].
+fn is_valid_size(pixels: &Pixels) -> bool {
+    let valid = 1..=MAX_SIZE;
+    valid.contains(&pixels.width) && valid.contains(&pixels.height)
+}
+
+/// Clips the source region of a copy within a canvas of the given size so
+/// that both the source and the destination at `(x, y)` are in bounds.
+///
+/// Returns `None` if the clipped copy has no area.
+fn clip_copy(width: u32, height: u32, src: Rect, x: u32, y: u32) -> Option<Rect> {
+    let clipped = Rect {
+        width: src
+            .width
+            .min(width.saturating_sub(src.x))
+            .min(width.saturating_sub(x)),
+        height: src
+            .height
+            .min(height.saturating_sub(src.y))
+            .min(height.saturating_sub(y)),
+        ..src
+    };
+
+    (clipped.width > 0 && clipped.height > 0).then_some(clipped)
+}
+
+/// Finds the smallest region containing every pixel that differs between two
+/// RGBA8 pixel buffers with the same dimensions.
+///
+/// Returns `None` if the buffers are identical.
+fn dirty_rect(width: u32, old: &[u8], new: &[u8]) -> Option<Rect> {
+    let stride = width as usize * 4;
+    let rows = old.chunks_exact(stride).zip(new.chunks_exact(stride));
+
+    // inclusive bounds of the dirty region
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+
+    for (y, (old, new)) in rows.enumerate() {
+        if old == new {
+            continue;
+        }
+
+        let mut pixels = old.chunks_exact(4).zip(new.chunks_exact(4));
+        let first = pixels.position(|(a, b)| a != b).unwrap_or(0);
+        let last = pixels
+            .rposition(|(a, b)| a != b)
+            .map_or(first, |x| first + 1 + x);
+
+        bounds = Some(match bounds {
+            None => (first, y, last, y),
+            Some((x0, y0, x1, _)) => (x0.min(first), y0, x1.max(last), y),
+        });
+    }
+
+    let (x0, y0, x1, y1) = bounds?;
+
+    Some(Rect {
+        x: x0 as u32,
+        y: y0 as u32,
+        width: (x1 - x0 + 1) as u32,
+        height: (y1 - y0 + 1) as u32,
+    })
+}
+
+/// Copies a region of an RGBA8 pixel buffer into another at `(x, y)`.
+///
+/// Both regions must be in bounds.
+fn copy_pixels(
+    dst: &mut [u8],
+    dst_width: u32,
+    x: u32,
+    y: u32,
+    src: &[u8],
+    src_width: u32,
+    rect: Rect,
+) {
+    let len = rect.width as usize * 4;
+
+    for row in 0..rect.height {
+        let src_start = ((rect.y + row) * src_width + rect.x) as usize * 4;
+        let dst_start = ((y + row) * dst_width + x) as usize * 4;
+        dst[dst_start..(dst_start + len)].copy_from_slice(&src[src_start..(src_start + len)]);
+    }
+}
+
 /// A specific kind of operation on a canvas.
 pub enum CanvasOperationKind {
     /// Create a new canvas with this ID.
@@ -51,6 +151,9 @@ pub enum CanvasOperationKind {
 
     /// Update this canvas.
     Update(CanvasUpdate),
+
+    /// Replace this canvas's contents with a tessellated display list.
+    Draw(VectorMesh),
 }
 
 /// An identifier for a specific canvas within a [CanvasRoutine].
@@ -78,6 +181,22 @@ pub struct CanvasDraw {
     height: u32,
     texture: Texture,
     bind_group: BindGroup,
+
+    /// A CPU-side copy of the texture's contents, used to only upload the
+    /// changed regions of same-size resizes.
+    ///
+    /// This is `None` after GPU-only operations like vector draws, until the
+    /// next full resize.
+    shadow: Option<Vec<u8>>,
+
+    /// A persistent intermediate texture for [Self::copy_rect].
+    scratch: Option<Texture>,
+
+    /// A persistent multisampled render target for [Self::draw_vector].
+    msaa: Option<(Texture, TextureView)>,
+
+    /// Persistent vertex and index buffers for [Self::draw_vector].
+    vector_mesh: DynamicMesh<VectorVertex>,
 }
 
 impl CanvasDraw {
@@ -88,7 +207,7 @@ impl CanvasDraw {
         sampler: &Sampler,
         sampling_mode: CanvasSamplingMode,
         position: Position,
-        pixels: Pixels,
+        mut pixels: Pixels,
     ) -> Self {
         let ubo = device.create_buffer(&BufferDescriptor {
             label: Some("canvas uniform"),
@@ -99,7 +218,7 @@ impl CanvasDraw {
 
         let width = pixels.width;
         let height = pixels.height;
-        let texture = Self::create_texture(device, queue, pixels);
+        let texture = Self::create_texture(device, queue, &mut pixels);
         let bind_group = Self::create_bind_group(device, bgl, &ubo, &texture, sampler);
 
         Self {
@@ -110,13 +229,18 @@ impl CanvasDraw {
             texture,
             sampling_mode,
             bind_group,
+            shadow: Some(pixels.data),
+            scratch: None,
+            msaa: None,
+            vector_mesh: DynamicMesh::new(device, Some("canvas vector mesh".to_string())),
         }
     }
 
     /// Resizes the canvas pixel buffer and recreates GPU objects.
     ///
     /// Does not reallocate any GPU objects if the size of the new pixel buffer
-    /// is identical to the old one, and performs a blit operation instead.
+    /// is identical to the old one. Instead, only the region of the new pixels
+    /// that differs from the canvas's current contents is uploaded.
     ///
     /// The effects of this function are immediately applied to the next draw
     /// call.
@@ -124,21 +248,43 @@ impl CanvasDraw {
         &mut self,
         device: &Device,
         queue: &Queue,
-        pixels: Pixels,
+        mut pixels: Pixels,
         bgl: &BindGroupLayout,
         sampler: &Sampler,
     ) {
-        // don't allocate a new texture if the size is the same. just blit.
+        // don't allocate a new texture if the size is the same
         if self.width == pixels.width && self.height == pixels.height {
-            let blit = Blit { x: 0, y: 0, pixels };
-            self.blit(queue, blit);
+            // correct the pixel data length
+            pixels
+                .data
+                .resize((pixels.width * pixels.height) as usize * 4, 0xff);
+
+            // upload the whole canvas if its current contents are unknown
+            let dirty = match self.shadow.as_ref() {
+                Some(shadow) => dirty_rect(self.width, shadow, &pixels.data),
+                None => Some(Rect {
+                    x: 0,
+                    y: 0,
+                    width: self.width,
+                    height: self.height,
+                }),
+            };
+
+            if let Some(rect) = dirty {
+                self.write_region(queue, &pixels.data, self.width, rect, rect.x, rect.y);
+            }
+
+            self.shadow = Some(pixels.data);
             return;
         }
 
         self.width = pixels.width;
         self.height = pixels.height;
-        self.texture = Self::create_texture(device, queue, pixels);
+        self.texture = Self::create_texture(device, queue, &mut pixels);
         self.bind_group = Self::create_bind_group(device, bgl, &self.ubo, &self.texture, sampler);
+        self.shadow = Some(pixels.data);
+        self.scratch = None;
+        self.msaa = None;
     }
 
     /// Update this buffer's position.
@@ -176,7 +322,7 @@ impl CanvasDraw {
 
     /// Implements the [Blit] operation: copies a pixel buffer to a target
     /// destination region of this canvas.
-    pub fn blit(&self, queue: &Queue, mut blit: Blit) {
+    pub fn blit(&mut self, queue: &Queue, mut blit: Blit) {
         // available width and height
         let aw = self.width.saturating_sub(blit.x);
         let ah = self.height.saturating_sub(blit.y);
@@ -195,33 +341,201 @@ impl CanvasDraw {
             .data
             .resize((blit.pixels.width * blit.pixels.height) as usize * 4, 0xff);
 
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+
+        let data = &blit.pixels.data;
+        self.write_region(queue, data, blit.pixels.width, rect, blit.x, blit.y);
+
+        if let Some(shadow) = self.shadow.as_mut() {
+            copy_pixels(
+                shadow,
+                self.width,
+                blit.x,
+                blit.y,
+                data,
+                blit.pixels.width,
+                rect,
+            );
+        }
+    }
+
+    /// Uploads a region of a pixel buffer with the given width to `(x, y)`
+    /// on this canvas's texture.
+    fn write_region(
+        &self,
+        queue: &Queue,
+        data: &[u8],
+        data_width: u32,
+        rect: Rect,
+        x: u32,
+        y: u32,
+    ) {
         queue.write_texture(
             ImageCopyTexture {
                 texture: &self.texture,
                 mip_level: 0,
-                origin: Origin3d {
-                    x: blit.x,
-                    y: blit.y,
-                    z: 0,
-                },
+                origin: Origin3d { x, y, z: 0 },
                 aspect: TextureAspect::All,
             },
-            &blit.pixels.data,
+            data,
             ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some((blit.pixels.width * 4).try_into().unwrap()),
-                rows_per_image: Some((blit.pixels.height).try_into().unwrap()),
+                offset: ((rect.y * data_width + rect.x) * 4) as BufferAddress,
+                bytes_per_row: Some((data_width * 4).try_into().unwrap()),
+                rows_per_image: Some(rect.height.try_into().unwrap()),
             },
             Extent3d {
-                width,
-                height,
+                width: rect.width,
+                height: rect.height,
                 depth_or_array_layers: 1,
             },
         );
     }
 
+    /// Implements the [CanvasUpdate::CopyRect] operation: copies a region of
+    /// this canvas to another position within it.
+    pub fn copy_rect(&mut self, device: &Device, queue: &Queue, src: Rect, x: u32, y: u32) {
+        let Some(src) = clip_copy(self.width, self.height, src, x, y) else {
+            return;
+        };
+
+        let size = Extent3d {
+            width: src.width,
+            height: src.height,
+            depth_or_array_layers: 1,
+        };
+
+        // textures can't be copied onto themselves, so copy through a
+        // scratch texture to allow the regions to overlap. the scratch
+        // texture covers the whole canvas so that it can be reused.
+        let (width, height) = (self.width, self.height);
+        let scratch = &*self.scratch.get_or_insert_with(|| {
+            device.create_texture(&TextureDescriptor {
+                label: Some("canvas copy scratch texture"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+            })
+        });
+
+        let copy = |texture, x, y| ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d { x, y, z: 0 },
+            aspect: TextureAspect::All,
+        };
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("canvas copy encoder"),
+        });
+
+        encoder.copy_texture_to_texture(
+            copy(&self.texture, src.x, src.y),
+            copy(scratch, 0, 0),
+            size,
+        );
+        encoder.copy_texture_to_texture(copy(scratch, 0, 0), copy(&self.texture, x, y), size);
+
+        // submit now so that the copy is ordered after any previous blits
+        // and before any later ones
+        queue.submit(Some(encoder.finish()));
+
+        // mirror the copy on the shadow
+        if let Some(shadow) = self.shadow.as_mut() {
+            let start = ((src.y * width) as usize) * 4;
+            let end = (((src.y + src.height) * width) as usize) * 4;
+            let rows = shadow[start..end].to_vec();
+            let rect = Rect { y: 0, ..src };
+            copy_pixels(shadow, width, x, y, &rows, width, rect);
+        }
+    }
+
+    /// Replaces the contents of this canvas with a tessellated display list.
+    pub fn draw_vector(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        pipeline: &RenderPipeline,
+        mut mesh: VectorMesh,
+    ) {
+        mesh.to_clip_space(self.width, self.height);
+
+        self.vector_mesh
+            .update(device, queue, &mesh.vertices, &mesh.indices);
+
+        // render with MSAA and resolve straight into the canvas
+        let (width, height) = (self.width, self.height);
+        let (_, msaa_view) = self.msaa.get_or_insert_with(|| {
+            let msaa = device.create_texture(&TextureDescriptor {
+                label: Some("canvas vector MSAA target"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: vector::SAMPLE_COUNT,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+            });
+
+            let view = msaa.create_view(&Default::default());
+            (msaa, view)
+        });
+
+        let view = self.texture.create_view(&Default::default());
+        let [r, g, b, a] = vector::srgb_to_linear(mesh.background);
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("canvas vector encoder"),
+        });
+
+        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("canvas vector pass"),
+            color_attachments: &[RenderPassColorAttachment {
+                view: msaa_view,
+                resolve_target: Some(&view),
+                ops: Operations {
+                    load: LoadOp::Clear(Color {
+                        r: r as f64,
+                        g: g as f64,
+                        b: b as f64,
+                        a: a as f64,
+                    }),
+                    store: false,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        if !mesh.indices.is_empty() {
+            rpass.set_pipeline(pipeline);
+            self.vector_mesh.draw(&mut rpass);
+        }
+
+        drop(rpass);
+
+        // submit now to order the draw with blits, like in copy_rect()
+        queue.submit(Some(encoder.finish()));
+
+        // the rendered contents only exist on the GPU
+        self.shadow = None;
+    }
+
     /// Helper function to recreate the canvas's texture object with the given pixels.
-    fn create_texture(device: &Device, queue: &Queue, mut pixels: Pixels) -> Texture {
+    fn create_texture(device: &Device, queue: &Queue, pixels: &mut Pixels) -> Texture {
         // correct the pixel data length
         pixels
             .data
@@ -240,7 +554,10 @@ impl CanvasDraw {
                 sample_count: 1,
                 dimension: TextureDimension::D2,
                 format: TextureFormat::Rgba8UnormSrgb,
-                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::RENDER_ATTACHMENT
+                    | TextureUsages::COPY_SRC
+                    | TextureUsages::COPY_DST,
             },
             &pixels.data,
         )
@@ -285,12 +602,13 @@ pub struct CanvasRoutine {
     queue: Arc<Queue>,
     bgl: BindGroupLayout,
     pipeline: RenderPipeline,
+    vector_pipeline: RenderPipeline,
     sampler: Sampler,
     draws: HashMap<CanvasId, CanvasDraw>,
 }
 
 impl CanvasRoutine {
-    fn new(rend3: &mut Rend3Plugin, ops_rx: Receiver<CanvasOperation>) -> Self {
+    fn new(rend3: &Rend3Plugin, ops_rx: Receiver<CanvasOperation>) -> Self {
         let device = rend3.iad.device.as_ref();
 
         let shader = device.create_shader_module(&include_wgsl!("shaders.wgsl"));
@@ -365,6 +683,51 @@ impl CanvasRoutine {
             multiview: None,
         });
 
+        let vector_shader = device.create_shader_module(&include_wgsl!("vector.wgsl"));
+
+        let vector_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("canvas vector pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let vector_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("canvas vector pipeline"),
+            layout: Some(&vector_layout),
+            vertex: VertexState {
+                module: &vector_shader,
+                entry_point: "vs_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<VectorVertex>() as BufferAddress,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &vertex_attr_array![
+                        0 => Float32x2,
+                        1 => Float32x2,
+                        2 => Float32x4,
+                        3 => Float32x4,
+                        4 => Float32x4,
+                        5 => Uint32,
+                    ],
+                }],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: vector::SAMPLE_COUNT,
+                ..Default::default()
+            },
+            fragment: Some(FragmentState {
+                module: &vector_shader,
+                entry_point: "fs_main",
+                targets: &[ColorTargetState {
+                    format: TextureFormat::Rgba8UnormSrgb,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        });
+
         let sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
@@ -381,6 +744,7 @@ impl CanvasRoutine {
             queue: rend3.iad.queue.to_owned(),
             bgl,
             pipeline,
+            vector_pipeline,
             sampler,
             draws: HashMap::new(),
         }
@@ -399,11 +763,31 @@ impl Routine for CanvasRoutine {
                     match update {
                         CanvasUpdate::Relocate(position) => draw.set_position(position),
                         CanvasUpdate::Blit(blit) => draw.blit(&self.queue, blit),
+                        CanvasUpdate::Damage(blits) => {
+                            for blit in blits {
+                                draw.blit(&self.queue, blit);
+                            }
+                        }
+                        CanvasUpdate::CopyRect { src, x, y } => {
+                            draw.copy_rect(&self.device, &self.queue, src, x, y)
+                        }
                         CanvasUpdate::Resize(pixels) => {
                             draw.resize(&self.device, &self.queue, pixels, &self.bgl, &self.sampler)
                         }
+                        // instances rasterize text into blits, tessellate
+                        // display lists, and open streams before sending
+                        CanvasUpdate::DrawText(_)
+                        | CanvasUpdate::Draw(_)
+                        | CanvasUpdate::OpenStream { .. } => {}
                     }
                 }
+                CanvasOperationKind::Draw(mesh) => {
+                    let Some(draw) = self.draws.get_mut(&id) else {
+                        continue;
+                    };
+
+                    draw.draw_vector(&self.device, &self.queue, &self.vector_pipeline, mesh);
+                }
                 CanvasOperationKind::Create {
                     position,
                     pixels,
@@ -430,6 +814,11 @@ impl Routine for CanvasRoutine {
 
         Box::new(CanvasNode { routine: self })
     }
+
+    fn recreate(&mut self, rend3: &Rend3Plugin) {
+        // updates to the dropped canvases are ignored until they're re-created
+        *self = Self::new(rend3, self.ops_rx.clone());
+    }
 }
 
 /// The canvas rend3 render node.
@@ -440,7 +829,7 @@ pub struct CanvasNode<'a> {
 impl<'a> Node<'a> for CanvasNode<'a> {
     fn draw<'graph>(&'graph self, info: &mut RoutineInfo<'_, 'graph>) {
         let output = info.graph.add_surface_texture();
-        let depth = info.state.depth;
+        let depth = info.depth;
 
         let mut builder = info.graph.add_node("canvas");
         let output_handle = builder.add_render_target_output(output);
@@ -487,19 +876,129 @@ pub struct CanvasInstance {
 
     /// A sender to the canvas routine.
     ops_tx: Sender<CanvasOperation>,
+
+    /// The streams of blits opened to this canvas.
+    streams: Vec<Arc<RingBuffer>>,
 }
 
 impl Drop for CanvasInstance {
     fn drop(&mut self) {
+        for stream in self.streams.iter() {
+            stream.close();
+        }
+
         let _ = self.ops_tx.send((self.id, CanvasOperationKind::Destroy));
     }
 }
 
+/// Forwards the blits streamed to a canvas to the canvas routine until the
+/// stream is closed.
+async fn consume_stream(id: CanvasId, stream: Arc<RingBuffer>, ops_tx: Sender<CanvasOperation>) {
+    while let Some(frame) = stream.recv().await {
+        if stream.is_closed() {
+            break;
+        }
+
+        let Some(blit) = Blit::from_frame(&frame) else {
+            warn!("ignoring malformed canvas stream frame");
+            continue;
+        };
+
+        if !is_valid_size(&blit.pixels) {
+            warn!(
+                "ignoring streamed canvas blit with invalid size {}x{}",
+                blit.pixels.width, blit.pixels.height
+            );
+            continue;
+        }
+
+        let update = CanvasUpdate::Blit(blit);
+        if ops_tx
+            .send((id, CanvasOperationKind::Update(update)))
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
 #[async_trait]
 impl SinkProcess for CanvasInstance {
     type Message = CanvasUpdate;
 
-    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+    async fn on_message<'a>(&'a mut self, mut message: MessageInfo<'a, Self::Message>) {
+        if let CanvasUpdate::OpenStream { capacity } = &message.data {
+            let Some(reply) = message.caps.first() else {
+                warn!("canvas stream request is missing a reply capability");
+                return;
+            };
+
+            let (token, stream) = message.runtime.streams.create(*capacity as usize);
+            let ops_tx = self.ops_tx.clone();
+            tokio::spawn(consume_stream(self.id, stream.clone(), ops_tx));
+            self.streams.push(stream);
+
+            let data = message.codec.encode(&CanvasStream { token });
+            let _ = reply.send(&data, &[]).await;
+            return;
+        }
+
+        // display lists are tessellated here instead of on the render thread
+        if let CanvasUpdate::Draw(list) = &message.data {
+            match VectorMesh::load(&message.runtime.asset_store, list).await {
+                Ok(mesh) => {
+                    let _ = self.ops_tx.send((self.id, CanvasOperationKind::Draw(mesh)));
+                }
+                Err(err) => warn!("ignoring invalid canvas display list: {:?}", err),
+            }
+
+            return;
+        }
+
+        // text is rasterized here and sent to the routine as a regular blit
+        if let CanvasUpdate::DrawText(draw) = &message.data {
+            let asset_store = &message.runtime.asset_store;
+            let font = match asset_store.load_asset::<FontLoader>(&draw.font).await {
+                Ok(font) => font,
+                Err(err) => {
+                    warn!("failed to load canvas text font: {:?}", err);
+                    return;
+                }
+            };
+
+            let Some(pixels) = text::rasterize(&font, draw, MAX_SIZE) else {
+                warn!("ignoring canvas text with invalid size");
+                return;
+            };
+
+            message.data = CanvasUpdate::Blit(Blit {
+                x: draw.x,
+                y: draw.y,
+                pixels,
+            });
+        }
+
+        let pixels = match &message.data {
+            CanvasUpdate::Resize(pixels) => vec![pixels],
+            CanvasUpdate::Blit(blit) => vec![&blit.pixels],
+            CanvasUpdate::Damage(blits) => blits.iter().map(|blit| &blit.pixels).collect(),
+            CanvasUpdate::Relocate(_)
+            | CanvasUpdate::CopyRect { .. }
+            | CanvasUpdate::DrawText(_)
+            | CanvasUpdate::Draw(_)
+            | CanvasUpdate::OpenStream { .. } => vec![],
+        };
+
+        for pixels in pixels {
+            if !is_valid_size(pixels) {
+                warn!(
+                    "ignoring canvas update with invalid size {}x{}",
+                    pixels.width, pixels.height
+                );
+                return;
+            }
+        }
+
         let _ = self
             .ops_tx
             .send((self.id, CanvasOperationKind::Update(message.data)));
@@ -531,6 +1030,10 @@ impl RequestResponseProcess for CanvasFactory {
                 pixels,
                 sampling,
             } => {
+                if !is_valid_size(pixels) {
+                    return FactoryError::InvalidSize.into();
+                }
+
                 // allocate a new ID
                 let id = self.next_id;
                 self.next_id += 1;
@@ -549,6 +1052,7 @@ impl RequestResponseProcess for CanvasFactory {
                 let instance = CanvasInstance {
                     id,
                     ops_tx: self.ops_tx.clone(),
+                    streams: Vec::new(),
                 };
 
                 // spawn the instance child process
@@ -595,6 +1099,82 @@ impl Plugin for CanvasPlugin {
         let (ops_tx, ops_rx) = flume::unbounded();
         let routine = CanvasRoutine::new(rend3, ops_rx);
         rend3.add_routine(routine);
-        builder.add_plugin(CanvasFactory { next_id: 0, ops_tx });
+        builder
+            .add_asset_loader(FontLoader)
+            .add_plugin(CanvasFactory { next_id: 0, ops_tx });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn clip_copy_in_bounds() {
+        let src = rect(0, 4, 16, 12);
+        assert_eq!(clip_copy(16, 16, src, 0, 0), Some(src));
+    }
+
+    #[test]
+    fn clip_copy_clips_source_and_destination() {
+        assert_eq!(
+            clip_copy(16, 16, rect(8, 0, 16, 16), 0, 0),
+            Some(rect(8, 0, 8, 16))
+        );
+
+        assert_eq!(
+            clip_copy(16, 16, rect(0, 0, 16, 16), 0, 12),
+            Some(rect(0, 0, 16, 4))
+        );
+    }
+
+    #[test]
+    fn clip_copy_empty() {
+        assert_eq!(clip_copy(16, 16, rect(16, 0, 4, 4), 0, 0), None);
+        assert_eq!(clip_copy(16, 16, rect(0, 0, 4, 4), 0, 16), None);
+        assert_eq!(clip_copy(16, 16, rect(0, 0, 0, 4), 0, 0), None);
+    }
+
+    /// Creates a 4x4 pixel buffer where each pixel's channels are its index.
+    fn numbered() -> Vec<u8> {
+        (0..16u8).flat_map(|i| [i; 4]).collect()
+    }
+
+    #[test]
+    fn dirty_rect_identical() {
+        assert_eq!(dirty_rect(4, &numbered(), &numbered()), None);
+    }
+
+    #[test]
+    fn dirty_rect_single_pixel() {
+        let mut new = numbered();
+        new[(2 * 4 + 1) * 4 + 3] = 0xff;
+        assert_eq!(dirty_rect(4, &numbered(), &new), Some(rect(1, 2, 1, 1)));
+    }
+
+    #[test]
+    fn dirty_rect_spans_rows() {
+        let mut new = numbered();
+        new[2 * 4] = 0xff;
+        new[3 * 4 * 4] = 0xff;
+        assert_eq!(dirty_rect(4, &numbered(), &new), Some(rect(0, 0, 3, 4)));
+    }
+
+    #[test]
+    fn copy_pixels_region() {
+        let mut dst = vec![0; 16 * 4];
+        copy_pixels(&mut dst, 4, 2, 3, &numbered(), 4, rect(1, 1, 2, 1));
+        let mut expected = vec![0; 16 * 4];
+        expected[(3 * 4 + 2) * 4..].copy_from_slice(&numbered()[5 * 4..7 * 4]);
+        assert_eq!(dst, expected);
     }
 }
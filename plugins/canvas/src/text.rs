@@ -0,0 +1,203 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! CPU-side text rasterization for canvases.
+
+use ab_glyph::{point, Font, FontVec, Glyph, PxScale, ScaleFont};
+use hearth_runtime::{
+    anyhow::{self, Context},
+    asset::{AssetLoader, AssetStore},
+    async_trait,
+    hearth_schema::canvas::{Pixels, TextDraw},
+};
+
+/// Loads TrueType and OpenType fonts from lumps.
+pub struct FontLoader;
+
+#[async_trait]
+impl AssetLoader for FontLoader {
+    type Asset = FontVec;
+
+    async fn load_asset(&self, _store: &AssetStore, data: &[u8]) -> anyhow::Result<Self::Asset> {
+        FontVec::try_from_vec(data.to_vec()).context("parsing font")
+    }
+}
+
+/// Blends `fg` over `bg` with the given coverage.
+fn blend(bg: [u8; 4], fg: [u8; 4], coverage: f32) -> [u8; 4] {
+    let alpha = coverage.clamp(0.0, 1.0) * fg[3] as f32 / 255.0;
+    let mut out = [0; 4];
+    for (out, (bg, fg)) in out.iter_mut().zip(bg.into_iter().zip(fg)) {
+        *out = (bg as f32 + (fg as f32 - bg as f32) * alpha).round() as u8;
+    }
+
+    out
+}
+
+/// A run of text laid out into positioned glyphs.
+pub struct TextLayout {
+    pub glyphs: Vec<Glyph>,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl TextLayout {
+    /// Lays out lines of text with kerning at a given line height in pixels.
+    ///
+    /// Glyphs are positioned on their baselines relative to the top-left
+    /// corner of the text. Returns `None` if the size isn't positive.
+    pub fn new(font: &FontVec, size: f32, text: &str) -> Option<Self> {
+        if !size.is_finite() || size <= 0.0 {
+            return None;
+        }
+
+        let scale = PxScale::from(size);
+        let font = font.as_scaled(scale);
+        let line_height = font.height() + font.line_gap();
+
+        let mut glyphs = Vec::new();
+        let mut width = 0.0f32;
+        let mut lines = 0;
+        for (line, text) in text.lines().enumerate() {
+            let baseline = line as f32 * line_height + font.ascent();
+            let mut caret = 0.0;
+            let mut last = None;
+            for c in text.chars() {
+                let id = font.glyph_id(c);
+                if let Some(last) = last {
+                    caret += font.kern(last, id);
+                }
+
+                glyphs.push(id.with_scale_and_position(scale, point(caret, baseline)));
+                caret += font.h_advance(id);
+                last = Some(id);
+            }
+
+            width = width.max(caret);
+            lines = line + 1;
+        }
+
+        Some(Self {
+            glyphs,
+            width,
+            height: lines as f32 * line_height,
+        })
+    }
+}
+
+/// Rasterizes a [TextDraw] into a pixel buffer fit to the text's bounds.
+///
+/// Returns `None` if the text is empty or its bounds exceed `max_size`.
+pub fn rasterize(font: &FontVec, draw: &TextDraw, max_size: u32) -> Option<Pixels> {
+    let layout = TextLayout::new(font, draw.size, &draw.text)?;
+    let width = layout.width.ceil() as u32;
+    let height = layout.height.ceil() as u32;
+    let valid = 1..=max_size;
+    if !valid.contains(&width) || !valid.contains(&height) {
+        return None;
+    }
+
+    let mut data: Vec<u8> = draw
+        .background
+        .iter()
+        .copied()
+        .cycle()
+        .take(width as usize * height as usize * 4)
+        .collect();
+
+    for glyph in layout.glyphs {
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+
+        let bounds = outlined.px_bounds();
+        outlined.draw(|x, y, coverage| {
+            let x = bounds.min.x as i32 + x as i32;
+            let y = bounds.min.y as i32 + y as i32;
+            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                return;
+            }
+
+            let idx = (y as usize * width as usize + x as usize) * 4;
+            let pixel = &mut data[idx..(idx + 4)];
+            let bg = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            pixel.copy_from_slice(&blend(bg, draw.color, coverage));
+        });
+    }
+
+    Some(Pixels {
+        width,
+        height,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hearth_runtime::hearth_schema::LumpId;
+
+    fn mononoki() -> FontVec {
+        let src = include_bytes!("../../../resources/mononoki/mononoki-Regular.ttf");
+        FontVec::try_from_vec(src.to_vec()).unwrap()
+    }
+
+    fn text_draw(text: &str) -> TextDraw {
+        TextDraw {
+            x: 0,
+            y: 0,
+            font: LumpId([0; 32]),
+            size: 16.0,
+            color: [255, 255, 255, 255],
+            background: [0, 0, 0, 255],
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn blend_coverage() {
+        let bg = [0, 0, 0, 255];
+        let fg = [255, 255, 255, 255];
+        assert_eq!(blend(bg, fg, 0.0), bg);
+        assert_eq!(blend(bg, fg, 1.0), fg);
+        assert_eq!(blend(bg, [255, 255, 255, 0], 1.0), bg);
+    }
+
+    #[test]
+    fn rasterize_fits_bounds() {
+        let font = mononoki();
+        let one = rasterize(&font, &text_draw("hi"), 8192).unwrap();
+        let two = rasterize(&font, &text_draw("hi\nhi"), 8192).unwrap();
+        assert_eq!(one.width, two.width);
+        assert!(two.height > one.height);
+        assert_eq!(one.data.len(), (one.width * one.height * 4) as usize);
+        assert!(one.data.chunks(4).any(|pixel| pixel[0] > 0));
+    }
+
+    #[test]
+    fn rasterize_rejects_invalid() {
+        let font = mononoki();
+        assert!(rasterize(&font, &text_draw(""), 8192).is_none());
+        assert!(rasterize(&font, &text_draw("too wide"), 4).is_none());
+
+        let mut draw = text_draw("hi");
+        draw.size = f32::NAN;
+        assert!(rasterize(&font, &draw, 8192).is_none());
+    }
+}
@@ -0,0 +1,508 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tessellation and GPU rendering of vector [DisplayList]s.
+
+use std::collections::HashMap;
+
+use ab_glyph::{Font, FontVec, OutlineCurve, ScaleFont};
+use bytemuck::{Pod, Zeroable};
+use hearth_runtime::{
+    anyhow::{self, bail, ensure, Context},
+    asset::AssetStore,
+    hearth_schema::{canvas::*, LumpId},
+};
+use lyon::{
+    math::point,
+    path::{path::Builder, Path},
+    tessellation::{
+        BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator,
+        StrokeVertex, VertexBuffers,
+    },
+};
+
+use crate::text::{FontLoader, TextLayout};
+
+/// The most vertices that a single display list may tessellate into.
+pub const MAX_VERTICES: usize = 1 << 20;
+
+/// The maximum distance in pixels between tessellated curves and the curves
+/// they approximate.
+const TOLERANCE: f32 = 0.1;
+
+/// The number of samples per pixel to render display lists with.
+pub const SAMPLE_COUNT: u32 = 4;
+
+/// A tessellated vertex of a display list.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+pub struct VectorVertex {
+    /// The position of this vertex in clip space. Filled in by
+    /// [VectorMesh::to_clip_space] once the canvas's size is known.
+    pub position: [f32; 2],
+
+    /// The position of this vertex in canvas pixels.
+    pub pixel: [f32; 2],
+
+    /// The gradient's parameters: start and end points for linear gradients,
+    /// or the center and radius for radial gradients.
+    pub params: [f32; 4],
+
+    /// The linear, unpremultiplied start color of the paint.
+    pub color0: [f32; 4],
+
+    /// The linear, unpremultiplied end color of the paint.
+    pub color1: [f32; 4],
+
+    /// The kind of paint: 0 for solid, 1 for linear, and 2 for radial.
+    pub kind: u32,
+    pub _padding: [u32; 3],
+}
+
+impl VectorVertex {
+    /// Creates a vertex at a pixel position with the given paint.
+    fn new(pixel: [f32; 2], paint: &Paint) -> Self {
+        let (kind, params, color0, color1) = match *paint {
+            Paint::Solid(color) => (0, [0.0; 4], color, color),
+            Paint::LinearGradient {
+                start,
+                end,
+                start_color,
+                end_color,
+            } => (1, [start.x, start.y, end.x, end.y], start_color, end_color),
+            Paint::RadialGradient {
+                center,
+                radius,
+                inner_color,
+                outer_color,
+            } => (
+                2,
+                [center.x, center.y, radius, 0.0],
+                inner_color,
+                outer_color,
+            ),
+        };
+
+        Self {
+            position: [0.0; 2],
+            pixel,
+            params,
+            color0: srgb_to_linear(color0),
+            color1: srgb_to_linear(color1),
+            kind,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Converts an sRGB color to linear RGB. Alpha is already linear.
+pub fn srgb_to_linear(color: [u8; 4]) -> [f32; 4] {
+    let convert = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    [
+        convert(color[0]),
+        convert(color[1]),
+        convert(color[2]),
+        color[3] as f32 / 255.0,
+    ]
+}
+
+/// A tessellated display list, ready to upload to the GPU.
+#[derive(Debug)]
+pub struct VectorMesh {
+    pub background: [u8; 4],
+    pub vertices: Vec<VectorVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl VectorMesh {
+    /// Loads the fonts used by a display list and tessellates it.
+    pub async fn load(asset_store: &AssetStore, list: &DisplayList) -> anyhow::Result<Self> {
+        let mut fonts = HashMap::new();
+        for command in list.commands.iter() {
+            if let DrawCommand::Text { font, .. } = command {
+                if !fonts.contains_key(font) {
+                    let loaded = asset_store.load_asset::<FontLoader>(font).await?;
+                    fonts.insert(*font, loaded);
+                }
+            }
+        }
+
+        let fonts = fonts
+            .iter()
+            .map(|(id, font)| (*id, font.as_ref()))
+            .collect();
+
+        Self::new(list, &fonts)
+    }
+
+    /// Tessellates a display list.
+    ///
+    /// `fonts` must contain every font used by the list's text commands.
+    pub fn new(list: &DisplayList, fonts: &HashMap<LumpId, &FontVec>) -> anyhow::Result<Self> {
+        let mut buffers: VertexBuffers<VectorVertex, u32> = VertexBuffers::new();
+        let mut fill = FillTessellator::new();
+        let mut stroke = StrokeTessellator::new();
+        let fill_options = FillOptions::non_zero().with_tolerance(TOLERANCE);
+
+        for command in list.commands.iter() {
+            match command {
+                DrawCommand::Fill { path, paint } => {
+                    let path = build_path(path)?;
+                    let mut builder = BuffersBuilder::new(&mut buffers, |v: FillVertex| {
+                        VectorVertex::new(v.position().to_array(), paint)
+                    });
+
+                    fill.tessellate_path(&path, &fill_options, &mut builder)
+                        .context("tessellating fill")?;
+                }
+                DrawCommand::Stroke { path, width, paint } => {
+                    ensure!(width.is_finite() && *width > 0.0, "invalid stroke width");
+
+                    let path = build_path(path)?;
+                    let options = StrokeOptions::default()
+                        .with_line_width(*width)
+                        .with_tolerance(TOLERANCE);
+
+                    let mut builder = BuffersBuilder::new(&mut buffers, |v: StrokeVertex| {
+                        VectorVertex::new(v.position().to_array(), paint)
+                    });
+
+                    stroke
+                        .tessellate_path(&path, &options, &mut builder)
+                        .context("tessellating stroke")?;
+                }
+                DrawCommand::Text {
+                    position,
+                    font,
+                    size,
+                    paint,
+                    text,
+                } => {
+                    ensure!(position.is_finite(), "invalid text position");
+                    let font = fonts.get(font).context("text font is not loaded")?;
+                    let path = build_text_path(font, *size, text, [position.x, position.y])?;
+                    let mut builder = BuffersBuilder::new(&mut buffers, |v: FillVertex| {
+                        VectorVertex::new(v.position().to_array(), paint)
+                    });
+
+                    fill.tessellate_path(&path, &fill_options, &mut builder)
+                        .context("tessellating text")?;
+                }
+            }
+
+            if buffers.vertices.len() > MAX_VERTICES {
+                bail!("display list has more than {} vertices", MAX_VERTICES);
+            }
+        }
+
+        Ok(Self {
+            background: list.background,
+            vertices: buffers.vertices,
+            indices: buffers.indices,
+        })
+    }
+
+    /// Fills in the clip space positions of the vertices for a canvas of the
+    /// given size.
+    pub fn to_clip_space(&mut self, width: u32, height: u32) {
+        for vertex in self.vertices.iter_mut() {
+            // canvas pixels are Y-down, while clip space is Y-up
+            vertex.position = [
+                vertex.pixel[0] / width as f32 * 2.0 - 1.0,
+                1.0 - vertex.pixel[1] / height as f32 * 2.0,
+            ];
+        }
+    }
+}
+
+/// Builds a lyon path from [PathSegment]s.
+fn build_path(segments: &[PathSegment]) -> anyhow::Result<Path> {
+    let mut builder = PathBuilder::default();
+
+    for segment in segments.iter() {
+        match *segment {
+            PathSegment::MoveTo(to) => builder.move_to(to.to_array())?,
+            PathSegment::LineTo(to) => builder.line_to(to.to_array())?,
+            PathSegment::QuadTo(ctrl, to) => builder.quad_to(ctrl.to_array(), to.to_array())?,
+            PathSegment::CubicTo(ctrl1, ctrl2, to) => {
+                builder.cubic_to(ctrl1.to_array(), ctrl2.to_array(), to.to_array())?
+            }
+            PathSegment::Close => builder.close(),
+        }
+    }
+
+    Ok(builder.build())
+}
+
+/// Builds a single path from the glyph outlines of a run of text.
+fn build_text_path(
+    font: &FontVec,
+    size: f32,
+    text: &str,
+    origin: [f32; 2],
+) -> anyhow::Result<Path> {
+    let layout = TextLayout::new(font, size, text).context("invalid text size")?;
+    let mut builder = PathBuilder::default();
+
+    for glyph in layout.glyphs {
+        let Some(outline) = font.outline(glyph.id) else {
+            continue;
+        };
+
+        // outlines are in unscaled font units and are Y-up
+        let factor = font.as_scaled(glyph.scale).scale_factor();
+        let x = origin[0] + glyph.position.x;
+        let y = origin[1] + glyph.position.y;
+        let transform =
+            |p: ab_glyph::Point| [x + p.x * factor.horizontal, y - p.y * factor.vertical];
+
+        for curve in outline.curves {
+            let (from, to) = match curve {
+                OutlineCurve::Line(from, to) => (from, to),
+                OutlineCurve::Quad(from, _, to) => (from, to),
+                OutlineCurve::Cubic(from, _, _, to) => (from, to),
+            };
+
+            // a curve that doesn't continue the last one starts a new contour
+            builder.continue_from(transform(from))?;
+
+            match curve {
+                OutlineCurve::Line(..) => builder.line_to(transform(to))?,
+                OutlineCurve::Quad(_, ctrl, _) => {
+                    builder.quad_to(transform(ctrl), transform(to))?
+                }
+                OutlineCurve::Cubic(_, ctrl1, ctrl2, _) => {
+                    builder.cubic_to(transform(ctrl1), transform(ctrl2), transform(to))?
+                }
+            }
+        }
+
+        builder.close();
+    }
+
+    Ok(builder.build())
+}
+
+/// A wrapper around lyon's path builder that validates points and tracks
+/// whether a subpath is open, which lyon requires callers to do.
+struct PathBuilder {
+    inner: Builder,
+
+    /// The current point of the open subpath, if any.
+    current: Option<[f32; 2]>,
+}
+
+impl Default for PathBuilder {
+    fn default() -> Self {
+        Self {
+            inner: Path::builder(),
+            current: None,
+        }
+    }
+}
+
+impl PathBuilder {
+    fn check(point: [f32; 2]) -> anyhow::Result<()> {
+        ensure!(point.iter().all(|c| c.is_finite()), "invalid path point");
+        Ok(())
+    }
+
+    fn move_to(&mut self, to: [f32; 2]) -> anyhow::Result<()> {
+        Self::check(to)?;
+
+        if self.current.is_some() {
+            self.inner.end(false);
+        }
+
+        self.inner.begin(point(to[0], to[1]));
+        self.current = Some(to);
+        Ok(())
+    }
+
+    /// Begins a new subpath at a point unless the open subpath is already
+    /// there, closing the previous subpath.
+    fn continue_from(&mut self, from: [f32; 2]) -> anyhow::Result<()> {
+        if self.current != Some(from) {
+            self.close();
+            self.move_to(from)?;
+        }
+
+        Ok(())
+    }
+
+    /// Begins a subpath at a point if one isn't already open.
+    fn ensure_begun(&mut self, at: [f32; 2]) -> anyhow::Result<()> {
+        if self.current.is_none() {
+            self.move_to(at)?;
+        }
+
+        Ok(())
+    }
+
+    fn line_to(&mut self, to: [f32; 2]) -> anyhow::Result<()> {
+        Self::check(to)?;
+        self.ensure_begun(to)?;
+        self.inner.line_to(point(to[0], to[1]));
+        self.current = Some(to);
+        Ok(())
+    }
+
+    fn quad_to(&mut self, ctrl: [f32; 2], to: [f32; 2]) -> anyhow::Result<()> {
+        Self::check(ctrl)?;
+        Self::check(to)?;
+        self.ensure_begun(ctrl)?;
+        self.inner
+            .quadratic_bezier_to(point(ctrl[0], ctrl[1]), point(to[0], to[1]));
+        self.current = Some(to);
+        Ok(())
+    }
+
+    fn cubic_to(&mut self, ctrl1: [f32; 2], ctrl2: [f32; 2], to: [f32; 2]) -> anyhow::Result<()> {
+        Self::check(ctrl1)?;
+        Self::check(ctrl2)?;
+        Self::check(to)?;
+        self.ensure_begun(ctrl1)?;
+        self.inner.cubic_bezier_to(
+            point(ctrl1[0], ctrl1[1]),
+            point(ctrl2[0], ctrl2[1]),
+            point(to[0], to[1]),
+        );
+        self.current = Some(to);
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if self.current.take().is_some() {
+            self.inner.end(true);
+        }
+    }
+
+    fn build(mut self) -> Path {
+        if self.current.take().is_some() {
+            self.inner.end(false);
+        }
+
+        self.inner.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hearth_rend3::rend3::types::glam::vec2;
+
+    fn square(size: f32) -> Vec<PathSegment> {
+        vec![
+            PathSegment::MoveTo(vec2(0.0, 0.0)),
+            PathSegment::LineTo(vec2(size, 0.0)),
+            PathSegment::LineTo(vec2(size, size)),
+            PathSegment::LineTo(vec2(0.0, size)),
+            PathSegment::Close,
+        ]
+    }
+
+    fn tessellate(commands: Vec<DrawCommand>) -> anyhow::Result<VectorMesh> {
+        let list = DisplayList {
+            background: [0, 0, 0, 255],
+            commands,
+        };
+
+        VectorMesh::new(&list, &HashMap::new())
+    }
+
+    #[test]
+    fn srgb_to_linear_endpoints() {
+        assert_eq!(srgb_to_linear([0, 0, 0, 0]), [0.0; 4]);
+        assert_eq!(srgb_to_linear([255, 255, 255, 255]), [1.0; 4]);
+        assert!(srgb_to_linear([128, 0, 0, 128])[0] < 0.5);
+    }
+
+    #[test]
+    fn fill_square() {
+        let mesh = tessellate(vec![DrawCommand::Fill {
+            path: square(10.0),
+            paint: Paint::Solid([255; 4]),
+        }])
+        .unwrap();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+    }
+
+    #[test]
+    fn clip_space_corners() {
+        let mut mesh = tessellate(vec![DrawCommand::Fill {
+            path: square(10.0),
+            paint: Paint::Solid([255; 4]),
+        }])
+        .unwrap();
+
+        mesh.to_clip_space(10, 10);
+
+        for vertex in mesh.vertices.iter() {
+            let expected = [
+                if vertex.pixel[0] == 0.0 { -1.0 } else { 1.0 },
+                if vertex.pixel[1] == 0.0 { 1.0 } else { -1.0 },
+            ];
+
+            assert_eq!(vertex.position, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_geometry() {
+        let mut path = square(10.0);
+        path.push(PathSegment::LineTo(vec2(f32::NAN, 0.0)));
+        let fill = DrawCommand::Fill {
+            path,
+            paint: Paint::Solid([255; 4]),
+        };
+
+        assert!(tessellate(vec![fill]).is_err());
+
+        let stroke = DrawCommand::Stroke {
+            path: square(10.0),
+            width: -1.0,
+            paint: Paint::Solid([255; 4]),
+        };
+
+        assert!(tessellate(vec![stroke]).is_err());
+    }
+
+    #[test]
+    fn text_requires_loaded_font() {
+        let text = DrawCommand::Text {
+            position: vec2(0.0, 0.0),
+            font: LumpId([0; 32]),
+            size: 16.0,
+            paint: Paint::Solid([255; 4]),
+            text: "hi".to_string(),
+        };
+
+        assert!(tessellate(vec![text]).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+struct VertexIn {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] pixel: vec2<f32>;
+    [[location(2)]] params: vec4<f32>;
+    [[location(3)]] color0: vec4<f32>;
+    [[location(4)]] color1: vec4<f32>;
+    [[location(5)]] kind: u32;
+};
+
+struct VertexOut {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] pixel: vec2<f32>;
+    [[location(1)]] params: vec4<f32>;
+    [[location(2)]] color0: vec4<f32>;
+    [[location(3)]] color1: vec4<f32>;
+    [[location(4), interpolate(flat)]] kind: u32;
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: VertexIn) -> VertexOut {
+    var output: VertexOut;
+    output.clip_position = vec4<f32>(vertex.position, 0.0, 1.0);
+    output.pixel = vertex.pixel;
+    output.params = vertex.params;
+    output.color0 = vertex.color0;
+    output.color1 = vertex.color1;
+    output.kind = vertex.kind;
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_main(frag: VertexOut) -> [[location(0)]] vec4<f32> {
+    var t: f32 = 0.0;
+
+    if (frag.kind == 1u) {
+        // linear gradient: project onto the line from start to end
+        let dir = frag.params.zw - frag.params.xy;
+        t = dot(frag.pixel - frag.params.xy, dir) / max(dot(dir, dir), 0.000001);
+    } else if (frag.kind == 2u) {
+        // radial gradient: distance from the center relative to the radius
+        t = length(frag.pixel - frag.params.xy) / max(frag.params.z, 0.000001);
+    }
+
+    let color = mix(frag.color0, frag.color1, vec4<f32>(clamp(t, 0.0, 1.0)));
+
+    // output premultiplied alpha
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
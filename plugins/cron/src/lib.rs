@@ -0,0 +1,478 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc};
+
+use chrono::Utc;
+use cron::Schedule;
+use hearth_runtime::{
+    anyhow::{self, bail, Context},
+    async_trait,
+    flue::{CapabilityRef, OwnedCapability, Permissions, PostOffice, Table, TableSignal},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::{
+        codec::{self, Codec},
+        cron::*,
+        registry::*,
+    },
+    process::Process,
+    runtime::{Plugin, Runtime, RuntimeBuilder},
+    tokio::{self, task::JoinHandle},
+    tracing::{debug, error, info, warn},
+    utils::*,
+};
+use serde::Deserialize;
+
+/// Configuration for the cron plugin, read from the `cron` table of the
+/// config file.
+#[derive(Debug, Default, Deserialize)]
+pub struct CronConfig {
+    /// The file to persist service-targeted tasks in.
+    ///
+    /// Defaults to `cron.json` in the Hearth data directory.
+    pub state: Option<PathBuf>,
+
+    /// Tasks to schedule at startup. These are not persisted.
+    #[serde(default)]
+    pub tasks: Vec<ConfigTask>,
+}
+
+/// A task defined in the config file.
+#[derive(Debug, Deserialize)]
+pub struct ConfigTask {
+    /// The task's cron expression. See [CronTask::schedule].
+    pub schedule: String,
+
+    /// The name of the native service to deliver the message to.
+    pub service: String,
+
+    /// The message to deliver, converted to JSON.
+    pub message: toml::Value,
+}
+
+/// A plugin that provides the [CronService].
+pub struct CronPlugin {
+    state_path: PathBuf,
+    config_tasks: Vec<CronTask>,
+}
+
+impl Default for CronPlugin {
+    fn default() -> Self {
+        Self::new(CronConfig::default())
+    }
+}
+
+impl Plugin for CronPlugin {
+    fn finalize(self, builder: &mut RuntimeBuilder) {
+        builder.add_plugin(CronService {
+            scheduler: Scheduler {
+                state_path: self.state_path,
+                next_id: 0,
+                tasks: HashMap::new(),
+            },
+            config_tasks: self.config_tasks,
+        });
+    }
+}
+
+impl CronPlugin {
+    /// Creates a new cron plugin with the given configuration.
+    pub fn new(config: CronConfig) -> Self {
+        let state_path = config
+            .state
+            .unwrap_or_else(|| hearth_runtime::get_data_dir().join("cron.json"));
+
+        let config_tasks = config
+            .tasks
+            .into_iter()
+            .map(|task| CronTask {
+                schedule: task.schedule,
+                target: CronTarget::Service(task.service),
+                // the service may be a guest that only parses JSON
+                data: Codec::Json.encode(&task.message),
+            })
+            .collect();
+
+        Self {
+            state_path,
+            config_tasks,
+        }
+    }
+
+    /// Creates a new cron plugin from the `cron` table of a config file.
+    ///
+    /// Logs an error and uses the default configuration if the table is
+    /// malformed.
+    pub fn from_config_file(config: &toml::Table) -> Self {
+        let Some(table) = config.get("cron") else {
+            return Self::default();
+        };
+
+        match table.clone().try_into() {
+            Ok(config) => Self::new(config),
+            Err(err) => {
+                error!("Failed to parse cron config: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// A scheduled task and its delivery loop.
+struct ScheduledTask {
+    task: CronTask,
+    schedule: Schedule,
+    persist: bool,
+    join: JoinHandle<()>,
+}
+
+impl Drop for ScheduledTask {
+    fn drop(&mut self) {
+        self.join.abort();
+    }
+}
+
+/// The request-handling half of the [CronService].
+struct Scheduler {
+    state_path: PathBuf,
+    next_id: u64,
+    tasks: HashMap<u64, ScheduledTask>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for Scheduler {
+    type Request = CronRequest;
+    type Response = CronResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Request>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        match &request.data {
+            CronRequest::Schedule(task) => {
+                let persist = matches!(task.target, CronTarget::Service(_));
+                let cap = request.cap_args.first().ok_or(CronError::MissingTarget);
+                let target = match (&task.target, cap) {
+                    (_, Err(err)) => Err(err),
+                    (CronTarget::Capability, Ok(cap)) => Ok(cap.to_owned()),
+                    (CronTarget::Service(name), Ok(cap)) => {
+                        check_service(request.runtime, request.process, cap, name).await
+                    }
+                };
+
+                let result = target.and_then(|target| {
+                    self.add_task(request.runtime, target, task.clone(), persist)
+                });
+
+                if persist && result.is_ok() {
+                    self.save().await;
+                }
+
+                result.map(CronSuccess::Scheduled).into()
+            }
+            CronRequest::Cancel(id) => {
+                let Some(task) = self.tasks.remove(id) else {
+                    return CronError::TaskNotFound.into();
+                };
+
+                if task.persist {
+                    self.save().await;
+                }
+
+                Ok(CronSuccess::Cancelled).into()
+            }
+            CronRequest::List => {
+                let mut list: Vec<_> = self
+                    .tasks
+                    .iter()
+                    .map(|(id, task)| CronTaskInfo {
+                        id: *id,
+                        task: task.task.clone(),
+                        next: task.schedule.upcoming(Utc).next().map(|t| t.timestamp()),
+                    })
+                    .collect();
+
+                list.sort_by_key(|info| info.id);
+
+                Ok(CronSuccess::List(list)).into()
+            }
+        }
+    }
+}
+
+impl Scheduler {
+    /// Parses a task and starts delivering it to its resolved target.
+    fn add_task(
+        &mut self,
+        runtime: &Runtime,
+        target: OwnedCapability,
+        task: CronTask,
+        persist: bool,
+    ) -> Result<u64, CronError> {
+        let schedule = Schedule::from_str(&task.schedule)
+            .map_err(|err| CronError::InvalidSchedule(err.to_string()))?;
+
+        let join = tokio::spawn(deliver(
+            runtime.post.clone(),
+            schedule.clone(),
+            target,
+            task.data.clone(),
+        ));
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.tasks.insert(
+            id,
+            ScheduledTask {
+                task,
+                schedule,
+                persist,
+                join,
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Loads the persisted tasks from the state file.
+    async fn load(&self) -> anyhow::Result<Vec<CronTask>> {
+        if !self.state_path.exists() {
+            return Ok(vec![]);
+        }
+
+        let data = tokio::fs::read(&self.state_path).await?;
+        let tasks = serde_json::from_slice(&data)?;
+        Ok(tasks)
+    }
+
+    /// Writes all persisted tasks to the state file, logging any errors.
+    async fn save(&self) {
+        let mut tasks: Vec<_> = self.tasks.iter().filter(|(_, task)| task.persist).collect();
+        tasks.sort_by_key(|(id, _)| **id);
+        let tasks: Vec<_> = tasks.into_iter().map(|(_, task)| &task.task).collect();
+        let data = serde_json::to_vec_pretty(&tasks).unwrap();
+
+        if let Some(parent) = self.state_path.parent() {
+            if let Err(err) = tokio::fs::create_dir_all(parent).await {
+                error!("Failed to create cron state directory: {:?}", err);
+                return;
+            }
+        }
+
+        if let Err(err) = tokio::fs::write(&self.state_path, data).await {
+            error!("Failed to save cron state: {:?}", err);
+        }
+    }
+}
+
+/// Native service that delivers messages on recurring schedules. Accepts
+/// [CronRequest].
+///
+/// Tasks that target native services by name are saved to disk and
+/// rescheduled when the runtime restarts. Processes can only schedule them
+/// with a capability to the named service.
+#[derive(GetProcessMetadata)]
+pub struct CronService {
+    scheduler: Scheduler,
+    config_tasks: Vec<CronTask>,
+}
+
+#[async_trait]
+impl ProcessRunner for CronService {
+    async fn run(
+        mut self,
+        label: String,
+        runtime: Arc<Runtime>,
+        ctx: &Process,
+        token: ProcessRunToken,
+    ) {
+        let persisted = match self.scheduler.load().await {
+            Ok(tasks) => tasks,
+            Err(err) => {
+                error!("Failed to load cron state: {:?}", err);
+                vec![]
+            }
+        };
+
+        let tasks = persisted
+            .into_iter()
+            .map(|task| (task, true))
+            .chain(self.config_tasks.into_iter().map(|task| (task, false)));
+
+        // these tasks come from the config file or were checked when they
+        // were first scheduled, so their services are looked up directly
+        for (task, persist) in tasks {
+            let schedule = task.schedule.clone();
+            let target = match &task.target {
+                CronTarget::Capability => Err(CronError::MissingTarget),
+                CronTarget::Service(name) => resolve_service(&runtime, ctx, name).await,
+            };
+
+            match target.and_then(|target| self.scheduler.add_task(&runtime, target, task, persist))
+            {
+                Ok(_) => info!("Scheduled cron task {:?}", schedule),
+                Err(err) => warn!("Failed to schedule cron task {:?}: {:?}", schedule, err),
+            }
+        }
+
+        self.scheduler.run(label, runtime, ctx, token).await;
+    }
+}
+
+impl ServiceRunner for CronService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// Delivers a task's message to its target on every tick of its schedule.
+async fn deliver(
+    post: Arc<PostOffice>,
+    schedule: Schedule,
+    target: OwnedCapability,
+    data: Vec<u8>,
+) {
+    let table = Table::new(post);
+    let target = match table.import_owned(target) {
+        Ok(target) => target,
+        Err(err) => {
+            error!("Failed to import cron task target: {:?}", err);
+            return;
+        }
+    };
+
+    let mut last = Utc::now();
+    while let Some(next) = schedule.after(&last).next() {
+        let delay = (next - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(delay).await;
+        last = next;
+
+        if let Err(err) = table.send(target, &data, &[]).await {
+            debug!("Cron task target is unavailable: {:?}", err);
+            break;
+        }
+    }
+}
+
+/// Checks that a capability given by a process is to the named native
+/// service, so that processes can't schedule deliveries to services that
+/// were withheld from them. Returns the service on success.
+async fn check_service(
+    runtime: &Runtime,
+    ctx: &Process,
+    cap: &CapabilityRef<'_>,
+    name: &str,
+) -> Result<OwnedCapability, CronError> {
+    let service = resolve_service(runtime, ctx, name).await?;
+
+    let table = ctx.borrow_table();
+    let Some(service_ref) = table
+        .import_owned(service.clone())
+        .ok()
+        .and_then(|handle| table.wrap_handle(handle).ok())
+    else {
+        return Err(CronError::ServiceNotFound(name.to_owned()));
+    };
+
+    // capabilities to the same route share a handle once their permissions
+    // are removed
+    let (Ok(held), Ok(service_key)) = (
+        cap.demote(Permissions::empty()),
+        service_ref.demote(Permissions::empty()),
+    ) else {
+        return Err(CronError::ServiceNotHeld(name.to_owned()));
+    };
+
+    let held = held.into_handle();
+    let service_key = service_key.into_handle();
+    let _ = table.dec_ref(held);
+    let _ = table.dec_ref(service_key);
+
+    if held == service_key {
+        Ok(service)
+    } else {
+        Err(CronError::ServiceNotHeld(name.to_owned()))
+    }
+}
+
+/// Looks up a native service by name in the runtime's registry, logging any
+/// errors.
+async fn resolve_service(
+    runtime: &Runtime,
+    ctx: &Process,
+    name: &str,
+) -> Result<OwnedCapability, CronError> {
+    get_service(runtime, ctx, name).await.map_err(|err| {
+        debug!("Failed to get cron target {:?}: {:?}", name, err);
+        CronError::ServiceNotFound(name.to_owned())
+    })
+}
+
+/// Looks up a native service by name in the runtime's registry.
+async fn get_service(
+    runtime: &Runtime,
+    ctx: &Process,
+    name: &str,
+) -> anyhow::Result<OwnedCapability> {
+    let table = ctx.borrow_table();
+
+    let registry = runtime
+        .registry
+        .borrow_parent()
+        .export_to(Permissions::SEND, table)
+        .context("exporting registry")?;
+
+    let response = ctx
+        .borrow_group()
+        .create_mailbox()
+        .context("process has been killed")?;
+
+    let response_cap = response
+        .export(Permissions::SEND)
+        .context("exporting response mailbox")?;
+
+    let request = RegistryRequest::Get {
+        name: name.to_string(),
+    };
+
+    registry
+        .send(&codec::encode(&request), &[&response_cap])
+        .await
+        .context("sending registry request")?;
+
+    let handle = response
+        .recv(|signal| {
+            let TableSignal::Message { data, caps } = signal else {
+                return None;
+            };
+
+            match codec::decode(data) {
+                Ok(RegistryResponse::Get(true)) => caps.first().copied(),
+                _ => None,
+            }
+        })
+        .await
+        .context("process has been killed")?;
+
+    let Some(handle) = handle else {
+        bail!("service not found");
+    };
+
+    let cap = table.get_owned(handle).context("getting owned capability");
+    let _ = table.dec_ref(handle);
+    cap
+}
@@ -25,8 +25,12 @@ use std::{
 use hearth_init::InitPlugin;
 use hearth_ipc::get_socket_path;
 use hearth_runtime::{
+    cargo_process_metadata,
     connection::Connection,
     flue::OwnedCapability,
+    hearth_schema::process::SERVICE_NAME as PROCESS_STORE_SERVICE_NAME,
+    process::{ProcessMetadata, ProcessStoreService},
+    registry::register_service,
     runtime::{Plugin, Runtime, RuntimeBuilder},
     tokio::{
         self,
@@ -142,6 +146,8 @@ impl Plugin for DaemonPlugin {
                     }
                 };
 
+                register_process_store(&runtime, root_cap.clone()).await;
+
                 tracing::info!("Listening on IPC daemon...");
 
                 let listener = match Listener::new().await {
@@ -171,9 +177,28 @@ impl DaemonPlugin {
         transport: hearth_ipc::Connection,
     ) {
         tracing::info!("Beginning IPC connection");
-        let conn = Connection::begin(runtime.post.clone(), transport.op_rx, transport.op_tx, None);
+        let lumps = Some(runtime.lump_store.clone());
+        let post = runtime.post.clone();
+        let conn = Connection::begin(post, lumps, transport.op_rx, transport.op_tx, None);
 
         tracing::info!("Sending the IPC client our root cap");
         conn.export_root(root_cap);
     }
 }
+
+/// Registers a [ProcessStoreService] in the daemon's root registry.
+///
+/// The process store reaches every process, so it's only given to IPC
+/// clients and not registered in the runtime's registry.
+async fn register_process_store(runtime: &Arc<Runtime>, root: OwnedCapability) {
+    let store = ProcessStoreService::new(runtime.process_factory.store().clone());
+    let store = store.spawn_owned(runtime.clone());
+
+    let mut meta = cargo_process_metadata!();
+    meta.name = Some("IPC daemon".to_string());
+    let ctx = runtime.process_factory.spawn(meta);
+
+    if !register_service(&ctx, root, PROCESS_STORE_SERVICE_NAME, store).await {
+        tracing::warn!("daemon's registry refused the process store");
+    }
+}
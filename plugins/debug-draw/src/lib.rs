@@ -16,11 +16,15 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bytemuck::{Pod, Zeroable};
 use flume::{unbounded, Receiver, Sender};
-use glam::Vec3;
+use glam::{Vec2, Vec3, Vec4};
 use hearth_rend3::{
     rend3::graph::{DepthHandle, RenderPassDepthTarget, RenderPassTarget, RenderPassTargets},
     utils::DynamicMesh,
@@ -40,6 +44,12 @@ use itertools::Itertools;
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct CameraUniform {
     pub mvp: glam::Mat4,
+
+    /// The camera's right axis in world space, used to orient billboards.
+    pub right: Vec4,
+
+    /// The camera's up axis in world space, used to orient billboards.
+    pub up: Vec4,
 }
 
 /// GPU-ready debug draw vertex data.
@@ -48,6 +58,7 @@ pub struct CameraUniform {
 struct Vertex {
     pub position: Vec3,
     pub color: u32,
+    pub offset: Vec2,
 }
 
 impl Vertex {
@@ -57,7 +68,7 @@ impl Vertex {
         attributes: &[
             VertexAttribute {
                 offset: 0,
-                format: VertexFormat::Float32x4,
+                format: VertexFormat::Float32x3,
                 shader_location: 0,
             },
             VertexAttribute {
@@ -65,13 +76,72 @@ impl Vertex {
                 format: VertexFormat::Unorm8x4,
                 shader_location: 1,
             },
+            VertexAttribute {
+                offset: std::mem::size_of::<[f32; 4]>() as BufferAddress,
+                format: VertexFormat::Float32x2,
+                shader_location: 2,
+            },
         ],
     };
 }
 
+/// GPU-ready vertices and indices of a debug draw mesh.
+#[derive(Default)]
+struct LineMesh {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl From<DebugDrawMesh> for LineMesh {
+    fn from(mesh: DebugDrawMesh) -> Self {
+        let vertices = mesh
+            .vertices
+            .into_iter()
+            .map(|v| Vertex {
+                position: v.position,
+                color: v.color.0,
+                offset: v.offset,
+            })
+            .collect();
+
+        Self {
+            vertices,
+            indices: mesh.indices,
+        }
+    }
+}
+
 struct DebugDraw {
     mesh: DynamicMesh<Vertex>,
     hide: bool,
+
+    /// The mesh set by the latest [DebugDrawUpdate::Contents].
+    contents: LineMesh,
+
+    /// Timed meshes and the instants that they expire at.
+    timed: Vec<(Instant, LineMesh)>,
+
+    /// Whether the GPU mesh needs to be rebuilt.
+    dirty: bool,
+}
+
+impl DebugDraw {
+    /// Rebuilds the GPU mesh from the contents and unexpired timed meshes.
+    fn rebuild(&mut self, device: &Device, queue: &Queue) {
+        let meshes = std::iter::once(&self.contents).chain(self.timed.iter().map(|(_, m)| m));
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for mesh in meshes {
+            let base = vertices.len() as u32;
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend(mesh.indices.iter().map(|index| index + base));
+        }
+
+        self.mesh.update(device, queue, &vertices, &indices);
+        self.dirty = false;
+    }
 }
 
 pub struct DebugDrawRoutine {
@@ -94,6 +164,9 @@ impl Routine for DebugDrawRoutine {
             let mut new_contents = None;
             let mut new_hide = None;
 
+            // timed meshes accumulate instead, so keep every one of them
+            let mut new_timed = Vec::new();
+
             // whether a destroy message has been received
             let mut destroy = false;
 
@@ -104,6 +177,12 @@ impl Routine for DebugDrawRoutine {
                     Contents(mesh) if new_contents.is_none() => {
                         new_contents = Some(mesh);
                     }
+                    Timed { mesh, duration } => {
+                        // ignore negative, NaN, and overflowing durations
+                        if let Ok(duration) = Duration::try_from_secs_f32(duration) {
+                            new_timed.push((Instant::now() + duration, mesh.into()));
+                        }
+                    }
                     Hide(hide) if new_hide.is_none() => {
                         new_hide = Some(hide);
                     }
@@ -125,24 +204,19 @@ impl Routine for DebugDrawRoutine {
             let draw = self.draws.entry(id).or_insert_with(|| DebugDraw {
                 mesh: DynamicMesh::new(self.device.as_ref(), Some(format!("debug draw #{id}"))),
                 hide: false,
+                contents: LineMesh::default(),
+                timed: Vec::new(),
+                dirty: false,
             });
 
             if let Some(mesh) = new_contents {
-                let vertices: Vec<_> = mesh
-                    .vertices
-                    .into_iter()
-                    .map(|v| Vertex {
-                        position: v.position,
-                        color: v.color.0,
-                    })
-                    .collect();
-
-                draw.mesh.update(
-                    self.device.as_ref(),
-                    self.queue.as_ref(),
-                    &vertices,
-                    &mesh.indices,
-                );
+                draw.contents = mesh.into();
+                draw.dirty = true;
+            }
+
+            if !new_timed.is_empty() {
+                draw.timed.extend(new_timed);
+                draw.dirty = true;
             }
 
             if let Some(hide) = new_hide {
@@ -150,8 +224,24 @@ impl Routine for DebugDrawRoutine {
             }
         }
 
+        // drop expired timed meshes and upload every changed draw
+        let now = Instant::now();
+        for draw in self.draws.values_mut() {
+            let timed_num = draw.timed.len();
+            draw.timed.retain(|(expires, _)| *expires > now);
+
+            if draw.dirty || draw.timed.len() != timed_num {
+                draw.rebuild(self.device.as_ref(), self.queue.as_ref());
+            }
+        }
+
         Box::new(DebugDrawNode { routine: self })
     }
+
+    fn recreate(&mut self, rend3: &Rend3Plugin) {
+        // existing draws are dropped and reappear on their next update
+        *self = Self::new(rend3, self.update_rx.clone());
+    }
 }
 
 impl DebugDrawRoutine {
@@ -257,7 +347,7 @@ struct DebugDrawNode<'a> {
 impl<'a> Node<'a> for DebugDrawNode<'a> {
     fn draw<'graph>(&'graph self, info: &mut RoutineInfo<'_, 'graph>) {
         let output = info.graph.add_surface_texture();
-        let depth = info.state.depth;
+        let depth = info.depth;
 
         let mut builder = info.graph.add_node("debug draw");
         let output_handle = builder.add_render_target_output(output);
@@ -284,10 +374,15 @@ impl<'a> Node<'a> for DebugDrawNode<'a> {
                 let rpass = encoder_or_pass.get_rpass(rpass_handle);
                 let mvp = graph_data.camera_manager.view_proj();
 
+                // the rows of the view matrix are the camera's axes
+                let view = graph_data.camera_manager.view();
+                let right = view.row(0).truncate().normalize_or_zero().extend(0.0);
+                let up = view.row(1).truncate().normalize_or_zero().extend(0.0);
+
                 routine.queue.write_buffer(
                     &routine.camera_buffer,
                     0,
-                    bytemuck::bytes_of(&CameraUniform { mvp }),
+                    bytemuck::bytes_of(&CameraUniform { mvp, right, up }),
                 );
 
                 rpass.set_pipeline(&routine.pipeline);
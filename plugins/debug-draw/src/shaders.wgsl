@@ -19,6 +19,7 @@
 struct VertexIn {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] color: vec4<f32>;
+    [[location(2)]] offset: vec2<f32>;
 };
 
 struct VertexOut {
@@ -28,6 +29,8 @@ struct VertexOut {
 
 struct CameraUniform {
     mvp: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
 };
 
 [[group(0), binding(0)]] var<uniform> camera: CameraUniform;
@@ -42,7 +45,8 @@ fn srgb_to_linear(l: vec3<f32>) -> vec3<f32> {
 [[stage(vertex)]]
 fn vs_main(in: VertexIn) -> VertexOut {
     var out: VertexOut;
-    out.clip_position = camera.mvp * vec4<f32>(in.position, 1.0);
+    let position = in.position + camera.right.xyz * in.offset.x + camera.up.xyz * in.offset.y;
+    out.clip_position = camera.mvp * vec4<f32>(position, 1.0);
     out.color = vec4<f32>(srgb_to_linear(in.color.bgr), 1.0);
     return out;
 }
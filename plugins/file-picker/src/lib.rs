@@ -0,0 +1,165 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A native file picker service that lets guests import and export files
+//! chosen by the user.
+//!
+//! Files are passed to and from guests as lumps, so guests never see or gain
+//! access to paths on the host's filesystem.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use hearth_runtime::{
+    async_trait, flue::Table, hearth_macros::GetProcessMetadata, hearth_schema::file_picker::*,
+    runtime::Runtime, tokio, tracing::debug, utils::*,
+};
+use rfd::AsyncFileDialog;
+
+/// The native file picker service. Accepts [FilePickerRequest].
+///
+/// This shows dialogs to the user of the local machine, so it should only
+/// be added to clients. Only processes whose registry includes this service
+/// can use it, and Kindling's policy withholds it from guest services that
+/// aren't allowed it.
+///
+/// Only one dialog is shown at a time. Requests made while a dialog is open
+/// fail with [FilePickerError::Busy] instead of waiting for it to close.
+#[derive(Default, GetProcessMetadata)]
+pub struct FilePickerPlugin {
+    /// Set while a dialog is open.
+    busy: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl SinkProcess for FilePickerPlugin {
+    type Message = FilePickerRequest;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let Some(reply) = message.caps.first() else {
+            debug!("Request to {:?} has no reply address", message.label);
+            return;
+        };
+
+        let codec = message.codec;
+
+        if self.busy.swap(true, Ordering::AcqRel) {
+            let response: FilePickerResponse = Err(FilePickerError::Busy);
+            let data = codec.encode(&response);
+            if let Err(err) = reply.send(&data, &[]).await {
+                debug!("file picker reply error: {:?}", err);
+            }
+
+            return;
+        }
+
+        let busy = self.busy.clone();
+        let reply = reply.to_owned();
+        let runtime = message.runtime.to_owned();
+        let request = message.data;
+
+        // dialogs stay open until the user closes them, so show them in the
+        // background to keep turning away other requests in the meantime
+        tokio::spawn(async move {
+            let response = handle_request(&runtime, request).await;
+            busy.store(false, Ordering::Release);
+
+            let table = Table::new(runtime.post.clone());
+            let Ok(reply) = table.import_owned(reply) else {
+                return;
+            };
+
+            let data = codec.encode(&response);
+            if let Err(err) = table.send(reply, &data, &[]).await {
+                debug!("file picker reply error: {:?}", err);
+            }
+        });
+    }
+}
+
+impl ServiceRunner for FilePickerPlugin {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+async fn handle_request(runtime: &Runtime, request: FilePickerRequest) -> FilePickerResponse {
+    match request {
+        FilePickerRequest::Open { filters } => {
+            let file = dialog("Open File", &filters)
+                .pick_file()
+                .await
+                .ok_or(FilePickerError::Cancelled)?;
+
+            let contents = tokio::fs::read(file.path())
+                .await
+                .map_err(|err| FilePickerError::Io(err.to_string()))?;
+
+            let contents = runtime.lump_store.add_lump(contents.into()).await;
+
+            Ok(FilePickerSuccess::Opened {
+                name: file.file_name(),
+                contents,
+            })
+        }
+        FilePickerRequest::Save {
+            filters,
+            file_name,
+            contents,
+        } => {
+            // look up the contents first so that the user isn't asked
+            // where to save a file that can't be written
+            let contents = runtime
+                .lump_store
+                .get_lump(&contents)
+                .await
+                .ok_or(FilePickerError::LumpNotFound)?;
+
+            let mut dialog = dialog("Save File", &filters);
+
+            if let Some(file_name) = file_name {
+                dialog = dialog.set_file_name(file_name);
+            }
+
+            let file = dialog.save_file().await.ok_or(FilePickerError::Cancelled)?;
+
+            tokio::fs::write(file.path(), contents)
+                .await
+                .map_err(|err| FilePickerError::Io(err.to_string()))?;
+
+            Ok(FilePickerSuccess::Saved {
+                name: file.file_name(),
+            })
+        }
+    }
+}
+
+/// Creates a dialog with the given title and filters.
+///
+/// Titles are chosen by the host so that guests can't pass off a dialog as
+/// something else.
+fn dialog(title: &str, filters: &[FileFilter]) -> AsyncFileDialog {
+    let mut dialog = AsyncFileDialog::new().set_title(title);
+
+    for filter in filters.iter() {
+        let extensions: Vec<&str> = filter.extensions.iter().map(String::as_str).collect();
+        dialog = dialog.add_filter(&filter.name, &extensions);
+    }
+
+    dialog
+}
@@ -17,18 +17,47 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
-    fs::{read, read_dir},
-    path::{Component, PathBuf},
+    collections::HashMap,
+    fs::{create_dir_all, metadata, read, read_dir, remove_dir, remove_file, OpenOptions},
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
 };
 
 use hearth_runtime::{
-    async_trait, hearth_macros::GetProcessMetadata, hearth_schema::fs::*, utils::*,
+    async_trait,
+    flue::{CapabilityRef, OwnedTableSignal, Permissions},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::{codec::Codec, fs::*},
+    runtime::Runtime,
+    tokio::{self, sync::mpsc},
+    tracing::debug,
+    utils::*,
 };
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecursiveMode, Watcher,
+};
+
+/// Command-line arguments for the filesystem service.
+#[derive(clap::Args, Clone, Debug)]
+pub struct FsArgs {
+    /// A path to the guest-side filesystem root.
+    #[clap(short, long = "fs-root", alias = "root")]
+    pub root: PathBuf,
+
+    /// The most bytes that the files in each writable filesystem service's
+    /// root may take up. Services may ask for a smaller quota of their own.
+    #[clap(long = "fs-quota")]
+    pub quota: Option<u64>,
+}
 
 /// The native filesystem access service. Accepts FsRequest.
 #[derive(GetProcessMetadata)]
 pub struct FsPlugin {
     root: PathBuf,
+    writable: bool,
+    quota: Option<Quota>,
 }
 
 #[async_trait]
@@ -52,31 +81,33 @@ impl ServiceRunner for FsPlugin {
 }
 
 impl FsPlugin {
+    /// Creates a read-only filesystem service.
     pub fn new(root: PathBuf) -> Self {
-        Self { root }
+        Self {
+            root,
+            writable: false,
+            quota: None,
+        }
     }
 
-    async fn handle_request<'a>(&'a mut self, request: &mut RequestInfo<'a, Request>) -> Response {
-        let target = PathBuf::try_from(&request.data.target).map_err(|_| Error::InvalidTarget)?;
-
-        let mut path = self.root.to_path_buf();
-        for component in target.components() {
-            match component {
-                Component::Normal(normal) => path.push(normal),
-                _ => return Err(Error::DirectoryTraversal),
-            }
+    /// Creates a filesystem service that can modify the files in its root.
+    pub fn new_writable(root: PathBuf) -> Self {
+        Self {
+            root,
+            writable: true,
+            quota: None,
         }
+    }
 
-        let to_response_error = |err: std::io::Error| -> Error {
-            use std::io::ErrorKind::*;
-            match err.kind() {
-                NotFound => Error::NotFound,
-                PermissionDenied => Error::PermissionDenied,
-                e => Error::Other(e.to_string()),
-            }
-        };
+    /// Resolves a guest-provided target path to a path within the root.
+    fn resolve(&self, target: &str) -> Result<PathBuf, Error> {
+        resolve(&self.root, target)
+    }
 
-        match request.data.kind {
+    async fn handle_request<'a>(&'a mut self, request: &mut RequestInfo<'a, Request>) -> Response {
+        let path = self.resolve(&request.data.target)?;
+
+        match &request.data.kind {
             RequestKind::Get => {
                 let contents = match read(path) {
                     Ok(contents) => contents,
@@ -93,19 +124,672 @@ impl FsPlugin {
                     Err(e) => return Err(to_response_error(e)),
                 };
 
-                let dirs: Vec<_> = dirs
+                let dirs = dirs
                     .into_iter()
                     .map(|dir| {
-                        let dir = dir.unwrap();
+                        let dir = dir.map_err(to_response_error)?;
 
-                        FileInfo {
+                        Ok(FileInfo {
                             name: dir.file_name().to_string_lossy().to_string(),
-                        }
+                        })
                     })
-                    .collect();
+                    .collect::<Result<_, _>>()?;
 
                 Ok(Success::List(dirs))
             }
+            RequestKind::Write(lump) | RequestKind::Append(lump) => {
+                let append = matches!(request.data.kind, RequestKind::Append(_));
+                let Some(data) = request.runtime.lump_store.get_lump(lump).await else {
+                    return Err(Error::InvalidRequest);
+                };
+
+                self.write(&path, &data, append)?;
+
+                if append {
+                    Ok(Success::Append)
+                } else {
+                    Ok(Success::Write)
+                }
+            }
+            RequestKind::Delete => {
+                self.delete(&path)?;
+                Ok(Success::Delete)
+            }
+            RequestKind::Mkdir => {
+                self.mkdir(&path)?;
+                Ok(Success::Mkdir)
+            }
+            RequestKind::Watch => {
+                let Some(subscriber) = request.cap_args.first() else {
+                    return Err(Error::InvalidRequest);
+                };
+
+                self.watch(request.runtime, &path, subscriber, request.codec)?;
+                Ok(Success::Watch)
+            }
+        }
+    }
+
+    /// Sends [WatchEvent]s for every change under a path to a subscriber.
+    fn watch(
+        &self,
+        runtime: &Arc<Runtime>,
+        path: &Path,
+        subscriber: &CapabilityRef<'_>,
+        codec: Codec,
+    ) -> Result<(), Error> {
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+        let root = self.root.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    if let Some(event) = to_watch_event(&root, event) {
+                        let _ = events_tx.send(event);
+                    }
+                }
+                Err(err) => debug!("filesystem watch error: {:?}", err),
+            })
+            .map_err(to_watch_error)?;
+
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .map_err(to_watch_error)?;
+
+        let mut meta = Self::get_process_metadata();
+        meta.name = Some("FsWatcher".to_string());
+        meta.description = Some("Sends filesystem changes to a subscriber.".to_string());
+        let process = runtime.process_factory.spawn(meta);
+        let subscriber = subscriber.to_owned();
+
+        tokio::spawn(async move {
+            // the watch stops when the watcher is dropped with this task
+            let _watcher = watcher;
+
+            let table = process.borrow_table();
+            let Ok(subscriber) = table.import_owned(subscriber) else {
+                return;
+            };
+
+            let Ok(subscriber) = table.wrap_handle(subscriber) else {
+                return;
+            };
+
+            if subscriber.get_permissions().contains(Permissions::MONITOR) {
+                let _ = subscriber.monitor(process.borrow_parent());
+            }
+
+            loop {
+                tokio::select! {
+                    event = events_rx.recv() => {
+                        let Some(event) = event else {
+                            break;
+                        };
+
+                        let data = codec.encode(&event);
+                        if subscriber.send(&data, &[]).await.is_err() {
+                            break;
+                        }
+                    }
+                    signal = process.borrow_parent().recv_owned() => {
+                        if !matches!(signal, Some(OwnedTableSignal::Message { .. })) {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Writes or appends data to a file, creating it if it doesn't exist.
+    fn write(&self, path: &Path, data: &[u8], append: bool) -> Result<(), Error> {
+        if !self.writable {
+            return Err(Error::ReadOnly);
+        }
+
+        if path.is_dir() {
+            return Err(Error::IsADirectory);
+        }
+
+        // hold the quota's lock until the write is done so that concurrent
+        // writes can't both fit in the quota
+        let mut usage = self.quota.as_ref().map(|quota| quota.lock());
+        let old_len = file_len(path);
+
+        if let (Some(quota), Some(usage)) = (&self.quota, &usage) {
+            // appending keeps the existing contents, while writing replaces them
+            let new_len = data.len() as u64 + if append { old_len } else { 0 };
+            if quota.exceeded(usage, old_len, new_len) {
+                return Err(Error::QuotaExceeded);
+            }
+        }
+
+        let result = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .and_then(|mut file| file.write_all(data))
+            .map_err(to_response_error);
+
+        // count what was actually written, even if the write failed partway
+        if let Some(usage) = &mut usage {
+            update_usage(usage, path, old_len, file_len(path));
         }
+
+        result
+    }
+
+    /// Deletes a file or an empty directory.
+    fn delete(&self, path: &Path) -> Result<(), Error> {
+        if !self.writable {
+            return Err(Error::ReadOnly);
+        }
+
+        if path == self.root {
+            return Err(Error::InvalidTarget);
+        }
+
+        if path.is_dir() {
+            let mut entries = read_dir(path).map_err(to_response_error)?;
+            if entries.next().is_some() {
+                return Err(Error::DirectoryNotEmpty);
+            }
+
+            remove_dir(path).map_err(to_response_error)
+        } else {
+            let mut usage = self.quota.as_ref().map(|quota| quota.lock());
+            let len = file_len(path);
+            remove_file(path).map_err(to_response_error)?;
+
+            if let Some(usage) = &mut usage {
+                update_usage(usage, path, len, 0);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Creates a directory and any missing parent directories.
+    fn mkdir(&self, path: &Path) -> Result<(), Error> {
+        if !self.writable {
+            return Err(Error::ReadOnly);
+        }
+
+        if path.exists() {
+            return Err(Error::AlreadyExists);
+        }
+
+        create_dir_all(path).map_err(to_response_error)
+    }
+}
+
+/// The native filesystem factory service. Accepts FactoryRequest and spawns
+/// a new read-only [FsPlugin] scoped to a subdirectory of this factory's root.
+#[derive(GetProcessMetadata)]
+pub struct FsFactory {
+    root: PathBuf,
+}
+
+#[async_trait]
+impl FactoryProcess for FsFactory {
+    type Config = FactoryRequest;
+    type Error = Error;
+    type Child = FsPlugin;
+
+    async fn create_child<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, FactoryRequest>,
+    ) -> Result<FsPlugin, Error> {
+        Ok(FsPlugin::new(scope(&self.root, &request.data.root)?))
+    }
+}
+
+impl ServiceRunner for FsFactory {
+    const NAME: &'static str = "hearth.fs.Factory";
+}
+
+impl FsFactory {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+/// The native writable filesystem factory service. Accepts FactoryRequest and
+/// spawns a new writable [FsPlugin] scoped to a subdirectory of this
+/// factory's root, creating the subdirectory if it doesn't exist.
+///
+/// Each child has its own quota on the files in its root, which may be
+/// smaller than this factory's limit but never larger. Because its children
+/// can replace any file in their roots, this service should only be given to
+/// trusted processes.
+#[derive(GetProcessMetadata)]
+pub struct WritableFsFactory {
+    root: PathBuf,
+    limit: Option<u64>,
+    usage: Arc<Mutex<Usage>>,
+    protected: Vec<PathBuf>,
+}
+
+#[async_trait]
+impl FactoryProcess for WritableFsFactory {
+    type Config = FactoryRequest;
+    type Error = Error;
+    type Child = FsPlugin;
+
+    async fn create_child<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, FactoryRequest>,
+    ) -> Result<FsPlugin, Error> {
+        let root = self.scope(&request.data.root)?;
+        let mut child = FsPlugin::new_writable(root.clone());
+        child.quota = Some(self.quota(root, request.data.quota)?);
+        Ok(child)
+    }
+}
+
+impl ServiceRunner for WritableFsFactory {
+    const NAME: &'static str = "hearth.fs.WritableFactory";
+}
+
+impl WritableFsFactory {
+    /// Creates a writable filesystem factory.
+    ///
+    /// If `limit` is provided, a child's writes fail once the files in its
+    /// root would take up more than that many bytes. Children can't be rooted
+    /// at any directory containing a path in `protected`.
+    pub fn new(root: PathBuf, limit: Option<u64>, protected: Vec<PathBuf>) -> Self {
+        Self {
+            root,
+            limit,
+            usage: Default::default(),
+            protected,
+        }
+    }
+
+    /// Resolves a guest-provided sub-root to a directory within the root,
+    /// creating it if it doesn't exist.
+    ///
+    /// Fails if the sub-root contains a protected path.
+    fn scope(&self, target: &str) -> Result<PathBuf, Error> {
+        let path = resolve(&self.root, target)?;
+        if self.protected.iter().any(|file| file.starts_with(&path)) {
+            return Err(Error::PermissionDenied);
+        }
+
+        if !path.exists() {
+            create_dir_all(&path).map_err(to_response_error)?;
+        }
+
+        scope(&self.root, target)
+    }
+
+    /// Creates the quota of a child rooted at `root`, capping the child's
+    /// requested limit at this factory's.
+    ///
+    /// The files already in the root are counted the first time that a child
+    /// with a limit is rooted there. Children with the same root share its
+    /// count, but each is held to its own limit.
+    fn quota(&mut self, root: PathBuf, requested: Option<u64>) -> Result<Quota, Error> {
+        let limit = match (requested, self.limit) {
+            (Some(requested), Some(limit)) => Some(requested.min(limit)),
+            (requested, limit) => requested.or(limit),
+        };
+
+        if limit.is_some() {
+            let mut usage = lock_usage(&self.usage);
+            if !usage.contains_key(&root) {
+                let used = dir_usage(&root).map_err(to_response_error)?;
+                usage.insert(root.clone(), used);
+            }
+        }
+
+        Ok(Quota {
+            root,
+            limit,
+            usage: self.usage.clone(),
+        })
+    }
+}
+
+/// The running counts of the bytes used in the roots of a
+/// [WritableFsFactory]'s children that have limits, locked while a file is
+/// modified.
+type Usage = HashMap<PathBuf, u64>;
+
+/// A writable [FsPlugin]'s limit on the bytes taken up by the files in its
+/// root.
+struct Quota {
+    root: PathBuf,
+    limit: Option<u64>,
+
+    /// The usage of every root, shared with the factory and its other
+    /// children so that writes to nested roots are counted in each.
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl Quota {
+    fn lock(&self) -> MutexGuard<'_, Usage> {
+        lock_usage(&self.usage)
+    }
+
+    /// Tests if replacing a file of `old_len` bytes with one of `new_len`
+    /// bytes would take this quota's root over its limit.
+    fn exceeded(&self, usage: &Usage, old_len: u64, new_len: u64) -> bool {
+        let Some(limit) = self.limit else {
+            return false;
+        };
+
+        let used = usage.get(&self.root).copied().unwrap_or(0);
+        used.saturating_sub(old_len) + new_len > limit
+    }
+}
+
+/// Locks the usage of a [WritableFsFactory]'s children's roots.
+fn lock_usage(usage: &Mutex<Usage>) -> MutexGuard<'_, Usage> {
+    usage.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Counts a file changing from `old_len` to `new_len` bytes in every root
+/// that contains it.
+fn update_usage(usage: &mut Usage, path: &Path, old_len: u64, new_len: u64) {
+    for (root, used) in usage.iter_mut() {
+        if path.starts_with(root) {
+            *used = used.saturating_sub(old_len) + new_len;
+        }
+    }
+}
+
+/// Resolves a guest-provided sub-root to an existing directory within a
+/// factory's root.
+fn scope(root: &Path, target: &str) -> Result<PathBuf, Error> {
+    let path = resolve(root, target)?;
+
+    if path.is_dir() {
+        Ok(path)
+    } else if path.exists() {
+        Err(Error::NotADirectory)
+    } else {
+        Err(Error::NotFound)
+    }
+}
+
+/// Converts an IO error to a response error.
+fn to_response_error(err: std::io::Error) -> Error {
+    use std::io::ErrorKind::*;
+    match err.kind() {
+        NotFound => Error::NotFound,
+        PermissionDenied => Error::PermissionDenied,
+        AlreadyExists => Error::AlreadyExists,
+        e => Error::Other(e.to_string()),
+    }
+}
+
+/// Converts a watcher error to a response error.
+fn to_watch_error(err: notify::Error) -> Error {
+    match err.kind {
+        notify::ErrorKind::PathNotFound => Error::NotFound,
+        notify::ErrorKind::Io(err) => to_response_error(err),
+        kind => Error::Other(format!("{:?}", kind)),
+    }
+}
+
+/// Converts a watcher event to a [WatchEvent] with paths relative to a root.
+///
+/// Returns `None` for events that don't change any files, like accesses.
+fn to_watch_event(root: &Path, event: notify::Event) -> Option<WatchEvent> {
+    let kind = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            WatchEventKind::Created
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            WatchEventKind::Removed
+        }
+        EventKind::Modify(_) => WatchEventKind::Modified,
+        EventKind::Any | EventKind::Access(_) | EventKind::Other => return None,
+    };
+
+    let paths = event
+        .paths
+        .iter()
+        .filter_map(|path| path.strip_prefix(root).ok())
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    Some(WatchEvent { kind, paths })
+}
+
+/// Gets the length of a file, or 0 if it doesn't exist.
+fn file_len(path: &Path) -> u64 {
+    metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+/// Sums the sizes of every file within a directory and its subdirectories.
+fn dir_usage(dir: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            total += dir_usage(&entry.path())?;
+        } else {
+            total += meta.len();
+        }
+    }
+
+    Ok(total)
+}
+
+/// Resolves a guest-provided target path to a path within a root.
+fn resolve(root: &Path, target: &str) -> Result<PathBuf, Error> {
+    let mut path = root.to_path_buf();
+    for component in Path::new(target).components() {
+        match component {
+            Component::Normal(normal) => path.push(normal),
+            _ => return Err(Error::DirectoryTraversal),
+        }
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn resolve_stays_in_root(target in "[a-z./\\\\]{0,32}") {
+            let root = PathBuf::from("/hearth/root");
+            let fs = FsPlugin::new(root.clone());
+
+            if let Ok(path) = fs.resolve(&target) {
+                prop_assert!(path.starts_with(&root));
+                prop_assert!(path.components().all(|c| c != Component::ParentDir));
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_rejects_traversal() {
+        let fs = FsPlugin::new(PathBuf::from("/hearth/root"));
+        let is_traversal = |target| matches!(fs.resolve(target), Err(Error::DirectoryTraversal));
+
+        assert_eq!(
+            fs.resolve("a/b").unwrap(),
+            PathBuf::from("/hearth/root/a/b")
+        );
+        assert_eq!(
+            fs.resolve("a/./b").unwrap(),
+            PathBuf::from("/hearth/root/a/b")
+        );
+        assert!(is_traversal("../etc"));
+        assert!(is_traversal("a/../../etc"));
+        assert!(is_traversal("/etc/passwd"));
+    }
+
+    /// Creates an empty directory to test writes in.
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("hearth-fs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn read_only_rejects_writes() {
+        let root = temp_root("read-only");
+        let fs = FsPlugin::new(root.clone());
+        let path = root.join("file");
+
+        assert!(matches!(
+            fs.write(&path, b"data", false),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(fs.mkdir(&path), Err(Error::ReadOnly)));
+        assert!(matches!(fs.delete(&path), Err(Error::ReadOnly)));
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn writes_respect_quota() {
+        let root = temp_root("quota");
+        let mut factory = WritableFsFactory::new(root.clone(), Some(8), vec![]);
+        let mut fs = FsPlugin::new_writable(root.clone());
+        fs.quota = Some(factory.quota(root.clone(), None).unwrap());
+        let dir = root.join("dir");
+        let path = dir.join("file");
+
+        fs.mkdir(&dir).unwrap();
+        assert!(matches!(fs.mkdir(&dir), Err(Error::AlreadyExists)));
+
+        fs.write(&path, b"1234", false).unwrap();
+        fs.write(&path, b"12345678", false).unwrap();
+        assert!(matches!(
+            fs.write(&path, b"9", true),
+            Err(Error::QuotaExceeded)
+        ));
+
+        fs.write(&path, b"1234", false).unwrap();
+        fs.write(&path, b"5678", true).unwrap();
+        assert_eq!(read(&path).unwrap(), b"12345678");
+
+        assert!(matches!(fs.delete(&dir), Err(Error::DirectoryNotEmpty)));
+        fs.delete(&path).unwrap();
+        fs.delete(&dir).unwrap();
+        assert!(matches!(fs.delete(&root), Err(Error::InvalidTarget)));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn watch_events_are_relative_to_root() {
+        use notify::event::{CreateKind, DataChange};
+
+        let root = PathBuf::from("/hearth/root");
+        let event = |kind| notify::Event::new(kind).add_path(root.join("scenes/main.json"));
+        let convert = |kind| to_watch_event(&root, event(kind)).map(|event| event.kind);
+
+        assert_eq!(
+            to_watch_event(&root, event(EventKind::Create(CreateKind::File))),
+            Some(WatchEvent {
+                kind: WatchEventKind::Created,
+                paths: vec!["scenes/main.json".to_string()],
+            })
+        );
+        assert_eq!(
+            convert(EventKind::Modify(ModifyKind::Data(DataChange::Content))),
+            Some(WatchEventKind::Modified)
+        );
+        assert_eq!(
+            convert(EventKind::Modify(ModifyKind::Name(RenameMode::From))),
+            Some(WatchEventKind::Removed)
+        );
+        assert_eq!(
+            convert(EventKind::Access(notify::event::AccessKind::Any)),
+            None
+        );
+    }
+
+    #[test]
+    fn children_have_their_own_quotas() {
+        let root = temp_root("child-quotas");
+        let (a_root, b_root) = (root.join("a"), root.join("b"));
+        create_dir_all(&a_root).unwrap();
+        create_dir_all(&b_root).unwrap();
+        create_dir_all(a_root.join("small")).unwrap();
+        std::fs::write(a_root.join("existing"), b"1234").unwrap();
+
+        let mut factory = WritableFsFactory::new(root.clone(), Some(8), vec![]);
+        let mut a = FsPlugin::new_writable(a_root.clone());
+        a.quota = Some(factory.quota(a_root.clone(), None).unwrap());
+        let mut b = FsPlugin::new_writable(b_root.clone());
+        b.quota = Some(factory.quota(b_root.clone(), Some(16)).unwrap());
+        let mut small = FsPlugin::new_writable(a_root.join("small"));
+        small.quota = Some(factory.quota(a_root.join("small"), Some(2)).unwrap());
+
+        // one child filling its quota doesn't take space from another
+        b.write(&b_root.join("file"), b"12345678", false).unwrap();
+        a.write(&a_root.join("file"), b"1234", false).unwrap();
+
+        // requested limits are capped at the factory's
+        assert!(matches!(
+            b.write(&b_root.join("file"), b"9", true),
+            Err(Error::QuotaExceeded)
+        ));
+
+        // writes to nested roots count against every root that contains them
+        assert!(matches!(
+            small.write(&a_root.join("small/file"), b"123", false),
+            Err(Error::QuotaExceeded)
+        ));
+        a.delete(&a_root.join("existing")).unwrap();
+        small
+            .write(&a_root.join("small/file"), b"12", false)
+            .unwrap();
+        assert!(matches!(
+            a.write(&a_root.join("file"), b"123", true),
+            Err(Error::QuotaExceeded)
+        ));
+        a.write(&a_root.join("file"), b"12", true).unwrap();
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn writable_factory_refuses_protected_roots() {
+        let root = temp_root("protected");
+        let protected = vec![root.join("init.wasm"), root.join("config/policy.toml")];
+        let factory = WritableFsFactory::new(root.clone(), None, protected);
+        let is_denied = |target| matches!(factory.scope(target), Err(Error::PermissionDenied));
+
+        assert!(is_denied(""));
+        assert!(is_denied("config"));
+        assert_eq!(factory.scope("data").unwrap(), root.join("data"));
+        assert!(root.join("data").is_dir());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn factory_scope_requires_directory() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+        assert_eq!(scope(&root, "src").unwrap(), root.join("src"));
+        assert!(matches!(
+            scope(&root, "Cargo.toml"),
+            Err(Error::NotADirectory)
+        ));
+        assert!(matches!(scope(&root, "missing"), Err(Error::NotFound)));
+        assert!(matches!(
+            scope(&root, "../"),
+            Err(Error::DirectoryTraversal)
+        ));
     }
 }
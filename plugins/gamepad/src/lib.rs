@@ -0,0 +1,244 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Gamepad and controller input through gilrs.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use gilrs::{EventType, Gilrs};
+use hearth_runtime::{
+    async_trait,
+    flue::{CapabilityRef, Permissions},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::gamepad::*,
+    runtime::{Plugin, RuntimeBuilder},
+    tokio::{self, sync::mpsc},
+    tracing::{debug, warn},
+    utils::*,
+};
+use parking_lot::Mutex;
+
+/// How long the gamepad thread waits for an event before checking if the
+/// runtime is still listening.
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A plugin that provides gamepad input to guests.
+#[derive(Default)]
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn finalize(self, builder: &mut RuntimeBuilder) {
+        let pubsub = Arc::new(PubSub::new(builder.get_post()));
+        let connected = Arc::new(Mutex::new(HashMap::new()));
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+
+        // gilrs is not thread-safe on every platform, so it gets its own
+        // thread for its entire lifetime
+        std::thread::spawn(move || poll_gamepads(events_tx));
+
+        tokio::spawn({
+            let pubsub = pubsub.clone();
+            let connected = connected.clone();
+            async move {
+                while let Some(event) = events_rx.recv().await {
+                    // track connected gamepads for new subscribers
+                    match &event {
+                        GamepadEvent::Connected { id, name } => {
+                            connected.lock().insert(*id, name.clone());
+                        }
+                        GamepadEvent::Disconnected { id } => {
+                            connected.lock().remove(id);
+                        }
+                        _ => {}
+                    }
+
+                    pubsub.notify(&event).await;
+                }
+            }
+        });
+
+        builder.add_plugin(GamepadService { pubsub, connected });
+    }
+}
+
+/// The native gamepad service. Accepts [GamepadCommand].
+#[derive(GetProcessMetadata)]
+pub struct GamepadService {
+    pubsub: Arc<PubSub<GamepadEvent>>,
+    connected: Arc<Mutex<HashMap<GamepadId, String>>>,
+}
+
+#[async_trait]
+impl SinkProcess for GamepadService {
+    type Message = GamepadCommand;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, GamepadCommand>) {
+        match message.data {
+            GamepadCommand::Subscribe => {
+                let Some(sub) = message.caps.get(0) else {
+                    warn!("Subscribe message is missing capability");
+                    return;
+                };
+
+                if sub.get_permissions().contains(Permissions::MONITOR) {
+                    sub.monitor(message.process.borrow_parent()).unwrap();
+                }
+
+                // catch the new subscriber up on the current gamepads
+                let connected: Vec<_> = self
+                    .connected
+                    .lock()
+                    .iter()
+                    .map(|(id, name)| GamepadEvent::Connected {
+                        id: *id,
+                        name: name.clone(),
+                    })
+                    .collect();
+
+                for event in connected {
+                    let data = message.codec.encode(&event);
+                    if let Err(err) = sub.send(&data, &[]).await {
+                        debug!("gamepad subscriber is unavailable: {:?}", err);
+                        return;
+                    }
+                }
+
+                self.pubsub.subscribe(sub.clone(), message.codec);
+            }
+            GamepadCommand::Unsubscribe => {
+                let Some(sub) = message.caps.get(0) else {
+                    warn!("Unsubscribe message is missing capability");
+                    return;
+                };
+
+                self.pubsub.unsubscribe(sub.clone());
+            }
+        }
+    }
+
+    async fn on_down<'a>(&'a mut self, cap: CapabilityRef<'a>) {
+        self.pubsub.unsubscribe(cap);
+    }
+}
+
+impl ServiceRunner for GamepadService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// Polls gamepad events until the receiving end of the channel is closed.
+fn poll_gamepads(events_tx: mpsc::UnboundedSender<GamepadEvent>) {
+    let mut gilrs = match Gilrs::new() {
+        Ok(gilrs) => gilrs,
+        Err(err) => {
+            warn!("gamepad input is unavailable: {:?}", err);
+            return;
+        }
+    };
+
+    // announce gamepads that were connected before startup
+    for (id, gamepad) in gilrs.gamepads() {
+        let event = GamepadEvent::Connected {
+            id: GamepadId(id.into()),
+            name: gamepad.name().to_string(),
+        };
+
+        if events_tx.send(event).is_err() {
+            return;
+        }
+    }
+
+    while !events_tx.is_closed() {
+        let Some(event) = gilrs.next_event_blocking(Some(POLL_TIMEOUT)) else {
+            continue;
+        };
+
+        let id = GamepadId(event.id.into());
+
+        let event = match event.event {
+            EventType::Connected => GamepadEvent::Connected {
+                id,
+                name: gilrs.gamepad(event.id).name().to_string(),
+            },
+            EventType::Disconnected => GamepadEvent::Disconnected { id },
+            EventType::ButtonPressed(button, _) => GamepadEvent::ButtonPressed {
+                id,
+                button: conv_button(button),
+            },
+            EventType::ButtonReleased(button, _) => GamepadEvent::ButtonReleased {
+                id,
+                button: conv_button(button),
+            },
+            EventType::ButtonChanged(button, value, _) => GamepadEvent::ButtonChanged {
+                id,
+                button: conv_button(button),
+                value,
+            },
+            EventType::AxisChanged(axis, value, _) => GamepadEvent::AxisChanged {
+                id,
+                axis: conv_axis(axis),
+                value,
+            },
+            _ => continue,
+        };
+
+        let _ = events_tx.send(event);
+    }
+}
+
+fn conv_button(button: gilrs::Button) -> Button {
+    use gilrs::Button as Gilrs;
+    use Button as Schema;
+    match button {
+        Gilrs::South => Schema::South,
+        Gilrs::East => Schema::East,
+        Gilrs::North => Schema::North,
+        Gilrs::West => Schema::West,
+        Gilrs::C => Schema::C,
+        Gilrs::Z => Schema::Z,
+        Gilrs::LeftTrigger => Schema::LeftTrigger,
+        Gilrs::LeftTrigger2 => Schema::LeftTrigger2,
+        Gilrs::RightTrigger => Schema::RightTrigger,
+        Gilrs::RightTrigger2 => Schema::RightTrigger2,
+        Gilrs::Select => Schema::Select,
+        Gilrs::Start => Schema::Start,
+        Gilrs::Mode => Schema::Mode,
+        Gilrs::LeftThumb => Schema::LeftThumb,
+        Gilrs::RightThumb => Schema::RightThumb,
+        Gilrs::DPadUp => Schema::DPadUp,
+        Gilrs::DPadDown => Schema::DPadDown,
+        Gilrs::DPadLeft => Schema::DPadLeft,
+        Gilrs::DPadRight => Schema::DPadRight,
+        Gilrs::Unknown => Schema::Unknown,
+    }
+}
+
+fn conv_axis(axis: gilrs::Axis) -> Axis {
+    use gilrs::Axis as Gilrs;
+    use Axis as Schema;
+    match axis {
+        Gilrs::LeftStickX => Schema::LeftStickX,
+        Gilrs::LeftStickY => Schema::LeftStickY,
+        Gilrs::LeftZ => Schema::LeftZ,
+        Gilrs::RightStickX => Schema::RightStickX,
+        Gilrs::RightStickY => Schema::RightStickY,
+        Gilrs::RightZ => Schema::RightZ,
+        Gilrs::DPadX => Schema::DPadX,
+        Gilrs::DPadY => Schema::DPadY,
+        Gilrs::Unknown => Schema::Unknown,
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A service that decodes compressed images into textures, so that guests
+//! don't need to bundle their own image decoders.
+
+use std::io::Cursor;
+
+use hearth_runtime::{
+    async_trait,
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::{codec, image::*, renderer::TextureData},
+    tokio,
+    tracing::error,
+    utils::*,
+};
+use image::{
+    io::{Limits, Reader},
+    ImageError,
+};
+
+/// The largest width or height of an image that will be decoded.
+pub const MAX_IMAGE_SIZE: u32 = 8192;
+
+/// The image decoding service. Accepts [ImageDecoderRequest].
+#[derive(Default, GetProcessMetadata)]
+pub struct ImageDecoderService;
+
+#[async_trait]
+impl RequestResponseProcess for ImageDecoderService {
+    type Request = ImageDecoderRequest;
+    type Response = ImageDecoderResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, ImageDecoderRequest>,
+    ) -> ResponseInfo<'a, ImageDecoderResponse> {
+        ResponseInfo {
+            data: self.handle_request(request).await,
+            caps: vec![],
+        }
+    }
+}
+
+impl ServiceRunner for ImageDecoderService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl ImageDecoderService {
+    async fn handle_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, ImageDecoderRequest>,
+    ) -> ImageDecoderResponse {
+        match &request.data {
+            ImageDecoderRequest::Decode {
+                image,
+                format,
+                label,
+            } => {
+                let data = request
+                    .runtime
+                    .lump_store
+                    .get_lump(image)
+                    .await
+                    .ok_or(ImageDecoderError::LumpNotFound)?;
+
+                // decoding large images takes a while, so keep it off of the
+                // async executor
+                let format = *format;
+                let label = label.clone();
+                let (texture, format) =
+                    tokio::task::spawn_blocking(move || decode(&data, format, label))
+                        .await
+                        .map_err(|err| {
+                            error!("image decoding panicked: {err:?}");
+                            ImageDecoderError::Decode("decoder panicked".to_string())
+                        })??;
+
+                let size = texture.size;
+                let texture = codec::encode(&texture);
+                let texture = request.runtime.lump_store.add_lump(texture.into()).await;
+
+                Ok(ImageDecoderSuccess::Decoded {
+                    texture,
+                    size,
+                    format,
+                })
+            }
+        }
+    }
+}
+
+/// Decodes a compressed image into RGBA8 texture data.
+///
+/// If `format` is `None`, it is guessed from the image's contents. Returns
+/// the texture along with the format it was decoded from.
+pub fn decode(
+    data: &[u8],
+    format: Option<ImageFormat>,
+    label: Option<String>,
+) -> Result<(TextureData, ImageFormat), ImageDecoderError> {
+    let mut reader = Reader::new(Cursor::new(data));
+
+    let format = match format {
+        Some(format) => {
+            reader.set_format(to_image_format(format));
+            format
+        }
+        None => {
+            reader = reader
+                .with_guessed_format()
+                .map_err(|err| ImageDecoderError::Decode(err.to_string()))?;
+
+            reader
+                .format()
+                .and_then(from_image_format)
+                .ok_or(ImageDecoderError::UnsupportedFormat)?
+        }
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_SIZE);
+    limits.max_image_height = Some(MAX_IMAGE_SIZE);
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(|err| match err {
+        ImageError::Limits(_) => ImageDecoderError::TooLarge,
+        ImageError::Unsupported(_) => ImageDecoderError::UnsupportedFormat,
+        err => ImageDecoderError::Decode(err.to_string()),
+    })?;
+
+    let image = image.into_rgba8();
+
+    let texture = TextureData {
+        label,
+        size: image.dimensions().into(),
+        data: image.into_raw(),
+    };
+
+    Ok((texture, format))
+}
+
+fn to_image_format(format: ImageFormat) -> image::ImageFormat {
+    match format {
+        ImageFormat::Png => image::ImageFormat::Png,
+        ImageFormat::Jpeg => image::ImageFormat::Jpeg,
+        ImageFormat::WebP => image::ImageFormat::WebP,
+        ImageFormat::Gif => image::ImageFormat::Gif,
+    }
+}
+
+fn from_image_format(format: image::ImageFormat) -> Option<ImageFormat> {
+    match format {
+        image::ImageFormat::Png => Some(ImageFormat::Png),
+        image::ImageFormat::Jpeg => Some(ImageFormat::Jpeg),
+        image::ImageFormat::WebP => Some(ImageFormat::WebP),
+        image::ImageFormat::Gif => Some(ImageFormat::Gif),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgba, RgbaImage};
+
+    fn encode(image: &RgbaImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn decode_png() {
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(2, 1, Rgba([10, 20, 30, 40]));
+        let data = encode(&image, image::ImageFormat::Png);
+
+        let (texture, format) = decode(&data, None, Some("test".into())).unwrap();
+        assert_eq!(format, ImageFormat::Png);
+        assert_eq!(texture.label.as_deref(), Some("test"));
+        assert_eq!(texture.size, (3, 2).into());
+        assert_eq!(texture.data, image.into_raw());
+    }
+
+    #[test]
+    fn decode_gif_first_frame() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let data = encode(&image, image::ImageFormat::Gif);
+
+        let (texture, format) = decode(&data, None, None).unwrap();
+        assert_eq!(format, ImageFormat::Gif);
+        assert_eq!(texture.size, (4, 4).into());
+        assert_eq!(&texture.data[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn explicit_format() {
+        let image = RgbaImage::new(1, 1);
+        let data = encode(&image, image::ImageFormat::Png);
+
+        let (_, format) = decode(&data, Some(ImageFormat::Png), None).unwrap();
+        assert_eq!(format, ImageFormat::Png);
+
+        assert!(matches!(
+            decode(&data, Some(ImageFormat::Jpeg), None),
+            Err(ImageDecoderError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_format() {
+        let result = decode(b"not an image", None, None);
+        assert!(matches!(result, Err(ImageDecoderError::UnsupportedFormat)));
+    }
+
+    #[test]
+    fn too_large() {
+        let image = RgbaImage::new(MAX_IMAGE_SIZE + 1, 1);
+        let data = encode(&image, image::ImageFormat::Png);
+        let result = decode(&data, None, None);
+        assert!(matches!(result, Err(ImageDecoderError::TooLarge)));
+    }
+}
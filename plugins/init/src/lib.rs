@@ -16,7 +16,11 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use hearth_runtime::{
     async_trait, cargo_process_metadata,
@@ -25,9 +29,18 @@ use hearth_runtime::{
     process::{Process, ProcessMetadata},
     runtime::{Plugin, Runtime, RuntimeBuilder},
     tokio::{spawn, sync::oneshot::Sender},
-    utils::{ProcessRunToken, ProcessRunner},
+    utils::{ProcessRunToken, ProcessRunner, RequestInfo, RequestResponseProcess, ResponseInfo},
 };
-use tracing::{debug, warn};
+use tracing::{debug, error, info, warn};
+
+/// The name of the service that gives init the capability policy.
+const POLICY_SERVICE_NAME: &str = "hearth.init.Policy";
+
+/// The name of the capability policy file in the config directory.
+const POLICY_FILE: &str = "policy.toml";
+
+/// The policy used when the config directory doesn't have one.
+const DEFAULT_POLICY: &str = include_str!("default_policy.toml");
 
 struct Hook {
     service: String,
@@ -69,13 +82,69 @@ impl ProcessRunner for Hook {
     }
 }
 
+/// Replies to every request with the capability policy's source, or `None`
+/// if it couldn't be read.
+///
+/// The policy is kept in the host's config directory instead of the
+/// filesystem root so that guests with write access to the root can't
+/// loosen it.
+struct PolicyService {
+    policy: Option<String>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for PolicyService {
+    type Request = ();
+    type Response = Option<String>;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        _request: &mut RequestInfo<'a, ()>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        self.policy.clone().into()
+    }
+}
+
+impl PolicyService {
+    /// Reads the policy from `path`, or uses [DEFAULT_POLICY] if there's no
+    /// file there.
+    fn load(path: &Path) -> Self {
+        let policy = match std::fs::read_to_string(path) {
+            Ok(policy) => {
+                info!("Loaded capability policy from {:?}", path);
+                Some(policy)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                debug!("No capability policy at {:?}; using the default", path);
+                Some(DEFAULT_POLICY.to_string())
+            }
+            Err(err) => {
+                error!(
+                    "Failed to read capability policy from {:?}: {:?}",
+                    path, err
+                );
+                None
+            }
+        };
+
+        Self { policy }
+    }
+}
+
 pub struct InitPlugin {
     init_path: PathBuf,
+    policy_path: PathBuf,
     hooks: Vec<Hook>,
 }
 
 impl Plugin for InitPlugin {
     fn finalize(self, builder: &mut RuntimeBuilder) {
+        let mut meta = cargo_process_metadata!();
+        meta.name = Some(POLICY_SERVICE_NAME.to_string());
+        meta.description = Some("Gives init the capability policy for its services.".to_string());
+        let policy = PolicyService::load(&self.policy_path);
+        builder.add_service(POLICY_SERVICE_NAME.to_string(), meta, policy);
+
         for hook in self.hooks {
             let mut meta = cargo_process_metadata!();
             meta.name = Some(hook.service.clone());
@@ -93,6 +162,7 @@ impl Plugin for InitPlugin {
                 let spawn_info = WasmSpawnInfo {
                     lump: wasm_lump,
                     entrypoint: None,
+                    record: false,
                 };
 
                 debug!("Running init system");
@@ -148,6 +218,7 @@ impl InitPlugin {
     pub fn new(init_path: PathBuf) -> Self {
         Self {
             init_path,
+            policy_path: hearth_runtime::get_config_dir().join(POLICY_FILE),
             hooks: Vec::new(),
         }
     }
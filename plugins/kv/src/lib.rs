@@ -0,0 +1,382 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::{path::PathBuf, sync::Arc};
+
+use hearth_runtime::{
+    async_trait,
+    flue::{OwnedCapability, PostOffice, Table},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::{codec::Codec, kv::*},
+    runtime::{Plugin, RuntimeBuilder},
+    tokio,
+    tracing::{debug, error, info},
+    utils::*,
+};
+use serde::Deserialize;
+use sled::{Db, Tree};
+
+/// Configuration for the key-value store plugin, read from the `kv` table of
+/// the config file.
+#[derive(Debug, Default, Deserialize)]
+pub struct KvConfig {
+    /// The directory of the store's database.
+    ///
+    /// Defaults to `kv` in the Hearth data directory.
+    pub path: Option<PathBuf>,
+}
+
+/// A plugin that provides the [KvStore] service.
+pub struct KvPlugin {
+    path: PathBuf,
+}
+
+impl Default for KvPlugin {
+    fn default() -> Self {
+        Self::new(KvConfig::default())
+    }
+}
+
+impl Plugin for KvPlugin {
+    fn finalize(self, builder: &mut RuntimeBuilder) {
+        let db = match sled::open(&self.path) {
+            Ok(db) => db,
+            Err(err) => {
+                error!(
+                    "Failed to open key-value store at {:?}: {:?}",
+                    self.path, err
+                );
+                return;
+            }
+        };
+
+        info!("Opened key-value store at {:?}", self.path);
+        builder.add_plugin(KvStore::root(db));
+    }
+}
+
+impl KvPlugin {
+    /// Creates a new key-value store plugin with the given configuration.
+    pub fn new(config: KvConfig) -> Self {
+        let path = config
+            .path
+            .unwrap_or_else(|| hearth_runtime::get_data_dir().join("kv"));
+
+        Self { path }
+    }
+
+    /// Creates a new key-value store plugin from the `kv` table of a config
+    /// file.
+    ///
+    /// Logs an error and uses the default configuration if the table is
+    /// malformed.
+    pub fn from_config_file(config: &toml::Table) -> Self {
+        let Some(table) = config.get("kv") else {
+            return Self::default();
+        };
+
+        match table.clone().try_into() {
+            Ok(config) => Self::new(config),
+            Err(err) => {
+                error!("Failed to parse kv config: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// A namespace of a key-value store. Accepts [KvRequest].
+///
+/// Each namespace is its own database tree, so namespaces can't read or write
+/// each other's keys.
+#[derive(GetProcessMetadata)]
+pub struct KvStore {
+    db: Db,
+    tree: Tree,
+
+    /// The names of this namespace and its parents, joined with `/`. Empty
+    /// for the root namespace.
+    path: String,
+}
+
+#[async_trait]
+impl RequestResponseProcess for KvStore {
+    type Request = KvRequest;
+    type Response = KvResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, KvRequest>,
+    ) -> ResponseInfo<'a, KvResponse> {
+        if let KvRequest::Namespace { name } = &request.data {
+            return match self.namespace(name) {
+                Ok(child) => ResponseInfo {
+                    data: Ok(KvSuccess::Done),
+                    caps: vec![request.spawn(child)],
+                },
+                Err(err) => err.into(),
+            };
+        }
+
+        if let KvRequest::Watch { prefix } = &request.data {
+            let Some(subscriber) = request.cap_args.first() else {
+                return KvError::MissingSubscriber.into();
+            };
+
+            let post = request.runtime.post.clone();
+            self.watch(prefix, subscriber.to_owned(), request.codec, post);
+            return Ok(KvSuccess::Done).into();
+        }
+
+        self.handle(&request.data).into()
+    }
+}
+
+impl ServiceRunner for KvStore {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl KvStore {
+    /// Creates the root namespace of a database.
+    pub fn root(db: Db) -> Self {
+        let tree = (*db).clone();
+
+        Self {
+            db,
+            tree,
+            path: String::new(),
+        }
+    }
+
+    /// Opens a namespace nested in this one.
+    fn namespace(&self, name: &str) -> Result<Self, KvError> {
+        if name.is_empty() || name.contains('/') {
+            return Err(KvError::InvalidNamespace);
+        }
+
+        let path = if self.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.path, name)
+        };
+
+        // the default tree's name is reserved by sled, so prefix every
+        // namespace to keep them from colliding with it
+        let tree = self
+            .db
+            .open_tree(format!("ns/{}", path))
+            .map_err(storage_error)?;
+
+        Ok(Self {
+            db: self.db.clone(),
+            tree,
+            path,
+        })
+    }
+
+    /// Handles a request that only accesses this namespace's keys.
+    fn handle(&self, request: &KvRequest) -> KvResponse {
+        match request {
+            KvRequest::Get { key } => {
+                let value = self.tree.get(key).map_err(storage_error)?;
+                Ok(KvSuccess::Value(value.map(|value| value.to_vec())))
+            }
+            KvRequest::Put { key, value } => {
+                if value.len() > MAX_VALUE_SIZE {
+                    return Err(KvError::ValueTooLarge);
+                }
+
+                self.tree
+                    .insert(key, value.as_slice())
+                    .map_err(storage_error)?;
+
+                Ok(KvSuccess::Done)
+            }
+            KvRequest::Delete { key } => {
+                let old = self.tree.remove(key).map_err(storage_error)?;
+                Ok(KvSuccess::Deleted(old.is_some()))
+            }
+            KvRequest::List { prefix } => {
+                let keys = self
+                    .tree
+                    .scan_prefix(prefix)
+                    .keys()
+                    .map(|key| {
+                        let key = key.map_err(storage_error)?;
+                        Ok(String::from_utf8_lossy(&key).to_string())
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                Ok(KvSuccess::Keys(keys))
+            }
+            KvRequest::Watch { .. } | KvRequest::Namespace { .. } => {
+                unreachable!("handled by on_request")
+            }
+        }
+    }
+
+    /// Spawns a task that sends changes to keys with the given prefix to a
+    /// subscriber until it can no longer be sent to.
+    fn watch(
+        &self,
+        prefix: &str,
+        subscriber: OwnedCapability,
+        codec: Codec,
+        post: Arc<PostOffice>,
+    ) {
+        let mut events = self.tree.watch_prefix(prefix);
+
+        tokio::spawn(async move {
+            let table = Table::new(post);
+            let Ok(subscriber) = table.import_owned(subscriber) else {
+                return;
+            };
+
+            while let Some(event) = (&mut events).await {
+                let event = match event {
+                    sled::Event::Insert { key, value } => KvEvent::Put {
+                        key: String::from_utf8_lossy(&key).to_string(),
+                        value: value.to_vec(),
+                    },
+                    sled::Event::Remove { key } => KvEvent::Delete {
+                        key: String::from_utf8_lossy(&key).to_string(),
+                    },
+                };
+
+                let data = codec.encode(&event);
+                if let Err(err) = table.send(subscriber, &data, &[]).await {
+                    debug!("ending key-value watch: {:?}", err);
+                    break;
+                }
+            }
+        });
+    }
+}
+
+fn storage_error(err: sled::Error) -> KvError {
+    KvError::Storage(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary() -> KvStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        KvStore::root(db)
+    }
+
+    fn put(store: &KvStore, key: &str, value: &[u8]) {
+        let request = KvRequest::Put {
+            key: key.to_string(),
+            value: value.to_vec(),
+        };
+
+        assert!(matches!(store.handle(&request), Ok(KvSuccess::Done)));
+    }
+
+    fn get(store: &KvStore, key: &str) -> Option<Vec<u8>> {
+        let request = KvRequest::Get {
+            key: key.to_string(),
+        };
+
+        match store.handle(&request) {
+            Ok(KvSuccess::Value(value)) => value,
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn put_get_delete() {
+        let store = temporary();
+        put(&store, "hello", b"world");
+        assert_eq!(get(&store, "hello"), Some(b"world".to_vec()));
+
+        let request = KvRequest::Delete {
+            key: "hello".to_string(),
+        };
+
+        assert!(matches!(
+            store.handle(&request),
+            Ok(KvSuccess::Deleted(true))
+        ));
+
+        assert_eq!(get(&store, "hello"), None);
+    }
+
+    #[test]
+    fn list_by_prefix() {
+        let store = temporary();
+        put(&store, "b/2", &[]);
+        put(&store, "a", &[]);
+        put(&store, "b/1", &[]);
+
+        let request = KvRequest::List {
+            prefix: "b/".to_string(),
+        };
+
+        match store.handle(&request) {
+            Ok(KvSuccess::Keys(keys)) => assert_eq!(keys, vec!["b/1", "b/2"]),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn namespaces_are_isolated() {
+        let root = temporary();
+        let a = root.namespace("a").unwrap();
+        let b = root.namespace("b").unwrap();
+        let nested = a.namespace("b").unwrap();
+
+        put(&a, "key", b"a");
+        put(&b, "key", b"b");
+        put(&nested, "key", b"nested");
+
+        assert_eq!(get(&root, "key"), None);
+        assert_eq!(get(&a, "key"), Some(b"a".to_vec()));
+        assert_eq!(get(&b, "key"), Some(b"b".to_vec()));
+        assert_eq!(get(&nested, "key"), Some(b"nested".to_vec()));
+
+        // reopening a namespace sees the same keys
+        assert_eq!(
+            get(&root.namespace("a").unwrap(), "key"),
+            Some(b"a".to_vec())
+        );
+    }
+
+    #[test]
+    fn namespace_names_are_validated() {
+        let root = temporary();
+        assert_eq!(root.namespace("").err(), Some(KvError::InvalidNamespace));
+        assert_eq!(root.namespace("a/b").err(), Some(KvError::InvalidNamespace));
+    }
+
+    #[test]
+    fn large_values_are_rejected() {
+        let store = temporary();
+        let request = KvRequest::Put {
+            key: "big".to_string(),
+            value: vec![0; MAX_VALUE_SIZE + 1],
+        };
+
+        assert!(matches!(
+            store.handle(&request),
+            Err(KvError::ValueTooLarge)
+        ));
+    }
+}
@@ -0,0 +1,561 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hearth_runtime::{
+    async_trait,
+    flue::{OwnedCapability, PostOffice, Table},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::{codec::Codec, logs::*, ProcessId},
+    process::{ProcessLogRecord, ProcessStore},
+    runtime::{Plugin, RuntimeBuilder},
+    tokio::{self, sync::broadcast},
+    tracing::{debug, error, info, warn},
+    utils::*,
+};
+use serde::Deserialize;
+
+/// The maximum number of log files that are kept open at once.
+const MAX_OPEN_FILES: usize = 64;
+
+/// Configuration for the process log plugin, read from the `process_logs`
+/// table of the config file.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ProcessLogConfig {
+    /// The directory to store process logs in.
+    ///
+    /// Defaults to `process-logs` in the Hearth data directory.
+    pub path: Option<PathBuf>,
+
+    /// The size in bytes past which a process's log file is rotated.
+    pub max_file_size: u64,
+
+    /// The number of rotated log files kept for each process, besides the one
+    /// being written to.
+    pub rotated_files: usize,
+}
+
+impl Default for ProcessLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_file_size: 1024 * 1024,
+            rotated_files: 2,
+        }
+    }
+}
+
+/// A plugin that persists the logs of every local process as JSON lines and
+/// provides the [ProcessLogService] to read them back.
+///
+/// Process IDs are reused between runs, so each run writes to a `current`
+/// subdirectory of the configured directory. At startup, the last run's logs
+/// are moved to `previous`, replacing the logs of the run before it.
+pub struct ProcessLogPlugin {
+    root: PathBuf,
+    dir: LogDir,
+}
+
+impl Default for ProcessLogPlugin {
+    fn default() -> Self {
+        Self::new(ProcessLogConfig::default())
+    }
+}
+
+impl Plugin for ProcessLogPlugin {
+    fn finalize(self, builder: &mut RuntimeBuilder) {
+        if let Err(err) = start_run(&self.root) {
+            error!(
+                "Failed to prepare process log directory at {:?}: {:?}",
+                self.root, err
+            );
+            return;
+        }
+
+        info!("Writing process logs to {:?}", self.dir.path);
+
+        // subscribe before any process can be spawned so no events are missed
+        let store = builder.get_process_store();
+        let logs = store.subscribe_logs();
+        let writer = LogWriter::new(self.dir.clone());
+        builder.add_runner(move |_| {
+            tokio::spawn(writer.run(logs));
+        });
+
+        builder.add_plugin(ProcessLogService {
+            dir: self.dir,
+            store,
+        });
+    }
+}
+
+impl ProcessLogPlugin {
+    /// Creates a new process log plugin with the given configuration.
+    pub fn new(config: ProcessLogConfig) -> Self {
+        let root = config
+            .path
+            .unwrap_or_else(|| hearth_runtime::get_data_dir().join("process-logs"));
+
+        let dir = LogDir {
+            path: root.join("current"),
+            max_file_size: config.max_file_size,
+            rotated_files: config.rotated_files,
+        };
+
+        Self { root, dir }
+    }
+
+    /// Stores this plugin's logs in a subdirectory of the configured
+    /// directory, so that runtimes sharing a data directory don't rotate each
+    /// other's logs away.
+    pub fn with_subdir(mut self, name: &str) -> Self {
+        self.root = self.root.join(name);
+        self.dir.path = self.root.join("current");
+        self
+    }
+
+    /// Creates a new process log plugin from the `process_logs` table of a
+    /// config file.
+    ///
+    /// Logs an error and uses the default configuration if the table is
+    /// malformed.
+    pub fn from_config_file(config: &toml::Table) -> Self {
+        let Some(table) = config.get("process_logs") else {
+            return Self::default();
+        };
+
+        match table.clone().try_into() {
+            Ok(config) => Self::new(config),
+            Err(err) => {
+                error!("Failed to parse process_logs config: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Moves the last run's logs in `root` aside and creates an empty directory
+/// for the new run's logs.
+fn start_run(root: &Path) -> std::io::Result<()> {
+    let current = root.join("current");
+    let previous = root.join("previous");
+
+    if previous.exists() {
+        std::fs::remove_dir_all(&previous)?;
+    }
+
+    if current.exists() {
+        std::fs::rename(&current, &previous)?;
+    }
+
+    std::fs::create_dir_all(&current)
+}
+
+/// The native process log service. Accepts [ProcessLogRequest].
+///
+/// Reads the logs that have been persisted by the process log plugin,
+/// including those of processes that have exited, and follows new events
+/// live.
+#[derive(GetProcessMetadata)]
+pub struct ProcessLogService {
+    dir: LogDir,
+    store: Arc<ProcessStore>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for ProcessLogService {
+    type Request = ProcessLogRequest;
+    type Response = ProcessLogResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, ProcessLogRequest>,
+    ) -> ResponseInfo<'a, ProcessLogResponse> {
+        if let ProcessLogRequest::Follow { pids } = &request.data {
+            let Some(subscriber) = request.cap_args.first() else {
+                return ProcessLogError::MissingSubscriber.into();
+            };
+
+            let post = request.runtime.post.clone();
+            self.follow(pids, subscriber.to_owned(), request.codec, post);
+            return Ok(ProcessLogSuccess::Following).into();
+        }
+
+        self.handle(&request.data).into()
+    }
+}
+
+impl ServiceRunner for ProcessLogService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl ProcessLogService {
+    fn handle(&self, request: &ProcessLogRequest) -> ProcessLogResponse {
+        match request {
+            ProcessLogRequest::Get { pid, limit } => {
+                match self
+                    .dir
+                    .read(*pid, *limit as usize)
+                    .map_err(storage_error)?
+                {
+                    Some(events) => Ok(ProcessLogSuccess::Events(events)),
+                    None => Err(ProcessLogError::NoLogs),
+                }
+            }
+            ProcessLogRequest::List => {
+                let pids = self.dir.list().map_err(storage_error)?;
+                Ok(ProcessLogSuccess::Processes(pids))
+            }
+            ProcessLogRequest::Follow { .. } => unreachable!("handled by on_request"),
+        }
+    }
+
+    /// Spawns a task that sends the events of the given processes, or every
+    /// process if none are given, to a subscriber until it can no longer be
+    /// sent to.
+    fn follow(
+        &self,
+        pids: &[ProcessId],
+        subscriber: OwnedCapability,
+        codec: Codec,
+        post: Arc<PostOffice>,
+    ) {
+        let mut logs = self.store.subscribe_logs();
+        let pids = pids.to_vec();
+
+        tokio::spawn(async move {
+            let table = Table::new(post);
+            let Ok(subscriber) = table.import_owned(subscriber) else {
+                return;
+            };
+
+            loop {
+                let record = match logs.recv().await {
+                    Ok(record) => record,
+                    Err(broadcast::error::RecvError::Lagged(num)) => {
+                        debug!("Process log follower skipped {} events", num);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+
+                let pid = ProcessId(record.pid as u32);
+                if !pids.is_empty() && !pids.contains(&pid) {
+                    continue;
+                }
+
+                let event = FollowedLogEntry {
+                    pid,
+                    entry: ProcessLogEntry {
+                        name: record.name,
+                        time: record.time,
+                        level: record.event.level,
+                        module: record.event.module,
+                        content: record.event.content,
+                        fields: record.event.fields,
+                    },
+                };
+
+                let data = codec.encode(&event);
+                if let Err(err) = table.send(subscriber, &data, &[]).await {
+                    debug!("Ending process log follow: {:?}", err);
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// A directory of per-process log files.
+///
+/// Each process's events are appended to `<pid>.jsonl`. When that file grows
+/// too large, it's rotated to `<pid>.1.jsonl`, the old `<pid>.1.jsonl` to
+/// `<pid>.2.jsonl`, and so on, and the oldest file is deleted.
+#[derive(Clone, Debug)]
+struct LogDir {
+    path: PathBuf,
+    max_file_size: u64,
+    rotated_files: usize,
+}
+
+impl LogDir {
+    /// Gets the path of a log file. Generation zero is the file being written
+    /// to, and higher generations are older.
+    fn file(&self, pid: ProcessId, generation: usize) -> PathBuf {
+        match generation {
+            0 => self.path.join(format!("{}.jsonl", pid.0)),
+            generation => self.path.join(format!("{}.{}.jsonl", pid.0, generation)),
+        }
+    }
+
+    /// Opens a process's current log file for appending.
+    fn open(&self, pid: ProcessId) -> std::io::Result<File> {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.file(pid, 0))
+    }
+
+    /// Rotates a process's log files, deleting the oldest.
+    fn rotate(&self, pid: ProcessId) -> std::io::Result<()> {
+        if self.rotated_files == 0 {
+            return std::fs::remove_file(self.file(pid, 0));
+        }
+
+        for generation in (1..=self.rotated_files).rev() {
+            match std::fs::rename(self.file(pid, generation - 1), self.file(pid, generation)) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads at most `limit` of a process's most recent events, oldest first.
+    ///
+    /// Returns `None` if the process has no log files.
+    fn read(&self, pid: ProcessId, limit: usize) -> std::io::Result<Option<Vec<ProcessLogEntry>>> {
+        let mut events = VecDeque::new();
+        let mut found = false;
+
+        for generation in (0..=self.rotated_files).rev() {
+            let data = match std::fs::read(self.file(pid, generation)) {
+                Ok(data) => data,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+
+            found = true;
+
+            // the last line may be cut short if the runtime exited mid-write
+            for line in data.split(|b| *b == b'\n') {
+                let Ok(event) = serde_json::from_slice(line) else {
+                    continue;
+                };
+
+                if events.len() >= limit {
+                    events.pop_front();
+                }
+
+                if limit > 0 {
+                    events.push_back(event);
+                }
+            }
+        }
+
+        Ok(found.then(|| events.into()))
+    }
+
+    /// Lists the IDs of every process with log files, in order.
+    fn list(&self) -> std::io::Result<Vec<ProcessId>> {
+        let mut pids = BTreeSet::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let name = entry?.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+
+            let pid = name.split('.').next().and_then(|pid| pid.parse().ok());
+            if let Some(pid) = pid {
+                pids.insert(ProcessId(pid));
+            }
+        }
+
+        Ok(pids.into_iter().collect())
+    }
+}
+
+/// Appends the events of every process to their log files.
+struct LogWriter {
+    dir: LogDir,
+    files: HashMap<ProcessId, File>,
+}
+
+impl LogWriter {
+    fn new(dir: LogDir) -> Self {
+        Self {
+            dir,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Writes every event broadcast by a process store until it closes.
+    async fn run(mut self, mut logs: broadcast::Receiver<ProcessLogRecord>) {
+        loop {
+            let record = match logs.recv().await {
+                Ok(record) => record,
+                Err(broadcast::error::RecvError::Lagged(num)) => {
+                    warn!("Process log writer skipped {} events", num);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            if let Err(err) = self.write(&record) {
+                warn!("Failed to write log of process {}: {:?}", record.pid, err);
+            }
+        }
+    }
+
+    /// Appends a single event to its process's log file.
+    fn write(&mut self, record: &ProcessLogRecord) -> std::io::Result<()> {
+        let pid = ProcessId(record.pid as u32);
+
+        if !self.files.contains_key(&pid) {
+            // processes don't tell the writer when they exit, so close every
+            // file when too many are open instead of tracking their lifetimes
+            if self.files.len() >= MAX_OPEN_FILES {
+                self.files.clear();
+            }
+
+            let file = self.dir.open(pid)?;
+            self.files.insert(pid, file);
+        }
+
+        let file = self.files.get_mut(&pid).unwrap();
+
+        let mut line = serde_json::to_vec(record).unwrap();
+        line.push(b'\n');
+        file.write_all(&line)?;
+
+        if file.metadata()?.len() > self.dir.max_file_size {
+            self.files.remove(&pid);
+            self.dir.rotate(pid)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn storage_error(err: std::io::Error) -> ProcessLogError {
+    ProcessLogError::Storage(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use hearth_runtime::hearth_schema::ProcessLogLevel;
+    use hearth_runtime::process::ProcessLogEvent;
+
+    use super::*;
+
+    fn temporary(name: &str, max_file_size: u64) -> LogWriter {
+        let path =
+            std::env::temp_dir().join(format!("hearth-logs-test-{}-{}", std::process::id(), name));
+
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        LogWriter::new(LogDir {
+            path,
+            max_file_size,
+            rotated_files: 1,
+        })
+    }
+
+    fn record(pid: usize, content: &str) -> ProcessLogRecord {
+        ProcessLogRecord {
+            pid,
+            name: None,
+            time: 0,
+            event: ProcessLogEvent {
+                level: ProcessLogLevel::Info,
+                module: "test".to_string(),
+                content: content.to_string(),
+                fields: BTreeMap::new(),
+            },
+        }
+    }
+
+    fn log(writer: &mut LogWriter, pid: usize, content: &str) {
+        writer.write(&record(pid, content)).unwrap();
+    }
+
+    fn contents(events: Vec<ProcessLogEntry>) -> Vec<String> {
+        events.into_iter().map(|event| event.content).collect()
+    }
+
+    #[test]
+    fn read_most_recent() {
+        let mut writer = temporary("read", 1024 * 1024);
+        for content in ["a", "b", "c"] {
+            log(&mut writer, 1, content);
+        }
+
+        log(&mut writer, 2, "other");
+
+        let events = writer.dir.read(ProcessId(1), 2).unwrap().unwrap();
+        assert_eq!(contents(events), ["b", "c"]);
+        assert_eq!(writer.dir.list().unwrap(), [ProcessId(1), ProcessId(2)]);
+        assert_eq!(writer.dir.read(ProcessId(3), 2).unwrap(), None);
+    }
+
+    #[test]
+    fn rotation_drops_oldest() {
+        // rotate after every second event
+        let line = serde_json::to_vec(&record(1, "a")).unwrap().len() as u64 + 1;
+        let mut writer = temporary("rotate", line + 1);
+
+        for content in ["a", "b", "c"] {
+            log(&mut writer, 1, content);
+        }
+
+        let events = writer.dir.read(ProcessId(1), 10).unwrap().unwrap();
+        assert_eq!(contents(events), ["a", "b", "c"]);
+
+        for content in ["d", "e"] {
+            log(&mut writer, 1, content);
+        }
+
+        let events = writer.dir.read(ProcessId(1), 10).unwrap().unwrap();
+        assert_eq!(contents(events), ["c", "d", "e"]);
+    }
+
+    #[test]
+    fn fields_are_stored() {
+        let mut writer = temporary("fields", 1024 * 1024);
+        let mut structured = record(1, "structured");
+        let fields = BTreeMap::from([("user".to_string(), "alice".to_string())]);
+        structured.event.fields = fields.clone();
+        writer.write(&structured).unwrap();
+        log(&mut writer, 1, "plain");
+
+        let events = writer.dir.read(ProcessId(1), 10).unwrap().unwrap();
+        assert_eq!(events[0].fields, fields);
+        assert!(events[1].fields.is_empty());
+    }
+
+    #[test]
+    fn names_are_stored() {
+        let mut writer = temporary("names", 1024 * 1024);
+        let mut named = record(1, "named");
+        named.name = Some("worker".to_string());
+        writer.write(&named).unwrap();
+
+        let events = writer.dir.read(ProcessId(1), 10).unwrap().unwrap();
+        assert_eq!(events[0].name.as_deref(), Some("worker"));
+    }
+}
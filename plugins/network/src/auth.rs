@@ -16,20 +16,61 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
+//! Authentication of peers using the OPAQUE password-authenticated key
+//! exchange.
+//!
+//! Clients log in either as a user registered in the server's [UserStore] or,
+//! with an empty username, using the server's shared password. Logging in
+//! yields the client's [Identity] and a [SessionKey] for encryption.
+//!
+//! Clients start the login with [LOGIN_MARKER] and the version of the login
+//! handshake. Clients from before usernames were added start with their
+//! OPAQUE credential request instead, which can't begin with the marker, and
+//! are logged in with the shared password.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+
 use chacha20::cipher::Unsigned;
 use opaque_ke::errors::*;
 use opaque_ke::*;
 use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
+use crate::profile::Profile;
+
 /// The 64-byte key generated by the authentication step.
 pub type SessionKey = [u8; 64];
 
+/// The name of the user store within the data directory.
+pub const USERS_FILE: &str = "users.toml";
+
+/// The longest username in bytes that can be sent while logging in.
+pub const MAX_USERNAME_LEN: usize = u8::MAX as usize;
+
+/// The first byte sent by clients that version their login handshake.
+///
+/// Ristretto255 points are always encoded with an even first byte, so a
+/// credential request, which starts with one, never begins with this odd byte.
+pub const LOGIN_MARKER: u8 = 0xff;
+
+/// The version of the login handshake, sent after [LOGIN_MARKER].
+///
+/// Version 1 sends the length of the username in a byte followed by the
+/// username before the credential request.
+pub const LOGIN_VERSION: u8 = 1;
+
 #[derive(Debug)]
 pub enum AuthenticationError {
     IoError(std::io::Error),
     ProtocolError(ProtocolError),
     InternalError(InternalError),
+    InvalidUsername,
+
+    /// The client's login handshake has an unknown version.
+    UnsupportedVersion(u8),
 }
 
 impl From<std::io::Error> for AuthenticationError {
@@ -59,48 +100,248 @@ impl CipherSuite for CS {
     type Ksf = argon2::Argon2<'static>;
 }
 
+/// Registers a password with a server setup on behalf of a client.
+///
+/// The credential identifier must be the same one that the client logs in
+/// with.
+fn register(
+    setup: &ServerSetup<CS>,
+    cred_id: &[u8],
+    pw: &[u8],
+) -> Result<ServerRegistration<CS>, AuthenticationError> {
+    let mut rng = OsRng;
+    let client_start = ClientRegistration::start(&mut rng, pw)?;
+    let server_start = ServerRegistration::start(setup, client_start.message, cred_id)?;
+    let client_finish =
+        client_start
+            .state
+            .finish(&mut rng, pw, server_start.message, Default::default())?;
+    Ok(ServerRegistration::finish(client_finish.message))
+}
+
+/// The registered users of a server, stored in [USERS_FILE].
+///
+/// The store only holds the server's OPAQUE keys and each user's OPAQUE
+/// registration, so users' passwords are never stored. The keys are secret,
+/// though, and registrations are only valid with the keys they were made
+/// with.
+///
+/// The store lives in the data directory, so it's included in backups, which
+/// have to be kept as private as the store itself.
+#[derive(Deserialize, Serialize)]
+pub struct UserStore {
+    #[serde(with = "hex")]
+    setup: Vec<u8>,
+
+    #[serde(default)]
+    users: BTreeMap<String, User>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct User {
+    profile: Profile,
+
+    #[serde(with = "hex")]
+    registration: Vec<u8>,
+}
+
+impl Default for UserStore {
+    fn default() -> Self {
+        let setup = ServerSetup::<CS>::new(&mut OsRng);
+
+        Self {
+            setup: setup.serialize().to_vec(),
+            users: BTreeMap::new(),
+        }
+    }
+}
+
+impl UserStore {
+    /// Loads a user store from a file.
+    ///
+    /// Returns an empty store with new keys if the file doesn't exist.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let src = match std::fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&src)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+
+    /// Saves this user store to a file, creating its directory if needed.
+    ///
+    /// The store is written to a temporary file that only the owner can read
+    /// and then renamed into place, so a failed save never leaves the file
+    /// half-written.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let src = toml::to_string(self)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        // a leftover partial file may have been created with other permissions
+        let partial = path.with_extension("toml.partial");
+        let _ = std::fs::remove_file(&partial);
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(&partial)?;
+        file.write_all(src.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(partial, path)
+    }
+
+    /// Registers a user, replacing their password and profile if they're
+    /// already registered.
+    pub fn add_user(
+        &mut self,
+        username: &str,
+        pw: &[u8],
+        profile: Profile,
+    ) -> Result<(), AuthenticationError> {
+        if username.is_empty() || username.len() > MAX_USERNAME_LEN {
+            return Err(AuthenticationError::InvalidUsername);
+        }
+
+        let setup = ServerSetup::<CS>::deserialize(&self.setup)?;
+        let registration = register(&setup, username.as_bytes(), pw)?;
+
+        let user = User {
+            profile,
+            registration: registration.serialize().to_vec(),
+        };
+
+        self.users.insert(username.to_string(), user);
+        Ok(())
+    }
+
+    /// Unregisters a user. Returns false if they weren't registered.
+    pub fn remove_user(&mut self, username: &str) -> bool {
+        self.users.remove(username).is_some()
+    }
+
+    /// Returns true if no users are registered.
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+/// The identity of an authenticated client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    /// The client's username, or `None` if it logged in using the shared
+    /// password.
+    pub username: Option<String>,
+
+    /// The client's permission profile.
+    pub profile: Profile,
+}
+
 pub struct ServerListener {}
 
 pub struct ServerAuthenticator {
     setup: ServerSetup<CS>,
-    registration: ServerRegistration<CS>,
+    password: Option<(ServerRegistration<CS>, Profile)>,
+    users: HashMap<String, (ServerRegistration<CS>, Profile)>,
 }
 
 impl ServerAuthenticator {
+    /// Creates an authenticator that only accepts a shared password, which
+    /// grants the admin profile.
     pub fn from_password(pw: &[u8]) -> Result<Self, AuthenticationError> {
-        let mut rng = OsRng;
-        let client_start = ClientRegistration::start(&mut rng, pw)?;
-        let setup = ServerSetup::new(&mut rng);
-        let cred_id = b"";
-        let server_start = ServerRegistration::start(&setup, client_start.message, cred_id)?;
-        let client_finish =
-            client_start
-                .state
-                .finish(&mut rng, pw, server_start.message, Default::default())?;
-        let registration = ServerRegistration::finish(client_finish.message);
+        let mut auth = Self {
+            setup: ServerSetup::new(&mut OsRng),
+            password: None,
+            users: HashMap::new(),
+        };
+
+        auth.allow_password(pw, Profile::Admin)?;
+        Ok(auth)
+    }
+
+    /// Creates an authenticator that accepts the users in a user store.
+    ///
+    /// Logging in with the shared password is disabled until
+    /// [Self::allow_password] is called.
+    pub fn from_users(store: &UserStore) -> Result<Self, AuthenticationError> {
+        let setup = ServerSetup::deserialize(&store.setup)?;
+        let mut users = HashMap::new();
+        for (name, user) in store.users.iter() {
+            let registration = ServerRegistration::deserialize(&user.registration)?;
+            users.insert(name.clone(), (registration, user.profile));
+        }
 
         Ok(Self {
             setup,
-            registration,
+            password: None,
+            users,
         })
     }
 
+    /// Lets clients log in without a username using a shared password, which
+    /// grants them the given profile.
+    pub fn allow_password(
+        &mut self,
+        pw: &[u8],
+        profile: Profile,
+    ) -> Result<(), AuthenticationError> {
+        let registration = register(&self.setup, b"", pw)?;
+        self.password = Some((registration, profile));
+        Ok(())
+    }
+
     pub async fn login<T: AsyncRead + AsyncWrite + Unpin>(
         &self,
         client: &mut T,
-    ) -> Result<SessionKey, AuthenticationError> {
+    ) -> Result<(SessionKey, Identity), AuthenticationError> {
         let request_len = CredentialRequestLen::<CS>::to_usize();
         let mut request_msg = vec![0u8; request_len];
-        client.read_exact(&mut request_msg).await?;
+        let first = client.read_u8().await?;
+
+        let username = if first == LOGIN_MARKER {
+            match client.read_u8().await? {
+                LOGIN_VERSION => {}
+                version => return Err(AuthenticationError::UnsupportedVersion(version)),
+            }
+
+            let username_len = client.read_u8().await? as usize;
+            let mut username = vec![0u8; username_len];
+            client.read_exact(&mut username).await?;
+            client.read_exact(&mut request_msg).await?;
+            String::from_utf8(username).map_err(|_| AuthenticationError::InvalidUsername)?
+        } else {
+            // unversioned clients only know the shared password
+            request_msg[0] = first;
+            client.read_exact(&mut request_msg[1..]).await?;
+            String::new()
+        };
+
+        // unknown users go through a fake login so that they can't be told
+        // apart from registered users with a wrong password
+        let registration = if username.is_empty() {
+            self.password.as_ref()
+        } else {
+            self.users.get(&username)
+        };
+
         let request = CredentialRequest::deserialize(&request_msg)?;
 
         let mut rng = OsRng;
         let login_start = ServerLogin::start(
             &mut rng,
             &self.setup,
-            Some(self.registration.clone()),
+            registration.map(|(registration, _)| registration.clone()),
             request,
-            b"",
+            username.as_bytes(),
             Default::default(),
         )?;
 
@@ -113,14 +354,36 @@ impl ServerAuthenticator {
         client.read_exact(&mut finalize_msg).await?;
         let finalize = CredentialFinalization::<CS>::deserialize(&finalize_msg)?;
         let finish = login_start.state.finish(finalize)?;
-        Ok(finish.session_key.into())
+
+        let Some((_, profile)) = registration else {
+            let err = ProtocolError::InvalidLoginError;
+            return Err(AuthenticationError::ProtocolError(err));
+        };
+
+        let identity = Identity {
+            username: (!username.is_empty()).then_some(username),
+            profile: *profile,
+        };
+
+        Ok((finish.session_key.into(), identity))
     }
 }
 
+/// Logs in to a server as a registered user, or with the server's shared
+/// password if the username is empty.
 pub async fn login<T: AsyncRead + AsyncWrite + Unpin>(
     server: &mut T,
+    username: &str,
     pw: &[u8],
 ) -> Result<SessionKey, AuthenticationError> {
+    if username.len() > MAX_USERNAME_LEN {
+        return Err(AuthenticationError::InvalidUsername);
+    }
+
+    server.write_all(&[LOGIN_MARKER, LOGIN_VERSION]).await?;
+    server.write_u8(username.len() as u8).await?;
+    server.write_all(username.as_bytes()).await?;
+
     let mut rng = OsRng;
     let start = ClientLogin::<CS>::start(&mut rng, pw)?;
     let start_msg = start.message.serialize();
@@ -143,6 +406,8 @@ pub async fn login<T: AsyncRead + AsyncWrite + Unpin>(
 mod tests {
     use super::*;
 
+    use std::sync::Arc;
+
     #[test]
     fn authenticator_from_password() {
         let _auth = ServerAuthenticator::from_password(b"deadbeef").unwrap();
@@ -154,11 +419,13 @@ mod tests {
         let auth = ServerAuthenticator::from_password(password).unwrap();
         let (mut client, mut server) = tokio::io::duplex(128);
         let server_join = tokio::spawn(async move { auth.login(&mut client).await });
-        let client_result = login(&mut server, password).await;
+        let client_result = login(&mut server, "", password).await;
         let server_result = server_join.await.unwrap();
-        let server_key = server_result.unwrap();
+        let (server_key, identity) = server_result.unwrap();
         let client_key = client_result.unwrap();
         assert_eq!(server_key, client_key);
+        assert_eq!(identity.username, None);
+        assert_eq!(identity.profile, Profile::Admin);
     }
 
     #[tokio::test]
@@ -168,10 +435,102 @@ mod tests {
         let auth = ServerAuthenticator::from_password(password).unwrap();
         let (mut client, mut server) = tokio::io::duplex(128);
         tokio::spawn(async move { auth.login(&mut client).await });
-        let client_result = login(&mut server, wrong_password).await;
+        let client_result = login(&mut server, "", wrong_password).await;
         match client_result {
             Err(AuthenticationError::ProtocolError(ProtocolError::InvalidLoginError)) => {}
             result => panic!("Unexpected result: {:?}", result),
         }
     }
+
+    #[tokio::test]
+    async fn authenticate_unversioned_client() {
+        let password = b"deadbeef";
+        let auth = ServerAuthenticator::from_password(password).unwrap();
+        let (mut client, mut server) = tokio::io::duplex(128);
+        let server_join = tokio::spawn(async move { auth.login(&mut client).await });
+
+        // clients from before versioning send the credential request first
+        let start = ClientLogin::<CS>::start(&mut OsRng, password).unwrap();
+        server.write_all(&start.message.serialize()).await.unwrap();
+        let mut response_msg = vec![0u8; CredentialResponseLen::<CS>::to_usize()];
+        server.read_exact(&mut response_msg).await.unwrap();
+        let response = CredentialResponse::<CS>::deserialize(&response_msg).unwrap();
+        let finish = start
+            .state
+            .finish(password, response, Default::default())
+            .unwrap();
+        server.write_all(&finish.message.serialize()).await.unwrap();
+
+        let (server_key, identity) = server_join.await.unwrap().unwrap();
+        assert_eq!(server_key, <SessionKey>::from(finish.session_key));
+        assert_eq!(identity.username, None);
+    }
+
+    #[tokio::test]
+    async fn unknown_login_versions_are_rejected() {
+        let auth = ServerAuthenticator::from_password(b"deadbeef").unwrap();
+        let (mut client, mut server) = tokio::io::duplex(128);
+        server.write_all(&[LOGIN_MARKER, 2]).await.unwrap();
+        assert!(matches!(
+            auth.login(&mut client).await,
+            Err(AuthenticationError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn saved_users_are_private() {
+        let dir = std::env::temp_dir().join(format!("hearth-users-{}", std::process::id()));
+        let path = dir.join(USERS_FILE);
+        UserStore::default().save(&path).unwrap();
+        UserStore::load(&path).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn authenticate_user() {
+        let mut store = UserStore::default();
+        store
+            .add_user("alice", b"hunter2", Profile::Builder)
+            .unwrap();
+
+        // users keep working after the store is saved and loaded
+        let store: UserStore = toml::from_str(&toml::to_string(&store).unwrap()).unwrap();
+        let auth = ServerAuthenticator::from_users(&store).unwrap();
+        let (mut client, mut server) = tokio::io::duplex(128);
+        let server_join = tokio::spawn(async move { auth.login(&mut client).await });
+        let client_key = login(&mut server, "alice", b"hunter2").await.unwrap();
+        let (server_key, identity) = server_join.await.unwrap().unwrap();
+        assert_eq!(server_key, client_key);
+        assert_eq!(identity.username.as_deref(), Some("alice"));
+        assert_eq!(identity.profile, Profile::Builder);
+    }
+
+    #[tokio::test]
+    async fn authenticate_unknown_user() {
+        let mut store = UserStore::default();
+        store
+            .add_user("alice", b"hunter2", Profile::Builder)
+            .unwrap();
+        let auth = Arc::new(ServerAuthenticator::from_users(&store).unwrap());
+
+        // neither unknown users nor the disabled shared password can log in
+        for username in ["bob", ""] {
+            let auth = auth.clone();
+            let (mut client, mut server) = tokio::io::duplex(128);
+            tokio::spawn(async move { auth.login(&mut client).await });
+            let client_result = login(&mut server, username, b"hunter2").await;
+            match client_result {
+                Err(AuthenticationError::ProtocolError(ProtocolError::InvalidLoginError)) => {}
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
 }
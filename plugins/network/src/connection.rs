@@ -16,53 +16,542 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use flume::{unbounded, Receiver, Sender};
-use hearth_schema::protocol::CapOperation;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use flume::{unbounded, Receiver, Sender, TryRecvError};
+use hearth_schema::protocol::{CapOperation, RemoteCapOperation, MAX_OP_LEN};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
+use crate::auth::SessionKey;
+use crate::encryption::{AsyncDecryptor, AsyncEncryptor, DatagramCipher, Key};
+use crate::stats::ConnectionStats;
+use crate::transport::{Datagrams, Link};
+use crate::NetworkConfig;
+
+/// The flag set in a frame header when the frame is a chunk of bulk data.
+const BULK_FRAME: u32 = 1 << 31;
+
+/// The maximum size of a chunk of bulk data. Operations from the reliable
+/// channel wait for at most one chunk before they are sent.
+const BULK_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Which end of a connection this side is. Used to pick encryption keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Client,
+    Server,
+}
+
 pub struct Connection {
     /// An outgoing channel for capability operations.
     pub op_tx: Sender<CapOperation>,
 
+    /// An outgoing channel for bulk capability operations.
+    ///
+    /// Operations sent on this channel are only ordered relative to each
+    /// other. They are split into chunks that are only sent while no
+    /// operations on [Self::op_tx] are waiting, so that large transfers like
+    /// lumps don't hold up small control messages.
+    pub bulk_tx: Sender<CapOperation>,
+
+    /// An outgoing channel for lossy capability operations.
+    ///
+    /// Operations sent on this channel may be dropped or reordered, so this
+    /// is meant for frequent state updates where only the latest one matters.
+    /// They are sent as datagrams when the transport supports them. Otherwise,
+    /// and for operations that transfer capabilities or don't fit in a
+    /// datagram, they fall back to the reliable channel.
+    pub lossy_tx: Sender<CapOperation>,
+
     /// A channel for incoming capability operations.
     pub op_rx: Receiver<CapOperation>,
+
+    /// The traffic statistics of this connection.
+    pub stats: Arc<ConnectionStats>,
 }
 
 impl Connection {
     /// Creates a connection for the given transport.
     pub fn new(
-        mut rx: impl AsyncRead + Unpin + Send + 'static,
-        mut tx: impl AsyncWrite + Unpin + Send + 'static,
+        rx: impl AsyncRead + Unpin + Send + 'static,
+        tx: impl AsyncWrite + Unpin + Send + 'static,
+        config: &NetworkConfig,
     ) -> Self {
+        let stats = Arc::new(ConnectionStats::default());
+        let (op_tx, bulk_tx, op_rx, _) = Self::spawn_stream(rx, tx, config, stats.clone());
+
+        Self {
+            lossy_tx: op_tx.clone(),
+            op_tx,
+            bulk_tx,
+            op_rx,
+            stats,
+        }
+    }
+
+    /// Creates an encrypted connection over a [Link] that has finished
+    /// authentication with the given session key.
+    pub fn encrypted(link: Link, session: &SessionKey, side: Side, config: &NetworkConfig) -> Self {
+        let client_key = Key::from_client_session(session);
+        let server_key = Key::from_server_session(session);
+
+        let (send_key, recv_key) = match side {
+            Side::Client => (client_key, server_key),
+            Side::Server => (server_key, client_key),
+        };
+
+        let stats = Arc::new(ConnectionStats::default());
+        let (rx, tx) = tokio::io::split(link.stream);
+        let rx = AsyncDecryptor::new(&recv_key, rx);
+        let tx = AsyncEncryptor::new(&send_key, tx);
+        let (op_tx, bulk_tx, op_rx, incoming_tx) =
+            Self::spawn_stream(rx, tx, config, stats.clone());
+
+        let Some(datagrams) = link.datagrams else {
+            return Self {
+                lossy_tx: op_tx.clone(),
+                op_tx,
+                bulk_tx,
+                op_rx,
+                stats,
+            };
+        };
+
+        let client_key = Key::datagram_from_client_session(session);
+        let server_key = Key::datagram_from_server_session(session);
+
+        let (send_key, recv_key) = match side {
+            Side::Client => (client_key, server_key),
+            Side::Server => (server_key, client_key),
+        };
+
+        let lossy_tx = Self::spawn_datagrams(
+            datagrams,
+            DatagramCipher::new(send_key),
+            DatagramCipher::new(recv_key),
+            op_tx.clone(),
+            incoming_tx,
+            stats.clone(),
+        );
+
+        Self {
+            op_tx,
+            bulk_tx,
+            lossy_tx,
+            op_rx,
+            stats,
+        }
+    }
+
+    /// Spawns the tasks that send and receive operations over a reliable
+    /// stream.
+    ///
+    /// The stream is made of frames, each prefixed by a little-endian `u32`
+    /// header. Normal frames contain a single operation. Frames with
+    /// [BULK_FRAME] set in their header contain a chunk of a second stream of
+    /// length-prefixed bulk operations, which is interleaved with the first.
+    ///
+    /// Returns the outgoing sender, the bulk outgoing sender, the incoming
+    /// receiver, and a sender for incoming operations from other channels.
+    fn spawn_stream(
+        rx: impl AsyncRead + Unpin + Send + 'static,
+        tx: impl AsyncWrite + Unpin + Send + 'static,
+        config: &NetworkConfig,
+        stats: Arc<ConnectionStats>,
+    ) -> (
+        Sender<CapOperation>,
+        Sender<CapOperation>,
+        Receiver<CapOperation>,
+        Sender<CapOperation>,
+    ) {
         let (outgoing_tx, outgoing_rx) = unbounded();
+        let (bulk_tx, bulk_rx) = unbounded();
         let (incoming_tx, incoming_rx) = unbounded();
 
+        let mut writer = FrameWriter {
+            tx,
+            limiter: (config.upload_limit > 0).then(|| RateLimiter::new(config.upload_limit)),
+            stats: stats.clone(),
+        };
+
         tokio::spawn(async move {
-            while let Ok(op) = outgoing_rx.recv_async().await {
+            let mut bulk_open = true;
+            let mut pending = Vec::new();
+            let mut pending_pos = 0;
+
+            loop {
+                // operations on the main channel always go first
+                match outgoing_rx.try_recv() {
+                    Ok(op) => {
+                        if writer.write_op(&op).await.is_err() {
+                            break;
+                        }
+
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => break,
+                    Err(TryRecvError::Empty) => {}
+                }
+
+                if pending_pos == pending.len() {
+                    let next = match bulk_rx.try_recv() {
+                        Ok(op) => Some(op),
+                        Err(_) if !bulk_open => None,
+                        Err(TryRecvError::Empty) => None,
+                        Err(TryRecvError::Disconnected) => {
+                            bulk_open = false;
+                            None
+                        }
+                    };
+
+                    let Some(op) = next else {
+                        // wait for more work on either channel
+                        tokio::select! {
+                            op = outgoing_rx.recv_async() => match op {
+                                Ok(op) => {
+                                    if writer.write_op(&op).await.is_err() {
+                                        break;
+                                    }
+                                }
+                                Err(_) => break,
+                            },
+                            op = bulk_rx.recv_async(), if bulk_open => match op {
+                                Ok(op) => {
+                                    pending = encode_bulk(&op);
+                                    pending_pos = 0;
+                                    writer.stats.add_op_sent();
+                                }
+                                Err(_) => bulk_open = false,
+                            },
+                        }
+
+                        continue;
+                    };
+
+                    pending = encode_bulk(&op);
+                    pending_pos = 0;
+                    writer.stats.add_op_sent();
+                }
+
+                let end = pending.len().min(pending_pos + BULK_CHUNK_SIZE);
+                let chunk = &pending[pending_pos..end];
+                let header = chunk.len() as u32 | BULK_FRAME;
+                if writer.write_frame(header, chunk).await.is_err() {
+                    break;
+                }
+
+                pending_pos = end;
+            }
+        });
+
+        let incoming = incoming_tx.clone();
+        tokio::spawn(async move {
+            let _ = read_frames(rx, incoming, stats).await;
+        });
+
+        (outgoing_tx, bulk_tx, incoming_rx, incoming_tx)
+    }
+
+    /// Spawns the tasks that send and receive lossy operations as datagrams.
+    ///
+    /// Returns the lossy outgoing sender.
+    fn spawn_datagrams(
+        datagrams: Arc<dyn Datagrams>,
+        send_cipher: DatagramCipher,
+        recv_cipher: DatagramCipher,
+        reliable_tx: Sender<CapOperation>,
+        incoming_tx: Sender<CapOperation>,
+        stats: Arc<ConnectionStats>,
+    ) -> Sender<CapOperation> {
+        let (lossy_tx, lossy_rx) = unbounded::<CapOperation>();
+
+        let sender = datagrams.clone();
+        let send_stats = stats.clone();
+        tokio::spawn(async move {
+            while let Ok(op) = lossy_rx.recv_async().await {
+                // capabilities can't be transferred unreliably
+                let reliable_only = !matches!(
+                    &op,
+                    CapOperation::Remote(RemoteCapOperation::Send { caps, .. }) if caps.is_empty()
+                );
+
+                let max_size = sender.max_size().unwrap_or(0);
                 let payload = bincode::serialize(&op).unwrap();
-                let len = payload.len() as u32;
-                tx.write_u32_le(len).await.unwrap();
-                tx.write_all(&payload).await.unwrap();
+
+                if reliable_only || payload.len() + DatagramCipher::OVERHEAD > max_size {
+                    if reliable_tx.send(op).is_err() {
+                        break;
+                    }
+
+                    continue;
+                }
+
+                let datagram = send_cipher.seal(&payload);
+                let len = datagram.len();
+                match sender.send(datagram) {
+                    Ok(()) => {
+                        send_stats.add_sent(len);
+                        send_stats.add_op_sent();
+                    }
+                    Err(err) => tracing::debug!("Failed to send datagram: {:?}", err),
+                }
             }
         });
 
-        #[allow(clippy::read_zero_byte_vec)]
         tokio::spawn(async move {
-            let mut buf = Vec::new();
-            loop {
-                let len = rx.read_u32_le().await.unwrap();
-                buf.resize(len as usize, 0);
-                rx.read_exact(&mut buf).await.unwrap();
-                let op = bincode::deserialize(&buf).unwrap();
+            while let Ok(datagram) = datagrams.recv().await {
+                stats.add_received(datagram.len());
+                let Some(payload) = recv_cipher.open(&datagram) else {
+                    continue;
+                };
+
+                let op = match bincode::deserialize(&payload) {
+                    Ok(op) => op,
+                    Err(err) => {
+                        tracing::debug!("Failed to decode datagram from peer: {:?}", err);
+                        continue;
+                    }
+                };
+
+                stats.add_op_received();
                 if incoming_tx.send(op).is_err() {
                     break;
                 }
             }
         });
 
+        lossy_tx
+    }
+}
+
+/// Serializes a bulk operation into its length-prefixed encoding.
+fn encode_bulk(op: &CapOperation) -> Vec<u8> {
+    let payload = bincode::serialize(op).unwrap();
+    let mut encoded = Vec::with_capacity(payload.len() + 4);
+    encoded.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    encoded.extend_from_slice(&payload);
+    encoded
+}
+
+/// Decodes an operation from a peer, logging a warning on failure.
+fn decode_op(payload: &[u8]) -> Option<CapOperation> {
+    match bincode::deserialize(payload) {
+        Ok(op) => Some(op),
+        Err(err) => {
+            tracing::warn!("Failed to decode operation from peer: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Reads frames from a reliable stream until it closes or the peer
+/// misbehaves, forwarding the operations in them to `incoming`.
+async fn read_frames(
+    mut rx: impl AsyncRead + Unpin,
+    incoming: Sender<CapOperation>,
+    stats: Arc<ConnectionStats>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut bulk = Vec::new();
+
+    loop {
+        let header = rx.read_u32_le().await?;
+        let len = header & !BULK_FRAME;
+
+        if header & BULK_FRAME == 0 {
+            if len > MAX_OP_LEN {
+                tracing::warn!("Peer sent an oversized operation ({} bytes)", len);
+                return Ok(());
+            }
+
+            buf.resize(len as usize, 0);
+            rx.read_exact(&mut buf).await?;
+            stats.add_received(buf.len() + 4);
+
+            let Some(op) = decode_op(&buf) else {
+                return Ok(());
+            };
+
+            stats.add_op_received();
+            if incoming.send(op).is_err() {
+                return Ok(());
+            }
+
+            continue;
+        }
+
+        if len as usize > BULK_CHUNK_SIZE {
+            tracing::warn!("Peer sent an oversized bulk chunk ({} bytes)", len);
+            return Ok(());
+        }
+
+        let start = bulk.len();
+        bulk.resize(start + len as usize, 0);
+        rx.read_exact(&mut bulk[start..]).await?;
+        stats.add_received(len as usize + 4);
+
+        // deliver every bulk operation that has been completed
+        let mut consumed = 0;
+        while let Some(prefix) = bulk.get(consumed..consumed + 4) {
+            let op_len = u32::from_le_bytes(prefix.try_into().unwrap());
+            if op_len > MAX_OP_LEN {
+                tracing::warn!("Peer sent an oversized operation ({} bytes)", op_len);
+                return Ok(());
+            }
+
+            let Some(payload) = bulk.get(consumed + 4..consumed + 4 + op_len as usize) else {
+                break;
+            };
+
+            let Some(op) = decode_op(payload) else {
+                return Ok(());
+            };
+
+            stats.add_op_received();
+            if incoming.send(op).is_err() {
+                return Ok(());
+            }
+
+            consumed += 4 + op_len as usize;
+        }
+
+        bulk.drain(..consumed);
+    }
+}
+
+/// Writes frames to a reliable stream, keeping statistics and honoring the
+/// upload limit.
+struct FrameWriter<T> {
+    tx: T,
+    limiter: Option<RateLimiter>,
+    stats: Arc<ConnectionStats>,
+}
+
+impl<T: AsyncWrite + Unpin> FrameWriter<T> {
+    /// Writes a frame containing a single operation.
+    async fn write_op(&mut self, op: &CapOperation) -> std::io::Result<()> {
+        let payload = bincode::serialize(op).unwrap();
+        self.write_frame(payload.len() as u32, &payload).await?;
+        self.stats.add_op_sent();
+        Ok(())
+    }
+
+    /// Writes and flushes a single frame.
+    async fn write_frame(&mut self, header: u32, payload: &[u8]) -> std::io::Result<()> {
+        let len = payload.len() + 4;
+        if let Some(limiter) = self.limiter.as_mut() {
+            limiter.acquire(len).await;
+        }
+
+        self.tx.write_u32_le(header).await?;
+        self.tx.write_all(payload).await?;
+        self.tx.flush().await?;
+        self.stats.add_sent(len);
+        Ok(())
+    }
+}
+
+/// A token bucket limiting a byte rate.
+///
+/// Up to one second's worth of bytes may be sent in a burst. Writes larger
+/// than the bucket go into debt that later writes wait out.
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter for the given rate in bytes per second.
+    fn new(rate: u64) -> Self {
         Self {
-            op_tx: outgoing_tx,
-            op_rx: incoming_rx,
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Waits until `amount` bytes may be sent.
+    async fn acquire(&mut self, amount: usize) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.tokens -= amount as f64;
+
+        if self.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-self.tokens / self.rate)).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lossy_falls_back_to_reliable() {
+        let session = [7u8; 64];
+        let (client, server) = tokio::io::duplex(1024);
+        let config = NetworkConfig::default();
+        let client = Connection::encrypted(Link::reliable(client), &session, Side::Client, &config);
+        let server = Connection::encrypted(Link::reliable(server), &session, Side::Server, &config);
+
+        let op = CapOperation::Remote(RemoteCapOperation::Send {
+            id: 0,
+            data: b"Hello, world!".to_vec(),
+            caps: vec![],
+        });
+
+        client.lossy_tx.send(op.clone()).unwrap();
+        assert_eq!(server.op_rx.recv_async().await.unwrap(), op);
+
+        server.op_tx.send(op.clone()).unwrap();
+        assert_eq!(client.op_rx.recv_async().await.unwrap(), op);
+    }
+
+    #[tokio::test]
+    async fn control_overtakes_bulk() {
+        let config = NetworkConfig::default();
+        let (client, server) = tokio::io::duplex(1024);
+        let (client_rx, client_tx) = tokio::io::split(client);
+        let (server_rx, server_tx) = tokio::io::split(server);
+        let client = Connection::new(client_rx, client_tx, &config);
+        let server = Connection::new(server_rx, server_tx, &config);
+
+        let bulk = CapOperation::Remote(RemoteCapOperation::Send {
+            id: 0,
+            data: vec![0xab; BULK_CHUNK_SIZE * 4 + 7],
+            caps: vec![],
+        });
+
+        let control = CapOperation::Remote(RemoteCapOperation::FreeCap { id: 1 });
+
+        // neither is sent until this task yields, so the writer sees both
+        client.bulk_tx.send(bulk.clone()).unwrap();
+        client.op_tx.send(control.clone()).unwrap();
+
+        assert_eq!(server.op_rx.recv_async().await.unwrap(), control);
+        assert_eq!(server.op_rx.recv_async().await.unwrap(), bulk);
+
+        let sent = client.stats.totals();
+        let received = server.stats.totals();
+        assert_eq!(sent.ops_sent, 2);
+        assert_eq!(received.ops_received, 2);
+        assert_eq!(sent.bytes_sent, received.bytes_received);
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_waits_out_debt() {
+        let mut limiter = RateLimiter::new(1000);
+
+        // a full bucket can be spent immediately
+        let start = Instant::now();
+        limiter.acquire(1000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        limiter.acquire(100).await;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+}
@@ -18,9 +18,14 @@
 
 use std::io::Result as IoResult;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::task::{ready, Context, Poll};
 
 use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::ChaCha20Poly1305;
+use sha2::{Digest, Sha512};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 use crate::auth::SessionKey;
@@ -52,10 +57,143 @@ impl Key {
         Self { key, iv }
     }
 
+    /// Derives a key + IV pair from a session key for client-to-server datagrams.
+    pub fn datagram_from_client_session(session: &SessionKey) -> Self {
+        Self::derive(session, b"hearth client datagrams")
+    }
+
+    /// Derives a key + IV pair from a session key for server-to-client datagrams.
+    pub fn datagram_from_server_session(session: &SessionKey) -> Self {
+        Self::derive(session, b"hearth server datagrams")
+    }
+
     /// Initializes a [Cipher] from this key and IV.
     pub fn make_cipher(&self) -> Cipher {
         Cipher::new(&self.key, &self.iv)
     }
+
+    /// Derives a key + IV pair that is independent from the stream keys.
+    ///
+    /// Datagrams each use their own nonce, so they can't share a key with
+    /// the streams without risking keystream reuse.
+    fn derive(session: &SessionKey, label: &[u8]) -> Self {
+        let mut hasher = Sha512::new();
+        hasher.update(label);
+        hasher.update(session);
+        let hash = hasher.finalize();
+        let key = chacha20::Key::clone_from_slice(&hash[..32]);
+        let iv = chacha20::Nonce::clone_from_slice(&hash[32..44]);
+        Self { key, iv }
+    }
+}
+
+/// Seals and opens individual datagrams with ChaCha20-Poly1305.
+///
+/// Each datagram is prefixed with a sequence number that is mixed into the
+/// nonce, so datagrams can be opened independently and in any order. Forged
+/// or modified datagrams fail authentication, and datagrams whose sequence
+/// numbers have already been opened are rejected as replays.
+pub struct DatagramCipher {
+    aead: ChaCha20Poly1305,
+    iv: chacha20::Nonce,
+    next_seq: AtomicU64,
+    window: Mutex<ReplayWindow>,
+}
+
+impl DatagramCipher {
+    /// The number of bytes that [Self::seal] adds to each datagram: the
+    /// sequence number and the authentication tag.
+    pub const OVERHEAD: usize = 8 + 16;
+
+    pub fn new(key: Key) -> Self {
+        Self {
+            aead: ChaCha20Poly1305::new(&key.key),
+            iv: key.iv,
+            next_seq: AtomicU64::new(0),
+            window: Mutex::new(ReplayWindow::default()),
+        }
+    }
+
+    /// Encrypts and authenticates a datagram.
+    pub fn seal(&self, data: &[u8]) -> Vec<u8> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let encrypted = self
+            .aead
+            .encrypt(&self.make_nonce(seq), data)
+            .expect("datagram is too large to encrypt");
+
+        let mut sealed = Vec::with_capacity(Self::OVERHEAD + data.len());
+        sealed.extend_from_slice(&seq.to_le_bytes());
+        sealed.extend_from_slice(&encrypted);
+        sealed
+    }
+
+    /// Authenticates and decrypts a datagram. Returns `None` if it's invalid
+    /// or a replay of a datagram that has already been opened.
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        let (seq, data) = sealed.split_first_chunk::<8>()?;
+        let seq = u64::from_le_bytes(*seq);
+        let data = self.aead.decrypt(&self.make_nonce(seq), data).ok()?;
+
+        // only authentic datagrams may move the window
+        if !self.window.lock().unwrap().insert(seq) {
+            return None;
+        }
+
+        Some(data)
+    }
+
+    fn make_nonce(&self, seq: u64) -> chacha20::Nonce {
+        let mut nonce = self.iv;
+        for (nonce, seq) in nonce.iter_mut().zip(seq.to_le_bytes()) {
+            *nonce ^= seq;
+        }
+
+        nonce
+    }
+}
+
+/// The sequence numbers of the most recently opened datagrams.
+#[derive(Default)]
+struct ReplayWindow {
+    /// One more than the highest sequence number opened so far.
+    next: u64,
+
+    /// A bitmask of the sequence numbers opened below `next`. Bit `i` is set
+    /// if `next - 1 - i` has been opened.
+    seen: u64,
+}
+
+impl ReplayWindow {
+    /// Marks a sequence number as opened. Returns false if it's already been
+    /// opened or it's too old to tell.
+    fn insert(&mut self, seq: u64) -> bool {
+        if seq >= self.next {
+            let shift = seq - self.next + 1;
+            self.seen = if shift >= u64::BITS as u64 {
+                0
+            } else {
+                self.seen << shift
+            };
+
+            self.seen |= 1;
+            self.next = seq + 1;
+            return true;
+        }
+
+        let age = self.next - 1 - seq;
+        if age >= u64::BITS as u64 {
+            return false;
+        }
+
+        let bit = 1 << age;
+        if self.seen & bit != 0 {
+            return false;
+        }
+
+        self.seen |= bit;
+        true
+    }
 }
 
 pub struct AsyncDecryptor<T> {
@@ -76,8 +214,11 @@ impl<T: AsyncRead + Unpin> AsyncRead for AsyncDecryptor<T> {
         cx: &mut Context,
         buf: &mut ReadBuf,
     ) -> Poll<IoResult<()>> {
+        // only decrypt the bytes read by this call, since the caller may
+        // have already filled part of the buffer
+        let start = buf.filled().len();
         let result = Pin::new(&mut self.transport).poll_read(cx, buf);
-        self.cipher.apply_keystream(buf.filled_mut());
+        self.cipher.apply_keystream(&mut buf.filled_mut()[start..]);
         result
     }
 }
@@ -85,27 +226,64 @@ impl<T: AsyncRead + Unpin> AsyncRead for AsyncDecryptor<T> {
 pub struct AsyncEncryptor<T> {
     cipher: Cipher,
     transport: T,
+
+    /// Encrypted bytes that the transport hasn't accepted yet.
+    ///
+    /// The keystream advances as soon as bytes are encrypted, so they have to
+    /// be kept until they're written instead of being encrypted again.
+    pending: Vec<u8>,
 }
 
 impl<T: AsyncWrite + Unpin> AsyncEncryptor<T> {
     pub fn new(key: &Key, transport: T) -> Self {
         let cipher = key.make_cipher();
-        Self { cipher, transport }
+        Self {
+            cipher,
+            transport,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Writes all pending encrypted bytes to the transport.
+    fn poll_write_pending(&mut self, cx: &mut Context) -> Poll<IoResult<()>> {
+        while !self.pending.is_empty() {
+            let written = ready!(Pin::new(&mut self.transport).poll_write(cx, &self.pending))?;
+
+            if written == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+
+            self.pending.drain(..written);
+        }
+
+        Poll::Ready(Ok(()))
     }
 }
 
 impl<T: AsyncWrite + Unpin> AsyncWrite for AsyncEncryptor<T> {
-    fn poll_write(mut self: Pin<&mut Self>, cw: &mut Context, buf: &[u8]) -> Poll<IoResult<usize>> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<IoResult<usize>> {
+        ready!(self.poll_write_pending(cx))?;
+
         let mut encrypted = buf.to_owned();
         self.cipher.apply_keystream(&mut encrypted);
-        Pin::new(&mut self.transport).poll_write(cw, &encrypted)
+        self.pending = encrypted;
+
+        // the bytes are accepted even if the transport isn't ready for them
+        // yet; they'll be written by the next write or flush
+        if let Poll::Ready(Err(err)) = self.poll_write_pending(cx) {
+            return Poll::Ready(Err(err));
+        }
+
+        Poll::Ready(Ok(buf.len()))
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<IoResult<()>> {
+        ready!(self.poll_write_pending(cx))?;
         Pin::new(&mut self.transport).poll_flush(cx)
     }
 
     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<IoResult<()>> {
+        ready!(self.poll_write_pending(cx))?;
         Pin::new(&mut self.transport).poll_shutdown(cx)
     }
 }
@@ -170,4 +348,71 @@ mod tests {
         decryptor.read_exact(&mut rx).await.unwrap();
         assert_eq!(TEST_DATA, rx);
     }
+
+    #[tokio::test]
+    async fn backpressure() {
+        let key = generate_key();
+        let (client, server) = tokio::io::duplex(16);
+        let mut encryptor = AsyncEncryptor::new(&key, server);
+        let mut decryptor = AsyncDecryptor::new(&key, client);
+
+        let reader = tokio::spawn(async move {
+            let mut rx = vec![0u8; TEST_DATA.len()];
+            decryptor.read_exact(&mut rx).await.unwrap();
+            rx
+        });
+
+        encryptor.write_all(TEST_DATA).await.unwrap();
+        encryptor.flush().await.unwrap();
+        assert_eq!(TEST_DATA, reader.await.unwrap());
+    }
+
+    #[test]
+    fn datagrams() {
+        let sender = DatagramCipher::new(generate_key());
+        let receiver = DatagramCipher::new(Key {
+            key: sender.key.key,
+            iv: sender.key.iv,
+        });
+
+        let first = sender.seal(TEST_DATA);
+        let second = sender.seal(TEST_DATA);
+        assert_ne!(first, second);
+
+        // datagrams can be opened out of order
+        assert_eq!(receiver.open(&second).unwrap(), TEST_DATA);
+        assert_eq!(receiver.open(&first).unwrap(), TEST_DATA);
+        assert!(receiver.open(&[0; 4]).is_none());
+
+        // but only once
+        assert!(receiver.open(&first).is_none());
+        assert!(receiver.open(&second).is_none());
+    }
+
+    #[test]
+    fn tampered_datagrams() {
+        let key = generate_key();
+        let receiver = DatagramCipher::new(Key {
+            key: key.key,
+            iv: key.iv,
+        });
+
+        let sender = DatagramCipher::new(key);
+        let mut sealed = sender.seal(TEST_DATA);
+        sealed[DatagramCipher::OVERHEAD] ^= 1;
+        assert!(receiver.open(&sealed).is_none());
+    }
+
+    #[test]
+    fn replay_window() {
+        let mut window = ReplayWindow::default();
+        assert!(window.insert(100));
+        assert!(window.insert(40));
+        assert!(!window.insert(36));
+        assert!(!window.insert(100));
+        assert!(window.insert(99));
+        assert!(!window.insert(99));
+        assert!(window.insert(1000));
+        assert!(!window.insert(100));
+    }
 }
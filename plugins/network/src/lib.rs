@@ -16,9 +16,75 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
+use std::net::SocketAddr;
+
+use serde::Deserialize;
+
 pub mod auth;
 pub mod connection;
 pub mod encryption;
+pub mod lumps;
+pub mod profile;
+pub mod quic;
+pub mod stats;
+pub mod transport;
+pub mod webrtc;
+
+/// Command-line arguments for accepting network connections.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct NetworkArgs {
+    /// IP address and port to listen on.
+    #[clap(short, long, alias = "bind", short_alias = 'b')]
+    pub listen: Option<SocketAddr>,
+
+    /// The transport to accept connections over.
+    #[clap(long, value_enum, default_value = "tcp")]
+    pub transport: transport::TransportKind,
+}
+
+/// Configuration for network connections, read from the `network` table of
+/// the config file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// The maximum rate in bytes per second to upload to each peer. Zero
+    /// means unlimited.
+    pub upload_limit: u64,
+
+    /// The TCP port that a client serves lumps to its peers on, or zero for
+    /// any free port. Clients that leave this unset still fetch lumps from
+    /// peers that serve them. Unused by servers.
+    pub lump_port: Option<u16>,
+
+    /// The profile of clients that log in with the server's shared password
+    /// instead of as a registered user. If unset, the shared password grants
+    /// the admin profile while no users are registered and is disabled
+    /// otherwise.
+    pub password_profile: Option<profile::Profile>,
+
+    /// The services that each profile can access.
+    pub grants: profile::Grants,
+}
+
+impl NetworkConfig {
+    /// Loads a network config from the `network` table of a config file.
+    ///
+    /// Logs an error and uses the default configuration if the table is
+    /// malformed.
+    pub fn from_config_file(config: &toml::Table) -> Self {
+        let Some(table) = config.get("network") else {
+            return Self::default();
+        };
+
+        match table.clone().try_into() {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::error!("Failed to parse network config: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -39,7 +105,7 @@ mod tests {
         let (mut client, mut server) = tokio::io::duplex(128);
 
         tokio::spawn(async move {
-            let session_key = authenticator.login(&mut client).await.unwrap();
+            let (session_key, _identity) = authenticator.login(&mut client).await.unwrap();
             let client_key = Key::from_client_session(&session_key);
             let server_key = Key::from_server_session(&session_key);
             let (rx, tx) = tokio::io::split(client);
@@ -54,7 +120,7 @@ mod tests {
             encryptor.flush().await.unwrap();
         });
 
-        let session_key = auth::login(&mut server, PASSWORD).await.unwrap();
+        let session_key = auth::login(&mut server, "", PASSWORD).await.unwrap();
         let client_key = Key::from_client_session(&session_key);
         let server_key = Key::from_server_session(&session_key);
         let (rx, tx) = tokio::io::split(server);
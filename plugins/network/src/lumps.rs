@@ -0,0 +1,788 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+//! Peer-to-peer lump exchange.
+//!
+//! Lumps are content-addressed, so any peer that holds a lump can serve it
+//! and the fetcher can check the data against its [LumpId] without trusting
+//! that peer. Lumps are exchanged with [LumpOperation]s over the same
+//! authenticated and encrypted [Connection] as capabilities, and each side
+//! only serves the lumps that it has referred to in messages sent over that
+//! connection.
+//!
+//! Clients can also serve lumps directly to each other, and advertise the
+//! lumps that they hold to the server, which keeps a [LumpDirectory] of them.
+//! When a client locates a lump, the server tells every peer that has it to
+//! expect a one-time ticket and hands the client a [LumpTicket] for each of
+//! them. A peer only serves the lump that a ticket was issued for, encrypted
+//! with the ticket's secret. The server itself is the fallback when no peer
+//! can provide a lump.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use flume::{Receiver, Sender};
+use hearth_schema::codec::find_lump_ids;
+use hearth_schema::protocol::{
+    CapOperation, LumpOperation, LumpTicket, RemoteCapOperation, MAX_OP_LEN,
+};
+use hearth_schema::{decode_spillover, LumpId};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha512};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, Notify};
+use tracing::{debug, warn};
+
+use crate::auth::SessionKey;
+use crate::connection::{Connection, Side};
+use crate::encryption::{AsyncDecryptor, AsyncEncryptor, Key};
+
+/// The largest lump that will be sent or accepted over the exchange.
+///
+/// Leaves room for the rest of the [LumpOperation] that carries it.
+pub const MAX_LUMP_SIZE: u32 = MAX_OP_LEN - 1024;
+
+/// The longest time to wait on a peer before moving on to the next one.
+///
+/// Tickets that haven't been redeemed by then expire.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A local store of lumps that a [LumpExchange] serves from.
+#[async_trait]
+pub trait LumpSource: Send + Sync + 'static {
+    /// Gets the contents of a lump, if it is available.
+    async fn get_lump(&self, id: &LumpId) -> Option<Vec<u8>>;
+}
+
+/// Tracks which peers advertise which lumps.
+#[derive(Debug, Default)]
+pub struct LumpDirectory {
+    inner: Mutex<DirectoryInner>,
+}
+
+#[derive(Debug, Default)]
+struct DirectoryInner {
+    providers: HashMap<LumpId, HashSet<u64>>,
+    peers: HashMap<u64, Provider>,
+}
+
+/// A peer that serves lumps directly to other peers.
+#[derive(Debug)]
+struct Provider {
+    /// The address that this peer serves lumps on.
+    addr: SocketAddr,
+
+    /// The lumps that this peer advertises.
+    lumps: Vec<LumpId>,
+
+    /// The peer's connection, for telling it to expect tickets.
+    op_tx: Sender<CapOperation>,
+}
+
+impl LumpDirectory {
+    /// Replaces the lumps that a peer advertises.
+    fn advertise(&self, peer: u64, provider: Provider) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(peer);
+
+        for lump in provider.lumps.iter() {
+            inner.providers.entry(*lump).or_default().insert(peer);
+        }
+
+        inner.peers.insert(peer, provider);
+    }
+
+    /// Withdraws every lump that a peer advertises.
+    fn withdraw(&self, peer: u64) {
+        self.inner.lock().unwrap().remove(peer);
+    }
+
+    /// Lists the addresses of the peers that advertise a lump.
+    pub fn providers(&self, lump: &LumpId) -> Vec<SocketAddr> {
+        let inner = self.inner.lock().unwrap();
+        let Some(providers) = inner.providers.get(lump) else {
+            return vec![];
+        };
+
+        providers
+            .iter()
+            .map(|peer| inner.peers[peer].addr)
+            .collect()
+    }
+
+    /// Issues a ticket for a lump from every peer that advertises it, other
+    /// than the peer that asked, and tells those peers to expect them.
+    fn issue_tickets(&self, lump: &LumpId, asker: u64) -> Vec<LumpTicket> {
+        let inner = self.inner.lock().unwrap();
+        let Some(providers) = inner.providers.get(lump) else {
+            return vec![];
+        };
+
+        let mut tickets = Vec::new();
+        for peer in providers.iter().filter(|peer| **peer != asker) {
+            let provider = &inner.peers[peer];
+            let ticket = rand::random();
+            let secret = rand::random();
+            let expect = LumpOperation::Expect {
+                ticket,
+                secret,
+                lump: *lump,
+            };
+
+            if provider.op_tx.send(CapOperation::Lump(expect)).is_ok() {
+                tickets.push(LumpTicket {
+                    addr: provider.addr,
+                    ticket,
+                    secret,
+                });
+            }
+        }
+
+        tickets
+    }
+}
+
+impl DirectoryInner {
+    fn remove(&mut self, peer: u64) {
+        let Some(provider) = self.peers.remove(&peer) else {
+            return;
+        };
+
+        for lump in provider.lumps {
+            if let Some(providers) = self.providers.get_mut(&lump) {
+                providers.remove(&peer);
+
+                if providers.is_empty() {
+                    self.providers.remove(&lump);
+                }
+            }
+        }
+    }
+}
+
+/// The tickets that this node has been told to expect.
+#[derive(Default)]
+struct Tickets {
+    pending: Mutex<HashMap<[u8; 32], ExpectedTicket>>,
+    issued: Notify,
+}
+
+struct ExpectedTicket {
+    secret: [u8; 32],
+    lump: LumpId,
+    expires: Instant,
+}
+
+impl Tickets {
+    /// Expects a ticket to be redeemed for a lump.
+    fn expect(&self, ticket: [u8; 32], secret: [u8; 32], lump: LumpId) {
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, expected| expected.expires > now);
+        pending.insert(
+            ticket,
+            ExpectedTicket {
+                secret,
+                lump,
+                expires: now + PEER_TIMEOUT,
+            },
+        );
+
+        drop(pending);
+        self.issued.notify_waiters();
+    }
+
+    /// Redeems a ticket, waiting for it to be expected if it hasn't been yet.
+    ///
+    /// Returns the ticket's secret and lump, or `None` if it's never
+    /// expected.
+    async fn redeem(&self, ticket: &[u8; 32]) -> Option<([u8; 32], LumpId)> {
+        let deadline = tokio::time::Instant::now() + PEER_TIMEOUT;
+
+        loop {
+            let issued = self.issued.notified();
+
+            if let Some(expected) = self.pending.lock().unwrap().remove(ticket) {
+                if expected.expires < Instant::now() {
+                    return None;
+                }
+
+                return Some((expected.secret, expected.lump));
+            }
+
+            tokio::time::timeout_at(deadline, issued).await.ok()?;
+        }
+    }
+}
+
+/// A node's end of the lump exchange.
+pub struct LumpExchange {
+    source: Arc<dyn LumpSource>,
+    directory: Option<Arc<LumpDirectory>>,
+    tickets: Tickets,
+    next_peer: AtomicU64,
+}
+
+impl LumpExchange {
+    /// Creates an exchange that serves lumps from a source.
+    pub fn new(source: Arc<dyn LumpSource>) -> Self {
+        Self {
+            source,
+            directory: None,
+            tickets: Tickets::default(),
+            next_peer: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates an exchange that also keeps a directory of the lumps that its
+    /// clients advertise.
+    pub fn with_directory(source: Arc<dyn LumpSource>, directory: Arc<LumpDirectory>) -> Self {
+        Self {
+            directory: Some(directory),
+            ..Self::new(source)
+        }
+    }
+
+    /// Interposes on a connection to exchange lumps with the peer on its
+    /// other end.
+    ///
+    /// `addr` is the peer's address, which its advertised lumps are served
+    /// from. Returns the peer and a connection that carries every other
+    /// operation.
+    pub fn attach(
+        self: &Arc<Self>,
+        conn: Connection,
+        side: Side,
+        addr: Option<SocketAddr>,
+    ) -> (Arc<LumpPeer>, Connection) {
+        let peer = Arc::new(LumpPeer {
+            id: self.next_peer.fetch_add(1, Ordering::Relaxed),
+            exchange: self.clone(),
+            side,
+            addr,
+            op_tx: conn.op_tx.clone(),
+            bulk_tx: conn.bulk_tx.clone(),
+            referenced: Default::default(),
+            requests: Default::default(),
+        });
+
+        let (incoming_tx, op_rx) = flume::unbounded();
+        tokio::spawn({
+            let peer = peer.clone();
+            let conn_rx = conn.op_rx;
+            async move {
+                while let Ok(op) = conn_rx.recv_async().await {
+                    let op = match op {
+                        CapOperation::Lump(op) => {
+                            peer.on_op(op);
+                            continue;
+                        }
+                        op => op,
+                    };
+
+                    if incoming_tx.send(op).is_err() {
+                        break;
+                    }
+                }
+
+                peer.close();
+            }
+        });
+
+        let conn = Connection {
+            op_tx: peer.spawn_outgoing(conn.op_tx),
+            bulk_tx: peer.spawn_outgoing(conn.bulk_tx),
+            lossy_tx: peer.spawn_outgoing(conn.lossy_tx),
+            op_rx,
+            stats: conn.stats,
+        };
+
+        (peer, conn)
+    }
+
+    /// Serves lumps to the peers that present tickets to a listener until
+    /// it fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let exchange = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = exchange.serve_ticket(stream).await {
+                    debug!("Failed to serve lump ticket from {}: {:?}", addr, err);
+                }
+            });
+        }
+    }
+
+    async fn serve_ticket(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut ticket = [0u8; 32];
+        tokio::time::timeout(PEER_TIMEOUT, stream.read_exact(&mut ticket)).await??;
+
+        let Some((secret, lump)) = self.tickets.redeem(&ticket).await else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "unknown or expired ticket",
+            ));
+        };
+
+        let data = self.source.get_lump(&lump).await;
+        let data = data.filter(|data| data.len() <= MAX_LUMP_SIZE as usize);
+        let key = Key::from_server_session(&ticket_session(&secret));
+        let mut tx = AsyncEncryptor::new(&key, stream);
+        write_frame(&mut tx, &data).await
+    }
+}
+
+/// The lump exchange with the peer on the other end of a connection.
+pub struct LumpPeer {
+    id: u64,
+    exchange: Arc<LumpExchange>,
+    side: Side,
+    addr: Option<SocketAddr>,
+    op_tx: Sender<CapOperation>,
+    bulk_tx: Sender<CapOperation>,
+
+    /// The lumps that this side has referred to in messages to the peer.
+    referenced: Mutex<HashSet<LumpId>>,
+
+    requests: Mutex<Requests>,
+}
+
+#[derive(Default)]
+struct Requests {
+    next: u32,
+    pending: HashMap<u32, oneshot::Sender<LumpOperation>>,
+    closed: bool,
+}
+
+impl LumpPeer {
+    /// Advertises the lumps that this client serves to its peers on the
+    /// given port, replacing any previous advertisement.
+    pub fn advertise(&self, port: u16, lumps: Vec<LumpId>) -> io::Result<()> {
+        let op = LumpOperation::Advertise { port, lumps };
+        self.op_tx
+            .send(CapOperation::Lump(op))
+            .map_err(|_| closed())
+    }
+
+    /// Fetches a lump, preferring other peers over the one on this
+    /// connection.
+    ///
+    /// Returns `None` if no peer can provide the lump.
+    pub async fn fetch(&self, lump: &LumpId) -> io::Result<Option<Vec<u8>>> {
+        let lump = *lump;
+        let tickets = match self
+            .request(|request| LumpOperation::Locate { request, lump })
+            .await?
+        {
+            LumpOperation::Providers { tickets, .. } => tickets,
+            other => return Err(unexpected(other)),
+        };
+
+        for ticket in tickets {
+            let addr = ticket.addr;
+            match tokio::time::timeout(PEER_TIMEOUT, fetch_from_peer(ticket, &lump)).await {
+                Ok(Ok(data)) => return Ok(Some(data)),
+                Ok(Err(err)) => debug!("Failed to fetch lump {} from {}: {:?}", lump, addr, err),
+                Err(_) => debug!("Timed out fetching lump {} from {}", lump, addr),
+            }
+        }
+
+        match self
+            .request(|request| LumpOperation::Fetch { request, lump })
+            .await?
+        {
+            LumpOperation::Lump {
+                data: Some(data), ..
+            } if verify(&lump, &data) => Ok(Some(data)),
+            LumpOperation::Lump { data: Some(_), .. } => {
+                warn!("Peer sent corrupt data for lump {}", lump);
+                Ok(None)
+            }
+            LumpOperation::Lump { data: None, .. } => Ok(None),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Sends a request to the peer and waits for its answer.
+    async fn request(
+        &self,
+        make_op: impl FnOnce(u32) -> LumpOperation,
+    ) -> io::Result<LumpOperation> {
+        let (tx, rx) = oneshot::channel();
+
+        {
+            let mut requests = self.requests.lock().unwrap();
+            if requests.closed {
+                return Err(closed());
+            }
+
+            let request = requests.next;
+            requests.next = request.wrapping_add(1);
+            requests.pending.insert(request, tx);
+            let op = CapOperation::Lump(make_op(request));
+            self.op_tx.send(op).map_err(|_| closed())?;
+        }
+
+        rx.await.map_err(|_| closed())
+    }
+
+    /// Forwards outgoing operations to the connection, recording the lumps
+    /// that they refer to.
+    fn spawn_outgoing(self: &Arc<Self>, conn_tx: Sender<CapOperation>) -> Sender<CapOperation> {
+        let (tx, rx): (_, Receiver<CapOperation>) = flume::unbounded();
+        let peer = self.clone();
+        tokio::spawn(async move {
+            while let Ok(op) = rx.recv_async().await {
+                if let CapOperation::Remote(RemoteCapOperation::Send { data, .. }) = &op {
+                    let mut referenced = peer.referenced.lock().unwrap();
+                    referenced.extend(decode_spillover(data));
+                    referenced.extend(find_lump_ids(data));
+                }
+
+                if conn_tx.send(op).is_err() {
+                    break;
+                }
+            }
+        });
+
+        tx
+    }
+
+    fn is_referenced(&self, lump: &LumpId) -> bool {
+        self.referenced.lock().unwrap().contains(lump)
+    }
+
+    fn on_op(self: &Arc<Self>, op: LumpOperation) {
+        use LumpOperation::*;
+        match op {
+            Advertise { port, lumps } => {
+                let (Side::Server, Some(directory), Some(addr)) =
+                    (self.side, self.exchange.directory.as_ref(), self.addr)
+                else {
+                    debug!("Ignoring lump advertisement");
+                    return;
+                };
+
+                let addr = SocketAddr::new(addr.ip(), port);
+                debug!("{} advertised {} lumps", addr, lumps.len());
+                let op_tx = self.op_tx.clone();
+                let provider = Provider { addr, lumps, op_tx };
+                directory.advertise(self.id, provider);
+            }
+            Locate { request, lump } => {
+                let tickets = match (self.side, self.exchange.directory.as_ref()) {
+                    (Side::Server, Some(directory)) if self.is_referenced(&lump) => {
+                        directory.issue_tickets(&lump, self.id)
+                    }
+                    _ => vec![],
+                };
+
+                let op = Providers { request, tickets };
+                let _ = self.op_tx.send(CapOperation::Lump(op));
+            }
+            Expect {
+                ticket,
+                secret,
+                lump,
+            } => {
+                if self.side == Side::Client {
+                    self.exchange.tickets.expect(ticket, secret, lump);
+                } else {
+                    debug!("Ignoring lump ticket from client");
+                }
+            }
+            Fetch { request, lump } => {
+                if !self.is_referenced(&lump) {
+                    debug!("Refusing to serve unreferenced lump {}", lump);
+                    let op = Lump {
+                        request,
+                        data: None,
+                    };
+
+                    let _ = self.op_tx.send(CapOperation::Lump(op));
+                    return;
+                }
+
+                let peer = self.clone();
+                tokio::spawn(async move {
+                    let data = peer.exchange.source.get_lump(&lump).await;
+                    let data = data.filter(|data| data.len() <= MAX_LUMP_SIZE as usize);
+                    let op = Lump { request, data };
+                    let _ = peer.bulk_tx.send(CapOperation::Lump(op));
+                });
+            }
+            Providers { request, .. } | Lump { request, .. } => {
+                let pending = self.requests.lock().unwrap().pending.remove(&request);
+                match pending {
+                    Some(tx) => {
+                        let _ = tx.send(op);
+                    }
+                    None => debug!("Peer answered unknown lump request {}", request),
+                }
+            }
+        }
+    }
+
+    /// Withdraws this peer's lumps and fails its pending requests once the
+    /// connection has closed.
+    fn close(&self) {
+        if let Some(directory) = self.exchange.directory.as_ref() {
+            directory.withdraw(self.id);
+        }
+
+        let mut requests = self.requests.lock().unwrap();
+        requests.closed = true;
+        requests.pending.clear();
+    }
+}
+
+/// Fetches a lump from a single peer with a ticket and verifies its contents.
+async fn fetch_from_peer(ticket: LumpTicket, lump: &LumpId) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(ticket.addr).await?;
+    stream.write_all(&ticket.ticket).await?;
+
+    let key = Key::from_server_session(&ticket_session(&ticket.secret));
+    let mut rx = AsyncDecryptor::new(&key, stream);
+
+    match read_frame::<Option<Vec<u8>>>(&mut rx).await? {
+        Some(data) if verify(lump, &data) => Ok(data),
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "lump data does not match its ID",
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "peer does not have lump",
+        )),
+    }
+}
+
+/// Derives the session key of a ticketed transfer from the ticket's secret.
+fn ticket_session(secret: &[u8; 32]) -> SessionKey {
+    let mut hasher = Sha512::new();
+    hasher.update(b"hearth lump ticket");
+    hasher.update(secret);
+
+    let mut session = [0u8; 64];
+    session.copy_from_slice(&hasher.finalize());
+    session
+}
+
+/// Checks that lump data hashes to the expected ID.
+fn verify(lump: &LumpId, data: &[u8]) -> bool {
+    blake3::hash(data).as_bytes() == &lump.0
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "connection closed")
+}
+
+fn unexpected(op: LumpOperation) -> io::Error {
+    let msg = format!("unexpected lump exchange answer: {:?}", op);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+async fn write_frame<T: Serialize>(
+    tx: &mut (impl AsyncWrite + Unpin),
+    frame: &T,
+) -> io::Result<()> {
+    let payload = bincode::serialize(frame).unwrap();
+    tx.write_u32_le(payload.len() as u32).await?;
+    tx.write_all(&payload).await?;
+    tx.flush().await
+}
+
+async fn read_frame<T: DeserializeOwned>(rx: &mut (impl AsyncRead + Unpin)) -> io::Result<T> {
+    // room for a maximum-size lump plus its framing
+    let len = rx.read_u32_le().await?;
+    if len > MAX_LUMP_SIZE + 64 {
+        let msg = format!("lump exchange frame is too long ({} bytes)", len);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    rx.read_exact(&mut payload).await?;
+    bincode::deserialize(&payload).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hearth_schema::codec::encode;
+
+    struct MapSource(HashMap<LumpId, Vec<u8>>);
+
+    #[async_trait]
+    impl LumpSource for MapSource {
+        async fn get_lump(&self, id: &LumpId) -> Option<Vec<u8>> {
+            self.0.get(id).cloned()
+        }
+    }
+
+    fn lump(data: &[u8]) -> (LumpId, Vec<u8>) {
+        (LumpId(*blake3::hash(data).as_bytes()), data.to_vec())
+    }
+
+    /// Creates both ends of a connection that closes once the returned
+    /// sender is dropped.
+    fn connection_pair() -> (Connection, Connection, Sender<()>) {
+        let (close_tx, close_rx) = flume::unbounded::<()>();
+        let wire = || {
+            let (tx, relay_rx) = flume::unbounded::<CapOperation>();
+            let (relay_tx, rx) = flume::unbounded();
+            let close_rx = close_rx.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        Ok(op) = relay_rx.recv_async() => {
+                            if relay_tx.send(op).is_err() {
+                                break;
+                            }
+                        }
+                        _ = close_rx.recv_async() => break,
+                    }
+                }
+            });
+
+            (tx, rx)
+        };
+
+        let connection = |(tx, rx): (Sender<CapOperation>, _)| Connection {
+            op_tx: tx.clone(),
+            bulk_tx: tx.clone(),
+            lossy_tx: tx,
+            op_rx: rx,
+            stats: Default::default(),
+        };
+
+        let (a_tx, b_rx) = wire();
+        let (b_tx, a_rx) = wire();
+        let a = connection((a_tx, a_rx));
+        let b = connection((b_tx, b_rx));
+        (a, b, close_tx)
+    }
+
+    /// Connects a client to a server exchange.
+    fn attach(
+        server: &Arc<LumpExchange>,
+        client: &Arc<LumpExchange>,
+    ) -> (Connection, Arc<LumpPeer>, Connection, Sender<()>) {
+        let (server_conn, client_conn, close) = connection_pair();
+        let addr = "127.0.0.1:1".parse().unwrap();
+        let (_, server_conn) = server.attach(server_conn, Side::Server, Some(addr));
+        let (client, client_conn) = client.attach(client_conn, Side::Client, None);
+        (server_conn, client, client_conn, close)
+    }
+
+    /// Sends a message that refers to some lumps and waits for it to arrive.
+    async fn refer(from: &Connection, to: &Connection, lumps: &[LumpId]) {
+        let data = encode(lumps);
+        let op = RemoteCapOperation::Send {
+            id: 0,
+            data,
+            caps: vec![],
+        };
+
+        from.op_tx.send(CapOperation::Remote(op)).unwrap();
+        to.op_rx.recv_async().await.unwrap();
+    }
+
+    #[test]
+    fn directory_withdraws_replaced_lumps() {
+        let directory = LumpDirectory::default();
+        let addr = "127.0.0.1:1234".parse().unwrap();
+        let (op_tx, _op_rx) = flume::unbounded();
+        let (a, _) = lump(b"a");
+        let (b, _) = lump(b"b");
+
+        let provider = |lumps| Provider {
+            addr,
+            lumps,
+            op_tx: op_tx.clone(),
+        };
+
+        directory.advertise(0, provider(vec![a]));
+        directory.advertise(0, provider(vec![b]));
+        assert!(directory.providers(&a).is_empty());
+        assert_eq!(directory.providers(&b), vec![addr]);
+
+        directory.withdraw(0);
+        assert!(directory.providers(&b).is_empty());
+    }
+
+    #[tokio::test]
+    async fn fetch_prefers_peers_and_falls_back_to_server() {
+        let (shared, shared_data) = lump(b"held by a peer");
+        let (fallback, fallback_data) = lump(b"held by the server");
+        let (secret, _) = lump(b"never referred to");
+
+        // the server has a corrupt copy of the shared lump
+        let server_source = MapSource(
+            [
+                (shared, b"bogus".to_vec()),
+                (fallback, fallback_data.clone()),
+                (secret, b"secret".to_vec()),
+            ]
+            .into(),
+        );
+        let directory = Arc::new(LumpDirectory::default());
+        let server = LumpExchange::with_directory(Arc::new(server_source), directory.clone());
+        let server = Arc::new(server);
+
+        let provider_source = MapSource([(shared, shared_data.clone())].into());
+        let provider = Arc::new(LumpExchange::new(Arc::new(provider_source)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(provider.clone().serve(listener));
+        let (_, advertiser, _, close_advertiser) = attach(&server, &provider);
+        advertiser.advertise(port, vec![shared]).unwrap();
+        while directory.providers(&shared).is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let fetcher = Arc::new(LumpExchange::new(Arc::new(MapSource(HashMap::new()))));
+        let (server_conn, client, client_conn, _close) = attach(&server, &fetcher);
+        refer(&server_conn, &client_conn, &[shared, fallback]).await;
+
+        assert_eq!(client.fetch(&shared).await.unwrap(), Some(shared_data));
+        assert_eq!(client.fetch(&fallback).await.unwrap(), Some(fallback_data));
+        assert_eq!(client.fetch(&secret).await.unwrap(), None);
+
+        // closing the advertiser's connection withdraws its lumps
+        drop(close_advertiser);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(directory.providers(&shared).is_empty());
+        assert_eq!(client.fetch(&shared).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn tickets_are_redeemed_once() {
+        let tickets = Tickets::default();
+        let (lump, _) = lump(b"lump");
+        tickets.expect([1; 32], [2; 32], lump);
+        assert_eq!(tickets.redeem(&[1; 32]).await, Some(([2; 32], lump)));
+        assert!(tickets.pending.lock().unwrap().is_empty());
+    }
+}
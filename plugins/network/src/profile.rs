@@ -0,0 +1,149 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Permission profiles for authenticated peers.
+//!
+//! Every user is assigned a [Profile] when they are registered. When a peer
+//! connects, its profile's [Grant] decides which of the server's exported
+//! services it can look up in the server's network root.
+//!
+//! Grants only filter lookups in the network root. Capabilities that a peer
+//! receives in other ways, such as from the replies of a service that it was
+//! granted, are not restricted by its profile, so the services granted to
+//! less-trusted profiles shouldn't hand out capabilities to the services
+//! denied to them.
+
+use serde::{Deserialize, Serialize};
+
+/// The permission profile of an authenticated peer.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Trusted operators of the server.
+    Admin,
+
+    /// Peers that are trusted to build in the space.
+    Builder,
+
+    /// Untrusted guests.
+    Visitor,
+}
+
+/// The exported services that a [Profile] can access.
+///
+/// A name is granted if it matches an `allow` pattern and doesn't match any
+/// `deny` pattern. A pattern ending in `*` matches every name starting with
+/// the rest of the pattern. The default grant allows nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Grant {
+    /// Patterns of the service names to allow.
+    pub allow: Vec<String>,
+
+    /// Patterns of the service names to deny, even if they are allowed.
+    pub deny: Vec<String>,
+}
+
+impl Grant {
+    /// A grant that allows every service.
+    pub fn all() -> Self {
+        Self {
+            allow: vec!["*".to_string()],
+            deny: vec![],
+        }
+    }
+
+    /// Returns true if this grant allows access to the named service.
+    pub fn allows(&self, name: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        };
+
+        self.allow.iter().any(matches) && !self.deny.iter().any(matches)
+    }
+}
+
+/// The grant of each [Profile], read from the `network.grants` table of the
+/// config file.
+///
+/// By default, admins and builders can access every exported service and
+/// visitors can access none of them.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Grants {
+    pub admin: Grant,
+    pub builder: Grant,
+    pub visitor: Grant,
+}
+
+impl Default for Grants {
+    fn default() -> Self {
+        Self {
+            admin: Grant::all(),
+            builder: Grant::all(),
+            visitor: Grant::default(),
+        }
+    }
+}
+
+impl Grants {
+    /// Gets the grant of a profile.
+    pub fn get(&self, profile: Profile) -> &Grant {
+        match profile {
+            Profile::Admin => &self.admin,
+            Profile::Builder => &self.builder,
+            Profile::Visitor => &self.visitor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grant_patterns() {
+        let grant = Grant {
+            allow: vec!["rs.hearth.*".to_string(), "hearth.UnixTime".to_string()],
+            deny: vec!["rs.hearth.kindling.Admin*".to_string()],
+        };
+
+        assert!(grant.allows("rs.hearth.kindling.Chat"));
+        assert!(grant.allows("hearth.UnixTime"));
+        assert!(!grant.allows("hearth.UnixTimeFactory"));
+        assert!(!grant.allows("rs.hearth.kindling.AdminPanel"));
+        assert!(!Grant::default().allows("rs.hearth.kindling.Chat"));
+        assert!(Grant::all().allows("rs.hearth.kindling.Chat"));
+    }
+
+    #[test]
+    fn partial_grants_keep_defaults() {
+        let grants: Grants = toml::from_str(
+            r#"
+            [visitor]
+            allow = ["rs.hearth.kindling.Chat"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(grants.admin, Grant::all());
+        assert!(grants.visitor.allows("rs.hearth.kindling.Chat"));
+        assert!(!grants.visitor.allows("rs.hearth.kindling.Avatars"));
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A [Transport] over QUIC.
+//!
+//! Each link is a single bidirectional QUIC stream plus the connection's
+//! datagram channel. Peers are authenticated by the password exchange that
+//! runs over the stream, not by TLS certificates, so servers use a fresh
+//! self-signed certificate and clients accept any certificate.
+//!
+//! Because clients accept any certificate, QUIC's TLS layer doesn't protect
+//! against an active attacker between the peers. This transport relies
+//! entirely on the connection's own ciphers, which are keyed by the password
+//! exchange. The stream is protected like it is on the other transports, and
+//! datagrams are sealed with ChaCha20-Poly1305 and checked against a replay
+//! window, so forged, modified, and replayed datagrams are dropped. Datagrams
+//! that arrive before the connection starts receiving them, which it only
+//! does once it has a session key, are dropped unread.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use futures_util::FutureExt;
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream, ServerConfig};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, PrivateKey, ServerName};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+
+use crate::transport::{Datagrams, Link, Listener, Transport};
+
+/// The server name used in the TLS handshake.
+const SERVER_NAME: &str = "hearth";
+
+/// How long to wait for a peer to finish the QUIC handshake and open its
+/// stream.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A transport over QUIC. Addresses are `host:port` pairs.
+pub struct QuicTransport;
+
+#[async_trait]
+impl Transport for QuicTransport {
+    async fn connect(&self, addr: &str) -> io::Result<Link> {
+        let remote = resolve(addr).await?;
+
+        let local: SocketAddr = if remote.is_ipv6() {
+            "[::]:0".parse().unwrap()
+        } else {
+            "0.0.0.0:0".parse().unwrap()
+        };
+
+        let mut endpoint = Endpoint::client(local)?;
+        endpoint.set_default_client_config(client_config());
+
+        let conn = endpoint
+            .connect(remote, SERVER_NAME)
+            .map_err(io::Error::other)?
+            .await
+            .map_err(io::Error::other)?;
+
+        let (send, recv) = conn.open_bi().await.map_err(io::Error::other)?;
+
+        Ok(make_link(conn, send, recv))
+    }
+
+    async fn listen(&self, addr: &str) -> io::Result<Box<dyn Listener>> {
+        let addr = resolve(addr).await?;
+        let endpoint = Endpoint::server(server_config()?, addr)?;
+        Ok(Box::new(QuicListener::new(endpoint)))
+    }
+}
+
+/// Accepts QUIC connections and finishes each handshake in its own task, so
+/// that slow peers don't hold up the others.
+struct QuicListener {
+    endpoint: Endpoint,
+    links_tx: mpsc::UnboundedSender<(Link, String)>,
+    links_rx: mpsc::UnboundedReceiver<(Link, String)>,
+}
+
+#[async_trait]
+impl Listener for QuicListener {
+    async fn accept(&mut self) -> io::Result<(Link, String)> {
+        loop {
+            tokio::select! {
+                connecting = self.endpoint.accept() => {
+                    let connecting = connecting
+                        .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
+                    self.spawn_handshake(connecting);
+                }
+                Some(link) = self.links_rx.recv() => return Ok(link),
+            }
+        }
+    }
+}
+
+impl QuicListener {
+    fn new(endpoint: Endpoint) -> Self {
+        let (links_tx, links_rx) = mpsc::unbounded_channel();
+
+        Self {
+            endpoint,
+            links_tx,
+            links_rx,
+        }
+    }
+
+    /// Spawns a task that finishes an incoming connection's handshake and
+    /// queues its link to be accepted.
+    fn spawn_handshake(&self, connecting: quinn::Connecting) {
+        let addr = connecting.remote_address().to_string();
+        let links_tx = self.links_tx.clone();
+        tokio::spawn(async move {
+            let handshake = async {
+                let conn = connecting.await.map_err(io::Error::other)?;
+                let (send, recv) = conn.accept_bi().await.map_err(io::Error::other)?;
+                Ok::<_, io::Error>(make_link(conn, send, recv))
+            };
+
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                Ok(Ok(link)) => {
+                    let _ = links_tx.send((link, addr));
+                }
+                Ok(Err(err)) => tracing::debug!("QUIC handshake with {} failed: {:?}", addr, err),
+                Err(_) => tracing::debug!("QUIC handshake with {} timed out", addr),
+            }
+        });
+    }
+}
+
+/// The datagram channel of a QUIC connection.
+///
+/// Datagrams buffered before the first call to [Datagrams::recv] are
+/// dropped, since they arrived before the connection could decrypt them.
+struct QuicDatagrams {
+    conn: Connection,
+    receiving: AtomicBool,
+}
+
+#[async_trait]
+impl Datagrams for QuicDatagrams {
+    fn max_size(&self) -> Option<usize> {
+        self.conn.max_datagram_size()
+    }
+
+    fn send(&self, data: Vec<u8>) -> io::Result<()> {
+        self.conn
+            .send_datagram(data.into())
+            .map_err(io::Error::other)
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        if !self.receiving.swap(true, Ordering::Relaxed) {
+            while let Some(Ok(_)) = self.conn.read_datagram().now_or_never() {}
+        }
+
+        let data = self.conn.read_datagram().await.map_err(io::Error::other)?;
+        Ok(data.to_vec())
+    }
+}
+
+/// A bidirectional QUIC stream.
+struct QuicStream {
+    send: SendStream,
+    recv: RecvStream,
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.send).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_shutdown(cx)
+    }
+}
+
+fn make_link(conn: Connection, send: SendStream, recv: RecvStream) -> Link {
+    Link {
+        stream: Box::new(QuicStream { send, recv }),
+        datagrams: Some(Arc::new(QuicDatagrams {
+            conn,
+            receiving: AtomicBool::new(false),
+        })),
+    }
+}
+
+/// Resolves a `host:port` pair to its first socket address.
+async fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))
+}
+
+/// Creates a server config with a fresh self-signed certificate.
+fn server_config() -> io::Result<ServerConfig> {
+    let cert =
+        rcgen::generate_simple_self_signed(vec![SERVER_NAME.into()]).map_err(io::Error::other)?;
+    let cert_der = cert.serialize_der().map_err(io::Error::other)?;
+    let key_der = cert.serialize_private_key_der();
+
+    ServerConfig::with_single_cert(vec![Certificate(cert_der)], PrivateKey(key_der))
+        .map_err(io::Error::other)
+}
+
+/// Creates a client config that accepts any server certificate.
+fn client_config() -> ClientConfig {
+    let crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+
+    ClientConfig::new(Arc::new(crypto))
+}
+
+/// A certificate verifier that skips verification, since peers are
+/// authenticated by the password exchange instead.
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn stream_and_datagrams() {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let endpoint = Endpoint::server(server_config().unwrap(), addr).unwrap();
+        let addr = endpoint.local_addr().unwrap().to_string();
+        let mut listener = QuicListener::new(endpoint);
+        let server = tokio::spawn(async move { listener.accept().await.unwrap().0 });
+
+        // the server only sees the stream once the client writes to it
+        let mut client = QuicTransport.connect(&addr).await.unwrap();
+        client.stream.write_all(b"ping").await.unwrap();
+        client.stream.flush().await.unwrap();
+
+        let mut server = server.await.unwrap();
+        let mut buf = [0u8; 4];
+        server.stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        let client_datagrams = client.datagrams.unwrap();
+        let server_datagrams = server.datagrams.unwrap();
+        assert!(client_datagrams.max_size().is_some());
+
+        // datagrams from before the server starts receiving are dropped
+        client_datagrams.send(b"early".to_vec()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let received = tokio::spawn(async move { server_datagrams.recv().await.unwrap() });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        client_datagrams.send(b"pong".to_vec()).unwrap();
+        assert_eq!(received.await.unwrap(), b"pong");
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Per-connection traffic statistics.
+//!
+//! Servers periodically write a [StatsFile] into the data directory so that
+//! hearth-ctl can report on connected peers.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the statistics file within the data directory.
+pub const STATS_FILE: &str = "network-stats.json";
+
+/// Running totals of the traffic over a single connection.
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    ops_sent: AtomicU64,
+    ops_received: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// Reads the current totals.
+    pub fn totals(&self) -> Totals {
+        Totals {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            ops_sent: self.ops_sent.load(Ordering::Relaxed),
+            ops_received: self.ops_received.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn add_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_op_sent(&self) {
+        self.ops_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_op_received(&self) {
+        self.ops_received.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A snapshot of a connection's traffic totals.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Totals {
+    /// The number of bytes written to the transport, including framing.
+    pub bytes_sent: u64,
+
+    /// The number of bytes read from the transport, including framing.
+    pub bytes_received: u64,
+
+    /// The number of capability operations sent.
+    pub ops_sent: u64,
+
+    /// The number of capability operations received.
+    pub ops_received: u64,
+}
+
+/// The statistics of a single peer, as reported to hearth-ctl.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PeerStats {
+    /// The address of the peer.
+    pub address: String,
+
+    /// How many seconds the peer has been connected for.
+    pub connected_secs: u64,
+
+    /// The traffic totals of this connection.
+    pub totals: Totals,
+
+    /// The upload rate to this peer in bytes per second over the last sample.
+    pub send_rate: f64,
+
+    /// The download rate from this peer in bytes per second over the last
+    /// sample.
+    pub recv_rate: f64,
+}
+
+/// The contents of the [STATS_FILE].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StatsFile {
+    /// When this file was written, in seconds since the Unix epoch.
+    pub updated: u64,
+
+    /// All of the currently-connected peers.
+    pub peers: Vec<PeerStats>,
+}
+
+impl StatsFile {
+    /// The age of this file's contents.
+    pub fn age(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        now.saturating_sub(Duration::from_secs(self.updated))
+    }
+}
+
+struct Peer {
+    address: String,
+    connected: Instant,
+    stats: Weak<ConnectionStats>,
+    last: Totals,
+}
+
+/// Keeps track of the statistics of every open connection.
+///
+/// Connections are forgotten once their tasks have all exited.
+#[derive(Default)]
+pub struct PeerTracker {
+    peers: Mutex<Vec<Peer>>,
+}
+
+impl PeerTracker {
+    /// Starts tracking a connection.
+    pub fn add(&self, address: String, stats: &Arc<ConnectionStats>) {
+        self.peers.lock().unwrap().push(Peer {
+            address,
+            connected: Instant::now(),
+            stats: Arc::downgrade(stats),
+            last: Totals::default(),
+        });
+    }
+
+    /// Samples every open connection, computing throughput over the time
+    /// `elapsed` since the previous sample.
+    pub fn sample(&self, elapsed: Duration) -> StatsFile {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|peer| peer.stats.strong_count() > 0);
+
+        let peers = peers
+            .iter_mut()
+            .filter_map(|peer| {
+                let totals = peer.stats.upgrade()?.totals();
+                let sent = totals.bytes_sent - peer.last.bytes_sent;
+                let received = totals.bytes_received - peer.last.bytes_received;
+                peer.last = totals;
+
+                Some(PeerStats {
+                    address: peer.address.clone(),
+                    connected_secs: peer.connected.elapsed().as_secs(),
+                    totals,
+                    send_rate: sent as f64 / secs,
+                    recv_rate: received as f64 / secs,
+                })
+            })
+            .collect();
+
+        let updated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        StatsFile { updated, peers }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker_drops_closed_connections() {
+        let tracker = PeerTracker::default();
+        let stats = Arc::new(ConnectionStats::default());
+        tracker.add("peer".into(), &stats);
+
+        stats.add_sent(100);
+        stats.add_op_sent();
+        let sample = tracker.sample(Duration::from_secs(2));
+        assert_eq!(sample.peers.len(), 1);
+        assert_eq!(sample.peers[0].totals.ops_sent, 1);
+        assert_eq!(sample.peers[0].send_rate, 50.0);
+
+        // rates only cover traffic since the last sample
+        let sample = tracker.sample(Duration::from_secs(1));
+        assert_eq!(sample.peers[0].send_rate, 0.0);
+
+        drop(stats);
+        assert!(tracker.sample(Duration::from_secs(1)).peers.is_empty());
+    }
+}
@@ -0,0 +1,417 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Byte stream transports that network connections can run over.
+//!
+//! Authentication, encryption, and [crate::connection::Connection] only need
+//! a reliable, ordered byte stream, so each transport just has to provide a
+//! [Stream] to a peer. Transports that can also send unreliable datagrams
+//! provide [Datagrams] alongside the stream. New transports can be added by
+//! implementing [Transport] and [Listener] without touching the session logic.
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use async_trait::async_trait;
+use futures_util::{Sink, Stream as _};
+use tokio::io::{duplex, AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::quic::QuicTransport;
+use crate::webrtc::WebRtcTransport;
+
+/// A reliable, ordered, bidirectional byte stream to a peer.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+/// A boxed [Stream] from any transport.
+pub type BoxStream = Box<dyn Stream>;
+
+/// An unreliable channel of datagrams to a peer.
+///
+/// Datagrams may be dropped, duplicated, or arrive out of order.
+#[async_trait]
+pub trait Datagrams: Send + Sync {
+    /// Gets the largest datagram that can currently be sent, if datagrams
+    /// can be sent at all.
+    fn max_size(&self) -> Option<usize>;
+
+    /// Sends a datagram without waiting.
+    fn send(&self, data: Vec<u8>) -> io::Result<()>;
+
+    /// Waits for the next datagram from the peer.
+    async fn recv(&self) -> io::Result<Vec<u8>>;
+}
+
+/// A connection to a peer opened by a [Transport].
+pub struct Link {
+    /// The reliable stream to the peer.
+    pub stream: BoxStream,
+
+    /// The unreliable datagram channel to the peer, if the transport has one.
+    pub datagrams: Option<Arc<dyn Datagrams>>,
+}
+
+impl Link {
+    /// Creates a link with only a reliable stream.
+    pub fn reliable(stream: impl Stream) -> Self {
+        Self {
+            stream: Box::new(stream),
+            datagrams: None,
+        }
+    }
+}
+
+/// A way of opening links to peers and accepting links from them.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Opens a link to the peer at the given address.
+    async fn connect(&self, addr: &str) -> io::Result<Link>;
+
+    /// Starts accepting links from peers on the given address.
+    async fn listen(&self, addr: &str) -> io::Result<Box<dyn Listener>>;
+}
+
+/// Accepts incoming links for a [Transport].
+#[async_trait]
+pub trait Listener: Send {
+    /// Waits for the next incoming link.
+    ///
+    /// Returns the link and a human-readable description of the peer.
+    async fn accept(&mut self) -> io::Result<(Link, String)>;
+}
+
+/// The built-in transports, selectable from the command line.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransportKind {
+    /// Raw TCP.
+    #[default]
+    Tcp,
+
+    /// Binary WebSocket messages over TCP.
+    #[clap(name = "websocket", alias = "ws")]
+    WebSocket,
+
+    /// QUIC, with datagrams for lossy messages.
+    ///
+    /// Certificates aren't verified, so this relies entirely on the
+    /// connection's own encryption to protect its traffic.
+    Quic,
+
+    /// WebRTC data channels, signaled over a WebSocket, for browser peers.
+    #[clap(name = "webrtc")]
+    WebRtc,
+}
+
+impl TransportKind {
+    /// Creates an instance of this kind of transport.
+    pub fn transport(self) -> Box<dyn Transport> {
+        match self {
+            TransportKind::Tcp => Box::new(TcpTransport),
+            TransportKind::WebSocket => Box::new(WebSocketTransport),
+            TransportKind::Quic => Box::new(QuicTransport),
+            TransportKind::WebRtc => Box::new(WebRtcTransport::default()),
+        }
+    }
+}
+
+/// A transport over raw TCP. Addresses are `host:port` pairs.
+pub struct TcpTransport;
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn connect(&self, addr: &str) -> io::Result<Link> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Link::reliable(stream))
+    }
+
+    async fn listen(&self, addr: &str) -> io::Result<Box<dyn Listener>> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Box::new(TcpTransportListener(listener)))
+    }
+}
+
+struct TcpTransportListener(TcpListener);
+
+#[async_trait]
+impl Listener for TcpTransportListener {
+    async fn accept(&mut self) -> io::Result<(Link, String)> {
+        let (stream, addr) = self.0.accept().await?;
+        Ok((Link::reliable(stream), addr.to_string()))
+    }
+}
+
+/// A transport over WebSockets.
+///
+/// Addresses are either `ws://` URLs or `host:port` pairs. Listeners accept
+/// WebSocket connections on a plain TCP `host:port` address.
+pub struct WebSocketTransport;
+
+#[async_trait]
+impl Transport for WebSocketTransport {
+    async fn connect(&self, addr: &str) -> io::Result<Link> {
+        let url = if addr.contains("://") {
+            addr.to_string()
+        } else {
+            format!("ws://{addr}")
+        };
+
+        let (stream, _response) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(io::Error::other)?;
+
+        Ok(Link::reliable(WsStream::new(stream)))
+    }
+
+    async fn listen(&self, addr: &str) -> io::Result<Box<dyn Listener>> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Box::new(WebSocketListener(listener)))
+    }
+}
+
+struct WebSocketListener(TcpListener);
+
+#[async_trait]
+impl Listener for WebSocketListener {
+    async fn accept(&mut self) -> io::Result<(Link, String)> {
+        let (stream, addr) = self.0.accept().await?;
+
+        let stream = tokio_tungstenite::accept_async(stream)
+            .await
+            .map_err(io::Error::other)?;
+
+        Ok((Link::reliable(WsStream::new(stream)), addr.to_string()))
+    }
+}
+
+/// Adapts a [WebSocketStream] into a byte stream.
+///
+/// Writes are sent as binary messages and the payloads of received binary
+/// messages are read back in order. Other kinds of messages are skipped.
+pub struct WsStream<S> {
+    inner: WebSocketStream<S>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+}
+
+impl<S> WsStream<S> {
+    /// Wraps a WebSocket stream that has completed its handshake.
+    pub fn new(inner: WebSocketStream<S>) -> Self {
+        Self {
+            inner,
+            read_buf: Vec::new(),
+            read_pos: 0,
+        }
+    }
+}
+
+impl<S> AsyncRead for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if self.read_pos < self.read_buf.len() {
+                let remaining = &self.read_buf[self.read_pos..];
+                let len = remaining.len().min(buf.remaining());
+                buf.put_slice(&remaining[..len]);
+                self.read_pos += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            let message = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(message)) => message,
+                Some(Err(WsError::ConnectionClosed)) | None => return Poll::Ready(Ok(())),
+                Some(Err(err)) => return Poll::Ready(Err(io::Error::other(err))),
+            };
+
+            match message {
+                Message::Binary(data) => {
+                    self.read_buf = data;
+                    self.read_pos = 0;
+                }
+                Message::Close(_) => return Poll::Ready(Ok(())),
+                // pings are answered by tungstenite itself
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for WsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = Pin::new(&mut self.inner);
+        ready!(inner.as_mut().poll_ready(cx)).map_err(io::Error::other)?;
+
+        inner
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(io::Error::other)?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(io::Error::other)
+    }
+}
+
+/// The buffer size of each direction of a [DuplexTransport] stream.
+pub const DUPLEX_BUFFER_SIZE: usize = 64 * 1024;
+
+/// An in-process transport, mainly for testing.
+///
+/// Listeners are registered under arbitrary address strings and clones of
+/// the same transport can connect to them.
+#[derive(Clone, Default)]
+pub struct DuplexTransport {
+    listeners: Arc<Mutex<HashMap<String, UnboundedSender<DuplexStream>>>>,
+}
+
+impl DuplexTransport {
+    /// Creates a new in-process transport with no listeners.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Transport for DuplexTransport {
+    async fn connect(&self, addr: &str) -> io::Result<Link> {
+        let listeners = self.listeners.lock().unwrap();
+
+        let Some(listener) = listeners.get(addr) else {
+            return Err(io::ErrorKind::ConnectionRefused.into());
+        };
+
+        let (client, server) = duplex(DUPLEX_BUFFER_SIZE);
+
+        listener
+            .send(server)
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+
+        Ok(Link::reliable(client))
+    }
+
+    async fn listen(&self, addr: &str) -> io::Result<Box<dyn Listener>> {
+        let mut listeners = self.listeners.lock().unwrap();
+
+        if listeners.get(addr).is_some_and(|tx| !tx.is_closed()) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+
+        let (tx, rx) = unbounded_channel();
+        listeners.insert(addr.to_string(), tx);
+
+        Ok(Box::new(DuplexListener {
+            addr: addr.to_string(),
+            rx,
+        }))
+    }
+}
+
+struct DuplexListener {
+    addr: String,
+    rx: UnboundedReceiver<DuplexStream>,
+}
+
+#[async_trait]
+impl Listener for DuplexListener {
+    async fn accept(&mut self) -> io::Result<(Link, String)> {
+        let stream = self
+            .rx
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+        Ok((Link::reliable(stream), self.addr.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn duplex_connect() {
+        let transport = DuplexTransport::new();
+        let mut listener = transport.listen("peer").await.unwrap();
+        assert!(transport.listen("peer").await.is_err());
+        assert!(transport.connect("nobody").await.is_err());
+
+        let mut client = transport.connect("peer").await.unwrap().stream;
+        let (server, _) = listener.accept().await.unwrap();
+        let mut server = server.stream;
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test]
+    async fn websocket_bytes() {
+        let (client, server) = duplex(1024);
+
+        let (client, server) = tokio::join!(
+            tokio_tungstenite::client_async("ws://localhost/", client),
+            tokio_tungstenite::accept_async(server),
+        );
+
+        let mut client = WsStream::new(client.unwrap().0);
+        let mut server = WsStream::new(server.unwrap());
+
+        client.write_all(b"Hello, ").await.unwrap();
+        client.write_all(b"world!").await.unwrap();
+        client.flush().await.unwrap();
+
+        let mut buf = [0u8; 13];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"Hello, world!");
+
+        client.shutdown().await.unwrap();
+        assert_eq!(server.read(&mut buf).await.unwrap(), 0);
+    }
+}
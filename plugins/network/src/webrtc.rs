@@ -0,0 +1,554 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A [Transport] over WebRTC data channels, so that browsers can connect.
+//!
+//! Browsers can't open raw TCP or QUIC connections, but they can open WebRTC
+//! data channels. Peers exchange session descriptions over a WebSocket on
+//! the listener's address: the connecting peer sends its offer as a JSON text
+//! message, like `JSON.stringify(pc.localDescription)` in a browser, and the
+//! listener replies with its answer in the same format. Both descriptions
+//! are sent after ICE gathering completes so that no more signaling is needed
+//! and the WebSocket can be closed.
+//!
+//! The connecting peer opens a reliable, ordered data channel labeled
+//! [STREAM_LABEL] for the link's stream. It may also open an unordered data
+//! channel without retransmits labeled [DATAGRAM_LABEL] for the link's
+//! datagrams.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use webrtc::api::{setting_engine::SettingEngine, APIBuilder};
+use webrtc::data::data_channel::{DataChannel, PollDataChannel};
+use webrtc::data_channel::{data_channel_init::RTCDataChannelInit, RTCDataChannel};
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::{
+    configuration::RTCConfiguration, sdp::session_description::RTCSessionDescription,
+    RTCPeerConnection,
+};
+
+use crate::transport::{Datagrams, Link, Listener, Transport};
+
+/// The label of the data channel that carries a link's stream.
+pub const STREAM_LABEL: &str = "hearth";
+
+/// The label of the data channel that carries a link's datagrams.
+pub const DATAGRAM_LABEL: &str = "hearth-datagrams";
+
+/// How long to wait for a peer to finish signaling and open its channels.
+const SIGNAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest message written to a data channel.
+///
+/// Browsers only guarantee delivery of messages up to this size.
+const MAX_WRITE_SIZE: usize = 16 * 1024;
+
+/// The largest message that can be read from a data channel.
+const MAX_READ_SIZE: usize = 64 * 1024;
+
+/// The number of outgoing datagrams that can be queued before more are
+/// dropped.
+const DATAGRAM_QUEUE_SIZE: usize = 64;
+
+/// A transport over WebRTC data channels.
+///
+/// Addresses are the `ws://` URLs or `host:port` pairs of the WebSocket that
+/// signaling runs over. Listeners accept WebSockets on a plain TCP
+/// `host:port` address.
+#[derive(Clone, Debug, Default)]
+pub struct WebRtcTransport {
+    ice_servers: Vec<String>,
+}
+
+impl WebRtcTransport {
+    /// Creates a transport that uses the given STUN or TURN server URLs to
+    /// find routes to peers on other networks.
+    ///
+    /// Without any ICE servers, only peers that can reach each other's local
+    /// addresses can connect.
+    pub fn with_ice_servers(ice_servers: Vec<String>) -> Self {
+        Self { ice_servers }
+    }
+
+    /// Creates a peer connection that detaches its data channels so that
+    /// they can be used as byte streams.
+    async fn new_peer(&self) -> io::Result<Arc<RTCPeerConnection>> {
+        let mut settings = SettingEngine::default();
+        settings.detach_data_channels();
+
+        let api = APIBuilder::new().with_setting_engine(settings).build();
+
+        let ice_servers = match self.ice_servers.is_empty() {
+            true => vec![],
+            false => vec![RTCIceServer {
+                urls: self.ice_servers.clone(),
+                ..Default::default()
+            }],
+        };
+
+        let config = RTCConfiguration {
+            ice_servers,
+            ..Default::default()
+        };
+
+        let peer = api
+            .new_peer_connection(config)
+            .await
+            .map_err(io::Error::other)?;
+
+        Ok(Arc::new(peer))
+    }
+}
+
+#[async_trait]
+impl Transport for WebRtcTransport {
+    async fn connect(&self, addr: &str) -> io::Result<Link> {
+        let url = if addr.contains("://") {
+            addr.to_string()
+        } else {
+            format!("ws://{addr}")
+        };
+
+        let (mut ws, _response) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(io::Error::other)?;
+
+        let peer = self.new_peer().await?;
+
+        let stream = peer
+            .create_data_channel(STREAM_LABEL, None)
+            .await
+            .map_err(io::Error::other)?;
+
+        let datagram_init = RTCDataChannelInit {
+            ordered: Some(false),
+            max_retransmits: Some(0),
+            ..Default::default()
+        };
+
+        let datagrams = peer
+            .create_data_channel(DATAGRAM_LABEL, Some(datagram_init))
+            .await
+            .map_err(io::Error::other)?;
+
+        let stream = detach_on_open(stream);
+        let datagrams = detach_on_open(datagrams);
+
+        let signal = async {
+            let offer = peer.create_offer(None).await.map_err(io::Error::other)?;
+            let offer = gather(&peer, offer).await?;
+            send_description(&mut ws, &offer).await?;
+
+            let answer = recv_description(&mut ws).await?;
+            peer.set_remote_description(answer)
+                .await
+                .map_err(io::Error::other)?;
+
+            stream.await.map_err(|_| closed())
+        };
+
+        let stream = tokio::time::timeout(SIGNAL_TIMEOUT, signal)
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+        let _ = ws.close(None).await;
+
+        let datagrams = RtcDatagrams::spawn(async move { datagrams.await.ok() });
+        Ok(make_link(peer, stream, datagrams))
+    }
+
+    async fn listen(&self, addr: &str) -> io::Result<Box<dyn Listener>> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Box::new(WebRtcListener::new(self.clone(), listener)))
+    }
+}
+
+/// Accepts WebSockets and signals with each peer in its own task, so that
+/// slow peers don't hold up the others.
+struct WebRtcListener {
+    transport: WebRtcTransport,
+    listener: TcpListener,
+    links_tx: mpsc::UnboundedSender<(Link, String)>,
+    links_rx: mpsc::UnboundedReceiver<(Link, String)>,
+}
+
+#[async_trait]
+impl Listener for WebRtcListener {
+    async fn accept(&mut self) -> io::Result<(Link, String)> {
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (stream, addr) = accepted?;
+                    self.spawn_signal(stream, addr.to_string());
+                }
+                Some(link) = self.links_rx.recv() => return Ok(link),
+            }
+        }
+    }
+}
+
+impl WebRtcListener {
+    fn new(transport: WebRtcTransport, listener: TcpListener) -> Self {
+        let (links_tx, links_rx) = mpsc::unbounded_channel();
+
+        Self {
+            transport,
+            listener,
+            links_tx,
+            links_rx,
+        }
+    }
+
+    /// Spawns a task that signals with a peer over a newly-accepted socket
+    /// and queues its link to be accepted.
+    fn spawn_signal(&self, stream: TcpStream, addr: String) {
+        let transport = self.transport.clone();
+        let links_tx = self.links_tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(SIGNAL_TIMEOUT, signal(&transport, stream)).await {
+                Ok(Ok(link)) => {
+                    let _ = links_tx.send((link, addr));
+                }
+                Ok(Err(err)) => tracing::debug!("Failed to signal with {}: {:?}", addr, err),
+                Err(_) => tracing::debug!("Signaling with {} timed out", addr),
+            }
+        });
+    }
+}
+
+/// Accepts a WebSocket for signaling and answers the peer's offer over it.
+async fn signal(transport: &WebRtcTransport, stream: TcpStream) -> io::Result<Link> {
+    let mut ws = tokio_tungstenite::accept_async(stream)
+        .await
+        .map_err(io::Error::other)?;
+
+    let link = answer(transport, &mut ws).await?;
+    let _ = ws.close(None).await;
+    Ok(link)
+}
+
+/// Answers a peer's offer and waits for it to open its stream channel.
+async fn answer<S>(transport: &WebRtcTransport, ws: &mut WebSocketStream<S>) -> io::Result<Link>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let peer = transport.new_peer().await?;
+
+    let (channels_tx, mut channels_rx) = mpsc::unbounded_channel();
+    peer.on_data_channel(Box::new(move |channel: Arc<RTCDataChannel>| {
+        let label = channel.label().to_string();
+        let _ = channels_tx.send((label, detach_on_open(channel)));
+        Box::pin(async {})
+    }));
+
+    let offer = recv_description(ws).await?;
+    peer.set_remote_description(offer)
+        .await
+        .map_err(io::Error::other)?;
+
+    let answer = peer.create_answer(None).await.map_err(io::Error::other)?;
+    let answer = gather(&peer, answer).await?;
+    send_description(ws, &answer).await?;
+
+    let (datagrams_tx, datagrams_rx) = oneshot::channel();
+    let mut datagrams_tx = Some(datagrams_tx);
+    let stream = loop {
+        let (label, opened) = channels_rx.recv().await.ok_or_else(closed)?;
+        match label.as_str() {
+            STREAM_LABEL => break opened.await.map_err(|_| closed())?,
+            DATAGRAM_LABEL => {
+                if let Some(tx) = datagrams_tx.take() {
+                    let _ = tx.send(opened);
+                }
+            }
+            _ => tracing::debug!("Ignoring unknown data channel {:?}", label),
+        }
+    };
+
+    // the datagram channel is optional and may be opened after the stream
+    let datagrams = RtcDatagrams::spawn(async move {
+        let opened = match datagrams_tx {
+            None => datagrams_rx.await.ok()?,
+            Some(_) => loop {
+                let (label, opened) = channels_rx.recv().await?;
+                if label == DATAGRAM_LABEL {
+                    break opened;
+                }
+            },
+        };
+
+        opened.await.ok()
+    });
+
+    Ok(make_link(peer, stream, datagrams))
+}
+
+/// Creates a link from a peer connection's open channels.
+fn make_link(
+    peer: Arc<RTCPeerConnection>,
+    stream: Arc<DataChannel>,
+    datagrams: Arc<RtcDatagrams>,
+) -> Link {
+    let mut inner = PollDataChannel::new(stream);
+    inner.set_read_buf_capacity(MAX_READ_SIZE);
+
+    Link {
+        stream: Box::new(RtcStream { inner, peer }),
+        datagrams: Some(datagrams),
+    }
+}
+
+/// The error for a peer connection that closed while setting up a link.
+fn closed() -> io::Error {
+    io::ErrorKind::ConnectionAborted.into()
+}
+
+/// Detaches a data channel once it opens.
+fn detach_on_open(channel: Arc<RTCDataChannel>) -> oneshot::Receiver<Arc<DataChannel>> {
+    let (tx, rx) = oneshot::channel();
+    let detached = channel.clone();
+    channel.on_open(Box::new(move || {
+        Box::pin(async move {
+            match detached.detach().await {
+                Ok(channel) => {
+                    let _ = tx.send(channel);
+                }
+                Err(err) => tracing::debug!("Failed to detach data channel: {:?}", err),
+            }
+        })
+    }));
+
+    rx
+}
+
+/// Sets a local description and waits for ICE gathering to complete.
+///
+/// Returns the local description with every gathered candidate.
+async fn gather(
+    peer: &RTCPeerConnection,
+    description: RTCSessionDescription,
+) -> io::Result<RTCSessionDescription> {
+    let mut gathered = peer.gathering_complete_promise().await;
+    peer.set_local_description(description)
+        .await
+        .map_err(io::Error::other)?;
+
+    let _ = gathered.recv().await;
+    peer.local_description().await.ok_or_else(closed)
+}
+
+/// Sends a session description as a JSON text message.
+async fn send_description<S>(
+    ws: &mut WebSocketStream<S>,
+    description: &RTCSessionDescription,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let json = serde_json::to_string(description).map_err(io::Error::other)?;
+    ws.send(Message::Text(json)).await.map_err(io::Error::other)
+}
+
+/// Receives a session description sent as a JSON text message.
+async fn recv_description<S>(ws: &mut WebSocketStream<S>) -> io::Result<RTCSessionDescription>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let message = ws
+            .next()
+            .await
+            .ok_or_else(closed)?
+            .map_err(io::Error::other)?;
+
+        match message {
+            Message::Text(json) => {
+                return serde_json::from_str(&json)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            Message::Close(_) => return Err(closed()),
+            // pings are answered by tungstenite itself
+            _ => {}
+        }
+    }
+}
+
+/// A reliable, ordered data channel.
+///
+/// Closes its peer connection when dropped.
+struct RtcStream {
+    inner: PollDataChannel,
+    peer: Arc<RTCPeerConnection>,
+}
+
+impl Drop for RtcStream {
+    fn drop(&mut self) {
+        let peer = self.peer.clone();
+        tokio::spawn(async move {
+            let _ = peer.close().await;
+        });
+    }
+}
+
+impl AsyncRead for RtcStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for RtcStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // each write is sent as a message, so split up large writes
+        let len = buf.len().min(MAX_WRITE_SIZE);
+        Pin::new(&mut self.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// An unordered data channel without retransmits.
+///
+/// The channel may open after the link is created. Until it does,
+/// datagrams can't be sent and [Datagrams::recv] waits for it.
+struct RtcDatagrams {
+    channel: watch::Receiver<Option<Arc<DataChannel>>>,
+    queue: mpsc::Sender<Vec<u8>>,
+}
+
+impl RtcDatagrams {
+    /// Spawns a task that writes queued datagrams once the channel opens.
+    fn spawn(opened: impl Future<Output = Option<Arc<DataChannel>>> + Send + 'static) -> Arc<Self> {
+        let (channel_tx, channel) = watch::channel(None);
+        let (queue, mut queue_rx) = mpsc::channel::<Vec<u8>>(DATAGRAM_QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            let Some(opened) = opened.await else {
+                return;
+            };
+
+            let _ = channel_tx.send(Some(opened.clone()));
+
+            while let Some(datagram) = queue_rx.recv().await {
+                if opened.write(&Bytes::from(datagram)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Arc::new(Self { channel, queue })
+    }
+}
+
+#[async_trait]
+impl Datagrams for RtcDatagrams {
+    fn max_size(&self) -> Option<usize> {
+        self.channel.borrow().as_ref().map(|_| MAX_WRITE_SIZE)
+    }
+
+    fn send(&self, data: Vec<u8>) -> io::Result<()> {
+        if self.channel.borrow().is_none() {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+
+        self.queue
+            .try_send(data)
+            .map_err(|_| io::ErrorKind::WouldBlock.into())
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        let mut channel = self.channel.clone();
+        let channel = loop {
+            if let Some(channel) = channel.borrow_and_update().clone() {
+                break channel;
+            }
+
+            channel.changed().await.map_err(|_| closed())?;
+        };
+
+        let mut buf = vec![0; MAX_READ_SIZE];
+        let len = channel.read(&mut buf).await.map_err(io::Error::other)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn stream_and_datagrams() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let mut listener = WebRtcListener::new(WebRtcTransport::default(), listener);
+
+        // a peer that never signals doesn't hold up the others
+        let _stalled = TcpStream::connect(&addr).await.unwrap();
+
+        let server = tokio::spawn(async move { listener.accept().await.unwrap().0 });
+        let mut client = WebRtcTransport::default().connect(&addr).await.unwrap();
+        let mut server = server.await.unwrap();
+
+        client.stream.write_all(b"ping").await.unwrap();
+        client.stream.flush().await.unwrap();
+
+        let mut buf = [0u8; 4];
+        server.stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // the datagram channel may open after the stream
+        let client_datagrams = client.datagrams.unwrap();
+        let server_datagrams = server.datagrams.unwrap();
+        while client_datagrams.max_size().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        client_datagrams.send(b"pong".to_vec()).unwrap();
+        assert_eq!(server_datagrams.recv().await.unwrap(), b"pong");
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Desktop notifications through the operating system.
+
+use hearth_runtime::{
+    anyhow::{self, Context},
+    async_trait,
+    flue::Table,
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::notify::*,
+    tokio,
+    tracing::{debug, warn},
+    utils::*,
+};
+
+/// The native OS notification service. Accepts [Notification].
+///
+/// Guests usually post notifications to the in-world notifier, which
+/// forwards them here when the user isn't looking at the Hearth window.
+#[derive(Default, GetProcessMetadata)]
+pub struct OsNotifyService;
+
+#[async_trait]
+impl SinkProcess for OsNotifyService {
+    type Message = Notification;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let action = message.caps.first().map(|cap| cap.to_owned());
+        let wait_for_action = action.is_some();
+        let post = message.runtime.post.to_owned();
+        let codec = message.codec;
+        let notification = message.data;
+
+        // showing notifications and waiting for their actions blocks, so do
+        // it in the background to keep receiving notifications
+        tokio::spawn(async move {
+            let shown = tokio::task::spawn_blocking(move || show(notification, wait_for_action));
+
+            let activated = match shown.await {
+                Ok(Ok(activated)) => activated,
+                Ok(Err(err)) => {
+                    warn!("failed to show OS notification: {:?}", err);
+                    return;
+                }
+                Err(err) => {
+                    warn!("OS notification task panicked: {:?}", err);
+                    return;
+                }
+            };
+
+            let Some(action) = action.filter(|_| activated) else {
+                return;
+            };
+
+            let table = Table::new(post);
+            let Ok(action) = table.import_owned(action) else {
+                return;
+            };
+
+            let data = codec.encode(&NotificationAction::Activated);
+            if let Err(err) = table.send(action, &data, &[]).await {
+                debug!("notification action is unavailable: {:?}", err);
+            }
+        });
+    }
+}
+
+impl ServiceRunner for OsNotifyService {
+    const NAME: &'static str = OS_SERVICE_NAME;
+}
+
+/// Shows a notification, optionally waiting for the user to activate it.
+///
+/// Returns true if the notification was activated. Actions are only
+/// supported on XDG platforms, so this never returns true elsewhere.
+fn show(notification: Notification, wait_for_action: bool) -> anyhow::Result<bool> {
+    let mut os = notify_rust::Notification::new();
+    os.appname("Hearth")
+        .summary(&notification.title)
+        .body(&notification.body);
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use notify_rust::Urgency as OsUrgency;
+
+        os.urgency(match notification.urgency {
+            Urgency::Low => OsUrgency::Low,
+            Urgency::Normal => OsUrgency::Normal,
+            Urgency::Critical => OsUrgency::Critical,
+        });
+
+        if wait_for_action {
+            os.action("default", "Open");
+        }
+
+        let handle = os.show().context("showing notification")?;
+
+        let mut activated = false;
+        if wait_for_action {
+            handle.wait_for_action(|action| activated = action == "default");
+        }
+
+        Ok(activated)
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = wait_for_action;
+        os.show().context("showing notification")?;
+        Ok(false)
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+struct VertexOut {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+struct BloomUniform {
+    // the size of a texel in the half-resolution bloom targets
+    texel: vec2<f32>;
+    threshold: f32;
+    intensity: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> bloom: BloomUniform;
+[[group(0), binding(1)]] var input_t: texture_2d<f32>;
+[[group(0), binding(2)]] var input_s: sampler;
+
+// draws a single triangle that covers the whole target
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+
+    var out: VertexOut;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+
+    return out;
+}
+
+// downsamples the scene and keeps only the parts brighter than the threshold
+[[stage(fragment)]]
+fn fs_prefilter(frag: VertexOut) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(input_t, input_s, frag.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - bloom.threshold, 0.0) / max(brightness, 1e-5);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// a 9-tap Gaussian blur using linear filtering to sample two texels at once
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let offset1 = direction * 1.3846153846;
+    let offset2 = direction * 3.2307692308;
+
+    var color = textureSample(input_t, input_s, uv).rgb * 0.2270270270;
+    color = color + textureSample(input_t, input_s, uv + offset1).rgb * 0.3162162162;
+    color = color + textureSample(input_t, input_s, uv - offset1).rgb * 0.3162162162;
+    color = color + textureSample(input_t, input_s, uv + offset2).rgb * 0.0702702703;
+    color = color + textureSample(input_t, input_s, uv - offset2).rgb * 0.0702702703;
+
+    return vec4<f32>(color, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_blur_h(frag: VertexOut) -> [[location(0)]] vec4<f32> {
+    return blur(frag.uv, vec2<f32>(bloom.texel.x, 0.0));
+}
+
+[[stage(fragment)]]
+fn fs_blur_v(frag: VertexOut) -> [[location(0)]] vec4<f32> {
+    return blur(frag.uv, vec2<f32>(0.0, bloom.texel.y));
+}
+
+// upsamples the blurred highlights to be added onto the scene
+[[stage(fragment)]]
+fn fs_composite(frag: VertexOut) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(input_t, input_s, frag.uv).rgb;
+    return vec4<f32>(color * bloom.intensity, 0.0);
+}
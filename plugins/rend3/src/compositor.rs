@@ -0,0 +1,413 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Full-screen effects drawn over the main window and tweened over time.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use hearth_runtime::hearth_schema::renderer::VignetteSettings;
+use rend3::graph::{RenderGraph, RenderPassTarget, RenderPassTargets};
+use wgpu::{util::DeviceExt, *};
+
+/// A value that can be linearly interpolated.
+trait Lerp: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for Vec4 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Vec4::lerp(self, other, t)
+    }
+}
+
+impl Lerp for Mat4 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self * (1.0 - t) + other * t
+    }
+}
+
+/// A transition from one value to another.
+#[derive(Clone, Copy, Debug)]
+struct Tween<T> {
+    from: T,
+    to: T,
+    start: Instant,
+    duration: Duration,
+}
+
+impl<T: Lerp> Tween<T> {
+    /// Creates a tween that is settled on a value.
+    fn new(value: T, now: Instant) -> Self {
+        Self {
+            from: value,
+            to: value,
+            start: now,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Gets the value of this tween at a point in time. Transitions are
+    /// eased in and out.
+    fn sample(&self, now: Instant) -> T {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return self.to;
+        }
+
+        let t = elapsed.as_secs_f32() / self.duration.as_secs_f32();
+        let t = t * t * (3.0 - 2.0 * t);
+        self.from.lerp(self.to, t)
+    }
+
+    /// Starts a transition from this tween's current value to a new one.
+    fn retarget(&mut self, to: T, duration: Duration, now: Instant) {
+        self.from = self.sample(now);
+        self.to = to;
+        self.start = now;
+        self.duration = duration;
+    }
+}
+
+/// The current targets of every compositor effect.
+struct Effects {
+    /// The fade's linear RGB color and amount.
+    fade: Tween<Vec4>,
+
+    /// The vignette's intensity, radius, and softness.
+    vignette: Tween<Vec4>,
+
+    /// The main view's color filter matrix.
+    filter: Tween<Mat4>,
+}
+
+impl Effects {
+    fn new(now: Instant) -> Self {
+        let vignette = VignetteSettings::default();
+
+        Self {
+            fade: Tween::new(Vec4::ZERO, now),
+            vignette: Tween::new(Vec4::new(0.0, vignette.radius, vignette.softness, 0.0), now),
+            filter: Tween::new(Mat4::IDENTITY, now),
+        }
+    }
+}
+
+/// The values of every compositor effect in a single frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct CompositorFrame {
+    pub fade: Vec4,
+    pub vignette: Vec4,
+    pub filter: Mat4,
+}
+
+impl CompositorFrame {
+    /// Tests if the fade or the vignette cover any of the window.
+    pub fn has_overlay(&self) -> bool {
+        self.fade.w > 0.0 || self.vignette.x > 0.0
+    }
+
+    /// Gets the color filter, if it has any effect.
+    pub fn filter(&self) -> Option<Mat4> {
+        if self.filter.abs_diff_eq(Mat4::IDENTITY, 1e-4) {
+            None
+        } else {
+            Some(self.filter)
+        }
+    }
+}
+
+/// Full-screen effects applied to the main window.
+///
+/// Fades and vignettes are drawn over everything else in the window,
+/// including routines' overlays. The color filter is applied to the main view
+/// along with its other post-processing effects.
+///
+/// Effects are shared with the renderer and may be changed from any thread.
+/// They are kept when the renderer recovers from a lost GPU device.
+pub struct Compositor {
+    effects: Mutex<Effects>,
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self {
+            effects: Mutex::new(Effects::new(Instant::now())),
+        }
+    }
+}
+
+impl Compositor {
+    /// Fades the window towards a linear RGB color by an amount between 0
+    /// and 1.
+    pub fn set_fade(&self, color: Vec3, amount: f32, duration: Duration) {
+        let now = Instant::now();
+        let mut effects = self.effects.lock().unwrap();
+        let fade = &mut effects.fade;
+
+        // fading in from nothing shouldn't blend from the last fade's color
+        let current = fade.sample(now);
+        if current.w <= 0.0 {
+            *fade = Tween::new(color.extend(0.0), now);
+        }
+
+        fade.retarget(color.extend(amount.clamp(0.0, 1.0)), duration, now);
+    }
+
+    /// Sets or disables the window's vignette.
+    pub fn set_vignette(&self, settings: Option<VignetteSettings>, duration: Duration) {
+        let now = Instant::now();
+        let mut effects = self.effects.lock().unwrap();
+        let vignette = &mut effects.vignette;
+
+        // disabling the vignette only fades its intensity out
+        let current = vignette.sample(now);
+        let target = match settings {
+            Some(settings) => Vec4::new(
+                settings.intensity.clamp(0.0, 1.0),
+                settings.radius,
+                settings.softness,
+                0.0,
+            ),
+            None => Vec4::new(0.0, current.y, current.z, 0.0),
+        };
+
+        // and enabling it shouldn't grow it from the last vignette's shape
+        if current.x <= 0.0 {
+            *vignette = Tween::new(Vec4::new(0.0, target.y, target.z, 0.0), now);
+        }
+
+        vignette.retarget(target, duration, now);
+    }
+
+    /// Sets the main view's color filter matrix.
+    pub fn set_color_filter(&self, filter: Mat4, duration: Duration) {
+        let now = Instant::now();
+        let mut effects = self.effects.lock().unwrap();
+        effects.filter.retarget(filter, duration, now);
+    }
+
+    /// Disables every effect.
+    pub fn reset(&self, duration: Duration) {
+        self.set_fade(Vec3::ZERO, 0.0, duration);
+        self.set_vignette(None, duration);
+        self.set_color_filter(Mat4::IDENTITY, duration);
+    }
+
+    /// Samples the current values of every effect.
+    pub(crate) fn sample(&self) -> CompositorFrame {
+        let now = Instant::now();
+        let effects = self.effects.lock().unwrap();
+
+        CompositorFrame {
+            fade: effects.fade.sample(now),
+            vignette: effects.vignette.sample(now),
+            filter: effects.filter.sample(now),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct OverlayUniform {
+    fade: [f32; 4],
+    vignette: [f32; 4],
+}
+
+/// Draws the [Compositor]'s fade and vignette over the window.
+pub(crate) struct CompositorOverlay {
+    queue: Arc<Queue>,
+    ubo: Buffer,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl CompositorOverlay {
+    /// Creates an overlay that draws onto surfaces of the given format.
+    pub fn new(device: &Device, queue: Arc<Queue>, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(&include_wgsl!("compositor.wgsl"));
+
+        let bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("compositor bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let ubo = device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("compositor uniform"),
+            contents: bytemuck::bytes_of(&OverlayUniform::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("compositor bind group"),
+            layout: &bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: ubo.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("compositor pipeline layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+
+        // the shader outputs premultiplied colors so that the vignette can
+        // darken the window while the fade covers it
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("compositor pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: ColorWrites::COLOR,
+                }],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            queue,
+            ubo,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Adds a node to a graph that draws a frame's fade and vignette over
+    /// the surface, if either is visible.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        frame: &CompositorFrame,
+    ) {
+        if !frame.has_overlay() {
+            return;
+        }
+
+        let uniform = OverlayUniform {
+            fade: frame.fade.to_array(),
+            vignette: frame.vignette.to_array(),
+        };
+
+        self.queue
+            .write_buffer(&self.ubo, 0, bytemuck::bytes_of(&uniform));
+
+        let output = graph.add_surface_texture();
+        let mut builder = graph.add_node("compositor");
+        let output_handle = builder.add_render_target_output(output);
+
+        let rpass_handle = builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: output_handle,
+                clear: Color::BLACK,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        let this = builder.passthrough_ref(self);
+
+        builder.build(
+            move |pt, _renderer, encoder_or_pass, _temps, _ready, _graph_data| {
+                let this = pt.get(this);
+                let rpass = encoder_or_pass.get_rpass(rpass_handle);
+                rpass.set_pipeline(&this.pipeline);
+                rpass.set_bind_group(0, &this.bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tweens_ease_between_values() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut tween = Tween::new(Vec4::ZERO, start);
+        tween.retarget(Vec4::ONE, second, start);
+
+        assert_eq!(tween.sample(start), Vec4::ZERO);
+        assert_eq!(tween.sample(start + second / 2), Vec4::splat(0.5));
+        assert!(tween.sample(start + second / 4).x < 0.25);
+        assert_eq!(tween.sample(start + second), Vec4::ONE);
+        assert_eq!(tween.sample(start + second * 2), Vec4::ONE);
+    }
+
+    #[test]
+    fn retargeting_starts_from_current_value() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut tween = Tween::new(Vec4::ZERO, start);
+        tween.retarget(Vec4::ONE, second, start);
+        tween.retarget(Vec4::ZERO, second, start + second / 2);
+
+        assert_eq!(tween.sample(start + second / 2), Vec4::splat(0.5));
+        assert_eq!(tween.sample(start + second * 3 / 2), Vec4::ZERO);
+    }
+
+    #[test]
+    fn zero_duration_is_immediate() {
+        let start = Instant::now();
+        let mut tween = Tween::new(Mat4::IDENTITY, start);
+        tween.retarget(Mat4::ZERO, Duration::ZERO, start);
+        assert_eq!(tween.sample(start), Mat4::ZERO);
+    }
+
+    #[test]
+    fn identity_filter_is_disabled() {
+        let mut frame = CompositorFrame {
+            fade: Vec4::ZERO,
+            vignette: Vec4::ZERO,
+            filter: Mat4::IDENTITY,
+        };
+
+        assert!(!frame.has_overlay());
+        assert_eq!(frame.filter(), None);
+
+        frame.filter = Mat4::from_diagonal(Vec4::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(frame.filter(), Some(frame.filter));
+    }
+}
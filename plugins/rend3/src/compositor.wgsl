@@ -0,0 +1,70 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+struct VertexOut {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+struct CompositorUniform {
+    // linear RGB color and amount of the fade
+    fade: vec4<f32>;
+
+    // intensity, radius, and softness; intensity is 0.0 if disabled
+    vignette: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> compositor: CompositorUniform;
+
+// draws a single triangle that covers the whole target
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+
+    var out: VertexOut;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+
+    return out;
+}
+
+// this version of wgpu's WGSL doesn't support built-in smoothstep()
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+    let t = clamp((x - low) / (high - low), 0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+// outputs a premultiplied color: the vignette only darkens the window, and
+// the fade is drawn over the darkened window
+[[stage(fragment)]]
+fn fs_main(frag: VertexOut) -> [[location(0)]] vec4<f32> {
+    let intensity = compositor.vignette.x;
+    let radius = compositor.vignette.y;
+    let softness = compositor.vignette.z;
+
+    var darkening = 0.0;
+    if (intensity > 0.0) {
+        // 0.0 at the center and 1.0 at the corners
+        let dist = length(frag.uv - vec2<f32>(0.5)) * 1.41421356;
+        darkening = intensity * smoothstep(radius, radius + softness, dist);
+    }
+
+    let fade = compositor.fade.a;
+    let coverage = 1.0 - (1.0 - darkening) * (1.0 - fade);
+    return vec4<f32>(compositor.fade.rgb * fade, coverage);
+}
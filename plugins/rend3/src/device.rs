@@ -0,0 +1,150 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! GPU device loss detection and recovery.
+//!
+//! wgpu reports a lost device either as an uncaptured error from any device
+//! operation or as a panic from a queue submission. [DeviceMonitor] catches
+//! the former and [is_loss_panic] recognizes the latter, so that
+//! [crate::Rend3Plugin] can re-create its renderer on a fresh device.
+
+use std::any::Any;
+use std::error::Error as StdError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use hearth_runtime::tracing::{error, warn};
+use rend3::InstanceAdapterDevice;
+use wgpu::{DeviceDescriptor, RequestDeviceError};
+
+/// A change in the state of the renderer's GPU device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// The device was lost.
+    Lost,
+
+    /// The renderer was re-created on a new device.
+    Restored,
+}
+
+/// Watches a device's uncaptured errors for device loss.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceMonitor {
+    lost: Arc<AtomicBool>,
+}
+
+impl DeviceMonitor {
+    /// Starts monitoring a device.
+    ///
+    /// This replaces the device's default error handler, which panics, with
+    /// one that logs errors that aren't device loss.
+    pub fn attach(device: &wgpu::Device) -> Self {
+        let monitor = Self::default();
+        let lost = monitor.lost.clone();
+        device.on_uncaptured_error(move |err| {
+            if is_loss_error(&err) {
+                if !lost.swap(true, Ordering::SeqCst) {
+                    warn!("GPU device lost: {}", err);
+                }
+            } else {
+                error!("Uncaptured wgpu error: {}", err);
+            }
+        });
+
+        monitor
+    }
+
+    /// Checks if the device has been lost.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::SeqCst)
+    }
+
+    /// Flags the device as lost.
+    pub fn set_lost(&self) {
+        self.lost.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Requests a new device and queue from a lost device's adapter with the
+/// same features and limits.
+pub async fn recreate_device(
+    iad: &InstanceAdapterDevice,
+) -> Result<InstanceAdapterDevice, RequestDeviceError> {
+    let descriptor = DeviceDescriptor {
+        label: None,
+        features: iad.device.features(),
+        limits: iad.device.limits(),
+    };
+
+    let (device, queue) = iad.adapter.request_device(&descriptor, None).await?;
+
+    let mut iad = iad.to_owned();
+    iad.device = Arc::new(device);
+    iad.queue = Arc::new(queue);
+    Ok(iad)
+}
+
+/// Checks if an uncaptured wgpu error was caused by device loss.
+pub fn is_loss_error(err: &wgpu::Error) -> bool {
+    if is_loss_message(&err.to_string()) {
+        return true;
+    }
+
+    let mut source = err.source();
+    while let Some(err) = source {
+        if is_loss_message(&err.to_string()) {
+            return true;
+        }
+
+        source = err.source();
+    }
+
+    false
+}
+
+/// Checks if a panic's payload reports device loss.
+pub fn is_loss_panic(payload: &(dyn Any + Send)) -> bool {
+    let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.as_str()
+    } else {
+        return false;
+    };
+
+    is_loss_message(msg)
+}
+
+fn is_loss_message(msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    msg.contains("device is lost") || msg.contains("device lost")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_loss_panics() {
+        let lost = "Error in Queue::submit: parent device is lost".to_string();
+        let oom = "Error in Queue::submit: not enough memory left";
+        assert!(is_loss_panic(&lost));
+        assert!(!is_loss_panic(&oom));
+        assert!(!is_loss_panic(&42));
+    }
+}
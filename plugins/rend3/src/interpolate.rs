@@ -0,0 +1,231 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Smooths the motion of objects between transform updates.
+//!
+//! Guests that step their simulation at a fixed rate update object
+//! transforms less often than frames are drawn. Dynamic objects are moved
+//! from where they were drawn to their newest transform over the time that
+//! passed between their last two updates, so that motion looks continuous.
+
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use glam::{Mat4, Quat, Vec3};
+use rend3::types::ObjectHandle;
+use rend3::Renderer;
+
+/// The longest gap between updates that is interpolated over. Objects that
+/// haven't moved for longer than this jump straight to their new transform.
+const MAX_PERIOD: Duration = Duration::from_millis(250);
+
+/// A transform decomposed into parts that can be interpolated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pose {
+    scale: Vec3,
+    rotation: Quat,
+    translation: Vec3,
+}
+
+impl From<Mat4> for Pose {
+    fn from(transform: Mat4) -> Self {
+        let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+
+        Self {
+            scale,
+            rotation,
+            translation,
+        }
+    }
+}
+
+impl From<Pose> for Mat4 {
+    fn from(pose: Pose) -> Self {
+        Mat4::from_scale_rotation_translation(pose.scale, pose.rotation, pose.translation)
+    }
+}
+
+impl Pose {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Self {
+            scale: self.scale.lerp(other.scale, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            translation: self.translation.lerp(other.translation, t),
+        }
+    }
+}
+
+/// The movement of an object from one pose to another.
+struct Motion {
+    from: Pose,
+    to: Pose,
+    start: Instant,
+    period: Duration,
+    settled: bool,
+}
+
+impl Motion {
+    /// Gets how far along this motion is at a point in time, from 0 to 1.
+    fn progress(&self, now: Instant) -> f32 {
+        if self.period.is_zero() {
+            return 1.0;
+        }
+
+        let elapsed = now.saturating_duration_since(self.start);
+        (elapsed.as_secs_f32() / self.period.as_secs_f32()).min(1.0)
+    }
+
+    /// Gets the pose of the object at a point in time.
+    fn sample(&self, now: Instant) -> Pose {
+        self.from.lerp(self.to, self.progress(now))
+    }
+
+    /// Starts moving to a new pose from wherever the object is now.
+    fn retarget(&mut self, to: Pose, now: Instant) {
+        let period = now.saturating_duration_since(self.start);
+        self.from = self.sample(now);
+        self.to = to;
+        self.start = now;
+        self.settled = false;
+
+        self.period = if period > MAX_PERIOD {
+            Duration::ZERO
+        } else {
+            period
+        };
+    }
+}
+
+/// A renderer object whose transform updates are interpolated.
+///
+/// The object stops being interpolated when this is dropped.
+pub struct InterpolatedObject {
+    handle: ObjectHandle,
+    motion: Mutex<Motion>,
+}
+
+impl InterpolatedObject {
+    /// Moves this object to a new transform over the time since its last
+    /// update.
+    pub fn set_transform(&self, transform: Mat4) {
+        let mut motion = self.motion.lock().unwrap();
+        motion.retarget(transform.into(), Instant::now());
+    }
+}
+
+/// Keeps track of every [InterpolatedObject] and moves them each frame.
+#[derive(Default)]
+pub struct Interpolator {
+    objects: Mutex<Vec<Weak<InterpolatedObject>>>,
+}
+
+impl Interpolator {
+    /// Starts interpolating an object at its current transform.
+    pub fn add(&self, handle: ObjectHandle, transform: Mat4) -> Arc<InterpolatedObject> {
+        let pose = transform.into();
+        let object = Arc::new(InterpolatedObject {
+            handle,
+            motion: Mutex::new(Motion {
+                from: pose,
+                to: pose,
+                start: Instant::now(),
+                period: Duration::ZERO,
+                settled: true,
+            }),
+        });
+
+        self.objects.lock().unwrap().push(Arc::downgrade(&object));
+        object
+    }
+
+    /// Stops moving every object, such as when their renderer is replaced.
+    pub fn clear(&self) {
+        self.objects.lock().unwrap().clear();
+    }
+
+    /// Updates the transforms of all moving objects for a frame drawn now.
+    pub fn apply(&self, renderer: &Renderer) {
+        let now = Instant::now();
+        let mut objects = self.objects.lock().unwrap();
+        objects.retain(|object| object.strong_count() > 0);
+
+        for object in objects.iter().filter_map(Weak::upgrade) {
+            let mut motion = object.motion.lock().unwrap();
+            if motion.settled {
+                continue;
+            }
+
+            motion.settled = motion.progress(now) >= 1.0;
+            let transform = motion.sample(now).into();
+            renderer.set_object_transform(&object.handle, transform);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pose(x: f32) -> Pose {
+        Mat4::from_translation(Vec3::new(x, 0.0, 0.0)).into()
+    }
+
+    fn motion(start: Instant) -> Motion {
+        Motion {
+            from: pose(0.0),
+            to: pose(0.0),
+            start,
+            period: Duration::ZERO,
+            settled: true,
+        }
+    }
+
+    #[test]
+    fn interpolates_over_update_interval() {
+        let start = Instant::now();
+        let mut motion = motion(start);
+
+        let tick = Duration::from_millis(50);
+        motion.retarget(pose(1.0), start + tick);
+        assert_eq!(motion.sample(start + tick), pose(0.0));
+        assert_eq!(motion.sample(start + tick * 3 / 2).translation.x, 0.5);
+        assert_eq!(motion.sample(start + tick * 2), pose(1.0));
+        assert_eq!(motion.sample(start + tick * 5), pose(1.0));
+    }
+
+    #[test]
+    fn retarget_starts_from_current_pose() {
+        let start = Instant::now();
+        let mut motion = motion(start);
+
+        let tick = Duration::from_millis(50);
+        motion.retarget(pose(1.0), start + tick);
+        motion.retarget(pose(2.0), start + tick * 3 / 2);
+        assert_eq!(motion.sample(start + tick * 3 / 2).translation.x, 0.5);
+        assert_eq!(motion.sample(start + tick * 2).translation.x, 2.0);
+    }
+
+    #[test]
+    fn long_gaps_snap() {
+        let start = Instant::now();
+        let mut motion = motion(start);
+
+        motion.retarget(pose(1.0), start + MAX_PERIOD * 2);
+        assert_eq!(motion.sample(start + MAX_PERIOD * 2), pose(1.0));
+    }
+}
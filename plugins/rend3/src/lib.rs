@@ -16,38 +16,113 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
+use std::num::NonZeroU32;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 
 use glam::{UVec2, Vec4};
+use hearth_runtime::hearth_schema::profiler::FrameProfile;
+use hearth_runtime::hearth_schema::renderer::{PostProcessSettings, RenderSettings};
 use hearth_runtime::runtime::{Plugin, RuntimeBuilder};
-use rend3::graph::{ReadyData, RenderGraph};
-use rend3::types::{Camera, SampleCount, TextureHandle};
+use hearth_runtime::tracing::{error, info};
+use rend3::graph::{ReadyData, RenderGraph, RenderTargetDescriptor, RenderTargetHandle};
+use rend3::types::{Camera, MipmapCount, MipmapSource, SampleCount, Texture, TextureHandle};
 use rend3::util::output::OutputFrame;
 use rend3::{InstanceAdapterDevice, Renderer};
 use rend3_routine::base::{BaseRenderGraph, BaseRenderGraphIntermediateState};
 use rend3_routine::pbr::PbrRoutine;
 use rend3_routine::skybox::SkyboxRoutine;
 use rend3_routine::tonemapping::TonemappingRoutine;
-use tokio::sync::{mpsc, oneshot};
-use wgpu::TextureFormat;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::time::MissedTickBehavior;
+use wgpu::{Backend, CommandBuffer, Features, MapMode, TextureFormat, TextureUsages, TextureView};
 
 pub use rend3;
 pub use rend3_routine;
 pub use wgpu;
 
+pub mod compositor;
+pub mod device;
+pub mod interpolate;
+pub mod post;
+pub mod profiler;
 pub mod utils;
+pub mod viewport;
+
+use compositor::{Compositor, CompositorOverlay};
+use device::{DeviceEvent, DeviceMonitor};
+use interpolate::Interpolator;
+use post::{ColorLut, PostProcessor};
+use profiler::{gpu_timestamp, to_millis, CpuTimer, GpuTimer, Profiler};
+use viewport::{Viewport, ViewportCompositor};
+
+/// Command-line arguments for creating the rend3 renderer.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct Rend3Args {
+    /// Force the renderer to use the Vulkan graphics backend.
+    #[clap(long)]
+    pub force_vulkan: bool,
+
+    /// Time render passes on the GPU for the frame profiler. Fails to create
+    /// the renderer if the GPU doesn't support timestamp queries.
+    #[clap(long)]
+    pub gpu_timing: bool,
+}
+
+impl Rend3Args {
+    /// Gets the graphics backend to create the renderer with, if one is
+    /// required.
+    pub fn backend(&self) -> Option<Backend> {
+        self.force_vulkan.then_some(Backend::Vulkan)
+    }
+
+    /// Gets the optional GPU features to create the renderer with, if any
+    /// are required.
+    pub fn features(&self) -> Option<Features> {
+        self.gpu_timing.then_some(Features::TIMESTAMP_QUERY)
+    }
+}
 
 /// The info about a frame passed to [Routine::draw].
 pub struct RoutineInfo<'a, 'graph> {
     pub state: &'a BaseRenderGraphIntermediateState,
     pub sample_count: SampleCount,
     pub resolution: UVec2,
+
+    /// A depth target that matches the output surface's size and sample
+    /// count.
+    ///
+    /// This is the scene's depth buffer, unless MSAA or resolution scaling
+    /// are enabled, in which case this is a separate target and routines
+    /// drawing to the surface will not be occluded by the scene.
+    pub depth: RenderTargetHandle,
     pub ready_data: &'a ReadyData,
     pub graph: &'a mut RenderGraph<'graph>,
 }
 
+/// The minimum time between attempts to recover a lost GPU device.
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The texture format that a headless [Rend3Plugin] renders into.
+pub const HEADLESS_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
 pub trait Routine: Send + Sync + 'static {
     fn build_node(&mut self) -> Box<dyn Node<'_> + '_>;
+
+    /// Gets the name of this routine in frame profiles. Defaults to the name
+    /// of the routine's type.
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// Re-creates this routine's GPU resources on the plugin's new device
+    /// after the previous device was lost.
+    ///
+    /// Anything drawn on the lost device may be discarded, since guests are
+    /// notified to re-submit it.
+    fn recreate(&mut self, rend3: &Rend3Plugin);
 }
 
 pub trait Node<'a> {
@@ -76,6 +151,84 @@ pub enum Rend3Command {
 
     /// Updates the ambient lighting.
     SetAmbient(Vec4),
+
+    /// Updates the render settings.
+    SetRenderSettings(RenderSettings),
+
+    /// Starts rendering the scene into a [RenderTarget].
+    AddRenderTarget(Arc<RenderTarget>),
+
+    /// Starts drawing a [Viewport] over the main view.
+    AddViewport(Arc<Viewport>),
+
+    /// Replaces the main view's post-processing settings and color grading
+    /// LUT.
+    SetPostProcessing {
+        settings: PostProcessSettings,
+        lut: Option<Arc<ColorLut>>,
+    },
+}
+
+/// A texture that the scene is rendered into from its own camera.
+///
+/// Once added to the renderer with [Rend3Command::AddRenderTarget], the scene
+/// is rendered into the texture before every frame until this is dropped.
+/// Only the base scene is rendered; custom [Routine]s are not.
+pub struct RenderTarget {
+    /// The texture that the scene is rendered into.
+    pub texture: TextureHandle,
+
+    /// The size of the texture.
+    pub size: UVec2,
+
+    camera: Mutex<Camera>,
+    captures: Mutex<Vec<oneshot::Sender<Vec<u8>>>>,
+}
+
+impl RenderTarget {
+    /// Creates a new render target and its texture.
+    ///
+    /// The texture's format must match the format of the surface that the
+    /// scene is tonemapped to, which is [Rend3Plugin::surface_format].
+    pub fn new(renderer: &Renderer, format: TextureFormat, size: UVec2, camera: Camera) -> Self {
+        let texture = renderer.add_texture_2d(Texture {
+            label: Some("render target".into()),
+            data: vec![0; (size.x * size.y * 4) as usize],
+            format,
+            size,
+            mip_count: MipmapCount::ONE,
+            mip_source: MipmapSource::Uploaded,
+        });
+
+        Self {
+            texture,
+            size,
+            camera: Mutex::new(camera),
+            captures: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Updates the camera that the scene is rendered from.
+    pub fn set_camera(&self, camera: Camera) {
+        *self.camera.lock().unwrap() = camera;
+    }
+
+    /// Reads back this target's contents after it's next rendered.
+    ///
+    /// The receiver gets tightly-packed RGBA8 rows, or hangs up if the
+    /// contents couldn't be read.
+    pub fn capture(&self) -> oneshot::Receiver<Vec<u8>> {
+        let (tx, rx) = oneshot::channel();
+        self.captures.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+/// The renderer's half of a [RenderTarget].
+struct OffscreenTarget {
+    target: Weak<RenderTarget>,
+    texture: wgpu::Texture,
+    view: Arc<TextureView>,
 }
 
 /// A rend3 Hearth plugin for adding 3D rendering to a Hearth runtime.
@@ -93,18 +246,79 @@ pub struct Rend3Plugin {
     pub ambient: Vec4,
     pub frame_request_tx: mpsc::UnboundedSender<FrameRequest>,
     pub command_tx: mpsc::UnboundedSender<Rend3Command>,
+
+    /// Moves dynamic objects between their transform updates every frame.
+    pub interpolator: Arc<Interpolator>,
+
+    /// Full-screen effects drawn over the main window.
+    pub compositor: Arc<Compositor>,
+
+    /// The timings of recently drawn frames.
+    pub profiler: Arc<Profiler>,
+
+    settings: watch::Sender<RenderSettings>,
+    current_renderer: watch::Sender<Arc<Renderer>>,
+    device_events: broadcast::Sender<DeviceEvent>,
+    monitor: DeviceMonitor,
+    last_recovery: Option<Instant>,
     new_skybox: Option<TextureHandle>,
     frame_request_rx: mpsc::UnboundedReceiver<FrameRequest>,
     command_rx: mpsc::UnboundedReceiver<Rend3Command>,
     routines: Vec<Box<dyn Routine>>,
+    render_targets: Vec<OffscreenTarget>,
+    viewports: ViewportCompositor,
+    post: PostProcessor,
+    overlay: CompositorOverlay,
+    gpu_timer: Option<GpuTimer>,
+    frame_index: u64,
+    last_frame: Option<Instant>,
+    headless_interval: Option<Duration>,
 }
 
 impl Plugin for Rend3Plugin {
     fn finalize(mut self, _builder: &mut RuntimeBuilder) {
         tokio::spawn(async move {
-            while let Some(frame) = self.frame_request_rx.recv().await {
+            // headless renderers draw their offscreen views on a timer
+            // instead of when a window requests a frame
+            let mut ticks = self.headless_interval.map(|period| {
+                let mut ticks = tokio::time::interval(period);
+                ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                ticks
+            });
+
+            loop {
+                let frame = match ticks.as_mut() {
+                    Some(ticks) => tokio::select! {
+                        frame = self.frame_request_rx.recv() => frame,
+                        _ = ticks.tick() => {
+                            self.draw_headless_frame().await;
+                            continue;
+                        }
+                    },
+                    None => self.frame_request_rx.recv().await,
+                };
+
+                let Some(frame) = frame else {
+                    break;
+                };
+
+                // dropping the frame request tells the window that it's done
+                if self.monitor.is_lost() && !self.recover().await {
+                    continue;
+                }
+
                 self.flush_commands();
-                self.draw(frame);
+
+                // wgpu panics when submitting to a lost device
+                let result = catch_unwind(AssertUnwindSafe(|| self.draw(frame)));
+                if let Err(payload) = result {
+                    if !device::is_loss_panic(payload.as_ref()) {
+                        resume_unwind(payload);
+                    }
+
+                    error!("GPU device lost while drawing");
+                    self.monitor.set_lost();
+                }
             }
         });
     }
@@ -114,37 +328,109 @@ impl Rend3Plugin {
     /// Creates a new rend3 plugin from an existing [InstanceAdapterDevice] and
     /// the target window's texture format.
     pub fn new(iad: InstanceAdapterDevice, surface_format: TextureFormat) -> Self {
-        let handedness = rend3::types::Handedness::Right;
-        let renderer = Renderer::new(iad.to_owned(), handedness, None).unwrap();
-        let base_render_graph = BaseRenderGraph::new(&renderer);
-        let mut data_core = renderer.data_core.lock();
-        let interfaces = &base_render_graph.interfaces;
-        let pbr_routine = PbrRoutine::new(&renderer, &mut data_core, interfaces);
-        let tonemapping_routine = TonemappingRoutine::new(&renderer, interfaces, surface_format);
-        let skybox_routine = SkyboxRoutine::new(&renderer, interfaces);
-        drop(data_core);
+        let gpu = Gpu::new(iad, surface_format);
+        let viewports = ViewportCompositor::new(gpu.iad.device.clone(), surface_format);
+        let post = PostProcessor::new(
+            gpu.iad.device.clone(),
+            gpu.iad.queue.clone(),
+            surface_format,
+        );
+
+        let overlay =
+            CompositorOverlay::new(&gpu.iad.device, gpu.iad.queue.clone(), surface_format);
+
+        let gpu_timer = GpuTimer::new(&gpu.iad.device, &gpu.iad.queue);
+        let profiler = Arc::new(Profiler::default());
+        profiler.set_gpu_timing(gpu_timer.is_some());
 
+        let monitor = DeviceMonitor::attach(&gpu.iad.device);
         let (frame_request_tx, frame_request_rx) = mpsc::unbounded_channel();
         let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (settings, _) = watch::channel(RenderSettings::default());
+        let (current_renderer, _) = watch::channel(gpu.renderer.clone());
+        let (device_events, _) = broadcast::channel(16);
 
         Self {
-            iad,
+            iad: gpu.iad,
             surface_format,
-            renderer,
-            base_render_graph,
-            pbr_routine,
-            tonemapping_routine,
-            skybox_routine,
+            renderer: gpu.renderer,
+            base_render_graph: gpu.base_render_graph,
+            pbr_routine: gpu.pbr_routine,
+            tonemapping_routine: gpu.tonemapping_routine,
+            skybox_routine: gpu.skybox_routine,
             frame_request_tx,
             frame_request_rx,
             command_tx,
             command_rx,
+            interpolator: Default::default(),
+            compositor: Default::default(),
+            profiler,
+            settings,
+            current_renderer,
+            device_events,
+            monitor,
+            last_recovery: None,
             new_skybox: None,
             ambient: Vec4::ZERO,
             routines: Vec::new(),
+            render_targets: Vec::new(),
+            viewports,
+            post,
+            overlay,
+            gpu_timer,
+            frame_index: 0,
+            last_frame: None,
+            headless_interval: None,
         }
     }
 
+    /// Creates a new rend3 plugin without a window.
+    ///
+    /// Headless plugins render offscreen views, like [RenderTarget]s, on their
+    /// own `frame_rate` instead of when a window requests frames, so that
+    /// servers can render thumbnails, previews, and automated scene tests.
+    /// Custom [Routine]s are never drawn.
+    pub async fn new_headless(
+        args: &Rend3Args,
+        frame_rate: f32,
+    ) -> Result<Self, rend3::RendererInitializationError> {
+        let iad = rend3::create_iad(args.backend(), None, None, args.features()).await?;
+        let mut plugin = Self::new(iad, HEADLESS_FORMAT);
+        plugin.headless_interval = Some(Duration::from_secs_f32(1.0 / frame_rate.max(1.0)));
+        Ok(plugin)
+    }
+
+    /// Returns true if this plugin was created with [Self::new_headless].
+    pub fn is_headless(&self) -> bool {
+        self.headless_interval.is_some()
+    }
+
+    /// Replaces this plugin's [RenderSettings].
+    pub fn set_settings(&self, settings: RenderSettings) {
+        self.settings.send_replace(settings);
+    }
+
+    /// Subscribes to changes in this plugin's [RenderSettings].
+    ///
+    /// Settings that affect the output surface, like vsync, are applied by
+    /// the surface's owner through this receiver.
+    pub fn subscribe_settings(&self) -> watch::Receiver<RenderSettings> {
+        self.settings.subscribe()
+    }
+
+    /// Subscribes to the current [Renderer].
+    ///
+    /// The renderer is replaced when it is re-created after the GPU device is
+    /// lost. Resources from the old renderer are never drawn again.
+    pub fn subscribe_renderer(&self) -> watch::Receiver<Arc<Renderer>> {
+        self.current_renderer.subscribe()
+    }
+
+    /// Subscribes to [DeviceEvent]s.
+    pub fn subscribe_device_events(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.device_events.subscribe()
+    }
+
     /// Adds a new [Routine] to this plugin.
     pub fn add_routine(&mut self, routine: impl Routine) {
         self.routines.push(Box::new(routine));
@@ -161,33 +447,233 @@ impl Rend3Plugin {
                 SetAmbient(ambient) => {
                     self.ambient = ambient;
                 }
+                SetRenderSettings(settings) => {
+                    self.set_settings(settings);
+                }
+                AddRenderTarget(target) => {
+                    self.add_render_target(&target);
+                }
+                AddViewport(viewport) => {
+                    self.viewports.add(&viewport);
+                }
+                SetPostProcessing { settings, lut } => {
+                    self.post.set(settings, lut);
+                }
             }
         }
     }
 
-    /// Draws a frame in response to a [FrameRequest].
-    pub fn draw(&mut self, request: FrameRequest) {
-        let (cmd_bufs, ready) = self.renderer.ready();
+    /// Draws a frame of a headless plugin's offscreen views.
+    async fn draw_headless_frame(&mut self) {
+        if self.monitor.is_lost() && !self.recover().await {
+            return;
+        }
+
+        self.flush_commands();
+
+        // wgpu panics when submitting to a lost device
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            self.interpolator.apply(&self.renderer);
+            self.draw_render_targets();
+        }));
+
+        if let Err(payload) = result {
+            if !device::is_loss_panic(payload.as_ref()) {
+                resume_unwind(payload);
+            }
+
+            error!("GPU device lost while drawing headless frame");
+            self.monitor.set_lost();
+        }
+    }
+
+    /// Attempts to re-create the renderer on a new device after the current
+    /// device has been lost. Returns true if the renderer is ready to draw.
+    ///
+    /// Attempts are rate-limited by [RECOVERY_INTERVAL].
+    async fn recover(&mut self) -> bool {
+        match self.last_recovery {
+            None => {
+                let _ = self.device_events.send(DeviceEvent::Lost);
+            }
+            Some(last) if last.elapsed() < RECOVERY_INTERVAL => return false,
+            Some(_) => {}
+        }
+
+        self.last_recovery = Some(Instant::now());
+
+        let iad = match device::recreate_device(&self.iad).await {
+            Ok(iad) => iad,
+            Err(err) => {
+                error!("Failed to re-create GPU device: {:?}", err);
+                return false;
+            }
+        };
+
+        info!("Re-creating renderer on a new GPU device");
+        let gpu = Gpu::new(iad, self.surface_format);
+        self.monitor = DeviceMonitor::attach(&gpu.iad.device);
+        self.viewports = ViewportCompositor::new(gpu.iad.device.clone(), self.surface_format);
+        self.post = PostProcessor::new(
+            gpu.iad.device.clone(),
+            gpu.iad.queue.clone(),
+            self.surface_format,
+        );
+
+        self.overlay =
+            CompositorOverlay::new(&gpu.iad.device, gpu.iad.queue.clone(), self.surface_format);
+
+        self.gpu_timer = GpuTimer::new(&gpu.iad.device, &gpu.iad.queue);
+        self.profiler.set_gpu_timing(self.gpu_timer.is_some());
+
+        self.iad = gpu.iad;
+        self.renderer = gpu.renderer;
+        self.base_render_graph = gpu.base_render_graph;
+        self.pbr_routine = gpu.pbr_routine;
+        self.tonemapping_routine = gpu.tonemapping_routine;
+        self.skybox_routine = gpu.skybox_routine;
+
+        // everything else was created on the old device
+        self.new_skybox = None;
+
+        // hang up pending captures, since their targets are never drawn again
+        for offscreen in self.render_targets.drain(..) {
+            if let Some(target) = offscreen.target.upgrade() {
+                target.captures.lock().unwrap().clear();
+            }
+        }
+
+        self.interpolator.clear();
+
+        // drop commands that refer to the old renderer's resources
+        while self.command_rx.try_recv().is_ok() {}
+
+        let mut routines = std::mem::take(&mut self.routines);
+        for routine in routines.iter_mut() {
+            routine.recreate(self);
+        }
+
+        self.routines = routines;
+        self.last_recovery = None;
+        self.current_renderer.send_replace(self.renderer.clone());
+        let _ = self.device_events.send(DeviceEvent::Restored);
+        true
+    }
+
+    /// Creates the renderer-side texture for a [RenderTarget].
+    fn add_render_target(&mut self, target: &Arc<RenderTarget>) {
+        let texture = self.iad.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render target output"),
+            size: wgpu::Extent3d {
+                width: target.size.x,
+                height: target.size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.surface_format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        });
+
+        let view = texture.create_view(&Default::default());
+
+        self.render_targets.push(OffscreenTarget {
+            target: Arc::downgrade(target),
+            texture,
+            view: Arc::new(view),
+        });
+    }
+
+    /// Readies the renderer for a new graph and applies any skybox changes.
+    fn ready(&mut self) -> (Vec<CommandBuffer>, ReadyData) {
+        let ready = self.renderer.ready();
 
         if let Some(skybox) = self.new_skybox.take() {
             self.skybox_routine.set_background_texture(Some(skybox));
             self.skybox_routine.ready(&self.renderer);
         }
 
-        let aspect = request.resolution.as_vec2();
-        let aspect = aspect.x / aspect.y;
-        self.renderer.set_aspect_ratio(aspect);
-        self.renderer.set_camera_data(request.camera);
+        ready
+    }
 
-        let nodes: Vec<_> = self
-            .routines
-            .iter_mut()
-            .map(|routine| routine.build_node())
-            .collect();
+    /// Renders the scene into every live [RenderTarget].
+    fn draw_render_targets(&mut self) {
+        self.render_targets
+            .retain(|offscreen| offscreen.target.strong_count() > 0);
+
+        for idx in 0..self.render_targets.len() {
+            let Some(target) = self.render_targets[idx].target.upgrade() else {
+                continue;
+            };
+
+            let camera = *target.camera.lock().unwrap();
+            let view = self.render_targets[idx].view.clone();
+            self.draw_offscreen(camera, target.size, view);
+            let offscreen = &self.render_targets[idx];
+
+            // the render graph can only output to a texture it owns, so copy
+            // the output into the texture that materials sample from
+            let device = &self.iad.device;
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let data_core = self.renderer.data_core.lock();
+            let texture_manager = &data_core.d2_texture_manager;
+            let dst = texture_manager.get_internal(target.texture.get_raw());
+
+            encoder.copy_texture_to_texture(
+                offscreen.texture.as_image_copy(),
+                dst.texture.as_image_copy(),
+                wgpu::Extent3d {
+                    width: target.size.x,
+                    height: target.size.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+
+            drop(data_core);
+
+            let captures = std::mem::take(&mut *target.captures.lock().unwrap());
+            let readback = (!captures.is_empty())
+                .then(|| Readback::new(device, &mut encoder, &offscreen.texture, target.size));
+
+            self.iad.queue.submit(Some(encoder.finish()));
+
+            if let Some(readback) = readback {
+                readback.finish(self.surface_format, captures);
+            }
+        }
+    }
 
-        let mut graph_data = RenderGraph::new();
-        let graph = &mut graph_data;
-        let samples = SampleCount::One;
+    /// Renders the scene into every visible [Viewport]'s texture.
+    fn draw_viewports(&mut self, resolution: UVec2) {
+        for (camera, size, view) in self.viewports.layout(resolution) {
+            self.draw_offscreen(camera, size, view);
+        }
+    }
+
+    /// Renders the scene from a camera into a texture of the given size.
+    fn draw_offscreen(&mut self, camera: Camera, size: UVec2, view: Arc<TextureView>) {
+        let aspect = size.as_vec2();
+        self.renderer.set_aspect_ratio(aspect.x / aspect.y);
+        self.renderer.set_camera_data(camera);
+
+        let (cmd_bufs, ready) = self.ready();
+        let mut graph = RenderGraph::new();
+        let state = self.add_scene(&mut graph, &ready, size, SampleCount::One);
+        let surface = graph.add_surface_texture();
+        state.tonemapping(&mut graph, &self.tonemapping_routine, surface);
+        graph.execute(&self.renderer, OutputFrame::View(view), cmd_bufs, &ready);
+    }
+
+    /// Adds the nodes that render the 3D scene to a graph, up to but not
+    /// including tonemapping.
+    fn add_scene<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        ready: &ReadyData,
+        resolution: UVec2,
+        samples: SampleCount,
+    ) -> BaseRenderGraphIntermediateState {
         let base = &self.base_render_graph;
         let ambient = self.ambient;
         let pbr = &self.pbr_routine;
@@ -198,8 +684,7 @@ impl Rend3Plugin {
         //
         // we need to override this function so that we can hook into the
         // graph's state in our custom nodes
-        let state =
-            BaseRenderGraphIntermediateState::new(graph, &ready, request.resolution, samples);
+        let state = BaseRenderGraphIntermediateState::new(graph, ready, resolution, samples);
 
         // Preparing and uploading data
         state.pre_skinning(graph);
@@ -223,24 +708,291 @@ impl Rend3Plugin {
         // Forward rendering
         state.pbr_forward_rendering(graph, pbr, samples);
 
+        state
+    }
+
+    /// Draws a frame in response to a [FrameRequest].
+    pub fn draw(&mut self, request: FrameRequest) {
+        let mut cpu = CpuTimer::start();
+        let now = Instant::now();
+        let frame_time = self
+            .last_frame
+            .replace(now)
+            .map_or(0.0, |last| to_millis(now - last));
+
+        self.interpolator.apply(&self.renderer);
+        self.draw_render_targets();
+        self.draw_viewports(request.resolution);
+        cpu.lap("offscreen views");
+
+        // camera changes are applied when the renderer is readied, so they
+        // must be made first to not leak into the next offscreen view
+        let aspect = request.resolution.as_vec2();
+        let aspect = aspect.x / aspect.y;
+        self.renderer.set_aspect_ratio(aspect);
+        self.renderer.set_camera_data(request.camera);
+
+        let (cmd_bufs, ready) = self.ready();
+        cpu.lap("ready");
+
+        // take the routines so that their nodes don't borrow all of self
+        let mut routines = std::mem::take(&mut self.routines);
+        let names: Vec<String> = routines.iter().map(|r| r.name().to_string()).collect();
+        let mut build_times = Vec::with_capacity(routines.len());
+        let nodes: Vec<_> = routines
+            .iter_mut()
+            .map(|routine| {
+                let start = Instant::now();
+                let node = routine.build_node();
+                build_times.push(start.elapsed());
+                node
+            })
+            .collect();
+
+        // the graph borrows self, so the timer is taken for the same reason
+        let mut gpu_timer = self.gpu_timer.take();
+        if let Some(timer) = gpu_timer.as_mut() {
+            timer.begin_frame();
+        }
+
+        let mut graph_data = RenderGraph::new();
+        let graph = &mut graph_data;
+        let settings = self.settings.borrow().clone();
+
+        let samples = match settings.msaa_samples {
+            4.. => SampleCount::Four,
+            _ => SampleCount::One,
+        };
+
+        let scale = settings.resolution_scale.clamp(0.25, 2.0);
+        let scene_resolution = (request.resolution.as_vec2() * scale)
+            .round()
+            .as_uvec2()
+            .max(UVec2::ONE);
+
+        let effects = self.compositor.sample();
+        self.post.set_filter(effects.filter());
+
+        gpu_timestamp(&mut gpu_timer, graph, "start");
+        let state = self.add_scene(graph, &ready, scene_resolution, samples);
+        gpu_timestamp(&mut gpu_timer, graph, "scene");
+
+        // bloom is applied to the resolved HDR scene before tonemapping
+        let scene = state.resolve.unwrap_or(state.color);
+        self.post.prepare(scene_resolution, request.resolution);
+        self.post.add_bloom(graph, scene, scene_resolution);
+
         // Make the reference to the surface
         let surface = graph.add_surface_texture();
-        state.tonemapping(graph, &self.tonemapping_routine, surface);
+
+        // the rest of the effects need the tonemapped scene as an input
+        if self.post.has_ldr_effects() {
+            let ldr = graph.add_render_target(RenderTargetDescriptor {
+                label: Some("post-processing input".into()),
+                resolution: request.resolution,
+                samples: SampleCount::One,
+                format: self.surface_format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            });
+
+            state.tonemapping(graph, &self.tonemapping_routine, ldr);
+            self.post.add_post(graph, ldr, surface);
+        } else {
+            state.tonemapping(graph, &self.tonemapping_routine, surface);
+        }
+
+        gpu_timestamp(&mut gpu_timer, graph, "post-processing");
+        self.viewports.add_to_graph(graph);
+        gpu_timestamp(&mut gpu_timer, graph, "viewports");
+
+        // tonemapping resolves and rescales the scene to the surface, but
+        // routines drawing to the surface need a depth target that matches it
+        let same_size = scene_resolution == request.resolution;
+        let depth = if matches!(samples, SampleCount::One) && same_size {
+            state.depth
+        } else {
+            graph.add_render_target(RenderTargetDescriptor {
+                label: Some("overlay depth".into()),
+                resolution: request.resolution,
+                samples: SampleCount::One,
+                format: TextureFormat::Depth32Float,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+            })
+        };
 
         let mut info = RoutineInfo {
             state: &state,
             sample_count: SampleCount::One,
             resolution: request.resolution,
+            depth,
             ready_data: &ready,
             graph,
         };
 
-        for node in nodes.iter() {
+        for ((node, name), build_time) in nodes.iter().zip(names).zip(build_times) {
+            let start = Instant::now();
             node.draw(&mut info);
+            cpu.record(name.clone(), build_time + start.elapsed());
+            gpu_timestamp(&mut gpu_timer, info.graph, name);
         }
 
+        // the fade and vignette cover everything else in the window
+        self.overlay.add_to_graph(info.graph, &effects);
+        gpu_timestamp(&mut gpu_timer, info.graph, "overlay");
+        cpu.lap("graph building");
+
         graph_data.execute(&self.renderer, request.output_frame, cmd_bufs, &ready);
+        cpu.lap("graph execution");
+
+        drop(nodes);
+        self.routines = routines;
+
+        let index = self.frame_index;
+        self.frame_index += 1;
+
+        if let Some(timer) = gpu_timer.as_mut() {
+            timer.end_frame(index);
+
+            for (frame, timings) in timer.collect() {
+                self.profiler.set_gpu(frame, timings);
+            }
+        }
+
+        self.gpu_timer = gpu_timer;
+
+        let (cpu_time, cpu) = cpu.finish();
+        self.profiler.push(FrameProfile {
+            index,
+            frame_time,
+            cpu_time,
+            cpu,
+            gpu: None,
+        });
 
         let _ = request.on_complete.send(()); // ignore hangup
     }
 }
+
+/// A copy of a texture's contents in the process of being read back from the
+/// GPU.
+struct Readback {
+    buffer: wgpu::Buffer,
+    size: UVec2,
+    padded_row: u32,
+}
+
+impl Readback {
+    /// Encodes a copy of an RGBA8 or BGRA8 texture into a new readback
+    /// buffer.
+    fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        size: UVec2,
+    ) -> Self {
+        // buffer copies need rows aligned to a multiple of 256 bytes
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = (size.x * 4).div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("render target readback"),
+            size: padded_row as u64 * size.y as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Self {
+            buffer,
+            size,
+            padded_row,
+        }
+    }
+
+    /// Waits for the copy to be mapped after it's submitted and sends the
+    /// unpadded RGBA8 pixels to each capture.
+    fn finish(self, format: TextureFormat, captures: Vec<oneshot::Sender<Vec<u8>>>) {
+        // the mapping completes during a later submission
+        let mapping = self.buffer.slice(..).map_async(MapMode::Read);
+        tokio::spawn(async move {
+            if mapping.await.is_err() {
+                error!("Failed to map render target readback");
+                return;
+            }
+
+            let row_len = self.size.x as usize * 4;
+            let data = self.buffer.slice(..).get_mapped_range();
+            let mut pixels = Vec::with_capacity(row_len * self.size.y as usize);
+            for row in data.chunks_exact(self.padded_row as usize) {
+                pixels.extend_from_slice(&row[..row_len]);
+            }
+
+            drop(data);
+            self.buffer.unmap();
+
+            let bgra = matches!(
+                format,
+                TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+            );
+
+            if bgra {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+
+            for capture in captures {
+                let _ = capture.send(pixels.clone()); // ignore hangup
+            }
+        });
+    }
+}
+
+/// A renderer and the built-in routines created on a single device.
+struct Gpu {
+    iad: InstanceAdapterDevice,
+    renderer: Arc<Renderer>,
+    base_render_graph: BaseRenderGraph,
+    pbr_routine: PbrRoutine,
+    tonemapping_routine: TonemappingRoutine,
+    skybox_routine: SkyboxRoutine,
+}
+
+impl Gpu {
+    fn new(iad: InstanceAdapterDevice, surface_format: TextureFormat) -> Self {
+        let handedness = rend3::types::Handedness::Right;
+        let renderer = Renderer::new(iad.to_owned(), handedness, None).unwrap();
+        let base_render_graph = BaseRenderGraph::new(&renderer);
+        let mut data_core = renderer.data_core.lock();
+        let interfaces = &base_render_graph.interfaces;
+        let pbr_routine = PbrRoutine::new(&renderer, &mut data_core, interfaces);
+        let tonemapping_routine = TonemappingRoutine::new(&renderer, interfaces, surface_format);
+        let skybox_routine = SkyboxRoutine::new(&renderer, interfaces);
+        drop(data_core);
+
+        Self {
+            iad,
+            renderer,
+            base_render_graph,
+            pbr_routine,
+            tonemapping_routine,
+            skybox_routine,
+        }
+    }
+}
@@ -0,0 +1,565 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! The post-processing stack applied to the main view.
+
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, UVec2};
+use hearth_runtime::hearth_schema::renderer::PostProcessSettings;
+use rend3::graph::{
+    RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetDescriptor, RenderTargetHandle,
+};
+use rend3::types::SampleCount;
+use wgpu::{util::DeviceExt, *};
+
+/// A 3D color grading lookup table (LUT).
+pub struct ColorLut {
+    view: TextureView,
+    size: u32,
+}
+
+impl ColorLut {
+    /// The smallest supported LUT size.
+    pub const MIN_SIZE: u32 = 2;
+
+    /// The largest supported LUT size.
+    pub const MAX_SIZE: u32 = 64;
+
+    /// Uploads a LUT from a horizontal strip of `size` square slices, as
+    /// described by [PostProcessSettings::color_grading].
+    ///
+    /// Panics if `data` isn't `size * size * size` RGBA pixels.
+    pub fn from_strip(device: &Device, queue: &Queue, size: u32, data: &[u8]) -> Self {
+        let volume = strip_to_volume(size, data);
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("color grading LUT"),
+                size: Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+            },
+            &volume,
+        );
+
+        Self {
+            view: texture.create_view(&Default::default()),
+            size,
+        }
+    }
+}
+
+/// Rearranges a LUT strip's pixels into the layout of a 3D texture.
+fn strip_to_volume(size: u32, data: &[u8]) -> Vec<u8> {
+    let size = size as usize;
+    assert_eq!(
+        data.len(),
+        size * size * size * 4,
+        "invalid LUT data length"
+    );
+
+    // the strip's rows are `size` slices wide; the volume's are one slice
+    let mut volume = Vec::with_capacity(data.len());
+    for slice in 0..size {
+        for row in 0..size {
+            let start = (row * size * size + slice * size) * 4;
+            volume.extend_from_slice(&data[start..(start + size * 4)]);
+        }
+    }
+
+    volume
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct BloomUniform {
+    texel: [f32; 2],
+    threshold: f32,
+    intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct PostUniform {
+    texel: [f32; 2],
+    fxaa: f32,
+    lut_size: f32,
+    vignette: [f32; 4],
+    filter: [[f32; 4]; 4],
+}
+
+/// A fullscreen pass in the post-processing stack.
+#[derive(Clone, Copy, Debug)]
+enum Pass {
+    BloomPrefilter,
+    BloomBlurH,
+    BloomBlurV,
+    BloomComposite,
+    Post,
+}
+
+/// Renders the post-processing effects configured by [PostProcessSettings].
+///
+/// Bloom is applied to the HDR scene before tonemapping. The rest of the
+/// effects, along with the [Compositor]'s color filter, are applied in a
+/// single pass from a tonemapped copy of the scene onto the surface.
+///
+/// [Compositor]: crate::compositor::Compositor
+pub(crate) struct PostProcessor {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    settings: PostProcessSettings,
+    lut: Option<Arc<ColorLut>>,
+    filter: Option<Mat4>,
+    sampler: Sampler,
+    bloom_bgl: BindGroupLayout,
+    bloom_ubo: Buffer,
+    bloom_prefilter: RenderPipeline,
+    bloom_blur_h: RenderPipeline,
+    bloom_blur_v: RenderPipeline,
+    bloom_composite: RenderPipeline,
+    post_bgl: BindGroupLayout,
+    post_ubo: Buffer,
+    post_pipeline: RenderPipeline,
+
+    /// A LUT to bind when color grading is disabled.
+    dummy_lut: ColorLut,
+}
+
+impl PostProcessor {
+    /// The format of the HDR scene and of the bloom targets.
+    const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+    /// Creates a post-processor that outputs to surfaces of the given format.
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, surface_format: TextureFormat) -> Self {
+        let bloom_shader = device.create_shader_module(&include_wgsl!("bloom.wgsl"));
+        let post_shader = device.create_shader_module(&include_wgsl!("post.wgsl"));
+
+        let uniform_entry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let texture_entry = |binding, view_dimension| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let sampler_entry = BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+
+        let bloom_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("bloom bind group layout"),
+            entries: &[
+                uniform_entry,
+                texture_entry(1, TextureViewDimension::D2),
+                sampler_entry,
+            ],
+        });
+
+        let post_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("post-processing bind group layout"),
+            entries: &[
+                uniform_entry,
+                texture_entry(1, TextureViewDimension::D2),
+                sampler_entry,
+                texture_entry(3, TextureViewDimension::D3),
+            ],
+        });
+
+        let create_pipeline = |label: &str,
+                               bgl: &BindGroupLayout,
+                               shader: &ShaderModule,
+                               entry_point: &str,
+                               format: TextureFormat,
+                               blend: Option<BlendState>| {
+            let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[bgl],
+                push_constant_ranges: &[],
+            });
+
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: Some(FragmentState {
+                    module: shader,
+                    entry_point,
+                    targets: &[ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            })
+        };
+
+        let additive = BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent::OVER,
+        };
+
+        let bloom_prefilter = create_pipeline(
+            "bloom prefilter",
+            &bloom_bgl,
+            &bloom_shader,
+            "fs_prefilter",
+            Self::HDR_FORMAT,
+            None,
+        );
+
+        let bloom_blur_h = create_pipeline(
+            "bloom horizontal blur",
+            &bloom_bgl,
+            &bloom_shader,
+            "fs_blur_h",
+            Self::HDR_FORMAT,
+            None,
+        );
+
+        let bloom_blur_v = create_pipeline(
+            "bloom vertical blur",
+            &bloom_bgl,
+            &bloom_shader,
+            "fs_blur_v",
+            Self::HDR_FORMAT,
+            None,
+        );
+
+        let bloom_composite = create_pipeline(
+            "bloom composite",
+            &bloom_bgl,
+            &bloom_shader,
+            "fs_composite",
+            Self::HDR_FORMAT,
+            Some(additive),
+        );
+
+        let post_pipeline = create_pipeline(
+            "post-processing",
+            &post_bgl,
+            &post_shader,
+            "fs_main",
+            surface_format,
+            None,
+        );
+
+        let create_ubo = |label: &str, contents: &[u8]| {
+            device.create_buffer_init(&util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            })
+        };
+
+        let bloom_ubo = create_ubo("bloom uniform", bytemuck::bytes_of(&BloomUniform::zeroed()));
+        let post_ubo = create_ubo("post uniform", bytemuck::bytes_of(&PostUniform::zeroed()));
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("post-processing sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let dummy_lut = ColorLut::from_strip(&device, &queue, 1, &[0; 4]);
+
+        Self {
+            device,
+            queue,
+            settings: Default::default(),
+            lut: None,
+            filter: None,
+            sampler,
+            bloom_bgl,
+            bloom_ubo,
+            bloom_prefilter,
+            bloom_blur_h,
+            bloom_blur_v,
+            bloom_composite,
+            post_bgl,
+            post_ubo,
+            post_pipeline,
+            dummy_lut,
+        }
+    }
+
+    /// Replaces the post-processing settings and color grading LUT.
+    pub fn set(&mut self, settings: PostProcessSettings, lut: Option<Arc<ColorLut>>) {
+        self.settings = settings;
+        self.lut = lut;
+    }
+
+    /// Sets the [Compositor]'s color filter for the next frame.
+    ///
+    /// [Compositor]: crate::compositor::Compositor
+    pub fn set_filter(&mut self, filter: Option<Mat4>) {
+        self.filter = filter;
+    }
+
+    /// Tests if any effects need a tonemapped copy of the scene.
+    pub fn has_ldr_effects(&self) -> bool {
+        self.settings.fxaa
+            || self.lut.is_some()
+            || self.settings.vignette.is_some()
+            || self.filter.is_some()
+    }
+
+    /// Updates the effects' uniforms for a frame of the given resolutions.
+    pub fn prepare(&self, scene_resolution: UVec2, resolution: UVec2) {
+        if let Some(bloom) = self.settings.bloom.as_ref() {
+            let texel = 1.0 / Self::bloom_resolution(scene_resolution).as_vec2();
+
+            let uniform = BloomUniform {
+                texel: texel.to_array(),
+                threshold: bloom.threshold,
+                intensity: bloom.intensity,
+            };
+
+            self.queue
+                .write_buffer(&self.bloom_ubo, 0, bytemuck::bytes_of(&uniform));
+        }
+
+        let vignette = match self.settings.vignette.as_ref() {
+            Some(vignette) => [vignette.intensity, vignette.radius, vignette.softness, 0.0],
+            None => [0.0; 4],
+        };
+
+        let uniform = PostUniform {
+            texel: (1.0 / resolution.as_vec2()).to_array(),
+            fxaa: if self.settings.fxaa { 1.0 } else { 0.0 },
+            lut_size: self.lut.as_ref().map(|lut| lut.size as f32).unwrap_or(0.0),
+            vignette,
+            filter: self.filter.unwrap_or(Mat4::IDENTITY).to_cols_array_2d(),
+        };
+
+        self.queue
+            .write_buffer(&self.post_ubo, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Gets the resolution of the bloom targets for a scene resolution.
+    fn bloom_resolution(scene_resolution: UVec2) -> UVec2 {
+        (scene_resolution / 2).max(UVec2::ONE)
+    }
+
+    /// Adds bloom to an HDR scene target, if enabled.
+    pub fn add_bloom<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        scene: RenderTargetHandle,
+        scene_resolution: UVec2,
+    ) {
+        if self.settings.bloom.is_none() {
+            return;
+        }
+
+        let mut add_target = |label: &str| {
+            graph.add_render_target(RenderTargetDescriptor {
+                label: Some(label.into()),
+                resolution: Self::bloom_resolution(scene_resolution),
+                samples: SampleCount::One,
+                format: Self::HDR_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            })
+        };
+
+        let a = add_target("bloom A");
+        let b = add_target("bloom B");
+
+        self.add_pass(graph, Pass::BloomPrefilter, scene, a);
+        self.add_pass(graph, Pass::BloomBlurH, a, b);
+        self.add_pass(graph, Pass::BloomBlurV, b, a);
+        self.add_pass(graph, Pass::BloomComposite, a, scene);
+    }
+
+    /// Applies the rest of the effects from a tonemapped copy of the scene
+    /// onto an output target.
+    pub fn add_post<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        input: RenderTargetHandle,
+        output: RenderTargetHandle,
+    ) {
+        self.add_pass(graph, Pass::Post, input, output);
+    }
+
+    /// Adds a node for a single fullscreen pass to a graph.
+    fn add_pass<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        pass: Pass,
+        input: RenderTargetHandle,
+        output: RenderTargetHandle,
+    ) {
+        let mut builder = graph.add_node(format!("{:?}", pass));
+        let input_handle = builder.add_render_target_input(input);
+        let output_handle = builder.add_render_target_output(output);
+
+        let rpass_handle = builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: output_handle,
+                clear: Color::BLACK,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        let this = builder.passthrough_ref(self);
+
+        builder.build(
+            move |pt, _renderer, encoder_or_pass, temps, _ready, graph_data| {
+                let this = pt.get(this);
+                let rpass = encoder_or_pass.get_rpass(rpass_handle);
+                let input = graph_data.get_render_target(input_handle);
+                let bind_group = temps.add(this.create_bind_group(pass, input));
+
+                rpass.set_pipeline(this.get_pipeline(pass));
+                rpass.set_bind_group(0, bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+            },
+        );
+    }
+
+    /// Gets the pipeline for a pass.
+    fn get_pipeline(&self, pass: Pass) -> &RenderPipeline {
+        match pass {
+            Pass::BloomPrefilter => &self.bloom_prefilter,
+            Pass::BloomBlurH => &self.bloom_blur_h,
+            Pass::BloomBlurV => &self.bloom_blur_v,
+            Pass::BloomComposite => &self.bloom_composite,
+            Pass::Post => &self.post_pipeline,
+        }
+    }
+
+    /// Creates the bind group for a pass with the given input.
+    fn create_bind_group(&self, pass: Pass, input: &TextureView) -> BindGroup {
+        let (layout, ubo) = match pass {
+            Pass::Post => (&self.post_bgl, &self.post_ubo),
+            _ => (&self.bloom_bgl, &self.bloom_ubo),
+        };
+
+        let lut = self.lut.as_deref().unwrap_or(&self.dummy_lut);
+
+        let mut entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: ubo.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(input),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(&self.sampler),
+            },
+        ];
+
+        if let Pass::Post = pass {
+            entries.push(BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(&lut.view),
+            });
+        }
+
+        self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("post-processing bind group"),
+            layout,
+            entries: &entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_to_volume_layout() {
+        // each pixel's red channel is its X coordinate in the strip and its
+        // green channel is its Y coordinate
+        let size = 3;
+        let mut strip = Vec::new();
+        for y in 0..size {
+            for x in 0..(size * size) {
+                strip.extend_from_slice(&[x as u8, y as u8, 0, 255]);
+            }
+        }
+
+        let volume = strip_to_volume(size, &strip);
+        assert_eq!(volume.len(), strip.len());
+
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let idx = ((b * size * size + g * size + r) * 4) as usize;
+                    let x = b * size + r;
+                    assert_eq!(&volume[idx..(idx + 2)], &[x as u8, g as u8]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn strip_to_volume_invalid_length() {
+        strip_to_volume(4, &[0; 16]);
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+struct VertexOut {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+struct PostUniform {
+    // the size of a texel in the input
+    texel: vec2<f32>;
+
+    // 1.0 if FXAA is enabled
+    fxaa: f32;
+
+    // the size of the color grading LUT, or 0.0 if color grading is disabled
+    lut_size: f32;
+
+    // intensity, radius, and softness; intensity is 0.0 if disabled
+    vignette: vec4<f32>;
+
+    // the compositor's color filter, or the identity matrix if disabled
+    filter: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> post: PostUniform;
+[[group(0), binding(1)]] var input_t: texture_2d<f32>;
+[[group(0), binding(2)]] var input_s: sampler;
+[[group(0), binding(3)]] var lut_t: texture_3d<f32>;
+
+let FXAA_SPAN_MAX: f32 = 8.0;
+let FXAA_REDUCE_MUL: f32 = 0.125;
+let FXAA_REDUCE_MIN: f32 = 0.0078125;
+
+// draws a single triangle that covers the whole target
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+
+    var out: VertexOut;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+
+    return out;
+}
+
+// this version of wgpu's WGSL doesn't support built-in smoothstep()
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+    let t = clamp((x - low) / (high - low), 0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn sample_input(uv: vec2<f32>) -> vec3<f32> {
+    return textureSample(input_t, input_s, uv).rgb;
+}
+
+// based on the classic FXAA 3.11 "lite" algorithm
+fn fxaa(uv: vec2<f32>) -> vec3<f32> {
+    let texel = post.texel;
+    let rgb_nw = sample_input(uv + vec2<f32>(-1.0, -1.0) * texel);
+    let rgb_ne = sample_input(uv + vec2<f32>(1.0, -1.0) * texel);
+    let rgb_sw = sample_input(uv + vec2<f32>(-1.0, 1.0) * texel);
+    let rgb_se = sample_input(uv + vec2<f32>(1.0, 1.0) * texel);
+    let rgb_m = sample_input(uv);
+
+    let luma_nw = luma(rgb_nw);
+    let luma_ne = luma(rgb_ne);
+    let luma_sw = luma(rgb_sw);
+    let luma_se = luma(rgb_se);
+    let luma_m = luma(rgb_m);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+
+    let luma_sum = luma_nw + luma_ne + luma_sw + luma_se;
+    let dir_reduce = max(luma_sum * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-FXAA_SPAN_MAX), vec2<f32>(FXAA_SPAN_MAX)) * texel;
+
+    let rgb_a = 0.5 * (
+        sample_input(uv + dir * (1.0 / 3.0 - 0.5)) +
+        sample_input(uv + dir * (2.0 / 3.0 - 0.5))
+    );
+
+    let rgb_b = rgb_a * 0.5 + 0.25 * (
+        sample_input(uv + dir * -0.5) +
+        sample_input(uv + dir * 0.5)
+    );
+
+    let luma_b = luma(rgb_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return rgb_a;
+    }
+
+    return rgb_b;
+}
+
+// LUTs are authored in display space, so grade gamma-encoded colors
+fn color_grade(color: vec3<f32>) -> vec3<f32> {
+    let size = post.lut_size;
+    let encoded = pow(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(1.0 / 2.2));
+    let coords = (encoded * (size - 1.0) + vec3<f32>(0.5)) / size;
+    let graded = textureSampleLevel(lut_t, input_s, coords, 0.0).rgb;
+    return pow(graded, vec3<f32>(2.2));
+}
+
+fn vignette(color: vec3<f32>, uv: vec2<f32>) -> vec3<f32> {
+    let intensity = post.vignette.x;
+    let radius = post.vignette.y;
+    let softness = post.vignette.z;
+
+    // 0.0 at the center and 1.0 at the corners
+    let dist = length(uv - vec2<f32>(0.5)) * 1.41421356;
+    let darkening = smoothstep(radius, radius + softness, dist);
+    return color * (1.0 - intensity * darkening);
+}
+
+[[stage(fragment)]]
+fn fs_main(frag: VertexOut) -> [[location(0)]] vec4<f32> {
+    var color: vec3<f32>;
+    if (post.fxaa > 0.0) {
+        color = fxaa(frag.uv);
+    } else {
+        color = sample_input(frag.uv);
+    }
+
+    if (post.lut_size > 0.0) {
+        color = color_grade(color);
+    }
+
+    color = (post.filter * vec4<f32>(color, 1.0)).rgb;
+
+    if (post.vignette.x > 0.0) {
+        color = vignette(color, frag.uv);
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,441 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! CPU and GPU frame timing.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hearth_runtime::hearth_schema::profiler::{FrameProfile, Timing};
+use rend3::graph::RenderGraph;
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Features, MapMode,
+    QuerySet, QueryType, Queue,
+};
+
+/// The number of frames kept in a [Profiler]'s history.
+pub const HISTORY_LEN: usize = 240;
+
+/// The most GPU timestamps that can be written in a single frame.
+const MAX_TIMESTAMPS: u32 = 64;
+
+/// The number of frames whose GPU timestamps can be read back at once.
+const READBACK_FRAMES: usize = 4;
+
+/// The size of a single GPU timestamp in bytes.
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+/// The recent history of frame profiles.
+#[derive(Default)]
+pub struct Profiler {
+    frames: Mutex<VecDeque<FrameProfile>>,
+    gpu_timing: AtomicBool,
+}
+
+impl Profiler {
+    /// Gets whether the current GPU device can time its passes.
+    pub fn gpu_timing(&self) -> bool {
+        self.gpu_timing.load(Ordering::Relaxed)
+    }
+
+    /// Gets up to the given number of the most recent frames, ordered from
+    /// oldest to newest.
+    pub fn recent(&self, count: usize) -> Vec<FrameProfile> {
+        let frames = self.frames.lock().unwrap();
+        let skip = frames.len().saturating_sub(count);
+        frames.iter().skip(skip).cloned().collect()
+    }
+
+    /// Adds a newly drawn frame to the history.
+    pub(crate) fn push(&self, frame: FrameProfile) {
+        let mut frames = self.frames.lock().unwrap();
+
+        if frames.len() >= HISTORY_LEN {
+            frames.pop_front();
+        }
+
+        frames.push_back(frame);
+    }
+
+    /// Attaches the GPU timings of a frame still in the history.
+    pub(crate) fn set_gpu(&self, index: u64, timings: Vec<Timing>) {
+        let mut frames = self.frames.lock().unwrap();
+        if let Some(frame) = frames.iter_mut().find(|frame| frame.index == index) {
+            frame.gpu = Some(timings);
+        }
+    }
+
+    pub(crate) fn set_gpu_timing(&self, gpu_timing: bool) {
+        self.gpu_timing.store(gpu_timing, Ordering::Relaxed);
+    }
+}
+
+/// Records the CPU time taken by each step of drawing a frame.
+pub(crate) struct CpuTimer {
+    start: Instant,
+    last: Instant,
+
+    /// The time of steps recorded separately since the last lap.
+    excluded: Duration,
+
+    timings: Vec<Timing>,
+}
+
+impl CpuTimer {
+    pub fn start() -> Self {
+        let now = Instant::now();
+
+        Self {
+            start: now,
+            last: now,
+            excluded: Duration::ZERO,
+            timings: Vec::new(),
+        }
+    }
+
+    /// Ends the current step and records the time since the last one.
+    pub fn lap(&mut self, name: impl Into<String>) {
+        let now = Instant::now();
+        let duration = (now - self.last).saturating_sub(self.excluded);
+        self.push(name, duration);
+        self.last = now;
+        self.excluded = Duration::ZERO;
+    }
+
+    /// Records a step that was timed separately. Its time is excluded from
+    /// the current lap.
+    pub fn record(&mut self, name: impl Into<String>, duration: Duration) {
+        self.push(name, duration);
+        self.excluded += duration;
+    }
+
+    /// Gets the total time in milliseconds and the time of each step.
+    pub fn finish(self) -> (f32, Vec<Timing>) {
+        (to_millis(self.start.elapsed()), self.timings)
+    }
+
+    fn push(&mut self, name: impl Into<String>, duration: Duration) {
+        self.timings.push(Timing {
+            name: name.into(),
+            time: to_millis(duration),
+        });
+    }
+}
+
+// the states of a readback buffer
+const READBACK_FREE: u8 = 0;
+const READBACK_MAPPING: u8 = 1;
+const READBACK_MAPPED: u8 = 2;
+const READBACK_FAILED: u8 = 3;
+
+/// A buffer that a frame's timestamps are copied into to be read back.
+struct Readback {
+    buffer: Buffer,
+    state: Arc<AtomicU8>,
+    frame: u64,
+    names: Vec<String>,
+}
+
+/// Times passes on the GPU with timestamp queries.
+///
+/// Timestamps are written between nodes of the main render graph, so each
+/// timing covers every node added since the previous timestamp.
+pub(crate) struct GpuTimer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    query_set: Arc<QuerySet>,
+    resolve_buffer: Buffer,
+    readbacks: Vec<Readback>,
+
+    /// The names of the timestamps written in the current frame, or `None`
+    /// if the current frame isn't being timed.
+    names: Option<Vec<String>>,
+
+    /// The number of nanoseconds per timestamp tick.
+    period: f32,
+}
+
+impl GpuTimer {
+    /// Creates a GPU timer, if the device supports timestamp queries.
+    pub fn new(device: &Arc<Device>, queue: &Arc<Queue>) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu timer queries"),
+            ty: QueryType::Timestamp,
+            count: MAX_TIMESTAMPS,
+        });
+
+        let size = MAX_TIMESTAMPS as u64 * TIMESTAMP_SIZE;
+
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("gpu timer resolve"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readbacks = (0..READBACK_FRAMES)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&BufferDescriptor {
+                    label: Some("gpu timer readback"),
+                    size,
+                    usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: Arc::new(AtomicU8::new(READBACK_FREE)),
+                frame: 0,
+                names: Vec::new(),
+            })
+            .collect();
+
+        Some(Self {
+            device: device.to_owned(),
+            queue: queue.to_owned(),
+            query_set: Arc::new(query_set),
+            resolve_buffer,
+            readbacks,
+            names: None,
+            period: queue.get_timestamp_period(),
+        })
+    }
+
+    /// Starts timing a new frame.
+    ///
+    /// Frames are skipped while every readback buffer is still in use.
+    pub fn begin_frame(&mut self) {
+        let free = self
+            .readbacks
+            .iter()
+            .any(|readback| readback.state.load(Ordering::Acquire) == READBACK_FREE);
+
+        self.names = free.then(Vec::new);
+    }
+
+    /// Adds a node to a render graph that writes a timestamp after every
+    /// node before it.
+    ///
+    /// The time since the previous timestamp is reported under `name`. The
+    /// first timestamp of a frame only marks its start.
+    pub fn timestamp<'node>(&mut self, graph: &mut RenderGraph<'node>, name: impl Into<String>) {
+        let Some(names) = self.names.as_mut() else {
+            return;
+        };
+
+        let index = names.len() as u32;
+        if index >= MAX_TIMESTAMPS {
+            return;
+        }
+
+        names.push(name.into());
+
+        let query_set = self.query_set.clone();
+        let builder = graph.add_node("gpu timestamp");
+        builder.build(
+            move |_pt, _renderer, encoder_or_pass, _temps, _ready, _graph_data| {
+                let encoder = encoder_or_pass.get_encoder();
+                encoder.write_timestamp(&query_set, index);
+            },
+        );
+    }
+
+    /// Resolves the current frame's timestamps and starts reading them back.
+    ///
+    /// Must be called after the frame's render graph has been executed.
+    pub fn end_frame(&mut self, frame: u64) {
+        let Some(names) = self.names.take() else {
+            return;
+        };
+
+        if names.len() < 2 {
+            return;
+        }
+
+        let Some(readback) = self
+            .readbacks
+            .iter_mut()
+            .find(|readback| readback.state.load(Ordering::Acquire) == READBACK_FREE)
+        else {
+            return;
+        };
+
+        let count = names.len() as u32;
+        let size = count as u64 * TIMESTAMP_SIZE;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("gpu timer resolve"),
+            });
+
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &readback.buffer, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        readback.frame = frame;
+        readback.names = names;
+        readback.state.store(READBACK_MAPPING, Ordering::Release);
+
+        // the mapping completes during a later submission
+        let mapping = readback.buffer.slice(..size).map_async(MapMode::Read);
+        let state = readback.state.clone();
+        tokio::spawn(async move {
+            let result = match mapping.await {
+                Ok(()) => READBACK_MAPPED,
+                Err(_) => READBACK_FAILED,
+            };
+
+            state.store(result, Ordering::Release);
+        });
+    }
+
+    /// Collects the GPU timings of every frame that has been read back,
+    /// along with their frame indices.
+    pub fn collect(&mut self) -> Vec<(u64, Vec<Timing>)> {
+        let mut frames = Vec::new();
+
+        for readback in self.readbacks.iter_mut() {
+            match readback.state.load(Ordering::Acquire) {
+                READBACK_MAPPED => {}
+                READBACK_FAILED => {
+                    readback.state.store(READBACK_FREE, Ordering::Release);
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let size = readback.names.len() as u64 * TIMESTAMP_SIZE;
+            let data = readback.buffer.slice(..size).get_mapped_range();
+
+            let ticks: Vec<u64> = data
+                .chunks_exact(TIMESTAMP_SIZE as usize)
+                .map(|tick| u64::from_le_bytes(tick.try_into().unwrap()))
+                .collect();
+
+            drop(data);
+            readback.buffer.unmap();
+
+            let names = std::mem::take(&mut readback.names);
+            let timings = names
+                .into_iter()
+                .skip(1)
+                .zip(ticks.windows(2))
+                .map(|(name, ticks)| Timing {
+                    name,
+                    time: ticks[1].saturating_sub(ticks[0]) as f32 * self.period / 1_000_000.0,
+                })
+                .collect();
+
+            frames.push((readback.frame, timings));
+            readback.state.store(READBACK_FREE, Ordering::Release);
+        }
+
+        frames
+    }
+}
+
+/// Writes a GPU timestamp to a graph with [GpuTimer::timestamp], if the GPU
+/// supports timing.
+pub(crate) fn gpu_timestamp<'node>(
+    timer: &mut Option<GpuTimer>,
+    graph: &mut RenderGraph<'node>,
+    name: impl Into<String>,
+) {
+    if let Some(timer) = timer.as_mut() {
+        timer.timestamp(graph, name);
+    }
+}
+
+/// Converts a duration into milliseconds.
+pub(crate) fn to_millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(index: u64) -> FrameProfile {
+        FrameProfile {
+            index,
+            frame_time: 16.0,
+            cpu_time: 1.0,
+            cpu: Vec::new(),
+            gpu: None,
+        }
+    }
+
+    #[test]
+    fn laps_exclude_recorded_steps() {
+        let mut timer = CpuTimer::start();
+        std::thread::sleep(Duration::from_millis(5));
+        timer.record("routine", Duration::from_millis(5));
+        timer.lap("rest");
+
+        let (total, timings) = timer.finish();
+        assert!(total >= 5.0);
+        assert_eq!(timings[0].name, "routine");
+        assert!(timings[1].time < total - 4.0);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let profiler = Profiler::default();
+
+        for index in 0..(HISTORY_LEN as u64 + 10) {
+            profiler.push(frame(index));
+        }
+
+        let frames = profiler.recent(usize::MAX);
+        assert_eq!(frames.len(), HISTORY_LEN);
+        assert_eq!(frames.first().unwrap().index, 10);
+        assert_eq!(frames.last().unwrap().index, HISTORY_LEN as u64 + 9);
+    }
+
+    #[test]
+    fn recent_returns_newest() {
+        let profiler = Profiler::default();
+        (0..5).for_each(|index| profiler.push(frame(index)));
+
+        let indices: Vec<_> = profiler.recent(2).iter().map(|f| f.index).collect();
+        assert_eq!(indices, [3, 4]);
+    }
+
+    #[test]
+    fn gpu_timings_are_attached() {
+        let profiler = Profiler::default();
+        (0..3).for_each(|index| profiler.push(frame(index)));
+
+        let timing = Timing {
+            name: "scene".into(),
+            time: 2.0,
+        };
+
+        profiler.set_gpu(1, vec![timing]);
+        profiler.set_gpu(100, Vec::new());
+
+        let frames = profiler.recent(3);
+        assert!(frames[0].gpu.is_none());
+        assert_eq!(frames[1].gpu.as_ref().unwrap()[0].name, "scene");
+        assert!(frames[2].gpu.is_none());
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Additional views of the scene drawn into rectangles of the main window.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use glam::{UVec2, Vec2};
+use hearth_runtime::hearth_schema::renderer::ViewportRect;
+use rend3::graph::{RenderGraph, RenderPassTarget, RenderPassTargets};
+use rend3::types::Camera;
+use wgpu::*;
+
+/// A rectangle of the main window that the scene is rendered into from its
+/// own camera.
+///
+/// Once added to the renderer with [Rend3Command::AddViewport], the viewport
+/// is drawn over the main view every frame until this is dropped. Like
+/// [RenderTarget], only the base scene is rendered into viewports.
+///
+/// [Rend3Command::AddViewport]: crate::Rend3Command::AddViewport
+/// [RenderTarget]: crate::RenderTarget
+pub struct Viewport {
+    rect: Mutex<ViewportRect>,
+    camera: Mutex<Camera>,
+}
+
+impl Viewport {
+    /// Creates a new viewport.
+    pub fn new(rect: ViewportRect, camera: Camera) -> Self {
+        Self {
+            rect: Mutex::new(rect),
+            camera: Mutex::new(camera),
+        }
+    }
+
+    /// Moves this viewport to a new rectangle of the window.
+    pub fn set_rect(&self, rect: ViewportRect) {
+        *self.rect.lock().unwrap() = rect;
+    }
+
+    /// Updates the camera that the scene is rendered from.
+    pub fn set_camera(&self, camera: Camera) {
+        *self.camera.lock().unwrap() = camera;
+    }
+}
+
+/// Converts a [ViewportRect] to a pixel offset and size within a window of
+/// the given resolution.
+///
+/// Returns `None` if no pixels of the rectangle are inside of the window.
+fn pixel_rect(rect: ViewportRect, resolution: UVec2) -> Option<(UVec2, UVec2)> {
+    let resolution = resolution.as_vec2();
+    let to_pixels = |corner: Vec2| (corner * resolution).round().clamp(Vec2::ZERO, resolution);
+    let min = to_pixels(rect.position);
+    let max = to_pixels(rect.position + rect.size);
+
+    if max.x <= min.x || max.y <= min.y {
+        return None;
+    }
+
+    Some((min.as_uvec2(), (max - min).as_uvec2()))
+}
+
+/// The texture that a viewport's view of the scene is rendered into.
+struct ViewportOutput {
+    size: UVec2,
+    view: Arc<TextureView>,
+    bind_group: BindGroup,
+}
+
+impl ViewportOutput {
+    /// Creates a viewport's output texture and its bind group.
+    fn new(
+        device: &Device,
+        bgl: &BindGroupLayout,
+        sampler: &Sampler,
+        format: TextureFormat,
+        size: UVec2,
+    ) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("viewport output"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let view = texture.create_view(&Default::default());
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("viewport bind group"),
+            layout: bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        Self {
+            size,
+            view: Arc::new(view),
+            bind_group,
+        }
+    }
+}
+
+/// The renderer's half of a [Viewport].
+struct ViewportTarget {
+    viewport: Weak<Viewport>,
+
+    /// The pixel offset and size of this viewport in the current frame, if
+    /// it is visible.
+    rect: Option<(UVec2, UVec2)>,
+
+    output: Option<ViewportOutput>,
+}
+
+/// Lays out live [Viewport]s and composites them into the main window.
+pub(crate) struct ViewportCompositor {
+    device: Arc<Device>,
+    bgl: BindGroupLayout,
+    pipeline: RenderPipeline,
+    sampler: Sampler,
+    format: TextureFormat,
+    targets: Vec<ViewportTarget>,
+}
+
+impl ViewportCompositor {
+    /// Creates a compositor that draws onto surfaces of the given format.
+    pub fn new(device: Arc<Device>, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(&include_wgsl!("viewport.wgsl"));
+
+        let bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("viewport bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("viewport pipeline layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("viewport pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: Default::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::COLOR,
+                }],
+            }),
+            multiview: None,
+        });
+
+        // viewports are rendered at the same resolution they're drawn at
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("viewport sampler"),
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            device,
+            bgl,
+            pipeline,
+            sampler,
+            format,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Starts compositing a viewport.
+    pub fn add(&mut self, viewport: &Arc<Viewport>) {
+        self.targets.push(ViewportTarget {
+            viewport: Arc::downgrade(viewport),
+            rect: None,
+            output: None,
+        });
+    }
+
+    /// Removes dropped viewports and lays out the rest in a window of the
+    /// given resolution, resizing their textures as needed.
+    ///
+    /// Returns the camera, resolution, and texture of each visible viewport
+    /// for the scene to be rendered into.
+    pub fn layout(&mut self, resolution: UVec2) -> Vec<(Camera, UVec2, Arc<TextureView>)> {
+        self.targets
+            .retain(|target| target.viewport.strong_count() > 0);
+
+        let mut views = Vec::new();
+        for target in self.targets.iter_mut() {
+            let Some(viewport) = target.viewport.upgrade() else {
+                continue;
+            };
+
+            let rect = *viewport.rect.lock().unwrap();
+            target.rect = pixel_rect(rect, resolution);
+
+            let Some((_, size)) = target.rect else {
+                continue;
+            };
+
+            let output = match target.output.take() {
+                Some(output) if output.size == size => output,
+                _ => ViewportOutput::new(&self.device, &self.bgl, &self.sampler, self.format, size),
+            };
+
+            let camera = *viewport.camera.lock().unwrap();
+            views.push((camera, size, output.view.clone()));
+            target.output = Some(output);
+        }
+
+        views
+    }
+
+    /// Adds a node to a graph that draws every visible viewport onto the
+    /// surface.
+    pub fn add_to_graph<'node>(&'node self, graph: &mut RenderGraph<'node>) {
+        if self.targets.iter().all(|target| target.rect.is_none()) {
+            return;
+        }
+
+        let output = graph.add_surface_texture();
+        let mut builder = graph.add_node("viewports");
+        let output_handle = builder.add_render_target_output(output);
+
+        let rpass_handle = builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: output_handle,
+                clear: Color::BLACK,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        let compositor = builder.passthrough_ref(self);
+
+        builder.build(
+            move |pt, _renderer, encoder_or_pass, _temps, _ready, _graph_data| {
+                let compositor = pt.get(compositor);
+                let rpass = encoder_or_pass.get_rpass(rpass_handle);
+
+                rpass.set_pipeline(&compositor.pipeline);
+
+                for target in compositor.targets.iter() {
+                    let (Some((offset, size)), Some(output)) = (target.rect, &target.output) else {
+                        continue;
+                    };
+
+                    let offset = offset.as_vec2();
+                    let size = size.as_vec2();
+                    rpass.set_viewport(offset.x, offset.y, size.x, size.y, 0.0, 1.0);
+                    rpass.set_bind_group(0, &output.bind_group, &[]);
+                    rpass.draw(0..4, 0..1);
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> ViewportRect {
+        ViewportRect {
+            position: Vec2::new(x, y),
+            size: Vec2::new(w, h),
+        }
+    }
+
+    #[test]
+    fn pixel_rect_fullscreen() {
+        let resolution = UVec2::new(1920, 1080);
+        let result = pixel_rect(rect(0.0, 0.0, 1.0, 1.0), resolution);
+        assert_eq!(result, Some((UVec2::ZERO, resolution)));
+    }
+
+    #[test]
+    fn pixel_rect_splitscreen() {
+        let resolution = UVec2::new(1920, 1080);
+        let right = pixel_rect(rect(0.5, 0.0, 0.5, 1.0), resolution);
+        assert_eq!(right, Some((UVec2::new(960, 0), UVec2::new(960, 1080))));
+    }
+
+    #[test]
+    fn pixel_rect_clipped() {
+        let resolution = UVec2::new(100, 100);
+        let result = pixel_rect(rect(0.75, -0.25, 0.5, 0.5), resolution);
+        assert_eq!(result, Some((UVec2::new(75, 0), UVec2::new(25, 25))));
+    }
+
+    #[test]
+    fn pixel_rect_offscreen() {
+        let resolution = UVec2::new(100, 100);
+        assert_eq!(pixel_rect(rect(1.5, 0.0, 0.5, 0.5), resolution), None);
+        assert_eq!(pixel_rect(rect(0.0, 0.0, 0.001, 0.5), resolution), None);
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec3;
+use hearth_runtime::anyhow::{ensure, Context, Result};
+use image::{ColorType, DynamicImage};
+
+/// An equirectangular image of the radiance around a point.
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+impl EnvironmentMap {
+    /// Decodes an environment map from an image file.
+    ///
+    /// Floating-point images like Radiance HDR and OpenEXR files are assumed
+    /// to be linear. Other images are assumed to be sRGB.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let image = image::load_from_memory(data).context("decoding environment map")?;
+        let linear = matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+
+        Ok(Self::from_image(image, linear))
+    }
+
+    fn from_image(image: DynamicImage, linear: bool) -> Self {
+        let image = image.into_rgb32f();
+        let decode = |c: f32| if linear { c } else { srgb_to_linear(c) };
+
+        Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image
+                .pixels()
+                .map(|pixel| Vec3::from(pixel.0.map(decode)))
+                .collect(),
+        }
+    }
+
+    /// Samples the radiance in a direction with bilinear filtering.
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+
+        // -Z is at the center of the image and +Y is at its top
+        let u = 0.5 + direction.x.atan2(-direction.z) / TAU;
+        let v = 0.5 - direction.y.clamp(-1.0, 1.0).asin() / PI;
+
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        // wrap around horizontally and clamp vertically
+        let texel = |x: f32, y: f32| {
+            let x = (x as i64).rem_euclid(self.width as i64) as usize;
+            let y = (y as usize).min(self.height as usize - 1);
+            self.pixels[y * self.width as usize + x]
+        };
+
+        let top = texel(x0, y0).lerp(texel(x0 + 1.0, y0), fx);
+        let bottom = texel(x0, y0 + 1.0).lerp(texel(x0 + 1.0, y0 + 1.0), fx);
+        top.lerp(bottom, fy)
+    }
+
+    /// Gets the average radiance over the sphere, weighting each row of the
+    /// image by the solid angle that it covers.
+    pub fn average(&self) -> Vec3 {
+        let mut sum = Vec3::ZERO;
+        let mut total_weight = 0.0;
+
+        for (y, row) in self.pixels.chunks_exact(self.width as usize).enumerate() {
+            let latitude = PI * (0.5 - (y as f32 + 0.5) / self.height as f32);
+            let weight = latitude.cos();
+            sum += row.iter().fold(Vec3::ZERO, |sum, pixel| sum + *pixel) * weight;
+            total_weight += weight * self.width as f32;
+        }
+
+        sum / total_weight
+    }
+
+    /// Renders this map into the faces of a cube texture in RGBA8 sRGB,
+    /// ordered +X, -X, +Y, -Y, +Z, -Z.
+    pub fn to_cube(&self, face_size: u32, exposure: f32) -> Result<Vec<u8>> {
+        ensure!(face_size > 0, "cube faces must not be empty");
+
+        let mut data = Vec::with_capacity(face_size as usize * face_size as usize * 24);
+        for face in 0..6 {
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let u = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+                    let v = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+                    let radiance = self.sample(cube_direction(face, u, v)) * exposure;
+                    data.extend(radiance.to_array().map(linear_to_srgb));
+                    data.push(0xff);
+                }
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Gets the direction through a point on a cube face, where `u` and `v` go
+/// from -1 to 1 across the face's texels.
+fn cube_direction(face: usize, u: f32, v: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+
+    (c * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgb32FImage, RgbImage};
+
+    fn solid(width: u32, height: u32, color: [f32; 3]) -> EnvironmentMap {
+        let image = Rgb32FImage::from_pixel(width, height, image::Rgb(color));
+        EnvironmentMap::from_image(image.into(), true)
+    }
+
+    #[test]
+    fn uniform_average() {
+        let map = solid(16, 8, [0.5, 1.0, 2.0]);
+        assert!(map.average().abs_diff_eq(Vec3::new(0.5, 1.0, 2.0), 1e-5));
+    }
+
+    #[test]
+    fn sky_and_ground() {
+        let mut image = Rgb32FImage::new(8, 4);
+        for (_, y, pixel) in image.enumerate_pixels_mut() {
+            *pixel = image::Rgb(if y < 2 { [1.0; 3] } else { [0.0; 3] });
+        }
+
+        let map = EnvironmentMap::from_image(image.into(), true);
+        assert_eq!(map.sample(Vec3::Y), Vec3::ONE);
+        assert_eq!(map.sample(-Vec3::Y), Vec3::ZERO);
+        assert!((map.average().x - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn forward_is_center() {
+        let mut image = Rgb32FImage::new(4, 2);
+        image.put_pixel(1, 0, image::Rgb([1.0; 3]));
+        image.put_pixel(2, 0, image::Rgb([1.0; 3]));
+        image.put_pixel(1, 1, image::Rgb([1.0; 3]));
+        image.put_pixel(2, 1, image::Rgb([1.0; 3]));
+
+        let map = EnvironmentMap::from_image(image.into(), true);
+        assert_eq!(map.sample(-Vec3::Z), Vec3::ONE);
+        assert_eq!(map.sample(Vec3::Z), Vec3::ZERO);
+    }
+
+    #[test]
+    fn srgb_images_are_linearized() {
+        let image = RgbImage::from_pixel(4, 2, image::Rgb([188; 3]));
+        let map = EnvironmentMap::from_image(image.into(), false);
+        assert!((map.sample(Vec3::X).x - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn cube_faces_point_outwards() {
+        let axes = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+        for (face, axis) in axes.into_iter().enumerate() {
+            assert_eq!(cube_direction(face, 0.0, 0.0), axis);
+
+            // every texel's direction is closest to its own face's axis
+            let corner = cube_direction(face, 0.9, -0.9).normalize();
+            assert!(corner.dot(axis) > 0.5);
+        }
+    }
+
+    #[test]
+    fn cube_size() {
+        let map = solid(8, 4, [1.0; 3]);
+        let data = map.to_cube(4, 1.0).unwrap();
+        assert_eq!(data.len(), 4 * 4 * 6 * 4);
+        assert!(data.iter().all(|byte| *byte == 0xff));
+        assert!(map.to_cube(0, 1.0).is_err());
+    }
+
+    #[test]
+    fn exposure_scales_radiance() {
+        let map = solid(8, 4, [0.25; 3]);
+        let data = map.to_cube(1, 2.0).unwrap();
+        assert_eq!(data[0], linear_to_srgb(0.5));
+    }
+}
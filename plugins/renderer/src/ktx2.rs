@@ -0,0 +1,308 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use glam::UVec2;
+use hearth_rend3::wgpu::TextureFormat;
+use hearth_runtime::anyhow::{bail, ensure, Context, Result};
+
+/// The 12-byte identifier at the start of every KTX2 file.
+pub const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// The size of the fixed header and index before the level index.
+const HEADER_LEN: usize = 80;
+
+/// The size of each entry in the level index.
+const LEVEL_INDEX_LEN: usize = 24;
+
+/// The largest supported width or height of a texture.
+const MAX_DIMENSION: u32 = 16384;
+
+/// The supercompression schemes that may be applied to level data.
+const SUPERCOMPRESSION_NONE: u32 = 0;
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+const SUPERCOMPRESSION_ZSTD: u32 = 2;
+
+/// A 2D texture loaded from a KTX2 file.
+#[derive(Clone, Debug)]
+pub struct Ktx2Texture {
+    /// The GPU format of this texture.
+    pub format: TextureFormat,
+
+    /// The size of the base mip level.
+    pub size: UVec2,
+
+    /// The number of mip levels in the data. Zero if the file asks for mips
+    /// to be generated.
+    pub mip_count: u32,
+
+    /// The data of every mip level, from largest to smallest.
+    pub data: Vec<u8>,
+}
+
+/// Returns true if the given data starts with the KTX2 identifier.
+pub fn is_ktx2(data: &[u8]) -> bool {
+    data.starts_with(&IDENTIFIER)
+}
+
+/// Parses a 2D KTX2 texture, decompressing any supercompressed levels.
+pub fn parse(data: &[u8]) -> Result<Ktx2Texture> {
+    ensure!(is_ktx2(data), "missing KTX2 identifier");
+    ensure!(data.len() >= HEADER_LEN, "truncated KTX2 header");
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let vk_format = read_u32(12);
+    let width = read_u32(20);
+    let height = read_u32(24);
+    let depth = read_u32(28);
+    let layers = read_u32(32);
+    let faces = read_u32(36);
+    let level_count = read_u32(40);
+    let supercompression = read_u32(44);
+
+    ensure!(width > 0 && height > 0, "KTX2 texture must be 2D");
+    ensure!(
+        width <= MAX_DIMENSION && height <= MAX_DIMENSION,
+        "KTX2 texture is too large"
+    );
+    ensure!(depth == 0, "3D KTX2 textures are unsupported");
+    ensure!(layers <= 1, "KTX2 texture arrays are unsupported");
+    ensure!(faces == 1, "KTX2 cube maps are unsupported");
+
+    let max_levels = u32::BITS - width.max(height).leading_zeros();
+    ensure!(level_count <= max_levels, "too many KTX2 mip levels");
+
+    if vk_format == 0 || supercompression == SUPERCOMPRESSION_BASIS_LZ {
+        bail!("Basis Universal KTX2 textures must be transcoded before loading");
+    }
+
+    let format = vk_format_to_wgpu(vk_format)
+        .with_context(|| format!("unsupported KTX2 vkFormat {}", vk_format))?;
+
+    let info = format.describe();
+    let (block_width, block_height) = info.block_dimensions;
+    let size = UVec2::new(width, height);
+
+    let mut out = Vec::new();
+    for level in 0..level_count.max(1) {
+        let entry = HEADER_LEN + level as usize * LEVEL_INDEX_LEN;
+        let index = data
+            .get(entry..entry + LEVEL_INDEX_LEN)
+            .context("truncated KTX2 level index")?;
+
+        let read_u64 = |offset: usize| {
+            let bytes = index[offset..offset + 8].try_into().unwrap();
+            usize::try_from(u64::from_le_bytes(bytes)).ok()
+        };
+
+        let offset = read_u64(0).context("KTX2 level offset overflow")?;
+        let length = read_u64(8).context("KTX2 level length overflow")?;
+        let uncompressed_length = read_u64(16).context("KTX2 level length overflow")?;
+
+        let level_data = offset
+            .checked_add(length)
+            .and_then(|end| data.get(offset..end))
+            .context("KTX2 level data out of bounds")?;
+
+        let level_size = (size >> level).max(UVec2::ONE);
+        let blocks_x = level_size.x.div_ceil(block_width as u32) as usize;
+        let blocks_y = level_size.y.div_ceil(block_height as u32) as usize;
+        let expected_len = blocks_x * blocks_y * info.block_size as usize;
+
+        // check before decompressing so that a bogus length can't be used to
+        // allocate an arbitrarily large buffer
+        ensure!(
+            uncompressed_length == expected_len,
+            "KTX2 level {} has an uncompressed length of {}, expected {}",
+            level,
+            uncompressed_length,
+            expected_len
+        );
+
+        let level_data = match supercompression {
+            SUPERCOMPRESSION_NONE => level_data.to_vec(),
+            SUPERCOMPRESSION_ZSTD => zstd::bulk::decompress(level_data, uncompressed_length)
+                .context("decompressing KTX2 level")?,
+            other => bail!("unsupported KTX2 supercompression scheme {}", other),
+        };
+
+        ensure!(
+            level_data.len() == expected_len,
+            "KTX2 level {} has {} bytes, expected {}",
+            level,
+            level_data.len(),
+            expected_len
+        );
+
+        out.extend_from_slice(&level_data);
+    }
+
+    Ok(Ktx2Texture {
+        format,
+        size,
+        mip_count: level_count,
+        data: out,
+    })
+}
+
+/// Converts a Vulkan format enum value to the equivalent [TextureFormat].
+fn vk_format_to_wgpu(vk_format: u32) -> Option<TextureFormat> {
+    use TextureFormat::*;
+    Some(match vk_format {
+        37 => Rgba8Unorm,
+        43 => Rgba8UnormSrgb,
+        131 | 133 => Bc1RgbaUnorm,
+        132 | 134 => Bc1RgbaUnormSrgb,
+        135 => Bc2RgbaUnorm,
+        136 => Bc2RgbaUnormSrgb,
+        137 => Bc3RgbaUnorm,
+        138 => Bc3RgbaUnormSrgb,
+        139 => Bc4RUnorm,
+        140 => Bc4RSnorm,
+        141 => Bc5RgUnorm,
+        142 => Bc5RgSnorm,
+        143 => Bc6hRgbUfloat,
+        144 => Bc6hRgbSfloat,
+        145 => Bc7RgbaUnorm,
+        146 => Bc7RgbaUnormSrgb,
+        147 => Etc2Rgb8Unorm,
+        148 => Etc2Rgb8UnormSrgb,
+        149 => Etc2Rgb8A1Unorm,
+        150 => Etc2Rgb8A1UnormSrgb,
+        151 => Etc2Rgba8Unorm,
+        152 => Etc2Rgba8UnormSrgb,
+        153 => EacR11Unorm,
+        154 => EacR11Snorm,
+        155 => EacRg11Unorm,
+        156 => EacRg11Snorm,
+        157 => Astc4x4RgbaUnorm,
+        158 => Astc4x4RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    /// Builds a KTX2 file with the given header fields and level data.
+    fn build(vk_format: u32, size: u32, supercompression: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = IDENTIFIER.to_vec();
+        let header = [vk_format, 1, size, size, 0, 0, 1, levels.len() as u32];
+        for field in header.into_iter().chain([supercompression, 0, 0, 0, 0]) {
+            file.extend(field.to_le_bytes());
+        }
+
+        file.extend([0; 16]); // supercompression global data index
+
+        let mut offset = HEADER_LEN + LEVEL_INDEX_LEN * levels.len();
+        let mut body = Vec::new();
+        for level in levels {
+            let data = match supercompression {
+                SUPERCOMPRESSION_ZSTD => zstd::bulk::compress(level, 0).unwrap(),
+                _ => level.clone(),
+            };
+
+            file.extend((offset as u64).to_le_bytes());
+            file.extend((data.len() as u64).to_le_bytes());
+            file.extend((level.len() as u64).to_le_bytes());
+            offset += data.len();
+            body.extend(data);
+        }
+
+        file.extend(body);
+        file
+    }
+
+    /// Generates the mip levels of an 8x8 BC1 texture.
+    fn bc1_levels() -> Vec<Vec<u8>> {
+        // 8x8, 4x4, 2x2, and 1x1 all round up to whole 4x4 blocks
+        [4, 1, 1, 1]
+            .into_iter()
+            .enumerate()
+            .map(|(idx, blocks)| vec![idx as u8; blocks * 8])
+            .collect()
+    }
+
+    #[test]
+    fn parse_bc1() {
+        let levels = bc1_levels();
+        let texture = parse(&build(133, 8, 0, &levels)).unwrap();
+        assert_eq!(texture.format, TextureFormat::Bc1RgbaUnorm);
+        assert_eq!(texture.size, UVec2::new(8, 8));
+        assert_eq!(texture.mip_count, 4);
+        assert_eq!(texture.data, levels.concat());
+    }
+
+    #[test]
+    fn parse_zstd() {
+        let levels = bc1_levels();
+        let texture = parse(&build(133, 8, SUPERCOMPRESSION_ZSTD, &levels)).unwrap();
+        assert_eq!(texture.data, levels.concat());
+    }
+
+    #[test]
+    fn reject_bad_level_size() {
+        let levels = vec![vec![0; 7]];
+        assert!(parse(&build(133, 8, 0, &levels)).is_err());
+    }
+
+    #[test]
+    fn reject_basis() {
+        let levels = bc1_levels();
+        assert!(parse(&build(0, 8, SUPERCOMPRESSION_BASIS_LZ, &levels)).is_err());
+    }
+
+    #[test]
+    fn reject_truncated() {
+        let file = build(133, 8, 0, &bc1_levels());
+        assert!(parse(&file[..HEADER_LEN + 10]).is_err());
+    }
+
+    #[test]
+    fn reject_too_many_levels() {
+        let levels = vec![vec![0; 8]; 5];
+        assert!(parse(&build(133, 8, 0, &levels)).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn parse_arbitrary(data in prop::collection::vec(any::<u8>(), 0..1024)) {
+            let _ = parse(&[IDENTIFIER.as_slice(), &data].concat());
+        }
+
+        #[test]
+        fn parse_corrupted(
+            edits in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+            zstd in any::<bool>(),
+        ) {
+            let supercompression = if zstd { SUPERCOMPRESSION_ZSTD } else { 0 };
+            let mut file = build(133, 8, supercompression, &bc1_levels());
+            for (idx, byte) in edits {
+                let idx = idx.index(file.len());
+                file[idx] = byte;
+            }
+
+            let _ = parse(&file);
+        }
+    }
+}
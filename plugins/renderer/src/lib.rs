@@ -16,26 +16,73 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+use glam::UVec2;
 use hearth_rend3::{
+    compositor::Compositor,
+    device::DeviceEvent,
+    interpolate::{InterpolatedObject, Interpolator},
+    post::ColorLut,
+    profiler::Profiler,
     rend3::{types::*, *},
     rend3_routine::pbr::{AlbedoComponent, PbrMaterial},
-    Rend3Command, Rend3Plugin,
+    viewport::Viewport,
+    Rend3Command, Rend3Plugin, RenderTarget,
 };
 use hearth_runtime::{
-    anyhow::{self, bail},
+    anyhow::{self, bail, Context},
     asset::{AssetLoader, AssetStore, JsonAssetLoader},
     async_trait,
+    flue::{CapabilityRef, Permissions, Table},
     hearth_macros::GetProcessMetadata,
-    hearth_schema::{renderer::*, LumpId},
-    runtime::{Plugin, RuntimeBuilder},
-    tokio::sync::mpsc::UnboundedSender,
-    tracing::{error, warn},
+    hearth_schema::{
+        codec,
+        profiler::{ProfilerReport, ProfilerRequest, SERVICE_NAME as PROFILER_SERVICE_NAME},
+        renderer::*,
+        LumpId,
+    },
+    runtime::{Plugin, Runtime, RuntimeBuilder},
+    tokio::{
+        self,
+        sync::{
+            broadcast::{self, error::RecvError},
+            mpsc::UnboundedSender,
+            watch, RwLock,
+        },
+    },
+    tracing::{error, info, warn},
     utils::*,
 };
 
-pub struct MeshLoader(Arc<Renderer>);
+/// Equirectangular environment map conversion.
+pub mod environment;
+
+/// KTX2 texture container parsing.
+pub mod ktx2;
+
+/// The prefix of the placeholder lumps that refer to render target textures.
+///
+/// The rest of the lump is a random token that is looked up in
+/// [RenderTargetTextures].
+const RENDER_TARGET_MAGIC: &[u8] = b"hearth render target\0";
+
+/// The largest face size of an environment map's cube texture.
+const MAX_ENVIRONMENT_FACE_SIZE: u32 = 4096;
+
+/// The textures of all live render targets, keyed by their lumps' tokens.
+type RenderTargetTextures = Arc<Mutex<HashMap<Vec<u8>, TextureHandle>>>;
+
+/// The renderer that assets are loaded into, which changes when the renderer
+/// is re-created after the GPU device is lost.
+type CurrentRenderer = watch::Receiver<Arc<Renderer>>;
+
+pub struct MeshLoader(CurrentRenderer);
 
 #[async_trait]
 impl JsonAssetLoader for MeshLoader {
@@ -61,13 +108,13 @@ impl JsonAssetLoader for MeshLoader {
 
         let _ = mesh.validate()?;
 
-        let handle = self.0.add_mesh(mesh);
+        let handle = self.0.borrow().add_mesh(mesh);
 
         Ok(handle)
     }
 }
 
-pub struct MaterialLoader(Arc<Renderer>);
+pub struct MaterialLoader(CurrentRenderer);
 
 #[async_trait]
 impl JsonAssetLoader for MaterialLoader {
@@ -86,24 +133,38 @@ impl JsonAssetLoader for MaterialLoader {
             ..Default::default()
         };
 
-        let handle = self.0.add_material(material);
+        let handle = self.0.borrow().add_material(material);
         Ok(handle)
     }
 }
 
-pub struct TextureLoader(Arc<Renderer>);
+/// Loads 2D textures from either JSON-encoded [TextureData] or KTX2 files.
+///
+/// Also resolves the placeholder lumps of render target textures.
+pub struct TextureLoader(CurrentRenderer, RenderTargetTextures);
 
 #[async_trait]
-impl JsonAssetLoader for TextureLoader {
+impl AssetLoader for TextureLoader {
     type Asset = TextureHandle;
-    type Data = TextureData;
 
-    async fn load_asset(
-        &self,
-        _store: &AssetStore,
-        data: Self::Data,
-    ) -> anyhow::Result<Self::Asset> {
-        let expected_len = (data.size.x * data.size.y * 4) as usize;
+    async fn load_asset(&self, _store: &AssetStore, data: &[u8]) -> anyhow::Result<Self::Asset> {
+        if let Some(token) = data.strip_prefix(RENDER_TARGET_MAGIC) {
+            let textures = self.1.lock().unwrap();
+            let texture = textures
+                .get(token)
+                .context("render target has been killed")?;
+            return Ok(texture.to_owned());
+        }
+
+        if ktx2::is_ktx2(data) {
+            return self.load_ktx2(data);
+        }
+
+        let data: TextureData =
+            codec::decode(data).context("Deserializing asset from TextureData")?;
+
+        let renderer = self.0.borrow().clone();
+        let expected_len = texture_pixel_count(&renderer, data.size)? * 4;
 
         if data.data.len() != expected_len {
             bail!("invalid texture data length");
@@ -118,12 +179,60 @@ impl JsonAssetLoader for TextureLoader {
             mip_source: MipmapSource::Uploaded,
         };
 
-        let handle = self.0.add_texture_2d(texture);
+        let handle = renderer.add_texture_2d(texture);
+        Ok(handle)
+    }
+}
+
+impl TextureLoader {
+    /// Loads a texture from a KTX2 file.
+    fn load_ktx2(&self, data: &[u8]) -> anyhow::Result<TextureHandle> {
+        let texture = ktx2::parse(data)?;
+        let renderer = self.0.borrow().clone();
+
+        // reject sizes that the device can't create
+        texture_pixel_count(&renderer, texture.size)?;
+
+        let required = texture.format.describe().required_features;
+        if !renderer.features.contains(required) {
+            bail!(
+                "KTX2 texture format {:?} is unsupported by this device",
+                texture.format
+            );
+        }
+
+        let (mip_count, mip_source) = match NonZeroU32::new(texture.mip_count) {
+            Some(count) => (MipmapCount::Specific(count), MipmapSource::Uploaded),
+            None if required.is_empty() => (MipmapCount::Maximum, MipmapSource::Generated),
+            None => bail!("can't generate mipmaps for compressed KTX2 textures"),
+        };
+
+        let texture = Texture {
+            label: None,
+            data: texture.data,
+            format: texture.format,
+            size: texture.size,
+            mip_count,
+            mip_source,
+        };
+
+        let handle = renderer.add_texture_2d(texture);
         Ok(handle)
     }
 }
 
-pub struct CubeTextureLoader(Arc<Renderer>);
+/// Checks that a texture size is supported by the device and returns its
+/// number of pixels.
+fn texture_pixel_count(renderer: &Renderer, size: UVec2) -> anyhow::Result<usize> {
+    let max = renderer.limits.max_texture_dimension_2d;
+    if size.x == 0 || size.y == 0 || size.x > max || size.y > max {
+        bail!("unsupported texture size {}", size);
+    }
+
+    Ok(size.x as usize * size.y as usize)
+}
+
+pub struct CubeTextureLoader(CurrentRenderer);
 
 #[async_trait]
 impl JsonAssetLoader for CubeTextureLoader {
@@ -135,7 +244,8 @@ impl JsonAssetLoader for CubeTextureLoader {
         _store: &AssetStore,
         data: Self::Data,
     ) -> anyhow::Result<Self::Asset> {
-        let expected_len = (data.size.x * data.size.y * 24) as usize;
+        let renderer = self.0.borrow().clone();
+        let expected_len = texture_pixel_count(&renderer, data.size)? * 24;
 
         if data.data.len() != expected_len {
             bail!("invalid texture data length");
@@ -150,12 +260,40 @@ impl JsonAssetLoader for CubeTextureLoader {
             mip_source: MipmapSource::Generated,
         };
 
-        let handle = self.0.add_texture_cube(texture);
+        let handle = renderer.add_texture_cube(texture);
 
         Ok(handle)
     }
 }
 
+pub struct ColorLutLoader(CurrentRenderer);
+
+#[async_trait]
+impl JsonAssetLoader for ColorLutLoader {
+    type Asset = Arc<ColorLut>;
+    type Data = TextureData;
+
+    async fn load_asset(
+        &self,
+        _store: &AssetStore,
+        data: Self::Data,
+    ) -> anyhow::Result<Self::Asset> {
+        let size = data.size.y;
+        let valid_size = ColorLut::MIN_SIZE..=ColorLut::MAX_SIZE;
+        if !valid_size.contains(&size) || data.size.x != size * size {
+            bail!("invalid color grading LUT size");
+        }
+
+        if data.data.len() != (size * size * size * 4) as usize {
+            bail!("invalid texture data length");
+        }
+
+        let renderer = self.0.borrow().clone();
+        let lut = ColorLut::from_strip(&renderer.device, &renderer.queue, size, &data.data);
+        Ok(Arc::new(lut))
+    }
+}
+
 /// An instance of a renderer directional light. Accepts DirectionalLightUpdate.
 #[derive(GetProcessMetadata)]
 pub struct DirectionalLightInstance {
@@ -182,12 +320,41 @@ impl SinkProcess for DirectionalLightInstance {
     }
 }
 
+/// An instance of a renderer point light. Accepts PointLightUpdate.
+#[derive(GetProcessMetadata)]
+pub struct PointLightInstance {
+    renderer: Arc<Renderer>,
+    handle: ResourceHandle<PointLight>,
+}
+
+#[async_trait]
+impl SinkProcess for PointLightInstance {
+    type Message = PointLightUpdate;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let mut change = PointLightChange::default();
+
+        use PointLightUpdate::*;
+        match message.data {
+            Position(position) => change.position = Some(position),
+            Color(color) => change.color = Some(color),
+            Intensity(intensity) => change.intensity = Some(intensity),
+            Radius(radius) => change.radius = Some(radius),
+        }
+
+        self.renderer.update_point_light(&self.handle, change);
+    }
+}
+
 /// An instance of a renderer object. Accepts ObjectUpdate.
 #[derive(GetProcessMetadata)]
 pub struct ObjectInstance {
     renderer: Arc<Renderer>,
     handle: ObjectHandle,
     skeleton: Option<SkeletonHandle>,
+
+    /// The interpolated motion of this object, if it's dynamic.
+    motion: Option<Arc<InterpolatedObject>>,
 }
 
 #[async_trait]
@@ -197,9 +364,10 @@ impl SinkProcess for ObjectInstance {
     async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
         use ObjectUpdate::*;
         match &message.data {
-            Transform(transform) => {
-                self.renderer.set_object_transform(&self.handle, *transform);
-            }
+            Transform(transform) => match self.motion.as_ref() {
+                Some(motion) => motion.set_transform(*transform),
+                None => self.renderer.set_object_transform(&self.handle, *transform),
+            },
             JointMatrices(matrices) => {
                 let Some(skeleton) = self.skeleton.as_ref() else {
                     warn!("tried to update joint matrices on static object");
@@ -225,11 +393,101 @@ impl SinkProcess for ObjectInstance {
     }
 }
 
+/// A texture that the scene is rendered into. Accepts RenderTargetUpdate.
+#[derive(GetProcessMetadata)]
+pub struct RenderTargetInstance {
+    target: Arc<RenderTarget>,
+    token: Vec<u8>,
+    textures: RenderTargetTextures,
+}
+
+impl Drop for RenderTargetInstance {
+    fn drop(&mut self) {
+        self.textures.lock().unwrap().remove(&self.token);
+    }
+}
+
+#[async_trait]
+impl SinkProcess for RenderTargetInstance {
+    type Message = RenderTargetUpdate;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        use RenderTargetUpdate::*;
+        match message.data {
+            SetCamera { vfov, near, view } => {
+                self.target.set_camera(Camera {
+                    projection: CameraProjection::Perspective { vfov, near },
+                    view,
+                });
+            }
+            Capture => {
+                let Some(reply) = message.caps.first() else {
+                    warn!("render target capture is missing a reply capability");
+                    return;
+                };
+
+                let response: RendererResponse = match self.target.capture().await {
+                    Ok(data) => {
+                        let texture = TextureData {
+                            label: Some("render target capture".into()),
+                            size: self.target.size,
+                            data,
+                        };
+
+                        let lump = codec::encode(&texture);
+                        let lump_store = &message.runtime.lump_store;
+                        let texture = lump_store.add_lump(lump.into()).await;
+                        Ok(RendererSuccess::Capture { texture })
+                    }
+                    Err(_) => Err(RendererError::CaptureFailed),
+                };
+
+                let data = message.codec.encode(&response);
+                let _ = reply.send(&data, &[]).await;
+            }
+        }
+    }
+}
+
+/// A view of the scene drawn into the main window. Accepts ViewportUpdate.
+#[derive(GetProcessMetadata)]
+pub struct ViewportInstance {
+    viewport: Arc<Viewport>,
+}
+
+#[async_trait]
+impl SinkProcess for ViewportInstance {
+    type Message = ViewportUpdate;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        use ViewportUpdate::*;
+        match message.data {
+            SetCamera { vfov, near, view } => {
+                self.viewport.set_camera(Camera {
+                    projection: CameraProjection::Perspective { vfov, near },
+                    view,
+                });
+            }
+            SetRect(rect) => {
+                if rect.is_valid() {
+                    self.viewport.set_rect(rect);
+                } else {
+                    warn!("Ignoring invalid viewport rect {:?}", rect);
+                }
+            }
+        }
+    }
+}
+
 /// The native interface to the renderer. Accepts RendererRequest.
 #[derive(GetProcessMetadata)]
 pub struct RendererService {
-    renderer: Arc<Renderer>,
+    renderer: Arc<RwLock<Arc<Renderer>>>,
+    events: Arc<PubSub<RendererEvent>>,
     command_tx: UnboundedSender<Rend3Command>,
+    surface_format: TextureFormat,
+    render_targets: RenderTargetTextures,
+    interpolator: Arc<Interpolator>,
 }
 
 #[async_trait]
@@ -241,6 +499,11 @@ impl RequestResponseProcess for RendererService {
         &'a mut self,
         request: &mut RequestInfo<'a, Self::Request>,
     ) -> ResponseInfo<'a, Self::Response> {
+        // hold the renderer for the whole request so that it can't be
+        // replaced while resources are being added to it
+        let guard = self.renderer.read().await;
+        let renderer: &Arc<Renderer> = &guard;
+
         use RendererRequest::*;
         match &request.data {
             AddDirectionalLight { initial_state } => {
@@ -251,10 +514,10 @@ impl RequestResponseProcess for RendererService {
                     distance: initial_state.distance,
                 };
 
-                let handle = self.renderer.add_directional_light(light);
+                let handle = renderer.add_directional_light(light);
 
                 let child = request.spawn(DirectionalLightInstance {
-                    renderer: self.renderer.clone(),
+                    renderer: renderer.clone(),
                     handle,
                 });
 
@@ -263,11 +526,37 @@ impl RequestResponseProcess for RendererService {
                     caps: vec![child],
                 };
             }
+            AddPointLight { initial_state } => {
+                let light = PointLight {
+                    position: initial_state.position,
+                    color: initial_state.color,
+                    radius: initial_state.radius,
+                    intensity: initial_state.intensity,
+                };
+
+                let handle = renderer.add_point_light(light);
+
+                let child = request.spawn(PointLightInstance {
+                    renderer: renderer.clone(),
+                    handle,
+                });
+
+                return ResponseInfo {
+                    data: Ok(RendererSuccess::Ok),
+                    caps: vec![child],
+                };
+            }
+            AddSpotLight { .. } => {
+                // rend3 has no cone-attenuated lights, and a point light
+                // would light everything that the cone shouldn't
+                return RendererError::Unsupported.into();
+            }
             AddObject {
                 mesh,
                 skeleton,
                 material,
                 transform,
+                dynamic,
             } => {
                 let mesh = match Self::try_load_asset::<MeshLoader>(&request, mesh).await {
                     Ok(mesh) => mesh,
@@ -281,7 +570,7 @@ impl RequestResponseProcess for RendererService {
                     };
 
                 let (mesh_kind, skeleton) = if let Some(skeleton) = skeleton.as_ref() {
-                    let skeleton = self.renderer.add_skeleton(Skeleton {
+                    let skeleton = renderer.add_skeleton(Skeleton {
                         joint_matrices: skeleton.to_owned(),
                         mesh: mesh.as_ref().to_owned(),
                     });
@@ -297,12 +586,15 @@ impl RequestResponseProcess for RendererService {
                     transform: *transform,
                 };
 
-                let handle = self.renderer.add_object(object);
+                let handle = renderer.add_object(object);
+
+                let motion = dynamic.then(|| self.interpolator.add(handle.clone(), *transform));
 
                 let child = request.spawn(ObjectInstance {
-                    renderer: self.renderer.clone(),
+                    renderer: renderer.clone(),
                     handle,
                     skeleton,
+                    motion,
                 });
 
                 return ResponseInfo {
@@ -321,9 +613,177 @@ impl RequestResponseProcess for RendererService {
                     .command_tx
                     .send(Rend3Command::SetSkybox(texture.as_ref().clone()));
             }
+            SetEnvironmentMap {
+                map,
+                face_size,
+                exposure,
+                set_ambient,
+            } => {
+                let max = MAX_ENVIRONMENT_FACE_SIZE.min(renderer.limits.max_texture_dimension_2d);
+                if !(1..=max).contains(face_size) {
+                    return RendererError::InvalidSize.into();
+                }
+
+                let Some(data) = request.runtime.lump_store.get_lump(map).await else {
+                    return RendererError::LumpError.into();
+                };
+
+                let (face_size, exposure) = (*face_size, *exposure);
+                let result = tokio::task::spawn_blocking(move || {
+                    let map = environment::EnvironmentMap::decode(&data)?;
+                    let cube = map.to_cube(face_size, exposure)?;
+                    anyhow::Ok((cube, map.average() * exposure))
+                })
+                .await;
+
+                let (data, ambient) = match result {
+                    Ok(Ok(converted)) => converted,
+                    Ok(Err(err)) => {
+                        error!("failed to load environment map: {err:?}");
+                        return RendererError::LumpError.into();
+                    }
+                    Err(err) => {
+                        error!("environment map conversion panicked: {err:?}");
+                        return RendererError::LumpError.into();
+                    }
+                };
+
+                let texture = renderer.add_texture_cube(Texture {
+                    label: Some("environment map".to_string()),
+                    data,
+                    format: TextureFormat::Rgba8UnormSrgb,
+                    size: UVec2::splat(face_size),
+                    mip_count: MipmapCount::ONE,
+                    mip_source: MipmapSource::Generated,
+                });
+
+                let _ = self.command_tx.send(Rend3Command::SetSkybox(texture));
+
+                if *set_ambient {
+                    let _ = self
+                        .command_tx
+                        .send(Rend3Command::SetAmbient(ambient.extend(1.0)));
+                }
+            }
             SetAmbientLighting { ambient } => {
                 let _ = self.command_tx.send(Rend3Command::SetAmbient(*ambient));
             }
+            SetRenderSettings(settings) => {
+                let _ = self
+                    .command_tx
+                    .send(Rend3Command::SetRenderSettings(settings.clone()));
+            }
+            SetPostProcessing(settings) => {
+                let lut = match &settings.color_grading {
+                    None => None,
+                    Some(lump) => {
+                        match Self::try_load_asset::<ColorLutLoader>(&request, lump).await {
+                            Ok(lut) => Some(lut.as_ref().clone()),
+                            Err(err) => return err.into(),
+                        }
+                    }
+                };
+
+                let _ = self.command_tx.send(Rend3Command::SetPostProcessing {
+                    settings: settings.clone(),
+                    lut,
+                });
+            }
+            CreateRenderTarget {
+                size,
+                vfov,
+                near,
+                view,
+            } => {
+                let valid_size = 1..=MAX_RENDER_TARGET_SIZE;
+                if !valid_size.contains(&size.x) || !valid_size.contains(&size.y) {
+                    return RendererError::InvalidSize.into();
+                }
+
+                let camera = Camera {
+                    projection: CameraProjection::Perspective {
+                        vfov: *vfov,
+                        near: *near,
+                    },
+                    view: *view,
+                };
+
+                let target = RenderTarget::new(renderer, self.surface_format, *size, camera);
+                let target = Arc::new(target);
+
+                let token = rand::random::<[u8; 16]>().to_vec();
+                let lump = [RENDER_TARGET_MAGIC, token.as_slice()].concat();
+                let texture = request.runtime.lump_store.add_lump(lump.into()).await;
+
+                self.render_targets
+                    .lock()
+                    .unwrap()
+                    .insert(token.clone(), target.texture.clone());
+
+                let _ = self
+                    .command_tx
+                    .send(Rend3Command::AddRenderTarget(target.clone()));
+
+                let child = request.spawn(RenderTargetInstance {
+                    target,
+                    token,
+                    textures: self.render_targets.clone(),
+                });
+
+                return ResponseInfo {
+                    data: Ok(RendererSuccess::RenderTarget { texture }),
+                    caps: vec![child],
+                };
+            }
+            CreateViewport {
+                rect,
+                vfov,
+                near,
+                view,
+            } => {
+                if !rect.is_valid() {
+                    return RendererError::InvalidSize.into();
+                }
+
+                let camera = Camera {
+                    projection: CameraProjection::Perspective {
+                        vfov: *vfov,
+                        near: *near,
+                    },
+                    view: *view,
+                };
+
+                let viewport = Arc::new(Viewport::new(*rect, camera));
+
+                let _ = self
+                    .command_tx
+                    .send(Rend3Command::AddViewport(viewport.clone()));
+
+                let child = request.spawn(ViewportInstance { viewport });
+
+                return ResponseInfo {
+                    data: Ok(RendererSuccess::Ok),
+                    caps: vec![child],
+                };
+            }
+            Subscribe => {
+                let Some(sub) = request.cap_args.first() else {
+                    return RendererError::MissingSubscriber.into();
+                };
+
+                if sub.get_permissions().contains(Permissions::MONITOR) {
+                    sub.monitor(request.process.borrow_parent()).unwrap();
+                }
+
+                self.events.subscribe(sub.clone(), request.codec);
+            }
+            Unsubscribe => {
+                let Some(sub) = request.cap_args.first() else {
+                    return RendererError::MissingSubscriber.into();
+                };
+
+                self.events.unsubscribe(sub.clone());
+            }
         }
 
         ResponseInfo {
@@ -331,17 +791,42 @@ impl RequestResponseProcess for RendererService {
             caps: vec![],
         }
     }
+
+    async fn on_down<'a>(&'a mut self, cap: CapabilityRef<'a>) {
+        self.events.unsubscribe(cap);
+    }
 }
 
 impl ServiceRunner for RendererService {
     const NAME: &'static str = "hearth.Renderer";
 }
 
+/// The maximum width and height of a render target's texture.
+const MAX_RENDER_TARGET_SIZE: u32 = 4096;
+
 impl RendererService {
-    pub fn new(renderer: Arc<Renderer>, command_tx: UnboundedSender<Rend3Command>) -> Self {
+    /// Creates a new renderer service.
+    ///
+    /// `renderer` is replaced when the GPU device is lost,
+    /// `events` are the subscribers to [RendererEvent], `surface_format` is
+    /// the format that the scene is tonemapped to, `render_targets` must be
+    /// shared with the [TextureLoader], and `interpolator` moves dynamic
+    /// objects.
+    pub fn new(
+        renderer: Arc<RwLock<Arc<Renderer>>>,
+        events: Arc<PubSub<RendererEvent>>,
+        command_tx: UnboundedSender<Rend3Command>,
+        surface_format: TextureFormat,
+        render_targets: RenderTargetTextures,
+        interpolator: Arc<Interpolator>,
+    ) -> Self {
         Self {
             renderer,
+            events,
             command_tx,
+            surface_format,
+            render_targets,
+            interpolator,
         }
     }
 
@@ -367,6 +852,118 @@ impl RendererService {
     }
 }
 
+/// The longest transition between compositor effects.
+const MAX_TRANSITION: Duration = Duration::from_secs(60);
+
+/// Applies full-screen effects to the main window. Accepts
+/// [CompositorCommand].
+#[derive(GetProcessMetadata)]
+pub struct CompositorService {
+    compositor: Arc<Compositor>,
+}
+
+#[async_trait]
+impl SinkProcess for CompositorService {
+    type Message = CompositorCommand;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, Self::Message>) {
+        let duration = Duration::try_from_secs_f32(message.data.duration().max(0.0))
+            .unwrap_or(MAX_TRANSITION)
+            .min(MAX_TRANSITION);
+
+        use CompositorCommand::*;
+        match message.data {
+            Fade { color, amount, .. } => {
+                if color.is_finite() && amount.is_finite() {
+                    self.compositor.set_fade(color, amount, duration);
+                } else {
+                    warn!("Ignoring non-finite compositor fade");
+                }
+            }
+            Vignette { settings, .. } => {
+                let is_finite = settings.as_ref().map_or(true, |settings| {
+                    [settings.intensity, settings.radius, settings.softness]
+                        .iter()
+                        .all(|value| value.is_finite())
+                });
+
+                if is_finite {
+                    self.compositor.set_vignette(settings, duration);
+                } else {
+                    warn!("Ignoring non-finite compositor vignette");
+                }
+            }
+            ColorFilter { matrix, .. } => {
+                if matrix.is_finite() {
+                    self.compositor.set_color_filter(matrix, duration);
+                } else {
+                    warn!("Ignoring non-finite compositor color filter");
+                }
+            }
+            Reset { .. } => self.compositor.reset(duration),
+        }
+
+        let Some(reply) = message.caps.first() else {
+            return;
+        };
+
+        let reply = reply.to_owned();
+        let post = message.runtime.post.to_owned();
+
+        // notify the sender without blocking further commands
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+
+            let table = Table::new(post);
+            let Ok(reply_handle) = table.import_owned(reply) else {
+                return;
+            };
+
+            // the sender may have died in the meantime
+            let _ = table.send(reply_handle, &[], &[]).await;
+        });
+    }
+}
+
+impl ServiceRunner for CompositorService {
+    const NAME: &'static str = "hearth.Compositor";
+}
+
+/// The largest number of frames in a single profiler report.
+const MAX_REPORT_FRAMES: usize = 240;
+
+/// Reports the timings of recently drawn frames. Accepts [ProfilerRequest].
+#[derive(GetProcessMetadata)]
+pub struct ProfilerService {
+    profiler: Arc<Profiler>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for ProfilerService {
+    type Request = ProfilerRequest;
+    type Response = ProfilerReport;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, ProfilerRequest>,
+    ) -> ResponseInfo<'a, ProfilerReport> {
+        let ProfilerRequest::GetReport { frames } = request.data;
+        let frames = (frames as usize).min(MAX_REPORT_FRAMES);
+
+        ResponseInfo {
+            data: ProfilerReport {
+                gpu_timing: self.profiler.gpu_timing(),
+                frames: self.profiler.recent(frames),
+            },
+            caps: vec![],
+        }
+    }
+}
+
+impl ServiceRunner for ProfilerService {
+    const NAME: &'static str = PROFILER_SERVICE_NAME;
+}
+
 /// Initializes guest-available rendering code.
 #[derive(Default)]
 pub struct RendererPlugin {}
@@ -377,14 +974,117 @@ impl Plugin for RendererPlugin {
             .get_plugin::<Rend3Plugin>()
             .expect("rend3 plugin was not found");
 
-        let renderer = rend3.renderer.clone();
+        let current = rend3.subscribe_renderer();
+        let device_events = rend3.subscribe_device_events();
+        let renderer = Arc::new(RwLock::new(current.borrow().clone()));
         let command_tx = rend3.command_tx.clone();
+        let surface_format = rend3.surface_format;
+        let interpolator = rend3.interpolator.clone();
+        let compositor = rend3.compositor.clone();
+        let profiler = rend3.profiler.clone();
+        let render_targets = RenderTargetTextures::default();
+        let events = Arc::new(PubSub::new(builder.get_post()));
+
+        builder.add_runner({
+            let current = current.clone();
+            let renderer = renderer.clone();
+            let render_targets = render_targets.clone();
+            let events = events.clone();
+            move |runtime| {
+                tokio::spawn(watch_device(
+                    runtime,
+                    device_events,
+                    current,
+                    renderer,
+                    render_targets,
+                    events,
+                ));
+            }
+        });
 
         builder
-            .add_asset_loader(MeshLoader(renderer.clone()))
-            .add_asset_loader(MaterialLoader(renderer.clone()))
-            .add_asset_loader(TextureLoader(renderer.clone()))
-            .add_asset_loader(CubeTextureLoader(renderer.clone()))
-            .add_plugin(RendererService::new(renderer, command_tx));
+            .add_asset_loader(MeshLoader(current.clone()))
+            .add_asset_loader(MaterialLoader(current.clone()))
+            .add_asset_loader(TextureLoader(current.clone(), render_targets.clone()))
+            .add_asset_loader(CubeTextureLoader(current.clone()))
+            .add_asset_loader(ColorLutLoader(current))
+            .add_plugin(RendererService::new(
+                renderer,
+                events,
+                command_tx,
+                surface_format,
+                render_targets,
+                interpolator,
+            ))
+            .add_plugin(CompositorService { compositor })
+            .add_plugin(ProfilerService { profiler });
+    }
+}
+
+/// Moves the [RendererService] onto the new renderer whenever rend3 recovers
+/// from a lost GPU device, and notifies subscribers of [RendererEvent].
+///
+/// Renderer requests are held off from when the device is lost until every
+/// cached asset has been re-uploaded to the new renderer. Resources that guests
+/// created on the old renderer are left on it and are no longer drawn.
+async fn watch_device(
+    runtime: Arc<Runtime>,
+    mut device_events: broadcast::Receiver<DeviceEvent>,
+    current: CurrentRenderer,
+    renderer: Arc<RwLock<Arc<Renderer>>>,
+    render_targets: RenderTargetTextures,
+    events: Arc<PubSub<RendererEvent>>,
+) {
+    let mut paused = None;
+
+    loop {
+        let event = match device_events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(num)) => {
+                warn!("Missed {} device events", num);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        match event {
+            DeviceEvent::Lost => {
+                if paused.is_none() {
+                    paused = Some(renderer.clone().write_owned().await);
+                    events.notify(&RendererEvent::DeviceLost).await;
+                }
+            }
+            DeviceEvent::Restored => {
+                let mut guard = match paused.take() {
+                    Some(guard) => guard,
+                    None => renderer.clone().write_owned().await,
+                };
+
+                // render targets were drawn by the old renderer
+                render_targets.lock().unwrap().clear();
+
+                // materials reference textures, so reload textures first
+                let store = &runtime.asset_store;
+                let results = [
+                    store.reload_assets::<TextureLoader>().await,
+                    store.reload_assets::<CubeTextureLoader>().await,
+                    store.reload_assets::<MaterialLoader>().await,
+                    store.reload_assets::<MeshLoader>().await,
+                    store.reload_assets::<ColorLutLoader>().await,
+                ];
+
+                for result in results {
+                    if let Err(err) = result {
+                        error!("Failed to reload renderer assets: {:?}", err);
+                    }
+                }
+
+                *guard = current.borrow().clone();
+                drop(guard);
+
+                info!("Renderer assets restored after device loss");
+                events.notify(&RendererEvent::DeviceRestored).await;
+            }
+        }
     }
 }
@@ -0,0 +1,409 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Golden image tests for the renderer.
+//!
+//! Each test renders a small scene offscreen and compares it against a
+//! reference PNG in `tests/golden/`. These tests need a GPU, so they're
+//! ignored by default, and fail without one. Run them with:
+//!
+//! ```sh
+//! cargo test -p hearth-renderer --test golden -- --ignored
+//! ```
+//!
+//! After an intentional rendering change, regenerate the reference images by
+//! setting `HEARTH_UPDATE_GOLDEN=1` and review the new images before
+//! committing them. When a comparison fails, the mismatching frame is written
+//! next to the reference as `<name>.actual.png`.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::{Mat4, Quat, UVec2, Vec2, Vec3, Vec4};
+use hearth_rend3::{
+    rend3::{self, types::*, util::output::OutputFrame, InstanceAdapterDevice, Renderer},
+    wgpu, FrameRequest, Rend3Command, Rend3Plugin,
+};
+use hearth_renderer::{MaterialLoader, MeshLoader, RendererPlugin};
+use hearth_runtime::{
+    asset::AssetLoader,
+    hearth_schema::{renderer::*, ByteVec, LumpId},
+    runtime::{Runtime, RuntimeBuilder, RuntimeConfig},
+};
+use serde::Serialize;
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+
+/// The width and height of every rendered frame.
+///
+/// 64 RGBA8 pixels make a 256-byte row, which texture-to-buffer copies need.
+const SIZE: u32 = 64;
+
+/// The format of every rendered frame.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// The perceptual color difference (from 0 to 1) above which a pixel is
+/// considered mismatched.
+const THRESHOLD: f32 = 0.1;
+
+/// The fraction of pixels that may mismatch before a comparison fails.
+const MAX_MISMATCHED: f32 = 0.01;
+
+/// A headless renderer with a runtime to load assets with.
+struct Harness {
+    iad: InstanceAdapterDevice,
+    runtime: Arc<Runtime>,
+    renderer: Arc<Renderer>,
+    frame_request_tx: UnboundedSender<FrameRequest>,
+    command_tx: UnboundedSender<Rend3Command>,
+}
+
+impl Harness {
+    /// Creates a new harness.
+    ///
+    /// Panics if there's no usable GPU. These tests only run when they're
+    /// asked for with `--ignored`, so a missing GPU fails them instead of
+    /// letting them pass without comparing anything.
+    async fn new() -> Self {
+        let iad = rend3::create_iad(None, None, None, None)
+            .await
+            .unwrap_or_else(|err| panic!("golden image tests need a GPU: {:?}", err));
+
+        let rend3 = Rend3Plugin::new(iad.to_owned(), FORMAT);
+        let renderer = rend3.renderer.clone();
+        let frame_request_tx = rend3.frame_request_tx.clone();
+        let command_tx = rend3.command_tx.clone();
+
+        let mut builder = RuntimeBuilder::new();
+        builder.add_plugin(rend3);
+        builder.add_plugin(RendererPlugin::default());
+        let runtime = builder.run(RuntimeConfig::default()).await;
+
+        Self {
+            iad,
+            runtime,
+            renderer,
+            frame_request_tx,
+            command_tx,
+        }
+    }
+
+    /// Adds JSON-encoded data to the lump store.
+    async fn add_lump(&self, data: &impl Serialize) -> LumpId {
+        let data = serde_json::to_vec(data).unwrap();
+        self.runtime.lump_store.add_lump(data.into()).await
+    }
+
+    /// Loads an asset through the renderer's asset loaders.
+    async fn load<L: AssetLoader>(&self, lump: &LumpId) -> Arc<L::Asset> {
+        self.runtime
+            .asset_store
+            .load_asset::<L>(lump)
+            .await
+            .unwrap()
+    }
+
+    /// Loads a mesh and a material and adds them to the scene as an object.
+    async fn add_object(
+        &self,
+        mesh: &MeshData,
+        texture: &TextureData,
+        transform: Mat4,
+    ) -> ObjectHandle {
+        let mesh = self.add_lump(mesh).await;
+        let albedo = self.add_lump(texture).await;
+        let material = self.add_lump(&MaterialData { albedo }).await;
+
+        self.renderer.add_object(Object {
+            mesh_kind: ObjectMeshKind::Static(
+                self.load::<MeshLoader>(&mesh).await.as_ref().to_owned(),
+            ),
+            material: self
+                .load::<MaterialLoader>(&material)
+                .await
+                .as_ref()
+                .to_owned(),
+            transform,
+        })
+    }
+
+    /// Renders a frame and reads back its RGBA pixels.
+    async fn render(&self, camera: Camera) -> Vec<u8> {
+        let device = &self.iad.device;
+        let extent = wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("golden image target"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        let view = texture.create_view(&Default::default());
+        let (on_complete, on_complete_rx) = oneshot::channel();
+
+        self.frame_request_tx
+            .send(FrameRequest {
+                output_frame: OutputFrame::View(Arc::new(view)),
+                resolution: UVec2::splat(SIZE),
+                camera,
+                on_complete,
+            })
+            .unwrap();
+
+        on_complete_rx.await.unwrap();
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("golden image readback"),
+            size: (SIZE * SIZE * 4) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            extent,
+        );
+
+        self.iad.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapped = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        mapped.await.unwrap();
+
+        let pixels = slice.get_mapped_range().to_vec();
+        pixels
+    }
+}
+
+/// Creates a camera at `eye` looking at the origin.
+fn look_at(eye: Vec3) -> Camera {
+    Camera {
+        projection: CameraProjection::Perspective {
+            vfov: 60.0,
+            near: 0.1,
+        },
+        view: Mat4::look_at_rh(eye, Vec3::ZERO, Vec3::Y),
+    }
+}
+
+/// Creates the mesh of a unit cube centered on the origin.
+fn cube() -> MeshData {
+    let faces = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+    let corners = [
+        Vec2::new(-1.0, -1.0),
+        Vec2::new(1.0, -1.0),
+        Vec2::new(1.0, 1.0),
+        Vec2::new(-1.0, 1.0),
+    ];
+
+    let mut mesh = MeshData {
+        positions: ByteVec(vec![]),
+        normals: ByteVec(vec![]),
+        tangents: ByteVec(vec![]),
+        uv0: ByteVec(vec![]),
+        uv1: ByteVec(vec![]),
+        colors: ByteVec(vec![]),
+        joint_indices: ByteVec(vec![]),
+        joint_weights: ByteVec(vec![]),
+        indices: ByteVec(vec![]),
+    };
+
+    for normal in faces {
+        let rotation = Quat::from_rotation_arc(Vec3::Z, normal);
+        let tangent = rotation * Vec3::X;
+        let bitangent = rotation * Vec3::Y;
+        let base = mesh.positions.len() as u32;
+
+        for corner in corners {
+            let position = normal + tangent * corner.x + bitangent * corner.y;
+            mesh.positions.push(position * 0.5);
+            mesh.normals.push(normal);
+            mesh.tangents.push(tangent);
+            mesh.uv0.push((corner + 1.0) * 0.5);
+            mesh.uv1.push(Vec2::ZERO);
+            mesh.colors.push([0xff; 4]);
+            mesh.joint_indices.push([0; 4]);
+            mesh.joint_weights.push(Vec4::ZERO);
+        }
+
+        for index in [0, 1, 2, 0, 2, 3] {
+            mesh.indices.push(base + index);
+        }
+    }
+
+    mesh
+}
+
+/// Creates a 4x4 checkerboard texture of two colors.
+fn checkerboard(a: [u8; 4], b: [u8; 4]) -> TextureData {
+    let data = (0..16)
+        .flat_map(|idx| if (idx % 4 + idx / 4) % 2 == 0 { a } else { b })
+        .collect();
+
+    TextureData {
+        label: Some("checkerboard".into()),
+        size: UVec2::splat(4),
+        data,
+    }
+}
+
+/// Returns the perceptual difference between two RGBA pixels from 0 to 1.
+///
+/// Based on the YIQ color difference metric used by pixelmatch.
+fn color_delta(a: &[u8], b: &[u8]) -> f32 {
+    let yiq = |p: &[u8]| {
+        let [r, g, b] = [p[0], p[1], p[2]].map(f32::from);
+        Vec3::new(
+            r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+            r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+            r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+        )
+    };
+
+    let d = yiq(a) - yiq(b);
+    let delta = 0.5053 * d.x * d.x + 0.299 * d.y * d.y + 0.1957 * d.z * d.z;
+    (delta / 35215.0).sqrt()
+}
+
+/// Writes RGBA pixels to a PNG file.
+fn write_png(path: &Path, pixels: &[u8]) {
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, SIZE, SIZE);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(pixels).unwrap();
+}
+
+/// Reads RGBA pixels from a PNG file.
+fn read_png(path: &Path) -> Result<Vec<u8>, png::DecodingError> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+    assert_eq!((info.width, info.height), (SIZE, SIZE), "golden image size");
+    assert_eq!(info.color_type, png::ColorType::Rgba, "golden image format");
+    pixels.truncate(info.buffer_size());
+    Ok(pixels)
+}
+
+/// Compares a rendered frame with its golden image, or updates the golden
+/// image if `HEARTH_UPDATE_GOLDEN` is set.
+fn check_golden(name: &str, pixels: &[u8]) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let path = dir.join(format!("{}.png", name));
+
+    if std::env::var_os("HEARTH_UPDATE_GOLDEN").is_some() {
+        write_png(&path, pixels);
+        return;
+    }
+
+    let golden = read_png(&path).unwrap_or_else(|err| {
+        panic!(
+            "failed to read golden image {:?} ({}); run with HEARTH_UPDATE_GOLDEN=1 to create it",
+            path, err
+        )
+    });
+
+    let mismatched = pixels
+        .chunks_exact(4)
+        .zip(golden.chunks_exact(4))
+        .filter(|(a, b)| color_delta(a, b) > THRESHOLD)
+        .count();
+
+    let ratio = mismatched as f32 / (SIZE * SIZE) as f32;
+    if ratio > MAX_MISMATCHED {
+        let actual = dir.join(format!("{}.actual.png", name));
+        write_png(&actual, pixels);
+        panic!(
+            "{:.1}% of pixels differ from {:?}; see {:?}",
+            ratio * 100.0,
+            path,
+            actual
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires a GPU"]
+async fn ambient_cube() {
+    let harness = Harness::new().await;
+
+    let texture = checkerboard([0xff, 0x00, 0x00, 0xff], [0x00, 0x00, 0xff, 0xff]);
+    let _cube = harness.add_object(&cube(), &texture, Mat4::IDENTITY).await;
+    let _ = harness.command_tx.send(Rend3Command::SetAmbient(Vec4::ONE));
+
+    let pixels = harness.render(look_at(Vec3::new(1.5, 1.0, 2.0))).await;
+    check_golden("ambient_cube", &pixels);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires a GPU"]
+async fn directional_light() {
+    let harness = Harness::new().await;
+
+    let texture = checkerboard([0xff; 4], [0x40, 0x40, 0x40, 0xff]);
+    let transform = Mat4::from_rotation_y(0.5);
+    let _cube = harness.add_object(&cube(), &texture, transform).await;
+
+    let floor = Mat4::from_scale_rotation_translation(
+        Vec3::new(4.0, 0.1, 4.0),
+        Quat::IDENTITY,
+        Vec3::new(0.0, -0.55, 0.0),
+    );
+
+    let _floor = harness.add_object(&cube(), &texture, floor).await;
+
+    let _light = harness.renderer.add_directional_light(DirectionalLight {
+        color: Vec3::ONE,
+        intensity: 4.0,
+        direction: Vec3::new(-1.0, -4.0, -2.0),
+        distance: 10.0,
+    });
+
+    let pixels = harness.render(look_at(Vec3::new(2.0, 1.5, 2.5))).await;
+    check_golden("directional_light", &pixels);
+}
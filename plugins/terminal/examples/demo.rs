@@ -112,7 +112,7 @@ impl DemoInner {
 
         let command = None; // autoselect shell
         let config = TerminalConfig { fonts, command };
-        let terminal = Terminal::new(config.clone(), state.clone());
+        let terminal = Terminal::new(config.clone(), state.clone()).unwrap();
         let draw_state = TerminalDrawState::new(&pipelines, terminal.get_fonts());
 
         // load skybox
@@ -439,6 +439,8 @@ pub struct TerminalDrawState {
     pub grid_buffer: Buffer,
     pub grid_texture: Texture,
     pub grid_bind_group: BindGroup,
+    /// The grid contents that were last uploaded to [Self::grid_texture].
+    pub grid_contents: Vec<u32>,
     pub grid_size: UVec2,
     pub grid_capacity: UVec2,
     pub grid_half_size: Vec2,
@@ -559,6 +561,7 @@ impl TerminalDrawState {
             grid_buffer,
             grid_texture,
             grid_bind_group,
+            grid_contents: Vec::new(),
             grid_size,
             grid_capacity,
             grid_half_size: Vec2::ZERO,
@@ -16,18 +16,27 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use draw::{TerminalDrawState, TerminalPipelines};
+use hearth_rend3::rend3::Renderer;
 use hearth_rend3::*;
 use hearth_runtime::{
-    async_trait,
+    anyhow, async_trait,
     hearth_macros::GetProcessMetadata,
-    runtime::{Plugin, RuntimeBuilder},
-    tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    runtime::{Plugin, Runtime, RuntimeBuilder},
+    tokio::{
+        sync::{
+            mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+            watch,
+        },
+        task::spawn_blocking,
+    },
+    tracing::warn,
     utils::*,
 };
-use hearth_schema::terminal::*;
+use hearth_schema::{terminal::*, LumpId};
+use owned_ttf_parser::{FaceParsingError, OwnedFace};
 use terminal::{Terminal, TerminalConfig};
 use text::{FaceAtlas, FontSet};
 
@@ -52,6 +61,11 @@ impl TerminalWrapper {
         let quit = self.terminal.should_quit();
 
         if !quit {
+            // the draw state is bound to the previous fonts' atlases
+            if self.terminal.take_fonts_changed() {
+                self.draw_state = TerminalDrawState::new(pipelines, self.terminal.get_fonts());
+            }
+
             self.terminal
                 .update_draw_state(pipelines, &mut self.draw_state);
         }
@@ -60,14 +74,29 @@ impl TerminalWrapper {
     }
 }
 
+/// The font atlases that new terminals are created with, shared between the
+/// [TerminalRoutine] and the [TerminalFactory].
+pub type SharedFonts = Arc<Mutex<FontSet<Arc<FaceAtlas>>>>;
+
 pub struct TerminalRoutine {
     pipelines: TerminalPipelines,
     terminals: Vec<TerminalWrapper>,
     new_terminals: UnboundedReceiver<Arc<Terminal>>,
+    ttf_srcs: FontSet<Vec<u8>>,
+    fonts: SharedFonts,
 }
 
 impl TerminalRoutine {
-    pub fn new(rend3: &Rend3Plugin, new_terminals: UnboundedReceiver<Arc<Terminal>>) -> Self {
+    /// Creates a new terminal routine.
+    ///
+    /// `ttf_srcs` are the TrueType fonts that `fonts` were loaded from, which
+    /// are re-loaded if the GPU device is lost.
+    pub fn new(
+        rend3: &Rend3Plugin,
+        new_terminals: UnboundedReceiver<Arc<Terminal>>,
+        ttf_srcs: FontSet<Vec<u8>>,
+        fonts: SharedFonts,
+    ) -> Self {
         Self {
             pipelines: TerminalPipelines::new(
                 rend3.renderer.device.to_owned(),
@@ -76,8 +105,75 @@ impl TerminalRoutine {
             ),
             terminals: vec![],
             new_terminals,
+            ttf_srcs,
+            fonts,
+        }
+    }
+}
+
+/// Loads a set of TrueType fonts into font atlases on the rend3 device.
+fn load_fonts(rend3: &Rend3Plugin, ttf_srcs: FontSet<Vec<u8>>) -> FontSet<Arc<FaceAtlas>> {
+    load_atlases(&rend3.renderer, parse_faces(ttf_srcs).unwrap())
+}
+
+/// Parses a set of TrueType fonts.
+fn parse_faces(ttf_srcs: FontSet<Vec<u8>>) -> Result<FontSet<OwnedFace>, FaceParsingError> {
+    Ok(FontSet {
+        regular: OwnedFace::from_vec(ttf_srcs.regular, 0)?,
+        italic: OwnedFace::from_vec(ttf_srcs.italic, 0)?,
+        bold: OwnedFace::from_vec(ttf_srcs.bold, 0)?,
+        bold_italic: OwnedFace::from_vec(ttf_srcs.bold_italic, 0)?,
+    })
+}
+
+/// Creates font atlases for a set of faces on a renderer's device.
+fn load_atlases(renderer: &Renderer, faces: FontSet<OwnedFace>) -> FontSet<Arc<FaceAtlas>> {
+    faces.map(|face| {
+        let face_atlas = FaceAtlas::new(face, &renderer.device, renderer.queue.to_owned());
+        Arc::new(face_atlas)
+    })
+}
+
+/// Loads a guest-provided terminal font from lumps into font atlases.
+async fn load_lump_fonts(
+    runtime: &Runtime,
+    renderer: Arc<Renderer>,
+    fonts: &TerminalFonts,
+) -> anyhow::Result<FontSet<Arc<FaceAtlas>>> {
+    let get = |id: LumpId| async move {
+        let data = runtime.lump_store.get_lump(&id).await;
+        data.map(|data| data.to_vec())
+            .ok_or_else(|| anyhow::anyhow!("font lump {} not found", id))
+    };
+
+    // missing styles fall back to the regular style
+    let regular = get(fonts.regular).await?;
+    let mut ttf_srcs = FontSet {
+        regular: regular.clone(),
+        italic: regular.clone(),
+        bold: regular.clone(),
+        bold_italic: regular,
+    };
+
+    let styles = [
+        (fonts.italic, &mut ttf_srcs.italic),
+        (fonts.bold, &mut ttf_srcs.bold),
+        (fonts.bold_italic, &mut ttf_srcs.bold_italic),
+    ];
+
+    for (id, src) in styles {
+        if let Some(id) = id {
+            *src = get(id).await?;
         }
     }
+
+    // generating the atlases takes a while
+    let atlases = spawn_blocking(move || {
+        let faces = parse_faces(ttf_srcs)?;
+        anyhow::Ok(load_atlases(&renderer, faces))
+    });
+
+    atlases.await?
 }
 
 impl Routine for TerminalRoutine {
@@ -97,6 +193,21 @@ impl Routine for TerminalRoutine {
             draws: self.terminals.iter().map(|term| &term.draw_state).collect(),
         })
     }
+
+    fn recreate(&mut self, rend3: &Rend3Plugin) {
+        self.pipelines = TerminalPipelines::new(
+            rend3.renderer.device.to_owned(),
+            rend3.renderer.queue.to_owned(),
+            rend3.surface_format,
+        );
+
+        // existing terminals use the old device's font atlases, so they're
+        // dropped and guests create new ones
+        self.terminals.clear();
+        while self.new_terminals.try_recv().is_ok() {}
+
+        *self.fonts.lock().unwrap() = load_fonts(rend3, self.ttf_srcs.clone());
+    }
 }
 
 pub struct TerminalNode<'a> {
@@ -107,7 +218,7 @@ pub struct TerminalNode<'a> {
 impl<'a> Node<'a> for TerminalNode<'a> {
     fn draw<'graph>(&'graph self, info: &mut RoutineInfo<'_, 'graph>) {
         let output = info.graph.add_surface_texture();
-        let depth = info.state.depth;
+        let depth = info.depth;
         self.pipelines
             .add_to_graph(self.draws.as_slice(), info.graph, output, depth);
     }
@@ -117,6 +228,8 @@ impl<'a> Node<'a> for TerminalNode<'a> {
 #[derive(GetProcessMetadata)]
 pub struct TerminalSink {
     inner: Arc<Terminal>,
+    fonts: SharedFonts,
+    renderer: watch::Receiver<Arc<Renderer>>,
 }
 
 impl Drop for TerminalSink {
@@ -140,6 +253,39 @@ impl SinkProcess for TerminalSink {
             TerminalUpdate::State(state) => {
                 self.inner.update(state);
             }
+            TerminalUpdate::Scroll(scroll) => {
+                self.inner.scroll(scroll);
+            }
+            TerminalUpdate::Read { start, count } => {
+                let Some(reply) = request.caps.first() else {
+                    warn!("terminal read is missing a reply capability");
+                    return;
+                };
+
+                let contents = self.inner.read(start, count);
+                let data = request.codec.encode(&contents);
+                let _ = reply.send(&data, &[]).await;
+            }
+            TerminalUpdate::SetFont {
+                fonts,
+                units_per_em,
+            } => {
+                let atlases = match fonts {
+                    None => self.fonts.lock().unwrap().to_owned(),
+                    Some(fonts) => {
+                        let renderer = self.renderer.borrow().clone();
+                        match load_lump_fonts(request.runtime, renderer, &fonts).await {
+                            Ok(atlases) => atlases,
+                            Err(err) => {
+                                warn!("failed to load terminal font: {:?}", err);
+                                return;
+                            }
+                        }
+                    }
+                };
+
+                self.inner.set_fonts(atlases, units_per_em);
+            }
         }
     }
 }
@@ -147,8 +293,13 @@ impl SinkProcess for TerminalSink {
 /// The native terminal emulator factory service. Accepts FactoryRequest.
 #[derive(GetProcessMetadata)]
 pub struct TerminalFactory {
-    fonts: FontSet<Arc<FaceAtlas>>,
+    fonts: SharedFonts,
+    renderer: watch::Receiver<Arc<Renderer>>,
     new_terminals_tx: UnboundedSender<Arc<Terminal>>,
+
+    /// Whether this factory accepts custom commands. Only true for the
+    /// [COMMAND_SERVICE_NAME] service.
+    allow_commands: bool,
 }
 
 #[async_trait]
@@ -160,17 +311,38 @@ impl RequestResponseProcess for TerminalFactory {
         &'a mut self,
         request: &mut RequestInfo<'a, Self::Request>,
     ) -> ResponseInfo<'a, Self::Response> {
-        let FactoryRequest::CreateTerminal(state) = &request.data;
+        let FactoryRequest::CreateTerminal { state, command } = &request.data;
+
+        if command.is_some() && !self.allow_commands {
+            return ResponseInfo {
+                data: Err(FactoryError::CommandNotAllowed),
+                caps: vec![],
+            };
+        }
 
         let config = TerminalConfig {
-            fonts: self.fonts.to_owned(),
-            command: None,
+            fonts: self.fonts.lock().unwrap().to_owned(),
+            command: command.clone(),
+        };
+
+        let terminal = match Terminal::new(config, state.clone()) {
+            Ok(terminal) => terminal,
+            Err(err) => {
+                warn!("failed to start terminal command: {:?}", err);
+                return ResponseInfo {
+                    data: Err(FactoryError::SpawnFailed),
+                    caps: vec![],
+                };
+            }
         };
 
-        let terminal = Terminal::new(config, state.clone());
         let _ = self.new_terminals_tx.send(terminal.clone());
 
-        let child = request.spawn(TerminalSink { inner: terminal });
+        let child = request.spawn(TerminalSink {
+            inner: terminal,
+            fonts: self.fonts.clone(),
+            renderer: self.renderer.clone(),
+        });
 
         ResponseInfo {
             data: Ok(FactorySuccess::Terminal),
@@ -180,7 +352,7 @@ impl RequestResponseProcess for TerminalFactory {
 }
 
 impl ServiceRunner for TerminalFactory {
-    const NAME: &'static str = "hearth.terminal.TerminalFactory";
+    const NAME: &'static str = SERVICE_NAME;
 }
 
 #[derive(Default)]
@@ -200,25 +372,31 @@ impl Plugin for TerminalPlugin {
                 .to_vec(),
         };
 
-        let fonts = ttf_srcs.map(|src| {
-            let face = owned_ttf_parser::OwnedFace::from_vec(src, 0).unwrap();
-
-            let face_atlas = FaceAtlas::new(
-                face,
-                &rend3.renderer.device,
-                rend3.renderer.queue.to_owned(),
-            );
-
-            Arc::new(face_atlas)
-        });
+        let fonts = load_fonts(rend3, ttf_srcs.clone());
+        let fonts = Arc::new(Mutex::new(fonts));
 
         let (new_terminals_tx, new_terminals) = unbounded_channel();
-
-        rend3.add_routine(TerminalRoutine::new(rend3, new_terminals));
+        let renderer = rend3.subscribe_renderer();
+
+        let routine = TerminalRoutine::new(rend3, new_terminals, ttf_srcs, fonts.clone());
+        rend3.add_routine(routine);
+
+        builder.add_service(
+            COMMAND_SERVICE_NAME.to_string(),
+            TerminalFactory::get_process_metadata(),
+            TerminalFactory {
+                fonts: fonts.clone(),
+                renderer: renderer.clone(),
+                new_terminals_tx: new_terminals_tx.clone(),
+                allow_commands: true,
+            },
+        );
 
         builder.add_plugin(TerminalFactory {
             fonts,
+            renderer,
             new_terminals_tx,
+            allow_commands: false,
         });
     }
 }
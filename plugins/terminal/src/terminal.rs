@@ -17,6 +17,8 @@
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
+    io::ErrorKind,
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::{channel, Sender},
@@ -27,10 +29,11 @@ use std::{
 
 use alacritty_terminal::{
     ansi::{Color, CursorShape, NamedColor},
-    config::PtyConfig,
+    config::{Program, PtyConfig},
     event::{Event, EventListener},
     event_loop::{EventLoop, Msg, State},
-    grid::Indexed,
+    grid::{Dimensions, Indexed, Row, Scroll},
+    index::Line,
     sync::FairMutex,
     term::{
         cell::{Cell, Flags},
@@ -42,7 +45,10 @@ use alacritty_terminal::{
 };
 use glam::{vec2, IVec2, Mat4, UVec2, Vec2};
 use hearth_rend3::wgpu::{Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect};
-use hearth_schema::terminal::TerminalState;
+use hearth_schema::terminal::{
+    TerminalCommand, TerminalContents, TerminalFlags, TerminalLine, TerminalScroll, TerminalSpan,
+    TerminalState, MAX_READ_LINES,
+};
 use mio_extras::channel::Sender as MioSender;
 use owned_ttf_parser::AsFaceRef;
 
@@ -75,23 +81,82 @@ pub struct TerminalConfig {
     /// The command that this terminal will run.
     ///
     /// Defaults to a platform-specific shell.
-    pub command: Option<String>,
+    pub command: Option<TerminalCommand>,
 }
 
 impl TerminalConfig {
-    fn unwrap_command(&self) -> String {
-        match self.command.to_owned() {
-            Some(command) => command,
-            None => match std::env::consts::OS {
-                "dragonfly" | "freebsd" | "haiku" | "linux" | "macos" | "netbsd" | "openbsd"
-                | "redox" | "solaris" | "unix" => {
-                    std::env::var("SHELL").expect("Couldn't get system shell: `$SHELL` not set. ")
-                }
-                "windows" => std::env::var("COMSPEC")
-                    .expect("Couldn't get system shell: `%COMSPEC%` not set. "),
-                _ => todo!("OS {} is unrecognized", std::env::consts::OS),
-            },
+    fn default_shell() -> String {
+        match std::env::consts::OS {
+            "dragonfly" | "freebsd" | "haiku" | "linux" | "macos" | "netbsd" | "openbsd"
+            | "redox" | "solaris" | "unix" => {
+                std::env::var("SHELL").expect("Couldn't get system shell: `$SHELL` not set. ")
+            }
+            "windows" => {
+                std::env::var("COMSPEC").expect("Couldn't get system shell: `%COMSPEC%` not set. ")
+            }
+            _ => todo!("OS {} is unrecognized", std::env::consts::OS),
+        }
+    }
+
+    /// Gets the program to run and its working directory.
+    fn program(&self) -> std::io::Result<(Program, Option<PathBuf>)> {
+        let Some(command) = self.command.as_ref() else {
+            return Ok((Program::Just(Self::default_shell()), None));
+        };
+
+        let working_directory = command.working_directory.as_ref().map(PathBuf::from);
+
+        if command.env.is_empty() {
+            let program = Program::WithArgs {
+                program: command.program.clone(),
+                args: command.args.clone(),
+            };
+
+            return Ok((program, working_directory));
+        }
+
+        // alacritty only sets environment variables for the whole host
+        // process, so they're passed to the child through `env` instead
+        if !cfg!(unix) {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "terminal environment variables are only supported on Unix",
+            ));
+        }
+
+        // `env` would parse these as options or more variables instead of
+        // the program to run
+        if command.program.starts_with('-') || command.program.contains('=') {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "program {:?} can't be run with environment variables",
+                    command.program
+                ),
+            ));
         }
+
+        let mut args = Vec::with_capacity(command.env.len() + command.args.len() + 1);
+        for (key, value) in command.env.iter() {
+            if key.is_empty() || key.starts_with('-') || key.contains(['=', '\0']) {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid environment variable name {:?}", key),
+                ));
+            }
+
+            args.push(format!("{}={}", key, value));
+        }
+
+        args.push(command.program.clone());
+        args.extend(command.args.iter().cloned());
+
+        let program = Program::WithArgs {
+            program: "env".to_string(),
+            args,
+        };
+
+        Ok((program, working_directory))
     }
 }
 
@@ -150,6 +215,45 @@ impl From<Arc<FaceAtlas>> for FaceWithMetrics {
 struct TerminalInner {
     grid_size: UVec2,
     state: TerminalState,
+    fonts: LoadedFonts,
+
+    /// Set when the fonts are replaced so that the draw state is re-created
+    /// for the new font atlases.
+    fonts_changed: bool,
+}
+
+impl TerminalInner {
+    /// Gets the size of the grid that fits the current state and fonts.
+    fn fit_grid(&self) -> UVec2 {
+        let available = (self.state.half_size - self.state.padding) * 2.0;
+        (available / self.fonts.cell_size / self.state.units_per_em)
+            .floor()
+            .as_uvec2()
+    }
+}
+
+/// A terminal's fonts and their layout metrics.
+#[derive(Clone)]
+struct LoadedFonts {
+    fonts: FontSet<FaceWithMetrics>,
+    baselines: FontSet<f32>,
+    cell_size: Vec2,
+}
+
+impl LoadedFonts {
+    fn new(atlases: FontSet<Arc<FaceAtlas>>) -> Self {
+        let fonts = atlases.map(FaceWithMetrics::from);
+        let cell_size = Vec2::new(fonts.regular.width, fonts.regular.height);
+        let baselines = fonts
+            .as_ref()
+            .map(|font| (cell_size.y - font.height) / 2.0 + font.ascender);
+
+        Self {
+            fonts,
+            baselines,
+            cell_size,
+        }
+    }
 }
 
 /// A CPU-side wrapper around terminal functionality.
@@ -159,23 +263,22 @@ pub struct Terminal {
     term_channel: FairMutex<MioSender<Msg>>,
     should_quit: AtomicBool,
     inner: FairMutex<TerminalInner>,
-    fonts: FontSet<FaceWithMetrics>,
-    font_baselines: FontSet<f32>,
-    cell_size: Vec2,
 }
 
 impl Terminal {
-    pub fn new(config: TerminalConfig, initial_state: TerminalState) -> Arc<Self> {
-        let fonts = config.fonts.clone().map(FaceWithMetrics::from);
-        let cell_size = Vec2::new(fonts.regular.width, fonts.regular.height);
-        let font_baselines = fonts
-            .as_ref()
-            .map(|font| (cell_size.y - font.height) / 2.0 + font.ascender);
+    /// Creates a new terminal and starts its command.
+    ///
+    /// Fails if the command is malformed or could not be started.
+    pub fn new(config: TerminalConfig, initial_state: TerminalState) -> std::io::Result<Arc<Self>> {
+        let mut inner = TerminalInner {
+            grid_size: UVec2::ZERO,
+            state: initial_state,
+            fonts: LoadedFonts::new(config.fonts.clone()),
+            fonts_changed: false,
+        };
 
-        let available = (initial_state.half_size - initial_state.padding) * 2.0;
-        let grid_size = (available / cell_size / initial_state.units_per_em)
-            .floor()
-            .as_uvec2();
+        let grid_size = inner.fit_grid();
+        inner.grid_size = grid_size;
 
         let size_info = alacritty_terminal::term::SizeInfo::new(
             grid_size.x as f32,
@@ -189,13 +292,12 @@ impl Terminal {
 
         let (sender, term_events) = channel();
 
-        let command = config.unwrap_command();
-        let shell = alacritty_terminal::config::Program::Just(command);
+        let (shell, working_directory) = config.program()?;
 
         let term_config = alacritty_terminal::config::Config {
             pty_config: PtyConfig {
                 shell: Some(shell),
-                working_directory: None,
+                working_directory,
                 hold: false,
             },
             ..Default::default()
@@ -210,26 +312,18 @@ impl Terminal {
         let term = FairMutex::new(term);
         let term = Arc::new(term);
 
-        let pty = alacritty_terminal::tty::new(&term_config.pty_config, &size_info, None).unwrap();
+        let pty = alacritty_terminal::tty::new(&term_config.pty_config, &size_info, None)?;
 
         let term_listener = Listener::new(sender);
         let term_loop = EventLoop::new(term.clone(), term_listener, pty, false, false);
         let term_channel = term_loop.channel();
 
-        let inner = TerminalInner {
-            grid_size,
-            state: initial_state,
-        };
-
         let term = Self {
-            fonts,
             term,
             _term_loop: term_loop.spawn(),
             term_channel: FairMutex::new(term_channel),
             should_quit: AtomicBool::new(false),
             inner: FairMutex::new(inner),
-            cell_size,
-            font_baselines,
         };
 
         let term = Arc::new(term);
@@ -241,20 +335,37 @@ impl Terminal {
             }
         });
 
-        term
+        Ok(term)
     }
 
     pub fn get_fonts(&self) -> FontSet<Arc<FaceAtlas>> {
-        self.fonts.as_ref().map(|font| font.atlas.to_owned())
+        let inner = self.inner.lock();
+        inner.fonts.fonts.as_ref().map(|font| font.atlas.to_owned())
+    }
+
+    /// Returns true once after this terminal's fonts have been replaced.
+    pub fn take_fonts_changed(&self) -> bool {
+        std::mem::take(&mut self.inner.lock().fonts_changed)
     }
 
     pub fn update(&self, state: TerminalState) {
         let mut inner = self.inner.lock();
+        inner.state = state;
+        self.resize(&mut inner);
+    }
+
+    /// Replaces this terminal's fonts and the size of an em.
+    pub fn set_fonts(&self, fonts: FontSet<Arc<FaceAtlas>>, units_per_em: f32) {
+        let mut inner = self.inner.lock();
+        inner.fonts = LoadedFonts::new(fonts);
+        inner.fonts_changed = true;
+        inner.state.units_per_em = units_per_em;
+        self.resize(&mut inner);
+    }
 
-        let available = (state.half_size - state.padding) * 2.0;
-        let grid_size = (available / self.cell_size / state.units_per_em)
-            .floor()
-            .as_uvec2();
+    /// Resizes the grid to fit the current state and fonts.
+    fn resize(&self, inner: &mut TerminalInner) {
+        let grid_size = inner.fit_grid();
 
         if inner.grid_size != grid_size {
             inner.grid_size = grid_size;
@@ -276,23 +387,21 @@ impl Terminal {
 
             self.term.lock().resize(size_info);
         }
-
-        inner.state = state;
     }
 
     pub fn update_draw_state(&self, pipelines: &TerminalPipelines, draw: &mut TerminalDrawState) {
         let inner = self.inner.lock();
         let grid_size = inner.grid_size;
         let state = inner.state.clone();
+        let fonts = inner.fonts.clone();
         drop(inner); // get off the mutex
 
-        let font_baselines = self.font_baselines.clone();
         let mut canvas = TerminalCanvas::new(
-            self.fonts.clone(),
+            fonts.fonts,
             state,
             grid_size,
-            self.cell_size,
-            font_baselines,
+            fonts.cell_size,
+            fonts.baselines,
         );
 
         let term = self.term.lock();
@@ -311,6 +420,57 @@ impl Terminal {
         self.should_quit.load(Ordering::Relaxed)
     }
 
+    /// Scrolls the displayed part of this terminal's scrollback.
+    pub fn scroll(&self, scroll: TerminalScroll) {
+        let scroll = match scroll {
+            TerminalScroll::Lines(lines) => Scroll::Delta(lines),
+            TerminalScroll::PageUp => Scroll::PageUp,
+            TerminalScroll::PageDown => Scroll::PageDown,
+            TerminalScroll::Top => Scroll::Top,
+            TerminalScroll::Bottom => Scroll::Bottom,
+        };
+
+        self.term.lock().scroll_display(scroll);
+    }
+
+    /// Reads up to `count` lines starting at `start`, where line 0 is the top
+    /// of the screen and negative lines are in the scrollback.
+    pub fn read(&self, start: i32, count: u32) -> TerminalContents {
+        let mut colors = Colors::default();
+        for (index, color) in self.inner.lock().state.colors.iter() {
+            let (_a, r, g, b) = color.to_argb();
+            colors[*index] = Some(Rgb { r, g, b });
+        }
+
+        let term = self.term.lock();
+        for index in 0..COUNT {
+            if let Some(color) = term.colors()[index] {
+                colors[index] = Some(color);
+            }
+        }
+
+        let grid = term.grid();
+        let history_size = grid.history_size() as i32;
+        let screen_lines = grid.screen_lines() as i32;
+        let start = start.max(-history_size);
+        let count = count.min(MAX_READ_LINES) as i32;
+        let end = start.saturating_add(count).min(screen_lines);
+
+        let lines = (start..end)
+            .map(|line| read_line(&grid[Line(line)], &colors))
+            .collect();
+
+        TerminalContents {
+            columns: grid.columns() as u32,
+            screen_lines: screen_lines as u32,
+            history_size: history_size as u32,
+            display_offset: grid.display_offset() as u32,
+            cursor: (grid.cursor.point.line.0, grid.cursor.point.column.0 as u32),
+            start,
+            lines,
+        }
+    }
+
     pub fn send_input(&self, input: &str) {
         let bytes = input.as_bytes();
         let cow = std::borrow::Cow::Owned(bytes.to_owned());
@@ -345,6 +505,118 @@ impl Terminal {
     }
 }
 
+/// Converts a row of terminal cells into a [TerminalLine].
+fn read_line(row: &Row<Cell>, colors: &Colors) -> TerminalLine {
+    let mut line = TerminalLine::default();
+
+    for cell in row.into_iter() {
+        // wide characters are read from their first cell
+        if cell
+            .flags
+            .intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER)
+        {
+            continue;
+        }
+
+        let fg = resolve_color(colors, cell.fg);
+        let fg = hearth_schema::Color::from_rgb(fg.r, fg.g, fg.b);
+        let bg = resolve_color(colors, cell.bg);
+        let bg = hearth_schema::Color::from_rgb(bg.r, bg.g, bg.b);
+        let flags = conv_flags(cell.flags);
+
+        let span = match line.spans.last_mut() {
+            Some(span) if span.fg == fg && span.bg == bg && span.flags == flags => span,
+            _ => {
+                line.spans.push(TerminalSpan {
+                    text: String::new(),
+                    fg,
+                    bg,
+                    flags,
+                });
+
+                line.spans.last_mut().unwrap()
+            }
+        };
+
+        span.text.push(cell.c);
+        span.text.extend(cell.zerowidth().into_iter().flatten());
+    }
+
+    line.wrapped = row
+        .into_iter()
+        .last()
+        .is_some_and(|cell| cell.flags.contains(Flags::WRAPLINE));
+
+    line
+}
+
+/// Converts alacritty's cell flags into [TerminalFlags].
+fn conv_flags(flags: Flags) -> TerminalFlags {
+    let mapping = [
+        (Flags::BOLD, TerminalFlags::BOLD),
+        (Flags::ITALIC, TerminalFlags::ITALIC),
+        (Flags::UNDERLINE, TerminalFlags::UNDERLINE),
+        (Flags::DOUBLE_UNDERLINE, TerminalFlags::DOUBLE_UNDERLINE),
+        (Flags::STRIKEOUT, TerminalFlags::STRIKEOUT),
+        (Flags::DIM, TerminalFlags::DIM),
+        (Flags::INVERSE, TerminalFlags::INVERSE),
+        (Flags::HIDDEN, TerminalFlags::HIDDEN),
+    ];
+
+    mapping
+        .into_iter()
+        .filter(|(cell, _)| flags.contains(*cell))
+        .fold(TerminalFlags::empty(), |acc, (_, flag)| acc | flag)
+}
+
+/// Looks up the RGB value of a terminal color in a palette.
+fn resolve_color(colors: &Colors, color: Color) -> Rgb {
+    match color {
+        Color::Named(name) => colors[name].unwrap_or(Rgb {
+            r: 0xff,
+            g: 0x00,
+            b: 0xff,
+        }),
+        Color::Spec(rgb) => rgb,
+        Color::Indexed(index) => {
+            if let Some(color) = colors[index as usize] {
+                color
+            } else if let Some(gray) = index.checked_sub(232) {
+                let value = gray * 10 + 8;
+                Rgb {
+                    r: value,
+                    g: value,
+                    b: value,
+                }
+            } else if let Some(cube_idx) = index.checked_sub(16) {
+                let r = cube_idx / 36;
+                let g = (cube_idx / 6) % 6;
+                let b = cube_idx % 6;
+
+                let c = |c| {
+                    if c == 0 {
+                        0
+                    } else {
+                        c * 40 + 55
+                    }
+                };
+
+                Rgb {
+                    r: c(r),
+                    g: c(g),
+                    b: c(b),
+                }
+            } else {
+                Rgb {
+                    r: 0xff,
+                    g: 0x00,
+                    b: 0xff,
+                }
+            }
+        }
+    }
+}
+
 /// An in-progress terminal draw state.
 pub struct TerminalCanvas {
     fonts: FontSet<FaceWithMetrics>,
@@ -357,6 +629,7 @@ pub struct TerminalCanvas {
     grid_size: UVec2,
     cell_size: Vec2,
     font_baselines: FontSet<f32>,
+    display_offset: i32,
 }
 
 impl TerminalCanvas {
@@ -385,10 +658,12 @@ impl TerminalCanvas {
             grid_size,
             cell_size,
             font_baselines,
+            display_offset: 0,
         }
     }
 
     pub fn update_from_content(&mut self, content: RenderableContent) {
+        self.display_offset = content.display_offset as i32;
         self.draw_padding();
 
         for index in 0..COUNT {
@@ -455,36 +730,67 @@ impl TerminalCanvas {
 
         state.grid_half_size = self.grid_to_pos(self.grid_size.x as i32, self.grid_size.y as i32);
 
-        state.grid_size = self.grid_size;
+        // the previous contents are meaningless if the grid was resized
+        if state.grid_size != self.grid_size {
+            state.grid_size = self.grid_size;
+            state.grid_contents.clear();
+        }
 
         if state.grid_capacity.x < state.grid_size.x || state.grid_capacity.y < state.grid_size.y {
+            let capacity = state.grid_capacity.max(state.grid_size);
             let (texture, bind_group) =
-                TerminalDrawState::make_grid(pipelines, &state.grid_buffer, state.grid_size);
+                TerminalDrawState::make_grid(pipelines, &state.grid_buffer, capacity);
 
             state.grid_texture = texture;
             state.grid_bind_group = bind_group;
-            state.grid_capacity = state.grid_size;
+            state.grid_capacity = capacity;
+            state.grid_contents.clear();
         }
 
-        state.queue.write_texture(
-            ImageCopyTexture {
-                texture: &state.grid_texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: TextureAspect::All,
-            },
-            bytemuck::cast_slice(self.bg_texture.as_slice()),
-            ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some((state.grid_size.x * 4).try_into().unwrap()),
-                rows_per_image: Some(state.grid_size.y.try_into().unwrap()),
-            },
-            Extent3d {
-                width: state.grid_size.x,
-                height: state.grid_size.y,
-                depth_or_array_layers: 1,
-            },
-        );
+        // only upload the rows that have changed since the last update
+        let width = state.grid_size.x as usize;
+        let dirty = if self.bg_texture.is_empty() {
+            None
+        } else if state.grid_contents.len() != self.bg_texture.len() {
+            Some((0, state.grid_size.y as usize))
+        } else {
+            let rows = state
+                .grid_contents
+                .chunks_exact(width)
+                .zip(self.bg_texture.chunks_exact(width));
+            let mut changed = rows.enumerate().filter(|(_, (old, new))| old != new);
+            changed
+                .next()
+                .map(|(first, _)| (first, changed.last().map_or(first, |(last, _)| last) + 1))
+        };
+
+        if let Some((start, end)) = dirty {
+            state.queue.write_texture(
+                ImageCopyTexture {
+                    texture: &state.grid_texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: 0,
+                        y: start as u32,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                bytemuck::cast_slice(&self.bg_texture[(start * width)..(end * width)]),
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some((state.grid_size.x * 4).try_into().unwrap()),
+                    rows_per_image: Some(((end - start) as u32).try_into().unwrap()),
+                },
+                Extent3d {
+                    width: state.grid_size.x,
+                    height: (end - start) as u32,
+                    depth_or_array_layers: 1,
+                },
+            );
+
+            state.grid_contents.clone_from(&self.bg_texture);
+        }
 
         state.overlay_mesh.update(
             &state.device,
@@ -510,8 +816,9 @@ impl TerminalCanvas {
             return;
         }
 
+        // cells are in grid coordinates, which scroll with the display
         let col = cell.point.column.0 as i32;
-        let row = cell.point.line.0;
+        let row = cell.point.line.0 + self.display_offset;
         let mut fg = cell.fg;
         let mut bg = cell.bg;
 
@@ -566,7 +873,11 @@ impl TerminalCanvas {
         let cursor_color = Color::Named(NamedColor::Foreground);
         let cursor_color = self.color_to_u32(cursor_color);
         let col = cursor.point.column.0 as i32;
-        let row = cursor.point.line.0;
+        let row = cursor.point.line.0 + self.display_offset;
+        if row < 0 || row >= self.grid_size.y as i32 {
+            return;
+        }
+
         let line_width = 0.1 * self.state.units_per_em;
         match cursor.shape {
             CursorShape::Hidden => {}
@@ -646,50 +957,7 @@ impl TerminalCanvas {
     }
 
     pub fn color_to_rgb(&self, color: Color) -> Rgb {
-        match color {
-            Color::Named(name) => self.colors[name].unwrap_or(Rgb {
-                r: 0xff,
-                g: 0x00,
-                b: 0xff,
-            }),
-            Color::Spec(rgb) => rgb,
-            Color::Indexed(index) => {
-                if let Some(color) = self.colors[index as usize] {
-                    color
-                } else if let Some(gray) = index.checked_sub(232) {
-                    let value = gray * 10 + 8;
-                    Rgb {
-                        r: value,
-                        g: value,
-                        b: value,
-                    }
-                } else if let Some(cube_idx) = index.checked_sub(16) {
-                    let r = cube_idx / 36;
-                    let g = (cube_idx / 6) % 6;
-                    let b = cube_idx % 6;
-
-                    let c = |c| {
-                        if c == 0 {
-                            0
-                        } else {
-                            c * 40 + 55
-                        }
-                    };
-
-                    Rgb {
-                        r: c(r),
-                        g: c(g),
-                        b: c(b),
-                    }
-                } else {
-                    Rgb {
-                        r: 0xff,
-                        g: 0x00,
-                        b: 0xff,
-                    }
-                }
-            }
-        }
+        resolve_color(&self.colors, color)
     }
 
     pub fn color_to_u32(&self, color: Color) -> u32 {
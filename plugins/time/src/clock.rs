@@ -0,0 +1,304 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! A virtual clock that can be paused, scaled, and stepped, so that
+//! simulations timed by it can be debugged deterministically.
+
+use std::sync::Arc;
+
+use hearth_runtime::{
+    async_trait,
+    flue::{CapabilityRef, OwnedCapability, Permissions, PostOffice, Table},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::time::*,
+    tokio::{
+        self,
+        sync::Mutex,
+        time::{Duration, MissedTickBehavior},
+    },
+    tracing::warn,
+    utils::{
+        MessageInfo, PubSub, RequestInfo, RequestResponseProcess, ResponseInfo, ServiceRunner,
+        SinkProcess,
+    },
+};
+
+use crate::secs_to_duration;
+
+/// The fastest rate that the virtual clock can advance at.
+const MAX_SCALE: f32 = 1000.0;
+
+/// The most ticks that the virtual clock can be stepped by at once.
+const MAX_STEP: u32 = 10_000;
+
+/// Clamps a clock scale between 0 and [MAX_SCALE]. NaN becomes 1.
+fn clamp_scale(scale: f32) -> f32 {
+    if scale.is_nan() {
+        1.0
+    } else {
+        scale.clamp(0.0, MAX_SCALE)
+    }
+}
+
+/// Advances a clock's state by one tick of the given length.
+fn advance_state(state: &mut ClockState, dt: f32) -> ClockTick {
+    state.index += 1;
+    state.time += dt as f64;
+
+    ClockTick {
+        index: state.index,
+        dt,
+        time: state.time,
+    }
+}
+
+/// A process waiting for the virtual clock to reach a deadline.
+struct Sleeper {
+    deadline: f64,
+    reply: OwnedCapability,
+}
+
+/// The mutable state of a [VirtualClock].
+struct ClockInner {
+    state: ClockState,
+    sleepers: Vec<Sleeper>,
+}
+
+/// A clock that advances with the simulation tick, unless paused, at a
+/// scaled rate.
+///
+/// Ticks and wake-ups are sent while the clock is locked so that subscribers
+/// always receive them in order.
+pub struct VirtualClock {
+    inner: Mutex<ClockInner>,
+    ticks: PubSub<ClockTick>,
+    post: Arc<PostOffice>,
+    period: Duration,
+}
+
+impl VirtualClock {
+    /// Creates a new virtual clock with the given tick period.
+    pub fn new(post: Arc<PostOffice>, period: Duration) -> Self {
+        Self {
+            inner: Mutex::new(ClockInner {
+                state: ClockState {
+                    time: 0.0,
+                    index: 0,
+                    scale: 1.0,
+                    paused: false,
+                },
+                sleepers: Vec::new(),
+            }),
+            ticks: PubSub::new(post.clone()),
+            post,
+            period,
+        }
+    }
+
+    /// Advances the clock once per tick period while it's not paused.
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            let mut inner = self.inner.lock().await;
+            if !inner.state.paused {
+                self.tick(&mut inner).await;
+            }
+        }
+    }
+
+    /// Gets the clock's current state.
+    pub async fn state(&self) -> ClockState {
+        self.inner.lock().await.state.clone()
+    }
+
+    /// Performs a request to the clock's admin service and returns the new
+    /// state of the clock.
+    pub async fn admin(&self, request: &ClockAdminRequest) -> ClockState {
+        let mut inner = self.inner.lock().await;
+
+        match request {
+            ClockAdminRequest::GetState => {}
+            ClockAdminRequest::Pause => inner.state.paused = true,
+            ClockAdminRequest::Resume => inner.state.paused = false,
+            ClockAdminRequest::SetScale { scale } => inner.state.scale = clamp_scale(*scale),
+            ClockAdminRequest::Step { ticks } => {
+                for _ in 0..(*ticks).min(MAX_STEP) {
+                    self.tick(&mut inner).await;
+                }
+            }
+        }
+
+        inner.state.clone()
+    }
+
+    /// Sends an empty message to a capability once the clock has advanced by
+    /// the given number of seconds.
+    pub async fn sleep(&self, secs: f32, reply: OwnedCapability) {
+        let mut inner = self.inner.lock().await;
+        let deadline = inner.state.time + secs_to_duration(secs).as_secs_f64();
+        inner.sleepers.push(Sleeper { deadline, reply });
+        self.wake(&mut inner).await;
+    }
+
+    /// Advances the clock by one tick, then notifies subscribers and wakes
+    /// sleepers.
+    async fn tick(&self, inner: &mut ClockInner) {
+        let dt = self.period.as_secs_f32() * inner.state.scale;
+        let tick = advance_state(&mut inner.state, dt);
+        self.ticks.notify(&tick).await;
+        self.wake(inner).await;
+    }
+
+    /// Replies to every sleeper whose deadline has passed.
+    async fn wake(&self, inner: &mut ClockInner) {
+        let time = inner.state.time;
+        let (due, waiting) = std::mem::take(&mut inner.sleepers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|sleeper| sleeper.deadline <= time);
+
+        inner.sleepers = waiting;
+
+        if due.is_empty() {
+            return;
+        }
+
+        let table = Table::new(self.post.clone());
+        for sleeper in due {
+            let Ok(reply) = table.import_owned(sleeper.reply) else {
+                continue;
+            };
+
+            // the sleeper may have died in the meantime
+            let _ = table.send(reply, &[], &[]).await;
+        }
+    }
+}
+
+/// Gives processes the virtual clock's time. Accepts [ClockRequest].
+#[derive(GetProcessMetadata)]
+pub struct ClockService {
+    pub(crate) clock: Arc<VirtualClock>,
+}
+
+#[async_trait]
+impl SinkProcess for ClockService {
+    type Message = ClockRequest;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, ClockRequest>) {
+        let Some(cap) = message.caps.first() else {
+            warn!("Clock request is missing capability");
+            return;
+        };
+
+        match message.data {
+            ClockRequest::Subscribe => {
+                if cap.get_permissions().contains(Permissions::MONITOR) {
+                    cap.monitor(message.process.borrow_parent()).unwrap();
+                }
+
+                self.clock.ticks.subscribe(cap.clone(), message.codec);
+            }
+            ClockRequest::Unsubscribe => {
+                self.clock.ticks.unsubscribe(cap.clone());
+            }
+            ClockRequest::Sleep { secs } => {
+                self.clock.sleep(secs, cap.to_owned()).await;
+            }
+            ClockRequest::GetState => {
+                let state = self.clock.state().await;
+                let data = message.codec.encode(&state);
+
+                // the requester may have died in the meantime
+                let _ = cap.send(&data, &[]).await;
+            }
+        }
+    }
+
+    async fn on_down<'a>(&'a mut self, cap: CapabilityRef<'a>) {
+        self.clock.ticks.unsubscribe(cap);
+    }
+}
+
+impl ServiceRunner for ClockService {
+    const NAME: &'static str = CLOCK_SERVICE_NAME;
+}
+
+/// Pauses, scales, and steps the virtual clock. Accepts
+/// [ClockAdminRequest].
+#[derive(GetProcessMetadata)]
+pub struct ClockAdminService {
+    pub(crate) clock: Arc<VirtualClock>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for ClockAdminService {
+    type Request = ClockAdminRequest;
+    type Response = ClockState;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, Self::Request>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        self.clock.admin(&request.data).await.into()
+    }
+}
+
+impl ServiceRunner for ClockAdminService {
+    const NAME: &'static str = CLOCK_ADMIN_SERVICE_NAME;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_accumulates_ticks() {
+        let mut state = ClockState {
+            time: 0.0,
+            index: 0,
+            scale: 1.0,
+            paused: false,
+        };
+
+        advance_state(&mut state, 0.5);
+        let tick = advance_state(&mut state, 0.25);
+        assert_eq!(
+            tick,
+            ClockTick {
+                index: 2,
+                dt: 0.25,
+                time: 0.75,
+            }
+        );
+
+        assert_eq!(state.index, 2);
+        assert_eq!(state.time, 0.75);
+    }
+
+    #[test]
+    fn clamp_scale_edge_cases() {
+        assert_eq!(clamp_scale(0.5), 0.5);
+        assert_eq!(clamp_scale(-1.0), 0.0);
+        assert_eq!(clamp_scale(f32::INFINITY), MAX_SCALE);
+        assert_eq!(clamp_scale(f32::NAN), 1.0);
+    }
+}
@@ -16,23 +16,70 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::time::SystemTime;
+use std::{sync::Arc, time::SystemTime};
 
 use hearth_runtime::{
     async_trait,
-    flue::Table,
+    flue::{CapabilityRef, Permissions, Table},
     hearth_macros::GetProcessMetadata,
+    hearth_schema::time::*,
     runtime::{Plugin, RuntimeBuilder},
     tokio::{
         self,
-        time::{Duration, Instant},
+        time::{Duration, Instant, MissedTickBehavior},
     },
-    tracing::debug,
+    tracing::{debug, error, warn},
     utils::{
-        MessageInfo, RequestInfo, RequestResponseProcess, ResponseInfo, RunnerContext,
+        MessageInfo, PubSub, RequestInfo, RequestResponseProcess, ResponseInfo, RunnerContext,
         ServiceRunner, SinkProcess,
     },
 };
+use serde::Deserialize;
+
+use clock::{ClockAdminService, ClockService, VirtualClock};
+
+pub mod clock;
+
+/// The longest duration that a guest can wait for.
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60 * 24 * 365);
+
+/// Converts a guest-provided number of seconds to a [Duration].
+///
+/// Negative and NaN durations become zero, and durations longer than
+/// [MAX_WAIT] (including infinity) are clamped to it.
+fn secs_to_duration(secs: f32) -> Duration {
+    Duration::try_from_secs_f32(secs.max(0.0))
+        .unwrap_or(MAX_WAIT)
+        .min(MAX_WAIT)
+}
+
+/// Configuration for the time plugin, read from the `time` table of the
+/// config file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TimeConfig {
+    /// The number of simulation ticks per second. Clamped between 1 and 1000.
+    pub tick_rate: f32,
+}
+
+impl Default for TimeConfig {
+    fn default() -> Self {
+        Self { tick_rate: 60.0 }
+    }
+}
+
+impl TimeConfig {
+    /// Gets the length of a simulation tick.
+    pub fn tick_period(&self) -> Duration {
+        let rate = if self.tick_rate.is_nan() {
+            Self::default().tick_rate
+        } else {
+            self.tick_rate.clamp(1.0, 1000.0)
+        };
+
+        Duration::from_secs_f64(1.0 / rate as f64)
+    }
+}
 
 /// A plugin that provides timing services to guests.
 ///
@@ -41,19 +88,118 @@ use hearth_runtime::{
 /// - [TimerFactory]
 /// - [StopwatchFactory]
 /// - [UnixTimeService]
+/// - [TickService]
+/// - [ClockService]
+/// - [ClockAdminService]
 #[derive(Default)]
-pub struct TimePlugin;
+pub struct TimePlugin {
+    config: TimeConfig,
+}
 
 impl Plugin for TimePlugin {
-    fn build(&mut self, builder: &mut RuntimeBuilder) {
+    fn finalize(self, builder: &mut RuntimeBuilder) {
+        let pubsub = Arc::new(PubSub::new(builder.get_post()));
+        tokio::spawn(run_ticks(pubsub.clone(), self.config.tick_period()));
+
+        let clock = Arc::new(VirtualClock::new(
+            builder.get_post(),
+            self.config.tick_period(),
+        ));
+
+        tokio::spawn(clock.clone().run());
+
         builder
             .add_plugin(SleepService)
             .add_plugin(TimerFactory)
             .add_plugin(StopwatchFactory)
-            .add_plugin(UnixTimeService);
+            .add_plugin(UnixTimeService)
+            .add_plugin(TickService { pubsub })
+            .add_plugin(ClockService {
+                clock: clock.clone(),
+            })
+            .add_plugin(ClockAdminService { clock });
+    }
+}
+
+impl TimePlugin {
+    /// Creates a new time plugin with the given configuration.
+    pub fn new(config: TimeConfig) -> Self {
+        Self { config }
+    }
+
+    /// Creates a new time plugin from the `time` table of a config file.
+    ///
+    /// Logs an error and uses the default configuration if the table is
+    /// malformed.
+    pub fn from_config_file(config: &toml::Table) -> Self {
+        let Some(table) = config.get("time") else {
+            return Self::default();
+        };
+
+        match table.clone().try_into() {
+            Ok(config) => Self::new(config),
+            Err(err) => {
+                error!("Failed to parse time config: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Sends a [Tick] to every subscriber once per period.
+async fn run_ticks(pubsub: Arc<PubSub<Tick>>, period: Duration) {
+    let start = Instant::now();
+    let dt = period.as_secs_f32();
+    let mut interval = tokio::time::interval_at(start, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        let deadline = interval.tick().await;
+        let index = (deadline - start).as_nanos() / period.as_nanos();
+        let index = index as u64;
+        pubsub.notify(&Tick { index, dt }).await;
+    }
+}
+
+/// Sends fixed-rate simulation ticks to subscribers. Accepts [TickCommand].
+#[derive(GetProcessMetadata)]
+pub struct TickService {
+    pubsub: Arc<PubSub<Tick>>,
+}
+
+#[async_trait]
+impl SinkProcess for TickService {
+    type Message = TickCommand;
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, TickCommand>) {
+        let Some(sub) = message.caps.first() else {
+            warn!("Tick command is missing capability");
+            return;
+        };
+
+        match message.data {
+            TickCommand::Subscribe => {
+                if sub.get_permissions().contains(Permissions::MONITOR) {
+                    sub.monitor(message.process.borrow_parent()).unwrap();
+                }
+
+                self.pubsub.subscribe(sub.clone(), message.codec);
+            }
+            TickCommand::Unsubscribe => {
+                self.pubsub.unsubscribe(sub.clone());
+            }
+        }
+    }
+
+    async fn on_down<'a>(&'a mut self, cap: CapabilityRef<'a>) {
+        self.pubsub.unsubscribe(cap);
     }
 }
 
+impl ServiceRunner for TickService {
+    const NAME: &'static str = TICK_SERVICE_NAME;
+}
+
 /// Receives a single floating-point number as a request, waits the value of
 /// the number in seconds, then responds with an empty message.
 #[derive(GetProcessMetadata)]
@@ -71,7 +217,7 @@ impl SinkProcess for SleepService {
             return;
         };
 
-        let duration = Duration::from_secs_f32(message.data);
+        let duration = secs_to_duration(message.data);
         let reply = reply.to_owned();
         let post = message.runtime.post.to_owned();
 
@@ -80,9 +226,12 @@ impl SinkProcess for SleepService {
             tokio::time::sleep(duration).await;
 
             let table = Table::new(post);
-            let reply_handle = table.import_owned(reply).unwrap();
-            let reply_cap = table.wrap_handle(reply_handle).unwrap();
-            reply_cap.send(&[], &[]).await.unwrap();
+            let Ok(reply_handle) = table.import_owned(reply) else {
+                return;
+            };
+
+            // the sleeper may have died in the meantime
+            let _ = table.send(reply_handle, &[], &[]).await;
         });
     }
 }
@@ -144,7 +293,7 @@ impl RequestResponseProcess for Timer {
         &'a mut self,
         request: &mut RequestInfo<'a, Self::Request>,
     ) -> ResponseInfo<'a, Self::Response> {
-        let duration = Duration::from_secs_f32(request.data);
+        let duration = secs_to_duration(request.data);
         self.last_request += duration;
         tokio::time::sleep_until(self.last_request).await;
 
@@ -239,3 +388,41 @@ impl RequestResponseProcess for UnixTimeService {
 impl ServiceRunner for UnixTimeService {
     const NAME: &'static str = "hearth.UnixTime";
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn secs_to_duration_is_bounded(secs in any::<f32>()) {
+            prop_assert!(secs_to_duration(secs) <= MAX_WAIT);
+        }
+
+        #[test]
+        fn secs_to_duration_is_accurate(secs in 0.0f32..1_000_000.0) {
+            let duration = secs_to_duration(secs).as_secs_f32();
+            prop_assert!((duration - secs).abs() <= secs * f32::EPSILON + 1e-9);
+        }
+    }
+
+    #[test]
+    fn tick_period_is_bounded() {
+        let period = |tick_rate| TimeConfig { tick_rate }.tick_period();
+        assert_eq!(period(50.0), Duration::from_millis(20));
+        assert_eq!(period(0.0), Duration::from_secs(1));
+        assert_eq!(period(-5.0), Duration::from_secs(1));
+        assert_eq!(period(f32::INFINITY), Duration::from_millis(1));
+        assert_eq!(period(f32::NAN), TimeConfig::default().tick_period());
+    }
+
+    #[test]
+    fn secs_to_duration_edge_cases() {
+        assert_eq!(secs_to_duration(-1.0), Duration::ZERO);
+        assert_eq!(secs_to_duration(f32::NAN), Duration::ZERO);
+        assert_eq!(secs_to_duration(f32::INFINITY), MAX_WAIT);
+        assert_eq!(secs_to_duration(f32::NEG_INFINITY), Duration::ZERO);
+    }
+}
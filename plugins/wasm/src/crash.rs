@@ -0,0 +1,261 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Crash reports of Wasm processes that failed.
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use hearth_runtime::anyhow::Error;
+use hearth_runtime::hearth_macros::GetProcessMetadata;
+use hearth_runtime::hearth_schema::wasm::*;
+use hearth_runtime::hearth_schema::{LumpId, ProcessId};
+use hearth_runtime::process::ProcessInfo;
+use hearth_runtime::snapshot::unix_millis;
+use hearth_runtime::{async_trait, utils::*};
+use wasmtime::{Trap, WasmBacktrace};
+
+/// The name of the crash report directory within the data directory.
+pub const CRASH_REPORT_DIR: &str = "crashes";
+
+/// The number of crash reports kept on disk. Older reports are deleted.
+const MAX_REPORTS: usize = 64;
+
+/// The number of recently received messages included in a crash report.
+const RECENT_MESSAGES: usize = 16;
+
+/// The number of bytes of each message's data included in a crash report.
+const PREVIEW_LEN: usize = 256;
+
+/// The last few messages that a process has received.
+#[derive(Default)]
+pub(crate) struct RecentMessages(VecDeque<CrashMessage>);
+
+impl RecentMessages {
+    /// Records that a message has been received.
+    pub fn push(&mut self, data: &[u8], caps: usize) {
+        if self.0.len() >= RECENT_MESSAGES {
+            self.0.pop_front();
+        }
+
+        let preview = &data[..data.len().min(PREVIEW_LEN)];
+        self.0.push_back(CrashMessage {
+            time: unix_millis(),
+            len: data.len(),
+            preview: String::from_utf8_lossy(preview).into_owned(),
+            caps,
+        });
+    }
+
+    /// Copies the recorded messages, oldest first.
+    pub fn to_vec(&self) -> Vec<CrashMessage> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+/// Builds the crash report of a process that failed with an error.
+pub(crate) fn build_report(
+    info: &ProcessInfo,
+    lump: LumpId,
+    err: &Error,
+    messages: Vec<CrashMessage>,
+) -> CrashReport {
+    let trap = err.downcast_ref::<Trap>().map(|trap| trap.to_string());
+
+    let backtrace = err
+        .downcast_ref::<WasmBacktrace>()
+        .map(|backtrace| {
+            backtrace
+                .frames()
+                .iter()
+                .map(|frame| CrashFrame {
+                    func_index: frame.func_index(),
+                    func_name: frame.func_name().map(str::to_string),
+                    module_offset: frame.module_offset(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    CrashReport {
+        pid: ProcessId(info.pid as u32),
+        time: unix_millis(),
+        name: info.meta.name.clone(),
+        description: info.meta.description.clone(),
+        lump,
+        error: format!("{:#}", err),
+        trap,
+        backtrace,
+        messages,
+        recording: None,
+    }
+}
+
+/// A directory of crash reports, stored as one JSON file per crash.
+#[derive(Clone, Debug)]
+pub struct CrashReportDir {
+    path: PathBuf,
+}
+
+impl Default for CrashReportDir {
+    fn default() -> Self {
+        Self::new(hearth_runtime::get_data_dir().join(CRASH_REPORT_DIR))
+    }
+}
+
+impl CrashReportDir {
+    /// Opens a crash report directory. The directory is created when the
+    /// first report is written to it.
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Writes a crash report, deleting the oldest reports if there are too
+    /// many.
+    pub fn write(&self, report: &CrashReport) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.path)?;
+
+        // pad the time so that file names sort in the order they were written
+        let name = format!("{:016}-{}.json", report.time, report.pid.0);
+        std::fs::write(self.path.join(name), serde_json::to_vec(report)?)?;
+
+        let names = self.names()?;
+        for name in names.iter().take(names.len().saturating_sub(MAX_REPORTS)) {
+            std::fs::remove_file(self.path.join(name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads every crash report, oldest first.
+    ///
+    /// Skips files that aren't valid reports.
+    pub fn list(&self) -> std::io::Result<Vec<CrashReport>> {
+        let mut reports = Vec::new();
+        for name in self.names()? {
+            let data = std::fs::read(self.path.join(name))?;
+            if let Ok(report) = serde_json::from_slice(&data) {
+                reports.push(report);
+            }
+        }
+
+        Ok(reports)
+    }
+
+    /// Gets the names of every report file, oldest first.
+    fn names(&self) -> std::io::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".json") {
+                names.push(name);
+            }
+        }
+
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// The native Wasm crash report service. Accepts [CrashReportRequest].
+///
+/// Reads the crash reports of Wasm processes that failed.
+#[derive(Default, GetProcessMetadata)]
+pub struct CrashReportService {
+    dir: CrashReportDir,
+}
+
+#[async_trait]
+impl RequestResponseProcess for CrashReportService {
+    type Request = CrashReportRequest;
+    type Response = CrashReportResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, CrashReportRequest>,
+    ) -> ResponseInfo<'a, CrashReportResponse> {
+        self.handle(&request.data).into()
+    }
+}
+
+impl ServiceRunner for CrashReportService {
+    const NAME: &'static str = CRASH_REPORTS_SERVICE_NAME;
+}
+
+impl CrashReportService {
+    fn handle(&self, request: &CrashReportRequest) -> CrashReportResponse {
+        let reports = self
+            .dir
+            .list()
+            .map_err(|err| CrashReportError::Storage(err.to_string()))?;
+
+        match request {
+            CrashReportRequest::Get { pid } => reports
+                .into_iter()
+                .rev()
+                .find(|report| report.pid == *pid)
+                .map(CrashReportSuccess::Report)
+                .ok_or(CrashReportError::NotFound),
+            CrashReportRequest::List => Ok(CrashReportSuccess::Reports(
+                reports
+                    .into_iter()
+                    .map(|report| CrashReport {
+                        backtrace: Vec::new(),
+                        messages: Vec::new(),
+                        ..report
+                    })
+                    .collect(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_messages_are_bounded() {
+        let mut recent = RecentMessages::default();
+        for i in 0..RECENT_MESSAGES + 4 {
+            recent.push(i.to_string().as_bytes(), 0);
+        }
+
+        let messages = recent.to_vec();
+        assert_eq!(messages.len(), RECENT_MESSAGES);
+        assert_eq!(messages[0].preview, "4");
+    }
+
+    #[test]
+    fn previews_are_truncated() {
+        let mut recent = RecentMessages::default();
+        recent.push(&[b'a'; PREVIEW_LEN * 2], 1);
+
+        let message = &recent.to_vec()[0];
+        assert_eq!(message.len, PREVIEW_LEN * 2);
+        assert_eq!(message.preview.len(), PREVIEW_LEN);
+        assert_eq!(message.caps, 1);
+    }
+}
@@ -16,25 +16,42 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Hearth. If not, see <https://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use hearth_runtime::anyhow::{anyhow, bail, Context, Result};
+use hearth_runtime::anyhow::{anyhow, bail, Context, Error, Result};
 use hearth_runtime::asset::{AssetLoader, AssetStore};
+use hearth_runtime::audit::CapAudit;
 use hearth_runtime::flue::{
     CapabilityHandle, CapabilityRef, Mailbox, MailboxGroup, Permissions, Table, TableSignal,
 };
 use hearth_runtime::hearth_macros::{impl_wasm_linker, GetProcessMetadata};
-use hearth_runtime::lump::{bytes::Bytes, LumpStoreImpl};
-use hearth_runtime::process::{Process, ProcessMetadata};
+use hearth_runtime::lump::{bytes::Bytes, hash_lump, LumpStoreImpl};
+use hearth_runtime::process::{Process, ProcessLogEvent, ProcessMetadata};
 use hearth_runtime::runtime::{Plugin, Runtime, RuntimeBuilder};
+use hearth_runtime::stream::{RingBuffer, StreamStore};
+use hearth_runtime::waits::{WaitGraph, WaitGuard};
 use hearth_runtime::{async_trait, hearth_schema};
-use hearth_runtime::{tokio, utils::*};
-use hearth_schema::wasm::WasmSpawnInfo;
-use hearth_schema::{LumpId, ProcessLogLevel, SignalKind};
+use hearth_runtime::{tokio, tokio::task::JoinHandle, utils::*};
+use hearth_schema::wasm::{ReplayOutcome, WasmSpawnInfo};
+use hearth_schema::{LumpId, SignalKind, StructuredLogEvent};
 use slab::Slab;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 use wasmtime::{Caller, Config, Engine, Instance, Linker, Module, Store, UpdateDeadline};
 
+use crash::{CrashReportDir, CrashReportService, RecentMessages};
+use replay::{
+    diverged, CallSource, RecordedCall, RecordedCap, RecordedEvent, RecordedSignal, Recorder,
+    Recording, ReplayExhausted, Replayer, SignalSource, WasmReplayer,
+};
+use supervisor::WasmSupervisor;
+
+pub mod crash;
+pub mod replay;
+pub mod supervisor;
+
 /// An interface to attempt to acquire a Wasm ABI by type.
 pub trait GetAbi<T>
 where
@@ -140,6 +157,7 @@ impl<'a> GuestMemory<'a> {
 /// Implements the `hearth::log` ABI module.
 pub struct LogAbi {
     process: Arc<Process>,
+    calls: CallSource,
 }
 
 #[impl_wasm_linker(module = "hearth::log")]
@@ -163,18 +181,54 @@ impl LogAbi {
         let module = memory.get_str(module_ptr, module_len)?.to_string();
         let content = memory.get_str(content_ptr, content_len)?.to_string();
 
-        let info = self.process.borrow_info();
-        info.process_span.in_scope(|| match level {
-            ProcessLogLevel::Trace => tracing::trace!(module, "{content}"),
+        self.process.borrow_info().log(ProcessLogEvent {
+            level,
+            module,
+            content,
+            fields: Default::default(),
+        });
 
-            ProcessLogLevel::Debug => tracing::debug!(module, "{content}"),
-            ProcessLogLevel::Info => tracing::info!(module, "{content}"),
-            ProcessLogLevel::Warning => tracing::warn!(module, "{content}"),
-            ProcessLogLevel::Error => tracing::error!(module, "{content}"),
+        Ok(())
+    }
+
+    /// Logs an event with structured fields for this process.
+    ///
+    /// The event is a JSON-encoded [StructuredLogEvent].
+    async fn log_fields(&self, memory: GuestMemory<'_>, ptr: u32, len: u32) -> Result<()> {
+        let event: StructuredLogEvent = serde_json::from_slice(memory.get_slice(ptr, len)?)
+            .context("parsing structured log event")?;
+
+        self.process.borrow_info().log(ProcessLogEvent {
+            level: event.level,
+            module: event.module,
+            content: event.content,
+            fields: event.fields,
         });
 
         Ok(())
     }
+
+    /// Writes a new, random trace ID to guest memory via pointer, for the
+    /// guest to start a trace with.
+    fn new_trace_id(&self, memory: GuestMemory<'_>, ptr: u32) -> Result<()> {
+        let id = if self.calls.is_replaying() {
+            match self.calls.next_replayed()? {
+                RecordedCall::NewTraceId { id } => id,
+                call => return Err(diverged("new_trace_id", call.function())),
+            }
+        } else {
+            // every RandomState is seeded differently
+            let state = RandomState::new();
+            let id = (state.hash_one(0u8) as u128) << 64 | state.hash_one(1u8) as u128;
+            self.calls.record(RecordedCall::NewTraceId {
+                id: id.to_be_bytes(),
+            });
+            id.to_be_bytes()
+        };
+
+        *memory.get_memory_ref::<[u8; 16]>(ptr)? = id;
+        Ok(())
+    }
 }
 
 /// A script-local lump stored in [LumpAbi].
@@ -195,6 +249,8 @@ pub struct LumpAbi {
     pub lump_store: Arc<LumpStoreImpl>,
     pub lump_handles: Slab<LocalLump>,
     pub this_lump: LumpId,
+    pub max_message_size: usize,
+    calls: CallSource,
 }
 
 #[impl_wasm_linker(module = "hearth::lump")]
@@ -230,6 +286,67 @@ impl LumpAbi {
         Ok(handle)
     }
 
+    /// Spills the payload of a message over into a temporary lump and writes
+    /// its [LumpId] to guest memory via pointer.
+    ///
+    /// The lump is freed once the message's recipient loads it with
+    /// [Self::load_spill].
+    ///
+    /// Fails if the payload is larger than the runtime's message size limit.
+    async fn spill(
+        &self,
+        memory: GuestMemory<'_>,
+        data_ptr: u32,
+        data_len: u32,
+        id_ptr: u32,
+    ) -> Result<()> {
+        let limit = self.max_message_size;
+        if data_len as usize > limit {
+            bail!("spill: payload of {data_len} bytes exceeds limit of {limit} bytes");
+        }
+
+        let bytes: Bytes = memory.get_slice(data_ptr, data_len)?.to_vec().into();
+
+        // replayed messages go to stand-ins that never load their spills
+        let id = if self.calls.is_replaying() {
+            hash_lump(&bytes)
+        } else {
+            self.lump_store.add_spill(bytes).await
+        };
+
+        *memory.get_memory_ref::<LumpId>(id_ptr)? = id;
+        Ok(())
+    }
+
+    /// Loads the payload of a spilled-over message from its [LumpId],
+    /// retrieved from guest memory via pointer.
+    ///
+    /// Fails if the lump is not found in the lump store.
+    async fn load_spill(&mut self, memory: GuestMemory<'_>, id_ptr: u32) -> Result<u32> {
+        let id: LumpId = *memory.get_memory_ref(id_ptr)?;
+
+        if self.calls.is_replaying() {
+            let bytes = match self.calls.next_replayed()? {
+                RecordedCall::LoadSpill { data } => data.into(),
+                call => return Err(diverged("load_spill", call.function())),
+            };
+
+            return Ok(self.lump_handles.insert(LocalLump { id, bytes }) as u32);
+        }
+
+        let bytes = self
+            .lump_store
+            .take_spill(&id)
+            .await
+            .ok_or_else(|| anyhow!("couldn't find spilled {:?} in lump store", id))?;
+
+        self.calls.record(RecordedCall::LoadSpill {
+            data: bytes.to_vec(),
+        });
+
+        Ok(self.lump_handles.insert(LocalLump { id, bytes }) as u32)
+    }
+
     /// Writes the [LumpId] of a loaded lump to guest memory via pointer.
     fn get_id(&self, memory: GuestMemory<'_>, handle: u32, id_ptr: u32) -> Result<()> {
         let lump = self.get_lump(handle)?;
@@ -255,6 +372,29 @@ impl LumpAbi {
         Ok(())
     }
 
+    /// Copies part of a loaded lump's data into guest memory by handle.
+    ///
+    /// Copies at most `data_len` bytes starting at `offset` in the lump to
+    /// `data_ptr`, and returns the number of bytes copied, which is only less
+    /// than `data_len` at the end of the lump. Lets guests read large lumps in
+    /// chunks instead of copying them whole with [Self::get_data].
+    fn read(
+        &self,
+        memory: GuestMemory<'_>,
+        handle: u32,
+        offset: u32,
+        data_ptr: u32,
+        data_len: u32,
+    ) -> Result<u32> {
+        let bytes = &self.get_lump(handle)?.bytes;
+        let start = (offset as usize).min(bytes.len());
+        let end = start.saturating_add(data_len as usize).min(bytes.len());
+        let src = &bytes[start..end];
+        let dst = memory.get_slice(data_ptr, src.len() as u32)?;
+        dst.copy_from_slice(src);
+        Ok(src.len() as u32)
+    }
+
     /// Unloads a lump by handle.
     fn free(&mut self, handle: u32) -> Result<()> {
         self.lump_handles
@@ -265,11 +405,13 @@ impl LumpAbi {
 }
 
 impl LumpAbi {
-    pub fn new(runtime: &Runtime, this_lump: LumpId) -> Self {
+    pub(crate) fn new(runtime: &Runtime, this_lump: LumpId, calls: CallSource) -> Self {
         Self {
             lump_store: runtime.lump_store.clone(),
             lump_handles: Default::default(),
             this_lump,
+            max_message_size: runtime.process_factory.store().max_message_size(),
+            calls,
         }
     }
 
@@ -281,9 +423,110 @@ impl LumpAbi {
     }
 }
 
+/// Implements the `hearth::stream` ABI module.
+///
+/// Lets guests write frames to the ring buffers of host plugins, which have
+/// been given to them by token. See [hearth_runtime::stream] for more info.
+///
+/// Replayed processes don't open real streams. Their handles have no stream,
+/// and the results of their calls are taken from the recording.
+pub struct StreamAbi {
+    streams: Arc<StreamStore>,
+    handles: Slab<Option<Arc<RingBuffer>>>,
+    calls: CallSource,
+}
+
+#[impl_wasm_linker(module = "hearth::stream")]
+impl StreamAbi {
+    /// Opens a stream by token and returns its handle.
+    ///
+    /// Returns `u32::MAX` (or `0xFFFFFFFF`) if there is no stream with the
+    /// given token.
+    fn open(&mut self, token: u64) -> Result<u32> {
+        if self.calls.is_replaying() {
+            let handle = match self.calls.next_replayed()? {
+                RecordedCall::StreamOpen { handle } => handle,
+                call => return Err(diverged("open", call.function())),
+            };
+
+            // handles are allocated in the same order as when recorded
+            if handle != u32::MAX && self.handles.insert(None) as u32 != handle {
+                bail!("replay diverged: open() returned a different handle");
+            }
+
+            return Ok(handle);
+        }
+
+        let handle = match self.streams.open(token) {
+            Some(stream) => self.handles.insert(Some(stream)) as u32,
+            None => u32::MAX,
+        };
+
+        self.calls.record(RecordedCall::StreamOpen { handle });
+        Ok(handle)
+    }
+
+    /// Writes a frame from guest memory to a stream by handle.
+    ///
+    /// Returns 0 if the frame was written, 1 if it doesn't fit in the stream's
+    /// free space, and 2 if the stream has been closed by its host plugin.
+    fn write(&self, memory: GuestMemory<'_>, handle: u32, ptr: u32, len: u32) -> Result<u32> {
+        let stream = self.get_stream(handle)?;
+        let frame = memory.get_slice(ptr, len)?;
+
+        let Some(stream) = stream else {
+            return match self.calls.next_replayed()? {
+                RecordedCall::StreamWrite { status } => Ok(status),
+                call => Err(diverged("write", call.function())),
+            };
+        };
+
+        let status = if stream.push(frame) {
+            0
+        } else if stream.is_closed() {
+            2
+        } else {
+            1
+        };
+
+        self.calls.record(RecordedCall::StreamWrite { status });
+        Ok(status)
+    }
+
+    /// Closes a stream handle. The stream itself stays open for other
+    /// handles.
+    fn free(&mut self, handle: u32) -> Result<()> {
+        self.handles
+            .try_remove(handle as usize)
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("stream handle {} is invalid", handle))
+    }
+}
+
+impl StreamAbi {
+    pub(crate) fn new(runtime: &Runtime, calls: CallSource) -> Self {
+        Self {
+            streams: runtime.streams.clone(),
+            handles: Slab::new(),
+            calls,
+        }
+    }
+
+    /// Helper function to get a stream from a handle. Replayed handles have
+    /// no stream.
+    fn get_stream(&self, handle: u32) -> Result<Option<&RingBuffer>> {
+        self.handles
+            .get(handle as usize)
+            .map(|stream| stream.as_deref())
+            .ok_or_else(|| anyhow!("stream handle {} is invalid", handle))
+    }
+}
+
 /// Implements the `hearth::table` ABI module.
 pub struct TableAbi {
     process: Arc<Process>,
+    waits: Arc<WaitGraph>,
+    audit: Arc<CapAudit>,
 }
 
 impl AsRef<Table> for TableAbi {
@@ -353,7 +596,8 @@ impl TableAbi {
     /// data payload of the message. `caps_ptr` and `caps_len` point to an
     /// array of `u32`-sized capability handles to be sent in the message.
     ///
-    /// Fails if the capability does not have the send permission.
+    /// Fails if the capability does not have the send permission or if the
+    /// data is larger than the runtime's message size limit.
     async fn send(
         &self,
         memory: GuestMemory<'_>,
@@ -363,18 +607,49 @@ impl TableAbi {
         caps_ptr: u32,
         caps_len: u32,
     ) -> Result<()> {
+        if let Err(limit) = self
+            .process
+            .borrow_info()
+            .check_message_size(data_len as usize)
+        {
+            bail!("send({handle}): message of {data_len} bytes exceeds limit of {limit} bytes");
+        }
+
         let data = memory.get_slice(data_ptr, data_len)?;
         let caps = memory.get_memory_slice::<u32>(caps_ptr, caps_len)?;
         let caps: Vec<_> = caps
             .iter()
             .map(|cap| CapabilityHandle(*cap as usize))
             .collect();
-        self.process
-            .borrow_table()
-            .send(CapabilityHandle(handle as usize), data, &caps)
+        let table = self.process.borrow_table();
+        let target = CapabilityHandle(handle as usize);
+        table
+            .send(target, data, &caps)
             .await
             .with_context(|| format!("send({handle})"))?;
 
+        if self.waits.is_enabled() && !caps.is_empty() {
+            let target = table.get_owned(target)?;
+            let caps = caps
+                .iter()
+                .map(|cap| table.get_owned(*cap))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let pid = self.process.borrow_info().pid;
+            self.waits.record_request(pid, target, caps);
+        }
+
+        if self.audit.is_enabled() {
+            let target = table.get_permissions(target)?;
+            let caps = caps
+                .iter()
+                .map(|cap| table.get_owned(*cap))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let info = self.process.borrow_info();
+            self.audit.record_send(info, target, caps);
+        }
+
         Ok(())
     }
 
@@ -382,9 +657,17 @@ impl TableAbi {
     ///
     /// Fails if the capability does not have the kill permission.
     fn kill(&self, handle: u32) -> Result<()> {
+        let handle = CapabilityHandle(handle as usize);
+        let perms = self
+            .as_ref()
+            .get_permissions(handle)
+            .with_context(|| format!("kill({})", handle.0))?;
+
         self.as_ref()
-            .kill(CapabilityHandle(handle as usize))
-            .with_context(|| format!("kill({handle})"))?;
+            .kill(handle)
+            .with_context(|| format!("kill({})", handle.0))?;
+
+        self.audit.record_kill(self.process.borrow_info(), perms);
 
         Ok(())
     }
@@ -415,6 +698,10 @@ impl<'a> From<TableSignal<'a>> for Signal {
 struct MailboxArena<'a> {
     group: &'a MailboxGroup<'a>,
     mbs: Slab<Mailbox<'a>>,
+
+    /// The mailboxes behind the stand-ins for a replayed process's
+    /// capabilities.
+    standins: Vec<Mailbox<'a>>,
 }
 
 impl<'a> MailboxArena<'a> {
@@ -437,6 +724,10 @@ impl<'a> MailboxArena<'a> {
 pub struct MailboxAbi {
     process: Arc<Process>,
     signals: Slab<Signal>,
+    waits: Arc<WaitGraph>,
+    audit: Arc<CapAudit>,
+    recent: RecentMessages,
+    source: SignalSource,
 
     #[borrows(process)]
     #[covariant]
@@ -463,6 +754,9 @@ impl MailboxAbi {
                 .context("invalid handle")
         })?;
 
+        let pid = self.borrow_process().borrow_info().pid;
+        self.borrow_waits().remove_mailbox(pid, handle);
+
         Ok(())
     }
 
@@ -472,6 +766,13 @@ impl MailboxAbi {
         let mb = self.get_mb(handle)?;
         let perms = Permissions::from_bits(perms).context("unknown permission bits set")?;
         let cap = mb.export(perms).unwrap();
+
+        let waits = self.borrow_waits();
+        if waits.is_enabled() {
+            let pid = self.borrow_process().borrow_info().pid;
+            waits.add_mailbox(cap.to_owned(), pid, handle);
+        }
+
         Ok(cap.into_handle().0.try_into().unwrap())
     }
 
@@ -491,16 +792,35 @@ impl MailboxAbi {
 
     /// Waits for a signal to be received by a mailbox.
     async fn recv(&mut self, handle: u32) -> Result<u32> {
+        if self.is_replaying() {
+            self.get_mb(handle)?;
+            let event = self.next_replayed()?;
+            let RecordedEvent::Recv { signal } = event else {
+                return Err(diverged("recv", event.function()));
+            };
+
+            return self.insert_replayed(signal);
+        }
+
         let mb = self.get_mb(handle)?;
 
-        let signal = mb
-            .recv(|signal| Signal::from(signal))
-            .await
-            .context("process has been killed")?;
+        let signal = {
+            let waits = self.borrow_waits().clone();
+            let wait = self.begin_wait(&waits, &[handle]);
 
-        let handle = self.with_signals_mut(|signals| signals.insert(signal));
+            let signal = mb
+                .recv(|signal| Signal::from(signal))
+                .await
+                .context("process has been killed")?;
 
-        Ok(handle.try_into().unwrap())
+            if let Some(wait) = wait {
+                wait.received(handle);
+            }
+
+            signal
+        };
+
+        Ok(self.insert_signal(signal, |signal| RecordedEvent::Recv { signal }))
     }
 
     /// Checks if a mailbox has received any signals without waiting.
@@ -508,6 +828,17 @@ impl MailboxAbi {
     /// Returns `u32::MAX` (or `0xFFFFFFFF`) if the mailbox's queue is empty.
     /// Otherwise, returns the handle to the received signal.
     fn try_recv(&mut self, handle: u32) -> Result<u32> {
+        if self.is_replaying() {
+            self.get_mb(handle)?;
+            return match self.next_replayed()? {
+                RecordedEvent::TryRecv {
+                    signal: Some(signal),
+                } => self.insert_replayed(signal),
+                RecordedEvent::TryRecv { signal: None } => Ok(u32::MAX),
+                event => Err(diverged("try_recv", event.function())),
+            };
+        }
+
         let mb = self.get_mb(handle)?;
 
         let signal = mb
@@ -515,11 +846,13 @@ impl MailboxAbi {
             .context("process has been killed")?;
 
         match signal {
-            Some(signal) => {
-                let handle = self.with_signals_mut(|signals| signals.insert(signal));
-                Ok(handle.try_into().unwrap())
+            Some(signal) => Ok(self.insert_signal(signal, |signal| RecordedEvent::TryRecv {
+                signal: Some(signal),
+            })),
+            None => {
+                self.record(RecordedEvent::TryRecv { signal: None });
+                Ok(u32::MAX)
             }
-            None => Ok(u32::MAX),
         }
     }
 
@@ -540,6 +873,30 @@ impl MailboxAbi {
     ) -> Result<u64> {
         let handles = memory.get_memory_slice(handles_ptr, handles_len)?;
 
+        if self.is_replaying() {
+            for handle in handles.iter() {
+                self.get_mb(*handle)?;
+            }
+
+            let event = self.next_replayed()?;
+            let RecordedEvent::Poll { index, signal } = event else {
+                return Err(diverged("poll", event.function()));
+            };
+
+            if index >= handles_len {
+                bail!(
+                    "replay diverged: recorded poll() index {} is out of bounds",
+                    index
+                );
+            }
+
+            let handle = self.insert_replayed(signal)?;
+            return Ok(((index as u64) << 32) | (handle as u64));
+        }
+
+        let waits = self.borrow_waits().clone();
+        let wait = self.begin_wait(&waits, handles);
+
         let mbs = handles
             .iter()
             .map(|handle| self.get_mb(*handle))
@@ -549,12 +906,135 @@ impl MailboxAbi {
             .map(Box::pin);
 
         let (signal, index, _) = futures_util::future::select_all(mbs).await;
+        if let Some(wait) = wait {
+            wait.received(handles[index]);
+        }
+
         let signal = signal.context("process has been killed")?;
-        let handle = self.with_signals_mut(|signals| signals.insert(signal));
+        let handle = self.insert_signal(signal, |signal| RecordedEvent::Poll {
+            index: index as u32,
+            signal,
+        });
         let result = ((index as u64) << 32) | (handle as u64);
         Ok(result)
     }
 
+    /// Waits at most `timeout_ns` nanoseconds for a signal to be received by
+    /// a mailbox.
+    ///
+    /// Returns `u32::MAX` (or `0xFFFFFFFF`) if the timeout elapsed first.
+    /// Otherwise, returns the handle to the received signal.
+    async fn recv_timeout(&mut self, handle: u32, timeout_ns: u64) -> Result<u32> {
+        if self.is_replaying() {
+            self.get_mb(handle)?;
+            return match self.next_replayed()? {
+                RecordedEvent::RecvTimeout {
+                    signal: Some(signal),
+                } => self.insert_replayed(signal),
+                RecordedEvent::RecvTimeout { signal: None } => Ok(u32::MAX),
+                event => Err(diverged("recv_timeout", event.function())),
+            };
+        }
+
+        let mb = self.get_mb(handle)?;
+
+        let signal = {
+            let waits = self.borrow_waits().clone();
+            let wait = self.begin_wait(&waits, &[handle]);
+            let timeout = Duration::from_nanos(timeout_ns);
+            let signal =
+                tokio::time::timeout(timeout, mb.recv(|signal| Signal::from(signal))).await;
+
+            if let (Some(wait), Ok(_)) = (wait, &signal) {
+                wait.received(handle);
+            }
+
+            signal
+        };
+
+        let Ok(signal) = signal else {
+            self.record(RecordedEvent::RecvTimeout { signal: None });
+            return Ok(u32::MAX);
+        };
+
+        let signal = signal.context("process has been killed")?;
+        let handle = self.insert_signal(signal, |signal| RecordedEvent::RecvTimeout {
+            signal: Some(signal),
+        });
+
+        Ok(handle)
+    }
+
+    /// Waits at most `timeout_ns` nanoseconds for one of multiple mailboxes
+    /// to receive a signal.
+    ///
+    /// Takes the same mailbox handles and returns the same encoded result as
+    /// [Self::poll], except that `u64::MAX` is returned if the timeout elapsed
+    /// first.
+    async fn poll_timeout(
+        &mut self,
+        memory: GuestMemory<'_>,
+        handles_ptr: u32,
+        handles_len: u32,
+        timeout_ns: u64,
+    ) -> Result<u64> {
+        let handles = memory.get_memory_slice(handles_ptr, handles_len)?;
+
+        if self.is_replaying() {
+            for handle in handles.iter() {
+                self.get_mb(*handle)?;
+            }
+
+            let event = self.next_replayed()?;
+            let RecordedEvent::PollTimeout { received } = event else {
+                return Err(diverged("poll_timeout", event.function()));
+            };
+
+            let Some((index, signal)) = received else {
+                return Ok(u64::MAX);
+            };
+
+            if index >= handles_len {
+                bail!(
+                    "replay diverged: recorded poll_timeout() index {} is out of bounds",
+                    index
+                );
+            }
+
+            let handle = self.insert_replayed(signal)?;
+            return Ok(((index as u64) << 32) | (handle as u64));
+        }
+
+        let waits = self.borrow_waits().clone();
+        let wait = self.begin_wait(&waits, handles);
+
+        let mbs = handles
+            .iter()
+            .map(|handle| self.get_mb(*handle))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .map(|mb| mb.recv(|signal| Signal::from(signal)))
+            .map(Box::pin);
+
+        let timeout = Duration::from_nanos(timeout_ns);
+        let result = tokio::time::timeout(timeout, futures_util::future::select_all(mbs)).await;
+        if let (Some(wait), Ok((_, index, _))) = (wait, &result) {
+            wait.received(handles[*index]);
+        }
+
+        let Ok((signal, index, _)) = result else {
+            self.record(RecordedEvent::PollTimeout { received: None });
+            return Ok(u64::MAX);
+        };
+
+        let signal = signal.context("process has been killed")?;
+        let handle = self.insert_signal(signal, |signal| RecordedEvent::PollTimeout {
+            received: Some((index as u32, signal)),
+        });
+
+        Ok(((index as u64) << 32) | (handle as u64))
+    }
+
     /// Frees a signal by handle.
     fn destroy_signal(&mut self, handle: u32) -> Result<()> {
         self.with_signals_mut(|signals| signals.try_remove(handle as usize))
@@ -635,6 +1115,207 @@ impl MailboxAbi {
 }
 
 impl MailboxAbi {
+    /// Records that this process is waiting on the given mailboxes in the
+    /// runtime's [WaitGraph]. Call [WaitGuard::received] on the returned
+    /// guard when one of the mailboxes receives a signal.
+    ///
+    /// Waits that include the parent mailbox are not recorded, since those
+    /// are a process idling for new requests, not waiting for a reply.
+    fn begin_wait<'a>(&self, waits: &'a WaitGraph, handles: &[u32]) -> Option<WaitGuard<'a>> {
+        if handles.contains(&0) {
+            return None;
+        }
+
+        let info = self.borrow_process().borrow_info();
+        let label = info.meta.name.as_deref().unwrap_or("<no name>");
+        Some(waits.begin_wait(info.pid, label, handles))
+    }
+
+    /// Helper function to record a received signal in the audit log and the
+    /// process's recording, if any, and store it. Returns the signal's handle.
+    ///
+    /// `event` creates the recorded event for the signal.
+    fn insert_signal(
+        &mut self,
+        signal: Signal,
+        event: impl FnOnce(RecordedSignal) -> RecordedEvent,
+    ) -> u32 {
+        if let Signal::Message { data, caps } = &signal {
+            let process = self.borrow_process();
+            let table = process.borrow_table();
+            let audit = self.borrow_audit();
+            if audit.is_enabled() {
+                let caps = caps
+                    .iter()
+                    .filter_map(|cap| table.get_owned(CapabilityHandle(*cap as usize)).ok())
+                    .collect();
+
+                audit.record_receive(process.borrow_info(), caps);
+            }
+
+            self.with_recent_mut(|recent| recent.push(data, caps.len()));
+        }
+
+        if matches!(self.borrow_source(), SignalSource::Recording(_)) {
+            let recorded = self.record_signal(&signal);
+            self.record(event(recorded));
+        }
+
+        let handle = self.with_signals_mut(|signals| signals.insert(signal));
+        handle.try_into().unwrap()
+    }
+
+    /// Helper function to convert a received signal for the process's
+    /// recording.
+    fn record_signal(&self, signal: &Signal) -> RecordedSignal {
+        match signal {
+            Signal::Down { handle } => RecordedSignal::Down { handle: *handle },
+            Signal::Message { data, caps } => {
+                let table = self.borrow_process().borrow_table();
+                let caps = caps
+                    .iter()
+                    .map(|handle| RecordedCap {
+                        handle: *handle,
+                        perms: table
+                            .get_permissions(CapabilityHandle(*handle as usize))
+                            .map(|perms| perms.bits())
+                            .unwrap_or_default(),
+                    })
+                    .collect();
+
+                RecordedSignal::Message {
+                    data: data.clone(),
+                    caps,
+                }
+            }
+        }
+    }
+
+    /// Helper function to add an event to the process's recording, if it's
+    /// being recorded.
+    fn record(&mut self, event: RecordedEvent) {
+        self.with_source_mut(|source| {
+            if let SignalSource::Recording(recorder) = source {
+                recorder.lock().unwrap().push(event);
+            }
+        });
+    }
+
+    /// Adds stand-ins for a replayed process's initial capabilities, in
+    /// order.
+    fn insert_standins(&mut self, caps: &[RecordedCap]) -> Result<()> {
+        for cap in caps {
+            self.insert_standin(*cap)?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the process's recording, if it's being recorded.
+    fn take_recording(&mut self) -> Option<Arc<Mutex<Recorder>>> {
+        self.with_source_mut(
+            |source| match std::mem::replace(source, SignalSource::Live) {
+                SignalSource::Recording(recorder) => Some(recorder),
+                other => {
+                    *source = other;
+                    None
+                }
+            },
+        )
+    }
+
+    /// Returns true if the process is being replayed.
+    fn is_replaying(&self) -> bool {
+        matches!(self.borrow_source(), SignalSource::Replaying(_))
+    }
+
+    /// The number of recorded events that a replayed process hasn't received.
+    fn remaining_replayed(&self) -> usize {
+        match self.borrow_source() {
+            SignalSource::Replaying(replayer) => replayer.remaining(),
+            _ => 0,
+        }
+    }
+
+    /// Helper function to take the next recorded event of a replayed process.
+    fn next_replayed(&mut self) -> Result<RecordedEvent> {
+        self.with_source_mut(|source| match source {
+            SignalSource::Replaying(replayer) => Ok(replayer.next_event()?),
+            _ => bail!("process is not being replayed"),
+        })
+    }
+
+    /// Helper function to store a replayed signal, replacing its capabilities
+    /// with stand-ins. Returns the signal's handle.
+    fn insert_replayed(&mut self, signal: RecordedSignal) -> Result<u32> {
+        let signal = match signal {
+            RecordedSignal::Down { handle } => {
+                // capabilities that the process made itself have no stand-in,
+                // and are assumed to have the same handle as when recorded
+                let standin = self.with_source(|source| match source {
+                    SignalSource::Replaying(replayer) => replayer.get_standin(handle),
+                    _ => None,
+                });
+
+                Signal::Down {
+                    handle: standin.unwrap_or(handle),
+                }
+            }
+            RecordedSignal::Message { data, caps } => {
+                let caps = caps
+                    .into_iter()
+                    .map(|cap| self.insert_standin(cap))
+                    .collect::<Result<_>>()?;
+
+                self.with_recent_mut(|recent| recent.push(&data, caps.len()));
+                Signal::Message { data, caps }
+            }
+        };
+
+        let handle = self.with_signals_mut(|signals| signals.insert(signal));
+        Ok(handle.try_into().unwrap())
+    }
+
+    /// Adds a stand-in for a recorded capability to the process's table.
+    ///
+    /// Stand-ins are created once for each recorded capability, and the
+    /// process gets a new reference to the same stand-in each time that
+    /// capability is replayed, like it would for the original capability.
+    fn insert_standin(&mut self, cap: RecordedCap) -> Result<u32> {
+        self.with_mut(|fields| {
+            let SignalSource::Replaying(replayer) = fields.source else {
+                bail!("process is not being replayed");
+            };
+
+            let table = fields.process.borrow_table();
+            if let Some(handle) = replayer.get_standin(cap.handle) {
+                table
+                    .inc_ref(CapabilityHandle(handle as usize))
+                    .with_context(|| format!("inc_ref({handle})"))?;
+
+                return Ok(handle);
+            }
+
+            let mb = fields
+                .arena
+                .group
+                .create_mailbox()
+                .context("process has been killed")?;
+
+            let perms = Permissions::from_bits_truncate(cap.perms);
+            let handle = mb
+                .export(perms)
+                .unwrap()
+                .into_handle()
+                .0
+                .try_into()
+                .unwrap();
+            fields.arena.standins.push(mb);
+            replayer.set_standin(cap.handle, handle);
+            Ok(handle)
+        })
+    }
+
     /// Helper function to get a reference to a mailbox by its handle.
     ///
     /// Fails if the handle is invalid.
@@ -742,6 +1423,7 @@ pub enum ProcessData {
     Running {
         log: LogAbi,
         lump: LumpAbi,
+        stream: StreamAbi,
         table: TableAbi,
         mailbox: MailboxAbi,
     },
@@ -771,6 +1453,7 @@ macro_rules! impl_running_get_abi {
 
 impl_running_get_abi!(ProcessData, LogAbi, log);
 impl_running_get_abi!(ProcessData, LumpAbi, lump);
+impl_running_get_abi!(ProcessData, StreamAbi, stream);
 impl_running_get_abi!(ProcessData, TableAbi, table);
 impl_running_get_abi!(ProcessData, MailboxAbi, mailbox);
 
@@ -781,21 +1464,40 @@ impl ProcessData {
         }
     }
 
-    pub fn new_running(runtime: &Runtime, process: Process, this_lump: LumpId) -> Self {
+    pub(crate) fn new_running(
+        runtime: &Runtime,
+        process: Process,
+        this_lump: LumpId,
+        source: SignalSource,
+        calls: CallSource,
+    ) -> Self {
         let process = Arc::new(process);
 
         Self::Running {
             log: LogAbi {
                 process: process.clone(),
+                calls: calls.clone(),
             },
-            lump: LumpAbi::new(runtime, this_lump),
+            lump: LumpAbi::new(runtime, this_lump, calls.clone()),
+            stream: StreamAbi::new(runtime, calls),
             table: TableAbi {
                 process: process.clone(),
+                waits: runtime.waits.clone(),
+                audit: runtime.audit.clone(),
             },
-            mailbox: MailboxAbi::new(process, Slab::new(), |process| MailboxArena {
-                group: process.borrow_group(),
-                mbs: Slab::new(),
-            }),
+            mailbox: MailboxAbi::new(
+                process,
+                Slab::new(),
+                runtime.waits.clone(),
+                runtime.audit.clone(),
+                RecentMessages::default(),
+                source,
+                |process| MailboxArena {
+                    group: process.borrow_group(),
+                    mbs: Slab::new(),
+                    standins: Vec::new(),
+                },
+            ),
         }
     }
 
@@ -803,6 +1505,7 @@ impl ProcessData {
     pub fn add_to_linker(linker: &mut Linker<Self>) {
         LogAbi::add_to_linker(linker);
         LumpAbi::add_to_linker(linker);
+        StreamAbi::add_to_linker(linker);
         TableAbi::add_to_linker(linker);
         MailboxAbi::add_to_linker(linker);
         MetadataAbi::add_to_linker(linker);
@@ -867,8 +1570,8 @@ impl WasmProcess {
         Ok(metadata.meta.to_owned())
     }
 
-    /// Executes a Wasm process.
-    async fn run(mut self, runtime: Arc<Runtime>, ctx: Process, entrypoint: Option<u32>) {
+    /// Switches the process's ABIs to running in the given process context.
+    fn start(&mut self, runtime: &Runtime, ctx: Process, source: SignalSource, calls: CallSource) {
         // grab the PID for logging
         let pid = ctx.borrow_info().pid;
 
@@ -881,7 +1584,8 @@ impl WasmProcess {
         }
 
         // switch the process ABIs to running
-        *self.store.data_mut() = ProcessData::new_running(runtime.as_ref(), ctx, self.this_lump);
+        *self.store.data_mut() =
+            ProcessData::new_running(runtime, ctx, self.this_lump, source, calls);
 
         // while executing the main function, preemptively timeslice until killed
         self.store.epoch_deadline_callback(move |store| {
@@ -895,20 +1599,131 @@ impl WasmProcess {
 
             Ok(UpdateDeadline::Yield(1))
         });
+    }
 
-        // call inner execution behavior and handle its errors
-        match self
+    /// Executes a Wasm process. Returns false if the process crashed or was
+    /// killed.
+    async fn run(
+        mut self,
+        runtime: Arc<Runtime>,
+        ctx: Process,
+        entrypoint: Option<u32>,
+        source: SignalSource,
+        calls: CallSource,
+    ) -> bool {
+        // grab the PID for logging
+        let pid = ctx.borrow_info().pid;
+
+        self.start(&runtime, ctx, source, calls);
+
+        // call inner execution behavior
+        let result = self
             .run_inner(entrypoint)
             .await
-            .with_context(|| format!("PID {}", pid))
-        {
-            Ok(()) => {}
+            .with_context(|| format!("PID {}", pid));
+
+        // forget the process's mailboxes
+        runtime.waits.remove_process(pid);
+
+        // store the process's recording, if it was recorded
+        let recording = self.store_recording(&runtime, entrypoint).await;
+
+        // handle execution errors
+        match result {
+            Ok(()) => true,
             Err(err) => {
                 error!("{:?}", err);
+                self.report_crash(&err, recording);
+                false
+            }
+        }
+    }
+
+    /// Replays a recording of a Wasm process. The process must have been
+    /// created from the recording's module.
+    pub(crate) async fn replay(
+        mut self,
+        runtime: Arc<Runtime>,
+        ctx: Process,
+        recording: Recording,
+    ) -> ReplayOutcome {
+        // grab the PID for logging
+        let pid = ctx.borrow_info().pid;
+
+        let source = SignalSource::Replaying(Replayer::new(recording.events));
+        let calls = CallSource::Replaying(Arc::new(Mutex::new(recording.calls.into())));
+        self.start(&runtime, ctx, source, calls);
+
+        let ProcessData::Running { mailbox, .. } = self.store.data_mut() else {
+            unreachable!("replayed process is not running");
+        };
+
+        // stand in for the initial capabilities first so that their handles
+        // match the recorded ones
+        let result = match mailbox.insert_standins(&recording.initial_caps) {
+            Ok(()) => self.run_inner(recording.entrypoint).await,
+            Err(err) => Err(err),
+        };
+
+        runtime.waits.remove_process(pid);
+
+        let remaining = match self.store.data() {
+            ProcessData::Running { mailbox, .. } => mailbox.remaining_replayed(),
+            _ => 0,
+        };
+
+        match result.with_context(|| format!("PID {} (replay)", pid)) {
+            Ok(()) => ReplayOutcome::Exited { remaining },
+            Err(err) if err.downcast_ref::<ReplayExhausted>().is_some() => ReplayOutcome::Exhausted,
+            Err(err) => {
+                warn!("Replay failed: {:?}", err);
+                ReplayOutcome::Failed {
+                    error: format!("{:#}", err),
+                    remaining,
+                }
             }
         }
     }
 
+    /// Stores this process's recording in a lump, if it was recorded.
+    async fn store_recording(
+        &mut self,
+        runtime: &Runtime,
+        entrypoint: Option<u32>,
+    ) -> Option<LumpId> {
+        let ProcessData::Running { mailbox, .. } = self.store.data_mut() else {
+            return None;
+        };
+
+        let pid = mailbox.borrow_process().borrow_info().pid;
+        let recorder = mailbox.take_recording()?;
+        let recording = recorder.lock().unwrap().finish(self.this_lump, entrypoint);
+        let data = serde_json::to_vec(&recording).unwrap();
+        let lump = runtime.lump_store.add_lump(data.into()).await;
+        info!("Stored recording of PID {} in lump {}", pid, lump);
+        Some(lump)
+    }
+
+    /// Stores a crash report of this process failing with an error, unless
+    /// it failed because it was killed.
+    fn report_crash(&self, err: &Error, recording: Option<LumpId>) {
+        let ProcessData::Running { mailbox, .. } = self.store.data() else {
+            return;
+        };
+
+        let process = mailbox.borrow_process();
+        if process.borrow_group().poll_dead() {
+            return;
+        }
+
+        let messages = mailbox.borrow_recent().to_vec();
+        let mut report = crash::build_report(process.borrow_info(), self.this_lump, err, messages);
+        report.recording = recording;
+        if let Err(err) = CrashReportDir::default().write(&report) {
+            warn!("Failed to write crash report: {:?}", err);
+        }
+    }
+
     /// Performs the actual process execution using easy error handling.
     async fn run_inner(&mut self, entrypoint: Option<u32>) -> Result<()> {
         // run the `_hearth_init` export, if available
@@ -969,11 +1784,23 @@ impl RequestResponseProcess for WasmProcessSpawner {
         &'a mut self,
         request: &mut RequestInfo<'a, WasmSpawnInfo>,
     ) -> ResponseInfo<'a, Self::Response> {
+        let table = request.process.borrow_table();
+        let caps: Vec<_> = request.cap_args.iter().collect();
+        let spawned = spawn_process(
+            &self.engine,
+            &self.linker,
+            request.runtime,
+            table,
+            &request.data,
+            &caps,
+        )
+        .await;
+
         ResponseInfo {
             data: (),
-            caps: match self.spawn(request).await {
+            caps: match spawned {
                 // spawned successfully; return cap
-                Ok(child) => vec![child],
+                Ok((child, _exit)) => vec![child],
                 // error occurred. log and no cap
                 Err(err) => {
                     error!("Wasm spawning error: {:?}", err);
@@ -985,58 +1812,97 @@ impl RequestResponseProcess for WasmProcessSpawner {
 }
 
 impl ServiceRunner for WasmProcessSpawner {
-    const NAME: &'static str = "hearth.wasm.WasmProcessSpawner";
+    const NAME: &'static str = hearth_schema::wasm::SPAWNER_SERVICE_NAME;
 }
 
-impl WasmProcessSpawner {
-    pub async fn spawn<'a>(
-        &'a mut self,
-        request: &mut RequestInfo<'a, WasmSpawnInfo>,
-    ) -> Result<CapabilityRef<'a>> {
-        // load the WebAssembly module from the asset store
-        let module = request
-            .runtime
-            .asset_store
-            .load_asset::<WasmModuleLoader>(&request.data.lump)
-            .await
-            .context("loading Wasm module")?;
-
-        // instantiate a new WasmProcess
-        let mut process = WasmProcess::new(&self.engine, &self.linker, &module, request.data.lump)
-            .await
-            .context("initializing process")?;
-
-        // retrieve the process's metadata
-        let meta = process
-            .get_metadata()
-            .await
-            .context("retrieving process metadata")?;
-
-        // spawn a new local process
-        let child = request.runtime.process_factory.spawn(meta);
-
-        // import a capability to its parent mailbox
-        let child_cap = child
-            .borrow_parent()
-            .export_to(Permissions::all(), request.process.borrow_table())
-            .unwrap();
+/// Spawns a Wasm process from a module lump with the given initial
+/// capabilities.
+///
+/// Returns a capability with every permission to the new process in `table`
+/// and a handle to the process's task, which finishes with false if the
+/// process crashed or was killed.
+pub(crate) async fn spawn_process<'a>(
+    engine: &Engine,
+    linker: &Linker<ProcessData>,
+    runtime: &Arc<Runtime>,
+    table: &'a Table,
+    info: &WasmSpawnInfo,
+    caps: &[&CapabilityRef<'_>],
+) -> Result<(CapabilityRef<'a>, JoinHandle<bool>)> {
+    // load the WebAssembly module from the asset store
+    let module = runtime
+        .asset_store
+        .load_asset::<WasmModuleLoader>(&info.lump)
+        .await
+        .context("loading Wasm module")?;
+
+    // instantiate a new WasmProcess
+    let mut process = WasmProcess::new(engine, linker, &module, info.lump)
+        .await
+        .context("initializing process")?;
+
+    // retrieve the process's metadata
+    let mut meta = process
+        .get_metadata()
+        .await
+        .context("retrieving process metadata")?;
+
+    meta.lump = Some(info.lump);
+
+    // spawn a new local process
+    let child = runtime.process_factory.spawn(meta);
+
+    // import a capability to its parent mailbox
+    let child_cap = child
+        .borrow_parent()
+        .export_to(Permissions::all(), table)
+        .unwrap();
+
+    // let the wait graph find requests sent to the child
+    let pid = child.borrow_info().pid;
+    runtime.waits.add_mailbox(child_cap.to_owned(), pid, 0);
+
+    // send the child the initial capabilities
+    child_cap.send(&[], caps).await.unwrap();
+
+    // flush the child's mailbox to import the initial capabilities
+    let initial_caps = child
+        .borrow_parent()
+        .recv(|signal| match signal {
+            TableSignal::Message { caps, .. } => caps.to_vec(),
+            TableSignal::Down { .. } => Vec::new(),
+        })
+        .await
+        .unwrap();
 
-        // send the child the initial capabilities from the request
-        child_cap
-            .send(&[], request.cap_args.iter().collect::<Vec<_>>().as_slice())
-            .await
-            .unwrap();
+    // record the process if requested
+    let (source, calls) = if info.record {
+        let child_table = child.borrow_table();
+        let caps = initial_caps
+            .into_iter()
+            .map(|handle| RecordedCap {
+                handle: handle.0 as u32,
+                perms: child_table
+                    .get_permissions(handle)
+                    .map(|perms| perms.bits())
+                    .unwrap_or_default(),
+            })
+            .collect();
 
-        // flush the child's mailbox to import the initial capabilities
-        child.borrow_parent().recv(|_| ()).await.unwrap();
+        let recorder = Arc::new(Mutex::new(Recorder::new(caps)));
+        (
+            SignalSource::Recording(recorder.clone()),
+            CallSource::Recording(recorder),
+        )
+    } else {
+        (SignalSource::Live, CallSource::Live)
+    };
 
-        // run the process
-        let runtime = request.runtime.clone();
-        tokio::spawn(process.run(runtime, child, request.data.entrypoint));
+    // run the process
+    let exit = tokio::spawn(process.run(runtime.clone(), child, info.entrypoint, source, calls));
 
-        // return the child's cap
-        Ok(child_cap)
-    }
+    // return the child's cap
+    Ok((child_cap, exit))
 }
 
 pub struct WasmModuleLoader {
@@ -1076,11 +1942,25 @@ impl Plugin for WasmPlugin {
         let mut linker = Linker::new(&self.engine);
         ProcessData::add_to_linker(&mut linker);
 
+        let linker = Arc::new(linker);
+
         builder.add_plugin(WasmProcessSpawner {
             engine: self.engine.to_owned(),
-            linker: Arc::new(linker),
+            linker: linker.clone(),
+        });
+
+        builder.add_plugin(WasmSupervisor {
+            engine: self.engine.to_owned(),
+            linker: linker.clone(),
         });
 
+        builder.add_plugin(WasmReplayer {
+            engine: self.engine.to_owned(),
+            linker,
+        });
+
+        builder.add_plugin(CrashReportService::default());
+
         builder.add_asset_loader(WasmModuleLoader {
             engine: self.engine.to_owned(),
         });
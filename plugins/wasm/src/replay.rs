@@ -0,0 +1,435 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Recording and deterministic replay of the inputs of Wasm processes.
+//!
+//! Besides their initial capabilities, Wasm processes get inputs from outside
+//! of their module in two ways: the signals they receive through the mailbox
+//! ABI, and the results of host calls that depend on the state of the host,
+//! like writing to a plugin's stream or loading a spilled-over message.
+//! Recording both is enough to run a process again and have it take the same
+//! path, with the runtime's scheduling taken out of the picture.
+//!
+//! Replays are sandboxed. Recorded capabilities are replaced with stand-ins
+//! that nothing reads, and host calls that would reach outside of the
+//! process, like writing to streams, return their recorded results instead
+//! of being made.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::{Arc, Mutex};
+
+use hearth_runtime::anyhow::{anyhow, Context, Error};
+use hearth_runtime::hearth_macros::GetProcessMetadata;
+use hearth_runtime::hearth_schema::wasm::*;
+use hearth_runtime::hearth_schema::LumpId;
+use hearth_runtime::runtime::Runtime;
+use hearth_runtime::{async_trait, utils::*};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use wasmtime::{Engine, Linker};
+
+use crate::{ProcessData, WasmModuleLoader, WasmProcess};
+
+/// The most message and spill data that is recorded for a single process.
+/// Later inputs are not recorded, so replays of that process end early.
+const MAX_RECORDED_BYTES: usize = 64 * 1024 * 1024;
+
+/// A recorded run of a Wasm process, stored as JSON in a lump.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Recording {
+    /// The lump of the process's module.
+    pub module: LumpId,
+
+    /// The entrypoint that the process was spawned with.
+    pub entrypoint: Option<u32>,
+
+    /// The capabilities the process was spawned with, in order.
+    pub initial_caps: Vec<RecordedCap>,
+
+    /// Every signal the process received, in order.
+    pub events: Vec<RecordedEvent>,
+
+    /// The results of the process's other non-deterministic host calls, in
+    /// order.
+    #[serde(default)]
+    pub calls: Vec<RecordedCall>,
+}
+
+/// A signal given to a process by one of the mailbox ABI's receiving
+/// functions.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) enum RecordedEvent {
+    /// A signal returned by `recv`.
+    Recv { signal: RecordedSignal },
+
+    /// The result of a `try_recv`, which may have found no signal.
+    TryRecv { signal: Option<RecordedSignal> },
+
+    /// A signal returned by `poll` from the mailbox at `index`.
+    Poll { index: u32, signal: RecordedSignal },
+
+    /// The result of a `recv_timeout`, which may have timed out.
+    RecvTimeout { signal: Option<RecordedSignal> },
+
+    /// The result of a `poll_timeout`, which may have timed out, with the
+    /// index of the mailbox that received the signal.
+    PollTimeout {
+        received: Option<(u32, RecordedSignal)>,
+    },
+}
+
+impl RecordedEvent {
+    /// Gets the name of the ABI function that this event was recorded from.
+    pub fn function(&self) -> &'static str {
+        match self {
+            RecordedEvent::Recv { .. } => "recv",
+            RecordedEvent::TryRecv { .. } => "try_recv",
+            RecordedEvent::Poll { .. } => "poll",
+            RecordedEvent::RecvTimeout { .. } => "recv_timeout",
+            RecordedEvent::PollTimeout { .. } => "poll_timeout",
+        }
+    }
+
+    /// Gets the size of this event's message data, if any.
+    fn data_len(&self) -> usize {
+        let signal = match self {
+            RecordedEvent::Recv { signal } => Some(signal),
+            RecordedEvent::TryRecv { signal } => signal.as_ref(),
+            RecordedEvent::Poll { signal, .. } => Some(signal),
+            RecordedEvent::RecvTimeout { signal } => signal.as_ref(),
+            RecordedEvent::PollTimeout { received } => received.as_ref().map(|(_, signal)| signal),
+        };
+
+        match signal {
+            Some(RecordedSignal::Message { data, .. }) => data.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// The result of a host call that depends on the state of the host.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) enum RecordedCall {
+    /// The handle returned by `hearth::stream::open`, which is `u32::MAX` if
+    /// there was no stream with the token.
+    StreamOpen { handle: u32 },
+
+    /// The status returned by `hearth::stream::write`.
+    StreamWrite { status: u32 },
+
+    /// The payload of a spilled-over message returned by
+    /// `hearth::lump::load_spill`. Spills are freed once they're loaded, so
+    /// the payload can't be loaded again.
+    LoadSpill { data: Vec<u8> },
+
+    /// The ID returned by `hearth::log::new_trace_id`.
+    NewTraceId { id: [u8; 16] },
+}
+
+impl RecordedCall {
+    /// Gets the name of the ABI function that this call was recorded from.
+    pub fn function(&self) -> &'static str {
+        match self {
+            RecordedCall::StreamOpen { .. } => "open",
+            RecordedCall::StreamWrite { .. } => "write",
+            RecordedCall::LoadSpill { .. } => "load_spill",
+            RecordedCall::NewTraceId { .. } => "new_trace_id",
+        }
+    }
+
+    /// Gets the size of this call's data, if any.
+    fn data_len(&self) -> usize {
+        match self {
+            RecordedCall::LoadSpill { data } => data.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// A recorded signal, with capabilities identified by their handles in the
+/// recorded process's table.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) enum RecordedSignal {
+    Down {
+        handle: u32,
+    },
+    Message {
+        data: Vec<u8>,
+        caps: Vec<RecordedCap>,
+    },
+}
+
+/// A capability in a recorded process's table.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub(crate) struct RecordedCap {
+    pub handle: u32,
+    pub perms: u32,
+}
+
+/// Where the mailbox ABI gets the signals that it gives to a process.
+pub(crate) enum SignalSource {
+    /// Signals are received from the process's mailboxes.
+    Live,
+
+    /// Signals are received from the process's mailboxes and recorded.
+    Recording(Arc<Mutex<Recorder>>),
+
+    /// Signals are taken from a recording instead of the process's
+    /// mailboxes.
+    Replaying(Replayer),
+}
+
+/// Where the stream and lump ABIs get the results of host calls that depend
+/// on the state of the host. Shared by both ABIs, so that their calls are
+/// kept in one order.
+#[derive(Clone, Debug)]
+pub(crate) enum CallSource {
+    /// Host calls are made.
+    Live,
+
+    /// Host calls are made and their results are recorded.
+    Recording(Arc<Mutex<Recorder>>),
+
+    /// Host calls that reach outside of the process aren't made, and their
+    /// results are taken from a recording instead.
+    Replaying(Arc<Mutex<VecDeque<RecordedCall>>>),
+}
+
+impl CallSource {
+    /// Records the result of a host call, if the process is being recorded.
+    pub fn record(&self, call: RecordedCall) {
+        if let CallSource::Recording(recorder) = self {
+            recorder.lock().unwrap().push_call(call);
+        }
+    }
+
+    /// Returns true if the process is being replayed.
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, CallSource::Replaying(_))
+    }
+
+    /// Takes the result of the next recorded host call of a replayed process.
+    ///
+    /// Fails with [ReplayExhausted] once every call has been taken.
+    pub fn next_replayed(&self) -> Result<RecordedCall, Error> {
+        match self {
+            CallSource::Replaying(calls) => {
+                Ok(calls.lock().unwrap().pop_front().ok_or(ReplayExhausted)?)
+            }
+            _ => Err(anyhow!("process is not being replayed")),
+        }
+    }
+}
+
+/// Records the signals received by a process and the results of its
+/// host calls.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    initial_caps: Vec<RecordedCap>,
+    events: Vec<RecordedEvent>,
+    calls: Vec<RecordedCall>,
+    bytes: usize,
+    full: bool,
+}
+
+impl Recorder {
+    /// Starts recording a process that was spawned with the given
+    /// capabilities.
+    pub fn new(initial_caps: Vec<RecordedCap>) -> Self {
+        Self {
+            initial_caps,
+            events: Vec::new(),
+            calls: Vec::new(),
+            bytes: 0,
+            full: false,
+        }
+    }
+
+    /// Records an event, unless the recording is full.
+    pub fn push(&mut self, event: RecordedEvent) {
+        if self.reserve(event.data_len()) {
+            self.events.push(event);
+        }
+    }
+
+    /// Records the result of a host call, unless the recording is full.
+    pub fn push_call(&mut self, call: RecordedCall) {
+        if self.reserve(call.data_len()) {
+            self.calls.push(call);
+        }
+    }
+
+    /// Counts `len` more recorded bytes. Returns false and stops recording if
+    /// they don't fit.
+    fn reserve(&mut self, len: usize) -> bool {
+        if self.full {
+            return false;
+        }
+
+        if self.bytes + len > MAX_RECORDED_BYTES {
+            warn!("Recording is full; later inputs will not be recorded");
+            self.full = true;
+            return false;
+        }
+
+        self.bytes += len;
+        true
+    }
+
+    /// Finishes this recording, leaving this recorder empty.
+    pub fn finish(&mut self, module: LumpId, entrypoint: Option<u32>) -> Recording {
+        Recording {
+            module,
+            entrypoint,
+            initial_caps: std::mem::take(&mut self.initial_caps),
+            events: std::mem::take(&mut self.events),
+            calls: std::mem::take(&mut self.calls),
+        }
+    }
+}
+
+/// Gives a process the signals of a recording.
+pub(crate) struct Replayer {
+    events: VecDeque<RecordedEvent>,
+
+    /// Maps recorded capability handles to the handles of their stand-ins.
+    standins: HashMap<u32, u32>,
+}
+
+impl Replayer {
+    /// Creates a replayer for a recording's events.
+    pub fn new(events: Vec<RecordedEvent>) -> Self {
+        Self {
+            events: events.into(),
+            standins: HashMap::new(),
+        }
+    }
+
+    /// Takes the next recorded event.
+    ///
+    /// Fails with [ReplayExhausted] once every event has been taken.
+    pub fn next_event(&mut self) -> Result<RecordedEvent, ReplayExhausted> {
+        self.events.pop_front().ok_or(ReplayExhausted)
+    }
+
+    /// The number of events that haven't been taken yet.
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Gets the handle of a recorded capability's stand-in, if it has one.
+    pub fn get_standin(&self, handle: u32) -> Option<u32> {
+        self.standins.get(&handle).copied()
+    }
+
+    /// Sets the handle of a recorded capability's stand-in.
+    pub fn set_standin(&mut self, handle: u32, standin: u32) {
+        self.standins.insert(handle, standin);
+    }
+}
+
+/// Creates the error that a replayed process fails with when it calls a
+/// different function than the one that was recorded.
+pub(crate) fn diverged(function: &str, recorded: &str) -> Error {
+    anyhow!(
+        "replay diverged: process called {}() but the recording has {}()",
+        function,
+        recorded
+    )
+}
+
+/// The error that a replayed process fails with when it tries to receive a
+/// signal after every recorded one.
+#[derive(Debug)]
+pub(crate) struct ReplayExhausted;
+
+impl Display for ReplayExhausted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "every recorded input has been replayed")
+    }
+}
+
+impl std::error::Error for ReplayExhausted {}
+
+/// The native Wasm replay service. Accepts [ReplayRequest].
+///
+/// Runs recorded Wasm processes again with their recorded signals and host
+/// call results. Replays run one at a time.
+#[derive(GetProcessMetadata)]
+pub struct WasmReplayer {
+    pub(crate) engine: Arc<Engine>,
+    pub(crate) linker: Arc<Linker<ProcessData>>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for WasmReplayer {
+    type Request = ReplayRequest;
+    type Response = ReplayResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, ReplayRequest>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        self.replay(request.runtime, &request.data).await.into()
+    }
+}
+
+impl ServiceRunner for WasmReplayer {
+    const NAME: &'static str = REPLAYER_SERVICE_NAME;
+}
+
+impl WasmReplayer {
+    async fn replay(&self, runtime: &Arc<Runtime>, request: &ReplayRequest) -> ReplayResponse {
+        let data = runtime
+            .lump_store
+            .get_lump(&request.recording)
+            .await
+            .ok_or(ReplayError::MissingRecording)?;
+
+        let recording: Recording =
+            serde_json::from_slice(&data).map_err(|_| ReplayError::InvalidRecording)?;
+
+        let spawn_failed = |err: Error| ReplayError::SpawnFailed(format!("{:#}", err));
+
+        let module = runtime
+            .asset_store
+            .load_asset::<WasmModuleLoader>(&recording.module)
+            .await
+            .context("loading Wasm module")
+            .map_err(spawn_failed)?;
+
+        let mut process = WasmProcess::new(&self.engine, &self.linker, &module, recording.module)
+            .await
+            .context("initializing process")
+            .map_err(spawn_failed)?;
+
+        let mut meta = process
+            .get_metadata()
+            .await
+            .context("retrieving process metadata")
+            .map_err(spawn_failed)?;
+
+        let name = meta.name.as_deref().unwrap_or("Wasm process");
+        meta.name = Some(format!("{} (replay)", name));
+        meta.lump = Some(recording.module);
+
+        let ctx = runtime.process_factory.spawn(meta);
+        Ok(process.replay(runtime.clone(), ctx, recording).await)
+    }
+}
@@ -0,0 +1,560 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! Supervision trees that restart crashed Wasm processes.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hearth_runtime::flue::{CapabilityRef, OwnedCapability, OwnedTableSignal, Permissions, Table};
+use hearth_runtime::hearth_macros::GetProcessMetadata;
+use hearth_runtime::hearth_schema::codec::Codec;
+use hearth_runtime::hearth_schema::wasm::*;
+use hearth_runtime::process::Process;
+use hearth_runtime::runtime::Runtime;
+use hearth_runtime::tokio::{
+    self,
+    sync::{mpsc, oneshot, watch},
+};
+use hearth_runtime::{async_trait, utils::*};
+use tracing::{debug, error, info, warn};
+use wasmtime::{Engine, Linker};
+
+use crate::{spawn_process, ProcessData};
+
+/// The native Wasm process supervisor. Accepts [SupervisorSpec].
+///
+/// Host plugins can start supervision trees by sending this service the same
+/// requests that guests do.
+#[derive(GetProcessMetadata)]
+pub struct WasmSupervisor {
+    pub(crate) engine: Arc<Engine>,
+    pub(crate) linker: Arc<Linker<ProcessData>>,
+}
+
+#[async_trait]
+impl RequestResponseProcess for WasmSupervisor {
+    type Request = SupervisorSpec;
+    type Response = SupervisorResponse;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, SupervisorSpec>,
+    ) -> ResponseInfo<'a, Self::Response> {
+        let spec = request.data.clone();
+
+        for child in spec.children.iter() {
+            if let Some(index) = child.caps.iter().find(|i| **i >= request.cap_args.len()) {
+                return SupervisorError::InvalidCap {
+                    child: child.name.clone(),
+                    index: *index,
+                }
+                .into();
+            }
+        }
+
+        let table = request.process.borrow_table();
+        let runtime = request.runtime;
+
+        let mut meta = Self::get_process_metadata();
+        meta.name = Some(SUPERVISOR_SERVICE_NAME.to_string());
+        let supervisor = runtime.process_factory.spawn(meta);
+        let supervisor_cap = supervisor
+            .borrow_parent()
+            .export_to(Permissions::all(), table)
+            .unwrap();
+
+        let mut caps = vec![supervisor_cap];
+        let mut children = Vec::new();
+        for spec in spec.children.iter() {
+            let mut meta = Self::get_process_metadata();
+            meta.name = Some(spec.name.clone());
+            meta.description = Some("A handle to a supervised Wasm process.".to_string());
+            let handle = runtime.process_factory.spawn(meta);
+
+            let perms = Permissions::SEND | Permissions::MONITOR;
+            caps.push(handle.borrow_parent().export_to(perms, table).unwrap());
+
+            let (current_tx, current_rx) = watch::channel(None);
+            tokio::spawn(forward(handle, current_rx));
+
+            children.push(ChildArgs {
+                caps: spec
+                    .caps
+                    .iter()
+                    .map(|index| request.cap_args[*index].to_owned())
+                    .collect(),
+                current_tx,
+            });
+        }
+
+        let (ready_tx, ready_rx) = oneshot::channel();
+        tokio::spawn(supervise(
+            Supervisor {
+                engine: self.engine.clone(),
+                linker: self.linker.clone(),
+                runtime: runtime.clone(),
+                spec,
+            },
+            supervisor,
+            children,
+            ready_tx,
+        ));
+
+        match ready_rx.await {
+            Ok(Ok(())) => ResponseInfo { data: Ok(()), caps },
+            Ok(Err(err)) => err.into(),
+            Err(_) => unreachable!("supervisor quit before starting its children"),
+        }
+    }
+}
+
+impl ServiceRunner for WasmSupervisor {
+    const NAME: &'static str = SUPERVISOR_SERVICE_NAME;
+}
+
+/// Forwards messages from a child's handle to its current instance.
+///
+/// Messages are left in the handle's mailbox while the child isn't running.
+/// Quits, taking the handle down, once the supervisor drops `current`.
+async fn forward(handle: Process, mut current: watch::Receiver<Option<OwnedCapability>>) {
+    let table = handle.borrow_table();
+    let mut target = None;
+
+    loop {
+        tokio::select! {
+            changed = current.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+
+                let next = current.borrow().clone();
+                target = next
+                    .and_then(|cap| table.import_owned(cap).ok())
+                    .and_then(|handle| table.wrap_handle(handle).ok());
+            }
+            signal = handle.borrow_parent().recv_owned(), if target.is_some() => {
+                let Some(target) = target.as_ref() else {
+                    continue;
+                };
+
+                match signal {
+                    Some(OwnedTableSignal::Message { data, caps }) => {
+                        let caps: Vec<_> = caps.iter().collect();
+                        if let Err(err) = target.send(&data, &caps).await {
+                            debug!("failed to forward to supervised child: {:?}", err);
+                        }
+                    }
+                    Some(OwnedTableSignal::Down { .. }) => {}
+                    None => break,
+                }
+            }
+        }
+    }
+}
+
+/// The per-child arguments to [supervise].
+struct ChildArgs {
+    /// The capabilities given to the child every time it starts.
+    caps: Vec<OwnedCapability>,
+
+    /// Sends the child's current instance to its handle.
+    current_tx: watch::Sender<Option<OwnedCapability>>,
+}
+
+/// A supervised child's state.
+struct Child<'a> {
+    args: ChildArgs,
+
+    /// A capability to the running instance of this child, if any.
+    current: Option<CapabilityRef<'a>>,
+
+    /// Incremented whenever this child is started or stopped so that events
+    /// about its earlier instances are ignored.
+    generation: u64,
+
+    /// When this child was last started.
+    started: Instant,
+
+    /// The number of consecutive restarts, for backing off.
+    restarts: u32,
+
+    /// The total number of restarts, for [ChildStatus::restarts].
+    total_restarts: u32,
+
+    /// When this child is due to be restarted, if it is waiting to be.
+    restart_at: Option<Instant>,
+}
+
+/// An event in a supervisor's task.
+enum Event {
+    /// A child's instance has exited.
+    Exited {
+        index: usize,
+        generation: u64,
+        clean: bool,
+    },
+
+    /// Children are ready to be restarted.
+    Restart(Vec<(usize, u64)>),
+}
+
+/// The configuration of a running supervisor.
+struct Supervisor {
+    engine: Arc<Engine>,
+    linker: Arc<Linker<ProcessData>>,
+    runtime: Arc<Runtime>,
+    spec: SupervisorSpec,
+}
+
+impl Supervisor {
+    /// Starts a new instance of a child.
+    async fn start<'a>(
+        &self,
+        table: &'a Table,
+        children: &mut [Child<'a>],
+        index: usize,
+        events: &mpsc::UnboundedSender<Event>,
+    ) -> bool {
+        let spec = &self.spec.children[index];
+        let child = &mut children[index];
+        child.generation += 1;
+        child.started = Instant::now();
+        child.restart_at = None;
+
+        let caps: Vec<_> = child
+            .args
+            .caps
+            .iter()
+            .filter_map(|cap| table.import_owned(cap.clone()).ok())
+            .filter_map(|handle| table.wrap_handle(handle).ok())
+            .collect();
+
+        let cap_refs: Vec<_> = caps.iter().collect();
+
+        let spawned = spawn_process(
+            &self.engine,
+            &self.linker,
+            &self.runtime,
+            table,
+            &spec.spawn,
+            &cap_refs,
+        )
+        .await;
+
+        let (cap, exit) = match spawned {
+            Ok(spawned) => spawned,
+            Err(err) => {
+                error!(
+                    "Failed to start supervised child {:?}: {:?}",
+                    spec.name, err
+                );
+                return false;
+            }
+        };
+
+        let generation = child.generation;
+        let events = events.clone();
+        tokio::spawn(async move {
+            let clean = exit.await.unwrap_or(false);
+            let _ = events.send(Event::Exited {
+                index,
+                generation,
+                clean,
+            });
+        });
+
+        child.args.current_tx.send_replace(Some(cap.to_owned()));
+        child.current = Some(cap);
+        true
+    }
+
+    /// Kills a child's running instance, if any.
+    fn stop(&self, child: &mut Child) {
+        child.generation += 1;
+        child.args.current_tx.send_replace(None);
+
+        if let Some(cap) = child.current.take() {
+            let _ = cap.kill();
+        }
+    }
+
+    /// Tests if a child should be restarted after it exits.
+    fn should_restart(&self, index: usize, clean: bool) -> bool {
+        match self.spec.children[index].restart {
+            RestartPolicy::Permanent => true,
+            RestartPolicy::Transient => !clean,
+            RestartPolicy::Temporary => false,
+        }
+    }
+
+    /// Replies to a [SupervisorRequest] sent to this supervisor.
+    async fn on_request(&self, children: &[Child<'_>], data: &[u8], caps: &[CapabilityRef<'_>]) {
+        let Some(reply) = caps.first() else {
+            debug!("Supervisor request has no reply address");
+            return;
+        };
+
+        let codec = match Codec::detect(data) {
+            Ok(codec) => codec,
+            Err(err) => {
+                debug!("Invalid supervisor request: {:?}", err);
+                return;
+            }
+        };
+
+        match codec.decode(data) {
+            Ok(SupervisorRequest::Status) => {
+                let now = Instant::now();
+                let status: SupervisorStatus = self
+                    .spec
+                    .children
+                    .iter()
+                    .zip(children.iter())
+                    .map(|(spec, child)| ChildStatus {
+                        name: spec.name.clone(),
+                        running: child.current.is_some(),
+                        restarts: child.total_restarts,
+                        restart_in: child
+                            .restart_at
+                            .map(|at| at.saturating_duration_since(now).as_secs_f32()),
+                    })
+                    .collect();
+
+                if let Err(err) = reply.send(&codec.encode(&status), &[]).await {
+                    debug!("Supervisor status reply error: {:?}", err);
+                }
+            }
+            Err(err) => debug!("Invalid supervisor request: {:?}", err),
+        }
+    }
+
+    /// Gets the delay before a child's next restart and counts the restart.
+    fn next_delay(&self, child: &mut Child) -> Duration {
+        let backoff = &self.spec.backoff;
+        let max = secs_to_duration(backoff.max);
+
+        if child.started.elapsed() > max {
+            child.restarts = 0;
+        }
+
+        let factor = 2f32.powi(child.restarts.min(31) as i32);
+        child.restarts += 1;
+        secs_to_duration(backoff.initial * factor).min(max)
+    }
+}
+
+/// Converts a number of seconds from a [SupervisorSpec] to a [Duration].
+fn secs_to_duration(secs: f32) -> Duration {
+    Duration::try_from_secs_f32(secs.max(0.0)).unwrap_or(Duration::MAX)
+}
+
+/// Records a restart and tests if a supervisor has restarted its children too
+/// often in the intensity period.
+fn exceeds_intensity(
+    history: &mut VecDeque<Instant>,
+    intensity: &RestartIntensity,
+    now: Instant,
+) -> bool {
+    let period = secs_to_duration(intensity.period);
+    while let Some(oldest) = history.front() {
+        if now.duration_since(*oldest) > period {
+            history.pop_front();
+        } else {
+            break;
+        }
+    }
+
+    history.push_back(now);
+    history.len() > intensity.max_restarts as usize
+}
+
+/// Runs a supervisor until it is killed or gives up.
+///
+/// Reports the initial start of every child to `ready`.
+async fn supervise(
+    supervisor: Supervisor,
+    ctx: Process,
+    args: Vec<ChildArgs>,
+    ready: oneshot::Sender<SupervisorResponse>,
+) {
+    let table = ctx.borrow_table();
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+
+    let mut children: Vec<_> = args
+        .into_iter()
+        .map(|args| Child {
+            args,
+            current: None,
+            generation: 0,
+            started: Instant::now(),
+            restarts: 0,
+            total_restarts: 0,
+            restart_at: None,
+        })
+        .collect();
+
+    for index in 0..children.len() {
+        if !supervisor
+            .start(table, &mut children, index, &events_tx)
+            .await
+        {
+            let child = supervisor.spec.children[index].name.clone();
+            let _ = ready.send(Err(SupervisorError::SpawnFailed { child }));
+
+            for child in children.iter_mut() {
+                supervisor.stop(child);
+            }
+
+            return;
+        }
+    }
+
+    let _ = ready.send(Ok(()));
+
+    let mut history = VecDeque::new();
+
+    loop {
+        let event = tokio::select! {
+            signal = ctx.borrow_parent().recv_owned() => match signal {
+                Some(OwnedTableSignal::Message { data, caps }) => {
+                    supervisor.on_request(&children, &data, &caps).await;
+                    continue;
+                }
+                Some(_) => continue,
+                None => break, // killed
+            },
+            Some(event) = events.recv() => event,
+        };
+
+        match event {
+            Event::Exited {
+                index,
+                generation,
+                clean,
+            } => {
+                let child = &mut children[index];
+                if child.generation != generation {
+                    continue;
+                }
+
+                let name = &supervisor.spec.children[index].name;
+                supervisor.stop(child);
+
+                if !supervisor.should_restart(index, clean) {
+                    info!("Supervised child {:?} exited", name);
+                    continue;
+                }
+
+                if exceeds_intensity(&mut history, &supervisor.spec.intensity, Instant::now()) {
+                    error!(
+                        "Supervised child {:?} exited too often; stopping its supervisor",
+                        name
+                    );
+
+                    break;
+                }
+
+                let delay = supervisor.next_delay(&mut children[index]);
+                warn!("Restarting supervised child {:?} in {:?}", name, delay);
+
+                let restart: Vec<_> = match supervisor.spec.strategy {
+                    RestartStrategy::OneForOne => vec![index],
+                    RestartStrategy::OneForAll => (0..children.len()).collect(),
+                };
+
+                let restart_at = Instant::now().checked_add(delay);
+                let restart = restart
+                    .into_iter()
+                    .map(|index| {
+                        let child = &mut children[index];
+                        if child.current.is_some() {
+                            supervisor.stop(child);
+                        }
+
+                        child.restart_at = restart_at;
+                        (index, child.generation)
+                    })
+                    .collect();
+
+                let events_tx = events_tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = events_tx.send(Event::Restart(restart));
+                });
+            }
+            Event::Restart(restart) => {
+                for (index, generation) in restart {
+                    if children[index].generation != generation {
+                        continue;
+                    }
+
+                    children[index].total_restarts += 1;
+
+                    // failing to start counts as crashing
+                    if !supervisor
+                        .start(table, &mut children, index, &events_tx)
+                        .await
+                    {
+                        let _ = events_tx.send(Event::Exited {
+                            index,
+                            generation: children[index].generation,
+                            clean: false,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    for child in children.iter_mut() {
+        supervisor.stop(child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intensity_limits_restarts_in_period() {
+        let intensity = RestartIntensity {
+            max_restarts: 2,
+            period: 1.0,
+        };
+
+        let start = Instant::now();
+        let mut history = VecDeque::new();
+        assert!(!exceeds_intensity(&mut history, &intensity, start));
+        assert!(!exceeds_intensity(&mut history, &intensity, start));
+        assert!(exceeds_intensity(&mut history, &intensity, start));
+
+        // restarts outside of the period are forgotten
+        let later = start + Duration::from_secs(2);
+        assert!(!exceeds_intensity(&mut history, &intensity, later));
+    }
+
+    #[test]
+    fn secs_to_duration_clamps() {
+        assert_eq!(secs_to_duration(-1.0), Duration::ZERO);
+        assert_eq!(secs_to_duration(f32::NAN), Duration::ZERO);
+        assert_eq!(secs_to_duration(f32::INFINITY), Duration::MAX);
+        assert_eq!(secs_to_duration(0.5), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use hearth_bench::{Benchmark, Harness};
+use tokio::runtime::Runtime;
+
+fn messaging(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let harness = rt.block_on(Harness::new()).unwrap();
+
+    for bench in Benchmark::ALL {
+        c.bench_function(bench.name(), |b| {
+            b.to_async(&rt)
+                .iter_custom(|iters| async { harness.run(bench, iters).await.unwrap() })
+        });
+    }
+}
+
+criterion_group!(benches, messaging);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+;; A minimal guest that replies to every message with the message's data.
+;;
+;; The reply is sent to the message's first capability. Messages must fit in
+;; the guest's memory after the 1 KiB scratch area.
+(module
+  (import "hearth::mailbox" "recv" (func $recv (param i32) (result i32)))
+  (import "hearth::mailbox" "destroy_signal" (func $destroy_signal (param i32)))
+  (import "hearth::mailbox" "get_message_data_len" (func $get_data_len (param i32) (result i32)))
+  (import "hearth::mailbox" "get_message_data" (func $get_data (param i32 i32)))
+  (import "hearth::mailbox" "get_message_caps_num" (func $get_caps_num (param i32) (result i32)))
+  (import "hearth::mailbox" "get_message_caps" (func $get_caps (param i32 i32)))
+  (import "hearth::table" "send" (func $send (param i32 i32 i32 i32 i32)))
+  (import "hearth::table" "dec_ref" (func $dec_ref (param i32)))
+
+  (memory (export "memory") 2)
+
+  (func (export "run")
+    (local $signal i32)
+    (local $len i32)
+    (local $caps i32)
+    (local $reply i32)
+    (loop $next
+      ;; wait for a message on the parent mailbox
+      (local.set $signal (call $recv (i32.const 0)))
+      (local.set $len (call $get_data_len (local.get $signal)))
+      (call $get_data (local.get $signal) (i32.const 1024))
+      (local.set $caps (call $get_caps_num (local.get $signal)))
+
+      (if (i32.gt_u (local.get $caps) (i32.const 0))
+        (then
+          ;; the capability list is written to the start of the scratch area
+          (call $get_caps (local.get $signal) (i32.const 0))
+          (local.set $reply (i32.load (i32.const 0)))
+          (call $send (local.get $reply) (i32.const 1024) (local.get $len) (i32.const 0) (i32.const 0))
+
+          ;; release every received capability
+          (loop $free
+            (local.set $caps (i32.sub (local.get $caps) (i32.const 1)))
+            (call $dec_ref (i32.load (i32.shl (local.get $caps) (i32.const 2))))
+            (br_if $free (i32.gt_u (local.get $caps) (i32.const 0))))))
+
+      (call $destroy_signal (local.get $signal))
+      (br $next)))
+)
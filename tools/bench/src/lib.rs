@@ -0,0 +1,448 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+//! End-to-end benchmarks of Hearth's messaging and process spawning.
+//!
+//! Each benchmark runs a fixed number of iterations against a live runtime
+//! and returns the total time taken. The `hearth-bench` binary reports these
+//! directly, and the criterion benches in `benches/` wrap them for
+//! statistical comparison between runs.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use hearth_runtime::{
+    anyhow::{anyhow, bail, Context, Result},
+    async_trait, cargo_process_metadata,
+    flue::{CapabilityHandle, Mailbox, Permissions, Table, TableSignal},
+    hearth_macros::GetProcessMetadata,
+    hearth_schema::{
+        registry::{RegistryRequest, RegistryResponse},
+        wasm::WasmSpawnInfo,
+        LumpId,
+    },
+    process::{Process, ProcessMetadata},
+    runtime::{Runtime, RuntimeBuilder, RuntimeConfig},
+    utils::{
+        MessageInfo, RequestInfo, RequestResponseProcess, ResponseInfo, ServiceRunner, SinkProcess,
+    },
+};
+use hearth_wasm::WasmPlugin;
+use serde::Serialize;
+
+/// The source of the guest used by the Wasm benchmarks. It replies to every
+/// message with its data.
+pub const ECHO_GUEST: &str = include_str!("echo.wat");
+
+/// The payload sent by the round-trip benchmarks.
+pub const PAYLOAD: &str = "ping";
+
+/// The size in bytes of each lump added by [Benchmark::LumpLoad].
+pub const LUMP_SIZE: usize = 64 * 1024;
+
+/// The registry name of the Wasm process spawner.
+const SPAWNER: &str = "hearth.wasm.WasmProcessSpawner";
+
+/// A single benchmark run by a [Harness].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Benchmark {
+    /// Request-response round trips between the host and a native service.
+    NativeRoundTrip,
+
+    /// Request-response round trips between the host and a Wasm guest.
+    GuestRoundTrip,
+
+    /// One-way messages delivered to a [SinkProcess].
+    SinkThroughput,
+
+    /// Spawning (and then killing) a Wasm process.
+    ///
+    /// The guest's module is compiled once and cached by the asset store, so
+    /// this measures instantiation and process setup.
+    WasmSpawn,
+
+    /// Adding a fresh lump to the lump store and loading it back.
+    LumpLoad,
+}
+
+impl Benchmark {
+    /// Every benchmark, in the order that they are run.
+    pub const ALL: [Benchmark; 5] = [
+        Benchmark::NativeRoundTrip,
+        Benchmark::GuestRoundTrip,
+        Benchmark::SinkThroughput,
+        Benchmark::WasmSpawn,
+        Benchmark::LumpLoad,
+    ];
+
+    /// The name of this benchmark as it appears in reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Benchmark::NativeRoundTrip => "native_round_trip",
+            Benchmark::GuestRoundTrip => "guest_round_trip",
+            Benchmark::SinkThroughput => "sink_throughput",
+            Benchmark::WasmSpawn => "wasm_spawn",
+            Benchmark::LumpLoad => "lump_load",
+        }
+    }
+}
+
+/// The results of running a [Benchmark].
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    /// The [Benchmark::name] of the benchmark.
+    pub name: &'static str,
+
+    /// The number of iterations that were run.
+    pub iterations: u64,
+
+    /// The total time taken by every iteration in seconds.
+    pub total_secs: f64,
+
+    /// The mean time of a single iteration in nanoseconds.
+    pub mean_ns: f64,
+
+    /// The number of iterations completed per second.
+    pub ops_per_sec: f64,
+}
+
+impl Report {
+    /// Summarizes the total time taken by a benchmark run.
+    pub fn new(bench: Benchmark, iterations: u64, total: Duration) -> Self {
+        let total_secs = total.as_secs_f64();
+        let iterations_f = iterations.max(1) as f64;
+
+        Self {
+            name: bench.name(),
+            iterations,
+            total_secs,
+            mean_ns: total_secs * 1e9 / iterations_f,
+            ops_per_sec: if total_secs > 0.0 {
+                iterations as f64 / total_secs
+            } else {
+                f64::INFINITY
+            },
+        }
+    }
+}
+
+/// A native service that replies to each request with the request's data.
+#[derive(GetProcessMetadata)]
+pub struct EchoService;
+
+#[async_trait]
+impl RequestResponseProcess for EchoService {
+    type Request = String;
+    type Response = String;
+
+    async fn on_request<'a>(
+        &'a mut self,
+        request: &mut RequestInfo<'a, String>,
+    ) -> ResponseInfo<'a, String> {
+        std::mem::take(&mut request.data).into()
+    }
+}
+
+impl ServiceRunner for EchoService {
+    const NAME: &'static str = "hearth.bench.Echo";
+}
+
+/// A native service that discards its messages.
+///
+/// Messages carrying a capability are replied to with an empty message, so
+/// that senders can wait for every message before it to be processed.
+#[derive(GetProcessMetadata)]
+pub struct SinkService;
+
+#[async_trait]
+impl SinkProcess for SinkService {
+    type Message = ();
+
+    async fn on_message<'a>(&'a mut self, message: MessageInfo<'a, ()>) {
+        if let Some(reply) = message.caps.first() {
+            let _ = reply.send(&[], &[]).await;
+        }
+    }
+}
+
+impl ServiceRunner for SinkService {
+    const NAME: &'static str = "hearth.bench.Sink";
+}
+
+/// A running Hearth runtime with the services used by the benchmarks.
+pub struct Harness {
+    /// The runtime being benchmarked.
+    pub runtime: Arc<Runtime>,
+
+    /// The native process that the benchmarks run in.
+    client: Process,
+
+    /// The client's capability to [EchoService].
+    echo: CapabilityHandle,
+
+    /// The client's capability to [SinkService].
+    sink: CapabilityHandle,
+
+    /// The client's capability to the Wasm process spawner.
+    spawner: CapabilityHandle,
+
+    /// The client's capability to a running echo guest.
+    guest: CapabilityHandle,
+
+    /// The lump containing [ECHO_GUEST].
+    guest_lump: LumpId,
+
+    /// The number of lumps added by [Benchmark::LumpLoad] so far.
+    lump_count: AtomicU64,
+}
+
+impl Harness {
+    /// Starts a new runtime and spawns the echo guest.
+    pub async fn new() -> Result<Self> {
+        let mut builder = RuntimeBuilder::new();
+        builder
+            .add_plugin(WasmPlugin::default())
+            .add_plugin(EchoService)
+            .add_plugin(SinkService);
+
+        let runtime = builder.run(RuntimeConfig::default()).await;
+        let client = runtime.process_factory.spawn(cargo_process_metadata!());
+
+        let echo = get_service(&runtime, &client, EchoService::NAME).await?;
+        let sink = get_service(&runtime, &client, SinkService::NAME).await?;
+        let spawner = get_service(&runtime, &client, SPAWNER).await?;
+
+        let guest_lump = runtime
+            .lump_store
+            .add_lump(ECHO_GUEST.as_bytes().to_vec().into())
+            .await;
+
+        let guest = spawn_guest(&client, spawner, guest_lump).await?;
+
+        Ok(Self {
+            runtime,
+            client,
+            echo,
+            sink,
+            spawner,
+            guest,
+            guest_lump,
+            lump_count: AtomicU64::new(0),
+        })
+    }
+
+    /// Runs a benchmark for the given number of iterations and returns the
+    /// total time taken.
+    pub async fn run(&self, bench: Benchmark, iterations: u64) -> Result<Duration> {
+        match bench {
+            Benchmark::NativeRoundTrip => self.round_trip(self.echo, iterations).await,
+            Benchmark::GuestRoundTrip => self.round_trip(self.guest, iterations).await,
+            Benchmark::SinkThroughput => self.sink_throughput(iterations).await,
+            Benchmark::WasmSpawn => self.wasm_spawn(iterations).await,
+            Benchmark::LumpLoad => self.lump_load(iterations).await,
+        }
+    }
+
+    /// Sends [PAYLOAD] to a target and waits for its reply, one at a time.
+    async fn round_trip(&self, target: CapabilityHandle, iterations: u64) -> Result<Duration> {
+        let table = self.client.borrow_table();
+        let target = table.wrap_handle(target)?;
+        let response = self.create_mailbox()?;
+        let response_cap = response.export(Permissions::SEND)?;
+        let data = serde_json::to_vec(PAYLOAD)?;
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            target.send(&data, &[&response_cap]).await?;
+            recv_reply(table, &response).await?;
+        }
+
+        Ok(start.elapsed())
+    }
+
+    /// Sends messages to [SinkService] without waiting, then waits for the
+    /// sink to process all of them.
+    async fn sink_throughput(&self, iterations: u64) -> Result<Duration> {
+        let table = self.client.borrow_table();
+        let sink = table.wrap_handle(self.sink)?;
+        let response = self.create_mailbox()?;
+        let response_cap = response.export(Permissions::SEND)?;
+        let data = serde_json::to_vec(&())?;
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            sink.send(&data, &[]).await?;
+        }
+
+        sink.send(&data, &[&response_cap]).await?;
+        recv_reply(table, &response).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Spawns and immediately kills echo guests.
+    async fn wasm_spawn(&self, iterations: u64) -> Result<Duration> {
+        let table = self.client.borrow_table();
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            let guest = spawn_guest(&self.client, self.spawner, self.guest_lump).await?;
+            let guest_ref = table.wrap_handle(guest)?;
+            let _ = guest_ref.kill();
+            table.dec_ref(guest)?;
+        }
+
+        Ok(start.elapsed())
+    }
+
+    /// Adds lumps of [LUMP_SIZE] bytes and reads them back.
+    ///
+    /// Each lump's contents are unique so that the store never deduplicates
+    /// them.
+    async fn lump_load(&self, iterations: u64) -> Result<Duration> {
+        let lumps = &self.runtime.lump_store;
+        let mut data = vec![0u8; LUMP_SIZE];
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            let index = self.lump_count.fetch_add(1, Ordering::Relaxed);
+            data[..8].copy_from_slice(&index.to_le_bytes());
+            let id = lumps.add_lump(data.clone().into()).await;
+            lumps
+                .get_lump(&id)
+                .await
+                .ok_or_else(|| anyhow!("lump {} went missing", id))?;
+        }
+
+        Ok(start.elapsed())
+    }
+
+    /// Creates a mailbox in the client process.
+    fn create_mailbox(&self) -> Result<Mailbox<'_>> {
+        self.client
+            .borrow_group()
+            .create_mailbox()
+            .context("client has been killed")
+    }
+}
+
+/// Spawns a new echo guest and returns the client's capability to it.
+async fn spawn_guest(
+    client: &Process,
+    spawner: CapabilityHandle,
+    lump: LumpId,
+) -> Result<CapabilityHandle> {
+    let spawner = client.borrow_table().wrap_handle(spawner)?;
+
+    let response = client
+        .borrow_group()
+        .create_mailbox()
+        .context("client has been killed")?;
+
+    let response_cap = response.export(Permissions::SEND)?;
+
+    let request = WasmSpawnInfo {
+        lump,
+        entrypoint: None,
+        record: false,
+    };
+
+    spawner
+        .send(&serde_json::to_vec(&request)?, &[&response_cap])
+        .await?;
+
+    let guest = response
+        .recv(|signal| match signal {
+            TableSignal::Message { caps, .. } => caps.first().copied(),
+            _ => None,
+        })
+        .await
+        .context("client has been killed")?;
+
+    guest.ok_or_else(|| anyhow!("failed to spawn echo guest"))
+}
+
+/// Waits for the next message on a mailbox, releasing any capabilities in it.
+async fn recv_reply(table: &Table, mailbox: &Mailbox<'_>) -> Result<()> {
+    let caps = mailbox
+        .recv(|signal| match signal {
+            TableSignal::Message { caps, .. } => Ok(caps),
+            other => Err(anyhow!("expected reply, got {:?}", other)),
+        })
+        .await
+        .context("client has been killed")??;
+
+    for cap in caps {
+        table.dec_ref(cap)?;
+    }
+
+    Ok(())
+}
+
+/// Looks up a service in the runtime's registry and imports it into the
+/// client's table.
+async fn get_service(runtime: &Runtime, client: &Process, name: &str) -> Result<CapabilityHandle> {
+    let table = client.borrow_table();
+
+    let registry = runtime
+        .registry
+        .borrow_parent()
+        .export_to(Permissions::SEND, table)
+        .context("exporting registry")?;
+
+    let response = client
+        .borrow_group()
+        .create_mailbox()
+        .context("client has been killed")?;
+
+    let response_cap = response
+        .export(Permissions::SEND)
+        .context("exporting response mailbox")?;
+
+    let request = RegistryRequest::Get {
+        name: name.to_string(),
+    };
+
+    registry
+        .send(&serde_json::to_vec(&request)?, &[&response_cap])
+        .await
+        .context("sending registry request")?;
+
+    let handle = response
+        .recv(|signal| {
+            let TableSignal::Message { data, caps } = signal else {
+                return None;
+            };
+
+            match serde_json::from_slice(data) {
+                Ok(RegistryResponse::Get(true)) => caps.first().copied(),
+                _ => None,
+            }
+        })
+        .await
+        .context("client has been killed")?;
+
+    match handle {
+        Some(handle) => Ok(handle),
+        None => bail!("service {:?} not found", name),
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2023 the Hearth contributors.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+//
+// This file is part of Hearth.
+//
+// Hearth is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Affero General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Hearth is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with Hearth. If not, see <https://www.gnu.org/licenses/>.
+
+use clap::Parser;
+use hearth_bench::{Benchmark, Harness, Report};
+use hearth_runtime::anyhow::Result;
+
+/// Runs Hearth's end-to-end messaging and spawning benchmarks.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The number of iterations to run each benchmark for.
+    #[clap(short, long, default_value_t = 10_000)]
+    pub iterations: u64,
+
+    /// Only run benchmarks whose names contain this string.
+    #[clap(short, long)]
+    pub filter: Option<String>,
+
+    /// Print the results as JSON instead of as a table.
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let harness = Harness::new().await?;
+
+    let mut reports = Vec::new();
+    for bench in Benchmark::ALL {
+        if let Some(filter) = args.filter.as_ref() {
+            if !bench.name().contains(filter.as_str()) {
+                continue;
+            }
+        }
+
+        let total = harness.run(bench, args.iterations).await?;
+        reports.push(Report::new(bench, args.iterations, total));
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:>10} {:>12} {:>14} {:>14}",
+        "NAME", "ITERATIONS", "TOTAL (s)", "MEAN (ns)", "OPS/SEC"
+    );
+
+    for report in reports {
+        println!(
+            "{:<20} {:>10} {:>12.3} {:>14.0} {:>14.0}",
+            report.name, report.iterations, report.total_secs, report.mean_ns, report.ops_per_sec
+        );
+    }
+
+    Ok(())
+}